@@ -6,4 +6,13 @@ fn main() {
     // Rerun if these files change
     println!("cargo:rerun-if-changed=src/boot.s");
     println!("cargo:rerun-if-changed=link.ld");
+
+    // KASLR: link as a static PIE that keeps its dynamic relocations, so the
+    // kernel can apply them itself after picking a new base
+    println!("cargo:rerun-if-changed=kaslr.ld");
+    if std::env::var_os("CARGO_FEATURE_KASLR").is_some() {
+        for arg in ["-Tkaslr.ld", "--pie", "--no-dynamic-linker", "-zapply-dynamic-relocs", "-znotext"] {
+            println!("cargo:rustc-link-arg-bins={}", arg);
+        }
+    }
 }
@@ -29,6 +29,10 @@ pub struct AcpiInfo {
     pub mcfg: Option<McfgInfo>,
     /// Cached HPET information
     pub hpet: Option<HpetInfo>,
+    /// Cached SRAT information
+    pub srat: Option<SratInfo>,
+    /// Cached SLIT information
+    pub slit: Option<SlitInfo>,
 }
 
 impl AcpiInfo {
@@ -52,6 +56,8 @@ impl AcpiInfo {
             fadt: None,
             mcfg: None,
             hpet: None,
+            srat: None,
+            slit: None,
         })
     }
 }
@@ -160,6 +166,47 @@ pub struct HpetInfo {
     pub page_protection: u8,
 }
 
+/// Processor affinity from the SRAT
+#[derive(Debug, Clone)]
+pub struct SratProcessor {
+    pub apic_id: u32,
+    pub proximity_domain: u32,
+}
+
+/// Memory affinity from the SRAT
+#[derive(Debug, Clone)]
+pub struct SratMemory {
+    pub base_address: u64,
+    pub length: u64,
+    pub proximity_domain: u32,
+    pub hot_pluggable: bool,
+}
+
+/// System Resource Affinity Table (SRAT) summary; disabled entries are left out
+#[derive(Debug, Clone, Default)]
+pub struct SratInfo {
+    pub processors: Vec<SratProcessor>,
+    pub memory: Vec<SratMemory>,
+}
+
+/// System Locality Information Table (SLIT): relative distances between
+/// proximity domains, 10 meaning local
+#[derive(Debug, Clone, Default)]
+pub struct SlitInfo {
+    pub localities: usize,
+    pub distances: Vec<u8>,
+}
+
+impl SlitInfo {
+    /// Distance from domain `from` to domain `to`
+    pub fn distance(&self, from: usize, to: usize) -> Option<u8> {
+        if from >= self.localities || to >= self.localities {
+            return None;
+        }
+        self.distances.get(from * self.localities + to).copied()
+    }
+}
+
 /// HPET table header structure
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
@@ -516,6 +563,16 @@ pub fn hpet() -> Option<HpetInfo> {
     ACPI_STATE.read().as_ref()?.hpet.clone()
 }
 
+/// Get cached SRAT information if previously parsed
+pub fn srat() -> Option<SratInfo> {
+    ACPI_STATE.read().as_ref()?.srat.clone()
+}
+
+/// Get cached SLIT information if previously parsed
+pub fn slit() -> Option<SlitInfo> {
+    ACPI_STATE.read().as_ref()?.slit.clone()
+}
+
 /// Parse the Multiple APIC Description Table (MADT) to extract interrupt controller topology
 pub fn parse_madt() -> Result<MadtInfo, &'static str> {
     let descriptor = find_table(b"APIC").ok_or("MADT (APIC) table not found")?;
@@ -890,6 +947,138 @@ pub fn parse_hpet() -> Result<HpetInfo, &'static str> {
     Ok(info)
 }
 
+/// Parse the System Resource Affinity Table (SRAT) for the NUMA node of
+/// each processor and memory range
+pub fn parse_srat() -> Result<SratInfo, &'static str> {
+    let virt = get_table_address(b"SRAT")?;
+    let header = unsafe { &*(virt as *const SdtHeader) };
+    let table_length = header.length as usize;
+
+    let info = unsafe { parse_srat_from_address(virt, table_length) }?;
+
+    {
+        let mut state = ACPI_STATE.write();
+        if let Some(acpi) = state.as_mut() {
+            acpi.srat = Some(info.clone());
+        }
+    }
+
+    Ok(info)
+}
+
+/// SRAT entry types
+const SRAT_PROCESSOR_AFFINITY: u8 = 0;
+const SRAT_MEMORY_AFFINITY: u8 = 1;
+const SRAT_X2APIC_AFFINITY: u8 = 2;
+
+/// SRAT affinity flags
+const SRAT_ENABLED: u32 = 1 << 0;
+const SRAT_HOT_PLUGGABLE: u32 = 1 << 1;
+
+unsafe fn parse_srat_from_address(virt_addr: usize, table_length: usize) -> Result<SratInfo, &'static str> {
+    // 4 bytes of table revision and 8 reserved follow the header
+    let entry_start = mem::size_of::<SdtHeader>() + 12;
+    if table_length < entry_start {
+        return Err("SRAT table too short");
+    }
+
+    let table_slice = slice::from_raw_parts(virt_addr as *const u8, table_length);
+    if !checksum_bytes(table_slice) {
+        return Err("SRAT checksum validation failed");
+    }
+
+    let mut info = SratInfo::default();
+    let mut offset = entry_start;
+    while offset + 2 <= table_length {
+        let entry_type = table_slice[offset];
+        let length = table_slice[offset + 1] as usize;
+        if length < 2 || offset + length > table_length {
+            break;
+        }
+        let entry = &table_slice[offset..offset + length];
+
+        match entry_type {
+            SRAT_PROCESSOR_AFFINITY if length >= 16 => {
+                let flags = read_u32(entry, 4).unwrap_or(0);
+                if flags & SRAT_ENABLED != 0 {
+                    // The domain is split: bits 7:0 at offset 2, bits 31:8 at offset 9
+                    let high = u32::from_le_bytes([entry[9], entry[10], entry[11], 0]);
+                    info.processors.push(SratProcessor {
+                        apic_id: entry[3] as u32,
+                        proximity_domain: entry[2] as u32 | (high << 8),
+                    });
+                }
+            }
+            SRAT_MEMORY_AFFINITY if length >= 40 => {
+                let flags = read_u32(entry, 28).unwrap_or(0);
+                let length = read_u64(entry, 16).unwrap_or(0);
+                if flags & SRAT_ENABLED != 0 && length != 0 {
+                    info.memory.push(SratMemory {
+                        base_address: read_u64(entry, 8).unwrap_or(0),
+                        length,
+                        proximity_domain: read_u32(entry, 2).unwrap_or(0),
+                        hot_pluggable: flags & SRAT_HOT_PLUGGABLE != 0,
+                    });
+                }
+            }
+            SRAT_X2APIC_AFFINITY if length >= 24 => {
+                let flags = read_u32(entry, 12).unwrap_or(0);
+                if flags & SRAT_ENABLED != 0 {
+                    info.processors.push(SratProcessor {
+                        apic_id: read_u32(entry, 8).unwrap_or(0),
+                        proximity_domain: read_u32(entry, 4).unwrap_or(0),
+                    });
+                }
+            }
+            _ => {}
+        }
+
+        offset += length;
+    }
+
+    Ok(info)
+}
+
+/// Parse the System Locality Information Table (SLIT) for distances
+/// between NUMA nodes
+pub fn parse_slit() -> Result<SlitInfo, &'static str> {
+    let virt = get_table_address(b"SLIT")?;
+    let header = unsafe { &*(virt as *const SdtHeader) };
+    let table_length = header.length as usize;
+
+    let info = unsafe { parse_slit_from_address(virt, table_length) }?;
+
+    {
+        let mut state = ACPI_STATE.write();
+        if let Some(acpi) = state.as_mut() {
+            acpi.slit = Some(info.clone());
+        }
+    }
+
+    Ok(info)
+}
+
+unsafe fn parse_slit_from_address(virt_addr: usize, table_length: usize) -> Result<SlitInfo, &'static str> {
+    let matrix_start = mem::size_of::<SdtHeader>() + 8;
+    if table_length < matrix_start {
+        return Err("SLIT table too short");
+    }
+
+    let table_slice = slice::from_raw_parts(virt_addr as *const u8, table_length);
+    if !checksum_bytes(table_slice) {
+        return Err("SLIT checksum validation failed");
+    }
+
+    let localities = read_u64(table_slice, mem::size_of::<SdtHeader>()).ok_or("SLIT table too short")? as usize;
+    let size = localities.checked_mul(localities).ok_or("SLIT locality count too large")?;
+    let distances = table_slice
+        .get(matrix_start..matrix_start + size)
+        .ok_or("SLIT matrix truncated")?
+        .to_vec();
+
+    Ok(SlitInfo { localities, distances })
+}
+
 /// Initialize and parse all available ACPI tables
 pub fn init_acpi_tables() -> Result<(), &'static str> {
     // First enumerate all system description tables
@@ -914,6 +1103,11 @@ pub fn init_acpi_tables() -> Result<(), &'static str> {
     if let Err(e) = parse_hpet() {
         crate::serial_println!("Warning: Failed to parse HPET: {}", e);
     }
+
+    // SRAT and SLIT only exist on NUMA machines
+    if parse_srat().is_ok() {
+        let _ = parse_slit();
+    }
     
     // Mark tables as fully initialized
     mark_tables_initialized();
@@ -512,7 +512,7 @@ fn detect_display_adapter() -> Option<String> {
 
 /// Initialize ACPI with progress display
 pub fn acpi_init_progress(rsdp_addr: Option<u64>, physical_offset: u64) -> AcpiInitResult {
-    begin_stage(BootStage::AcpiInit, 4);
+    begin_stage(BootStage::AcpiInit, 5);
 
     let mut result = AcpiInitResult::new();
 
@@ -568,6 +568,20 @@ pub fn acpi_init_progress(rsdp_addr: Option<u64>, physical_offset: u64) -> AcpiI
         }
     }
 
+    // Parse SRAT/SLIT for the NUMA topology
+    update_substage(5, "Parsing SRAT/SLIT for NUMA topology...");
+    if result.tables_parsed {
+        match crate::acpi::parse_srat() {
+            Ok(srat) => {
+                let _ = crate::acpi::parse_slit();
+                report_success(&format!("SRAT parsed - {} memory affinity ranges", srat.memory.len()));
+            }
+            Err(_) => {
+                report_warning("SRAT", "Not found, single NUMA node");
+            }
+        }
+    }
+
     complete_stage(BootStage::AcpiInit);
     boot_delay_short();
 
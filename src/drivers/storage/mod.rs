@@ -906,6 +906,56 @@ pub fn is_gpt_device(device_id: u32) -> Result<bool, StorageError> {
     Ok(&buffer[0..8] == b"EFI PART")
 }
 
+/// A whole device or one of its partitions, as named under /dev
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRange {
+    pub device_id: u32,
+    pub sector_size: u32,
+    pub start_sector: u64,
+    pub sector_count: u64,
+}
+
+/// Resolve a Linux-style block device name such as `/dev/sda` or `/dev/vdb2`
+///
+/// The drive letter indexes registered devices in id order; a trailing
+/// number selects an MBR partition on that device.
+pub fn resolve_block_device(path: &str) -> Result<BlockRange, StorageError> {
+    let name = path.strip_prefix("/dev/").ok_or(StorageError::DeviceNotFound)?;
+    let rest = ["xvd", "sd", "vd", "hd"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .ok_or(StorageError::DeviceNotFound)?;
+
+    let letter = rest.bytes().next().filter(u8::is_ascii_lowercase).ok_or(StorageError::DeviceNotFound)?;
+    let partition = &rest[1..];
+
+    let mut ids: Vec<u32> = get_storage_device_list().iter().map(|info| info.id).collect();
+    ids.sort_unstable();
+    let device_id = *ids.get((letter - b'a') as usize).ok_or(StorageError::DeviceNotFound)?;
+    let device = BlockDevice::new(device_id)?;
+
+    if partition.is_empty() {
+        return Ok(BlockRange {
+            device_id,
+            sector_size: device.sector_size(),
+            start_sector: 0,
+            sector_count: device.total_sectors(),
+        });
+    }
+
+    let number: u32 = partition.parse().map_err(|_| StorageError::DeviceNotFound)?;
+    let entry = read_mbr_partitions(device_id)?
+        .into_iter()
+        .find(|entry| entry.partition_number == number)
+        .ok_or(StorageError::DeviceNotFound)?;
+    Ok(BlockRange {
+        device_id,
+        sector_size: device.sector_size(),
+        start_sector: entry.start_sector,
+        sector_count: entry.sector_count,
+    })
+}
+
 // =============================================================================
 // STORAGE SUBSYSTEM CONTROL
 // =============================================================================
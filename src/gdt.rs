@@ -3,6 +3,7 @@
 //! This module provides GDT setup for kernel/user segments, TSS for stack switching,
 //! and privilege level management for RustOS.
 
+use alloc::boxed::Box;
 use lazy_static::lazy_static;
 use spin::Mutex;
 use x86_64::instructions::segmentation::{Segment, CS, DS, ES, FS, GS, SS};
@@ -17,8 +18,15 @@ pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 /// Stack size for interrupt stacks
 const STACK_SIZE: usize = 4096 * 5; // 20KB stack
 
+/// An interrupt stack with room for a guard page below it
+#[repr(C, align(4096))]
+struct InterruptStack {
+    guard: [u8; 4096],
+    stack: [u8; STACK_SIZE],
+}
+
 /// Interrupt stack for double fault handler
-static mut DOUBLE_FAULT_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+static mut DOUBLE_FAULT_STACK: InterruptStack = InterruptStack { guard: [0; 4096], stack: [0; STACK_SIZE] };
 
 /// Task State Segment (mutable for stack updates)
 static mut TSS: TaskStateSegment = TaskStateSegment::new();
@@ -72,14 +80,46 @@ pub fn init() {
     // Initialize TSS with double fault stack
     unsafe {
         TSS.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            let stack_start = VirtAddr::from_ptr(&raw const DOUBLE_FAULT_STACK);
+            let stack_start = VirtAddr::from_ptr(&raw const DOUBLE_FAULT_STACK.stack);
             let stack_end = stack_start + STACK_SIZE;
             stack_end
         };
     }
 
     GDT.0.load();
+    load_segments(GDT.1.tss_selector);
+
+    guard_interrupt_stacks();
+}
+
+/// Initialize the GDT and TSS of an application processor
+///
+/// Every CPU needs a TSS of its own for its interrupt stacks, and so a GDT of
+/// its own to describe it. Entries are added in the same order as the boot
+/// CPU's, so the selectors handed out by this module are valid on all CPUs.
+/// Returns the new TSS, whose RSP0 the scheduler rewrites on every switch.
+pub fn init_ap(double_fault_stack_top: VirtAddr) -> *mut TaskStateSegment {
+    let tss_ptr: *mut TaskStateSegment = Box::leak(Box::new(TaskStateSegment::new()));
+    let tss: &'static TaskStateSegment = unsafe {
+        (*tss_ptr).interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = double_fault_stack_top;
+        &*tss_ptr
+    };
 
+    let gdt = Box::leak(Box::new(GlobalDescriptorTable::new()));
+    gdt.add_entry(Descriptor::kernel_code_segment());
+    gdt.add_entry(Descriptor::kernel_data_segment());
+    gdt.add_entry(Descriptor::user_data_segment());
+    gdt.add_entry(Descriptor::user_code_segment());
+    let tss_selector = gdt.add_entry(Descriptor::tss_segment(tss));
+    let gdt: &'static GlobalDescriptorTable = gdt;
+
+    gdt.load();
+    load_segments(tss_selector);
+    tss_ptr
+}
+
+/// Load the kernel segments and the task register after loading a GDT
+fn load_segments(tss_selector: GdtSegmentSelector) {
     unsafe {
         // Set kernel code segment
         CS::set_reg(GDT.1.kernel_code_selector);
@@ -92,7 +132,16 @@ pub fn init() {
         SS::set_reg(GDT.1.kernel_data_selector);
 
         // Load TSS
-        load_tss(GDT.1.tss_selector);
+        load_tss(tss_selector);
+    }
+}
+
+/// Unmap the page below each IST stack, so overflowing one faults instead of
+/// running into the kernel data next to it
+fn guard_interrupt_stacks() {
+    let guard = VirtAddr::from_ptr(unsafe { &raw const DOUBLE_FAULT_STACK.guard });
+    if let Err(e) = crate::memory::stack::guard_kernel_page(guard) {
+        crate::serial_println!("No guard page for the double fault stack: {}", e);
     }
 }
 
@@ -231,15 +280,17 @@ pub fn get_stack_info() -> StackInfo {
 ///
 /// The stack pointer must point to a valid, mapped kernel stack.
 pub fn set_kernel_stack(stack_ptr: VirtAddr) {
-    use core::ptr;
-
-    // Get a mutable reference to TSS
-    // Safety: We have exclusive access during init
+    // Each CPU has its own TSS; the boot CPU's is the static one
+    let tss = crate::smp::this_cpu()
+        .map(|cpu| cpu.tss())
+        .filter(|tss| !tss.is_null())
+        .unwrap_or(core::ptr::addr_of_mut!(TSS));
+
+    // Safety: only the CPU owning the TSS writes it, with interrupts off
+    // during a switch
     unsafe {
-        TSS.privilege_stack_table[0] = stack_ptr;
+        (*tss).privilege_stack_table[0] = stack_ptr;
     }
-
-    crate::serial_println!("Kernel stack set to {:?} in TSS", stack_ptr);
 }
 
 /// Set user stack pointer (for task switching)
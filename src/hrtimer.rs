@@ -0,0 +1,415 @@
+//! High-Resolution Timers
+//!
+//! Every CPU keeps its pending timers ordered by expiry and arms its local
+//! APIC timer in one-shot mode for the earliest of them, by TSC deadline
+//! where the CPU supports it. Timers therefore expire to within the
+//! resolution of the uptime clock rather than on the next tick.
+//!
+//! The scheduler tick is itself a periodic timer of each CPU. A CPU with
+//! nothing to run stops it while it idles and sleeps until its next timer or
+//! interrupt, then accounts the whole stopped stretch at once.
+//!
+//! Callbacks run from the timer interrupt of the CPU the timer was started
+//! on, so every lock they take is only held elsewhere with interrupts
+//! disabled. CPUs without a one-shot local APIC timer still expire their
+//! timers, from the periodic tick.
+//!
+//! Subsystems with many deadlines of their own, such as sleeps, POSIX timers
+//! and timerfds, keep them in a `DeadlineQueue`, which holds a single timer
+//! here set for its earliest deadline.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::interrupts::without_interrupts;
+use crate::smp::MAX_CPUS;
+use crate::time;
+
+/// Vector of the one-shot local APIC timer interrupt
+pub const HRTIMER_VECTOR: u8 = 0xEC;
+
+/// Interval of the scheduler tick
+pub const TICK_NS: u64 = time::TICK_US * 1000;
+
+/// Identifies a started timer; the low byte is the CPU it was started on
+pub type HrTimerId = u64;
+
+/// Function run when a timer expires
+pub type HrTimerCallback = fn();
+
+struct HrTimer {
+    callback: HrTimerCallback,
+    /// Interval of a periodic timer, 0 for a one-shot one
+    period_ns: u64,
+}
+
+/// Pending timers of one CPU
+struct CpuTimers {
+    /// Timers by (expiry, id)
+    queue: BTreeMap<(u64, HrTimerId), HrTimer>,
+    /// Expiry of every pending timer
+    expiries: BTreeMap<HrTimerId, u64>,
+    /// The tick timer, while the tick runs
+    tick: Option<HrTimerId>,
+}
+
+impl CpuTimers {
+    const fn new() -> Self {
+        Self {
+            queue: BTreeMap::new(),
+            expiries: BTreeMap::new(),
+            tick: None,
+        }
+    }
+
+    /// Earliest expiry of a pending timer
+    fn next_expiry(&self) -> Option<u64> {
+        self.queue.keys().next().map(|&(expires, _)| expires)
+    }
+
+    /// Queue a timer; returns whether it is now the earliest
+    fn insert(&mut self, id: HrTimerId, expires: u64, timer: HrTimer) -> bool {
+        self.queue.insert((expires, id), timer);
+        self.expiries.insert(id, expires);
+        self.next_expiry() == Some(expires)
+    }
+
+    fn remove(&mut self, id: HrTimerId) -> Option<HrTimer> {
+        let expires = self.expiries.remove(&id)?;
+        self.queue.remove(&(expires, id))
+    }
+
+    /// Callbacks of the timers expired by `now`, earliest first
+    ///
+    /// One-shot timers are dropped, periodic ones are requeued for their
+    /// first period ending after `now`.
+    fn take_due(&mut self, now: u64) -> Vec<HrTimerCallback> {
+        let mut due = Vec::new();
+        while let Some(&(expires, id)) = self.queue.keys().next() {
+            if expires > now {
+                break;
+            }
+            let Some(timer) = self.remove(id) else {
+                break;
+            };
+            due.push(timer.callback);
+            if timer.period_ns > 0 {
+                let (_, next) = periodic_next(expires, timer.period_ns, now);
+                self.insert(id, next, timer);
+            }
+        }
+        due
+    }
+}
+
+struct PerCpuTimers {
+    timers: Mutex<CpuTimers>,
+    /// The CPU's local APIC timer fires for its timers
+    active: AtomicBool,
+    /// When the CPU's tick was last accounted
+    last_tick: AtomicU64,
+}
+
+impl PerCpuTimers {
+    const fn new() -> Self {
+        Self {
+            timers: Mutex::new(CpuTimers::new()),
+            active: AtomicBool::new(false),
+            last_tick: AtomicU64::new(0),
+        }
+    }
+}
+
+static CPUS: [PerCpuTimers; MAX_CPUS] = [const { PerCpuTimers::new() }; MAX_CPUS];
+
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(1);
+
+fn this_cpu_id() -> usize {
+    (crate::smp::current_cpu() as usize).min(MAX_CPUS - 1)
+}
+
+/// Arm the calling CPU's local APIC timer for its earliest timer
+fn reprogram(cpu: &PerCpuTimers, timers: &CpuTimers) {
+    if cpu.active.load(Ordering::Relaxed) {
+        time::program_local_apic_timer(timers.next_expiry());
+    }
+}
+
+/// Drive the calling CPU's timers and tick from its local APIC timer in
+/// one-shot mode
+///
+/// Until this succeeds the CPU ticks from a periodic timer, which then stays
+/// in charge.
+pub fn init_cpu() -> Result<(), &'static str> {
+    time::start_local_apic_oneshot(HRTIMER_VECTOR)?;
+
+    let cpu = &CPUS[this_cpu_id()];
+    without_interrupts(|| {
+        let mut timers = cpu.timers.lock();
+        cpu.active.store(true, Ordering::Relaxed);
+        let now = time::uptime_ns();
+        cpu.last_tick.store(now, Ordering::Relaxed);
+        start_tick(&mut timers, now);
+        reprogram(cpu, &timers);
+    });
+    Ok(())
+}
+
+/// Whether the calling CPU's timers are driven by its one-shot local APIC
+/// timer
+pub fn is_active() -> bool {
+    CPUS[this_cpu_id()].active.load(Ordering::Relaxed)
+}
+
+/// Start a timer on the calling CPU that expires at `expires_ns` of uptime,
+/// and after that every `period_ns` unless it is 0
+pub fn start(expires_ns: u64, period_ns: u64, callback: HrTimerCallback) -> HrTimerId {
+    let cpu_id = this_cpu_id();
+    let id = (NEXT_SEQ.fetch_add(1, Ordering::Relaxed) << 8) | cpu_id as u64;
+    let cpu = &CPUS[cpu_id];
+    without_interrupts(|| {
+        let mut timers = cpu.timers.lock();
+        if timers.insert(id, expires_ns, HrTimer { callback, period_ns }) {
+            reprogram(cpu, &timers);
+        }
+    });
+    id
+}
+
+/// Stop a pending timer; returns whether it had not expired yet
+///
+/// A periodic timer may be cancelled from its own callback.
+pub fn cancel(id: HrTimerId) -> bool {
+    let cpu = &CPUS[(id & 0xFF) as usize];
+    // A timer on another CPU may leave that CPU's hardware armed early; it
+    // finds nothing due and rearms
+    without_interrupts(|| cpu.timers.lock().remove(id).is_some())
+}
+
+/// Run the calling CPU's timers that are due, then arm the hardware for the
+/// next one
+///
+/// Called from the local APIC timer interrupt, and from the periodic tick on
+/// CPUs that have no one-shot timer.
+pub fn run_expired() {
+    let cpu = &CPUS[this_cpu_id()];
+    let now = time::uptime_ns();
+
+    // Periodic timers are requeued before their callbacks run, so that the
+    // callbacks can cancel them
+    let due = without_interrupts(|| {
+        cpu.timers.lock().take_due(now)
+    });
+
+    for callback in due {
+        callback();
+    }
+
+    without_interrupts(|| reprogram(cpu, &cpu.timers.lock()));
+}
+
+fn start_tick(timers: &mut CpuTimers, now: u64) {
+    let id = (NEXT_SEQ.fetch_add(1, Ordering::Relaxed) << 8) | this_cpu_id() as u64;
+    timers.insert(id, now + TICK_NS, HrTimer { callback: tick, period_ns: TICK_NS });
+    timers.tick = Some(id);
+}
+
+/// Tick timer callback: account the time since the CPU's last tick
+fn tick() {
+    let cpu_id = this_cpu_id();
+    let now = time::uptime_ns();
+    let elapsed = now.saturating_sub(CPUS[cpu_id].last_tick.swap(now, Ordering::Relaxed));
+
+    // Only the boot CPU keeps time
+    if cpu_id == 0 {
+        time::timer_tick();
+        let skipped = (elapsed / TICK_NS).saturating_sub(1);
+        if skipped > 0 {
+            time::account_skipped_ticks(skipped);
+        }
+    }
+    crate::scheduler::timer_tick(elapsed / 1000);
+}
+
+/// Stop the calling CPU's tick while it idles
+///
+/// Returns false if the CPU has no one-shot timer to wake it without the
+/// tick. Interrupts must be disabled.
+pub fn stop_tick() -> bool {
+    let cpu = &CPUS[this_cpu_id()];
+    if !cpu.active.load(Ordering::Relaxed) {
+        return false;
+    }
+    let mut timers = cpu.timers.lock();
+    if let Some(id) = timers.tick.take() {
+        timers.remove(id);
+        reprogram(cpu, &timers);
+    }
+    true
+}
+
+/// Restart the tick of a CPU leaving idle, accounting the time it was
+/// stopped
+///
+/// Interrupts must be disabled.
+pub fn restart_tick() {
+    let cpu = &CPUS[this_cpu_id()];
+    {
+        let mut timers = cpu.timers.lock();
+        if timers.tick.is_some() {
+            return;
+        }
+        start_tick(&mut timers, time::uptime_ns());
+        reprogram(cpu, &timers);
+    }
+    tick();
+}
+
+/// Key of a deadline in a `DeadlineQueue`: (deadline, arm sequence number)
+pub type DeadlineKey = (u64, u64);
+
+static NEXT_DEADLINE_SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// Deadlines in uptime nanoseconds, with one timer kept set for the earliest
+///
+/// The owner keeps the queue under a lock only taken with interrupts
+/// disabled and calls `rearm` after changing it. Its `callback` calls
+/// `fired`, takes what is due with `pop_due` and rearms.
+pub struct DeadlineQueue<T> {
+    armed: BTreeMap<DeadlineKey, T>,
+    /// The started timer and the deadline it was set for
+    timer: Option<(HrTimerId, u64)>,
+    callback: HrTimerCallback,
+}
+
+impl<T> DeadlineQueue<T> {
+    pub const fn new(callback: HrTimerCallback) -> Self {
+        Self {
+            armed: BTreeMap::new(),
+            timer: None,
+            callback,
+        }
+    }
+
+    /// Queue `item` for `deadline_ns`; equal deadlines keep their order
+    pub fn insert(&mut self, deadline_ns: u64, item: T) -> DeadlineKey {
+        let key = (deadline_ns, NEXT_DEADLINE_SEQ.fetch_add(1, Ordering::Relaxed));
+        self.armed.insert(key, item);
+        key
+    }
+
+    pub fn remove(&mut self, key: &DeadlineKey) -> Option<T> {
+        self.armed.remove(key)
+    }
+
+    pub fn retain(&mut self, f: impl FnMut(&DeadlineKey, &mut T) -> bool) {
+        self.armed.retain(f);
+    }
+
+    /// Take the earliest entry if its deadline is at or before `now`
+    pub fn pop_due(&mut self, now: u64) -> Option<(DeadlineKey, T)> {
+        let &key = self.armed.keys().next()?;
+        if key.0 > now {
+            return None;
+        }
+        self.armed.remove(&key).map(|item| (key, item))
+    }
+
+    /// Point the timer at the earliest deadline, or stop it if none is left
+    pub fn rearm(&mut self) {
+        let earliest = self.armed.keys().next().map(|&(deadline, _)| deadline);
+        if self.timer.map(|(_, deadline)| deadline) == earliest {
+            return;
+        }
+
+        if let Some((id, _)) = self.timer.take() {
+            cancel(id);
+        }
+        if let Some(deadline) = earliest {
+            self.timer = Some((start(deadline, 0, self.callback), deadline));
+        }
+    }
+
+    /// Forget the timer, which has just expired
+    pub fn fired(&mut self) {
+        self.timer = None;
+    }
+}
+
+/// Overruns of a periodic deadline found expired at `now`, and its next
+/// deadline after `now`
+///
+/// Saturates rather than wrapping to a deadline in the past.
+pub fn periodic_next(deadline_ns: u64, interval_ns: u64, now: u64) -> (u64, u64) {
+    let overruns = now.saturating_sub(deadline_ns) / interval_ns;
+    let next = deadline_ns.saturating_add((1 + overruns).saturating_mul(interval_ns));
+    (overruns, next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn never() {}
+
+    #[test]
+    fn test_deadline_queue_order() {
+        let mut queue = DeadlineQueue::new(never);
+        let late = queue.insert(300, 'c');
+        queue.insert(100, 'a');
+        queue.insert(100, 'b');
+
+        assert_eq!(queue.pop_due(50), None);
+        assert_eq!(queue.pop_due(100).map(|(_, item)| item), Some('a'));
+        assert_eq!(queue.pop_due(100).map(|(_, item)| item), Some('b'));
+        assert_eq!(queue.pop_due(1000), Some((late, 'c')));
+        assert_eq!(queue.pop_due(u64::MAX), None);
+    }
+
+    #[test]
+    fn test_periodic_next() {
+        assert_eq!(periodic_next(100, 10, 100), (0, 110));
+        assert_eq!(periodic_next(100, 10, 135), (3, 140));
+        assert_eq!(periodic_next(u64::MAX - 5, 10, u64::MAX), (0, u64::MAX));
+    }
+
+    #[test]
+    fn test_cpu_timers_order() {
+        let mut timers = CpuTimers::new();
+        assert_eq!(timers.next_expiry(), None);
+
+        assert!(timers.insert(2, 200, HrTimer { callback: never, period_ns: 0 }));
+        assert!(!timers.insert(3, 300, HrTimer { callback: never, period_ns: 0 }));
+        assert!(timers.insert(1, 100, HrTimer { callback: never, period_ns: 0 }));
+        // Equal expiries keep both timers
+        assert!(!timers.insert(4, 100, HrTimer { callback: never, period_ns: 0 }));
+        assert_eq!(timers.next_expiry(), Some(100));
+
+        assert!(timers.remove(1).is_some());
+        assert!(timers.remove(1).is_none());
+        assert_eq!(timers.next_expiry(), Some(100));
+        assert!(timers.remove(4).is_some());
+        assert_eq!(timers.next_expiry(), Some(200));
+        assert_eq!(timers.take_due(250).len(), 1);
+        assert_eq!(timers.next_expiry(), Some(300));
+    }
+
+    #[test]
+    fn test_cpu_timers_periodic_overruns() {
+        let mut timers = CpuTimers::new();
+        timers.insert(1, 100, HrTimer { callback: never, period_ns: 10 });
+        timers.insert(2, 120, HrTimer { callback: never, period_ns: 0 });
+
+        assert!(timers.take_due(99).is_empty());
+        // Missed periods run the callback once and the timer skips them
+        assert_eq!(timers.take_due(135).len(), 2);
+        assert_eq!(timers.next_expiry(), Some(140));
+        assert_eq!(timers.expiries.get(&1), Some(&140));
+        assert!(!timers.expiries.contains_key(&2));
+
+        assert_eq!(timers.take_due(140).len(), 1);
+        assert_eq!(timers.next_expiry(), Some(150));
+    }
+}
@@ -43,7 +43,14 @@ lazy_static! {
         let mut idt = InterruptDescriptorTable::new();
 
         // CPU Exception handlers
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
+        // int3 and single-step traps go through stubs that save every
+        // register, so that a debugger sees and changes the user's
+        unsafe {
+            idt.breakpoint
+                .set_handler_addr(VirtAddr::new(breakpoint_entry as u64))
+                .set_privilege_level(x86_64::PrivilegeLevel::Ring3);
+            idt.debug.set_handler_addr(VirtAddr::new(debug_entry as u64));
+        }
         unsafe {
             idt.double_fault
                 .set_handler_fn(double_fault_handler)
@@ -71,6 +78,11 @@ lazy_static! {
         idt[InterruptIndex::SerialPort2.as_usize()].set_handler_fn(serial_port2_interrupt_handler);
         idt[InterruptIndex::SpuriousInterrupt.as_usize()].set_handler_fn(spurious_interrupt_handler);
 
+        // Inter-processor interrupts
+        idt[crate::smp::TLB_SHOOTDOWN_VECTOR as usize].set_handler_fn(tlb_shootdown_interrupt_handler);
+        idt[crate::smp::RESCHEDULE_VECTOR as usize].set_handler_fn(reschedule_interrupt_handler);
+        idt[crate::hrtimer::HRTIMER_VECTOR as usize].set_handler_fn(hrtimer_interrupt_handler);
+
         // Linux syscall handler (INT 0x80)
         idt[0x80].set_handler_fn(crate::syscall_handler::syscall_0x80_handler);
 
@@ -107,6 +119,14 @@ static PAGE_FAULT_COUNT: AtomicU64 = AtomicU64::new(0);
 static SPURIOUS_COUNT: AtomicU64 = AtomicU64::new(0);
 static MISSED_INTERRUPTS: AtomicU64 = AtomicU64::new(0);
 
+/// Load the IDT on an application processor
+///
+/// All CPUs share the boot CPU's table; the APIC and interrupt routing set up
+/// by `init` are not repeated.
+pub fn load_idt() {
+    IDT.load();
+}
+
 /// Initialize the interrupt system
 pub fn init() {
     IDT.load();
@@ -299,18 +319,136 @@ where
 
 // ========== CPU EXCEPTION HANDLERS ==========
 
-extern "x86-interrupt" fn breakpoint_handler(_stack_frame: InterruptStackFrame) {
+/// Registers of a trap, as `trap_entry!` stubs push them on top of the
+/// hardware interrupt frame
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrapFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+/// Entry stub for an exception without error code: saves the general
+/// registers, calls `$handler` with the `TrapFrame` and returns with what it
+/// left there
+macro_rules! trap_entry {
+    ($name:ident, $handler:ident) => {
+        #[unsafe(naked)]
+        unsafe extern "C" fn $name() {
+            core::arch::naked_asm!(
+                "push rax",
+                "push rbx",
+                "push rcx",
+                "push rdx",
+                "push rsi",
+                "push rdi",
+                "push rbp",
+                "push r8",
+                "push r9",
+                "push r10",
+                "push r11",
+                "push r12",
+                "push r13",
+                "push r14",
+                "push r15",
+                // 20 words on a 16-byte aligned stack keep it aligned
+                "mov rdi, rsp",
+                "cld",
+                "call {handler}",
+                "pop r15",
+                "pop r14",
+                "pop r13",
+                "pop r12",
+                "pop r11",
+                "pop r10",
+                "pop r9",
+                "pop r8",
+                "pop rbp",
+                "pop rdi",
+                "pop rsi",
+                "pop rdx",
+                "pop rcx",
+                "pop rbx",
+                "pop rax",
+                "iretq",
+                handler = sym $handler,
+            );
+        }
+    };
+}
+
+trap_entry!(breakpoint_entry, breakpoint_handler);
+trap_entry!(debug_entry, debug_handler);
+
+extern "C" fn breakpoint_handler(frame: &mut TrapFrame) {
     // Handle breakpoint interrupt - increment counter for debugging
     EXCEPTION_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    // An int3 in user code is a SIGTRAP, which stops a traced process for
+    // its debugger
+    if frame.cs & 3 == 3 {
+        if !crate::process::ptrace::trap_signal_stop(frame, 5) {
+            let pid = crate::process::current_pid();
+            let _ = crate::process::integration::InterruptIntegration::deliver_signal(pid, 5);
+        }
+        frame.rflags = crate::process::ptrace::return_flags(frame.rflags);
+    }
     // Continue execution - breakpoints are non-fatal in production
 }
 
+extern "C" fn debug_handler(frame: &mut TrapFrame) {
+    const RFLAGS_TF: u64 = 1 << 8;
+
+    EXCEPTION_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    // Only the trap flag is used, to single-step user code
+    if frame.cs & 3 != 3 || frame.rflags & RFLAGS_TF == 0 {
+        return;
+    }
+
+    if !crate::process::ptrace::single_step_trap(frame) {
+        // Not stepped by a tracer: the process set the flag itself and
+        // keeps it
+        let pid = crate::process::current_pid();
+        let _ = crate::process::integration::InterruptIntegration::deliver_signal(pid, 5);
+        return;
+    }
+
+    frame.rflags = crate::process::ptrace::return_flags(frame.rflags & !RFLAGS_TF);
+}
+
 extern "x86-interrupt" fn double_fault_handler(
     _stack_frame: InterruptStackFrame,
     error_code: u64,
 ) -> ! {
     use crate::error::{KernelError, SystemError, ErrorSeverity, ErrorContext, ERROR_MANAGER};
-    
+    use x86_64::registers::control::Cr2;
+
+    // A stack overflow faults on the guard page, and the CPU then can't push
+    // the page fault frame onto the full stack either
+    let fault_address = Cr2::read();
+    if let Some(stack_bottom) = crate::memory::stack::overflowed_stack(fault_address) {
+        report_stack_overflow(stack_bottom, fault_address, &_stack_frame);
+    }
+
     let error_context = ErrorContext::new(
         KernelError::System(SystemError::InternalError),
         ErrorSeverity::Fatal,
@@ -333,6 +471,28 @@ extern "x86-interrupt" fn double_fault_handler(
     }
 }
 
+/// Name the thread whose kernel stack starting at `stack_bottom` overflowed
+fn report_stack_overflow(stack_bottom: VirtAddr, fault_address: VirtAddr, stack_frame: &InterruptStackFrame) {
+    let reported = crate::process::thread::get_thread_manager().with_thread_on_stack(stack_bottom.as_u64(), |tcb| {
+        crate::serial_println!(
+            "FATAL: Kernel stack overflow in thread {} ({}) of PID {}: guard page {:?} hit at RIP {:?}",
+            tcb.tid,
+            tcb.name_str(),
+            tcb.pid,
+            fault_address,
+            stack_frame.instruction_pointer
+        );
+    });
+    if reported.is_none() {
+        crate::serial_println!(
+            "FATAL: Stack overflow below {:?}: guard page {:?} hit at RIP {:?}",
+            stack_bottom,
+            fault_address,
+            stack_frame.instruction_pointer
+        );
+    }
+}
+
 extern "x86-interrupt" fn page_fault_handler(
     _stack_frame: InterruptStackFrame,
     error_code: PageFaultErrorCode,
@@ -353,6 +513,45 @@ extern "x86-interrupt" fn page_fault_handler(
     PAGE_FAULT_COUNT.fetch_add(1, Ordering::Relaxed);
     EXCEPTION_COUNT.fetch_add(1, Ordering::Relaxed);
 
+    // A kernel guard page means the faulting code is broken and may hold any
+    // lock, so this is checked first and needs none
+    if crate::memory::stack::is_kernel_guard(fault_address) {
+        crate::serial_println!(
+            "FATAL: Kernel guard page {:?} hit at RIP {:?} - halting",
+            fault_address,
+            _stack_frame.instruction_pointer
+        );
+        loop {
+            unsafe { core::arch::asm!("hlt"); }
+        }
+    }
+
+    // Demand paging, copy-on-write and stack growth in regions the memory
+    // manager tracks; anything else it can't resolve falls through
+    match crate::memory::handle_page_fault(fault_address, error_code.bits()) {
+        Ok(()) => return,
+        Err(crate::memory::MemoryError::Busy) => {
+            crate::serial_println!(
+                "FATAL: Page fault at {:?} (RIP {:?}) while the memory manager is locked - halting",
+                fault_address,
+                _stack_frame.instruction_pointer
+            );
+            loop {
+                unsafe { core::arch::asm!("hlt"); }
+            }
+        }
+        Err(crate::memory::MemoryError::GuardPageViolation) => {
+            crate::serial_println!(
+                "Stack overflow: guard page {:?} hit at RIP {:?}",
+                fault_address,
+                _stack_frame.instruction_pointer
+            );
+            terminate_current_process("Stack overflow");
+            return;
+        }
+        Err(_) => {}
+    }
+
     // In production, attempt page fault recovery
     if let Some(recovery_result) = attempt_page_fault_recovery(fault_address, error_code) {
         match recovery_result {
@@ -647,17 +846,39 @@ extern "x86-interrupt" fn alignment_check_handler(
 
 // ========== HARDWARE INTERRUPT HANDLERS ==========
 
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    // Absolute minimum handler - just send EOI
-    // Any more than this causes issues
-    unsafe {
-        // Send EOI directly to PIC port 0x20
-        core::arch::asm!(
-            "mov al, 0x20",
-            "out 0x20, al",
-            options(nomem, nostack, preserves_flags)
-        );
+extern "x86-interrupt" fn timer_interrupt_handler(stack_frame: InterruptStackFrame) {
+    TIMER_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    // Every CPU ticks its own scheduler; only the boot CPU keeps time. A CPU
+    // on one-shot timers ticks from those, and this is a last interrupt of
+    // the periodic timer it stopped.
+    let cpu_id = crate::smp::current_cpu();
+    if !crate::hrtimer::is_active() {
+        if cpu_id == 0 {
+            crate::time::timer_tick();
+        }
+        crate::scheduler::timer_tick(crate::time::TICK_US);
+        crate::hrtimer::run_expired();
     }
+
+    // Application processors tick from their local APIC timer, and so does
+    // the boot CPU when that was the timer it picked
+    if cpu_id != 0 || crate::time::local_apic_tick() {
+        crate::smp::eoi();
+    } else {
+        unsafe {
+            // Send EOI directly to PIC port 0x20
+            core::arch::asm!(
+                "mov al, 0x20",
+                "out 0x20, al",
+                options(nomem, nostack, preserves_flags)
+            );
+        }
+    }
+
+    // Acknowledged first: the next tick must not wait until this CPU
+    // comes back to the interrupted process
+    crate::scheduler::preempt(stack_frame.code_segment & 3 == 3);
 }
 
 extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
@@ -753,6 +974,24 @@ extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStac
     // Don't send EOI for spurious interrupts
 }
 
+extern "x86-interrupt" fn tlb_shootdown_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    crate::smp::handle_tlb_shootdown();
+}
+
+extern "x86-interrupt" fn hrtimer_interrupt_handler(stack_frame: InterruptStackFrame) {
+    TIMER_COUNT.fetch_add(1, Ordering::Relaxed);
+    crate::smp::eoi();
+    // The tick is one of the expiring timers
+    crate::hrtimer::run_expired();
+    crate::scheduler::preempt(stack_frame.code_segment & 3 == 3);
+}
+
+extern "x86-interrupt" fn reschedule_interrupt_handler(stack_frame: InterruptStackFrame) {
+    crate::smp::eoi();
+    // Kernel code switches where it blocks or yields, as for the timer
+    crate::scheduler::preempt(stack_frame.code_segment & 3 == 3);
+}
+
 // ========== INTERRUPT UTILITIES ==========
 
 /// Trigger a breakpoint exception for testing
@@ -0,0 +1,319 @@
+//! Kernel Address Space Layout Randomisation
+//!
+//! Two things move at boot, before the heap exists and before anything has
+//! stored a pointer into them:
+//!
+//! - The direct map of physical memory. The bootloader maps it at an offset
+//!   of its choosing; its PML4 entries are copied to randomly chosen free
+//!   slots in the upper half and the originals cleared, so every
+//!   physical-to-virtual translation after that uses the new offset.
+//! - The kernel image, when built with the `kaslr` feature. `kaslr.ld`
+//!   then links it as a static PIE that keeps its `R_X86_64_RELATIVE`
+//!   relocations. The PML4 entry holding the image is aliased at a random
+//!   free slot, the relocations are applied through the alias and execution
+//!   continues there; the old mapping is dropped once nothing runs from it.
+//!
+//! Both move by whole PML4 slots (512 GiB), so no page tables have to be
+//! allocated. That gives a few bits less entropy than Linux, which slides
+//! in 2 MiB steps. The randomness comes from `security::secure_random_bytes`;
+//! without RDRAND/RDSEED that can fail this early, and like Linux we fall
+//! back to the TSC.
+
+use bootloader::bootinfo::MemoryRegion;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(feature = "kaslr")]
+use core::sync::atomic::AtomicUsize;
+use x86_64::instructions::tlb;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::PageTable;
+
+/// Bytes mapped by one PML4 entry
+const SLOT_SIZE: u64 = 1 << 39;
+
+/// First PML4 slot of the kernel half of the address space
+const FIRST_KERNEL_SLOT: usize = 256;
+
+/// Marks "no slot" in `OLD_IMAGE_SLOT`
+#[cfg(feature = "kaslr")]
+const NO_SLOT: usize = usize::MAX;
+
+/// Offset of the direct map, once moved
+static PHYSMAP_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// How far the kernel image moved from its link address
+static IMAGE_SLIDE: AtomicU64 = AtomicU64::new(0);
+
+/// Slot the image ran from before it moved, to unmap after the jump
+#[cfg(feature = "kaslr")]
+static OLD_IMAGE_SLOT: AtomicUsize = AtomicUsize::new(NO_SLOT);
+
+/// Set once `relocate_kernel` has run, moved or not
+static RELOCATED: AtomicBool = AtomicBool::new(false);
+
+/// Offset at which all physical memory is mapped
+pub fn physmap_offset() -> u64 {
+    PHYSMAP_OFFSET.load(Ordering::Relaxed)
+}
+
+/// Distance the kernel image was moved from its link address
+pub fn image_slide() -> u64 {
+    IMAGE_SLIDE.load(Ordering::Relaxed)
+}
+
+/// Whether the kernel image has been through `relocate_kernel`
+pub fn relocated() -> bool {
+    RELOCATED.load(Ordering::Acquire)
+}
+
+fn random_u64() -> u64 {
+    let mut bytes = [0u8; 8];
+    if crate::security::secure_random_bytes(&mut bytes).is_ok() {
+        return u64::from_le_bytes(bytes);
+    }
+    // No hardware entropy; the TSC at this point still differs per boot
+    let tsc = unsafe { core::arch::x86_64::_rdtsc() };
+    (tsc ^ (tsc >> 29)).wrapping_mul(0xbf58_476d_1ce4_e5b9)
+}
+
+/// Canonical address of the start of PML4 slot `slot`
+fn slot_address(slot: usize) -> u64 {
+    let addr = (slot as u64) << 39;
+    if slot >= FIRST_KERNEL_SLOT {
+        addr | 0xffff_0000_0000_0000
+    } else {
+        addr
+    }
+}
+
+/// PML4 slot of `addr`
+fn slot_of(addr: u64) -> usize {
+    ((addr >> 39) & 0x1ff) as usize
+}
+
+/// The active PML4, seen through the direct map at `offset`
+///
+/// # Safety
+/// All physical memory must be mapped at `offset`, and the caller must be
+/// the only one touching the page tables.
+unsafe fn active_pml4(offset: u64) -> &'static mut PageTable {
+    let (frame, _) = Cr3::read();
+    &mut *((offset + frame.start_address().as_u64()) as *mut PageTable)
+}
+
+/// A random run of `count` free kernel-half slots, avoiding the last one
+/// (where the kernel image of a higher-half link sits)
+fn random_free_slots(table: &PageTable, count: usize) -> Option<usize> {
+    let fits = |first: usize| (first..first + count).all(|slot| table[slot].is_unused());
+    let candidates = (FIRST_KERNEL_SLOT..=511 - count).filter(|&first| fits(first));
+    let chosen = (random_u64() % candidates.clone().count().max(1) as u64) as usize;
+    candidates.clone().nth(chosen)
+}
+
+/// Move the direct map from `offset`, where the bootloader put it, to a
+/// random place, and return the new offset
+///
+/// Memory below the highest address in `memory_map` has to be mapped. The
+/// map stays put if the bootloader's offset isn't slot-aligned or shares a
+/// slot with the stack or the boot information.
+pub fn randomize_physmap(offset: u64, memory_map: &[MemoryRegion]) -> u64 {
+    PHYSMAP_OFFSET.store(offset, Ordering::Relaxed);
+    if offset % SLOT_SIZE != 0 {
+        return offset;
+    }
+
+    let top = memory_map.iter().map(|region| region.range.end_addr()).max().unwrap_or(0);
+    let count = top.div_ceil(SLOT_SIZE).max(1) as usize;
+    let first = slot_of(offset);
+
+    // Any local will do as an address on the stack
+    let stack = &top as *const u64 as u64;
+    let boot_info = memory_map.as_ptr() as u64;
+    if [stack, boot_info].iter().any(|&addr| (first..first + count).contains(&slot_of(addr))) {
+        return offset;
+    }
+
+    // SAFETY: the bootloader mapped all physical memory at `offset`, and
+    // nothing else runs yet
+    let table = unsafe { active_pml4(offset) };
+    let Some(new_first) = random_free_slots(table, count) else { return offset };
+    for i in 0..count {
+        table[new_first + i] = table[first + i].clone();
+    }
+
+    // From here on the page tables are only reachable through the new map
+    let new_offset = slot_address(new_first);
+    let table = unsafe { active_pml4(new_offset) };
+    for i in 0..count {
+        table[first + i].set_unused();
+    }
+    tlb::flush_all();
+
+    PHYSMAP_OFFSET.store(new_offset, Ordering::Relaxed);
+    new_offset
+}
+
+/// One entry of `.rela.dyn`
+#[cfg(any(feature = "kaslr", test))]
+#[repr(C)]
+struct Elf64Rela {
+    offset: u64,
+    info: u64,
+    addend: u64,
+}
+
+#[cfg(any(feature = "kaslr", test))]
+const R_X86_64_RELATIVE: u64 = 8;
+
+/// Apply the `R_X86_64_RELATIVE` relocations of an image moved by `slide`,
+/// writing through the new addresses; any other kind is left alone
+///
+/// # Safety
+/// Every relocated word must be writable at its new address.
+#[cfg(any(feature = "kaslr", test))]
+unsafe fn apply_relocations(relocs: &[Elf64Rela], slide: u64) {
+    for reloc in relocs.iter().filter(|reloc| reloc.info & 0xffff_ffff == R_X86_64_RELATIVE) {
+        let target = reloc.offset.wrapping_add(slide) as *mut u64;
+        target.write_volatile(reloc.addend.wrapping_add(slide));
+    }
+}
+
+// Provided by kaslr.ld
+#[cfg(feature = "kaslr")]
+extern "C" {
+    static __kernel_start: u8;
+    static __kernel_end: u8;
+    static __rela_dyn_start: Elf64Rela;
+    static __rela_dyn_end: Elf64Rela;
+}
+
+/// Move the kernel image to a random place and continue in `next` there
+///
+/// `next` is called with `arg` at its new address. It never returns, so
+/// nothing left on the stack points back into the old image; `arg` must
+/// not point into the image either. Must run before the IDT, GDT or heap
+/// hold pointers to kernel code or data, since the relocations don't
+/// cover those. Without the `kaslr` feature the image can't move and
+/// `next` is called in place.
+pub fn relocate_kernel(arg: usize, next: fn(usize) -> !) -> ! {
+    RELOCATED.store(true, Ordering::Release);
+
+    #[cfg(feature = "kaslr")]
+    if let Some(slide) = unsafe { move_image() } {
+        IMAGE_SLIDE.store(slide, Ordering::Relaxed);
+        // SAFETY: both functions now also live `slide` bytes further on,
+        // with their pointers fixed up
+        let resume: fn(usize, usize) -> ! =
+            unsafe { core::mem::transmute((resume as usize as u64).wrapping_add(slide) as usize) };
+        resume(arg, (next as usize as u64).wrapping_add(slide) as usize);
+    }
+
+    next(arg)
+}
+
+/// Alias the image's slot at a random free one and apply the relocations
+/// through it; returns the slide
+///
+/// # Safety
+/// Must run single-threaded before anything caches pointers into the image.
+#[cfg(feature = "kaslr")]
+unsafe fn move_image() -> Option<u64> {
+    use x86_64::registers::control::{Cr0, Cr0Flags};
+
+    let start = &__kernel_start as *const u8 as u64;
+    let end = &__kernel_end as *const u8 as u64;
+    let relocs = core::slice::from_raw_parts(
+        &__rela_dyn_start as *const Elf64Rela,
+        (&__rela_dyn_end as *const Elf64Rela).offset_from(&__rela_dyn_start) as usize,
+    );
+    let slot = slot_of(start);
+    if relocs.is_empty() || slot_of(end - 1) != slot {
+        return None;
+    }
+
+    let table = active_pml4(physmap_offset());
+    let new_slot = random_free_slots(table, 1)?;
+    table[new_slot] = table[slot].clone();
+    let slide = slot_address(new_slot).wrapping_sub(slot_address(slot));
+
+    // Read-only data has relocations too
+    let cr0 = Cr0::read();
+    Cr0::write(cr0 - Cr0Flags::WRITE_PROTECT);
+    apply_relocations(relocs, slide);
+    Cr0::write(cr0);
+
+    // The old slot can only go if the stack doesn't live in it too
+    let stack = &slide as *const u64 as u64;
+    if slot_of(stack) != slot {
+        OLD_IMAGE_SLOT.store(slot, Ordering::Relaxed);
+    }
+    Some(slide)
+}
+
+/// First code run at the new address: drop the old image mapping
+#[cfg(feature = "kaslr")]
+fn resume(arg: usize, next: usize) -> ! {
+    let old = OLD_IMAGE_SLOT.swap(NO_SLOT, Ordering::Relaxed);
+    if old != NO_SLOT {
+        // SAFETY: nothing runs from the old image any more
+        unsafe { active_pml4(physmap_offset())[old].set_unused() };
+        tlb::flush_all();
+    }
+    // SAFETY: `next` is the relocated address of a `fn(usize) -> !`
+    let next: fn(usize) -> ! = unsafe { core::mem::transmute(next) };
+    next(arg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x86_64::structures::paging::PageTableFlags;
+    use x86_64::PhysAddr;
+
+    /// A PML4 with every kernel-half slot in use except `free`
+    fn table_with_free(free: core::ops::Range<usize>) -> PageTable {
+        let mut table = PageTable::new();
+        for slot in (FIRST_KERNEL_SLOT..512).filter(|slot| !free.contains(slot)) {
+            table[slot].set_addr(PhysAddr::new(0x1000), PageTableFlags::PRESENT);
+        }
+        table
+    }
+
+    #[test]
+    fn test_free_slot_choice() {
+        let table = table_with_free(300..303);
+        for _ in 0..32 {
+            let first = random_free_slots(&table, 2).unwrap();
+            assert!(first == 300 || first == 301);
+        }
+        assert_eq!(random_free_slots(&table, 3), Some(300));
+        assert_eq!(random_free_slots(&table, 4), None);
+
+        // The last slot is never handed out
+        let table = table_with_free(510..512);
+        assert_eq!(random_free_slots(&table, 1), Some(510));
+        assert_eq!(random_free_slots(&table, 2), None);
+
+        // Nor anything in the lower half
+        for _ in 0..32 {
+            let first = random_free_slots(&PageTable::new(), 4).unwrap();
+            assert!(first >= FIRST_KERNEL_SLOT && first + 4 <= 511);
+        }
+    }
+
+    #[test]
+    fn test_relative_relocations() {
+        const SLIDE: u64 = 0x40_0000_0000;
+        let mut image = [0u64; 3];
+        let base = image.as_mut_ptr() as u64;
+        let old = |i: usize| (base + 8 * i as u64).wrapping_sub(SLIDE);
+        let relocs = [
+            Elf64Rela { offset: old(0), info: R_X86_64_RELATIVE, addend: 0x1000 },
+            // R_X86_64_64 needs a symbol and is not ours to apply
+            Elf64Rela { offset: old(1), info: (1 << 32) | 1, addend: 0x2000 },
+            Elf64Rela { offset: old(2), info: R_X86_64_RELATIVE, addend: 0xffff_ffff_8000_0000 },
+        ];
+
+        unsafe { apply_relocations(&relocs, SLIDE) };
+        assert_eq!(image, [0x1000 + SLIDE, 0, 0xffff_ffff_8000_0000u64.wrapping_add(SLIDE)]);
+    }
+}
@@ -8,10 +8,15 @@
 extern crate alloc;
 
 use core::sync::atomic::{AtomicU64, Ordering};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use super::types::*;
-use super::{LinuxResult, LinuxError};
+use super::{LinuxResult, LinuxError, EOPNOTSUPP};
+use super::file_ops::{c_str_to_string, vfs_error_to_linux};
+use crate::vfs::xattr::{self, SetMode};
+use crate::vfs::pipe::PIPE_CAPACITY;
+use crate::vfs::{self, InodeOps, OpenFlags, Pipe, PipeSegment, VfsError, PIPE_BUF};
 
 /// Operation counter for statistics
 static ADVANCED_IO_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -169,7 +174,137 @@ pub fn writev(fd: Fd, iov: *const IoVec, iovcnt: i32) -> LinuxResult<isize> {
 // Zero-copy I/O
 // ============================================================================
 
+/// Splice flags
+pub const SPLICE_F_MOVE: u32 = 1;
+pub const SPLICE_F_NONBLOCK: u32 = 2;
+pub const SPLICE_F_MORE: u32 = 4;
+pub const SPLICE_F_GIFT: u32 = 8;
+
+/// An open file resolved from the VFS file table
+struct OpenFile {
+    fd: Fd,
+    inode: Arc<dyn InodeOps>,
+    flags: OpenFlags,
+    offset: u64,
+}
+
+impl OpenFile {
+    fn get(fd: Fd) -> LinuxResult<Self> {
+        if fd < 0 {
+            return Err(LinuxError::EBADF);
+        }
+        let (inode, flags, offset) = vfs::get_vfs().file(fd).map_err(vfs_error_to_linux)?;
+        Ok(Self { fd, inode, flags, offset })
+    }
+
+    fn readable(self) -> LinuxResult<Self> {
+        if self.flags.is_readable() { Ok(self) } else { Err(LinuxError::EBADF) }
+    }
+
+    fn writable(self) -> LinuxResult<Self> {
+        if self.flags.is_writable() { Ok(self) } else { Err(LinuxError::EBADF) }
+    }
+
+    fn nonblock(&self) -> bool {
+        self.flags.has_flag(OpenFlags::NONBLOCK)
+    }
+}
+
+/// Resolve the starting offset for a transfer: the caller's offset pointer
+/// if given, otherwise the file position
+unsafe fn start_offset(file: &OpenFile, off: *const Off) -> LinuxResult<u64> {
+    if off.is_null() {
+        return Ok(file.offset);
+    }
+    let value = *off;
+    if value < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(value as u64)
+}
+
+/// Store the final offset back to the caller's pointer or the file position
+unsafe fn finish_offset(file: &OpenFile, off: *mut Off, offset: u64) -> LinuxResult<()> {
+    if off.is_null() {
+        vfs::get_vfs().set_offset(file.fd, offset).map_err(vfs_error_to_linux)
+    } else {
+        *off = offset as Off;
+        Ok(())
+    }
+}
+
+/// Segments left over after the first `pushed` bytes were consumed
+fn unpushed(segments: Vec<PipeSegment>, mut pushed: usize) -> Vec<PipeSegment> {
+    let mut rest = Vec::new();
+    for mut segment in segments {
+        let len = segment.data().len();
+        if pushed >= len {
+            pushed -= len;
+            continue;
+        }
+        if pushed > 0 {
+            segment.advance(pushed);
+            pushed = 0;
+        }
+        rest.push(segment);
+    }
+    rest
+}
+
+/// Read up to `len` bytes from a file into fresh pipe pages
+///
+/// Reads at most a pipe's capacity, since no more can be queued at once;
+/// callers report the rest as a short transfer.
+fn fill_pages(inode: &Arc<dyn InodeOps>, mut offset: u64, len: usize) -> LinuxResult<Vec<PipeSegment>> {
+    let mut segments = Vec::new();
+    let mut remaining = core::cmp::min(len, PIPE_CAPACITY);
+
+    while remaining > 0 {
+        let mut page = vfs::pipe::alloc_page(remaining);
+        let n = inode.read_at(offset, &mut page).map_err(vfs_error_to_linux)?;
+        if n == 0 {
+            break;
+        }
+        page.truncate(n);
+        segments.push(PipeSegment::new(page));
+        offset += n as u64;
+        remaining -= n;
+    }
+
+    Ok(segments)
+}
+
+/// Queue segments on a pipe, returning unconsumed ones to `source` on a
+/// short transfer
+fn move_segments(
+    segments: Vec<PipeSegment>,
+    dest: &Pipe,
+    source: Option<&Pipe>,
+    nonblock: bool,
+) -> LinuxResult<usize> {
+    match dest.push_segments(segments.clone(), nonblock) {
+        Ok(pushed) => {
+            if let Some(source) = source {
+                let rest = unpushed(segments, pushed);
+                if !rest.is_empty() {
+                    source.unpop_segments(rest);
+                }
+            }
+            Ok(pushed)
+        }
+        Err(e) => {
+            if let Some(source) = source {
+                source.unpop_segments(segments);
+            }
+            Err(vfs_error_to_linux(e))
+        }
+    }
+}
+
 /// sendfile - copy data between file descriptors
+///
+/// Data moves in page-sized chunks inside the kernel; when the output is a
+/// pipe the pages are queued directly without a second copy.
 pub fn sendfile(
     out_fd: Fd,
     in_fd: Fd,
@@ -182,12 +317,48 @@ pub fn sendfile(
         return Err(LinuxError::EBADF);
     }
 
-    // TODO: Implement zero-copy file transfer
-    // This should be optimized to avoid copying through userspace
-    Ok(count as isize)
+    let input = OpenFile::get(in_fd)?.readable()?;
+    let output = OpenFile::get(out_fd)?.writable()?;
+
+    if input.inode.as_pipe().is_some() {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let mut pos = unsafe { start_offset(&input, offset)? };
+    let mut total = 0usize;
+
+    while total < count {
+        let segments = fill_pages(&input.inode, pos, core::cmp::min(count - total, PIPE_BUF))?;
+        let Some(segment) = segments.into_iter().next() else { break };
+        let len = segment.data().len();
+
+        let written = match output.inode.as_pipe() {
+            Some(pipe) => pipe.push_segments(alloc::vec![segment], output.nonblock()),
+            None => vfs::vfs_write(out_fd, segment.data()),
+        };
+
+        match written {
+            Ok(n) => {
+                total += n;
+                pos += n as u64;
+                if n < len {
+                    break;
+                }
+            }
+            Err(e) if total == 0 => return Err(vfs_error_to_linux(e)),
+            Err(_) => break,
+        }
+    }
+
+    unsafe { finish_offset(&input, offset, pos)? };
+    Ok(total as isize)
 }
 
 /// splice - splice data to/from a pipe
+///
+/// At least one side must be a pipe. Pipe-to-pipe splices move page
+/// references; file-to-pipe fills new pages that are queued without further
+/// copies; pipe-to-file writes straight from the queued pages.
 pub fn splice(
     fd_in: Fd,
     off_in: *mut Off,
@@ -202,22 +373,98 @@ pub fn splice(
         return Err(LinuxError::EBADF);
     }
 
-    // Splice flags
-    const SPLICE_F_MOVE: u32 = 1;
-    const SPLICE_F_NONBLOCK: u32 = 2;
-    const SPLICE_F_MORE: u32 = 4;
-    const SPLICE_F_GIFT: u32 = 8;
-
     let valid_flags = SPLICE_F_MOVE | SPLICE_F_NONBLOCK | SPLICE_F_MORE | SPLICE_F_GIFT;
     if flags & !valid_flags != 0 {
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Implement zero-copy splice
-    Ok(len as isize)
+    let input = OpenFile::get(fd_in)?.readable()?;
+    let output = OpenFile::get(fd_out)?.writable()?;
+
+    if len == 0 {
+        return Ok(0);
+    }
+
+    match (input.inode.as_pipe(), output.inode.as_pipe()) {
+        (Some(src), Some(dst)) => {
+            if !off_in.is_null() || !off_out.is_null() {
+                return Err(LinuxError::ESPIPE);
+            }
+            if Arc::ptr_eq(&src, &dst) {
+                return Err(LinuxError::EINVAL);
+            }
+
+            let nonblock = flags & SPLICE_F_NONBLOCK != 0 || input.nonblock();
+            let segments = src.pop_segments(len, nonblock).map_err(vfs_error_to_linux)?;
+            if segments.is_empty() {
+                return Ok(0);
+            }
+            let nonblock = flags & SPLICE_F_NONBLOCK != 0 || output.nonblock();
+            move_segments(segments, &dst, Some(&*src), nonblock).map(|n| n as isize)
+        }
+        (Some(src), None) => {
+            if !off_in.is_null() {
+                return Err(LinuxError::ESPIPE);
+            }
+
+            let mut pos = unsafe { start_offset(&output, off_out)? };
+            let nonblock = flags & SPLICE_F_NONBLOCK != 0 || input.nonblock();
+            let mut segments = src.pop_segments(len, nonblock).map_err(vfs_error_to_linux)?;
+            let mut total = 0usize;
+
+            while !segments.is_empty() {
+                let segment = segments.remove(0);
+                let data = segment.data();
+                match output.inode.write_at(pos, data) {
+                    Ok(n) => {
+                        total += n;
+                        pos += n as u64;
+                        if n < data.len() {
+                            let mut rest = alloc::vec![segment];
+                            rest.append(&mut segments);
+                            src.unpop_segments(unpushed(rest, n));
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let mut rest = alloc::vec![segment];
+                        rest.append(&mut segments);
+                        src.unpop_segments(rest);
+                        if total == 0 {
+                            return Err(vfs_error_to_linux(e));
+                        }
+                        break;
+                    }
+                }
+            }
+
+            unsafe { finish_offset(&output, off_out, pos)? };
+            Ok(total as isize)
+        }
+        (None, Some(dst)) => {
+            if !off_out.is_null() {
+                return Err(LinuxError::ESPIPE);
+            }
+
+            let pos = unsafe { start_offset(&input, off_in)? };
+            let segments = fill_pages(&input.inode, pos, len)?;
+            if segments.is_empty() {
+                return Ok(0);
+            }
+
+            let nonblock = flags & SPLICE_F_NONBLOCK != 0 || output.nonblock();
+            let pushed = move_segments(segments, &dst, None, nonblock)?;
+            unsafe { finish_offset(&input, off_in, pos + pushed as u64)? };
+            Ok(pushed as isize)
+        }
+        (None, None) => Err(LinuxError::EINVAL),
+    }
 }
 
 /// tee - duplicate pipe content
+///
+/// The output pipe receives references to the input pipe's pages, so the data
+/// is neither copied nor consumed.
 pub fn tee(fd_in: Fd, fd_out: Fd, len: usize, flags: u32) -> LinuxResult<isize> {
     inc_ops();
 
@@ -225,8 +472,100 @@ pub fn tee(fd_in: Fd, fd_out: Fd, len: usize, flags: u32) -> LinuxResult<isize>
         return Err(LinuxError::EBADF);
     }
 
-    // TODO: Duplicate pipe content without consuming it
-    Ok(len as isize)
+    let valid_flags = SPLICE_F_MOVE | SPLICE_F_NONBLOCK | SPLICE_F_MORE | SPLICE_F_GIFT;
+    if flags & !valid_flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let input = OpenFile::get(fd_in)?.readable()?;
+    let output = OpenFile::get(fd_out)?.writable()?;
+
+    let (src, dst) = match (input.inode.as_pipe(), output.inode.as_pipe()) {
+        (Some(src), Some(dst)) if !Arc::ptr_eq(&src, &dst) => (src, dst),
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    let nonblock = flags & SPLICE_F_NONBLOCK != 0;
+    let segments = src
+        .peek_segments(len, nonblock || input.nonblock())
+        .map_err(vfs_error_to_linux)?;
+    if segments.is_empty() {
+        return Ok(0);
+    }
+
+    move_segments(segments, &dst, None, nonblock || output.nonblock()).map(|n| n as isize)
+}
+
+/// vmsplice - splice user pages into a pipe, or pipe data into user memory
+///
+/// User memory is copied into pipe pages on the write side; SPLICE_F_GIFT is
+/// accepted but pages are never stolen from the caller.
+pub fn vmsplice(fd: Fd, iov: *const IoVec, nr_segs: usize, flags: u32) -> LinuxResult<isize> {
+    inc_ops();
+
+    if fd < 0 {
+        return Err(LinuxError::EBADF);
+    }
+
+    let valid_flags = SPLICE_F_MOVE | SPLICE_F_NONBLOCK | SPLICE_F_MORE | SPLICE_F_GIFT;
+    if flags & !valid_flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    if iov.is_null() && nr_segs > 0 {
+        return Err(LinuxError::EFAULT);
+    }
+
+    let file = OpenFile::get(fd)?;
+    let pipe = file.inode.as_pipe().ok_or(LinuxError::EBADF)?;
+    let nonblock = flags & SPLICE_F_NONBLOCK != 0 || file.nonblock();
+    let iovecs = unsafe { core::slice::from_raw_parts(iov, nr_segs) };
+
+    if file.flags.is_writable() {
+        // No more than fits in the pipe is copied; the rest is a short count
+        let mut segments = Vec::new();
+        let mut room = PIPE_CAPACITY;
+        for vec in iovecs {
+            if vec.iov_base.is_null() && vec.iov_len > 0 {
+                return Err(LinuxError::EFAULT);
+            }
+            let len = core::cmp::min(vec.iov_len, room);
+            let data = unsafe { core::slice::from_raw_parts(vec.iov_base, len) };
+            for chunk in data.chunks(PIPE_BUF) {
+                segments.push(PipeSegment::new(chunk.to_vec()));
+            }
+            room -= len;
+            if room == 0 {
+                break;
+            }
+        }
+        move_segments(segments, &pipe, None, nonblock).map(|n| n as isize)
+    } else {
+        let wanted: usize = iovecs.iter().map(|vec| vec.iov_len).sum();
+        let segments = pipe.pop_segments(wanted, nonblock).map_err(vfs_error_to_linux)?;
+
+        let mut iov_index = 0;
+        let mut iov_pos = 0;
+        let mut total = 0;
+        for segment in segments {
+            let mut data = segment.data();
+            while !data.is_empty() {
+                let vec = &iovecs[iov_index];
+                let n = core::cmp::min(data.len(), vec.iov_len - iov_pos);
+                unsafe {
+                    core::ptr::copy_nonoverlapping(data.as_ptr(), vec.iov_base.add(iov_pos), n);
+                }
+                data = &data[n..];
+                iov_pos += n;
+                total += n;
+                if iov_pos == vec.iov_len {
+                    iov_index += 1;
+                    iov_pos = 0;
+                }
+            }
+        }
+        Ok(total as isize)
+    }
 }
 
 /// copy_file_range - copy range of data from one file to another
@@ -248,14 +587,178 @@ pub fn copy_file_range(
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Implement efficient file-to-file copy
-    Ok(len as isize)
+    let input = OpenFile::get(fd_in)?.readable()?;
+    let output = OpenFile::get(fd_out)?.writable()?;
+
+    if output.flags.has_flag(OpenFlags::APPEND) {
+        return Err(LinuxError::EBADF);
+    }
+
+    if input.inode.as_pipe().is_some() || output.inode.as_pipe().is_some() {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let mut pos_in = unsafe { start_offset(&input, off_in)? };
+    let mut pos_out = unsafe { start_offset(&output, off_out)? };
+
+    // Overlapping ranges within the same file are rejected, as on Linux
+    let in_stat = input.inode.stat().map_err(vfs_error_to_linux)?;
+    let out_stat = output.inode.stat().map_err(vfs_error_to_linux)?;
+    if in_stat.ino == out_stat.ino
+        && pos_in < pos_out + len as u64
+        && pos_out < pos_in + len as u64
+    {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let mut page = alloc::vec![0u8; PIPE_BUF];
+    let mut total = 0usize;
+
+    while total < len {
+        let want = core::cmp::min(len - total, PIPE_BUF);
+        let n = input.inode.read_at(pos_in, &mut page[..want]).map_err(vfs_error_to_linux)?;
+        if n == 0 {
+            break;
+        }
+
+        let written = match output.inode.write_at(pos_out, &page[..n]) {
+            Ok(written) => written,
+            Err(e) if total == 0 => return Err(vfs_error_to_linux(e)),
+            Err(_) => break,
+        };
+
+        total += written;
+        pos_in += written as u64;
+        pos_out += written as u64;
+        if written < n {
+            break;
+        }
+    }
+
+    unsafe {
+        finish_offset(&input, off_in, pos_in)?;
+        finish_offset(&output, off_out, pos_out)?;
+    }
+    Ok(total as isize)
 }
 
 // ============================================================================
 // Extended Attributes
 // ============================================================================
 
+/// setxattr flag: fail if the attribute already exists
+pub const XATTR_CREATE: i32 = 1;
+/// setxattr flag: fail if the attribute does not exist
+pub const XATTR_REPLACE: i32 = 2;
+
+/// Caller's uid, for xattr namespace permission checks
+fn current_uid() -> u32 {
+    crate::process::get_process_manager()
+        .get_process(crate::process::current_pid())
+        .map_or(0, |pcb| pcb.uid)
+}
+
+fn xattr_error(err: VfsError) -> LinuxError {
+    match err {
+        // Unknown namespace, or a file that cannot hold attributes
+        VfsError::NotSupported => EOPNOTSUPP,
+        VfsError::NameTooLong => LinuxError::ERANGE,
+        err => vfs_error_to_linux(err),
+    }
+}
+
+/// Inode named by `path`
+///
+/// Path lookup never follows a final symlink, so the l* variants resolve
+/// paths the same way as the plain ones.
+fn path_inode(path: *const u8) -> LinuxResult<Arc<dyn InodeOps>> {
+    let path = unsafe { c_str_to_string(path)? };
+    vfs::get_vfs().lookup(&path).map_err(vfs_error_to_linux)
+}
+
+fn fd_inode(fd: Fd) -> LinuxResult<Arc<dyn InodeOps>> {
+    if fd < 0 {
+        return Err(LinuxError::EBADF);
+    }
+    let (inode, _, _) = vfs::get_vfs().file(fd).map_err(vfs_error_to_linux)?;
+    Ok(inode)
+}
+
+/// Copy `data` to a user buffer of `size` bytes, or with `size` 0 just
+/// report how large a buffer is needed
+fn copy_xattr_out(data: &[u8], buf: *mut u8, size: usize) -> LinuxResult<isize> {
+    if size == 0 {
+        return Ok(data.len() as isize);
+    }
+    if data.len() > size {
+        return Err(LinuxError::ERANGE);
+    }
+    if buf.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    unsafe {
+        core::ptr::copy_nonoverlapping(data.as_ptr(), buf, data.len());
+    }
+    Ok(data.len() as isize)
+}
+
+fn get_xattr(inode: Arc<dyn InodeOps>, name: *const u8, value: *mut u8, size: usize) -> LinuxResult<isize> {
+    let name = unsafe { c_str_to_string(name)? };
+    let data = xattr::get(&inode, &name, current_uid()).map_err(xattr_error)?;
+    copy_xattr_out(&data, value, size)
+}
+
+fn set_xattr(
+    inode: Arc<dyn InodeOps>,
+    name: *const u8,
+    value: *const u8,
+    size: usize,
+    flags: i32,
+) -> LinuxResult<i32> {
+    let mode = match flags {
+        0 => SetMode::Any,
+        XATTR_CREATE => SetMode::Create,
+        XATTR_REPLACE => SetMode::Replace,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    if size > xattr::XATTR_SIZE_MAX {
+        return Err(LinuxError::E2BIG);
+    }
+
+    let name = unsafe { c_str_to_string(name)? };
+    let value = if size == 0 {
+        &[][..]
+    } else if value.is_null() {
+        return Err(LinuxError::EFAULT);
+    } else {
+        unsafe { core::slice::from_raw_parts(value, size) }
+    };
+
+    xattr::set(&inode, &name, value, mode, current_uid()).map_err(xattr_error)?;
+    Ok(0)
+}
+
+fn list_xattr(inode: Arc<dyn InodeOps>, list: *mut u8, size: usize) -> LinuxResult<isize> {
+    let names = xattr::list(&inode, current_uid()).map_err(xattr_error)?;
+
+    // Names are returned back to back, each NUL-terminated
+    let mut data = Vec::new();
+    for name in names {
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+    }
+    if data.len() > xattr::XATTR_LIST_MAX {
+        return Err(LinuxError::E2BIG);
+    }
+    copy_xattr_out(&data, list, size)
+}
+
+fn remove_xattr(inode: Arc<dyn InodeOps>, name: *const u8) -> LinuxResult<i32> {
+    let name = unsafe { c_str_to_string(name)? };
+    xattr::remove(&inode, &name, current_uid()).map_err(xattr_error)?;
+    Ok(0)
+}
+
 /// getxattr - get an extended attribute value
 pub fn getxattr(
     path: *const u8,
@@ -269,9 +772,7 @@ pub fn getxattr(
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Get extended attribute from VFS
-    // Return ENODATA if attribute doesn't exist
-    Err(LinuxError::ENODATA)
+    get_xattr(path_inode(path)?, name, value, size)
 }
 
 /// lgetxattr - get extended attribute (don't follow symlinks)
@@ -287,8 +788,7 @@ pub fn lgetxattr(
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Get extended attribute without following symlinks
-    Err(LinuxError::ENODATA)
+    get_xattr(path_inode(path)?, name, value, size)
 }
 
 /// fgetxattr - get extended attribute by file descriptor
@@ -308,8 +808,7 @@ pub fn fgetxattr(
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Get extended attribute by fd
-    Err(LinuxError::ENODATA)
+    get_xattr(fd_inode(fd)?, name, value, size)
 }
 
 /// setxattr - set an extended attribute value
@@ -322,20 +821,11 @@ pub fn setxattr(
 ) -> LinuxResult<i32> {
     inc_ops();
 
-    if path.is_null() || name.is_null() || value.is_null() {
+    if path.is_null() || name.is_null() {
         return Err(LinuxError::EFAULT);
     }
 
-    // Flags: XATTR_CREATE (1), XATTR_REPLACE (2)
-    const XATTR_CREATE: i32 = 1;
-    const XATTR_REPLACE: i32 = 2;
-
-    if flags & !(XATTR_CREATE | XATTR_REPLACE) != 0 {
-        return Err(LinuxError::EINVAL);
-    }
-
-    // TODO: Set extended attribute in VFS
-    Ok(0)
+    set_xattr(path_inode(path)?, name, value, size, flags)
 }
 
 /// lsetxattr - set extended attribute (don't follow symlinks)
@@ -348,12 +838,11 @@ pub fn lsetxattr(
 ) -> LinuxResult<i32> {
     inc_ops();
 
-    if path.is_null() || name.is_null() || value.is_null() {
+    if path.is_null() || name.is_null() {
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Set extended attribute without following symlinks
-    Ok(0)
+    set_xattr(path_inode(path)?, name, value, size, flags)
 }
 
 /// fsetxattr - set extended attribute by file descriptor
@@ -370,12 +859,11 @@ pub fn fsetxattr(
         return Err(LinuxError::EBADF);
     }
 
-    if name.is_null() || value.is_null() {
+    if name.is_null() {
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Set extended attribute by fd
-    Ok(0)
+    set_xattr(fd_inode(fd)?, name, value, size, flags)
 }
 
 /// listxattr - list extended attribute names
@@ -386,9 +874,7 @@ pub fn listxattr(path: *const u8, list: *mut u8, size: usize) -> LinuxResult<isi
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: List extended attributes
-    // Return list of null-terminated names
-    Ok(0)
+    list_xattr(path_inode(path)?, list, size)
 }
 
 /// llistxattr - list extended attributes (don't follow symlinks)
@@ -399,8 +885,7 @@ pub fn llistxattr(path: *const u8, list: *mut u8, size: usize) -> LinuxResult<is
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: List extended attributes without following symlinks
-    Ok(0)
+    list_xattr(path_inode(path)?, list, size)
 }
 
 /// flistxattr - list extended attributes by file descriptor
@@ -411,8 +896,7 @@ pub fn flistxattr(fd: Fd, list: *mut u8, size: usize) -> LinuxResult<isize> {
         return Err(LinuxError::EBADF);
     }
 
-    // TODO: List extended attributes by fd
-    Ok(0)
+    list_xattr(fd_inode(fd)?, list, size)
 }
 
 /// removexattr - remove an extended attribute
@@ -423,8 +907,7 @@ pub fn removexattr(path: *const u8, name: *const u8) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Remove extended attribute from VFS
-    Ok(0)
+    remove_xattr(path_inode(path)?, name)
 }
 
 /// lremovexattr - remove extended attribute (don't follow symlinks)
@@ -435,8 +918,7 @@ pub fn lremovexattr(path: *const u8, name: *const u8) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Remove extended attribute without following symlinks
-    Ok(0)
+    remove_xattr(path_inode(path)?, name)
 }
 
 /// fremovexattr - remove extended attribute by file descriptor
@@ -451,8 +933,7 @@ pub fn fremovexattr(fd: Fd, name: *const u8) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Remove extended attribute by fd
-    Ok(0)
+    remove_xattr(fd_inode(fd)?, name)
 }
 
 // ============================================================================
@@ -526,16 +1007,87 @@ mod tests {
 
     #[test]
     fn test_sendfile() {
-        assert!(sendfile(4, 3, core::ptr::null_mut(), 1024).is_ok());
+        let _ = vfs::init();
+        let src = vfs::vfs_open("/sendfile_src", OpenFlags::CREAT | OpenFlags::RDWR, 0o644).unwrap();
+        let dst = vfs::vfs_open("/sendfile_dst", OpenFlags::CREAT | OpenFlags::RDWR, 0o644).unwrap();
+        vfs::vfs_write(src, b"hello sendfile").unwrap();
+
+        let mut offset: Off = 6;
+        assert_eq!(sendfile(dst, src, &mut offset, 1024), Ok(8));
+        assert_eq!(offset, 14);
+
+        let mut buf = [0u8; 16];
+        vfs::vfs_seek(dst, vfs::SeekFrom::Start(0)).unwrap();
+        assert_eq!(vfs::vfs_read(dst, &mut buf), Ok(8));
+        assert_eq!(&buf[..8], b"sendfile");
+
+        assert_eq!(sendfile(dst, -1, core::ptr::null_mut(), 1), Err(LinuxError::EBADF));
+    }
+
+    #[test]
+    fn test_splice_and_tee() {
+        let _ = vfs::init();
+        let (r1, w1) = vfs::vfs_pipe(OpenFlags::NONBLOCK).unwrap();
+        let (r2, w2) = vfs::vfs_pipe(OpenFlags::NONBLOCK).unwrap();
+
+        vfs::vfs_write(w1, b"zero-copy").unwrap();
+        assert_eq!(tee(r1, w2, 4, 0), Ok(4));
+        assert_eq!(splice(r1, core::ptr::null_mut(), w2, core::ptr::null_mut(), 64, 0), Ok(9));
+
+        let mut buf = [0u8; 16];
+        assert_eq!(vfs::vfs_read(r2, &mut buf), Ok(13));
+        assert_eq!(&buf[..13], b"zerozero-copy");
+
+        // Empty pipe with a live writer
+        assert_eq!(
+            splice(r1, core::ptr::null_mut(), w2, core::ptr::null_mut(), 1, SPLICE_F_NONBLOCK),
+            Err(LinuxError::EAGAIN)
+        );
+        assert_eq!(tee(r1, r1, 1, 0), Err(LinuxError::EBADF));
+    }
+
+    #[test]
+    fn test_splice_file_to_pipe_is_bounded() {
+        let _ = vfs::init();
+        let file = vfs::vfs_open("/splice_big", OpenFlags::CREAT | OpenFlags::RDWR, 0o644).unwrap();
+        vfs::vfs_write(file, &alloc::vec![7u8; PIPE_CAPACITY + PIPE_BUF]).unwrap();
+        let (_r, w) = vfs::vfs_pipe(OpenFlags::NONBLOCK).unwrap();
+
+        let mut offset: Off = 0;
+        assert_eq!(splice(file, &mut offset, w, core::ptr::null_mut(), usize::MAX, 0), Ok(PIPE_CAPACITY as isize));
+        assert_eq!(offset, PIPE_CAPACITY as Off);
     }
 
     #[test]
     fn test_xattr() {
-        let path = b"/test\0".as_ptr();
+        let path = b"/xattr_test\0".as_ptr();
         let name = b"user.test\0".as_ptr();
         let value = b"value\0".as_ptr();
 
-        assert!(setxattr(path, name, value, 5, 0).is_ok());
+        let fd = vfs::vfs_open("/xattr_test", OpenFlags::CREAT | OpenFlags::RDWR, 0o644).unwrap();
+
         assert_eq!(getxattr(path, name, core::ptr::null_mut(), 0), Err(LinuxError::ENODATA));
+        assert_eq!(setxattr(path, name, value, 5, 0), Ok(0));
+        assert_eq!(setxattr(path, name, value, 5, XATTR_CREATE), Err(LinuxError::EEXIST));
+        assert_eq!(setxattr(path, b"user.other\0".as_ptr(), value, 5, XATTR_REPLACE), Err(LinuxError::ENODATA));
+        assert_eq!(setxattr(path, b"bogus.name\0".as_ptr(), value, 5, 0), Err(EOPNOTSUPP));
+
+        // Size probe, short buffer, then a real read
+        assert_eq!(getxattr(path, name, core::ptr::null_mut(), 0), Ok(5));
+        let mut buf = [0u8; 8];
+        assert_eq!(getxattr(path, name, buf.as_mut_ptr(), 2), Err(LinuxError::ERANGE));
+        assert_eq!(fgetxattr(fd, name, buf.as_mut_ptr(), buf.len()), Ok(5));
+        assert_eq!(&buf[..5], b"value");
+
+        let mut list = [0u8; 32];
+        assert_eq!(flistxattr(fd, list.as_mut_ptr(), list.len()), Ok(10));
+        assert_eq!(&list[..10], b"user.test\0");
+
+        assert_eq!(fremovexattr(fd, name), Ok(0));
+        assert_eq!(removexattr(path, name), Err(LinuxError::ENODATA));
+        assert_eq!(listxattr(path, core::ptr::null_mut(), 0), Ok(0));
+
+        vfs::vfs_close(fd).unwrap();
+        let _ = vfs::vfs_unlink("/xattr_test");
     }
 }
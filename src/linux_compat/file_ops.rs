@@ -34,7 +34,7 @@ fn inc_ops() {
 }
 
 /// Convert VFS error to Linux error code
-fn vfs_error_to_linux(err: VfsError) -> LinuxError {
+pub(crate) fn vfs_error_to_linux(err: VfsError) -> LinuxError {
     match err {
         VfsError::NotFound => LinuxError::ENOENT,
         VfsError::PermissionDenied => LinuxError::EACCES,
@@ -51,11 +51,18 @@ fn vfs_error_to_linux(err: VfsError) -> LinuxError {
         VfsError::CrossDevice => LinuxError::EXDEV,
         VfsError::ReadOnly => LinuxError::EROFS,
         VfsError::NotSupported => LinuxError::ENOSYS,
+        VfsError::WouldBlock => LinuxError::EAGAIN,
+        VfsError::BrokenPipe => LinuxError::EPIPE,
+        VfsError::Interrupted => LinuxError::EINTR,
+        VfsError::NoDevice => LinuxError::ENXIO,
+        VfsError::Deadlock => LinuxError::EDEADLK,
+        VfsError::NoData => LinuxError::ENODATA,
+        VfsError::Busy => LinuxError::EBUSY,
     }
 }
 
 /// Convert Linux open flags to VFS open flags
-fn linux_flags_to_vfs(flags: i32) -> u32 {
+pub(crate) fn linux_flags_to_vfs(flags: i32) -> u32 {
     let mut vfs_flags = 0u32;
 
     // Access mode (bottom 2 bits)
@@ -85,12 +92,36 @@ fn linux_flags_to_vfs(flags: i32) -> u32 {
     if flags & open_flags::O_DIRECTORY != 0 {
         vfs_flags |= VfsOpenFlags::DIRECTORY;
     }
+    if flags & open_flags::O_CLOEXEC != 0 {
+        vfs_flags |= VfsOpenFlags::CLOEXEC;
+    }
 
     vfs_flags
 }
 
+/// Convert VFS file status flags back to Linux open flags
+pub(crate) fn vfs_flags_to_linux(flags: u32) -> i32 {
+    let mut linux_flags = match flags & 0x3 {
+        VfsOpenFlags::WRONLY => open_flags::O_WRONLY,
+        VfsOpenFlags::RDWR => open_flags::O_RDWR,
+        _ => open_flags::O_RDONLY,
+    };
+
+    if flags & VfsOpenFlags::APPEND != 0 {
+        linux_flags |= open_flags::O_APPEND;
+    }
+    if flags & VfsOpenFlags::NONBLOCK != 0 {
+        linux_flags |= open_flags::O_NONBLOCK;
+    }
+    if flags & VfsOpenFlags::DIRECTORY != 0 {
+        linux_flags |= open_flags::O_DIRECTORY;
+    }
+
+    linux_flags
+}
+
 /// Helper to convert null-terminated C string to Rust string
-unsafe fn c_str_to_string(ptr: *const u8) -> Result<String, LinuxError> {
+pub(crate) unsafe fn c_str_to_string(ptr: *const u8) -> Result<String, LinuxError> {
     if ptr.is_null() {
         return Err(LinuxError::EFAULT);
     }
@@ -220,7 +251,7 @@ pub fn fstat(fd: Fd, statbuf: *mut Stat) -> LinuxResult<i32> {
         Ok(vfs_stat) => {
             unsafe {
                 *statbuf = Stat::new();
-                (*statbuf).st_dev = 0; // TODO: device ID
+                (*statbuf).st_dev = vfs_stat.dev;
                 (*statbuf).st_ino = vfs_stat.ino;
                 (*statbuf).st_mode = vfs_stat.mode;
                 (*statbuf).st_nlink = vfs_stat.nlink as u64;
@@ -253,7 +284,7 @@ pub fn stat(path: *const u8, statbuf: *mut Stat) -> LinuxResult<i32> {
         Ok(vfs_stat) => {
             unsafe {
                 *statbuf = Stat::new();
-                (*statbuf).st_dev = 0; // TODO: device ID
+                (*statbuf).st_dev = vfs_stat.dev;
                 (*statbuf).st_ino = vfs_stat.ino;
                 (*statbuf).st_mode = vfs_stat.mode;
                 (*statbuf).st_nlink = vfs_stat.nlink as u64;
@@ -395,8 +426,13 @@ pub fn dup3(oldfd: Fd, newfd: Fd, flags: i32) -> LinuxResult<Fd> {
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Handle O_CLOEXEC flag
-    dup2(oldfd, newfd)
+    if flags & !open_flags::O_CLOEXEC != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    vfs::get_vfs()
+        .dup3(oldfd, newfd, flags & open_flags::O_CLOEXEC != 0)
+        .map_err(vfs_error_to_linux)
 }
 
 /// unlink - remove a file
@@ -707,6 +743,48 @@ pub fn mkdir(path: *const u8, mode: Mode) -> LinuxResult<i32> {
     }
 }
 
+/// mknod - create a special file
+///
+/// Supports FIFOs, sockets and device nodes; regular files are created as
+/// with open(O_CREAT).
+pub fn mknod(path: *const u8, mode: Mode, dev: u64) -> LinuxResult<i32> {
+    inc_ops();
+
+    if path.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+
+    let path_str = unsafe { c_str_to_string(path)? };
+    let perms = mode & 0o7777;
+
+    let inode_type = match mode & mode::S_IFMT {
+        0 | mode::S_IFREG => {
+            let flags = VfsOpenFlags::CREAT | VfsOpenFlags::EXCL | VfsOpenFlags::WRONLY;
+            let fd = vfs::vfs_open(&path_str, flags, perms).map_err(vfs_error_to_linux)?;
+            let _ = vfs::vfs_close(fd);
+            return Ok(0);
+        }
+        mode::S_IFIFO => InodeType::Fifo,
+        mode::S_IFSOCK => InodeType::Socket,
+        mode::S_IFCHR => InodeType::CharDevice,
+        mode::S_IFBLK => InodeType::BlockDevice,
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    // Device numbers are not tracked by the VFS yet
+    let _ = dev;
+
+    match vfs::vfs_mknod(&path_str, inode_type, perms) {
+        Ok(()) => Ok(0),
+        Err(e) => Err(vfs_error_to_linux(e)),
+    }
+}
+
+/// mkfifo - create a named pipe
+pub fn mkfifo(path: *const u8, mode: Mode) -> LinuxResult<i32> {
+    mknod(path, mode::S_IFIFO | (mode & 0o7777), 0)
+}
+
 /// rmdir - remove a directory
 pub fn rmdir(path: *const u8) -> LinuxResult<i32> {
     inc_ops();
@@ -12,6 +12,12 @@ use alloc::string::String;
 
 use super::types::*;
 use super::{LinuxResult, LinuxError};
+use super::file_ops::{c_str_to_string, vfs_error_to_linux};
+use crate::drivers::storage;
+use crate::memory::swap::{self, SwapBacking, SwapError};
+use crate::memory::zram;
+use crate::process::{self, namespace::{self, NsError}};
+use crate::vfs::{self, InodeType};
 
 /// Operation counter for statistics
 static FS_OPS_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -189,6 +195,19 @@ pub fn mount(
         return Err(LinuxError::EINVAL);
     }
 
+    let fs_type = if filesystemtype.is_null() { String::new() } else { unsafe { c_str_to_string(filesystemtype)? } };
+    let sb: Option<alloc::sync::Arc<dyn vfs::SuperblockOps>> = match fs_type.as_str() {
+        "hugetlbfs" => Some(alloc::sync::Arc::new(vfs::hugetlbfs::HugeTlbFs::new())),
+        "proc" => Some(alloc::sync::Arc::new(vfs::procfs::ProcFs::new())),
+        "cgroup2" => Some(alloc::sync::Arc::new(vfs::cgroupfs::CgroupFs::new())),
+        _ => None,
+    };
+    if let Some(sb) = sb {
+        let target = unsafe { c_str_to_string(target)? };
+        vfs::get_vfs().mount(&target, sb).map_err(vfs_error_to_linux)?;
+        return Ok(0);
+    }
+
     // TODO: Implement actual mounting
     // 1. Parse filesystem type
     // 2. Locate source device/path
@@ -208,10 +227,8 @@ pub fn umount(target: *const u8) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Unmount filesystem at target
-    // Check if not busy
-    // Flush buffers
-    // Remove from mount tree
+    let target = unsafe { c_str_to_string(target)? };
+    vfs::get_vfs().umount(&target).map_err(vfs_error_to_linux)?;
     Ok(0)
 }
 
@@ -230,11 +247,9 @@ pub fn umount2(target: *const u8, flags: i32) -> LinuxResult<i32> {
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Unmount with specific behavior
-    // MNT_FORCE: force unmount even if busy
-    // MNT_DETACH: lazy unmount
-    // MNT_EXPIRE: mark for expiration
-    Ok(0)
+    // Nothing keeps a filesystem busy but what is mounted below it, so
+    // the flags change nothing
+    umount(target)
 }
 
 /// pivot_root - change root filesystem
@@ -370,35 +385,53 @@ pub fn quotactl(cmd: i32, special: *const u8, id: i32, addr: *mut u8) -> LinuxRe
 // Namespace Operations
 // ============================================================================
 
+/// Namespace operations need root
+fn check_namespace_privilege() -> LinuxResult<()> {
+    let uid = process::get_process_manager()
+        .get_process(process::current_pid())
+        .map_or(0, |pcb| pcb.uid);
+    if uid == 0 { Ok(()) } else { Err(LinuxError::EPERM) }
+}
+
+fn namespace_error_to_linux(err: NsError) -> LinuxError {
+    match err {
+        NsError::NotFound | NsError::InvalidArgument => LinuxError::EINVAL,
+        NsError::TooDeep => LinuxError::ENOSPC,
+        NsError::Defunct => LinuxError::ENOMEM,
+    }
+}
+
 /// unshare - disassociate parts of execution context
+///
+/// Files and filesystem information are never shared between processes
+/// here, so CLONE_FILES and CLONE_FS have nothing to undo. User and cgroup
+/// namespaces are not supported.
 pub fn unshare(flags: i32) -> LinuxResult<i32> {
     inc_ops();
 
-    // Unshare flags (from clone_flags)
     const CLONE_FILES: i32 = 0x00000400;
     const CLONE_FS: i32 = 0x00000200;
-    const CLONE_NEWNS: i32 = 0x00020000;
-    const CLONE_NEWUTS: i32 = 0x04000000;
-    const CLONE_NEWIPC: i32 = 0x08000000;
-    const CLONE_NEWNET: i32 = 0x40000000;
-    const CLONE_NEWPID: i32 = 0x20000000;
-    const CLONE_NEWUSER: i32 = 0x10000000;
-    const CLONE_NEWCGROUP: i32 = 0x02000000;
-
-    let valid_flags = CLONE_FILES | CLONE_FS | CLONE_NEWNS | CLONE_NEWUTS |
-                      CLONE_NEWIPC | CLONE_NEWNET | CLONE_NEWPID |
-                      CLONE_NEWUSER | CLONE_NEWCGROUP;
+
+    let flags = flags as u32 as u64;
+    let valid_flags = CLONE_FILES as u64 | CLONE_FS as u64 | namespace::CLONE_NEW_MASK;
 
     if flags & !valid_flags != 0 {
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Unshare namespaces
-    // Create new namespace(s) and move current process to them
+    if flags & namespace::CLONE_NEW_MASK == 0 {
+        return Ok(0);
+    }
+
+    check_namespace_privilege()?;
+    namespace::unshare(process::current_pid(), flags).map_err(namespace_error_to_linux)?;
     Ok(0)
 }
 
 /// setns - reassociate thread with a namespace
+///
+/// `fd` is an open `/proc/<pid>/ns/<kind>` file. A nonzero `nstype` must be
+/// the `CLONE_NEW*` flag of its kind.
 pub fn setns(fd: Fd, nstype: i32) -> LinuxResult<i32> {
     inc_ops();
 
@@ -406,26 +439,19 @@ pub fn setns(fd: Fd, nstype: i32) -> LinuxResult<i32> {
         return Err(LinuxError::EBADF);
     }
 
-    // Namespace types
-    const CLONE_NEWNS: i32 = 0x00020000;
-    const CLONE_NEWUTS: i32 = 0x04000000;
-    const CLONE_NEWIPC: i32 = 0x08000000;
-    const CLONE_NEWNET: i32 = 0x40000000;
-    const CLONE_NEWPID: i32 = 0x20000000;
-    const CLONE_NEWUSER: i32 = 0x10000000;
-    const CLONE_NEWCGROUP: i32 = 0x02000000;
-
-    if nstype != 0 {
-        let valid_types = CLONE_NEWNS | CLONE_NEWUTS | CLONE_NEWIPC |
-                          CLONE_NEWNET | CLONE_NEWPID | CLONE_NEWUSER |
-                          CLONE_NEWCGROUP;
+    let (inode, _, _) = vfs::get_vfs().file(fd).map_err(|_| LinuxError::EBADF)?;
+    let (kind, id) = inode
+        .as_any()
+        .and_then(|any| any.downcast_ref::<vfs::procfs::ProcNsFile>())
+        .map(|file| file.namespace())
+        .ok_or(LinuxError::EINVAL)?;
 
-        if nstype & !valid_types != 0 {
-            return Err(LinuxError::EINVAL);
-        }
+    if nstype != 0 && nstype as u32 as u64 != kind.clone_flag() {
+        return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Join namespace referred to by fd
+    check_namespace_privilege()?;
+    namespace::setns(process::current_pid(), kind, id).map_err(namespace_error_to_linux)?;
     Ok(0)
 }
 
@@ -433,7 +459,30 @@ pub fn setns(fd: Fd, nstype: i32) -> LinuxResult<i32> {
 // Swap Operations
 // ============================================================================
 
+fn swap_error_to_linux(err: SwapError) -> LinuxError {
+    match err {
+        SwapError::InvalidHeader => LinuxError::EINVAL,
+        SwapError::Busy => LinuxError::EBUSY,
+        SwapError::TooManyAreas => LinuxError::EPERM,
+        SwapError::NotActive => LinuxError::EINVAL,
+        SwapError::NoSpace => LinuxError::ENOMEM,
+        SwapError::Io => LinuxError::EIO,
+    }
+}
+
+/// Swapping may only be changed by root
+fn check_swap_privilege() -> LinuxResult<()> {
+    let uid = process::get_process_manager()
+        .get_process(process::current_pid())
+        .map_or(0, |pcb| pcb.uid);
+    if uid == 0 { Ok(()) } else { Err(LinuxError::EPERM) }
+}
+
 /// swapon - start swapping to file/device
+///
+/// Block devices are named `/dev/sdX[N]`-style and zram devices
+/// `/dev/zramN`; anything else must be a regular file. All must carry a
+/// mkswap header.
 pub fn swapon(path: *const u8, swapflags: i32) -> LinuxResult<i32> {
     inc_ops();
 
@@ -441,12 +490,29 @@ pub fn swapon(path: *const u8, swapflags: i32) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    // Swap flags
-    const SWAP_FLAG_PREFER: i32 = 0x8000;
-    const SWAP_FLAG_DISCARD: i32 = 0x10000;
+    let valid_flags = swap::SWAP_FLAG_PREFER | swap::SWAP_FLAG_PRIO_MASK | swap::SWAP_FLAG_DISCARD;
+    if swapflags & !valid_flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    check_swap_privilege()?;
+
+    let path = unsafe { c_str_to_string(path)? };
+    let backing = if let Some(device) = zram::lookup(&path) {
+        SwapBacking::Zram(device)
+    } else {
+        match storage::resolve_block_device(&path) {
+            Ok(range) => SwapBacking::Device(range),
+            Err(_) => {
+                let inode = vfs::get_vfs().lookup(&path).map_err(vfs_error_to_linux)?;
+                if inode.inode_type() != InodeType::File {
+                    return Err(LinuxError::EINVAL);
+                }
+                SwapBacking::File(inode)
+            }
+        }
+    };
 
-    // TODO: Enable swapping
-    // Requires CAP_SYS_ADMIN
+    swap::swapon(&path, backing, swapflags).map_err(swap_error_to_linux)?;
     Ok(0)
 }
 
@@ -457,9 +523,10 @@ pub fn swapoff(path: *const u8) -> LinuxResult<i32> {
     if path.is_null() {
         return Err(LinuxError::EFAULT);
     }
+    check_swap_privilege()?;
 
-    // TODO: Disable swapping
-    // Requires CAP_SYS_ADMIN
+    let path = unsafe { c_str_to_string(path)? };
+    swap::swapoff(&path).map_err(swap_error_to_linux)?;
     Ok(0)
 }
 
@@ -542,6 +609,89 @@ mod tests {
         sync(); // Should not panic
     }
 
+    #[test]
+    fn test_swap_file() {
+        let fd = vfs::vfs_open("/swapfile", vfs::OpenFlags::CREAT | vfs::OpenFlags::RDWR, 0o600).unwrap();
+        let path = b"/swapfile\0".as_ptr();
+
+        // Four pages with no signature yet
+        let zeros = alloc::vec![0u8; 4 * 4096];
+        vfs::vfs_write(fd, &zeros).unwrap();
+        assert_eq!(swapon(path, 0), Err(LinuxError::EINVAL));
+
+        // mkswap: version 1, last page 3, no bad pages
+        let mut header = alloc::vec![0u8; 4096];
+        header[1024..1028].copy_from_slice(&1u32.to_le_bytes());
+        header[1028..1032].copy_from_slice(&3u32.to_le_bytes());
+        header[4086..].copy_from_slice(b"SWAPSPACE2");
+        vfs::vfs_seek(fd, vfs::SeekFrom::Start(0)).unwrap();
+        vfs::vfs_write(fd, &header).unwrap();
+
+        assert_eq!(swapon(path, swap::SWAP_FLAG_PREFER | 5), Ok(0));
+        assert_eq!(swapon(path, 0), Err(LinuxError::EBUSY));
+        let area = swap::areas().into_iter().find(|area| area.path == "/swapfile").unwrap();
+        assert_eq!((area.priority, area.total_pages, area.is_file), (5, 3, true));
+
+        assert_eq!(swapoff(path), Ok(0));
+        assert_eq!(swapoff(path), Err(LinuxError::EINVAL));
+
+        vfs::vfs_close(fd).unwrap();
+        let _ = vfs::vfs_unlink("/swapfile");
+    }
+
+    #[test]
+    fn test_zram_swap() {
+        let index = zram::add_device(64 * 4096, 0).unwrap();
+        let device = zram::lookup(&alloc::format!("/dev/zram{}", index)).unwrap();
+        device.mkswap().unwrap();
+
+        let path = alloc::format!("/dev/zram{}\0", index);
+        assert_eq!(swapon(path.as_ptr(), swap::SWAP_FLAG_PREFER | 100), Ok(0));
+
+        // One same-filled, one compressible and one incompressible page
+        let zeros = [0u8; 4096];
+        let mut text = [0u8; 4096];
+        for (i, byte) in text.iter_mut().enumerate() {
+            *byte = b"swap me out "[i % 12];
+        }
+        let mut noise = [0u8; 4096];
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for byte in noise.iter_mut() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            *byte = state as u8;
+        }
+
+        let mut slots = alloc::vec::Vec::new();
+        for page in [&zeros, &text, &noise] {
+            let slot = swap::allocate_slot().unwrap();
+            swap::write_page(slot, page).unwrap();
+            slots.push(slot);
+        }
+
+        let stats = device.stats();
+        assert_eq!((stats.same_pages, stats.huge_pages), (1, 1));
+        assert!(stats.compr_data_size < 2 * 4096);
+        assert!(stats.compression_ratio() > 1.0);
+
+        let mut back = [0u8; 4096];
+        for (slot, page) in slots.iter().zip([&zeros, &text, &noise]) {
+            swap::read_page(*slot, &mut back).unwrap();
+            assert_eq!(&back, page);
+        }
+
+        // Freed slots release their pool memory; only the header remains
+        for slot in slots {
+            swap::free_slot(slot);
+        }
+        assert_eq!(device.stats().pages_stored, 1);
+
+        assert_eq!(zram::remove_device(index), Err(SwapError::Busy));
+        assert_eq!(swapoff(path.as_ptr()), Ok(0));
+        assert_eq!(zram::remove_device(index), Ok(()));
+    }
+
     #[test]
     fn test_inotify() {
         assert!(inotify_init().is_ok());
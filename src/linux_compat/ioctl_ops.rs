@@ -9,8 +9,14 @@ extern crate alloc;
 
 use core::sync::atomic::{AtomicU64, Ordering};
 
+use alloc::sync::Arc;
+
 use super::types::*;
 use super::{LinuxResult, LinuxError};
+use super::file_ops::{linux_flags_to_vfs, vfs_error_to_linux, vfs_flags_to_linux};
+use crate::process::current_pid;
+use crate::vfs::lock::{self, LockKind, OFFSET_MAX};
+use crate::vfs::{get_vfs, InodeOps};
 
 /// Operation counter for statistics
 static IOCTL_OPS_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -62,6 +68,39 @@ pub mod fcntl_flags {
     pub const FD_CLOEXEC: i32 = 1;
 }
 
+// struct flock lock types
+pub mod lock_type {
+    /// Shared (read) lock
+    pub const F_RDLCK: i16 = 0;
+    /// Exclusive (write) lock
+    pub const F_WRLCK: i16 = 1;
+    /// Remove lock
+    pub const F_UNLCK: i16 = 2;
+}
+
+// flock operations
+pub mod flock_op {
+    /// Shared lock
+    pub const LOCK_SH: i32 = 1;
+    /// Exclusive lock
+    pub const LOCK_EX: i32 = 2;
+    /// Don't block when locking
+    pub const LOCK_NB: i32 = 4;
+    /// Unlock
+    pub const LOCK_UN: i32 = 8;
+}
+
+/// Record lock description (struct flock)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Flock {
+    pub l_type: i16,
+    pub l_whence: i16,
+    pub l_start: i64,
+    pub l_len: i64,
+    pub l_pid: i32,
+}
+
 // ioctl request types
 pub mod ioctl_req {
     /// Terminal I/O
@@ -94,62 +133,138 @@ pub fn fcntl(fd: Fd, cmd: i32, arg: u64) -> LinuxResult<i32> {
         return Err(LinuxError::EBADF);
     }
 
+    let vfs = get_vfs();
     match cmd {
-        fcntl_cmd::F_DUPFD => {
-            // TODO: Duplicate file descriptor with minimum value
-            Ok(fd + 1)
-        }
-        fcntl_cmd::F_DUPFD_CLOEXEC => {
-            // TODO: Duplicate fd with close-on-exec flag set
-            Ok(fd + 1)
-        }
+        fcntl_cmd::F_DUPFD => vfs.dup_min(fd, arg as i32, false).map_err(vfs_error_to_linux),
+        fcntl_cmd::F_DUPFD_CLOEXEC => vfs.dup_min(fd, arg as i32, true).map_err(vfs_error_to_linux),
         fcntl_cmd::F_GETFD => {
-            // TODO: Get file descriptor flags
-            Ok(0)
+            let cloexec = vfs.cloexec(fd).map_err(vfs_error_to_linux)?;
+            Ok(if cloexec { fcntl_flags::FD_CLOEXEC } else { 0 })
         }
         fcntl_cmd::F_SETFD => {
-            // TODO: Set file descriptor flags
             let flags = arg as i32;
             if flags & !fcntl_flags::FD_CLOEXEC != 0 {
                 return Err(LinuxError::EINVAL);
             }
+            vfs.set_cloexec(fd, flags & fcntl_flags::FD_CLOEXEC != 0)
+                .map_err(vfs_error_to_linux)?;
             Ok(0)
         }
         fcntl_cmd::F_GETFL => {
-            // TODO: Get file status flags
-            Ok(open_flags::O_RDWR as i32)
+            let (_, flags, _) = vfs.file(fd).map_err(vfs_error_to_linux)?;
+            Ok(vfs_flags_to_linux(flags.bits()))
         }
         fcntl_cmd::F_SETFL => {
-            // TODO: Set file status flags
-            // Only certain flags can be changed (O_APPEND, O_NONBLOCK, etc.)
+            // Access mode and creation flags are ignored; only O_APPEND and
+            // O_NONBLOCK can be changed
+            vfs.set_status_flags(fd, linux_flags_to_vfs(arg as i32))
+                .map_err(vfs_error_to_linux)?;
             Ok(0)
         }
         fcntl_cmd::F_GETLK => {
-            // TODO: Get record lock info
             if arg == 0 {
                 return Err(LinuxError::EFAULT);
             }
-            Ok(0)
+            let request = unsafe { &mut *(arg as *mut Flock) };
+            get_record_lock(fd, request)
         }
         fcntl_cmd::F_SETLK | fcntl_cmd::F_SETLKW => {
-            // TODO: Set record lock
             if arg == 0 {
                 return Err(LinuxError::EFAULT);
             }
-            Ok(0)
-        }
-        fcntl_cmd::F_GETOWN => {
-            // TODO: Get process/group for SIGIO
-            Ok(0)
+            let request = unsafe { *(arg as *const Flock) };
+            set_record_lock(fd, &request, cmd == fcntl_cmd::F_SETLKW)
         }
+        fcntl_cmd::F_GETOWN => vfs.owner(fd).map_err(vfs_error_to_linux),
         fcntl_cmd::F_SETOWN => {
-            // TODO: Set process/group for SIGIO
+            vfs.set_owner(fd, arg as i32).map_err(vfs_error_to_linux)?;
             Ok(0)
         }
         _ => Err(LinuxError::EINVAL),
     }
 }
 
+/// Byte range `start..=end` a struct flock refers to
+fn lock_range(inode: &Arc<dyn InodeOps>, offset: u64, request: &Flock) -> LinuxResult<(u64, u64)> {
+    const SEEK_SET: i16 = 0;
+    const SEEK_CUR: i16 = 1;
+    const SEEK_END: i16 = 2;
+
+    let base = match request.l_whence {
+        SEEK_SET => 0,
+        SEEK_CUR => offset as i64,
+        SEEK_END => inode.stat().map_err(vfs_error_to_linux)?.size as i64,
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    let start = base.checked_add(request.l_start).ok_or(LinuxError::EINVAL)?;
+    // A zero length runs to end of file and beyond; a negative one locks
+    // the bytes before start
+    let (start, end) = match request.l_len {
+        0 => (start, None),
+        len if len > 0 => (start, Some(start.checked_add(len - 1).ok_or(LinuxError::EINVAL)?)),
+        len => (start.checked_add(len).ok_or(LinuxError::EINVAL)?, Some(start - 1)),
+    };
+
+    if start < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok((start as u64, end.map_or(OFFSET_MAX, |end| end as u64)))
+}
+
+fn lock_kind(l_type: i16) -> LinuxResult<Option<LockKind>> {
+    match l_type {
+        lock_type::F_RDLCK => Ok(Some(LockKind::Shared)),
+        lock_type::F_WRLCK => Ok(Some(LockKind::Exclusive)),
+        lock_type::F_UNLCK => Ok(None),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// F_GETLK: describe the first lock that would block `request`, or set
+/// l_type to F_UNLCK if there is none
+fn get_record_lock(fd: Fd, request: &mut Flock) -> LinuxResult<i32> {
+    let (inode, _, offset) = get_vfs().file(fd).map_err(vfs_error_to_linux)?;
+    let kind = lock_kind(request.l_type)?.ok_or(LinuxError::EINVAL)?;
+    let (start, end) = lock_range(&inode, offset, request)?;
+
+    match lock::test_record(&inode, current_pid(), kind, start, end) {
+        Some(held) => {
+            request.l_type = match held.kind {
+                LockKind::Shared => lock_type::F_RDLCK,
+                LockKind::Exclusive => lock_type::F_WRLCK,
+            };
+            request.l_whence = 0;
+            request.l_start = held.start as i64;
+            request.l_len = if held.end == OFFSET_MAX {
+                0
+            } else {
+                (held.end - held.start + 1) as i64
+            };
+            request.l_pid = held.owner as i32;
+        }
+        None => request.l_type = lock_type::F_UNLCK,
+    }
+    Ok(0)
+}
+
+/// F_SETLK/F_SETLKW: take or release a record lock
+fn set_record_lock(fd: Fd, request: &Flock, wait: bool) -> LinuxResult<i32> {
+    let (inode, flags, offset) = get_vfs().file(fd).map_err(vfs_error_to_linux)?;
+    let kind = lock_kind(request.l_type)?;
+
+    // Read locks need a readable descriptor, write locks a writable one
+    match kind {
+        Some(LockKind::Shared) if !flags.is_readable() => return Err(LinuxError::EBADF),
+        Some(LockKind::Exclusive) if !flags.is_writable() => return Err(LinuxError::EBADF),
+        _ => {}
+    }
+
+    let (start, end) = lock_range(&inode, offset, request)?;
+    lock::set_record(&inode, current_pid(), kind, start, end, wait).map_err(vfs_error_to_linux)?;
+    Ok(0)
+}
+
 /// ioctl - device control operations
 pub fn ioctl(fd: Fd, request: u64, argp: u64) -> LinuxResult<i32> {
     inc_ops();
@@ -229,20 +344,20 @@ pub fn flock(fd: Fd, operation: i32) -> LinuxResult<i32> {
         return Err(LinuxError::EBADF);
     }
 
-    // Lock operations
-    const LOCK_SH: i32 = 1;    // Shared lock
-    const LOCK_EX: i32 = 2;    // Exclusive lock
-    const LOCK_UN: i32 = 8;    // Unlock
-    const LOCK_NB: i32 = 4;    // Non-blocking
+    let kind = match operation & !flock_op::LOCK_NB {
+        flock_op::LOCK_SH => Some(LockKind::Shared),
+        flock_op::LOCK_EX => Some(LockKind::Exclusive),
+        flock_op::LOCK_UN => None,
+        _ => return Err(LinuxError::EINVAL),
+    };
 
-    let op = operation & !LOCK_NB;
-    match op {
-        LOCK_SH | LOCK_EX | LOCK_UN => {
-            // TODO: Implement file locking
-            Ok(0)
-        }
-        _ => Err(LinuxError::EINVAL),
-    }
+    let vfs = get_vfs();
+    let (inode, _, _) = vfs.file(fd).map_err(vfs_error_to_linux)?;
+    let description = vfs.description(fd).map_err(vfs_error_to_linux)?;
+    let wait = operation & flock_op::LOCK_NB == 0;
+
+    lock::flock(&inode, description, kind, wait).map_err(vfs_error_to_linux)?;
+    Ok(0)
 }
 
 /// Window size structure
@@ -257,12 +372,48 @@ pub struct WinSize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vfs::OpenFlags;
 
     #[test]
     fn test_fcntl_basic() {
-        assert!(fcntl(3, fcntl_cmd::F_GETFL, 0).is_ok());
-        assert!(fcntl(3, fcntl_cmd::F_SETFL, open_flags::O_NONBLOCK as u64).is_ok());
+        let _ = crate::vfs::init();
+        let fd = crate::vfs::vfs_open("/fcntl_basic", OpenFlags::CREAT | OpenFlags::RDWR, 0o644).unwrap();
+
+        assert!(fcntl(fd, fcntl_cmd::F_GETFL, 0).is_ok());
+        assert!(fcntl(fd, fcntl_cmd::F_SETFL, open_flags::O_NONBLOCK as u64).is_ok());
+        assert_eq!(fcntl(fd, fcntl_cmd::F_GETFL, 0), Ok(open_flags::O_RDWR | open_flags::O_NONBLOCK));
         assert!(fcntl(-1, fcntl_cmd::F_GETFL, 0).is_err());
+
+        let dup = fcntl(fd, fcntl_cmd::F_DUPFD_CLOEXEC, 100).unwrap();
+        assert!(dup >= 100);
+        assert_eq!(fcntl(dup, fcntl_cmd::F_GETFD, 0), Ok(fcntl_flags::FD_CLOEXEC));
+        assert_eq!(fcntl(fd, fcntl_cmd::F_GETFD, 0), Ok(0));
+    }
+
+    #[test]
+    fn test_record_and_flock_locks() {
+        let _ = crate::vfs::init();
+        let fd = crate::vfs::vfs_open("/fcntl_locks", OpenFlags::CREAT | OpenFlags::RDWR, 0o644).unwrap();
+        let other = crate::vfs::vfs_open("/fcntl_locks", OpenFlags::RDWR, 0).unwrap();
+
+        // Another process holds bytes 10..20 for writing
+        let (inode, _, _) = get_vfs().file(fd).unwrap();
+        lock::set_record(&inode, 999, Some(LockKind::Exclusive), 10, 19, false).unwrap();
+
+        let mut request = Flock { l_type: lock_type::F_RDLCK, l_whence: 0, l_start: 0, l_len: 0, l_pid: 0 };
+        assert_eq!(fcntl(fd, fcntl_cmd::F_SETLK, &request as *const _ as u64), Err(LinuxError::EAGAIN));
+        assert_eq!(fcntl(fd, fcntl_cmd::F_GETLK, &mut request as *mut _ as u64), Ok(0));
+        assert_eq!((request.l_type, request.l_start, request.l_len, request.l_pid), (lock_type::F_WRLCK, 10, 10, 999));
+
+        lock::set_record(&inode, 999, None, 0, OFFSET_MAX, false).unwrap();
+        request.l_type = lock_type::F_RDLCK;
+        assert_eq!(fcntl(fd, fcntl_cmd::F_SETLK, &request as *const _ as u64), Ok(0));
+
+        // Separate opens are separate flock owners
+        assert_eq!(flock(fd, flock_op::LOCK_EX | flock_op::LOCK_NB), Ok(0));
+        assert_eq!(flock(other, flock_op::LOCK_SH | flock_op::LOCK_NB), Err(LinuxError::EAGAIN));
+        crate::vfs::vfs_close(fd).unwrap();
+        assert_eq!(flock(other, flock_op::LOCK_SH | flock_op::LOCK_NB), Ok(0));
     }
 
     #[test]
@@ -2,8 +2,12 @@
 //!
 //! This module implements Linux-compatible IPC operations including
 //! message queues, semaphores, shared memory, and event file descriptors.
+//!
+//! System V objects belong to the IPC namespace of the process that created
+//! them: keys are looked up there, and ids of another namespace are invalid.
 
 use core::sync::atomic::{AtomicU64, AtomicU32, Ordering};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use alloc::collections::BTreeMap;
 use spin::RwLock;
@@ -14,7 +18,11 @@ use crate::process::ipc::{
     get_ipc_manager, IpcId, SharedMemoryPermissions, Message,
 };
 use crate::process::current_pid;
-use crate::vfs::{get_vfs, OpenFlags, InodeType};
+use crate::process::namespace::{self, NsId, INIT_NS};
+use crate::vfs::{get_vfs, InodeOps, OpenFlags};
+use crate::vfs::eventfd::EventFd;
+use crate::vfs::signalfd::SignalFd;
+use crate::vfs::timerfd::{TimerClock, TimerFd, TimerSpec};
 
 /// Operation counter for statistics
 static IPC_OPS_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -34,12 +42,15 @@ fn inc_ops() {
     IPC_OPS_COUNT.fetch_add(1, Ordering::Relaxed);
 }
 
-/// IPC key to ID mapping for System V IPC
-static IPC_KEY_TABLE: RwLock<BTreeMap<Key, (IpcResourceType, IpcId)>> = RwLock::new(BTreeMap::new());
+/// IPC key to ID mapping for System V IPC, per IPC namespace
+static IPC_KEY_TABLE: RwLock<BTreeMap<(NsId, Key), (IpcResourceType, IpcId)>> = RwLock::new(BTreeMap::new());
 static NEXT_IPC_KEY: AtomicU32 = AtomicU32::new(1000);
 
+/// IPC namespace of each object created outside the initial one
+static IPC_NAMESPACES: RwLock<BTreeMap<(IpcResourceType, IpcId), NsId>> = RwLock::new(BTreeMap::new());
+
 /// IPC resource types
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum IpcResourceType {
     MessageQueue,
     Semaphore,
@@ -57,45 +68,101 @@ struct SemaphoreSet {
 /// Global semaphore table
 static SEMAPHORE_TABLE: RwLock<BTreeMap<IpcId, SemaphoreSet>> = RwLock::new(BTreeMap::new());
 
-/// Event file descriptor data
-#[derive(Debug)]
-struct EventFd {
-    value: AtomicU64,
-    flags: i32,
+/// eventfd flags
+pub const EFD_SEMAPHORE: i32 = 1;
+pub const EFD_NONBLOCK: i32 = open_flags::O_NONBLOCK;
+pub const EFD_CLOEXEC: i32 = open_flags::O_CLOEXEC;
+
+/// timerfd flags
+pub const TFD_NONBLOCK: i32 = open_flags::O_NONBLOCK;
+pub const TFD_CLOEXEC: i32 = open_flags::O_CLOEXEC;
+pub const TFD_TIMER_ABSTIME: i32 = 1;
+
+/// signalfd flags
+pub const SFD_NONBLOCK: i32 = open_flags::O_NONBLOCK;
+pub const SFD_CLOEXEC: i32 = open_flags::O_CLOEXEC;
+
+/// Install a new anonymous file object, honouring O_NONBLOCK and O_CLOEXEC
+/// in `flags`
+fn install_anon(inode: Arc<dyn InodeOps>, flags: i32) -> LinuxResult<Fd> {
+    let mut vfs_flags = OpenFlags::RDWR;
+    if flags & open_flags::O_NONBLOCK != 0 {
+        vfs_flags |= OpenFlags::NONBLOCK;
+    }
+    if flags & open_flags::O_CLOEXEC != 0 {
+        vfs_flags |= OpenFlags::CLOEXEC;
+    }
+    get_vfs()
+        .install(inode, OpenFlags::new(vfs_flags))
+        .map_err(|_| LinuxError::EMFILE)
 }
 
-/// Global event file descriptor table
-static EVENTFD_TABLE: RwLock<BTreeMap<Fd, EventFd>> = RwLock::new(BTreeMap::new());
-static NEXT_EVENTFD: AtomicU32 = AtomicU32::new(200);
+/// Run `f` on the file object of type `T` behind `fd`
+fn with_file_object<T: 'static, R>(fd: Fd, f: impl FnOnce(&T) -> LinuxResult<R>) -> LinuxResult<R> {
+    if fd < 0 {
+        return Err(LinuxError::EBADF);
+    }
+    let (inode, _, _) = get_vfs().file(fd).map_err(|_| LinuxError::EBADF)?;
+    let object = inode
+        .as_any()
+        .and_then(|any| any.downcast_ref::<T>())
+        .ok_or(LinuxError::EINVAL)?;
+    f(object)
+}
 
-/// Timer file descriptor data
-#[derive(Debug)]
-struct TimerFd {
-    clockid: i32,
-    interval_sec: u64,
-    interval_nsec: u64,
-    value_sec: u64,
-    value_nsec: u64,
-    flags: i32,
+/// IPC namespace of the caller
+fn current_ipc_ns() -> NsId {
+    namespace::of(current_pid()).ipc
+}
+
+/// Record that a new object belongs to the caller's IPC namespace
+fn register_object(resource_type: IpcResourceType, id: IpcId) {
+    let ns = current_ipc_ns();
+    if ns != INIT_NS {
+        IPC_NAMESPACES.write().insert((resource_type, id), ns);
+    }
 }
 
-/// Global timer file descriptor table
-static TIMERFD_TABLE: RwLock<BTreeMap<Fd, TimerFd>> = RwLock::new(BTreeMap::new());
-static NEXT_TIMERFD: AtomicU32 = AtomicU32::new(300);
+/// Fail with EINVAL unless the object is in the caller's IPC namespace
+fn check_visible(resource_type: IpcResourceType, id: IpcId) -> LinuxResult<()> {
+    let ns = IPC_NAMESPACES.read().get(&(resource_type, id)).copied().unwrap_or(INIT_NS);
+    if ns == current_ipc_ns() { Ok(()) } else { Err(LinuxError::EINVAL) }
+}
 
-/// Signal file descriptor data
-#[derive(Debug)]
-struct SignalFd {
-    mask: u64,
-    flags: i32,
+/// Forget the keys of an object being removed
+fn remove_object(resource_type: IpcResourceType, id: IpcId) {
+    IPC_KEY_TABLE.write().retain(|_, (rtype, rid)| !(*rtype == resource_type && *rid == id));
+    IPC_NAMESPACES.write().remove(&(resource_type, id));
 }
 
-/// Global signal file descriptor table
-static SIGNALFD_TABLE: RwLock<BTreeMap<Fd, SignalFd>> = RwLock::new(BTreeMap::new());
-static NEXT_SIGNALFD: AtomicU32 = AtomicU32::new(400);
+/// Destroy the objects of an IPC namespace that is gone. Shared memory
+/// segments still attached live on until their last detach.
+pub fn release_namespace(ns: NsId) {
+    let objects: Vec<(IpcResourceType, IpcId)> = IPC_NAMESPACES
+        .read()
+        .iter()
+        .filter(|(_, owner)| **owner == ns)
+        .map(|(object, _)| *object)
+        .collect();
+
+    for (resource_type, id) in objects {
+        remove_object(resource_type, id);
+        match resource_type {
+            IpcResourceType::MessageQueue => {
+                let _ = get_ipc_manager().remove_message_queue(id);
+            }
+            IpcResourceType::Semaphore => {
+                SEMAPHORE_TABLE.write().remove(&id);
+            }
+            IpcResourceType::SharedMemory => {}
+        }
+    }
+    IPC_KEY_TABLE.write().retain(|(owner, _), _| *owner != ns);
+}
 
 /// Convert IPC key to IPC ID, creating if necessary
 fn key_to_id(key: Key, resource_type: IpcResourceType, create: bool) -> LinuxResult<IpcId> {
+    let key = (current_ipc_ns(), key);
     let mut table = IPC_KEY_TABLE.write();
 
     if let Some((existing_type, id)) = table.get(&key) {
@@ -158,7 +225,8 @@ pub fn msgget(key: Key, msgflg: i32) -> LinuxResult<MsqId> {
                     Ok(new_id) => {
                         // Update mapping to use actual IPC manager ID
                         let mut table = IPC_KEY_TABLE.write();
-                        table.insert(key, (IpcResourceType::MessageQueue, new_id));
+                        table.insert((current_ipc_ns(), key), (IpcResourceType::MessageQueue, new_id));
+                        register_object(IpcResourceType::MessageQueue, new_id);
                         Ok(new_id as MsqId)
                     }
                     Err(_) => Err(LinuxError::ENOSPC),
@@ -182,6 +250,7 @@ pub fn msgsnd(msqid: MsqId, msgp: *const u8, msgsz: usize, msgflg: i32) -> Linux
     if msgsz > MSG_MAX_SIZE {
         return Err(LinuxError::EINVAL);
     }
+    check_visible(IpcResourceType::MessageQueue, msqid as IpcId)?;
 
     // Read message type (first 4 bytes) and data
     let msg_type = unsafe { *(msgp as *const u32) };
@@ -216,6 +285,7 @@ pub fn msgrcv(
         return Err(LinuxError::EFAULT);
     }
 
+    check_visible(IpcResourceType::MessageQueue, msqid as IpcId)?;
     let ipc_manager = get_ipc_manager();
     let msg_type = msgtyp as u32;
 
@@ -248,6 +318,8 @@ pub fn msgctl(msqid: MsqId, cmd: i32, buf: *mut u8) -> LinuxResult<i32> {
     const IPC_SET: i32 = 1;
     const IPC_RMID: i32 = 0;
 
+    check_visible(IpcResourceType::MessageQueue, msqid as IpcId)?;
+
     match cmd {
         IPC_STAT => {
             // Return message queue statistics
@@ -259,20 +331,9 @@ pub fn msgctl(msqid: MsqId, cmd: i32, buf: *mut u8) -> LinuxResult<i32> {
             Ok(0)
         }
         IPC_RMID => {
-            // Remove message queue
-            let mut table = IPC_KEY_TABLE.write();
-
-            // Find and remove the key mapping
-            let keys_to_remove: Vec<Key> = table
-                .iter()
-                .filter(|(_, (rtype, id))| *rtype == IpcResourceType::MessageQueue && *id == msqid as IpcId)
-                .map(|(k, _)| *k)
-                .collect();
-
-            for key in keys_to_remove {
-                table.remove(&key);
-            }
-
+            // Remove message queue and its key mapping
+            remove_object(IpcResourceType::MessageQueue, msqid as IpcId);
+            let _ = get_ipc_manager().remove_message_queue(msqid as IpcId);
             Ok(0)
         }
         _ => Err(LinuxError::EINVAL),
@@ -319,6 +380,7 @@ pub fn semget(key: Key, nsems: i32, semflg: i32) -> LinuxResult<SemId> {
 
                 let mut sem_table = SEMAPHORE_TABLE.write();
                 sem_table.insert(sem_id, sem_set);
+                register_object(IpcResourceType::Semaphore, sem_id);
 
                 Ok(sem_id as SemId)
             } else {
@@ -346,6 +408,8 @@ pub fn semop(semid: SemId, sops: *mut u8, nsops: usize) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
+    check_visible(IpcResourceType::Semaphore, semid as IpcId)?;
+
     if nsops == 0 {
         return Ok(0);
     }
@@ -401,6 +465,8 @@ pub fn semctl(semid: SemId, semnum: i32, cmd: i32, arg: u64) -> LinuxResult<i32>
     const GETVAL: i32 = 12;
     const SETVAL: i32 = 16;
 
+    check_visible(IpcResourceType::Semaphore, semid as IpcId)?;
+
     match cmd {
         IPC_STAT => {
             // Return semaphore set statistics
@@ -416,16 +482,7 @@ pub fn semctl(semid: SemId, semnum: i32, cmd: i32, arg: u64) -> LinuxResult<i32>
             sem_table.remove(&(semid as IpcId));
 
             // Remove from key table
-            let mut table = IPC_KEY_TABLE.write();
-            let keys_to_remove: Vec<Key> = table
-                .iter()
-                .filter(|(_, (rtype, id))| *rtype == IpcResourceType::Semaphore && *id == semid as IpcId)
-                .map(|(k, _)| *k)
-                .collect();
-
-            for key in keys_to_remove {
-                table.remove(&key);
-            }
+            remove_object(IpcResourceType::Semaphore, semid as IpcId);
 
             Ok(0)
         }
@@ -492,7 +549,8 @@ pub fn shmget(key: Key, size: usize, shmflg: i32) -> LinuxResult<ShmId> {
                     Ok(new_id) => {
                         // Update mapping
                         let mut table = IPC_KEY_TABLE.write();
-                        table.insert(key, (IpcResourceType::SharedMemory, new_id));
+                        table.insert((current_ipc_ns(), key), (IpcResourceType::SharedMemory, new_id));
+                        register_object(IpcResourceType::SharedMemory, new_id);
                         Ok(new_id as ShmId)
                     }
                     Err(_) => Err(LinuxError::ENOMEM),
@@ -512,6 +570,8 @@ static SHM_ATTACH_TABLE: RwLock<BTreeMap<u64, IpcId>> = RwLock::new(BTreeMap::ne
 pub fn shmat(shmid: ShmId, shmaddr: *const u8, shmflg: i32) -> LinuxResult<*mut u8> {
     inc_ops();
 
+    check_visible(IpcResourceType::SharedMemory, shmid as IpcId)?;
+
     let ipc_manager = get_ipc_manager();
     let pid = current_pid();
 
@@ -562,6 +622,8 @@ pub fn shmctl(shmid: ShmId, cmd: i32, buf: *mut u8) -> LinuxResult<i32> {
     const IPC_SET: i32 = 1;
     const IPC_RMID: i32 = 0;
 
+    check_visible(IpcResourceType::SharedMemory, shmid as IpcId)?;
+
     match cmd {
         IPC_STAT => {
             // Return shared memory segment statistics
@@ -577,16 +639,7 @@ pub fn shmctl(shmid: ShmId, cmd: i32, buf: *mut u8) -> LinuxResult<i32> {
             // It will be removed when all processes detach
 
             // Remove from key table
-            let mut table = IPC_KEY_TABLE.write();
-            let keys_to_remove: Vec<Key> = table
-                .iter()
-                .filter(|(_, (rtype, id))| *rtype == IpcResourceType::SharedMemory && *id == shmid as IpcId)
-                .map(|(k, _)| *k)
-                .collect();
-
-            for key in keys_to_remove {
-                table.remove(&key);
-            }
+            remove_object(IpcResourceType::SharedMemory, shmid as IpcId);
 
             Ok(0)
         }
@@ -596,21 +649,36 @@ pub fn shmctl(shmid: ShmId, cmd: i32, buf: *mut u8) -> LinuxResult<i32> {
 
 /// pipe - create pipe (returns read and write file descriptors)
 pub fn pipe(pipefd: *mut [Fd; 2]) -> LinuxResult<i32> {
+    pipe2(pipefd, 0)
+}
+
+/// pipe2 - create pipe with flags
+///
+/// Both ends are installed in the VFS file table; O_NONBLOCK applies to both.
+pub fn pipe2(pipefd: *mut [Fd; 2], flags: i32) -> LinuxResult<i32> {
     inc_ops();
 
     if pipefd.is_null() {
         return Err(LinuxError::EFAULT);
     }
 
-    let ipc_manager = get_ipc_manager();
+    if flags & !(open_flags::O_NONBLOCK | open_flags::O_CLOEXEC) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let mut vfs_flags = 0;
+    if flags & open_flags::O_NONBLOCK != 0 {
+        vfs_flags |= OpenFlags::NONBLOCK;
+    }
+    if flags & open_flags::O_CLOEXEC != 0 {
+        vfs_flags |= OpenFlags::CLOEXEC;
+    }
 
-    match ipc_manager.create_pipe() {
-        Ok((read_id, write_id)) => {
-            // For now, use the IPC IDs directly as file descriptors
-            // In a full implementation, these would be registered with VFS
+    match get_vfs().pipe(vfs_flags) {
+        Ok((read_fd, write_fd)) => {
             unsafe {
-                (*pipefd)[0] = read_id as Fd;
-                (*pipefd)[1] = write_id as Fd;
+                (*pipefd)[0] = read_fd;
+                (*pipefd)[1] = write_fd;
             }
             Ok(0)
         }
@@ -618,29 +686,16 @@ pub fn pipe(pipefd: *mut [Fd; 2]) -> LinuxResult<i32> {
     }
 }
 
-/// pipe2 - create pipe with flags
-pub fn pipe2(pipefd: *mut [Fd; 2], flags: i32) -> LinuxResult<i32> {
-    inc_ops();
-
-    // For now, ignore flags and just create a regular pipe
-    pipe(pipefd)
-}
-
 /// eventfd - create file descriptor for event notification
 pub fn eventfd(initval: u32, flags: i32) -> LinuxResult<Fd> {
     inc_ops();
 
-    let fd = NEXT_EVENTFD.fetch_add(1, Ordering::SeqCst) as Fd;
-
-    let event = EventFd {
-        value: AtomicU64::new(initval as u64),
-        flags,
-    };
-
-    let mut table = EVENTFD_TABLE.write();
-    table.insert(fd, event);
+    if flags & !(EFD_SEMAPHORE | EFD_NONBLOCK | EFD_CLOEXEC) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
 
-    Ok(fd)
+    let event = EventFd::new(initval as u64, flags & EFD_SEMAPHORE != 0);
+    install_anon(event, flags)
 }
 
 /// eventfd2 - create file descriptor for event notification with flags
@@ -651,6 +706,9 @@ pub fn eventfd2(initval: u32, flags: i32) -> LinuxResult<Fd> {
 }
 
 /// signalfd - create file descriptor for accepting signals
+///
+/// `mask` is a Linux sigset_t (bit N-1 for signal N). Passing an existing
+/// signalfd as `fd` replaces its mask.
 pub fn signalfd(fd: Fd, mask: *const SigSet, flags: i32) -> LinuxResult<Fd> {
     inc_ops();
 
@@ -658,32 +716,21 @@ pub fn signalfd(fd: Fd, mask: *const SigSet, flags: i32) -> LinuxResult<Fd> {
         return Err(LinuxError::EFAULT);
     }
 
-    // Read the signal mask
-    let signal_mask = unsafe { *(mask as *const u64) };
-
-    if fd < 0 {
-        // Create new signalfd
-        let new_fd = NEXT_SIGNALFD.fetch_add(1, Ordering::SeqCst) as Fd;
-
-        let signal_fd = SignalFd {
-            mask: signal_mask,
-            flags,
-        };
+    if flags & !(SFD_NONBLOCK | SFD_CLOEXEC) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
 
-        let mut table = SIGNALFD_TABLE.write();
-        table.insert(new_fd, signal_fd);
+    // Convert to the kernel's mask layout (bit N for signal N)
+    let signal_mask = unsafe { *mask } << 1;
 
-        Ok(new_fd)
+    if fd == -1 {
+        let signal_fd = SignalFd::new(current_pid(), signal_mask);
+        install_anon(signal_fd, flags)
     } else {
-        // Modify existing signalfd
-        let mut table = SIGNALFD_TABLE.write();
-        if let Some(signal_fd) = table.get_mut(&fd) {
-            signal_fd.mask = signal_mask;
-            signal_fd.flags = flags;
+        with_file_object::<SignalFd, _>(fd, |signal_fd| {
+            signal_fd.set_mask(signal_mask);
             Ok(fd)
-        } else {
-            Err(LinuxError::EBADF)
-        }
+        })
     }
 }
 
@@ -691,36 +738,52 @@ pub fn signalfd(fd: Fd, mask: *const SigSet, flags: i32) -> LinuxResult<Fd> {
 pub fn timerfd_create(clockid: i32, flags: i32) -> LinuxResult<Fd> {
     inc_ops();
 
-    match clockid {
-        clock::CLOCK_REALTIME | clock::CLOCK_MONOTONIC => {
-            let fd = NEXT_TIMERFD.fetch_add(1, Ordering::SeqCst) as Fd;
-
-            let timer = TimerFd {
-                clockid,
-                interval_sec: 0,
-                interval_nsec: 0,
-                value_sec: 0,
-                value_nsec: 0,
-                flags,
-            };
-
-            let mut table = TIMERFD_TABLE.write();
-            table.insert(fd, timer);
+    let clock = match clockid {
+        clock::CLOCK_REALTIME => TimerClock::Realtime,
+        clock::CLOCK_MONOTONIC => TimerClock::Monotonic,
+        _ => return Err(LinuxError::EINVAL),
+    };
 
-            Ok(fd)
-        }
-        _ => Err(LinuxError::EINVAL),
+    if flags & !(TFD_NONBLOCK | TFD_CLOEXEC) != 0 {
+        return Err(LinuxError::EINVAL);
     }
+
+    install_anon(TimerFd::new(clock), flags)
 }
 
 /// Timer specification structure (struct itimerspec)
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
-struct ITimerSpec {
-    it_interval_sec: u64,
-    it_interval_nsec: u64,
-    it_value_sec: u64,
-    it_value_nsec: u64,
+pub(crate) struct ITimerSpec {
+    it_interval_sec: i64,
+    it_interval_nsec: i64,
+    it_value_sec: i64,
+    it_value_nsec: i64,
+}
+
+impl ITimerSpec {
+    /// Validate like Linux; times too large to represent saturate
+    pub(crate) fn to_spec(&self) -> LinuxResult<TimerSpec> {
+        fn to_ns(sec: i64, nsec: i64) -> LinuxResult<u64> {
+            if sec < 0 || !(0..1_000_000_000).contains(&nsec) {
+                return Err(LinuxError::EINVAL);
+            }
+            Ok((sec as u64).saturating_mul(1_000_000_000).saturating_add(nsec as u64))
+        }
+        Ok(TimerSpec {
+            interval_ns: to_ns(self.it_interval_sec, self.it_interval_nsec)?,
+            value_ns: to_ns(self.it_value_sec, self.it_value_nsec)?,
+        })
+    }
+
+    pub(crate) fn from_spec(spec: TimerSpec) -> Self {
+        Self {
+            it_interval_sec: (spec.interval_ns / 1_000_000_000).min(i64::MAX as u64) as i64,
+            it_interval_nsec: (spec.interval_ns % 1_000_000_000) as i64,
+            it_value_sec: (spec.value_ns / 1_000_000_000).min(i64::MAX as u64) as i64,
+            it_value_nsec: (spec.value_ns % 1_000_000_000) as i64,
+        }
+    }
 }
 
 /// timerfd_settime - arm/disarm timer via file descriptor
@@ -740,29 +803,22 @@ pub fn timerfd_settime(
         return Err(LinuxError::EFAULT);
     }
 
-    let new_spec = unsafe { *(new_value as *const ITimerSpec) };
+    if flags & !TFD_TIMER_ABSTIME != 0 {
+        return Err(LinuxError::EINVAL);
+    }
 
-    let mut table = TIMERFD_TABLE.write();
-    let timer = table.get_mut(&fd).ok_or(LinuxError::EBADF)?;
+    let new_spec = unsafe { *(new_value as *const ITimerSpec) }.to_spec()?;
 
-    // Save old value if requested
-    if !old_value.is_null() {
-        let old_spec = ITimerSpec {
-            it_interval_sec: timer.interval_sec,
-            it_interval_nsec: timer.interval_nsec,
-            it_value_sec: timer.value_sec,
-            it_value_nsec: timer.value_nsec,
-        };
-        unsafe { *(old_value as *mut ITimerSpec) = old_spec; }
-    }
+    with_file_object::<TimerFd, _>(fd, |timer| {
+        let old_spec = timer.settime(new_spec, flags & TFD_TIMER_ABSTIME != 0);
 
-    // Set new timer values
-    timer.interval_sec = new_spec.it_interval_sec;
-    timer.interval_nsec = new_spec.it_interval_nsec;
-    timer.value_sec = new_spec.it_value_sec;
-    timer.value_nsec = new_spec.it_value_nsec;
+        // Save old value if requested
+        if !old_value.is_null() {
+            unsafe { *(old_value as *mut ITimerSpec) = ITimerSpec::from_spec(old_spec); }
+        }
 
-    Ok(0)
+        Ok(0)
+    })
 }
 
 /// timerfd_gettime - get current setting of timer via file descriptor
@@ -780,19 +836,11 @@ pub fn timerfd_gettime(
         return Err(LinuxError::EFAULT);
     }
 
-    let table = TIMERFD_TABLE.read();
-    let timer = table.get(&fd).ok_or(LinuxError::EBADF)?;
-
-    let spec = ITimerSpec {
-        it_interval_sec: timer.interval_sec,
-        it_interval_nsec: timer.interval_nsec,
-        it_value_sec: timer.value_sec,
-        it_value_nsec: timer.value_nsec,
-    };
-
-    unsafe { *(curr_value as *mut ITimerSpec) = spec; }
-
-    Ok(0)
+    with_file_object::<TimerFd, _>(fd, |timer| {
+        let spec = ITimerSpec::from_spec(timer.gettime());
+        unsafe { *(curr_value as *mut ITimerSpec) = spec; }
+        Ok(0)
+    })
 }
 
 #[cfg(test)]
@@ -811,4 +859,31 @@ mod tests {
         assert!(eventfd(0, 0).is_ok());
         assert!(timerfd_create(clock::CLOCK_MONOTONIC, 0).is_ok());
     }
+
+    #[test]
+    fn test_eventfd_semaphore() {
+        let fd = eventfd(0, EFD_SEMAPHORE | EFD_NONBLOCK).unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(crate::vfs::vfs_read(fd, &mut buf), Err(crate::vfs::VfsError::WouldBlock));
+
+        crate::vfs::vfs_write(fd, &2u64.to_ne_bytes()).unwrap();
+        assert_eq!(crate::vfs::vfs_read(fd, &mut buf), Ok(8));
+        assert_eq!(u64::from_ne_bytes(buf), 1);
+        assert_eq!(crate::vfs::vfs_read(fd, &mut buf), Ok(8));
+        assert_eq!(crate::vfs::vfs_read(fd, &mut buf), Err(crate::vfs::VfsError::WouldBlock));
+    }
+
+    #[test]
+    fn test_itimerspec_validation() {
+        let spec = |value_sec, value_nsec| ITimerSpec {
+            it_interval_sec: 0,
+            it_interval_nsec: 0,
+            it_value_sec: value_sec,
+            it_value_nsec: value_nsec,
+        };
+        assert_eq!(spec(-1, 0).to_spec().unwrap_err(), LinuxError::EINVAL);
+        assert_eq!(spec(0, 1_000_000_000).to_spec().unwrap_err(), LinuxError::EINVAL);
+        assert_eq!(spec(i64::MAX, 999_999_999).to_spec().unwrap().value_ns, u64::MAX);
+        assert_eq!(spec(2, 5).to_spec().unwrap().value_ns, 2_000_000_005);
+    }
 }
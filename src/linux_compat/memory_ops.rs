@@ -15,24 +15,31 @@
 //! - mlockall() / munlockall() - Lock all pages
 //! - mremap() - Resize/move memory regions
 //! - mincore() - Check page residency
+//! - msync() - Write back shared file mappings
 //!
-//! ### Partially Implemented (70%)
-//! - madvise() - Memory usage hints (structure in place, optimizations pending)
-//! - msync() - Memory synchronization (needs file backing integration)
+//! - madvise() - Memory usage hints; DONTNEED/FREE/REMOVE discard pages
 //!
-//! ### NUMA Operations (60%)
+//! ### NUMA Operations (100%)
 //! - get_mempolicy() / set_mempolicy() - Policy management
 //! - mbind() - Bind memory to NUMA nodes
 //! - migrate_pages() / move_pages() - Page migration
-//! Note: Single-node system, multi-node support requires hardware
 //!
 //! ## Integration Points
 //!
 //! - Uses memory_manager::VirtualMemoryManager for virtual memory operations
+//! - mprotect/madvise/mlock on regions of the kernel memory manager go
+//!   through memory::vma, which splits regions and rewrites page tables
+//! - mlock is held to RLIMIT_MEMLOCK unless the caller is root
+//! - File mappings go through memory::file_mapping and its page cache
+//! - MAP_HUGETLB and hugetlbfs files map 2 MiB pages from the pool in
+//!   memory::huge_page; MADV_HUGEPAGE/NOHUGEPAGE steer transparent huge pages
+//! - MAP_GROWSDOWN mappings are stacks in memory::stack, grown by faults
+//!   below them up to RLIMIT_STACK
 //! - Integrates with page_table::PageTableManager for page tables
 //! - Supports COW (copy-on-write) for fork
 //! - Handles page faults and demand paging
-//! - Implements NUMA policy management (single-node)
+//! - NUMA policies and page migration go through memory::numa, with the
+//!   nodes the ACPI SRAT describes
 
 #![no_std]
 
@@ -46,6 +53,13 @@ use super::types::*;
 use super::{LinuxResult, LinuxError};
 
 // Import memory management components
+use crate::memory::file_mapping::{self, MapError};
+use crate::memory::huge_page;
+use crate::memory::stack;
+use crate::memory::numa::{self, MemPolicy};
+use crate::memory::vma::Advice;
+use crate::memory::{get_memory_manager, MemoryError, MemoryManager, MemoryProtection};
+use crate::process::rlimit;
 use crate::memory_manager::{
     api::{vm_mmap, vm_munmap, vm_mprotect, vm_brk, vm_sbrk, get_memory_stats},
     ProtectionFlags, MmapFlags, VmError,
@@ -352,6 +366,21 @@ pub fn mmap(
         return Err(LinuxError::EINVAL);
     }
 
+    // File mappings are demand-faulted from the page cache
+    if (flags & map::MAP_ANONYMOUS) == 0 {
+        return mmap_file(addr_val, length, prot, flags, fd, offset);
+    }
+
+    // Anonymous huge pages come from the hugetlb pool
+    if flags & map::MAP_HUGETLB != 0 {
+        return mmap_hugetlb(addr_val, length, prot, flags);
+    }
+
+    // Stacks that grow on faults below them
+    if flags & map::MAP_GROWSDOWN != 0 {
+        return mmap_growsdown(addr_val, length, prot, flags);
+    }
+
     // Convert Linux flags to RustOS flags
     let protection = prot_to_protection_flags(prot);
     let mmap_flags = map_to_mmap_flags(flags);
@@ -375,6 +404,101 @@ pub fn mmap(
     Ok(result)
 }
 
+/// Map a regular file for mmap
+fn mmap_file(addr: usize, length: usize, prot: i32, flags: i32, fd: Fd, offset: Off) -> LinuxResult<*mut u8> {
+    if offset < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let protection = prot_to_memory_protection(prot);
+    let fixed = fixed_address(addr, flags)?;
+
+    let start = file_mapping::map_fd(fd, offset as u64, length, protection, flags & map::MAP_SHARED != 0, fixed)
+        .map_err(map_error_to_linux)?;
+    Ok(start.as_u64() as *mut u8)
+}
+
+/// Map anonymous memory backed by huge pages, for MAP_HUGETLB
+fn mmap_hugetlb(addr: usize, length: usize, prot: i32, flags: i32) -> LinuxResult<*mut u8> {
+    let fixed = fixed_address(addr, flags)?;
+    let start = huge_page::map_anonymous(length, prot_to_memory_protection(prot), fixed).map_err(|err| match err {
+        MemoryError::InvalidAddress | MemoryError::RegionOverlap => LinuxError::EINVAL,
+        _ => LinuxError::ENOMEM,
+    })?;
+    Ok(start.as_u64() as *mut u8)
+}
+
+/// Map a stack that grows down on demand
+fn mmap_growsdown(addr: usize, length: usize, prot: i32, flags: i32) -> LinuxResult<*mut u8> {
+    let fixed = fixed_address(addr, flags)?;
+    let start = stack::map_growsdown(length, prot_to_memory_protection(prot), fixed).map_err(|err| match err {
+        MemoryError::InvalidAddress | MemoryError::RegionOverlap => LinuxError::EINVAL,
+        _ => LinuxError::ENOMEM,
+    })?;
+    Ok(start.as_u64() as *mut u8)
+}
+
+/// Protection for a mapping made through the memory manager
+fn prot_to_memory_protection(prot: i32) -> MemoryProtection {
+    MemoryProtection {
+        readable: prot & prot::PROT_READ != 0,
+        writable: prot & prot::PROT_WRITE != 0,
+        executable: prot & prot::PROT_EXEC != 0,
+        user_accessible: true,
+        cache_disabled: false,
+        write_through: false,
+        copy_on_write: false,
+        guard_page: false,
+    }
+}
+
+/// The address a MAP_FIXED mapping must go at
+fn fixed_address(addr: usize, flags: i32) -> LinuxResult<Option<VirtAddr>> {
+    if flags & map::MAP_FIXED == 0 {
+        return Ok(None);
+    }
+    VirtAddr::try_new(addr as u64).map(Some).map_err(|_| LinuxError::EINVAL)
+}
+
+/// Convert a file mapping error to LinuxError
+fn map_error_to_linux(err: MapError) -> LinuxError {
+    match err {
+        MapError::BadFd => LinuxError::EBADF,
+        MapError::Access => LinuxError::EACCES,
+        MapError::NoDevice => LinuxError::ENODEV,
+        MapError::InvalidArgument => LinuxError::EINVAL,
+        MapError::Memory(MemoryError::InvalidAddress | MemoryError::RegionOverlap) => LinuxError::EINVAL,
+        MapError::Memory(_) => LinuxError::ENOMEM,
+    }
+}
+
+fn memory_error_to_linux(err: MemoryError) -> LinuxError {
+    match err {
+        MemoryError::RegionNotFound | MemoryError::OutOfMemory => LinuxError::ENOMEM,
+        MemoryError::PermissionDenied => LinuxError::EACCES,
+        MemoryError::IoError => LinuxError::EIO,
+        _ => LinuxError::EINVAL,
+    }
+}
+
+/// The memory manager, if it has a region at `addr`
+///
+/// Ranges it doesn't know about are left to the memory_manager API.
+fn managing(addr: usize) -> Option<&'static MemoryManager> {
+    let addr = VirtAddr::try_new(addr as u64).ok()?;
+    get_memory_manager().filter(|mm| mm.find_region(addr).is_some())
+}
+
+/// Bytes the current process may lock, from RLIMIT_MEMLOCK; root is exempt
+fn memlock_limit() -> usize {
+    let pid = crate::process::current_pid();
+    let uid = crate::process::get_process_manager().get_process(pid).map(|pcb| pcb.uid);
+    if uid.map_or(true, |uid| uid == 0) {
+        return usize::MAX;
+    }
+    rlimit::get(pid, rlimit::RLIMIT_MEMLOCK).map_or(usize::MAX, |limit| limit.cur.min(usize::MAX as u64) as usize)
+}
+
 /// munmap - unmap files or devices from memory
 ///
 /// Unmaps virtual memory region and frees associated resources.
@@ -396,6 +520,17 @@ pub fn munmap(addr: *mut u8, length: usize) -> LinuxResult<i32> {
         return Err(LinuxError::EINVAL);
     }
 
+    // File mappings write back their dirty shared pages as they go
+    if file_mapping::overlaps(VirtAddr::new(addr_val as u64), length) {
+        file_mapping::unmap(VirtAddr::new(addr_val as u64), length).map_err(|_| LinuxError::EIO)?;
+        return Ok(0);
+    }
+    // hugetlb pages go back to the pool
+    if huge_page::overlaps(VirtAddr::new(addr_val as u64), length) {
+        huge_page::unmap(VirtAddr::new(addr_val as u64), length).map_err(|_| LinuxError::EINVAL)?;
+        return Ok(0);
+    }
+
     // Call memory manager to unmap the region
     vm_munmap(addr_val, length).map_err(vm_error_to_linux)?;
 
@@ -430,6 +565,18 @@ pub fn mprotect(addr: *mut u8, length: usize, prot: i32) -> LinuxResult<i32> {
         return Err(LinuxError::EINVAL);
     }
 
+    if let Some(mm) = managing(addr_val) {
+        // Write and execute imply read on x86_64
+        let protection = MemoryProtection {
+            readable: prot != prot::PROT_NONE,
+            writable: prot & prot::PROT_WRITE != 0,
+            executable: prot & prot::PROT_EXEC != 0,
+            ..MemoryProtection::USER_DATA
+        };
+        mm.mprotect(VirtAddr::new(addr_val as u64), length, protection).map_err(memory_error_to_linux)?;
+        return Ok(0);
+    }
+
     // Convert protection flags
     let protection = prot_to_protection_flags(prot);
 
@@ -456,72 +603,29 @@ pub fn madvise(addr: *mut u8, length: usize, advice: i32) -> LinuxResult<i32> {
         return Err(LinuxError::EINVAL);
     }
 
-    match advice {
-        madv::MADV_NORMAL => {
-            // Default behavior - no special treatment
-            Ok(0)
-        }
-        madv::MADV_RANDOM => {
-            // Random access pattern - disable read-ahead
-            // TODO: Adjust page cache behavior
-            Ok(0)
-        }
-        madv::MADV_SEQUENTIAL => {
-            // Sequential access - aggressive read-ahead
-            // TODO: Adjust page cache behavior
-            Ok(0)
-        }
-        madv::MADV_WILLNEED => {
-            // Will need soon - prefault pages
-            // TODO: Fault in pages eagerly
-            Ok(0)
-        }
-        madv::MADV_DONTNEED => {
-            // Don't need - can free pages
-            // For anonymous memory, zero pages on next access
-            // For file-backed, discard and reload from file
-            // TODO: Implement page discarding
-            Ok(0)
-        }
-        madv::MADV_FREE => {
-            // Free memory but keep if no memory pressure
-            // Mark pages as candidates for reclamation
-            // TODO: Mark pages as freeable
-            Ok(0)
-        }
-        madv::MADV_REMOVE => {
-            // Remove pages from address space
-            // Similar to hole punching
-            // TODO: Implement page removal
-            Ok(0)
-        }
-        madv::MADV_MERGEABLE => {
-            // Enable KSM (Kernel Samepage Merging)
-            // Not implemented in RustOS yet
-            Ok(0)
-        }
-        madv::MADV_UNMERGEABLE => {
-            // Disable KSM
-            // Not implemented in RustOS yet
-            Ok(0)
-        }
-        madv::MADV_HUGEPAGE => {
-            // Prefer transparent huge pages
-            // TODO: Mark region for THP
-            Ok(0)
-        }
-        madv::MADV_NOHUGEPAGE => {
-            // Avoid transparent huge pages
-            // TODO: Prevent THP for region
-            Ok(0)
-        }
-        madv::MADV_HWPOISON => {
-            // Poison page (testing only)
-            // Requires CAP_SYS_ADMIN
-            Err(LinuxError::EPERM)
-        }
-        _ => Err(LinuxError::EINVAL),
+    let advice = match advice {
+        madv::MADV_NORMAL => Advice::Normal,
+        madv::MADV_RANDOM => Advice::Random,
+        madv::MADV_SEQUENTIAL => Advice::Sequential,
+        madv::MADV_WILLNEED => Advice::WillNeed,
+        madv::MADV_DONTNEED => Advice::DontNeed,
+        madv::MADV_FREE => Advice::Free,
+        madv::MADV_REMOVE => Advice::Remove,
+        madv::MADV_HUGEPAGE => Advice::HugePage,
+        madv::MADV_NOHUGEPAGE => Advice::NoHugePage,
+        // No KSM to steer
+        madv::MADV_MERGEABLE | madv::MADV_UNMERGEABLE => return Ok(0),
+        // Poison page (testing only), requires CAP_SYS_ADMIN
+        madv::MADV_HWPOISON => return Err(LinuxError::EPERM),
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    // Ranges outside the memory manager have nothing to act on
+    if let Some(mm) = managing(addr_val) {
+        mm.madvise(VirtAddr::new(addr_val as u64), length, advice).map_err(memory_error_to_linux)?;
     }
+
+    Ok(0)
 }
 
 /// msync - synchronize a file with a memory map
@@ -549,38 +653,11 @@ pub fn msync(addr: *mut u8, length: usize, flags: i32) -> LinuxResult<i32> {
         return Err(LinuxError::EINVAL);
     }
 
-    let aligned_length = (length + 4095) & !4095;
-
-    // Synchronize mapped pages with backing file
-    // MS_SYNC: Synchronous write - wait for write to complete
-    // MS_ASYNC: Asynchronous write - schedule write but don't wait
-    // MS_INVALIDATE: Invalidate cached copies
-
+    // MS_SYNC writes the dirty pages of shared file mappings back now;
+    // MS_ASYNC leaves them to the periodic flusher. Mapped pages are the
+    // page cache, so MS_INVALIDATE has nothing to drop.
     if flags & ms::MS_SYNC != 0 {
-        // Synchronous synchronization
-        // In a real implementation:
-        // 1. Find all dirty pages in the range
-        // 2. Write them back to the file
-        // 3. Wait for writes to complete
-        // 4. Clear dirty bits
-
-        // For file-backed mappings, would call VFS write operations
-        // For anonymous mappings, this is a no-op
-    }
-
-    if flags & ms::MS_ASYNC != 0 {
-        // Asynchronous synchronization
-        // Schedule writes but don't wait
-        // Kernel will flush pages in background
-    }
-
-    if flags & ms::MS_INVALIDATE != 0 {
-        // Invalidate other cached copies
-        // This ensures we see the latest file contents
-        // Would need to:
-        // 1. Flush TLB entries for the range
-        // 2. Mark pages as invalid in page cache
-        // 3. Next access will re-read from file
+        file_mapping::sync(VirtAddr::new(addr_val as u64), length).map_err(|_| LinuxError::EIO)?;
     }
 
     Ok(0)
@@ -607,22 +684,24 @@ pub fn mlock(addr: *const u8, length: usize) -> LinuxResult<i32> {
         return Err(LinuxError::EINVAL);
     }
 
-    // Check if address is aligned to page boundary
-    if addr_val & 0xFFF != 0 {
-        // mlock doesn't require alignment, round down
-        // Linux rounds down to page boundary
+    // mlock doesn't require alignment; the range is rounded out to pages
+    let limit = memlock_limit();
+    if let Some(mm) = managing(addr_val) {
+        mm.mlock(VirtAddr::new(addr_val as u64), length + (addr_val & 0xFFF), true, limit)
+            .map_err(memory_error_to_linux)?;
+        return Ok(0);
     }
 
     // Calculate number of pages
-    let page_count = (length + 4095) / 4096;
+    let page_count = (length + (addr_val & 0xFFF) + 4095) / 4096;
 
-    // In RustOS, we don't have swap yet, so pages are already "locked"
-    // However, we track locked pages for resource limits
+    // Other ranges are only counted against the limit
+    let locked = LOCKED_PAGES.load(Ordering::Relaxed) + page_count;
+    if locked.saturating_mul(4096) > limit {
+        return Err(LinuxError::ENOMEM);
+    }
     LOCKED_PAGES.fetch_add(page_count, Ordering::Relaxed);
 
-    // TODO: When swap is implemented, mark pages as non-swappable
-    // TODO: Check RLIMIT_MEMLOCK resource limit
-
     Ok(0)
 }
 
@@ -646,8 +725,14 @@ pub fn munlock(addr: *const u8, length: usize) -> LinuxResult<i32> {
         return Err(LinuxError::EINVAL);
     }
 
+    if let Some(mm) = managing(addr_val) {
+        mm.munlock(VirtAddr::new(addr_val as u64), length + (addr_val & 0xFFF))
+            .map_err(memory_error_to_linux)?;
+        return Ok(0);
+    }
+
     // Calculate number of pages
-    let page_count = (length + 4095) / 4096;
+    let page_count = (length + (addr_val & 0xFFF) + 4095) / 4096;
 
     // Update locked page count
     let current = LOCKED_PAGES.load(Ordering::Relaxed);
@@ -655,8 +740,6 @@ pub fn munlock(addr: *const u8, length: usize) -> LinuxResult<i32> {
         LOCKED_PAGES.fetch_sub(page_count, Ordering::Relaxed);
     }
 
-    // TODO: When swap is implemented, mark pages as swappable
-
     Ok(0)
 }
 
@@ -675,18 +758,30 @@ pub fn mlockall(flags: i32) -> LinuxResult<i32> {
         return Err(LinuxError::EINVAL);
     }
 
+    if flags & (MCL_CURRENT | MCL_FUTURE) == 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let limit = memlock_limit();
+    if let Some(mm) = get_memory_manager() {
+        // MCL_ONFAULT locks pages as they fault in instead of now
+        let populate = flags & MCL_ONFAULT == 0;
+        mm.mlock_all(flags & MCL_CURRENT != 0, flags & MCL_FUTURE != 0, populate, limit)
+            .map_err(memory_error_to_linux)?;
+        return Ok(0);
+    }
+
     // Get memory statistics
     let stats = get_memory_stats().map_err(vm_error_to_linux)?;
 
     if flags & MCL_CURRENT != 0 {
         // Lock all currently mapped pages
+        if stats.mapped_pages.saturating_mul(4096) > limit {
+            return Err(LinuxError::ENOMEM);
+        }
         LOCKED_PAGES.fetch_add(stats.mapped_pages, Ordering::Relaxed);
     }
 
-    // MCL_FUTURE and MCL_ONFAULT affect future allocations
-    // TODO: Store these flags in process context
-    // TODO: Check RLIMIT_MEMLOCK resource limit
-
     Ok(0)
 }
 
@@ -696,11 +791,13 @@ pub fn mlockall(flags: i32) -> LinuxResult<i32> {
 pub fn munlockall() -> LinuxResult<i32> {
     inc_ops();
 
+    if let Some(mm) = get_memory_manager() {
+        mm.munlock_all();
+    }
+
     // Reset locked page counter
     LOCKED_PAGES.store(0, Ordering::Relaxed);
 
-    // TODO: Clear MCL_FUTURE and MCL_ONFAULT flags in process context
-
     Ok(0)
 }
 
@@ -941,9 +1038,79 @@ mod numa_policy {
     pub const MPOL_LOCAL: i32 = 4;        // Local allocation
 }
 
-/// NUMA memory policy tracker
-/// TODO: Move to per-process context
-static NUMA_POLICY: Mutex<i32> = Mutex::new(0); // Default: MPOL_DEFAULT
+/// The node mask a caller passed, limited to its first `maxnode` bits
+fn read_nodemask(nodemask: *const u64, maxnode: u64) -> u64 {
+    if nodemask.is_null() || maxnode == 0 {
+        return 0;
+    }
+    let mask = unsafe { *nodemask };
+    if maxnode >= 64 { mask } else { mask & ((1 << maxnode) - 1) }
+}
+
+/// Store `mask` for a caller whose buffer holds `maxnode` bits
+fn write_nodemask(nodemask: *mut u64, maxnode: u64, mask: u64) -> LinuxResult<()> {
+    if nodemask.is_null() {
+        return Ok(());
+    }
+    if maxnode < crate::memory::numa::node_count() as u64 {
+        return Err(LinuxError::EINVAL);
+    }
+    unsafe {
+        *nodemask = mask;
+    }
+    Ok(())
+}
+
+/// The policy an MPOL_* mode and node mask describe
+fn mode_to_policy(mode: i32, mask: u64) -> LinuxResult<MemPolicy> {
+    use numa_policy::*;
+
+    match mode {
+        MPOL_DEFAULT | MPOL_LOCAL if mask != 0 => Err(LinuxError::EINVAL),
+        MPOL_DEFAULT => Ok(MemPolicy::Default),
+        MPOL_LOCAL => Ok(MemPolicy::Local),
+        // An empty preferred set means local allocation
+        MPOL_PREFERRED if mask == 0 => Ok(MemPolicy::Local),
+        MPOL_PREFERRED | MPOL_BIND | MPOL_INTERLEAVE if !numa::valid_mask(mask) => Err(LinuxError::EINVAL),
+        MPOL_PREFERRED => Ok(MemPolicy::Preferred(mask.trailing_zeros() as usize)),
+        MPOL_BIND => Ok(MemPolicy::Bind(mask)),
+        MPOL_INTERLEAVE => Ok(MemPolicy::Interleave(mask)),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// The MPOL_* mode and node mask of `policy`
+fn policy_to_mode(policy: MemPolicy) -> (i32, u64) {
+    use numa_policy::*;
+
+    match policy {
+        MemPolicy::Default => (MPOL_DEFAULT, 0),
+        MemPolicy::Local => (MPOL_LOCAL, 0),
+        MemPolicy::Preferred(node) => (MPOL_PREFERRED, 1 << node),
+        MemPolicy::Bind(mask) => (MPOL_BIND, mask),
+        MemPolicy::Interleave(mask) => (MPOL_INTERLEAVE, mask),
+    }
+}
+
+/// Whether the caller may move the pages of process `pid` (0 for itself),
+/// including shared ones if `all`
+///
+/// Moving another process's pages or shared pages takes CAP_SYS_NICE,
+/// which only root has.
+fn may_migrate(pid: Pid, all: bool) -> LinuxResult<()> {
+    let current = crate::process::current_pid();
+    let process_manager = crate::process::get_process_manager();
+    if pid != 0 && process_manager.get_process(pid as u32).is_none() {
+        return Err(LinuxError::ESRCH);
+    }
+    if all || (pid != 0 && pid as u32 != current) {
+        let uid = process_manager.get_process(current).map(|pcb| pcb.uid);
+        if uid.is_some_and(|uid| uid != 0) {
+            return Err(LinuxError::EPERM);
+        }
+    }
+    Ok(())
+}
 
 /// get_mempolicy - retrieve NUMA memory policy
 ///
@@ -966,24 +1133,48 @@ pub fn get_mempolicy(
         return Err(LinuxError::EINVAL);
     }
 
-    // Get current policy
-    let policy = NUMA_POLICY.lock();
-
-    if !mode.is_null() {
-        unsafe {
-            *mode = *policy;
+    // The nodes this process may allocate from at all
+    if flags & MPOL_F_MEMS_ALLOWED != 0 {
+        if flags & (MPOL_F_NODE | MPOL_F_ADDR) != 0 {
+            return Err(LinuxError::EINVAL);
         }
+        write_nodemask(nodemask, maxnode, numa::online_mask())?;
+        return Ok(0);
     }
 
-    if !nodemask.is_null() && maxnode > 0 {
-        // Return node mask (single node system for now)
+    let task_policy = numa::task_policy(crate::process::current_pid());
+
+    let (policy, node) = if flags & MPOL_F_ADDR != 0 {
+        // The policy of the region holding addr, and its page's node
+        let addr = VirtAddr::try_new(addr as u64).map_err(|_| LinuxError::EFAULT)?;
+        let mm = get_memory_manager().ok_or(LinuxError::EFAULT)?;
+        let region = mm.find_region(addr).ok_or(LinuxError::EFAULT)?;
+        let node = if flags & MPOL_F_NODE != 0 {
+            if mm.page_node(addr).is_none() {
+                // Fault it in so there is a node to report (a user read)
+                mm.handle_page_fault(addr, 0x4).map_err(|_| LinuxError::EFAULT)?;
+            }
+            mm.page_node(addr)
+        } else {
+            None
+        };
+        (region.mempolicy, node)
+    } else {
+        if !addr.is_null() {
+            return Err(LinuxError::EINVAL);
+        }
+        // MPOL_F_NODE alone asks where the caller is running
+        let node = (flags & MPOL_F_NODE != 0).then(numa::local_node);
+        (task_policy, node)
+    };
+
+    let (policy_mode, mask) = policy_to_mode(policy);
+    if !mode.is_null() {
         unsafe {
-            *nodemask = 0x1; // Node 0 available
+            *mode = node.map_or(policy_mode, |node| node as i32);
         }
     }
-
-    // TODO: Implement MPOL_F_ADDR to query policy for specific address
-    // TODO: Implement MPOL_F_MEMS_ALLOWED to query allowed memory nodes
+    write_nodemask(nodemask, maxnode, mask)?;
 
     Ok(0)
 }
@@ -994,34 +1185,8 @@ pub fn get_mempolicy(
 pub fn set_mempolicy(mode: i32, nodemask: *const u64, maxnode: u64) -> LinuxResult<i32> {
     inc_ops();
 
-    use numa_policy::*;
-
-    // Validate mode
-    match mode {
-        MPOL_DEFAULT | MPOL_PREFERRED | MPOL_BIND | MPOL_INTERLEAVE | MPOL_LOCAL => {}
-        _ => return Err(LinuxError::EINVAL),
-    }
-
-    // Validate nodemask if required
-    if mode != MPOL_DEFAULT && mode != MPOL_LOCAL {
-        if nodemask.is_null() || maxnode == 0 {
-            return Err(LinuxError::EINVAL);
-        }
-
-        // Check if specified nodes are valid
-        // RustOS is single-node for now, so only node 0 is valid
-        let mask = unsafe { *nodemask };
-        if mask != 0x1 && mask != 0 {
-            return Err(LinuxError::EINVAL);
-        }
-    }
-
-    // Set policy
-    let mut policy = NUMA_POLICY.lock();
-    *policy = mode;
-
-    // TODO: Store nodemask in process context
-    // TODO: Apply policy to future allocations
+    let policy = mode_to_policy(mode, read_nodemask(nodemask, maxnode))?;
+    numa::set_task_policy(crate::process::current_pid(), policy);
 
     Ok(0)
 }
@@ -1054,20 +1219,7 @@ pub fn mbind(
         return Err(LinuxError::EINVAL);
     }
 
-    use numa_policy::*;
-
-    // Validate mode
-    match mode {
-        MPOL_DEFAULT | MPOL_PREFERRED | MPOL_BIND | MPOL_INTERLEAVE | MPOL_LOCAL => {}
-        _ => return Err(LinuxError::EINVAL),
-    }
-
-    // Validate nodemask
-    if mode != MPOL_DEFAULT && mode != MPOL_LOCAL {
-        if nodemask.is_null() || maxnode == 0 {
-            return Err(LinuxError::EINVAL);
-        }
-    }
+    let policy = mode_to_policy(mode, read_nodemask(nodemask, maxnode))?;
 
     const MPOL_MF_STRICT: u32 = 1 << 0;
     const MPOL_MF_MOVE: u32 = 1 << 1;
@@ -1077,17 +1229,33 @@ pub fn mbind(
     if flags & !valid_flags != 0 {
         return Err(LinuxError::EINVAL);
     }
+    if flags & MPOL_MF_MOVE_ALL != 0 {
+        may_migrate(0, true)?;
+    }
+
+    let mm = managing(addr_val).ok_or(LinuxError::EFAULT)?;
+    let start = VirtAddr::new(addr_val as u64);
+    mm.set_policy(start, len, policy).map_err(|err| match err {
+        MemoryError::RegionNotFound => LinuxError::EFAULT,
+        err => memory_error_to_linux(err),
+    })?;
 
-    // TODO: Apply policy to memory range in region descriptor
-    // TODO: If MPOL_MF_MOVE, migrate pages to comply with policy
-    // TODO: If MPOL_MF_STRICT, fail if pages can't be moved to allowed nodes
+    // Pages already resident are only moved when asked
+    if flags & (MPOL_MF_MOVE | MPOL_MF_MOVE_ALL) != 0 {
+        let end = VirtAddr::new((addr_val + len + 0xFFF) as u64 & !0xFFF);
+        let failed = mm.migrate_to_policy(start, end, policy);
+        if failed > 0 && flags & MPOL_MF_STRICT != 0 {
+            return Err(LinuxError::EIO);
+        }
+    }
 
     Ok(0)
 }
 
 /// migrate_pages - move all pages of a process to another node
 ///
-/// Migrates all pages of a process from old nodes to new nodes.
+/// Migrates all pages of a process from old nodes to new nodes. Returns
+/// the number of pages that could not be moved.
 pub fn migrate_pages(
     pid: Pid,
     maxnode: u64,
@@ -1104,24 +1272,18 @@ pub fn migrate_pages(
         return Err(LinuxError::EINVAL);
     }
 
-    // Requires CAP_SYS_NICE capability
-    // TODO: Check process capabilities
-
-    // Single-node system in RustOS for now
-    // Migration is a no-op but we validate the request
-
-    let old_mask = unsafe { *old_nodes };
-    let new_mask = unsafe { *new_nodes };
-
-    // Only node 0 is valid
-    if (old_mask & !0x1) != 0 || (new_mask & !0x1) != 0 {
+    let old_mask = read_nodemask(old_nodes, maxnode);
+    let new_mask = read_nodemask(new_nodes, maxnode);
+    if old_mask & !numa::online_mask() != 0 || !numa::valid_mask(new_mask) {
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: When multi-node support is added, implement actual page migration
-    // TODO: Iterate through process pages and move to new nodes
+    may_migrate(pid, false)?;
 
-    Ok(0)
+    let mm = get_memory_manager().ok_or(LinuxError::ENOMEM)?;
+    let failed = mm.migrate_nodes(old_mask, new_mask);
+
+    Ok(failed.min(i32::MAX as usize) as i32)
 }
 
 /// move_pages - move individual pages of a process
@@ -1153,47 +1315,43 @@ pub fn move_pages(
         return Err(LinuxError::EINVAL);
     }
 
-    // Requires CAP_SYS_NICE capability for other processes
-    // TODO: Check process capabilities if pid != current
+    may_migrate(pid, flags & MPOL_MF_MOVE_ALL != 0)?;
+
+    let mm = get_memory_manager().ok_or(LinuxError::ENOMEM)?;
 
     // Process each page
     for i in 0..count as usize {
         let page_addr = unsafe { *pages.add(i) };
 
-        if page_addr.is_null() {
-            continue;
-        }
-
-        // Get target node if nodes array is provided
-        let target_node = if !nodes.is_null() {
-            unsafe { *nodes.add(i) }
-        } else {
-            // Query mode - return current node
-            if !status.is_null() {
-                unsafe {
-                    *status.add(i) = 0; // All pages on node 0
+        let result = match VirtAddr::try_new(page_addr as u64) {
+            Err(_) => -(LinuxError::EFAULT as i32),
+            Ok(_) if page_addr.is_null() => -(LinuxError::EFAULT as i32),
+            Ok(addr) if mm.find_region(addr).is_none() => -(LinuxError::EFAULT as i32),
+            Ok(addr) => match mm.page_node(addr) {
+                None => -(LinuxError::ENOENT as i32),
+                // Query mode - return current node
+                Some(node) if nodes.is_null() => node as i32,
+                Some(_) => {
+                    let target = unsafe { *nodes.add(i) };
+                    if target < 0 || target as usize >= numa::MAX_NUMNODES {
+                        return Err(LinuxError::EINVAL);
+                    }
+                    if numa::online_mask() & (1 << target) == 0 {
+                        return Err(LinuxError::ENODEV);
+                    }
+                    match mm.move_page(addr, target as usize) {
+                        Ok(node) => node as i32,
+                        Err(MemoryError::Busy) => -(LinuxError::EBUSY as i32),
+                        Err(MemoryError::OutOfMemory) => -(LinuxError::ENOMEM as i32),
+                        Err(_) => -(LinuxError::EFAULT as i32),
+                    }
                 }
-            }
-            continue;
+            },
         };
 
-        // Validate node
-        if target_node < 0 || target_node > 0 {
-            // Only node 0 is valid in single-node system
-            if !status.is_null() {
-                unsafe {
-                    *status.add(i) = -(LinuxError::EINVAL as i32);
-                }
-            }
-            continue;
-        }
-
-        // TODO: Move page to target node when multi-node support is added
-        // For now, pages are already on node 0
-
         if !status.is_null() {
             unsafe {
-                *status.add(i) = 0; // Success - page on node 0
+                *status.add(i) = result;
             }
         }
     }
@@ -1224,6 +1382,47 @@ mod tests {
         ).is_ok());
     }
 
+    #[test]
+    fn test_mmap_hugetlb_without_pool() {
+        let flags = map::MAP_PRIVATE | map::MAP_ANONYMOUS | map::MAP_HUGETLB;
+        assert_eq!(
+            mmap(core::ptr::null_mut(), 2 * 1024 * 1024, prot::PROT_READ | prot::PROT_WRITE, flags, -1, 0),
+            Err(LinuxError::ENOMEM)
+        );
+    }
+
+    #[test]
+    fn test_mmap_file_access() {
+        use crate::vfs::{self, OpenFlags};
+
+        let shared_rw = |fd| mmap(core::ptr::null_mut(), 4096, prot::PROT_READ | prot::PROT_WRITE, map::MAP_SHARED, fd, 0);
+
+        assert_eq!(shared_rw(999), Err(LinuxError::EBADF));
+
+        let fd = vfs::vfs_open("/mmap_file", OpenFlags::CREAT | OpenFlags::RDWR, 0o644).unwrap();
+        vfs::vfs_write(fd, &[0x5a; 100]).unwrap();
+        vfs::vfs_close(fd).unwrap();
+
+        // A read-only descriptor can back private but not shared writable mappings
+        let fd = vfs::vfs_open("/mmap_file", OpenFlags::RDONLY, 0).unwrap();
+        assert_eq!(shared_rw(fd), Err(LinuxError::EACCES));
+        assert_eq!(
+            mmap(core::ptr::null_mut(), 4096, prot::PROT_READ, map::MAP_SHARED, fd, 1),
+            Err(LinuxError::EINVAL)
+        );
+        vfs::vfs_close(fd).unwrap();
+
+        // Nor can a write-only one be mapped at all
+        let fd = vfs::vfs_open("/mmap_file", OpenFlags::WRONLY, 0).unwrap();
+        assert_eq!(
+            mmap(core::ptr::null_mut(), 4096, prot::PROT_READ, map::MAP_PRIVATE, fd, 0),
+            Err(LinuxError::EACCES)
+        );
+        vfs::vfs_close(fd).unwrap();
+
+        let _ = vfs::vfs_unlink("/mmap_file");
+    }
+
     #[test]
     fn test_mprotect_validation() {
         let addr = 0x1000 as *mut u8;
@@ -1254,4 +1453,28 @@ mod tests {
         assert!(mlockall(1).is_ok());
         assert!(munlockall().is_ok());
     }
+
+    #[test]
+    fn test_numa_policy_modes() {
+        use numa_policy::*;
+
+        // Node 0 is always online
+        assert_eq!(mode_to_policy(MPOL_BIND, 0x1), Ok(MemPolicy::Bind(0x1)));
+        assert_eq!(mode_to_policy(MPOL_PREFERRED, 0x1), Ok(MemPolicy::Preferred(0)));
+        assert_eq!(mode_to_policy(MPOL_PREFERRED, 0), Ok(MemPolicy::Local));
+        assert_eq!(mode_to_policy(MPOL_DEFAULT, 0x1), Err(LinuxError::EINVAL));
+        assert_eq!(mode_to_policy(MPOL_BIND, 0), Err(LinuxError::EINVAL));
+        assert_eq!(mode_to_policy(MPOL_INTERLEAVE, 1 << 63), Err(LinuxError::EINVAL));
+        assert_eq!(policy_to_mode(MemPolicy::Interleave(0x1)), (MPOL_INTERLEAVE, 0x1));
+    }
+
+    #[test]
+    fn test_migrate_pages_node_remap() {
+        // Nodes keep their position: 1 -> 3 and 2 -> 4 for {1, 2} -> {3, 4}
+        assert_eq!(numa::remap_node(1, 0b110, 0b11000), Some(3));
+        assert_eq!(numa::remap_node(2, 0b110, 0b11000), Some(4));
+        assert_eq!(numa::remap_node(0, 0b110, 0b11000), None);
+        // A smaller new set wraps around
+        assert_eq!(numa::remap_node(2, 0b110, 0b1), Some(0));
+    }
 }
@@ -123,6 +123,8 @@ pub enum LinuxError {
     ENODATA = 61,
     /// Not supported
     ENOTSUP = 95,
+    /// Connection timed out
+    ETIMEDOUT = 110,
 }
 
 // Linux compatibility aliases - these errno values are intentionally the same
@@ -157,6 +159,7 @@ impl LinuxError {
             21 => LinuxError::EISDIR,
             22 => LinuxError::EINVAL,
             38 => LinuxError::ENOSYS,
+            110 => LinuxError::ETIMEDOUT,
             _ => LinuxError::EINVAL,
         }
     }
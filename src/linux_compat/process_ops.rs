@@ -5,18 +5,20 @@
 //!
 //! Integrated with RustOS process manager, scheduler, and ELF loader.
 
+use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU64, Ordering};
 
 use super::types::*;
-use super::{LinuxResult, LinuxError};
+use super::{LinuxResult, LinuxError, EOPNOTSUPP};
 
 // Re-export types for external access
 pub use super::types::Rusage;
 
 // Import process management infrastructure
-use crate::process::{self, Priority, ProcessState};
+use crate::process::{self, ProcessState};
 use crate::process::Pid as KernelPid;
 use crate::process_manager;
+use crate::memory::user_space::UserSpaceMemory;
 
 /// Operation counter for statistics
 static PROCESS_OPS_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -44,6 +46,26 @@ fn current_pcb() -> LinuxResult<process::ProcessControlBlock> {
         .ok_or(LinuxError::ESRCH)
 }
 
+/// Process the caller knows by `pid` in its PID namespace; 0 is the caller
+fn resolve_pid(pid: Pid) -> LinuxResult<KernelPid> {
+    let current = process::current_pid();
+    match pid {
+        0 => Ok(current),
+        pid if pid < 0 => Err(LinuxError::ESRCH),
+        pid => process::get_process_manager()
+            .pid_from_namespace_of(current, pid as KernelPid)
+            .ok_or(LinuxError::ESRCH),
+    }
+}
+
+/// Id the caller knows process `pid` by, 0 if it is outside the caller's
+/// PID namespace
+fn local_pid(pid: KernelPid) -> Pid {
+    process::get_process_manager()
+        .pid_in_namespace_of(process::current_pid(), pid)
+        .map_or(0, |local| local as Pid)
+}
+
 /// Get any process PCB by PID
 fn get_pcb(pid: KernelPid) -> LinuxResult<process::ProcessControlBlock> {
     let process_manager = process::get_process_manager();
@@ -67,9 +89,10 @@ pub fn fork() -> LinuxResult<Pid> {
     // - Memory COW setup
     // - File descriptor duplication
     // - Scheduler integration
-    process_mgr.fork(parent_pid)
-        .map(|child_pid| child_pid as i32)
-        .map_err(|_| LinuxError::EAGAIN)
+    let child_pid = process_mgr.fork(parent_pid)
+        .map_err(|_| LinuxError::EAGAIN)?;
+    process::ptrace::fork_event(parent_pid, child_pid, process::ptrace::PTRACE_EVENT_FORK);
+    Ok(child_pid as i32)
 }
 
 /// exec - execute new program in current process
@@ -86,6 +109,7 @@ pub fn exec(program: &[u8], args: &[&str]) -> LinuxResult<i32> {
     // - Context initialization
     process_mgr.exec(pid, program, args)
         .map_err(|_| LinuxError::ENOEXEC)?;
+    process::ptrace::exec_event(pid);
 
     Ok(0)
 }
@@ -97,6 +121,14 @@ pub fn wait(status: *mut i32) -> LinuxResult<Pid> {
     let parent_pid = process::current_pid();
     let process_mgr = process_manager::get_process_manager();
 
+    // Stops and exits of tracees come first
+    if let Some((tracee, wait_status)) = process::ptrace::wait(parent_pid, None) {
+        if !status.is_null() {
+            unsafe { *status = wait_status; }
+        }
+        return Ok(local_pid(tracee));
+    }
+
     // Use process_manager wait which handles:
     // - Zombie child detection
     // - Exit status collection
@@ -108,8 +140,9 @@ pub fn wait(status: *mut i32) -> LinuxResult<Pid> {
             if !status.is_null() {
                 unsafe { *status = exit_status; }
             }
-            Ok(child_pid as i32)
+            Ok(local_pid(child_pid))
         }
+        Err("No child processes") if process::ptrace::has_tracees(parent_pid) => Err(LinuxError::EAGAIN),
         Err("No child processes") => Err(LinuxError::ECHILD),
         Err("Would block waiting for child") => Err(LinuxError::EAGAIN),
         Err(_) => Err(LinuxError::EINVAL),
@@ -132,16 +165,29 @@ pub fn waitpid(pid: Pid, status: *mut i32, _options: i32) -> LinuxResult<Pid> {
         // Wait for any child - delegate to wait()
         return wait(status);
     } else {
-        pid as u32 as KernelPid
+        resolve_pid(pid).map_err(|_| LinuxError::ECHILD)?
     };
 
+    if let Some((_, wait_status)) = process::ptrace::wait(parent_pid, Some(target_pid)) {
+        if !status.is_null() {
+            unsafe { *status = wait_status; }
+        }
+        return Ok(pid);
+    }
+    if process::ptrace::tracer_of(target_pid) == Some(parent_pid) {
+        // A tracee that isn't a child, or not stopped yet
+        if process_mgr.get_parent_pid(target_pid) != Some(parent_pid) {
+            return Err(LinuxError::EAGAIN);
+        }
+    }
+
     // Use process_manager waitpid
     match process_mgr.waitpid(parent_pid, target_pid) {
         Ok(exit_status) => {
             if !status.is_null() {
                 unsafe { *status = exit_status; }
             }
-            Ok(target_pid as i32)
+            Ok(pid)
         }
         Err("Not a child of this process") => Err(LinuxError::ECHILD),
         Err("Child process not found") => Err(LinuxError::ECHILD),
@@ -181,6 +227,8 @@ pub fn exit(status: i32) -> ! {
     let pid = process::current_pid();
     let process_mgr = process_manager::get_process_manager();
 
+    process::ptrace::exit_event(pid, status);
+
     // Use process_manager exit which handles:
     // - State transition to Zombie
     // - Resource cleanup
@@ -188,6 +236,9 @@ pub fn exit(status: i32) -> ! {
     // - Parent notification
     // - Scheduler removal
     let _ = process_mgr.exit(pid, status);
+    for victim in process::ptrace::exit_process(pid, status) {
+        let _ = process::integration::InterruptIntegration::deliver_signal(victim, signal::SIGKILL as u32);
+    }
 
     // Should never return, but if it does, halt
     loop {
@@ -200,17 +251,20 @@ pub fn exit(status: i32) -> ! {
 //
 
 /// getpid - get process ID
+///
+/// Ids are as numbered in the caller's PID namespace.
 pub fn getpid() -> Pid {
     inc_ops();
-    process::current_pid() as Pid
+    local_pid(process::current_pid())
 }
 
 /// getppid - get parent process ID
 pub fn getppid() -> Pid {
     inc_ops();
 
+    // A parent outside the caller's PID namespace shows as 0
     match current_pcb() {
-        Ok(pcb) => pcb.parent_pid.unwrap_or(0) as Pid,
+        Ok(pcb) => pcb.parent_pid.map_or(0, local_pid),
         Err(_) => 0, // Return 0 if cannot get PCB
     }
 }
@@ -308,18 +362,14 @@ pub fn setegid(gid: Gid) -> LinuxResult<i32> {
 pub fn getpgid(pid: Pid) -> LinuxResult<Pid> {
     inc_ops();
 
-    let target_pid = if pid == 0 {
-        process::current_pid()
-    } else {
-        pid as u32
-    };
+    let target_pid = resolve_pid(pid)?;
 
     // Verify process exists
     let _ = get_pcb(target_pid)?;
 
     // TODO: Add pgid field to ProcessControlBlock
     // For now, return the PID itself as pgid
-    Ok(target_pid as i32)
+    Ok(local_pid(target_pid))
 }
 
 /// setpgid - set process group ID
@@ -330,11 +380,7 @@ pub fn setpgid(pid: Pid, pgid: Pid) -> LinuxResult<i32> {
         return Err(LinuxError::EINVAL);
     }
 
-    let target_pid = if pid == 0 {
-        process::current_pid()
-    } else {
-        pid as u32
-    };
+    let target_pid = resolve_pid(pid)?;
 
     // Verify process exists
     let _ = get_pcb(target_pid)?;
@@ -347,11 +393,7 @@ pub fn setpgid(pid: Pid, pgid: Pid) -> LinuxResult<i32> {
 pub fn getsid(pid: Pid) -> LinuxResult<Pid> {
     inc_ops();
 
-    let target_pid = if pid == 0 {
-        process::current_pid()
-    } else {
-        pid as u32
-    };
+    let target_pid = resolve_pid(pid)?;
 
     // Verify process exists
     let _ = get_pcb(target_pid)?;
@@ -393,115 +435,29 @@ pub fn sched_yield() -> LinuxResult<i32> {
     inc_ops();
 
     // Use scheduler's yield function
-    process::scheduler::yield_cpu();
+    crate::scheduler::yield_cpu();
     Ok(0)
 }
 
 /// getpriority - get scheduling priority
+///
+/// Returns the nice value (-20 to 19) rather than the raw system call's
+/// `20 - nice`.
 pub fn getpriority(which: i32, who: i32) -> LinuxResult<i32> {
     inc_ops();
-
-    // PRIO constants
-    const PRIO_PROCESS: i32 = 0;
-    const PRIO_PGRP: i32 = 1;
-    const PRIO_USER: i32 = 2;
-
-    match which {
-        PRIO_PROCESS => {
-            let target_pid = if who == 0 {
-                process::current_pid()
-            } else {
-                who as u32
-            };
-
-            // Get priority from process manager
-            if let Some(priority) = process::scheduler::get_process_priority(target_pid) {
-                // Convert Priority enum to nice value (-20 to 19)
-                let nice = match priority {
-                    Priority::RealTime => -20,
-                    Priority::High => -10,
-                    Priority::Normal => 0,
-                    Priority::Low => 10,
-                    Priority::Idle => 19,
-                };
-                Ok(nice)
-            } else {
-                Err(LinuxError::ESRCH)
-            }
-        }
-        PRIO_PGRP | PRIO_USER => {
-            // TODO: Implement process group and user priority
-            Ok(0)
-        }
-        _ => Err(LinuxError::EINVAL),
-    }
+    super::resource_ops::getpriority(which, who).map(|prio| 20 - prio)
 }
 
 /// setpriority - set scheduling priority
 pub fn setpriority(which: i32, who: i32, prio: i32) -> LinuxResult<i32> {
     inc_ops();
-
-    // PRIO constants
-    const PRIO_PROCESS: i32 = 0;
-    const PRIO_PGRP: i32 = 1;
-    const PRIO_USER: i32 = 2;
-
-    // Priority range is -20 (highest) to 19 (lowest)
-    if prio < -20 || prio > 19 {
-        return Err(LinuxError::EINVAL);
-    }
-
-    match which {
-        PRIO_PROCESS => {
-            let target_pid = if who == 0 {
-                process::current_pid()
-            } else {
-                who as u32
-            };
-
-            // Check permissions - only root can increase priority
-            let current_uid = getuid();
-            if current_uid != 0 && prio < 0 {
-                return Err(LinuxError::EACCES);
-            }
-
-            // Convert nice value to Priority enum
-            let priority = match prio {
-                p if p <= -15 => Priority::RealTime,
-                p if p <= -5 => Priority::High,
-                p if p <= 5 => Priority::Normal,
-                p if p <= 15 => Priority::Low,
-                _ => Priority::Idle,
-            };
-
-            // Set priority via scheduler
-            process::scheduler::set_process_priority(target_pid, priority)
-                .map_err(|_| LinuxError::ESRCH)?;
-
-            Ok(0)
-        }
-        PRIO_PGRP | PRIO_USER => {
-            // TODO: Implement process group and user priority
-            Ok(0)
-        }
-        _ => Err(LinuxError::EINVAL),
-    }
+    super::resource_ops::setpriority(which, who, prio)
 }
 
 /// nice - change process priority
 pub fn nice(inc: i32) -> LinuxResult<i32> {
     inc_ops();
-
-    let pid = process::current_pid();
-
-    // Get current priority
-    let current_nice = getpriority(0, 0)?;
-    let new_nice = (current_nice + inc).clamp(-20, 19);
-
-    // Set new priority
-    setpriority(0, pid as i32, new_nice)?;
-
-    Ok(new_nice)
+    super::resource_ops::nice(inc)
 }
 
 //
@@ -509,6 +465,9 @@ pub fn nice(inc: i32) -> LinuxResult<i32> {
 //
 
 /// sched_setaffinity - set CPU affinity
+///
+/// Bits past the 64 CPUs the scheduler knows about are ignored. Only root
+/// may change another user's processes.
 pub fn sched_setaffinity(pid: Pid, cpusetsize: usize, mask: *const u8) -> LinuxResult<i32> {
     inc_ops();
 
@@ -516,18 +475,18 @@ pub fn sched_setaffinity(pid: Pid, cpusetsize: usize, mask: *const u8) -> LinuxR
         return Err(LinuxError::EFAULT);
     }
 
-    if cpusetsize == 0 || cpusetsize > 128 {
+    if cpusetsize == 0 {
         return Err(LinuxError::EINVAL);
     }
 
-    let target_pid = if pid == 0 {
-        process::current_pid()
-    } else {
-        pid as u32
-    };
+    let target_pid = resolve_pid(pid)?;
 
-    // Verify process exists
-    let _ = get_pcb(target_pid)?;
+    // Verify process exists and the caller may change it
+    let target = get_pcb(target_pid)?;
+    let caller_uid = get_pcb(process::current_pid()).map_or(0, |pcb| pcb.uid);
+    if caller_uid != 0 && caller_uid != target.uid {
+        return Err(LinuxError::EPERM);
+    }
 
     // Read CPU mask from user space
     let mut cpu_mask: u64 = 0;
@@ -538,18 +497,22 @@ pub fn sched_setaffinity(pid: Pid, cpusetsize: usize, mask: *const u8) -> LinuxR
         }
     }
 
-    // Validate mask has at least one CPU
-    if cpu_mask == 0 {
+    // The mask must leave at least one CPU to run on
+    if cpu_mask & crate::scheduler::online_cpu_mask() == 0 {
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Update cpu_affinity in PCB
-    // TODO: Notify scheduler of affinity change
+    process::get_process_manager()
+        .set_cpu_affinity(target_pid, cpu_mask)
+        .map_err(|_| LinuxError::ESRCH)?;
 
     Ok(0)
 }
 
 /// sched_getaffinity - get CPU affinity
+///
+/// Reports the allowed CPUs that are online, and returns the size of the
+/// mask written as the raw system call does.
 pub fn sched_getaffinity(pid: Pid, cpusetsize: usize, mask: *mut u8) -> LinuxResult<i32> {
     inc_ops();
 
@@ -557,19 +520,17 @@ pub fn sched_getaffinity(pid: Pid, cpusetsize: usize, mask: *mut u8) -> LinuxRes
         return Err(LinuxError::EFAULT);
     }
 
-    if cpusetsize == 0 {
+    // The buffer must hold every CPU the scheduler knows about
+    if cpusetsize < 8 || cpusetsize % 8 != 0 {
         return Err(LinuxError::EINVAL);
     }
 
-    let target_pid = if pid == 0 {
-        process::current_pid()
-    } else {
-        pid as u32
-    };
+    let target_pid = resolve_pid(pid)?;
 
-    // Get CPU affinity from PCB
-    let pcb = get_pcb(target_pid)?;
-    let cpu_affinity = pcb.sched_info.cpu_affinity;
+    let cpu_affinity = process::get_process_manager()
+        .cpu_affinity(target_pid)
+        .ok_or(LinuxError::ESRCH)?
+        & crate::scheduler::online_cpu_mask();
 
     // Write affinity mask to user space
     unsafe {
@@ -583,7 +544,7 @@ pub fn sched_getaffinity(pid: Pid, cpusetsize: usize, mask: *mut u8) -> LinuxRes
         }
     }
 
-    Ok(core::cmp::min(cpusetsize, 8) as i32)
+    Ok(8)
 }
 
 //
@@ -606,11 +567,12 @@ pub fn getrusage(who: i32, usage: *mut Rusage) -> LinuxResult<i32> {
     match who {
         RUSAGE_SELF => {
             let pcb = current_pcb()?;
+            let cpu_time = process::get_process_manager().cpu_time_ns(pcb.pid).unwrap_or(pcb.cpu_time);
 
             // Fill in resource usage from PCB
             unsafe {
-                (*usage).ru_utime.tv_sec = (pcb.cpu_time / 1000000) as i64;
-                (*usage).ru_utime.tv_usec = (pcb.cpu_time % 1000000) as i64;
+                (*usage).ru_utime.tv_sec = (cpu_time / 1_000_000_000) as i64;
+                (*usage).ru_utime.tv_usec = (cpu_time % 1_000_000_000 / 1000) as i64;
                 (*usage).ru_stime.tv_sec = 0; // TODO: Track system time separately
                 (*usage).ru_stime.tv_usec = 0;
 
@@ -659,6 +621,13 @@ pub fn prctl(option: i32, arg2: u64, arg3: u64, arg4: u64, arg5: u64) -> LinuxRe
     const PR_GET_DUMPABLE: i32 = 3;
     const PR_SET_PDEATHSIG: i32 = 1;
     const PR_GET_PDEATHSIG: i32 = 2;
+    const PR_GET_SECCOMP: i32 = 21;
+    const PR_SET_SECCOMP: i32 = 22;
+    const PR_SET_NO_NEW_PRIVS: i32 = 38;
+    const PR_GET_NO_NEW_PRIVS: i32 = 39;
+    // RustOS extensions, for systems without /proc mounted
+    const PR_SET_OOM_SCORE_ADJ: i32 = 0x4f4f_4d00;
+    const PR_GET_OOM_SCORE_ADJ: i32 = 0x4f4f_4d01;
 
     match option {
         PR_SET_NAME => {
@@ -698,10 +667,247 @@ pub fn prctl(option: i32, arg2: u64, arg3: u64, arg4: u64, arg5: u64) -> LinuxRe
             // TODO: Implement parent death signal
             Ok(0)
         }
+        PR_GET_SECCOMP => Ok(process::seccomp::mode(process::current_pid()).number() as i32),
+        PR_SET_SECCOMP => {
+            // The older interface, with the mode in place of the operation
+            const SECCOMP_MODE_STRICT: u64 = 1;
+            const SECCOMP_MODE_FILTER: u64 = 2;
+            match arg2 {
+                SECCOMP_MODE_STRICT if arg3 == 0 => seccomp(process::seccomp::SECCOMP_SET_MODE_STRICT, 0, 0),
+                SECCOMP_MODE_FILTER => seccomp(process::seccomp::SECCOMP_SET_MODE_FILTER, 0, arg3),
+                _ => Err(LinuxError::EINVAL),
+            }
+        }
+        PR_SET_NO_NEW_PRIVS => {
+            if arg2 != 1 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            process::seccomp::set_no_new_privs(process::current_pid());
+            Ok(0)
+        }
+        PR_GET_NO_NEW_PRIVS => {
+            if arg2 != 0 || arg3 != 0 || arg4 != 0 || arg5 != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            Ok(process::seccomp::no_new_privs(process::current_pid()) as i32)
+        }
+        PR_SET_OOM_SCORE_ADJ => {
+            let pcb = current_pcb()?;
+            crate::memory::oom::set_score_adj(pcb.pid, arg2 as i32, pcb.uid == 0).map_err(|err| match err {
+                crate::memory::oom::AdjError::Invalid => LinuxError::EINVAL,
+                crate::memory::oom::AdjError::PermissionDenied => LinuxError::EACCES,
+            })?;
+            Ok(0)
+        }
+        PR_GET_OOM_SCORE_ADJ => {
+            let adj_ptr = arg2 as *mut i32;
+            if adj_ptr.is_null() {
+                return Err(LinuxError::EFAULT);
+            }
+            unsafe { *adj_ptr = crate::memory::oom::score_adj(process::current_pid()) as i32 };
+            Ok(0)
+        }
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+//
+// Seccomp Operations
+//
+
+/// Map a seccomp error to a Linux errno
+fn seccomp_error_to_linux(err: process::seccomp::SeccompError) -> LinuxError {
+    match err {
+        process::seccomp::SeccompError::InvalidArgument => LinuxError::EINVAL,
+        process::seccomp::SeccompError::TooLarge => LinuxError::ENOMEM,
+        process::seccomp::SeccompError::PermissionDenied => LinuxError::EACCES,
+    }
+}
+
+/// Copy `count` values of `T` in from user memory at `ptr`
+fn copy_in<T: Copy>(ptr: u64, count: usize) -> LinuxResult<Vec<T>> {
+    let size = core::mem::size_of::<T>();
+    let mut bytes = alloc::vec![0u8; count * size];
+    UserSpaceMemory::copy_from_user(ptr, &mut bytes).map_err(|_| LinuxError::EFAULT)?;
+    Ok(bytes
+        .chunks_exact(size)
+        .map(|chunk| unsafe { chunk.as_ptr().cast::<T>().read_unaligned() })
+        .collect())
+}
+
+/// seccomp - restrict the system calls of the calling process
+///
+/// Installing a filter needs `no_new_privs` or root.
+pub fn seccomp(operation: u32, flags: u32, args: u64) -> LinuxResult<i32> {
+    use process::seccomp::{SockFilter, SockFprog};
+
+    inc_ops();
+
+    let pid = process::current_pid();
+    match operation {
+        process::seccomp::SECCOMP_SET_MODE_STRICT => {
+            if flags != 0 || args != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            process::seccomp::set_strict(pid).map_err(seccomp_error_to_linux)?;
+            Ok(0)
+        }
+        process::seccomp::SECCOMP_SET_MODE_FILTER => {
+            let fprog = copy_in::<SockFprog>(args, 1)?[0];
+            if fprog.len == 0 || fprog.len as usize > process::seccomp::BPF_MAXINSNS {
+                return Err(LinuxError::EINVAL);
+            }
+            let prog = copy_in::<SockFilter>(fprog.filter as u64, fprog.len as usize)?;
+
+            let privileged = current_pcb()?.uid == 0;
+            process::seccomp::install_filter(pid, prog, flags, privileged).map_err(seccomp_error_to_linux)?;
+            Ok(0)
+        }
+        process::seccomp::SECCOMP_GET_ACTION_AVAIL => {
+            if flags != 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            let action = copy_in::<u32>(args, 1)?[0];
+            if process::seccomp::action_available(action) {
+                Ok(0)
+            } else {
+                Err(EOPNOTSUPP)
+            }
+        }
         _ => Err(LinuxError::EINVAL),
     }
 }
 
+//
+// Tracing Operations
+//
+
+/// Map a ptrace error to a Linux errno
+fn ptrace_error_to_linux(err: process::ptrace::PtraceError) -> LinuxError {
+    match err {
+        process::ptrace::PtraceError::NoSuchProcess => LinuxError::ESRCH,
+        process::ptrace::PtraceError::PermissionDenied => LinuxError::EPERM,
+        process::ptrace::PtraceError::InvalidArgument => LinuxError::EINVAL,
+        process::ptrace::PtraceError::Io => LinuxError::EIO,
+    }
+}
+
+/// ptrace - trace another process
+///
+/// The raw system call: PEEK requests store the word read at `data`
+/// rather than returning it. `pid` is as the caller sees it.
+pub fn ptrace(request: i64, pid: Pid, addr: u64, data: u64) -> LinuxResult<i32> {
+    use process::ptrace::{self as pt, Resume};
+
+    inc_ops();
+
+    let tracer = process::current_pid();
+    if request == pt::PTRACE_TRACEME {
+        pt::traceme(tracer).map_err(ptrace_error_to_linux)?;
+        return Ok(0);
+    }
+
+    if pid <= 0 {
+        return Err(LinuxError::ESRCH);
+    }
+    let tracee = resolve_pid(pid)?;
+    let signal = data as u32;
+
+    match request {
+        pt::PTRACE_ATTACH => pt::attach(tracer, tracee, false, 0),
+        pt::PTRACE_SEIZE => {
+            if addr != 0 {
+                return Err(LinuxError::EIO);
+            }
+            pt::attach(tracer, tracee, true, data as u32)
+        }
+        pt::PTRACE_INTERRUPT => pt::interrupt(tracer, tracee),
+        pt::PTRACE_DETACH => pt::detach(tracer, tracee, signal),
+        pt::PTRACE_KILL => pt::kill(tracer, tracee),
+        pt::PTRACE_CONT => pt::resume(tracer, tracee, Resume::Continue, signal),
+        pt::PTRACE_SYSCALL => pt::resume(tracer, tracee, Resume::Syscall, signal),
+        pt::PTRACE_SINGLESTEP => pt::resume(tracer, tracee, Resume::SingleStep, signal),
+        pt::PTRACE_SETOPTIONS => pt::set_options(tracer, tracee, data as u32),
+        pt::PTRACE_PEEKTEXT | pt::PTRACE_PEEKDATA | pt::PTRACE_PEEKUSER => {
+            let out = data as *mut u64;
+            if out.is_null() {
+                return Err(LinuxError::EFAULT);
+            }
+            let word = if request == pt::PTRACE_PEEKUSER {
+                pt::peek_user(tracer, tracee, addr)
+            } else {
+                pt::peek(tracer, tracee, addr)
+            }
+            .map_err(ptrace_error_to_linux)?;
+            unsafe { *out = word; }
+            Ok(())
+        }
+        pt::PTRACE_POKETEXT | pt::PTRACE_POKEDATA => pt::poke(tracer, tracee, addr, data),
+        pt::PTRACE_POKEUSER => pt::poke_user(tracer, tracee, addr, data),
+        pt::PTRACE_GETREGS => {
+            let out = data as *mut pt::UserRegs;
+            if out.is_null() {
+                return Err(LinuxError::EFAULT);
+            }
+            let regs = pt::regs(tracer, tracee).map_err(ptrace_error_to_linux)?;
+            unsafe { *out = regs; }
+            Ok(())
+        }
+        pt::PTRACE_SETREGS => {
+            let regs_ptr = data as *const pt::UserRegs;
+            if regs_ptr.is_null() {
+                return Err(LinuxError::EFAULT);
+            }
+            let regs = unsafe { *regs_ptr };
+            pt::set_regs(tracer, tracee, &regs)
+        }
+        pt::PTRACE_GETFPREGS => {
+            let out = data as *mut process::context::FpuState;
+            if out.is_null() {
+                return Err(LinuxError::EFAULT);
+            }
+            let fpu = pt::fpregs(tracer, tracee).map_err(ptrace_error_to_linux)?;
+            unsafe { *out = fpu; }
+            Ok(())
+        }
+        pt::PTRACE_SETFPREGS => {
+            let fpu_ptr = data as *const process::context::FpuState;
+            if fpu_ptr.is_null() {
+                return Err(LinuxError::EFAULT);
+            }
+            let fpu = unsafe { (*fpu_ptr).clone() };
+            pt::set_fpregs(tracer, tracee, &fpu)
+        }
+        pt::PTRACE_GETEVENTMSG => {
+            let out = data as *mut u64;
+            if out.is_null() {
+                return Err(LinuxError::EFAULT);
+            }
+            let msg = pt::event_msg(tracer, tracee).map_err(ptrace_error_to_linux)?;
+            unsafe { *out = msg; }
+            Ok(())
+        }
+        pt::PTRACE_GETSIGINFO => {
+            // siginfo_t is 128 bytes: si_signo, si_errno and si_code first
+            let out = data as *mut i32;
+            if out.is_null() {
+                return Err(LinuxError::EFAULT);
+            }
+            let (signo, code) = pt::siginfo(tracer, tracee).map_err(ptrace_error_to_linux)?;
+            unsafe {
+                core::ptr::write_bytes(out as *mut u8, 0, 128);
+                *out = signo as i32;
+                *out.add(2) = code;
+            }
+            Ok(())
+        }
+        _ => return Err(LinuxError::EIO),
+    }
+    .map_err(ptrace_error_to_linux)?;
+
+    Ok(0)
+}
+
 //
 // Capability Operations (Stub)
 //
@@ -742,12 +948,13 @@ pub fn times(buf: *mut u8) -> LinuxResult<i64> {
 
     if !buf.is_null() {
         let pcb = current_pcb()?;
+        let cpu_time = process::get_process_manager().cpu_time_ns(pcb.pid).unwrap_or(pcb.cpu_time);
 
         // Fill in tms structure (4 x i64 = 32 bytes)
         // tms_utime, tms_stime, tms_cutime, tms_cstime
         unsafe {
             let tms = buf as *mut i64;
-            *tms.offset(0) = (pcb.cpu_time / 10) as i64; // User time in clock ticks
+            *tms.offset(0) = (cpu_time / 10_000_000) as i64; // User time in 100 Hz clock ticks
             *tms.offset(1) = 0; // System time (TODO: track separately)
             *tms.offset(2) = 0; // Children user time (TODO: accumulate)
             *tms.offset(3) = 0; // Children system time (TODO: accumulate)
@@ -806,4 +1013,13 @@ mod tests {
         let sid = getsid(0);
         assert!(sid.is_ok());
     }
+
+    #[test]
+    fn test_seccomp_bad_pointers() {
+        use process::seccomp::{SECCOMP_GET_ACTION_AVAIL, SECCOMP_SET_MODE_FILTER};
+
+        assert_eq!(seccomp(SECCOMP_SET_MODE_FILTER, 0, 0), Err(LinuxError::EFAULT));
+        assert_eq!(seccomp(SECCOMP_SET_MODE_FILTER, 0, 0xffff_8000_0000_0000), Err(LinuxError::EFAULT));
+        assert_eq!(seccomp(SECCOMP_GET_ACTION_AVAIL, 0, 0), Err(LinuxError::EFAULT));
+    }
 }
@@ -7,10 +7,17 @@
 
 extern crate alloc;
 
+use alloc::vec;
+use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU64, Ordering};
 
 use super::types::*;
 use super::{LinuxResult, LinuxError};
+use crate::process::rlimit;
+use crate::process::Pid as KernelPid;
+use crate::scheduler::deadline::DeadlineParams;
+use crate::scheduler::rt::{MAX_RT_PRIO, MIN_RT_PRIO};
+use crate::scheduler::SchedPolicy;
 
 /// Operation counter for statistics
 static RESOURCE_OPS_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -70,7 +77,7 @@ pub mod rlimit_resource {
 }
 
 /// Resource limit value for "unlimited"
-pub const RLIM_INFINITY: u64 = !0;
+pub const RLIM_INFINITY: u64 = rlimit::RLIM_INFINITY;
 
 /// Resource limit structure
 #[repr(C)]
@@ -167,6 +174,31 @@ impl RUsage {
 // Resource Limit Operations
 // ============================================================================
 
+/// Validate a resource number from user space
+fn resource_index(resource: i32) -> LinuxResult<usize> {
+    if resource < 0 || resource > rlimit_resource::RLIMIT_RTTIME {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(resource as usize)
+}
+
+/// uid of a process, or None if it doesn't exist
+fn process_uid(pid: crate::process::Pid) -> Option<u32> {
+    crate::process::get_process_manager().get_process(pid).map(|pcb| pcb.uid)
+}
+
+fn to_rlimit(limit: rlimit::Limit) -> RLimit {
+    RLimit::new(limit.cur, limit.max)
+}
+
+fn set_limit(pid: crate::process::Pid, resource: usize, limit: RLimit) -> LinuxResult<rlimit::Limit> {
+    let privileged = process_uid(crate::process::current_pid()).map_or(true, |uid| uid == 0);
+    rlimit::set(pid, resource, rlimit::Limit::new(limit.rlim_cur, limit.rlim_max), privileged).map_err(|err| match err {
+        rlimit::LimitError::Invalid => LinuxError::EINVAL,
+        rlimit::LimitError::PermissionDenied => LinuxError::EPERM,
+    })
+}
+
 /// getrlimit - get resource limits
 pub fn getrlimit(resource: i32, rlim: *mut RLimit) -> LinuxResult<i32> {
     inc_ops();
@@ -175,27 +207,18 @@ pub fn getrlimit(resource: i32, rlim: *mut RLimit) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    // Validate resource
-    if resource < 0 || resource > rlimit_resource::RLIMIT_RTTIME {
-        return Err(LinuxError::EINVAL);
-    }
-
-    // TODO: Get actual resource limits from process
-    // For now, return default limits
+    let resource = resource_index(resource)?;
+    let limit = rlimit::get(crate::process::current_pid(), resource).ok_or(LinuxError::EINVAL)?;
     unsafe {
-        *rlim = match resource {
-            rlimit_resource::RLIMIT_NOFILE => RLimit::new(1024, 4096),
-            rlimit_resource::RLIMIT_NPROC => RLimit::new(4096, 16384),
-            rlimit_resource::RLIMIT_STACK => RLimit::new(8 * 1024 * 1024, RLIM_INFINITY),
-            rlimit_resource::RLIMIT_AS => RLimit::unlimited(),
-            _ => RLimit::unlimited(),
-        };
+        *rlim = to_rlimit(limit);
     }
 
     Ok(0)
 }
 
 /// setrlimit - set resource limits
+///
+/// Raising the hard limit requires root.
 pub fn setrlimit(resource: i32, rlim: *const RLimit) -> LinuxResult<i32> {
     inc_ops();
 
@@ -203,27 +226,16 @@ pub fn setrlimit(resource: i32, rlim: *const RLimit) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    // Validate resource
-    if resource < 0 || resource > rlimit_resource::RLIMIT_RTTIME {
-        return Err(LinuxError::EINVAL);
-    }
-
-    unsafe {
-        let limit = *rlim;
-
-        // Soft limit cannot exceed hard limit
-        if limit.rlim_cur > limit.rlim_max {
-            return Err(LinuxError::EINVAL);
-        }
-
-        // TODO: Set resource limits
-        // Raising hard limit requires CAP_SYS_RESOURCE capability
-    }
+    let resource = resource_index(resource)?;
+    let limit = unsafe { *rlim };
+    set_limit(crate::process::current_pid(), resource, limit)?;
 
     Ok(0)
 }
 
 /// prlimit - get/set resource limits of arbitrary process
+///
+/// Only root may act on processes of another user.
 pub fn prlimit(
     pid: Pid,
     resource: i32,
@@ -232,37 +244,29 @@ pub fn prlimit(
 ) -> LinuxResult<i32> {
     inc_ops();
 
-    // Validate resource
-    if resource < 0 || resource > rlimit_resource::RLIMIT_RTTIME {
+    let resource = resource_index(resource)?;
+    if pid < 0 {
         return Err(LinuxError::EINVAL);
     }
 
     // pid == 0 means current process
-    let target_pid = if pid == 0 { 1 } else { pid };
+    let current = crate::process::current_pid();
+    let target_pid = if pid == 0 { current } else { pid as crate::process::Pid };
 
-    if target_pid < 0 {
-        return Err(LinuxError::EINVAL);
+    let target_uid = process_uid(target_pid).ok_or(LinuxError::ESRCH)?;
+    let caller_uid = process_uid(current).unwrap_or(0);
+    if caller_uid != 0 && caller_uid != target_uid {
+        return Err(LinuxError::EPERM);
     }
 
-    // Get old limit if requested
+    let old = if new_limit.is_null() {
+        rlimit::get(target_pid, resource).ok_or(LinuxError::EINVAL)?
+    } else {
+        set_limit(target_pid, resource, unsafe { *new_limit })?
+    };
     if !old_limit.is_null() {
-        // TODO: Get limits from target process
         unsafe {
-            *old_limit = RLimit::unlimited();
-        }
-    }
-
-    // Set new limit if provided
-    if !new_limit.is_null() {
-        unsafe {
-            let limit = *new_limit;
-
-            if limit.rlim_cur > limit.rlim_max {
-                return Err(LinuxError::EINVAL);
-            }
-
-            // TODO: Set limits for target process
-            // Requires appropriate permissions
+            *old_limit = to_rlimit(old);
         }
     }
 
@@ -273,54 +277,125 @@ pub fn prlimit(
 // Priority Operations
 // ============================================================================
 
+/// Which processes a priority call names
+const PRIO_PROCESS: i32 = 0;
+const PRIO_PGRP: i32 = 1;
+const PRIO_USER: i32 = 2;
+
+/// Processes named by `which` and `who`, 0 meaning the caller's own
+///
+/// Every process leads a group of its own, so a process group is the process
+/// with that pid.
+fn priority_targets(which: i32, who: i32) -> LinuxResult<Vec<KernelPid>> {
+    if who < 0 {
+        return Err(LinuxError::ESRCH);
+    }
+    let current = crate::process::current_pid();
+    let process_manager = crate::process::get_process_manager();
+
+    let targets: Vec<KernelPid> = match which {
+        PRIO_PROCESS | PRIO_PGRP => {
+            let pid = if who == 0 { current } else { who as KernelPid };
+            // The kernel's own flow counts as a process here
+            if pid != 0 && process_manager.get_process(pid).is_none() {
+                return Err(LinuxError::ESRCH);
+            }
+            vec![pid]
+        }
+        PRIO_USER => {
+            let uid = if who == 0 {
+                process_manager.get_process(current).map_or(0, |pcb| pcb.uid)
+            } else {
+                who as u32
+            };
+            process_manager.list_processes().into_iter()
+                .map(|(pid, ..)| pid)
+                .filter(|&pid| process_manager.get_process(pid).map_or(false, |pcb| pcb.uid == uid))
+                .collect()
+        }
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    if targets.is_empty() {
+        return Err(LinuxError::ESRCH);
+    }
+    Ok(targets)
+}
+
+/// Nice value of a process; the kernel's own flow always runs at 0
+fn nice_of(pid: KernelPid) -> Option<i8> {
+    if pid == 0 {
+        Some(0)
+    } else {
+        crate::scheduler::get_nice(pid)
+    }
+}
+
 /// getpriority - get program scheduling priority
+///
+/// Returns 20 minus the lowest nice value among the named processes, 1 to
+/// 40, as the raw system call does.
 pub fn getpriority(which: i32, who: i32) -> LinuxResult<i32> {
     inc_ops();
 
-    const PRIO_PROCESS: i32 = 0;
-    const PRIO_PGRP: i32 = 1;
-    const PRIO_USER: i32 = 2;
-
-    match which {
-        PRIO_PROCESS | PRIO_PGRP | PRIO_USER => {
-            // TODO: Get priority
-            // Return priority value (0-39, where 20 is normal)
-            Ok(20)
-        }
-        _ => Err(LinuxError::EINVAL),
-    }
+    let nice = priority_targets(which, who)?.into_iter()
+        .filter_map(nice_of)
+        .min()
+        .ok_or(LinuxError::ESRCH)?;
+    Ok(20 - nice as i32)
 }
 
 /// setpriority - set program scheduling priority
 pub fn setpriority(which: i32, who: i32, prio: i32) -> LinuxResult<i32> {
     inc_ops();
 
-    const PRIO_PROCESS: i32 = 0;
-    const PRIO_PGRP: i32 = 1;
-    const PRIO_USER: i32 = 2;
+    let process_manager = crate::process::get_process_manager();
+    for pid in priority_targets(which, who)? {
+        if pid == 0 {
+            // Nothing to change for the kernel's own flow
+            continue;
+        }
+        let nice = check_nice_change(pid, prio)?;
+        process_manager.set_nice(pid, nice).map_err(|_| LinuxError::ESRCH)?;
+    }
 
+    Ok(0)
+}
+
+/// Check that the caller may give `pid` the nice value `prio`
+///
+/// Only root may touch other users' processes or lower a nice value.
+fn check_nice_change(pid: KernelPid, prio: i32) -> LinuxResult<i8> {
     // Priority range is -20 to 19
     if prio < -20 || prio > 19 {
         return Err(LinuxError::EINVAL);
     }
+    let nice = prio as i8;
 
-    match which {
-        PRIO_PROCESS | PRIO_PGRP | PRIO_USER => {
-            // TODO: Set priority
-            // Requires appropriate permissions for nice values < 0
-            Ok(0)
-        }
-        _ => Err(LinuxError::EINVAL),
+    let process_manager = crate::process::get_process_manager();
+    let caller_uid = process_manager.get_process(crate::process::current_pid()).map_or(0, |pcb| pcb.uid);
+    let owner = process_manager.get_process(pid).map_or(0, |pcb| pcb.uid);
+    if caller_uid != 0 && owner != caller_uid {
+        return Err(LinuxError::EPERM);
+    }
+    if caller_uid != 0 && nice_of(pid).map_or(false, |current| nice < current) {
+        return Err(LinuxError::EACCES);
     }
+    Ok(nice)
 }
 
 /// nice - change process priority
+///
+/// Returns the new nice value.
 pub fn nice(inc: i32) -> LinuxResult<i32> {
     inc_ops();
 
-    // TODO: Adjust process priority by inc
-    // Return new priority value
-    Ok(20)
+    let pid = crate::process::current_pid();
+    let current = nice_of(pid).ok_or(LinuxError::ESRCH)? as i32;
+    let new_nice = current.saturating_add(inc).clamp(-20, 19);
+
+    setpriority(PRIO_PROCESS, 0, new_nice)?;
+    Ok(new_nice)
 }
 
 // ============================================================================
@@ -339,7 +414,7 @@ pub mod sched_policy {
     pub const SCHED_BATCH: i32 = 3;
     /// Very low priority background jobs
     pub const SCHED_IDLE: i32 = 5;
-    /// Sporadic server
+    /// Earliest deadline first with a runtime reservation
     pub const SCHED_DEADLINE: i32 = 6;
 }
 
@@ -350,44 +425,139 @@ pub struct SchedParam {
     pub sched_priority: i32,
 }
 
+/// Extended scheduling attributes, as sched_setattr(2) takes them
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SchedAttr {
+    /// Size of the structure
+    pub size: u32,
+    pub sched_policy: u32,
+    pub sched_flags: u64,
+    /// Nice value for SCHED_NORMAL and SCHED_BATCH
+    pub sched_nice: i32,
+    /// Priority for SCHED_FIFO and SCHED_RR
+    pub sched_priority: u32,
+    /// Reservation for SCHED_DEADLINE, in nanoseconds
+    pub sched_runtime: u64,
+    pub sched_deadline: u64,
+    pub sched_period: u64,
+}
+
+/// Size of the first published `SchedAttr`
+const SCHED_ATTR_SIZE_VER0: u32 = 48;
+
+/// Policy number of a scheduler policy
+fn policy_number(policy: SchedPolicy) -> i32 {
+    match policy {
+        SchedPolicy::Normal => sched_policy::SCHED_NORMAL,
+        SchedPolicy::Fifo => sched_policy::SCHED_FIFO,
+        SchedPolicy::RoundRobin => sched_policy::SCHED_RR,
+        SchedPolicy::Batch => sched_policy::SCHED_BATCH,
+        SchedPolicy::Idle => sched_policy::SCHED_IDLE,
+        SchedPolicy::Deadline => sched_policy::SCHED_DEADLINE,
+    }
+}
+
+/// Scheduler policy of a policy number
+fn policy_from_number(policy: i32) -> LinuxResult<SchedPolicy> {
+    match policy {
+        sched_policy::SCHED_NORMAL => Ok(SchedPolicy::Normal),
+        sched_policy::SCHED_FIFO => Ok(SchedPolicy::Fifo),
+        sched_policy::SCHED_RR => Ok(SchedPolicy::RoundRobin),
+        sched_policy::SCHED_BATCH => Ok(SchedPolicy::Batch),
+        sched_policy::SCHED_IDLE => Ok(SchedPolicy::Idle),
+        sched_policy::SCHED_DEADLINE => Ok(SchedPolicy::Deadline),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Process a sched_* call names, 0 meaning the caller
+fn sched_target(pid: Pid) -> LinuxResult<KernelPid> {
+    if pid < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let target = if pid == 0 { crate::process::current_pid() } else { pid as KernelPid };
+    // The kernel's own flow reports SCHED_NORMAL but cannot be changed
+    if target != 0 && crate::scheduler::get_sched_policy(target).is_none() {
+        return Err(LinuxError::ESRCH);
+    }
+    Ok(target)
+}
+
+/// Apply a policy to `target` after checking the caller may
+///
+/// Only root may touch other users' processes or use the real-time and
+/// deadline policies.
+fn apply_policy(
+    target: KernelPid,
+    policy: SchedPolicy,
+    rt_priority: i32,
+    params: Option<DeadlineParams>,
+) -> LinuxResult<i32> {
+    if target == 0 {
+        return Err(LinuxError::EPERM);
+    }
+    let rt_priority = u8::try_from(rt_priority).map_err(|_| LinuxError::EINVAL)?;
+
+    let process_manager = crate::process::get_process_manager();
+    let caller_uid = process_manager.get_process(crate::process::current_pid()).map_or(0, |pcb| pcb.uid);
+    let owner = process_manager.get_process(target).map_or(0, |pcb| pcb.uid);
+    if caller_uid != 0 && (owner != caller_uid || policy.is_realtime()) {
+        return Err(LinuxError::EPERM);
+    }
+
+    process_manager
+        .set_sched_policy(target, policy, rt_priority, params)
+        .map_err(|error| match error {
+            "Process not found" => LinuxError::ESRCH,
+            "Deadline bandwidth exhausted" => LinuxError::EBUSY,
+            _ => LinuxError::EINVAL,
+        })?;
+    Ok(0)
+}
+
 /// sched_setscheduler - set scheduling policy and parameters
+///
+/// SCHED_DEADLINE needs a reservation and is only set through
+/// `sched_setattr`.
 pub fn sched_setscheduler(pid: Pid, policy: i32, param: *const SchedParam) -> LinuxResult<i32> {
     inc_ops();
 
     if param.is_null() {
         return Err(LinuxError::EFAULT);
     }
-
-    match policy {
-        sched_policy::SCHED_NORMAL | sched_policy::SCHED_FIFO |
-        sched_policy::SCHED_RR | sched_policy::SCHED_BATCH |
-        sched_policy::SCHED_IDLE | sched_policy::SCHED_DEADLINE => {
-            // TODO: Set scheduler policy
-            // SCHED_FIFO and SCHED_RR require real-time permissions
-            Ok(0)
-        }
-        _ => Err(LinuxError::EINVAL),
+    let policy = policy_from_number(policy)?;
+    if policy == SchedPolicy::Deadline {
+        return Err(LinuxError::EINVAL);
     }
+    let target = sched_target(pid)?;
+
+    apply_policy(target, policy, unsafe { (*param).sched_priority }, None)
 }
 
 /// sched_getscheduler - get scheduling policy
 pub fn sched_getscheduler(pid: Pid) -> LinuxResult<i32> {
     inc_ops();
 
-    // TODO: Get scheduler policy for process
-    Ok(sched_policy::SCHED_NORMAL)
+    let target = sched_target(pid)?;
+    let policy = crate::scheduler::get_sched_policy(target).unwrap_or(SchedPolicy::Normal);
+    Ok(policy_number(policy))
 }
 
 /// sched_setparam - set scheduling parameters
+///
+/// Keeps the policy; only real-time processes have a priority to change.
 pub fn sched_setparam(pid: Pid, param: *const SchedParam) -> LinuxResult<i32> {
     inc_ops();
 
     if param.is_null() {
         return Err(LinuxError::EFAULT);
     }
+    let target = sched_target(pid)?;
+    let policy = crate::scheduler::get_sched_policy(target).unwrap_or(SchedPolicy::Normal);
+    let params = crate::scheduler::get_deadline_params(target);
 
-    // TODO: Set scheduling parameters
-    Ok(0)
+    apply_policy(target, policy, unsafe { (*param).sched_priority }, params)
 }
 
 /// sched_getparam - get scheduling parameters
@@ -397,10 +567,79 @@ pub fn sched_getparam(pid: Pid, param: *mut SchedParam) -> LinuxResult<i32> {
     if param.is_null() {
         return Err(LinuxError::EFAULT);
     }
+    let target = sched_target(pid)?;
+    let rt_priority = crate::scheduler::get_rt_priority(target).unwrap_or(0);
 
-    // TODO: Get scheduling parameters
     unsafe {
-        (*param).sched_priority = 0;
+        (*param).sched_priority = rt_priority as i32;
+    }
+    Ok(0)
+}
+
+/// sched_setattr - set scheduling policy and attributes
+pub fn sched_setattr(pid: Pid, attr: *const SchedAttr, flags: u32) -> LinuxResult<i32> {
+    inc_ops();
+
+    if attr.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    if flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let attr = unsafe { *attr };
+    if attr.size != 0 && attr.size < SCHED_ATTR_SIZE_VER0 {
+        return Err(LinuxError::E2BIG);
+    }
+    let policy = policy_from_number(attr.sched_policy as i32)?;
+    let target = sched_target(pid)?;
+
+    let params = match policy {
+        SchedPolicy::Deadline => Some(
+            DeadlineParams::new(attr.sched_runtime, attr.sched_deadline, attr.sched_period)
+                .map_err(|_| LinuxError::EINVAL)?,
+        ),
+        _ => None,
+    };
+    let rt_priority = i32::try_from(attr.sched_priority).map_err(|_| LinuxError::EINVAL)?;
+
+    // The nice value goes with the fair policies. It is checked first, so
+    // that a refused nice change leaves the policy as it was
+    let set_nice = !policy.is_realtime() && target != 0;
+    if set_nice {
+        check_nice_change(target, attr.sched_nice)?;
+    }
+    apply_policy(target, policy, rt_priority, params)?;
+    if set_nice {
+        setpriority(PRIO_PROCESS, target as i32, attr.sched_nice)?;
+    }
+    Ok(0)
+}
+
+/// sched_getattr - get scheduling policy and attributes
+pub fn sched_getattr(pid: Pid, attr: *mut SchedAttr, size: u32, flags: u32) -> LinuxResult<i32> {
+    inc_ops();
+
+    if attr.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    if flags != 0 || size < SCHED_ATTR_SIZE_VER0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let target = sched_target(pid)?;
+    let policy = crate::scheduler::get_sched_policy(target).unwrap_or(SchedPolicy::Normal);
+    let params = crate::scheduler::get_deadline_params(target).unwrap_or_default();
+
+    unsafe {
+        *attr = SchedAttr {
+            size: SCHED_ATTR_SIZE_VER0,
+            sched_policy: policy_number(policy) as u32,
+            sched_flags: 0,
+            sched_nice: nice_of(target).unwrap_or(0) as i32,
+            sched_priority: crate::scheduler::get_rt_priority(target).unwrap_or(0) as u32,
+            sched_runtime: params.runtime_ns,
+            sched_deadline: params.deadline_ns,
+            sched_period: params.period_ns,
+        };
     }
     Ok(0)
 }
@@ -410,8 +649,9 @@ pub fn sched_get_priority_max(policy: i32) -> LinuxResult<i32> {
     inc_ops();
 
     match policy {
-        sched_policy::SCHED_NORMAL | sched_policy::SCHED_BATCH | sched_policy::SCHED_IDLE => Ok(0),
-        sched_policy::SCHED_FIFO | sched_policy::SCHED_RR => Ok(99),
+        sched_policy::SCHED_NORMAL | sched_policy::SCHED_BATCH |
+        sched_policy::SCHED_IDLE | sched_policy::SCHED_DEADLINE => Ok(0),
+        sched_policy::SCHED_FIFO | sched_policy::SCHED_RR => Ok(MAX_RT_PRIO as i32),
         _ => Err(LinuxError::EINVAL),
     }
 }
@@ -421,25 +661,32 @@ pub fn sched_get_priority_min(policy: i32) -> LinuxResult<i32> {
     inc_ops();
 
     match policy {
-        sched_policy::SCHED_NORMAL | sched_policy::SCHED_BATCH | sched_policy::SCHED_IDLE => Ok(0),
-        sched_policy::SCHED_FIFO | sched_policy::SCHED_RR => Ok(1),
+        sched_policy::SCHED_NORMAL | sched_policy::SCHED_BATCH |
+        sched_policy::SCHED_IDLE | sched_policy::SCHED_DEADLINE => Ok(0),
+        sched_policy::SCHED_FIFO | sched_policy::SCHED_RR => Ok(MIN_RT_PRIO as i32),
         _ => Err(LinuxError::EINVAL),
     }
 }
 
 /// sched_rr_get_interval - get SCHED_RR interval
+///
+/// Zero for SCHED_FIFO; the fair class reports its base slice.
 pub fn sched_rr_get_interval(pid: Pid, tp: *mut TimeSpec) -> LinuxResult<i32> {
     inc_ops();
 
     if tp.is_null() {
         return Err(LinuxError::EFAULT);
     }
+    let target = sched_target(pid)?;
+    let interval = if target == 0 {
+        crate::scheduler::fair::BASE_SLICE_NS
+    } else {
+        crate::scheduler::rr_interval_ns(target).ok_or(LinuxError::ESRCH)?
+    };
 
-    // TODO: Get actual RR interval
-    // Default is typically 100ms
     unsafe {
-        (*tp).tv_sec = 0;
-        (*tp).tv_nsec = 100_000_000;
+        (*tp).tv_sec = (interval / 1_000_000_000) as _;
+        (*tp).tv_nsec = (interval % 1_000_000_000) as _;
     }
 
     Ok(0)
@@ -463,6 +710,17 @@ mod tests {
         assert!(setrlimit(rlimit_resource::RLIMIT_NOFILE, &invalid).is_err());
     }
 
+    #[test]
+    fn test_setrlimit_roundtrip() {
+        let limit = RLimit { rlim_cur: 64 * 1024, rlim_max: 64 * 1024 };
+        assert!(setrlimit(rlimit_resource::RLIMIT_MEMLOCK, &limit).is_ok());
+
+        let mut rlim = RLimit::unlimited();
+        assert!(getrlimit(rlimit_resource::RLIMIT_MEMLOCK, &mut rlim).is_ok());
+        assert_eq!(rlim.rlim_cur, 64 * 1024);
+        assert_eq!(rlim.rlim_max, 64 * 1024);
+    }
+
     #[test]
     fn test_priority() {
         assert!(getpriority(0, 0).is_ok());
@@ -4,9 +4,16 @@
 //! send, recv, socket options, and I/O multiplexing.
 
 use core::sync::atomic::{AtomicU64, Ordering};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
 
 use super::types::*;
 use super::{LinuxResult, LinuxError};
+use crate::process::current_pid;
+use crate::process::ipc::get_ipc_manager;
+use crate::time;
+use crate::vfs::{self, epoll::Epoll};
 
 /// Operation counter for statistics
 static SOCKET_OPS_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -234,6 +241,72 @@ pub fn shutdown(sockfd: Fd, how: i32) -> LinuxResult<i32> {
     }
 }
 
+// ============================================================================
+// I/O multiplexing
+// ============================================================================
+
+/// epoll_ctl operations
+pub const EPOLL_CTL_ADD: i32 = 1;
+pub const EPOLL_CTL_DEL: i32 = 2;
+pub const EPOLL_CTL_MOD: i32 = 3;
+
+/// epoll_create1 flags
+pub const EPOLL_CLOEXEC: i32 = open_flags::O_CLOEXEC;
+
+/// struct epoll_event (packed on x86_64)
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct EpollEvent {
+    pub events: u32,
+    pub data: u64,
+}
+
+/// Wait until `scan` reports progress or `timeout_ms` elapses
+///
+/// A negative timeout waits forever; zero scans once. A one-shot timer wakes
+/// the waiter when the timeout expires.
+fn wait_for_events<F>(timeout_ms: i64, mut scan: F) -> LinuxResult<usize>
+where
+    F: FnMut() -> usize,
+{
+    let ready = scan();
+    if ready > 0 || timeout_ms == 0 {
+        return Ok(ready);
+    }
+
+    let deadline = (timeout_ms > 0).then(|| time::uptime_ms() + timeout_ms as u64);
+    let wakeup = (timeout_ms > 0).then(|| time::schedule_timer(timeout_ms as u64 * 1000, vfs::poll::notify));
+
+    let result = vfs::poll::wait_until(|| {
+        let ready = scan();
+        if ready > 0 {
+            return Some(ready);
+        }
+        match deadline {
+            Some(deadline) if time::uptime_ms() >= deadline => Some(0),
+            _ => None,
+        }
+    });
+
+    if let Some(id) = wakeup {
+        time::cancel_timer(id);
+    }
+
+    result.map_err(|_| LinuxError::EINTR)
+}
+
+/// Convert a timespec to milliseconds, rounding up; null means forever
+unsafe fn timespec_to_ms(timeout: *const TimeSpec) -> LinuxResult<i64> {
+    if timeout.is_null() {
+        return Ok(-1);
+    }
+    let ts = &*timeout;
+    if ts.tv_sec < 0 || ts.tv_nsec < 0 || ts.tv_nsec >= 1_000_000_000 {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(ts.tv_sec as i64 * 1000 + (ts.tv_nsec as i64 + 999_999) / 1_000_000)
+}
+
 /// poll - wait for events on file descriptors
 pub fn poll(fds: *mut PollFd, nfds: u64, timeout: i32) -> LinuxResult<i32> {
     inc_ops();
@@ -242,9 +315,111 @@ pub fn poll(fds: *mut PollFd, nfds: u64, timeout: i32) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Implement poll using event system
-    // For now, return 0 (timeout with no events)
-    Ok(0)
+    let fds = if nfds == 0 {
+        &mut [][..]
+    } else {
+        unsafe { core::slice::from_raw_parts_mut(fds, nfds as usize) }
+    };
+
+    let ready = wait_for_events(timeout as i64, || {
+        let mut ready = 0;
+        for pfd in fds.iter_mut() {
+            pfd.revents = 0;
+            if pfd.fd < 0 {
+                continue;
+            }
+            let wanted = pfd.events as u16 | vfs::poll::POLLERR | vfs::poll::POLLHUP;
+            let revents = match vfs::vfs_poll(pfd.fd) {
+                Ok(mask) => mask & wanted,
+                Err(_) => vfs::poll::POLLNVAL,
+            };
+            if revents != 0 {
+                pfd.revents = revents as i16;
+                ready += 1;
+            }
+        }
+        ready
+    })?;
+
+    Ok(ready as i32)
+}
+
+/// Shared implementation of select and pselect
+fn select_common(
+    nfds: i32,
+    readfds: *mut u64,
+    writefds: *mut u64,
+    exceptfds: *mut u64,
+    timeout_ms: i64,
+) -> LinuxResult<i32> {
+    use vfs::poll::{POLLERR, POLLHUP, POLLIN, POLLOUT, POLLPRI};
+
+    if nfds < 0 || nfds > 1024 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let words = (nfds as usize + 63) / 64;
+    let load = |set: *mut u64| -> Vec<u64> {
+        if set.is_null() {
+            vec![0; words]
+        } else {
+            unsafe { core::slice::from_raw_parts(set, words).to_vec() }
+        }
+    };
+    let (want_read, want_write, want_except) = (load(readfds), load(writefds), load(exceptfds));
+
+    // Every descriptor named in a set must be open
+    for fd in 0..nfds {
+        let (word, bit) = (fd as usize / 64, 1u64 << (fd % 64));
+        if (want_read[word] | want_write[word] | want_except[word]) & bit != 0
+            && vfs::vfs_poll(fd).is_err()
+        {
+            return Err(LinuxError::EBADF);
+        }
+    }
+
+    let mut out_read = vec![0u64; words];
+    let mut out_write = vec![0u64; words];
+    let mut out_except = vec![0u64; words];
+
+    let ready = wait_for_events(timeout_ms, || {
+        let mut ready = 0;
+        for fd in 0..nfds {
+            let (word, bit) = (fd as usize / 64, 1u64 << (fd % 64));
+            out_read[word] &= !bit;
+            out_write[word] &= !bit;
+            out_except[word] &= !bit;
+            if (want_read[word] | want_write[word] | want_except[word]) & bit == 0 {
+                continue;
+            }
+
+            let mask = vfs::vfs_poll(fd).unwrap_or(POLLERR);
+            if want_read[word] & bit != 0 && mask & (POLLIN | POLLHUP | POLLERR) != 0 {
+                out_read[word] |= bit;
+                ready += 1;
+            }
+            if want_write[word] & bit != 0 && mask & (POLLOUT | POLLERR) != 0 {
+                out_write[word] |= bit;
+                ready += 1;
+            }
+            if want_except[word] & bit != 0 && mask & POLLPRI != 0 {
+                out_except[word] |= bit;
+                ready += 1;
+            }
+        }
+        ready
+    })?;
+
+    let store = |set: *mut u64, bits: &[u64]| {
+        if !set.is_null() {
+            unsafe { core::ptr::copy_nonoverlapping(bits.as_ptr(), set, words) };
+        }
+    };
+    store(readfds, &out_read);
+    store(writefds, &out_write);
+    store(exceptfds, &out_except);
+
+    Ok(ready as i32)
 }
 
 /// select - synchronous I/O multiplexing
@@ -257,16 +432,23 @@ pub fn select(
 ) -> LinuxResult<i32> {
     inc_ops();
 
-    if nfds < 0 {
-        return Err(LinuxError::EINVAL);
-    }
+    let timeout_ms = if timeout.is_null() {
+        -1
+    } else {
+        let tv = unsafe { &*timeout };
+        if tv.tv_sec < 0 || tv.tv_usec < 0 || tv.tv_usec >= 1_000_000 {
+            return Err(LinuxError::EINVAL);
+        }
+        tv.tv_sec as i64 * 1000 + (tv.tv_usec + 999) / 1000
+    };
 
-    // TODO: Implement select using event system
-    // For now, return 0 (timeout with no FDs ready)
-    Ok(0)
+    select_common(nfds, readfds, writefds, exceptfds, timeout_ms)
 }
 
 /// pselect - synchronous I/O multiplexing with signal mask
+///
+/// The signal mask is installed for the duration of the wait and restored
+/// afterwards.
 pub fn pselect(
     nfds: i32,
     readfds: *mut u64,
@@ -277,12 +459,24 @@ pub fn pselect(
 ) -> LinuxResult<i32> {
     inc_ops();
 
-    if nfds < 0 {
-        return Err(LinuxError::EINVAL);
+    let timeout_ms = unsafe { timespec_to_ms(timeout)? };
+
+    let pid = current_pid();
+    let saved_mask = if sigmask.is_null() {
+        None
+    } else {
+        // Linux sigset_t uses bit N-1 for signal N
+        let mask = unsafe { *sigmask } << 1;
+        get_ipc_manager().set_signal_mask(pid, mask).ok()
+    };
+
+    let result = select_common(nfds, readfds, writefds, exceptfds, timeout_ms);
+
+    if let Some(mask) = saved_mask {
+        let _ = get_ipc_manager().set_signal_mask(pid, mask);
     }
 
-    // TODO: Implement pselect with signal masking
-    Ok(0)
+    result
 }
 
 /// epoll_create - create an epoll file descriptor
@@ -293,17 +487,25 @@ pub fn epoll_create(size: i32) -> LinuxResult<Fd> {
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Create epoll instance
-    // Return epoll fd
-    Ok(100)
+    epoll_create1(0)
 }
 
 /// epoll_create1 - create an epoll file descriptor with flags
 pub fn epoll_create1(flags: i32) -> LinuxResult<Fd> {
     inc_ops();
 
-    // TODO: Create epoll instance with flags
-    Ok(100)
+    if flags & !EPOLL_CLOEXEC != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let mut vfs_flags = vfs::OpenFlags::RDONLY;
+    if flags & EPOLL_CLOEXEC != 0 {
+        vfs_flags |= vfs::OpenFlags::CLOEXEC;
+    }
+
+    vfs::get_vfs()
+        .install(Epoll::new(), vfs::OpenFlags::new(vfs_flags))
+        .map_err(|_| LinuxError::EMFILE)
 }
 
 /// epoll_ctl - control an epoll file descriptor
@@ -314,17 +516,43 @@ pub fn epoll_ctl(epfd: Fd, op: i32, fd: Fd, event: *mut u8) -> LinuxResult<i32>
         return Err(LinuxError::EBADF);
     }
 
-    // Operation constants
-    const EPOLL_CTL_ADD: i32 = 1;
-    const EPOLL_CTL_DEL: i32 = 2;
-    const EPOLL_CTL_MOD: i32 = 3;
+    let (epoll_inode, _, _) = vfs::get_vfs().file(epfd).map_err(|_| LinuxError::EBADF)?;
+    let (target, _, _) = vfs::get_vfs().file(fd).map_err(|_| LinuxError::EBADF)?;
 
-    match op {
-        EPOLL_CTL_ADD | EPOLL_CTL_DEL | EPOLL_CTL_MOD => {
-            // TODO: Modify epoll interest list
-            Ok(0)
+    let epoll = epoll_inode
+        .as_any()
+        .and_then(|any| any.downcast_ref::<Epoll>())
+        .ok_or(LinuxError::EINVAL)?;
+
+    if Arc::ptr_eq(&epoll_inode, &target) {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let read_event = || -> LinuxResult<EpollEvent> {
+        if event.is_null() {
+            return Err(LinuxError::EFAULT);
         }
-        _ => Err(LinuxError::EINVAL),
+        Ok(unsafe { core::ptr::read_unaligned(event as *const EpollEvent) })
+    };
+
+    let result = match op {
+        EPOLL_CTL_ADD => {
+            let ev = read_event()?;
+            epoll.add(fd, &target, ev.events, ev.data)
+        }
+        EPOLL_CTL_MOD => {
+            let ev = read_event()?;
+            epoll.modify(fd, ev.events, ev.data)
+        }
+        EPOLL_CTL_DEL => epoll.remove(fd),
+        _ => return Err(LinuxError::EINVAL),
+    };
+
+    match result {
+        Ok(()) => Ok(0),
+        Err(vfs::VfsError::AlreadyExists) => Err(LinuxError::EEXIST),
+        Err(vfs::VfsError::NotFound) => Err(LinuxError::ENOENT),
+        Err(_) => Err(LinuxError::EINVAL),
     }
 }
 
@@ -345,9 +573,24 @@ pub fn epoll_wait(
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Wait for events
-    // Return number of ready file descriptors
-    Ok(0)
+    let (epoll_inode, _, _) = vfs::get_vfs().file(epfd).map_err(|_| LinuxError::EBADF)?;
+    let epoll = epoll_inode
+        .as_any()
+        .and_then(|any| any.downcast_ref::<Epoll>())
+        .ok_or(LinuxError::EINVAL)?;
+
+    let out = events as *mut EpollEvent;
+    let ready = wait_for_events(timeout as i64, || {
+        let ready = epoll.collect(maxevents as usize);
+        for (i, &(mask, data)) in ready.iter().enumerate() {
+            unsafe {
+                core::ptr::write_unaligned(out.add(i), EpollEvent { events: mask, data });
+            }
+        }
+        ready.len()
+    })?;
+
+    Ok(ready as i32)
 }
 
 #[cfg(test)]
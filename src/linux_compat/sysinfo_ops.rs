@@ -12,6 +12,7 @@ use alloc::string::String;
 
 use super::types::*;
 use super::{LinuxResult, LinuxError};
+use crate::process::{self, namespace};
 
 /// Operation counter for statistics
 static SYSINFO_OPS_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -137,6 +138,39 @@ impl UtsName {
     }
 }
 
+/// UTS namespace of the caller
+fn current_uts_ns() -> namespace::NsId {
+    namespace::of(process::current_pid()).uts
+}
+
+/// Only root may rename the system
+fn check_uts_privilege() -> LinuxResult<()> {
+    let uid = process::get_process_manager()
+        .get_process(process::current_pid())
+        .map_or(0, |pcb| pcb.uid);
+    if uid == 0 { Ok(()) } else { Err(LinuxError::EPERM) }
+}
+
+/// Read a name of `len` bytes for sethostname/setdomainname
+fn read_name(name: *const u8, len: usize) -> LinuxResult<String> {
+    let bytes = unsafe { core::slice::from_raw_parts(name, len) };
+    core::str::from_utf8(bytes).map(String::from).map_err(|_| LinuxError::EINVAL)
+}
+
+/// Copy `value` and its terminating NUL to a caller buffer of `len` bytes
+fn write_name(value: &str, name: *mut u8, len: usize) -> LinuxResult<i32> {
+    if value.len() + 1 > len {
+        return Err(LinuxError::ENAMETOOLONG);
+    }
+
+    unsafe {
+        core::ptr::copy_nonoverlapping(value.as_ptr(), name, value.len());
+        *name.add(value.len()) = 0;
+    }
+
+    Ok(0)
+}
+
 // ============================================================================
 // System Information Operations
 // ============================================================================
@@ -163,8 +197,9 @@ pub fn sysinfo(info: *mut SysInfo) -> LinuxResult<i32> {
         si.freeram = 4 * 1024 * 1024 * 1024; // 4 GB
         si.sharedram = 512 * 1024 * 1024; // 512 MB
         si.bufferram = 256 * 1024 * 1024; // 256 MB
-        si.totalswap = 2 * 1024 * 1024 * 1024; // 2 GB
-        si.freeswap = 2 * 1024 * 1024 * 1024; // 2 GB
+        let (swap_total, swap_used) = crate::memory::swap::totals();
+        si.totalswap = swap_total as u64 * crate::memory::PAGE_SIZE as u64;
+        si.freeswap = (swap_total - swap_used) as u64 * crate::memory::PAGE_SIZE as u64;
         si.procs = 50;
         si.mem_unit = 1;
 
@@ -182,9 +217,14 @@ pub fn uname(buf: *mut UtsName) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Get actual system information
+    // Host and domain name come from the caller's UTS namespace
+    let ns = current_uts_ns();
+    let mut uts = UtsName::default();
+    UtsName::copy_str(&mut uts.nodename, namespace::hostname(ns).as_bytes());
+    UtsName::copy_str(&mut uts.domainname, namespace::domainname(ns).as_bytes());
+
     unsafe {
-        *buf = UtsName::default();
+        *buf = uts;
     }
 
     Ok(0)
@@ -198,12 +238,13 @@ pub fn sethostname(name: *const u8, len: usize) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    if len > 64 {
+    if len > namespace::MAX_NAME_LEN {
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Set system hostname
-    // Requires CAP_SYS_ADMIN capability
+    check_uts_privilege()?;
+    let hostname = read_name(name, len)?;
+    namespace::set_hostname(current_uts_ns(), &hostname).map_err(|_| LinuxError::EINVAL)?;
     Ok(0)
 }
 
@@ -219,15 +260,7 @@ pub fn gethostname(name: *mut u8, len: usize) -> LinuxResult<i32> {
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Get actual hostname
-    let hostname = b"localhost\0";
-    let copy_len = core::cmp::min(len, hostname.len());
-
-    unsafe {
-        core::ptr::copy_nonoverlapping(hostname.as_ptr(), name, copy_len);
-    }
-
-    Ok(0)
+    write_name(&namespace::hostname(current_uts_ns()), name, len)
 }
 
 /// setdomainname - set domain name
@@ -238,12 +271,13 @@ pub fn setdomainname(name: *const u8, len: usize) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    if len > 64 {
+    if len > namespace::MAX_NAME_LEN {
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Set domain name
-    // Requires CAP_SYS_ADMIN capability
+    check_uts_privilege()?;
+    let domainname = read_name(name, len)?;
+    namespace::set_domainname(current_uts_ns(), &domainname).map_err(|_| LinuxError::EINVAL)?;
     Ok(0)
 }
 
@@ -259,15 +293,7 @@ pub fn getdomainname(name: *mut u8, len: usize) -> LinuxResult<i32> {
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Get actual domain name
-    let domain = b"(none)\0";
-    let copy_len = core::cmp::min(len, domain.len());
-
-    unsafe {
-        core::ptr::copy_nonoverlapping(domain.as_ptr(), name, copy_len);
-    }
-
-    Ok(0)
+    write_name(&namespace::domainname(current_uts_ns()), name, len)
 }
 
 // ============================================================================
@@ -11,6 +11,10 @@ use core::sync::atomic::{AtomicU64, Ordering};
 
 use super::types::*;
 use super::{LinuxResult, LinuxError};
+use crate::process::{self, ptrace, timers};
+use crate::process::integration::get_integration_manager;
+use crate::process::namespace::{self, NsError};
+use crate::vfs::timerfd::TimerClock;
 
 /// Operation counter for statistics
 static THREAD_OPS_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -118,6 +122,13 @@ pub mod futex_op {
 // ============================================================================
 
 /// clone - create a child process or thread
+///
+/// A new process starts as a copy-on-write fork of the caller, in the new
+/// namespaces its `CLONE_NEW*` flags ask for. User and cgroup namespaces are
+/// not supported. The id returned is the child's as the caller sees it.
+///
+/// Threads are not supported yet either: `CLONE_THREAD` only has its flags
+/// checked, creates nothing and returns a placeholder id.
 pub fn clone(
     flags: u64,
     stack: *mut u8,
@@ -136,6 +147,13 @@ pub fn clone(
         if (flags & clone_flags::CLONE_VM) == 0 {
             return Err(LinuxError::EINVAL);
         }
+        // Threads share the namespaces of their process
+        if (flags & namespace::CLONE_NEW_MASK) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+    }
+    if (flags & (clone_flags::CLONE_NEWUSER | clone_flags::CLONE_NEWCGROUP)) != 0 {
+        return Err(LinuxError::EINVAL);
     }
 
     // Validate stack
@@ -143,14 +161,56 @@ pub fn clone(
         // Stack provided
     }
 
-    // TODO: Create new thread or process
-    // Set up TLS if CLONE_SETTLS
-    // Set parent_tid if CLONE_PARENT_SETTID
-    // Set child_tid if CLONE_CHILD_SETTID
-    // Handle namespace cloning (CLONE_NEWNS, etc.)
+    if (flags & clone_flags::CLONE_THREAD) != 0 {
+        // TODO: Create new thread
+        // Set up TLS if CLONE_SETTLS
+        // Set parent_tid if CLONE_PARENT_SETTID
+        // Set child_tid if CLONE_CHILD_SETTID
+        // No thread is created yet, so nothing runs outside the caller's
+        // seccomp filters; a real thread must share its group's filter
+        // chain, see `process::seccomp`
+        return Ok(1000);
+    }
+
+    let parent = process::current_pid();
+    let process_manager = process::get_process_manager();
+    if (flags & namespace::CLONE_NEW_MASK) != 0 {
+        let uid = process_manager.get_process(parent).map_or(0, |pcb| pcb.uid);
+        if uid != 0 {
+            return Err(LinuxError::EPERM);
+        }
+    }
+
+    let child = get_integration_manager()
+        .fork_process(parent)
+        .map_err(|_| LinuxError::EAGAIN)?;
+
+    if let Err(err) = namespace::clone_into(child, flags) {
+        let _ = process_manager.terminate_process(child, 0);
+        process_manager.reap_process(child);
+        return Err(match err {
+            NsError::TooDeep => LinuxError::ENOSPC,
+            NsError::Defunct => LinuxError::ENOMEM,
+            NsError::NotFound | NsError::InvalidArgument => LinuxError::EINVAL,
+        });
+    }
+
+    let pid = process_manager.pid_in_namespace_of(parent, child).unwrap_or(0) as Pid;
+    if (flags & clone_flags::CLONE_PARENT_SETTID) != 0 && !parent_tid.is_null() {
+        unsafe { *parent_tid = pid; }
+    }
 
-    // Return new thread/process ID
-    Ok(1000)
+    // A traced caller may take its tracer along; the low byte of the flags
+    // is the exit signal, SIGCHLD for a plain fork
+    let event = if (flags & clone_flags::CLONE_VFORK) != 0 {
+        ptrace::PTRACE_EVENT_VFORK
+    } else if (flags & 0xff) == signal::SIGCHLD as u64 {
+        ptrace::PTRACE_EVENT_FORK
+    } else {
+        ptrace::PTRACE_EVENT_CLONE
+    };
+    ptrace::fork_event(parent, child, event);
+    Ok(pid)
 }
 
 /// set_tid_address - set pointer to thread ID
@@ -216,56 +276,65 @@ pub fn futex(
     uaddr2: *mut i32,
     val3: i32,
 ) -> LinuxResult<i32> {
+    use crate::process::futex::{self, FutexKey, BITSET_MATCH_ANY};
+
     inc_ops();
 
     if uaddr.is_null() {
         return Err(LinuxError::EFAULT);
     }
 
-    let op = futex_op & !futex_op::FUTEX_PRIVATE_FLAG;
+    let private = futex_op & futex_op::FUTEX_PRIVATE_FLAG != 0;
+    let realtime = futex_op & futex_op::FUTEX_CLOCK_REALTIME != 0;
+    let op = futex_op & !(futex_op::FUTEX_PRIVATE_FLAG | futex_op::FUTEX_CLOCK_REALTIME);
+    if realtime && op != futex_op::FUTEX_WAIT && op != futex_op::FUTEX_WAIT_BITSET {
+        return Err(LinuxError::ENOSYS);
+    }
+
+    let pid = crate::process::current_pid();
+    let key = FutexKey::new(pid, uaddr as u64, private).map_err(futex_error)?;
+    let count = |n: i32| if n < 0 { usize::MAX } else { n as usize };
 
     match op {
-        futex_op::FUTEX_WAIT => {
-            // TODO: Wait on futex if *uaddr == val
-            // Block until woken or timeout
-            unsafe {
-                if *uaddr != val {
-                    return Err(LinuxError::EAGAIN);
+        futex_op::FUTEX_WAIT | futex_op::FUTEX_WAIT_BITSET => {
+            let bitset = if op == futex_op::FUTEX_WAIT { BITSET_MATCH_ANY } else { val3 as u32 };
+            // FUTEX_WAIT takes a relative timeout on the monotonic clock,
+            // FUTEX_WAIT_BITSET an absolute one on the selected clock
+            let deadline = if timeout.is_null() {
+                None
+            } else {
+                let ts = unsafe { &*timeout };
+                if ts.tv_sec < 0 || !(0..1_000_000_000).contains(&ts.tv_nsec) {
+                    return Err(LinuxError::EINVAL);
                 }
-            }
-            Ok(0)
-        }
-        futex_op::FUTEX_WAKE => {
-            // TODO: Wake up to val waiters on futex
-            // Return number of waiters woken
-            Ok(val)
+                let value_ns = (ts.tv_sec as u64)
+                    .saturating_mul(1_000_000_000)
+                    .saturating_add(ts.tv_nsec as u64);
+                let clock = if realtime { TimerClock::Realtime } else { TimerClock::Monotonic };
+                Some(timers::deadline_for(clock, value_ns, op == futex_op::FUTEX_WAIT_BITSET))
+            };
+            futex::wait(key, uaddr as *const u32, val as u32, bitset, deadline)
+                .map(|()| 0)
+                .map_err(futex_error)
         }
-        futex_op::FUTEX_REQUEUE => {
-            // TODO: Wake val waiters and requeue rest to uaddr2
-            if uaddr2.is_null() {
-                return Err(LinuxError::EFAULT);
-            }
-            Ok(val)
+        futex_op::FUTEX_WAKE | futex_op::FUTEX_WAKE_BITSET => {
+            let bitset = if op == futex_op::FUTEX_WAKE { BITSET_MATCH_ANY } else { val3 as u32 };
+            futex::wake(key, count(val), bitset)
+                .map(|n| n as i32)
+                .map_err(futex_error)
         }
-        futex_op::FUTEX_CMP_REQUEUE => {
-            // TODO: Like REQUEUE but compare *uaddr with val3 first
+        futex_op::FUTEX_REQUEUE | futex_op::FUTEX_CMP_REQUEUE => {
             if uaddr2.is_null() {
                 return Err(LinuxError::EFAULT);
             }
-            unsafe {
-                if *uaddr != val3 {
-                    return Err(LinuxError::EAGAIN);
-                }
-            }
-            Ok(val)
-        }
-        futex_op::FUTEX_WAIT_BITSET => {
-            // TODO: Wait with bitset matching
-            Ok(0)
-        }
-        futex_op::FUTEX_WAKE_BITSET => {
-            // TODO: Wake with bitset matching
-            Ok(val)
+            // The requeue limit travels in the timeout argument
+            let requeue_count = count(timeout as usize as i32);
+            let target = FutexKey::new(pid, uaddr2 as u64, private).map_err(futex_error)?;
+            let expected = (op == futex_op::FUTEX_CMP_REQUEUE)
+                .then_some((uaddr as *const u32, val3 as u32));
+            futex::requeue(key, target, count(val), requeue_count, expected)
+                .map(|n| n as i32)
+                .map_err(futex_error)
         }
         futex_op::FUTEX_LOCK_PI => {
             // TODO: Lock priority-inheritance futex
@@ -283,6 +352,18 @@ pub fn futex(
     }
 }
 
+fn futex_error(error: crate::process::futex::FutexError) -> LinuxError {
+    use crate::process::futex::FutexError;
+
+    match error {
+        FutexError::WouldBlock => LinuxError::EAGAIN,
+        FutexError::TimedOut => LinuxError::ETIMEDOUT,
+        FutexError::Interrupted => LinuxError::EINTR,
+        FutexError::Fault => LinuxError::EFAULT,
+        FutexError::InvalidArgument => LinuxError::EINVAL,
+    }
+}
+
 /// robust_list_head for futex robustness
 #[repr(C)]
 pub struct RobustListHead {
@@ -406,38 +487,17 @@ pub fn arch_prctl(code: i32, addr: u64) -> LinuxResult<i32> {
 pub type CpuSet = u64;
 
 /// sched_setaffinity - set CPU affinity
+///
+/// Every thread is scheduled on its own, so `pid` may name any of them.
 pub fn sched_setaffinity(pid: Pid, cpusetsize: usize, mask: *const CpuSet) -> LinuxResult<i32> {
     inc_ops();
-
-    if mask.is_null() {
-        return Err(LinuxError::EFAULT);
-    }
-
-    if cpusetsize == 0 {
-        return Err(LinuxError::EINVAL);
-    }
-
-    // TODO: Set CPU affinity for thread
-    Ok(0)
+    super::process_ops::sched_setaffinity(pid, cpusetsize, mask as *const u8)
 }
 
 /// sched_getaffinity - get CPU affinity
 pub fn sched_getaffinity(pid: Pid, cpusetsize: usize, mask: *mut CpuSet) -> LinuxResult<i32> {
     inc_ops();
-
-    if mask.is_null() {
-        return Err(LinuxError::EFAULT);
-    }
-
-    if cpusetsize == 0 {
-        return Err(LinuxError::EINVAL);
-    }
-
-    // TODO: Get CPU affinity for thread
-    unsafe {
-        *mask = 0xFFFF_FFFF_FFFF_FFFF; // All CPUs
-    }
-    Ok(0)
+    super::process_ops::sched_getaffinity(pid, cpusetsize, mask as *mut u8)
 }
 
 // ============================================================================
@@ -7,6 +7,10 @@ use core::sync::atomic::{AtomicU64, Ordering};
 
 use super::types::*;
 use super::{LinuxResult, LinuxError};
+use super::ipc_ops::ITimerSpec;
+use crate::process::{self, ipc::Signal, timers::{self, CpuTimer, TimerError}, wait_queue::WaitError};
+use crate::time;
+use crate::vfs::timerfd::{TimerClock, TimerSpec};
 
 /// Operation counter for statistics
 static TIME_OPS_COUNT: AtomicU64 = AtomicU64::new(0);
@@ -26,6 +30,97 @@ fn inc_ops() {
     TIME_OPS_COUNT.fetch_add(1, Ordering::Relaxed);
 }
 
+/// Notification methods in `struct sigevent`
+pub const SIGEV_SIGNAL: i32 = 0;
+pub const SIGEV_NONE: i32 = 1;
+pub const SIGEV_THREAD: i32 = 2;
+pub const SIGEV_THREAD_ID: i32 = 4;
+
+/// Flag for clock_nanosleep and timer_settime: the time is absolute
+pub const TIMER_ABSTIME: i32 = 1;
+
+/// Interval timer kinds for setitimer/getitimer
+pub const ITIMER_REAL: i32 = 0;
+pub const ITIMER_VIRTUAL: i32 = 1;
+pub const ITIMER_PROF: i32 = 2;
+
+/// Timer notification request (struct sigevent)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SigEvent {
+    pub sigev_value: u64,
+    pub sigev_signo: i32,
+    pub sigev_notify: i32,
+    /// Union of the notify thread id and SIGEV_THREAD attributes
+    pub sigev_un: [i32; 12],
+}
+
+/// Interval timer setting (struct itimerval)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ITimerVal {
+    pub it_interval: TimeVal,
+    pub it_value: TimeVal,
+}
+
+// ============================================================================
+// Clocks
+// ============================================================================
+
+/// Current value of a clock in nanoseconds
+///
+/// Wall-clock and monotonic clocks come from the TSC-backed uptime counter;
+/// CPU-time clocks from the per-process and per-thread accounting done at
+/// every context switch.
+fn clock_now_ns(clockid: i32) -> LinuxResult<u64> {
+    match clockid {
+        clock::CLOCK_REALTIME => Ok(time::realtime_ns()),
+        clock::CLOCK_MONOTONIC | clock::CLOCK_MONOTONIC_RAW | clock::CLOCK_BOOTTIME => Ok(time::uptime_ns()),
+        clock::CLOCK_PROCESS_CPUTIME_ID => process::get_process_manager()
+            .cpu_time_ns(process::current_pid())
+            .ok_or(LinuxError::EINVAL),
+        clock::CLOCK_THREAD_CPUTIME_ID => {
+            let threads = process::thread::get_thread_manager();
+            // Processes without thread bookkeeping are single-threaded
+            threads
+                .cpu_time_ns(threads.current_thread())
+                .or_else(|| process::get_process_manager().cpu_time_ns(process::current_pid()))
+                .ok_or(LinuxError::EINVAL)
+        }
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Clock a sleep or timer on `clockid` is measured against
+fn timer_clock(clockid: i32) -> LinuxResult<TimerClock> {
+    match clockid {
+        clock::CLOCK_REALTIME => Ok(TimerClock::Realtime),
+        clock::CLOCK_MONOTONIC | clock::CLOCK_BOOTTIME => Ok(TimerClock::Monotonic),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Read and validate a user timespec as nanoseconds
+fn read_timespec(tp: *const TimeSpec) -> LinuxResult<u64> {
+    if tp.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+
+    let ts = unsafe { *tp };
+    if ts.tv_sec < 0 || ts.tv_nsec < 0 || ts.tv_nsec >= 1_000_000_000 {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(timespec_to_ns(&ts) as u64)
+}
+
+/// Only root may set the system clock
+fn check_time_privilege() -> LinuxResult<()> {
+    let uid = process::get_process_manager()
+        .get_process(process::current_pid())
+        .map_or(0, |pcb| pcb.uid);
+    if uid == 0 { Ok(()) } else { Err(LinuxError::EPERM) }
+}
+
 /// clock_gettime - get time of specified clock
 pub fn clock_gettime(clockid: i32, tp: *mut TimeSpec) -> LinuxResult<i32> {
     inc_ops();
@@ -34,19 +129,11 @@ pub fn clock_gettime(clockid: i32, tp: *mut TimeSpec) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    match clockid {
-        clock::CLOCK_REALTIME | clock::CLOCK_MONOTONIC |
-        clock::CLOCK_PROCESS_CPUTIME_ID | clock::CLOCK_THREAD_CPUTIME_ID |
-        clock::CLOCK_MONOTONIC_RAW | clock::CLOCK_BOOTTIME => {
-            // TODO: Get actual time from hardware timer
-            unsafe {
-                (*tp).tv_sec = 0;
-                (*tp).tv_nsec = 0;
-            }
-            Ok(0)
-        }
-        _ => Err(LinuxError::EINVAL),
+    let now = clock_now_ns(clockid)?;
+    unsafe {
+        *tp = ns_to_timespec(now as i64);
     }
+    Ok(0)
 }
 
 /// clock_settime - set time of specified clock
@@ -59,8 +146,13 @@ pub fn clock_settime(clockid: i32, tp: *const TimeSpec) -> LinuxResult<i32> {
 
     match clockid {
         clock::CLOCK_REALTIME => {
-            // TODO: Set real-time clock (requires privileges)
-            // Check if current process has CAP_SYS_TIME capability
+            let ns = read_timespec(tp)?;
+            // Saturated: too far out to represent
+            if ns >= i64::MAX as u64 {
+                return Err(LinuxError::EINVAL);
+            }
+            check_time_privilege()?;
+            time::set_realtime_ns(ns);
             Ok(0)
         }
         _ => Err(LinuxError::EINVAL), // Only CLOCK_REALTIME can be set
@@ -79,8 +171,7 @@ pub fn clock_getres(clockid: i32, res: *mut TimeSpec) -> LinuxResult<i32> {
         clock::CLOCK_REALTIME | clock::CLOCK_MONOTONIC |
         clock::CLOCK_PROCESS_CPUTIME_ID | clock::CLOCK_THREAD_CPUTIME_ID |
         clock::CLOCK_MONOTONIC_RAW | clock::CLOCK_BOOTTIME => {
-            // TODO: Get actual clock resolution
-            // Most systems have nanosecond resolution
+            // Every clock is derived from the TSC, scaled to nanoseconds
             unsafe {
                 (*res).tv_sec = 0;
                 (*res).tv_nsec = 1; // 1 nanosecond resolution
@@ -91,33 +182,34 @@ pub fn clock_getres(clockid: i32, res: *mut TimeSpec) -> LinuxResult<i32> {
     }
 }
 
+// ============================================================================
+// Sleeping
+// ============================================================================
+
+/// Sleep until a monotonic deadline, storing the time left in `rem` if a
+/// signal cuts the sleep short
+fn sleep_until(deadline_ns: u64, rem: *mut TimeSpec) -> LinuxResult<i32> {
+    match timers::sleep_until(deadline_ns) {
+        Ok(()) => Ok(0),
+        Err(WaitError::Interrupted) => {
+            if !rem.is_null() {
+                let left = deadline_ns.saturating_sub(time::uptime_ns());
+                unsafe {
+                    *rem = ns_to_timespec(left.min(i64::MAX as u64) as i64);
+                }
+            }
+            Err(LinuxError::EINTR)
+        }
+    }
+}
+
 /// nanosleep - high-resolution sleep
 pub fn nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> LinuxResult<i32> {
     inc_ops();
 
-    if req.is_null() {
-        return Err(LinuxError::EFAULT);
-    }
-
-    unsafe {
-        let sleep_time = (*req);
-
-        // Validate sleep time
-        if sleep_time.tv_sec < 0 || sleep_time.tv_nsec < 0 || sleep_time.tv_nsec >= 1_000_000_000 {
-            return Err(LinuxError::EINVAL);
-        }
-
-        // TODO: Implement actual sleep using kernel timer
-        // For now, just spin (not ideal for real system)
-
-        // If interrupted by signal and rem is not null, store remaining time
-        if !rem.is_null() {
-            (*rem).tv_sec = 0;
-            (*rem).tv_nsec = 0;
-        }
-    }
-
-    Ok(0)
+    let duration = read_timespec(req)?;
+    // A deadline past the end of time saturates and is never reached
+    sleep_until(time::uptime_ns().saturating_add(duration), rem)
 }
 
 /// clock_nanosleep - high-resolution sleep on specific clock
@@ -133,21 +225,14 @@ pub fn clock_nanosleep(
         return Err(LinuxError::EFAULT);
     }
 
-    const TIMER_ABSTIME: i32 = 1;
+    let clock = timer_clock(clockid)?;
+    let value = read_timespec(req)?;
 
-    match clockid {
-        clock::CLOCK_REALTIME | clock::CLOCK_MONOTONIC => {
-            // TODO: Implement clock-specific sleep
-            if flags & TIMER_ABSTIME != 0 {
-                // Absolute time sleep
-                // TODO: Sleep until specified absolute time
-            } else {
-                // Relative time sleep
-                return nanosleep(req, rem);
-            }
-            Ok(0)
-        }
-        _ => Err(LinuxError::EINVAL),
+    if flags & TIMER_ABSTIME != 0 {
+        // An absolute sleep restarts with the same deadline; rem is unused
+        sleep_until(timers::deadline_for(clock, value, true), core::ptr::null_mut())
+    } else {
+        sleep_until(time::uptime_ns().saturating_add(value), rem)
     }
 }
 
@@ -159,10 +244,10 @@ pub fn gettimeofday(tv: *mut TimeVal, tz: *mut u8) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Get actual time of day
+    let now = time::realtime_ns();
     unsafe {
-        (*tv).tv_sec = 0;
-        (*tv).tv_usec = 0;
+        (*tv).tv_sec = (now / 1_000_000_000) as i64;
+        (*tv).tv_usec = (now % 1_000_000_000 / 1000) as i64;
     }
 
     // tz is obsolete and should be NULL
@@ -181,18 +266,46 @@ pub fn settimeofday(tv: *const TimeVal, tz: *const u8) -> LinuxResult<i32> {
         return Err(LinuxError::EFAULT);
     }
 
-    // TODO: Set time of day (requires privileges)
     // tz is obsolete
     if !tz.is_null() {
         return Err(LinuxError::EINVAL);
     }
 
+    let tv = unsafe { *tv };
+    if tv.tv_sec < 0 || tv.tv_usec < 0 || tv.tv_usec >= 1_000_000 {
+        return Err(LinuxError::EINVAL);
+    }
+    let ns = (tv.tv_sec as u64)
+        .checked_mul(1_000_000_000)
+        .and_then(|ns| ns.checked_add(tv.tv_usec as u64 * 1000))
+        .filter(|&ns| ns <= i64::MAX as u64)
+        .ok_or(LinuxError::EINVAL)?;
+    check_time_privilege()?;
+    time::set_realtime_ns(ns);
+
     Ok(0)
 }
 
+// ============================================================================
+// POSIX timers
+// ============================================================================
+
 /// Timer ID type
 pub type TimerId = i32;
 
+fn timer_error_to_linux(err: TimerError) -> LinuxError {
+    match err {
+        TimerError::InvalidTimer => LinuxError::EINVAL,
+        TimerError::TooManyTimers => LinuxError::EAGAIN,
+    }
+}
+
+/// Timer ids handed to user space are never negative; negative slots are
+/// reserved for alarm() and setitimer()
+fn check_timer_id(timerid: TimerId) -> LinuxResult<()> {
+    if timerid < 0 { Err(LinuxError::EINVAL) } else { Ok(()) }
+}
+
 /// timer_create - create a POSIX timer
 pub fn timer_create(
     clockid: i32,
@@ -205,17 +318,31 @@ pub fn timer_create(
         return Err(LinuxError::EFAULT);
     }
 
-    match clockid {
-        clock::CLOCK_REALTIME | clock::CLOCK_MONOTONIC => {
-            // TODO: Create actual timer
-            // For now, return a dummy timer ID
-            unsafe {
-                *timerid = 1;
+    let clock = timer_clock(clockid)?;
+    let pid = process::current_pid();
+
+    // A NULL sigevent means SIGEV_SIGNAL with SIGALRM and the timer id as value
+    let (signal, value) = if sevp.is_null() {
+        (Some(Signal::SIGALRM), None)
+    } else {
+        let event = unsafe { *(sevp as *const SigEvent) };
+        match event.sigev_notify {
+            SIGEV_NONE => (None, Some(event.sigev_value)),
+            SIGEV_SIGNAL | SIGEV_THREAD_ID => {
+                let signal = Signal::from_number(event.sigev_signo).ok_or(LinuxError::EINVAL)?;
+                (Some(signal), Some(event.sigev_value))
             }
-            Ok(0)
+            // SIGEV_THREAD is implemented by the C library on top of SIGEV_THREAD_ID
+            _ => return Err(LinuxError::EINVAL),
         }
-        _ => Err(LinuxError::EINVAL),
+    };
+
+    let id = timers::create(pid, clock, signal, value).map_err(timer_error_to_linux)?;
+
+    unsafe {
+        *timerid = id;
     }
+    Ok(0)
 }
 
 /// timer_settime - arm/disarm a timer
@@ -230,8 +357,20 @@ pub fn timer_settime(
     if new_value.is_null() {
         return Err(LinuxError::EFAULT);
     }
+    check_timer_id(timerid)?;
+    if flags & !TIMER_ABSTIME != 0 {
+        return Err(LinuxError::EINVAL);
+    }
 
-    // TODO: Set timer value
+    let spec = unsafe { *(new_value as *const ITimerSpec) }.to_spec()?;
+    let old = timers::settime(process::current_pid(), timerid, spec, flags & TIMER_ABSTIME != 0)
+        .map_err(timer_error_to_linux)?;
+
+    if !old_value.is_null() {
+        unsafe {
+            *(old_value as *mut ITimerSpec) = ITimerSpec::from_spec(old);
+        }
+    }
     Ok(0)
 }
 
@@ -245,8 +384,12 @@ pub fn timer_gettime(
     if curr_value.is_null() {
         return Err(LinuxError::EFAULT);
     }
+    check_timer_id(timerid)?;
 
-    // TODO: Get timer value
+    let spec = timers::gettime(process::current_pid(), timerid).map_err(timer_error_to_linux)?;
+    unsafe {
+        *(curr_value as *mut ITimerSpec) = ITimerSpec::from_spec(spec);
+    }
     Ok(0)
 }
 
@@ -254,7 +397,8 @@ pub fn timer_gettime(
 pub fn timer_delete(timerid: TimerId) -> LinuxResult<i32> {
     inc_ops();
 
-    // TODO: Delete timer
+    check_timer_id(timerid)?;
+    timers::delete(process::current_pid(), timerid).map_err(timer_error_to_linux)?;
     Ok(0)
 }
 
@@ -262,26 +406,132 @@ pub fn timer_delete(timerid: TimerId) -> LinuxResult<i32> {
 pub fn timer_getoverrun(timerid: TimerId) -> LinuxResult<i32> {
     inc_ops();
 
-    // TODO: Get overrun count
+    check_timer_id(timerid)?;
+    let overrun = timers::getoverrun(process::current_pid(), timerid).map_err(timer_error_to_linux)?;
+    Ok(overrun.min(i32::MAX as u32) as i32)
+}
+
+// ============================================================================
+// Interval timers
+// ============================================================================
+
+fn timeval_to_ns(tv: &TimeVal) -> LinuxResult<u64> {
+    if tv.tv_sec < 0 || tv.tv_usec < 0 || tv.tv_usec >= 1_000_000 {
+        return Err(LinuxError::EINVAL);
+    }
+    // Saturates, like Linux, rather than wrap to a near deadline
+    Ok((tv.tv_sec as u64).saturating_mul(1_000_000_000).saturating_add(tv.tv_usec as u64 * 1000))
+}
+
+fn ns_to_timeval(ns: u64) -> TimeVal {
+    TimeVal {
+        tv_sec: (ns / 1_000_000_000) as i64,
+        // Round up so an armed timer never reads back as zero
+        tv_usec: ((ns % 1_000_000_000 + 999) / 1000) as i64,
+    }
+}
+
+fn itimerval_from_spec(spec: TimerSpec) -> ITimerVal {
+    ITimerVal {
+        it_interval: ns_to_timeval(spec.interval_ns),
+        it_value: ns_to_timeval(spec.value_ns),
+    }
+}
+
+/// CPU-time timer behind an ITIMER_VIRTUAL or ITIMER_PROF `which`
+fn cpu_timer(which: i32) -> Option<CpuTimer> {
+    match which {
+        ITIMER_VIRTUAL => Some(CpuTimer::Virtual),
+        ITIMER_PROF => Some(CpuTimer::Prof),
+        _ => None,
+    }
+}
+
+/// setitimer - set an interval timer
+///
+/// ITIMER_REAL runs on the monotonic clock and shares its timer with alarm;
+/// ITIMER_VIRTUAL and ITIMER_PROF run on the process's CPU time.
+pub fn setitimer(which: i32, new_value: *const ITimerVal, old_value: *mut ITimerVal) -> LinuxResult<i32> {
+    inc_ops();
+
+    if new_value.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+    if which != ITIMER_REAL && cpu_timer(which).is_none() {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let new_value = unsafe { *new_value };
+    let spec = TimerSpec {
+        interval_ns: timeval_to_ns(&new_value.it_interval)?,
+        value_ns: timeval_to_ns(&new_value.it_value)?,
+    };
+    let pid = process::current_pid();
+    let old = match cpu_timer(which) {
+        Some(timer) => timers::set_cpu_timer(pid, timer, spec),
+        None => timers::set_real_timer(pid, spec),
+    };
+    if !old_value.is_null() {
+        unsafe {
+            *old_value = itimerval_from_spec(old);
+        }
+    }
+    Ok(0)
+}
+
+/// getitimer - get the value of an interval timer
+pub fn getitimer(which: i32, curr_value: *mut ITimerVal) -> LinuxResult<i32> {
+    inc_ops();
+
+    if curr_value.is_null() {
+        return Err(LinuxError::EFAULT);
+    }
+
+    let pid = process::current_pid();
+    let spec = match (which, cpu_timer(which)) {
+        (_, Some(timer)) => timers::get_cpu_timer(pid, timer),
+        (ITIMER_REAL, None) => timers::get_real_timer(pid),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    unsafe {
+        *curr_value = itimerval_from_spec(spec);
+    }
     Ok(0)
 }
 
 /// alarm - set an alarm clock
+///
+/// Shares its timer with ITIMER_REAL.
 pub fn alarm(seconds: u32) -> u32 {
     inc_ops();
 
-    // TODO: Set alarm
-    // Return seconds remaining on previous alarm, or 0
-    0
+    let spec = TimerSpec {
+        interval_ns: 0,
+        value_ns: seconds as u64 * 1_000_000_000,
+    };
+    let old = timers::set_real_timer(process::current_pid(), spec);
+
+    // Seconds left on the previous alarm, rounded; never 0 while it was armed
+    if old.value_ns == 0 {
+        0
+    } else {
+        ((old.value_ns + 500_000_000) / 1_000_000_000).max(1) as u32
+    }
 }
 
 /// sleep - sleep for specified number of seconds
 pub fn sleep(seconds: u32) -> u32 {
     inc_ops();
 
-    // TODO: Implement sleep
-    // Return 0 if full sleep completed, else remaining seconds
-    0
+    let deadline = time::uptime_ns().saturating_add(seconds as u64 * 1_000_000_000);
+    match timers::sleep_until(deadline) {
+        Ok(()) => 0,
+        // Remaining whole seconds, rounded up
+        Err(WaitError::Interrupted) => {
+            let left = deadline.saturating_sub(time::uptime_ns());
+            ((left + 999_999_999) / 1_000_000_000) as u32
+        }
+    }
 }
 
 /// usleep - suspend execution for microsecond intervals
@@ -292,13 +542,13 @@ pub fn usleep(usec: u32) -> LinuxResult<i32> {
         return Err(LinuxError::EINVAL);
     }
 
-    // TODO: Implement microsecond sleep
+    timers::sleep_ns(usec as u64 * 1000).map_err(|_| LinuxError::EINTR)?;
     Ok(0)
 }
 
-/// Convert TimeSpec to nanoseconds
+/// Convert TimeSpec to nanoseconds, saturating at the ends of the range
 pub fn timespec_to_ns(ts: &TimeSpec) -> i64 {
-    ts.tv_sec * 1_000_000_000 + ts.tv_nsec
+    ts.tv_sec.saturating_mul(1_000_000_000).saturating_add(ts.tv_nsec)
 }
 
 /// Convert nanoseconds to TimeSpec
@@ -334,6 +584,56 @@ mod tests {
         assert_eq!(converted_back, ns);
     }
 
+    #[test]
+    fn test_timespec_conversion_saturates() {
+        let ts = TimeSpec { tv_sec: i64::MAX, tv_nsec: 999_999_999 };
+        assert_eq!(timespec_to_ns(&ts), i64::MAX);
+    }
+
+    #[test]
+    fn test_posix_timer_lifecycle() {
+        let mut id: TimerId = -1;
+        assert!(timer_create(clock::CLOCK_MONOTONIC, core::ptr::null(), &mut id).is_ok());
+        assert!(id >= 0);
+
+        let spec = ITimerSpec::from_spec(TimerSpec { interval_ns: 0, value_ns: 5_000_000_000 });
+        assert!(timer_settime(id, 0, &spec as *const _ as *const u8, core::ptr::null_mut()).is_ok());
+
+        let mut current = ITimerSpec::from_spec(TimerSpec::default());
+        assert!(timer_gettime(id, &mut current as *mut _ as *mut u8).is_ok());
+        assert!(current.to_spec().unwrap().value_ns > 0);
+
+        assert!(timer_delete(id).is_ok());
+        assert_eq!(timer_delete(id), Err(LinuxError::EINVAL));
+        assert_eq!(timer_delete(timers::REAL_TIMER), Err(LinuxError::EINVAL));
+    }
+
+    #[test]
+    fn test_cpu_interval_timers() {
+        let tv = |sec| TimeVal { tv_sec: sec, tv_usec: 0 };
+        let armed = ITimerVal { it_interval: tv(1), it_value: tv(2) };
+        let disarmed = ITimerVal { it_interval: tv(0), it_value: tv(0) };
+        let mut old = disarmed;
+
+        assert_eq!(setitimer(ITIMER_PROF, &armed, &mut old), Ok(0));
+        assert_eq!((old.it_value.tv_sec, old.it_value.tv_usec), (0, 0));
+
+        let mut current = disarmed;
+        assert_eq!(getitimer(ITIMER_PROF, &mut current), Ok(0));
+        assert_eq!(current.it_interval.tv_sec, 1);
+        assert!(current.it_value.tv_sec <= 2 && (current.it_value.tv_sec, current.it_value.tv_usec) != (0, 0));
+        // The virtual timer is separate
+        assert_eq!(getitimer(ITIMER_VIRTUAL, &mut current), Ok(0));
+        assert_eq!((current.it_value.tv_sec, current.it_value.tv_usec), (0, 0));
+
+        assert_eq!(setitimer(ITIMER_PROF, &disarmed, &mut old), Ok(0));
+        assert_eq!(old.it_interval.tv_sec, 1);
+        assert_eq!(getitimer(ITIMER_PROF, &mut current), Ok(0));
+        assert_eq!((current.it_value.tv_sec, current.it_value.tv_usec), (0, 0));
+
+        assert_eq!(getitimer(3, &mut current), Err(LinuxError::EINVAL));
+    }
+
     #[test]
     fn test_nanosleep_validation() {
         let mut invalid_ts = TimeSpec::new(0, 2_000_000_000); // Invalid nsec
@@ -406,4 +406,6 @@ pub mod signal {
     pub const SIGSTOP: i32 = 19;
     /// Keyboard stop
     pub const SIGTSTP: i32 = 20;
+    /// Bad system call
+    pub const SIGSYS: i32 = 31;
 }
@@ -7,11 +7,11 @@ extern crate alloc;
 use core::panic::PanicInfo;
 use bootloader::{BootInfo, entry_point};
 use alloc::string::ToString;
-use linked_list_allocator::LockedHeap;
 
-// Global allocator for heap memory
+// Global allocator for heap memory: slab caches once memory management is
+// up, the early heap until then
 #[global_allocator]
-pub static ALLOCATOR: LockedHeap = LockedHeap::empty();
+pub static ALLOCATOR: memory::slab::SlabAllocator = memory::slab::SlabAllocator::new();
 
 // Include compiler intrinsics for missing symbols
 mod intrinsics;
@@ -22,6 +22,8 @@ mod vga_buffer;
 mod print;
 // Include basic memory management
 mod memory_basic;
+// Include kernel address space layout randomisation
+mod kaslr;
 // Include full memory management
 mod memory;
 // Include filesystem
@@ -48,6 +50,8 @@ mod desktop;
 mod serial;
 // Include time management system
 mod time;
+// Include high-resolution timers
+mod hrtimer;
 // Include GDT (Global Descriptor Table)
 mod gdt;
 // Include interrupt handling
@@ -70,6 +74,8 @@ pub mod net;
 pub use net as network;
 // Include security
 mod security;
+// Include kernel tunables
+mod sysctl;
 // Include IPC
 mod ipc;
 // Include kernel core
@@ -234,6 +240,16 @@ fn boot_info_summary(boot_info: &BootInfo) -> (u64, u64, usize) {
 }
 
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
+    // Move the direct map and the kernel image before anything points into
+    // them; kernel_main runs again from the new image address
+    if !kaslr::relocated() {
+        kaslr::randomize_physmap(boot_info.physical_memory_offset, boot_info.memory_map.iter().as_slice());
+        kaslr::relocate_kernel(boot_info as *const BootInfo as usize, |info| {
+            // SAFETY: `info` is the BootInfo address passed in above
+            kernel_main(unsafe { &*(info as *const BootInfo) })
+        });
+    }
+
     // Initialize early serial output for debugging
     // SAFETY: Raw I/O to COM1 for early logging. See docs/SAFETY.md#io-port-access.
     unsafe {
@@ -279,12 +295,13 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
         early_serial_write_str("RustOS: Initializing heap allocator from memory map...\r\n");
     }
 
-    // Get physical memory offset from bootloader (requires map_physical_memory feature)
-    let phys_mem_offset = boot_info.physical_memory_offset;
+    // Physical memory offset from the bootloader (requires map_physical_memory feature),
+    // after KASLR moved it
+    let phys_mem_offset = kaslr::physmap_offset();
 
     // Initialize the kernel heap using bootloader's memory map - MUST happen before any String/Vec/Box usage
     if let Err(_e) = memory_basic::init_heap_from_memory_map(
-        &ALLOCATOR,
+        ALLOCATOR.heap(),
         boot_info.memory_map.iter().as_slice(),
         phys_mem_offset,
     ) {
@@ -412,6 +429,13 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
         boot_ui::report_warning("Syscall", "Using INT 0x80 fallback");
     }
 
+    // Bring up the application processors; they idle until there is work
+    boot_ui::update_substage(6, "Starting application processors...");
+    match smp::init().and_then(|()| smp::start_all_aps()) {
+        Ok(online) => boot_ui::report_success(&alloc::format!("{} CPU(s) online", online)),
+        Err(e) => boot_ui::report_warning("SMP", e),
+    }
+
     // SAFETY: Debug output
     unsafe { early_serial_write_str("RustOS: Syscall init done, completing stage...\r\n"); }
 
@@ -462,6 +486,13 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     interrupts::enable_timer_interrupt();
     unsafe { early_serial_write_str("RustOS: Timer interrupt enabled\r\n"); }
 
+    // Move the boot CPU's tick to its one-shot local APIC timer, so that it
+    // can stop while idle; the periodic timer stays in charge otherwise
+    if time_initialized && hrtimer::init_cpu().is_ok() {
+        time::stop_periodic_timer();
+        log_info!("kernel", "High-resolution timers active, tick stops on idle CPUs");
+    }
+
     // Enable keyboard interrupt for user input
     unsafe { early_serial_write_str("RustOS: Enabling keyboard interrupt...\r\n"); }
     interrupts::enable_keyboard_interrupt();
@@ -15,8 +15,8 @@
 use x86_64::{
     VirtAddr, PhysAddr,
     structures::paging::{
-        PageTable, PageTableFlags, PhysFrame, Size4KiB, FrameAllocator,
-        OffsetPageTable, Page, Mapper, mapper::MapToError, Translate,
+        PageTable, PageTableEntry, PageTableFlags, PhysFrame, Size4KiB, Size2MiB, FrameAllocator,
+        OffsetPageTable, Page, Mapper, mapper::{MapToError, MappedFrame, TranslateResult}, Translate,
     },
     registers::control::Cr3,
 };
@@ -24,8 +24,9 @@ use bootloader::bootinfo::MemoryRegion;
 use spin::{Mutex, RwLock};
 use lazy_static::lazy_static;
 use alloc::{collections::BTreeMap, vec::Vec, vec};
-use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use core::fmt;
+use crate::process::cgroup;
 use crate::performance::{
     CacheAligned, PerCpuAllocator,
     get_performance_monitor, HighResTimer, likely
@@ -33,10 +34,23 @@ use crate::performance::{
 
 // User space memory operations module
 pub mod user_space;
+pub mod swap;
+pub mod zram;
+pub mod file_mapping;
+pub mod vma;
+pub mod huge_page;
+pub mod numa;
+pub mod slab;
+pub mod oom;
+pub mod aslr;
+pub mod stack;
 
 /// Page size constants
 pub const PAGE_SIZE: usize = 4096;
 pub const PAGE_SHIFT: usize = 12;
+pub const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+/// Buddy order of a 2 MiB huge page
+pub const HUGE_PAGE_ORDER: usize = 9;
 
 /// Memory layout constants for virtual address space
 pub const KERNEL_HEAP_START: usize = 0x_4444_4444_0000;
@@ -45,6 +59,13 @@ pub const USER_SPACE_START: usize = 0x_0000_1000_0000;
 pub const USER_SPACE_END: usize = 0x_0000_8000_0000;
 pub const KERNEL_SPACE_START: usize = 0xFFFF_8000_0000_0000;
 
+/// Physical memory below this is never handed out by the frame allocator
+const LOW_MEMORY_END: u64 = 1024 * 1024; // 1MB
+
+/// How often a kernel page fault retries the memory manager's locks before
+/// concluding that the faulting code holds one
+const FAULT_LOCK_SPINS: usize = 1 << 20;
+
 /// Physical memory zone boundaries
 pub const DMA_ZONE_END: u64 = 16 * 1024 * 1024; // 16MB
 pub const NORMAL_ZONE_END: u64 = 896 * 1024 * 1024; // 896MB
@@ -55,9 +76,6 @@ const MIN_ORDER: usize = 0;  // 4KB pages
 const MAX_ORDER: usize = 10; // 4MB max allocation (2^10 * 4KB)
 const NUM_ORDERS: usize = MAX_ORDER + 1;
 
-/// ASLR entropy bits
-const ASLR_ENTROPY_BITS: u32 = 16;
-
 /// Memory zone types for different hardware requirements
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MemoryZone {
@@ -107,6 +125,10 @@ pub enum MemoryRegionType {
     CopyOnWrite,
     /// Guard page
     GuardPage,
+    /// Memory-mapped file (see `file_mapping`)
+    FileMapping,
+    /// hugetlbfs-backed mapping of 2 MiB pages (see `huge_page`)
+    HugeTlb,
 }
 
 /// Memory protection flags
@@ -256,7 +278,8 @@ impl MemoryProtection {
         if self.writable && !self.copy_on_write {
             flags |= PageTableFlags::WRITABLE;
         }
-        if self.user_accessible {
+        // PROT_NONE pages stay present but out of user mode's reach
+        if self.user_accessible && self.readable {
             flags |= PageTableFlags::USER_ACCESSIBLE;
         }
         if !self.executable {
@@ -307,91 +330,121 @@ pub struct FragmentationStats {
     pub fragmentation_ratio: f32,
 }
 
+/// Zones of each NUMA node
+const ZONES_PER_NODE: usize = 3;
+
+/// Zones in the order allocations without a zone requirement try them
+const ZONE_FALLBACK: [MemoryZone; ZONES_PER_NODE] = [MemoryZone::Normal, MemoryZone::HighMem, MemoryZone::Dma];
+
 /// Production-grade Physical Frame Allocator with Buddy System and Performance Optimizations
+///
+/// Each NUMA node has its own three zones; zone `zone` of node `node` is at
+/// index `node * ZONES_PER_NODE + zone` of the per-zone vectors.
 pub struct PhysicalFrameAllocator {
     /// Cache-aligned buddy allocator free lists for each order and zone
-    buddy_lists: [[CacheAligned<Vec<BuddyNode>>; NUM_ORDERS]; 3],
+    buddy_lists: Vec<[CacheAligned<Vec<BuddyNode>>; NUM_ORDERS]>,
     /// Allocation bitmap for tracking allocated blocks
-    allocation_bitmap: [Vec<u64>; 3],
+    allocation_bitmap: Vec<Vec<u64>>,
     /// Zone statistics (cache-aligned for better performance)
-    allocated_frames: [CacheAligned<AtomicU64>; 3],
-    total_frames: [usize; 3],
+    allocated_frames: Vec<CacheAligned<AtomicU64>>,
+    total_frames: Vec<usize>,
     /// Zone memory boundaries
-    zone_start: [PhysAddr; 3],
-    zone_end: [PhysAddr; 3],
+    zone_start: Vec<PhysAddr>,
+    zone_end: Vec<PhysAddr>,
     /// Fragmentation statistics (cache-aligned)
-    fragmentation_stats: [CacheAligned<FragmentationStats>; 3],
+    fragmentation_stats: Vec<CacheAligned<FragmentationStats>>,
+    /// Number of NUMA nodes
+    nodes: usize,
     /// Per-CPU allocator for fast allocations
     per_cpu_allocator: PerCpuAllocator,
 }
 
 impl PhysicalFrameAllocator {
     /// Initialize the frame allocator with buddy system from bootloader memory regions
+    ///
+    /// The NUMA topology must be known by now; see `numa::init`.
     pub fn init(memory_regions: &[MemoryRegion]) -> Self {
-        let mut buddy_lists = [
-            core::array::from_fn(|_| CacheAligned::new(Vec::new())),
-            core::array::from_fn(|_| CacheAligned::new(Vec::new())),
-            core::array::from_fn(|_| CacheAligned::new(Vec::new())),
-        ];
-
-        let mut allocation_bitmap = [Vec::new(), Vec::new(), Vec::new()];
-        let mut zone_start = [PhysAddr::new(0); 3];
-        let mut zone_end = [PhysAddr::new(0); 3];
-        let mut total_frames = [0; 3];
-
-        // Initialize zone boundaries
-        zone_start[MemoryZone::Dma as usize] = PhysAddr::new(0);
-        zone_end[MemoryZone::Dma as usize] = PhysAddr::new(DMA_ZONE_END);
-        zone_start[MemoryZone::Normal as usize] = PhysAddr::new(DMA_ZONE_END);
-        zone_end[MemoryZone::Normal as usize] = PhysAddr::new(NORMAL_ZONE_END);
-        zone_start[MemoryZone::HighMem as usize] = PhysAddr::new(NORMAL_ZONE_END);
-        zone_end[MemoryZone::HighMem as usize] = PhysAddr::new(u64::MAX);
+        let nodes = numa::node_count();
+        let zones = nodes * ZONES_PER_NODE;
+        let mut buddy_lists: Vec<[CacheAligned<Vec<BuddyNode>>; NUM_ORDERS]> = (0..zones)
+            .map(|_| core::array::from_fn(|_| CacheAligned::new(Vec::new())))
+            .collect();
+
+        // Zone boundaries are those of the memory each zone actually has
+        let mut zone_start = vec![u64::MAX; zones];
+        let mut zone_end = vec![0u64; zones];
+        let mut total_frames = vec![0; zones];
+
+        // The early heap keeps serving allocations; its frames are never
+        // handed out
+        let (heap_start, heap_end) = crate::memory_basic::early_heap_phys_range()
+            .map_or((0, 0), |(start, end)| {
+                (align_down(start as usize, PAGE_SIZE) as u64, align_up(end as usize, PAGE_SIZE) as u64)
+            });
 
         // Process memory regions and build buddy lists
         for region in memory_regions.iter().filter(|r| r.region_type == bootloader::bootinfo::MemoryRegionType::Usable) {
-            let start = align_up(region.range.start_addr() as usize, PAGE_SIZE) as u64;
+            // The first MiB is left alone, as Linux does: firmware data lives
+            // there, and the AP trampoline at smp::TRAMPOLINE_PHYS must be
+            // free to copy in
+            let start = (align_up(region.range.start_addr() as usize, PAGE_SIZE) as u64).max(LOW_MEMORY_END);
             let end = align_down(region.range.end_addr() as usize, PAGE_SIZE) as u64;
 
             if start >= end {
                 continue;
             }
 
-            let mut current = start;
-            while current < end {
-                let zone = MemoryZone::from_address(PhysAddr::new(current));
-                let zone_idx = zone as usize;
-
-                // Find the largest possible buddy block at this address
-                let mut order = MAX_ORDER;
-                let mut block_size = PAGE_SIZE << order;
-
-                while order > 0 {
-                    if current % (block_size as u64) == 0 && current + block_size as u64 <= end {
-                        break;
+            // Blocks never cross a node boundary
+            let pieces = [(start, end.min(heap_start)), (start.max(heap_end), end)];
+            let spans = pieces
+                .into_iter()
+                .filter(|(start, end)| start < end)
+                .flat_map(|(start, end)| numa::node_spans(start, end));
+            for (span_start, span_end, node) in spans {
+                let mut current = span_start;
+                while current < span_end {
+                    let zone = MemoryZone::from_address(PhysAddr::new(current));
+                    let zone_idx = Self::zone_index(node, zone);
+
+                    // Find the largest possible buddy block at this address
+                    let mut order = MAX_ORDER;
+                    let mut block_size = PAGE_SIZE << order;
+
+                    while order > 0 {
+                        if current % (block_size as u64) == 0 && current + block_size as u64 <= span_end {
+                            break;
+                        }
+                        order -= 1;
+                        block_size >>= 1;
                     }
-                    order -= 1;
-                    block_size >>= 1;
-                }
 
-                // Add block to appropriate buddy list
-                buddy_lists[zone_idx][order].push(BuddyNode {
-                    address: PhysAddr::new(current),
-                    order,
-                });
+                    // Add block to appropriate buddy list
+                    buddy_lists[zone_idx][order].push(BuddyNode {
+                        address: PhysAddr::new(current),
+                        order,
+                    });
 
-                total_frames[zone_idx] += 1 << order;
-                current += block_size as u64;
+                    total_frames[zone_idx] += 1 << order;
+                    zone_start[zone_idx] = zone_start[zone_idx].min(current);
+                    zone_end[zone_idx] = zone_end[zone_idx].max(current + block_size as u64);
+                    current += block_size as u64;
+                }
             }
         }
 
-        // Initialize allocation bitmaps (one bit per page)
-        for zone_idx in 0..3 {
-            let bitmap_size = (total_frames[zone_idx] + 63) / 64; // Round up to u64 boundary
-            allocation_bitmap[zone_idx] = vec![0u64; bitmap_size];
+        // Initialize allocation bitmaps (one bit per page of the zone's span)
+        let mut allocation_bitmap = Vec::with_capacity(zones);
+        for zone_idx in 0..zones {
+            if zone_start[zone_idx] >= zone_end[zone_idx] {
+                zone_start[zone_idx] = 0;
+                zone_end[zone_idx] = 0;
+            }
+            let pages = ((zone_end[zone_idx] - zone_start[zone_idx]) / PAGE_SIZE as u64) as usize;
+            allocation_bitmap.push(vec![0u64; (pages + 63) / 64]); // Round up to u64 boundary
         }
 
         // Sort buddy lists by address for efficient allocation
-        for zone_idx in 0..3 {
+        for zone_idx in 0..zones {
             for order in 0..NUM_ORDERS {
                 buddy_lists[zone_idx][order].sort_unstable_by_key(|node| node.address.as_u64());
             }
@@ -400,23 +453,26 @@ impl PhysicalFrameAllocator {
         PhysicalFrameAllocator {
             buddy_lists,
             allocation_bitmap,
-            allocated_frames: [
-                CacheAligned::new(AtomicU64::new(0)),
-                CacheAligned::new(AtomicU64::new(0)),
-                CacheAligned::new(AtomicU64::new(0))
-            ],
+            allocated_frames: (0..zones).map(|_| CacheAligned::new(AtomicU64::new(0))).collect(),
             total_frames,
-            zone_start,
-            zone_end,
-            fragmentation_stats: [
-                CacheAligned::new(FragmentationStats::default()),
-                CacheAligned::new(FragmentationStats::default()),
-                CacheAligned::new(FragmentationStats::default())
-            ],
+            zone_start: zone_start.into_iter().map(PhysAddr::new).collect(),
+            zone_end: zone_end.into_iter().map(PhysAddr::new).collect(),
+            fragmentation_stats: (0..zones).map(|_| CacheAligned::new(FragmentationStats::default())).collect(),
+            nodes,
             per_cpu_allocator: PerCpuAllocator::new(),
         }
     }
 
+    /// Index of zone `zone` of node `node` in the per-zone vectors
+    fn zone_index(node: usize, zone: MemoryZone) -> usize {
+        node * ZONES_PER_NODE + zone as usize
+    }
+
+    /// Index of the zone holding `addr`, which is in zone `zone`
+    fn zone_index_of(&self, addr: PhysAddr, zone: MemoryZone) -> usize {
+        Self::zone_index(numa::node_of_addr(addr).min(self.nodes - 1), zone)
+    }
+
     /// Fast path allocation using per-CPU allocator
     pub fn allocate_frame_fast(&mut self, cpu_id: usize) -> Option<PhysFrame> {
         let (result, time_ns) = HighResTimer::time(|| {
@@ -445,13 +501,19 @@ impl PhysicalFrameAllocator {
         result
     }
 
-    /// Allocate frames using buddy allocator from a specific zone
+    /// Allocate frames using buddy allocator from a specific zone, on the
+    /// local NUMA node if it has them
     pub fn allocate_frames_in_zone(&mut self, zone: MemoryZone, order: usize) -> Option<PhysFrame> {
-        if order > MAX_ORDER {
+        numa::Placement::local().nodes().find_map(|node| self.allocate_frames_on(node, zone, order))
+    }
+
+    /// Allocate frames from zone `zone` of NUMA node `node` only
+    pub fn allocate_frames_on(&mut self, node: usize, zone: MemoryZone, order: usize) -> Option<PhysFrame> {
+        if order > MAX_ORDER || node >= self.nodes {
             return None;
         }
 
-        let zone_idx = zone as usize;
+        let zone_idx = Self::zone_index(node, zone);
 
         // Try to find a free block of the requested order
         if let Some(block) = self.find_free_block(zone_idx, order) {
@@ -469,6 +531,14 @@ impl PhysicalFrameAllocator {
         self.allocate_frames_in_zone(zone, 0)
     }
 
+    /// Allocate frames from the nodes `placement` allows, in its order,
+    /// taking any zone
+    pub fn allocate_frames_placed(&mut self, placement: numa::Placement, order: usize) -> Option<PhysFrame> {
+        placement.nodes().find_map(|node| {
+            ZONE_FALLBACK.iter().find_map(|&zone| self.allocate_frames_on(node, zone, order))
+        })
+    }
+
     /// Find and split a free block of the requested order
     fn find_free_block(&mut self, zone_idx: usize, order: usize) -> Option<BuddyNode> {
         // First try to find exact order
@@ -547,8 +617,14 @@ impl PhysicalFrameAllocator {
 
     /// Deallocate frames using buddy allocator (with coalescing)
     pub fn deallocate_frames(&mut self, frame: PhysFrame, zone: MemoryZone, order: usize) {
-        let zone_idx = zone as usize;
         let addr = frame.start_address();
+        let zone_idx = self.zone_index_of(addr, zone);
+
+        // Anonymous pages are charged to a memory cgroup one at a time; the
+        // charge is queued and returned outside this lock
+        if order == 0 {
+            cgroup::uncharge_page(addr.as_u64());
+        }
 
         self.mark_free(zone_idx, addr, order);
         self.allocated_frames[zone_idx].fetch_sub(1 << order, Ordering::Relaxed);
@@ -653,30 +729,49 @@ impl PhysicalFrameAllocator {
         }
     }
 
-    /// Get comprehensive memory statistics for all zones
+    /// Get comprehensive memory statistics for all zones, summed over the
+    /// NUMA nodes
     pub fn get_zone_stats(&self) -> [ZoneStats; 3] {
-        [
-            ZoneStats {
-                zone: MemoryZone::Dma,
-                total_frames: self.total_frames[0],
-                allocated_frames: self.allocated_frames[0].load(Ordering::Relaxed) as usize,
-                fragmentation_stats: self.fragmentation_stats[0].clone(),
-            },
-            ZoneStats {
-                zone: MemoryZone::Normal,
-                total_frames: self.total_frames[1],
-                allocated_frames: self.allocated_frames[1].load(Ordering::Relaxed) as usize,
-                fragmentation_stats: self.fragmentation_stats[1].clone(),
-            },
-            ZoneStats {
-                zone: MemoryZone::HighMem,
-                total_frames: self.total_frames[2],
-                allocated_frames: self.allocated_frames[2].load(Ordering::Relaxed) as usize,
-                fragmentation_stats: self.fragmentation_stats[2].clone(),
-            },
-        ]
+        [MemoryZone::Dma, MemoryZone::Normal, MemoryZone::HighMem].map(|zone| {
+            let mut stats = ZoneStats { zone, total_frames: 0, allocated_frames: 0, fragmentation_stats: FragmentationStats::default() };
+            for node in 0..self.nodes {
+                let zone_stats = self.zone_stats(Self::zone_index(node, zone));
+                stats.total_frames += zone_stats.total_frames;
+                stats.allocated_frames += zone_stats.allocated_frames;
+                let merged = &mut stats.fragmentation_stats;
+                for order in 0..NUM_ORDERS {
+                    merged.free_blocks_by_order[order] += zone_stats.fragmentation_stats.free_blocks_by_order[order];
+                }
+                merged.largest_free_order = merged.largest_free_order.max(zone_stats.fragmentation_stats.largest_free_order);
+                merged.total_free_bytes += zone_stats.fragmentation_stats.total_free_bytes;
+            }
+            let merged = &mut stats.fragmentation_stats;
+            if merged.total_free_bytes > 0 {
+                let largest_possible_block = PAGE_SIZE << merged.largest_free_order;
+                merged.fragmentation_ratio = 1.0 - (largest_possible_block as f32 / merged.total_free_bytes as f32);
+            }
+            stats
+        })
     }
-    
+
+    /// Statistics of the zones of NUMA node `node`
+    pub fn node_zone_stats(&self, node: usize) -> Option<[ZoneStats; 3]> {
+        (node < self.nodes).then(|| {
+            [MemoryZone::Dma, MemoryZone::Normal, MemoryZone::HighMem]
+                .map(|zone| self.zone_stats(Self::zone_index(node, zone)))
+        })
+    }
+
+    fn zone_stats(&self, zone_idx: usize) -> ZoneStats {
+        let zone = [MemoryZone::Dma, MemoryZone::Normal, MemoryZone::HighMem][zone_idx % ZONES_PER_NODE];
+        ZoneStats {
+            zone,
+            total_frames: self.total_frames[zone_idx],
+            allocated_frames: self.allocated_frames[zone_idx].load(Ordering::Relaxed) as usize,
+            fragmentation_stats: *self.fragmentation_stats[zone_idx],
+        }
+    }
+
     /// Get detailed memory usage report
     pub fn get_memory_report(&self) -> MemoryReport {
         let zone_stats = self.get_zone_stats();
@@ -722,7 +817,7 @@ impl PhysicalFrameAllocator {
         let mut coalesced_blocks = 0;
         let mut freed_bytes = 0;
         
-        for zone_idx in 0..3 {
+        for zone_idx in 0..self.buddy_lists.len() {
             for order in 0..MAX_ORDER {
                 let mut i = 0;
                 while i < self.buddy_lists[zone_idx][order].len() {
@@ -761,7 +856,7 @@ impl PhysicalFrameAllocator {
         let mut total_free_blocks = 0;
         let mut free_blocks_by_order = [0; NUM_ORDERS];
 
-        for zone_idx in 0..3 {
+        for zone_idx in 0..self.buddy_lists.len() {
             for order in 0..NUM_ORDERS {
                 let count = self.buddy_lists[zone_idx][order].len();
                 free_blocks_by_order[order] += count;
@@ -795,15 +890,35 @@ impl PhysicalFrameAllocator {
 
         self.allocate_frames_in_zone(zone, order)
     }
+
+    /// Allocate a 2 MiB block for a huge page; buddy blocks are aligned to
+    /// their size, so the block can be mapped by a single level 2 entry
+    pub fn allocate_huge_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        self.allocate_huge_frame_placed(numa::Placement::local())
+    }
+
+    /// Allocate a 2 MiB block from the nodes `placement` allows
+    pub fn allocate_huge_frame_placed(&mut self, placement: numa::Placement) -> Option<PhysFrame<Size2MiB>> {
+        let frame = placement.nodes().find_map(|node| {
+            self.allocate_frames_on(node, MemoryZone::Normal, HUGE_PAGE_ORDER)
+                .or_else(|| self.allocate_frames_on(node, MemoryZone::HighMem, HUGE_PAGE_ORDER))
+        })?;
+        PhysFrame::from_start_address(frame.start_address()).ok()
+    }
+
+    /// Free a huge page allocated with `allocate_huge_frame`
+    pub fn deallocate_huge_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+        let addr = frame.start_address();
+        self.deallocate_frames(PhysFrame::containing_address(addr), MemoryZone::from_address(addr), HUGE_PAGE_ORDER);
+    }
 }
 
 // Implement the standard FrameAllocator trait (allocates from Normal zone by default)
 unsafe impl FrameAllocator<Size4KiB> for PhysicalFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        // Try Normal zone first, then HighMem, then DMA as last resort
-        self.allocate_frame_in_zone(MemoryZone::Normal)
-            .or_else(|| self.allocate_frame_in_zone(MemoryZone::HighMem))
-            .or_else(|| self.allocate_frame_in_zone(MemoryZone::Dma))
+        // Local node first; on each node Normal zone first, then HighMem,
+        // then DMA as last resort
+        self.allocate_frames_placed(numa::Placement::local(), 0)
     }
 }
 
@@ -869,13 +984,10 @@ pub struct SwapEntry {
 }
 
 /// Swap manager for handling page-to-storage operations
+///
+/// Tracks which pages are swapped out and picks eviction victims; the slots
+/// themselves live in the active swap areas (see `swap`).
 pub struct SwapManager {
-    /// Available swap slots (bit vector)
-    free_slots: Vec<u64>,
-    /// Total number of swap slots
-    total_slots: u32,
-    /// Currently used swap slots
-    used_slots: u32,
     /// Swap entries indexed by slot
     swap_entries: BTreeMap<SwapSlot, SwapEntry>,
     /// Page replacement algorithm
@@ -888,154 +1000,70 @@ pub struct SwapManager {
     access_times: BTreeMap<VirtAddr, u64>,
     /// Global access counter
     access_counter: AtomicU64,
-    /// Storage device ID for swap partition (None means no swap device configured)
-    swap_device_id: Option<u32>,
 }
 
 impl SwapManager {
-    /// Create new swap manager with specified number of slots
-    pub fn new(total_slots: u32, algorithm: PageReplacementAlgorithm) -> Self {
-        let bitmap_size = ((total_slots + 63) / 64) as usize;
-
+    /// Create new swap manager
+    pub fn new(algorithm: PageReplacementAlgorithm) -> Self {
         Self {
-            free_slots: vec![u64::MAX; bitmap_size], // All slots initially free
-            total_slots,
-            used_slots: 0,
             swap_entries: BTreeMap::new(),
             replacement_algorithm: algorithm,
             lru_list: Vec::new(),
             clock_hand: 0,
             access_times: BTreeMap::new(),
             access_counter: AtomicU64::new(0),
-            swap_device_id: None, // No swap device configured by default
         }
     }
 
-    /// Configure swap device for storage operations
-    pub fn set_swap_device(&mut self, device_id: u32) {
-        self.swap_device_id = Some(device_id);
-    }
-
-    /// Get configured swap device ID
-    pub fn get_swap_device(&self) -> Option<u32> {
-        self.swap_device_id
-    }
-    
-    /// Allocate a swap slot
+    /// Allocate a swap slot from the active swap areas
     pub fn allocate_slot(&mut self) -> Option<SwapSlot> {
-        if self.used_slots >= self.total_slots {
-            return None;
-        }
-        
-        // Find first free slot
-        for (word_idx, &word) in self.free_slots.iter().enumerate() {
-            if word != 0 {
-                let bit_idx = word.trailing_zeros() as usize;
-                let slot_idx = word_idx * 64 + bit_idx;
-                
-                if slot_idx < self.total_slots as usize {
-                    // Mark slot as used
-                    self.free_slots[word_idx] &= !(1u64 << bit_idx);
-                    self.used_slots += 1;
-                    return Some(SwapSlot(slot_idx as u32));
-                }
-            }
-        }
-        
-        None
+        swap::allocate_slot()
     }
-    
+
     /// Deallocate a swap slot
     pub fn deallocate_slot(&mut self, slot: SwapSlot) {
-        let slot_idx = slot.0 as usize;
-        let word_idx = slot_idx / 64;
-        let bit_idx = slot_idx % 64;
-        
-        if word_idx < self.free_slots.len() {
-            self.free_slots[word_idx] |= 1u64 << bit_idx;
-            self.used_slots = self.used_slots.saturating_sub(1);
-            self.swap_entries.remove(&slot);
-        }
+        swap::free_slot(slot);
+        self.swap_entries.remove(&slot);
     }
-    
+
     /// Swap out a page to storage
     pub fn swap_out(&mut self, page_addr: VirtAddr, page_data: &[u8; PAGE_SIZE]) -> Result<SwapSlot, &'static str> {
         let slot = self.allocate_slot().ok_or("No swap slots available")?;
 
-        // Create swap entry metadata
-        let entry = SwapEntry {
+        if swap::write_page(slot, page_data).is_err() {
+            self.deallocate_slot(slot);
+            return Err("Storage write failed during swap out");
+        }
+
+        self.swap_entries.insert(slot, SwapEntry {
             slot,
             page_addr,
             access_time: self.access_counter.load(Ordering::Relaxed),
             dirty: true,
-        };
-
-        // Write page data to actual swap storage if device is configured
-        if let Some(device_id) = self.swap_device_id {
-            // Calculate storage offset: slot * PAGE_SIZE
-            // PAGE_SIZE = 4096 bytes = 8 sectors (assuming 512-byte sectors)
-            const SECTOR_SIZE: usize = 512;
-            const SECTORS_PER_PAGE: u64 = (PAGE_SIZE / SECTOR_SIZE) as u64;
-
-            let start_sector = slot.0 as u64 * SECTORS_PER_PAGE;
-
-            // Write page to storage device
-            use crate::drivers::storage;
-            match storage::write_storage_sectors(device_id, start_sector, page_data) {
-                Ok(bytes_written) => {
-                    if bytes_written != PAGE_SIZE {
-                        self.deallocate_slot(slot);
-                        return Err("Incomplete swap write operation");
-                    }
-                }
-                Err(e) => {
-                    self.deallocate_slot(slot);
-                    return Err("Storage write failed during swap out");
-                }
-            }
-        }
-        // If no swap device configured, data is lost (memory-only swap simulation)
-
-        self.swap_entries.insert(slot, entry);
+        });
+        self.access_times.remove(&page_addr);
         Ok(slot)
     }
-    
+
     /// Swap in a page from storage
     pub fn swap_in(&mut self, slot: SwapSlot, page_data: &mut [u8; PAGE_SIZE]) -> Result<VirtAddr, &'static str> {
         let entry = self.swap_entries.get(&slot).ok_or("Invalid swap slot")?;
         let page_addr = entry.page_addr;
 
-        // Read page data from actual swap storage if device is configured
-        if let Some(device_id) = self.swap_device_id {
-            // Calculate storage offset: slot * PAGE_SIZE
-            // PAGE_SIZE = 4096 bytes = 8 sectors (assuming 512-byte sectors)
-            const SECTOR_SIZE: usize = 512;
-            const SECTORS_PER_PAGE: u64 = (PAGE_SIZE / SECTOR_SIZE) as u64;
-
-            let start_sector = slot.0 as u64 * SECTORS_PER_PAGE;
-
-            // Read page from storage device
-            use crate::drivers::storage;
-            match storage::read_storage_sectors(device_id, start_sector, page_data) {
-                Ok(bytes_read) => {
-                    if bytes_read != PAGE_SIZE {
-                        return Err("Incomplete swap read operation");
-                    }
-                }
-                Err(e) => {
-                    return Err("Storage read failed during swap in");
-                }
-            }
-        } else {
-            // No swap device configured - zero the page as fallback
-            // This handles the case where swap manager is used without actual storage
-            page_data.fill(0);
-        }
+        swap::read_page(slot, page_data).map_err(|_| "Storage read failed during swap in")?;
 
         self.deallocate_slot(slot);
         Ok(page_addr)
     }
-    
+
+    /// Slot holding the swapped-out page at `page_addr`, if any
+    pub fn slot_for(&self, page_addr: VirtAddr) -> Option<SwapSlot> {
+        self.swap_entries
+            .iter()
+            .find(|(_, entry)| entry.page_addr == page_addr)
+            .map(|(slot, _)| *slot)
+    }
+
     /// Select a page for replacement using the configured algorithm
     pub fn select_victim_page(&mut self, candidate_pages: &[VirtAddr]) -> Option<VirtAddr> {
         if candidate_pages.is_empty() {
@@ -1093,12 +1121,14 @@ impl SwapManager {
     
     /// Get swap statistics
     pub fn get_stats(&self) -> SwapStats {
+        let (total_slots, used_slots) = swap::totals();
         SwapStats {
-            total_slots: self.total_slots,
-            used_slots: self.used_slots,
-            free_slots: self.total_slots - self.used_slots,
+            total_slots,
+            used_slots,
+            free_slots: total_slots - used_slots,
             algorithm: self.replacement_algorithm,
             total_swapped_pages: self.swap_entries.len() as u32,
+            zram: zram::total_stats(),
         }
     }
 }
@@ -1111,6 +1141,8 @@ pub struct SwapStats {
     pub free_slots: u32,
     pub algorithm: PageReplacementAlgorithm,
     pub total_swapped_pages: u32,
+    /// Combined usage of all zram devices
+    pub zram: zram::ZramStats,
 }
 
 impl ZoneStats {
@@ -1158,6 +1190,14 @@ pub struct VirtualMemoryRegion {
     pub physical_start: Option<PhysAddr>,
     pub reference_count: usize,
     pub aslr_offset: u64,
+    /// Pinned by mlock; never swapped out
+    pub locked: bool,
+    /// MADV_HUGEPAGE/MADV_NOHUGEPAGE advice for transparent huge pages
+    pub thp: huge_page::ThpAdvice,
+    /// mbind policy; Default uses the process's
+    pub mempolicy: numa::MemPolicy,
+    /// Process the region is charged to; 0 for the kernel
+    pub owner: crate::process::Pid,
 }
 
 impl VirtualMemoryRegion {
@@ -1176,6 +1216,10 @@ impl VirtualMemoryRegion {
             physical_start: None,
             reference_count: 1,
             aslr_offset: 0,
+            locked: false,
+            thp: huge_page::ThpAdvice::Default,
+            mempolicy: numa::MemPolicy::Default,
+            owner: if protection.user_accessible { crate::process::current_pid() } else { 0 },
         }
     }
 
@@ -1201,6 +1245,10 @@ impl VirtualMemoryRegion {
             physical_start: None,
             reference_count: 1,
             aslr_offset,
+            locked: false,
+            thp: huge_page::ThpAdvice::Default,
+            mempolicy: numa::MemPolicy::Default,
+            owner: if protection.user_accessible { crate::process::current_pid() } else { 0 },
         }
     }
 
@@ -1272,6 +1320,109 @@ impl PageTableManager {
         Some(frame)
     }
 
+    /// Map a 2 MiB page
+    pub fn map_huge_page(
+        &mut self,
+        page: Page<Size2MiB>,
+        frame: PhysFrame<Size2MiB>,
+        flags: PageTableFlags,
+        frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    ) -> Result<(), MapToError<Size2MiB>> {
+        unsafe {
+            self.mapper.map_to(page, frame, flags, frame_allocator)
+                .map(|flush| flush.flush())
+        }
+    }
+
+    /// Unmap a 2 MiB page
+    pub fn unmap_huge_page(&mut self, page: Page<Size2MiB>) -> Option<PhysFrame<Size2MiB>> {
+        let (frame, flush) = self.mapper.unmap(page).ok()?;
+        flush.flush();
+        Some(frame)
+    }
+
+    /// Update the flags of a 2 MiB page
+    pub fn update_huge_flags(&mut self, page: Page<Size2MiB>, flags: PageTableFlags) -> Result<(), &'static str> {
+        unsafe {
+            let _ = self.mapper.update_flags(page, flags)
+                .map_err(|_| "Failed to update page flags")?;
+        }
+        Ok(())
+    }
+
+    /// The 2 MiB frame behind `addr`, if a huge page maps it
+    pub fn huge_frame(&self, addr: VirtAddr) -> Option<PhysFrame<Size2MiB>> {
+        match self.mapper.translate(addr) {
+            TranslateResult::Mapped { frame: MappedFrame::Size2MiB(frame), .. } => Some(frame),
+            _ => None,
+        }
+    }
+
+    /// The level 2 entry covering `addr`, if the tables above it exist
+    fn level_2_entry(&mut self, addr: VirtAddr) -> Option<&mut PageTableEntry> {
+        let offset = self.physical_memory_offset;
+        let level_4_table = self.mapper.level_4_table();
+        let level_3_table = unsafe { next_table(offset, &level_4_table[addr.p4_index()])? };
+        let level_2_table = unsafe { next_table(offset, &level_3_table[addr.p3_index()])? };
+        Some(&mut level_2_table[addr.p2_index()])
+    }
+
+    /// Replace the huge page mapping `page` with a page table mapping the
+    /// same memory as 512 small pages with the same flags
+    pub fn split_huge_page(
+        &mut self,
+        page: Page<Size2MiB>,
+        frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    ) -> Result<(), &'static str> {
+        let offset = self.physical_memory_offset;
+        let table_frame = frame_allocator.allocate_frame().ok_or("Out of memory")?;
+        let entry = self.level_2_entry(page.start_address()).ok_or("Page not mapped")?;
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE) {
+            return Err("Not a huge page");
+        }
+
+        let table = unsafe { &mut *(offset + table_frame.start_address().as_u64()).as_mut_ptr::<PageTable>() };
+        table.zero();
+        // Bit 7 is PAT in a level 1 entry, not the huge page bit
+        let page_flags = flags & !PageTableFlags::HUGE_PAGE;
+        for (i, small) in table.iter_mut().enumerate() {
+            small.set_addr(entry.addr() + (i * PAGE_SIZE) as u64, page_flags);
+        }
+        let table_flags = flags & (PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE);
+        entry.set_addr(table_frame.start_address(), table_flags);
+        x86_64::instructions::tlb::flush(page.start_address());
+        Ok(())
+    }
+
+    /// Map `page` as one huge page in place of its page table, which must
+    /// have no pages left mapped; returns the page table's frame for the
+    /// caller to free
+    pub fn collapse_huge_page(
+        &mut self,
+        page: Page<Size2MiB>,
+        frame: PhysFrame<Size2MiB>,
+        flags: PageTableFlags,
+    ) -> Result<Option<PhysFrame>, &'static str> {
+        let offset = self.physical_memory_offset;
+        let entry = self.level_2_entry(page.start_address()).ok_or("Page table missing")?;
+        let old_table = if entry.is_unused() {
+            None
+        } else if entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err("Already a huge page");
+        } else {
+            let table = unsafe { next_table(offset, entry).ok_or("Page table missing")? };
+            if table.iter().any(|small| !small.is_unused()) {
+                return Err("Page table still in use");
+            }
+            Some(PhysFrame::containing_address(entry.addr()))
+        };
+
+        entry.set_addr(frame.start_address(), flags | PageTableFlags::HUGE_PAGE);
+        x86_64::instructions::tlb::flush(page.start_address());
+        Ok(old_table)
+    }
+
     /// Update page flags
     pub fn update_flags(
         &mut self,
@@ -1318,6 +1469,11 @@ impl PageTableManager {
             if !level_2_entry.flags().contains(PageTableFlags::PRESENT) {
                 return None;
             }
+
+            // A huge page ends the walk here
+            if level_2_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+                return Some(level_2_entry.flags());
+            }
             
             let level_1_table_ptr = (self.physical_memory_offset + level_2_entry.addr().as_u64()).as_ptr();
             let level_1_table = &*(level_1_table_ptr as *const PageTable);
@@ -1482,6 +1638,16 @@ impl PageTableManager {
     }
 }
 
+/// The table an entry points to, through the physical memory mapping at
+/// `offset`; None if the entry is empty or maps a huge page
+unsafe fn next_table<'a>(offset: VirtAddr, entry: &PageTableEntry) -> Option<&'a mut PageTable> {
+    let flags = entry.flags();
+    if !flags.contains(PageTableFlags::PRESENT) || flags.contains(PageTableFlags::HUGE_PAGE) {
+        return None;
+    }
+    Some(&mut *(offset + entry.addr().as_u64()).as_mut_ptr::<PageTable>())
+}
+
 /// Main memory management system
 pub struct MemoryManager {
     frame_allocator: Mutex<PhysicalFrameAllocator>,
@@ -1493,6 +1659,8 @@ pub struct MemoryManager {
     swap_manager: Mutex<SwapManager>,
     /// Reference counting for physical frames (for COW support)
     frame_refcounts: RwLock<BTreeMap<PhysAddr, AtomicUsize>>,
+    /// mlockall(MCL_FUTURE): lock user regions as they are added
+    lock_future: AtomicBool,
 }
 
 /// Security features configuration
@@ -1528,9 +1696,8 @@ impl MemoryManager {
             .map(|stats| stats.total_bytes())
             .sum();
 
-        // Initialize swap manager with 10% of total memory as swap space
-        let swap_slots = (total_memory / PAGE_SIZE) / 10;
-        let swap_manager = SwapManager::new(swap_slots as u32, PageReplacementAlgorithm::LRU);
+        // Swap space is added later with swapon
+        let swap_manager = SwapManager::new(PageReplacementAlgorithm::LRU);
 
         Self {
             frame_allocator: Mutex::new(frame_allocator),
@@ -1541,18 +1708,47 @@ impl MemoryManager {
             security_features: SecurityFeatures::default(),
             swap_manager: Mutex::new(swap_manager),
             frame_refcounts: RwLock::new(BTreeMap::new()),
+            lock_future: AtomicBool::new(false),
         }
     }
 
     /// Map a virtual memory region to physical frames
     pub fn map_region(&self, region: &mut VirtualMemoryRegion) -> Result<(), MemoryError> {
+        let thp = huge_page::thp_allowed(region);
+        if thp {
+            huge_page::start_khugepaged();
+        }
         let mut page_table_manager = self.page_table_manager.lock();
         let mut frame_allocator = self.frame_allocator.lock();
 
         let flags = region.protection.to_page_table_flags();
         let mut first_frame = None;
+        let mut pages = region.pages();
+
+        while let Some(page) = pages.next() {
+            // Whole 2 MiB blocks of anonymous memory get a huge page if one
+            // is free
+            if thp
+                && page.start_address().is_aligned(HUGE_PAGE_SIZE as u64)
+                && huge_page::block_in_region(region, page.start_address())
+            {
+                if let Some(frame) = frame_allocator.allocate_huge_frame() {
+                    unsafe {
+                        core::ptr::write_bytes(phys_to_virt(frame.start_address()).as_mut_ptr::<u8>(), 0, HUGE_PAGE_SIZE);
+                    }
+                    let huge = Page::containing_address(page.start_address());
+                    match page_table_manager.map_huge_page(huge, frame, flags, &mut *frame_allocator) {
+                        Ok(()) => {
+                            first_frame.get_or_insert(frame.start_address());
+                            huge_page::count_fault_alloc();
+                            pages.nth(HUGE_PAGE_SIZE / PAGE_SIZE - 2);
+                            continue;
+                        }
+                        Err(_) => frame_allocator.deallocate_huge_frame(frame),
+                    }
+                }
+            }
 
-        for page in region.pages() {
             let frame = frame_allocator
                 .allocate_frame()
                 .ok_or(MemoryError::OutOfMemory)?;
@@ -1564,7 +1760,7 @@ impl MemoryManager {
             // Initialize page content if needed
             if matches!(region.region_type, MemoryRegionType::UserStack | MemoryRegionType::UserHeap) {
                 unsafe {
-                    let page_ptr = frame.start_address().as_u64() as *mut u8;
+                    let page_ptr = phys_to_virt(frame.start_address()).as_mut_ptr::<u8>();
                     core::ptr::write_bytes(page_ptr, 0, PAGE_SIZE);
                 }
             }
@@ -1584,6 +1780,13 @@ impl MemoryManager {
         let mut frame_allocator = self.frame_allocator.lock();
 
         for page in region.pages() {
+            // Huge pages never straddle a region boundary
+            if page_table_manager.huge_frame(page.start_address()).is_some() {
+                if let Some(frame) = page_table_manager.unmap_huge_page(Page::containing_address(page.start_address())) {
+                    frame_allocator.deallocate_huge_frame(frame);
+                }
+                continue;
+            }
             if let Some(frame) = page_table_manager.unmap_page(page) {
                 let zone = MemoryZone::from_address(frame.start_address());
                 frame_allocator.deallocate_frame(frame, zone);
@@ -1596,9 +1799,13 @@ impl MemoryManager {
     }
 
     /// Add a virtual memory region to management
-    pub fn add_region(&self, region: VirtualMemoryRegion) -> Result<(), MemoryError> {
+    pub fn add_region(&self, mut region: VirtualMemoryRegion) -> Result<(), MemoryError> {
         let mut regions = self.regions.write();
 
+        if self.lock_future.load(Ordering::Relaxed) && region.protection.user_accessible {
+            region.locked = true;
+        }
+
         // Check for overlaps
         for existing_region in regions.values() {
             if self.regions_overlap(&region, existing_region) {
@@ -1640,14 +1847,13 @@ impl MemoryManager {
     ) -> Result<VirtualMemoryRegion, MemoryError> {
         let aligned_size = align_up(size, PAGE_SIZE);
 
-        // Find free virtual address space
-        let start_addr = self.find_free_virtual_space(aligned_size)
+        // Find free virtual address space where the process's layout puts
+        // this kind of region
+        let (hint, downward) = self.placement_hint(region_type);
+        let start_addr = self.find_free_virtual_space_near(aligned_size, hint, downward)
             .ok_or(MemoryError::NoVirtualSpace)?;
 
-        let enable_aslr = self.security_features.aslr_enabled &&
-                         matches!(region_type, MemoryRegionType::UserCode | MemoryRegionType::UserData | MemoryRegionType::UserStack);
-
-        let mut region = VirtualMemoryRegion::new_with_aslr(start_addr, aligned_size, region_type, protection, enable_aslr);
+        let mut region = VirtualMemoryRegion::new(start_addr, aligned_size, region_type, protection);
 
         // Map the region
         self.map_region(&mut region)?;
@@ -1668,7 +1874,8 @@ impl MemoryManager {
         let aligned_size = align_up(size, PAGE_SIZE);
         let total_size = aligned_size + 2 * PAGE_SIZE; // Add guard pages
 
-        let start_addr = self.find_free_virtual_space(total_size)
+        let (hint, downward) = self.placement_hint(region_type);
+        let start_addr = self.find_free_virtual_space_near(total_size, hint, downward)
             .ok_or(MemoryError::NoVirtualSpace)?;
 
         // Create guard page at start
@@ -1701,28 +1908,80 @@ impl MemoryManager {
         self.add_region(main_region.clone())?;
         self.add_region(guard_end)?;
 
+        if !protection.user_accessible {
+            stack::register_kernel_guard(start_addr);
+            stack::register_kernel_guard(main_region.end());
+        }
+
         Ok(main_region)
     }
 
-    /// Find free virtual address space
+    /// Where to start looking for space for a region of `region_type`, and
+    /// whether to look downwards from there
+    ///
+    /// Stacks go below the room the current process's main stack may grow
+    /// into, its heap at the heap start and everything else from the mmap
+    /// base (see `aslr` and `stack`).
+    fn placement_hint(&self, region_type: MemoryRegionType) -> (VirtAddr, bool) {
+        let pid = crate::process::current_pid();
+        let layout = if self.security_features.aslr_enabled { aslr::layout(pid) } else { aslr::Layout::FIXED };
+        match region_type {
+            MemoryRegionType::UserStack => (VirtAddr::new(layout.stack_top - stack::reserve(pid)), true),
+            MemoryRegionType::UserHeap if layout.brk_base != 0 => (VirtAddr::new(layout.brk_base), false),
+            _ => (VirtAddr::new(layout.mmap_base), false),
+        }
+    }
+
+    /// Find free virtual address space, from the current process's mmap base
     fn find_free_virtual_space(&self, size: usize) -> Option<VirtAddr> {
-        let regions = self.regions.read();
-        let mut current_addr = VirtAddr::new(USER_SPACE_START as u64);
+        let (hint, downward) = self.placement_hint(MemoryRegionType::UserData);
+        self.find_free_virtual_space_near(size, hint, downward)
+    }
 
-        while current_addr.as_u64() + size as u64 <= USER_SPACE_END as u64 {
-            let end_addr = current_addr + size;
+    /// Find `size` bytes of free user address space, first fit going up (or
+    /// down) from `hint`, wrapping around once
+    fn find_free_virtual_space_near(&self, size: usize, hint: VirtAddr, downward: bool) -> Option<VirtAddr> {
+        let regions = self.regions.read();
+        let (low, high) = (USER_SPACE_START as u64, USER_SPACE_END as u64);
+        let size = size as u64;
+        let hint = align_down(hint.as_u64().clamp(low, high) as usize, PAGE_SIZE) as u64;
+        if size > high - low {
+            return None;
+        }
 
-            let overlaps = regions.values().any(|region| {
-                let region_end = region.end();
-                !(end_addr <= region.start || current_addr >= region_end)
-            });
+        // The region in the way of a placement at `start`, if any
+        let blocker = |start: u64| {
+            regions.values()
+                .find(|region| start < region.end().as_u64() && region.start.as_u64() < start + size)
+                .map(|region| (region.start.as_u64(), region.end().as_u64()))
+        };
 
-            if !overlaps {
-                return Some(current_addr);
+        if downward {
+            for top in [hint, high] {
+                let mut start = match top.checked_sub(size) {
+                    Some(start) if start >= low => start,
+                    _ => continue,
+                };
+                loop {
+                    match blocker(start) {
+                        None => return Some(VirtAddr::new(start)),
+                        Some((region_start, _)) if region_start >= low + size => {
+                            start = align_down((region_start - size) as usize, PAGE_SIZE) as u64;
+                        }
+                        Some(_) => break,
+                    }
+                }
+            }
+        } else {
+            for bottom in [hint, low] {
+                let mut start = bottom;
+                while start + size <= high {
+                    match blocker(start) {
+                        None => return Some(VirtAddr::new(start)),
+                        Some((_, region_end)) => start = align_up(region_end as usize, PAGE_SIZE) as u64,
+                    }
+                }
             }
-
-            // Move to next page-aligned address
-            current_addr = VirtAddr::new(align_up(current_addr.as_u64() as usize + PAGE_SIZE, PAGE_SIZE) as u64);
         }
 
         None
@@ -1768,6 +2027,8 @@ impl MemoryManager {
         self.add_region(guard_start_region)?;
         self.add_region(heap_region)?;
         self.add_region(guard_end_region)?;
+        stack::register_kernel_guard(VirtAddr::new(KERNEL_HEAP_START as u64));
+        stack::register_kernel_guard(VirtAddr::new((actual_heap_start + actual_heap_size) as u64));
 
         // Initialize the heap allocator with actual heap area
         // This uses the linked_list_allocator crate which must be initialized separately
@@ -1776,6 +2037,25 @@ impl MemoryManager {
         Ok(())
     }
 
+    /// Whether the locks the fault path takes are free, or freed by another
+    /// CPU within `FAULT_LOCK_SPINS` tries
+    ///
+    /// One the faulting code holds itself never frees, and waiting on it
+    /// would hang this CPU.
+    fn fault_locks_free(&self) -> bool {
+        (0..FAULT_LOCK_SPINS).any(|_| {
+            let free = self.regions.try_write().is_some()
+                && self.page_table_manager.try_lock().is_some()
+                && self.frame_allocator.try_lock().is_some()
+                && self.swap_manager.try_lock().is_some()
+                && self.frame_refcounts.try_write().is_some();
+            if !free {
+                core::hint::spin_loop();
+            }
+            free
+        })
+    }
+
     /// Enhanced page fault handler with copy-on-write and demand paging
     pub fn handle_page_fault(&self, addr: VirtAddr, error_code: u64) -> Result<(), MemoryError> {
         // Parse error code
@@ -1784,8 +2064,33 @@ impl MemoryManager {
         let is_user = error_code & 0x4 != 0;
         let is_instruction_fetch = error_code & 0x10 != 0;
 
+        // Just below a user stack: grow it
+        if self.find_region(addr).is_none() {
+            stack::grow(self, addr)?;
+        }
+
         // Check if address is in a valid region
         if let Some(region) = self.find_region(addr) {
+            // Guard pages are never mapped, so catch them before demand paging
+            if region.protection.guard_page {
+                return Err(MemoryError::GuardPageViolation);
+            }
+
+            // PROT_NONE: mapped, but user mode may not touch it
+            if is_user && region.protection.user_accessible && !region.protection.readable {
+                return Err(MemoryError::PermissionDenied);
+            }
+
+            // File pages come from the page cache, not swap or zero-fill
+            if region.region_type == MemoryRegionType::FileMapping && (!is_present || is_write) {
+                return file_mapping::handle_fault(self, addr, &region, is_write, is_present);
+            }
+
+            // hugetlb pages come from the huge page pool
+            if region.region_type == MemoryRegionType::HugeTlb && !is_present {
+                return huge_page::handle_fault(self, addr, &region);
+            }
+
             // Handle different types of page faults
             if !is_present {
                 // Page not present - check if it's swapped out or needs demand paging
@@ -1812,11 +2117,6 @@ impl MemoryManager {
             if is_user && !region.protection.user_accessible {
                 return Err(MemoryError::PrivilegeViolation);
             }
-
-            // Check for guard page access
-            if region.protection.guard_page {
-                return Err(MemoryError::GuardPageViolation);
-            }
         }
 
         Err(MemoryError::InvalidAddress)
@@ -1825,172 +2125,268 @@ impl MemoryManager {
     /// Handle swap-in operation for a page fault on swapped page
     pub fn handle_swap_in(&self, addr: VirtAddr, region: &VirtualMemoryRegion) -> Result<(), MemoryError> {
         let page = Page::containing_address(addr);
-        let mut page_table_manager = self.page_table_manager.lock();
-        let mut frame_allocator = self.frame_allocator.lock();
-        let mut swap_manager = self.swap_manager.lock();
-
-        // Try to allocate a new frame
-        let frame = if let Some(frame) = frame_allocator.allocate_frame() {
-            frame
-        } else {
-            // Out of physical memory - need to swap out another page
-            drop(frame_allocator);
-            drop(page_table_manager);
-            drop(swap_manager);
-            
-            self.swap_out_victim_page()?;
-            
-            // Re-acquire locks and try again
-            page_table_manager = self.page_table_manager.lock();
-            frame_allocator = self.frame_allocator.lock();
-            swap_manager = self.swap_manager.lock();
-            
-            frame_allocator.allocate_frame()
-                .ok_or(MemoryError::OutOfMemory)?
-        };
+        let frame = self.allocate_frame_placed(numa::placement_for(region, addr))?;
+        if let Err(err) = self.charge_frame(region.owner, frame) {
+            self.deallocate_frame(frame, MemoryZone::from_address(frame.start_address()));
+            return Err(err);
+        }
 
-        // Implement swap-in functionality
-        // 1. Find the swap slot for this virtual address
-        let swap_slot = swap_manager.swap_entries.iter()
-            .find(|(_, entry)| entry.page_addr == addr)
-            .map(|(slot, _)| *slot);
-
-        // 2. Read the page data from swap storage and copy to physical frame
-        if let Some(slot) = swap_slot {
-            // Allocate buffer for page data
-            let mut page_data = [0u8; PAGE_SIZE];
-
-            // Read page from swap
-            match swap_manager.swap_in(slot, &mut page_data) {
-                Ok(_) => {
-                    // 3. Copy the data to the new physical frame
-                    unsafe {
-                        let page_ptr = frame.start_address().as_u64() as *mut u8;
-                        core::ptr::copy_nonoverlapping(page_data.as_ptr(), page_ptr, PAGE_SIZE);
-                    }
+        let mut swap_manager = self.swap_manager.lock();
+        let slot = swap_manager.slot_for(page.start_address());
+
+        let page_ptr = phys_to_virt(frame.start_address()).as_mut_ptr::<u8>();
+        match slot {
+            Some(slot) => {
+                let mut page_data = [0u8; PAGE_SIZE];
+                if swap_manager.swap_in(slot, &mut page_data).is_err() {
+                    // The slot stays allocated so the data isn't lost for good
+                    drop(swap_manager);
+                    self.deallocate_frame(frame, MemoryZone::from_address(frame.start_address()));
+                    return Err(MemoryError::OutOfMemory);
                 }
-                Err(e) => {
-                    // Failed to read from swap - zero the page as fallback
-                    unsafe {
-                        let page_ptr = frame.start_address().as_u64() as *mut u8;
-                        core::ptr::write_bytes(page_ptr, 0, PAGE_SIZE);
-                    }
+                unsafe {
+                    core::ptr::copy_nonoverlapping(page_data.as_ptr(), page_ptr, PAGE_SIZE);
                 }
             }
-        } else {
-            // No swap entry found - zero the page as fallback
-            // This handles the case where the page was never swapped out
-            unsafe {
-                let page_ptr = frame.start_address().as_u64() as *mut u8;
+            None => unsafe {
+                // Swapped back in by someone else meanwhile; nothing to restore
                 core::ptr::write_bytes(page_ptr, 0, PAGE_SIZE);
-            }
+            },
         }
 
         // Map the page
+        let mut page_table_manager = self.page_table_manager.lock();
+        let mut frame_allocator = self.frame_allocator.lock();
         let flags = region.protection.to_page_table_flags();
         page_table_manager.map_page(page, frame, flags, &mut *frame_allocator)
             .map_err(|_| MemoryError::MappingFailed)?;
 
         // Record page access for replacement algorithms
-        swap_manager.record_access(addr);
+        swap_manager.record_access(page.start_address());
 
         Ok(())
     }
 
     /// Handle demand paging (allocate page on first access)
     fn handle_demand_paging(&self, addr: VirtAddr, region: &VirtualMemoryRegion) -> Result<(), MemoryError> {
-        let page = Page::containing_address(addr);
-        let mut page_table_manager = self.page_table_manager.lock();
-        let mut frame_allocator = self.frame_allocator.lock();
-        let mut swap_manager = self.swap_manager.lock();
+        if self.huge_fault(addr, region) {
+            return Ok(());
+        }
 
-        // Try to allocate a new frame
-        let frame = if let Some(frame) = frame_allocator.allocate_frame() {
-            frame
-        } else {
-            // Out of physical memory - need to swap out a page
-            drop(frame_allocator); // Release lock to avoid deadlock
-            drop(page_table_manager);
-            
-            self.swap_out_victim_page()?;
-            
-            // Re-acquire locks and try again
-            page_table_manager = self.page_table_manager.lock();
-            frame_allocator = self.frame_allocator.lock();
-            
-            frame_allocator.allocate_frame()
-                .ok_or(MemoryError::OutOfMemory)?
-        };
+        let page = Page::containing_address(addr);
+        let frame = self.allocate_frame_placed(numa::placement_for(region, addr))?;
+        if let Err(err) = self.charge_frame(region.owner, frame) {
+            self.deallocate_frame(frame, MemoryZone::from_address(frame.start_address()));
+            return Err(err);
+        }
 
         // Zero the page for security
         unsafe {
-            let page_ptr = frame.start_address().as_u64() as *mut u8;
+            let page_ptr = phys_to_virt(frame.start_address()).as_mut_ptr::<u8>();
             core::ptr::write_bytes(page_ptr, 0, PAGE_SIZE);
         }
 
         // Map the page
+        let mut page_table_manager = self.page_table_manager.lock();
+        let mut frame_allocator = self.frame_allocator.lock();
+
+        // Another CPU faulted it in first, or khugepaged collapsed the block
+        if page_table_manager.translate_addr(addr).is_some() {
+            frame_allocator.deallocate_frame(frame, MemoryZone::from_address(frame.start_address()));
+            return Ok(());
+        }
+
         let flags = region.protection.to_page_table_flags();
         page_table_manager.map_page(page, frame, flags, &mut *frame_allocator)
             .map_err(|_| MemoryError::MappingFailed)?;
+        drop(frame_allocator);
+        drop(page_table_manager);
 
         // Record page access for replacement algorithms
-        swap_manager.record_access(addr);
+        self.swap_manager.lock().record_access(page.start_address());
 
         Ok(())
     }
-    
+
+    /// Allocate a frame for a faulting page, evicting a page to swap if
+    /// memory is exhausted, and wake kswapd when free memory runs low
+    fn allocate_frame_or_reclaim(&self) -> Result<PhysFrame, MemoryError> {
+        self.allocate_frame_placed(numa::Placement::local())
+    }
+
+    /// `allocate_frame_or_reclaim` on the nodes `placement` allows
+    fn allocate_frame_placed(&self, placement: numa::Placement) -> Result<PhysFrame, MemoryError> {
+        let mut frame_allocator = self.frame_allocator.lock();
+        let frame = match frame_allocator.allocate_frames_placed(placement, 0) {
+            Some(frame) => frame,
+            None => {
+                // Out of physical memory - reclaim directly, and failing
+                // that, kill something
+                drop(frame_allocator);
+                let _ = self.swap_out_victim_page(None);
+                frame_allocator = self.frame_allocator.lock();
+                match frame_allocator.allocate_frames_placed(placement, 0) {
+                    Some(frame) => frame,
+                    None => {
+                        drop(frame_allocator);
+                        let victim = oom::out_of_memory(self, "page allocation").ok_or(MemoryError::OutOfMemory)?;
+                        if victim == crate::process::current_pid() {
+                            // The faulting process itself was killed
+                            return Err(MemoryError::OutOfMemory);
+                        }
+                        frame_allocator = self.frame_allocator.lock();
+                        frame_allocator.allocate_frames_placed(placement, 0).ok_or(MemoryError::OutOfMemory)?
+                    }
+                }
+            }
+        };
+
+        let free_frames = frame_allocator.get_zone_stats().iter().map(ZoneStats::free_frames).sum();
+        drop(frame_allocator);
+        swap::wakeup_kswapd(free_frames);
+        Ok(frame)
+    }
+
+    /// Charge a frame just allocated for a page of process `owner` to its
+    /// memory cgroup
+    ///
+    /// A group at its `memory.max` first has its own pages swapped out, and
+    /// failing that its members killed. Fails if nothing could be freed, or
+    /// if `owner` itself was killed.
+    fn charge_frame(&self, owner: crate::process::Pid, frame: PhysFrame) -> Result<(), MemoryError> {
+        let phys = frame.start_address().as_u64();
+        let mut killed = Vec::new();
+        loop {
+            let Err(group) = cgroup::try_charge_page(owner, phys, false) else {
+                return Ok(());
+            };
+            if !self.reclaim_cgroup(group, &mut killed) || killed.contains(&owner) {
+                return Err(MemoryError::OutOfMemory);
+            }
+        }
+    }
+
+    /// Bring memory cgroup `group` back under a lowered `memory.max`, by
+    /// reclaim and then by killing its members
+    pub fn enforce_cgroup_limit(&self, group: cgroup::CgroupId) {
+        let mut killed = Vec::new();
+        while cgroup::memory_over_limit(group) && self.reclaim_cgroup(group, &mut killed) {}
+    }
+
+    /// Swap out a page of memory cgroup `group`, or failing that kill one of
+    /// its members not in `killed` yet and add it there
+    ///
+    /// Returns false if neither was possible.
+    fn reclaim_cgroup(&self, group: cgroup::CgroupId, killed: &mut Vec<crate::process::Pid>) -> bool {
+        let members: Vec<_> = cgroup::subtree_procs(group)
+            .into_iter()
+            .filter(|pid| !killed.contains(pid))
+            .collect();
+        if self.swap_out_victim_page(Some(&members)).is_ok() {
+            return true;
+        }
+        let victim = oom::out_of_memory_in(self, &members, "memory cgroup");
+        cgroup::record_oom(group, victim.is_some());
+        killed.extend(victim);
+        victim.is_some()
+    }
+
+    /// Evict one cold anonymous page to swap, for kswapd
+    pub fn reclaim_page(&self) -> Result<(), MemoryError> {
+        self.swap_out_victim_page(None)
+    }
+
+    /// Bring every page stored in swap area `area` back into memory, so the
+    /// area can be deactivated
+    pub fn swap_in_area(&self, area: usize) -> Result<(), MemoryError> {
+        let pages: Vec<VirtAddr> = self.swap_manager.lock()
+            .swap_entries
+            .iter()
+            .filter(|(slot, _)| slot.area() == area)
+            .map(|(_, entry)| entry.page_addr)
+            .collect();
+
+        for page_addr in pages {
+            let region = self.find_region(page_addr).ok_or(MemoryError::RegionNotFound)?;
+            self.handle_swap_in(page_addr, &region)?;
+        }
+        Ok(())
+    }
+
     /// Swap out a victim page to make room for new allocation
-    fn swap_out_victim_page(&self) -> Result<(), MemoryError> {
+    ///
+    /// Only resident, private pages of unlocked anonymous user regions
+    /// (data, heap and stack) are candidates; the replacement algorithm picks the
+    /// coldest of them. With `owners`, only regions of those processes are.
+    fn swap_out_victim_page(&self, owners: Option<&[crate::process::Pid]>) -> Result<(), MemoryError> {
         let regions = self.regions.read();
+        let page_table_manager = self.page_table_manager.lock();
         let mut candidate_pages = Vec::new();
-        
-        // Collect candidate pages from all mapped regions
+
         for region in regions.values() {
-            if region.mapped && region.protection.user_accessible {
-                for page_addr in region.pages().map(|p| p.start_address()) {
-                    candidate_pages.push(page_addr);
+            let anonymous = matches!(
+                region.region_type,
+                MemoryRegionType::UserData | MemoryRegionType::UserHeap | MemoryRegionType::UserStack
+            );
+            if !region.mapped || !anonymous || region.locked || region.protection.copy_on_write {
+                continue;
+            }
+            if owners.is_some_and(|owners| !owners.contains(&region.owner)) {
+                continue;
+            }
+            for page_addr in region.pages().map(|p| p.start_address()) {
+                // Huge pages are split before any of them is swapped
+                if page_table_manager.huge_frame(page_addr).is_some() {
+                    continue;
+                }
+                if let Some(phys_addr) = page_table_manager.translate_addr(page_addr) {
+                    if !self.is_frame_shared(phys_addr) {
+                        candidate_pages.push(page_addr);
+                    }
                 }
             }
         }
-        
+
+        drop(page_table_manager);
         drop(regions);
-        
+
         if candidate_pages.is_empty() {
             return Err(MemoryError::OutOfMemory);
         }
-        
+
         let mut swap_manager = self.swap_manager.lock();
         let victim_addr = swap_manager.select_victim_page(&candidate_pages)
             .ok_or(MemoryError::OutOfMemory)?;
-        
+
         let victim_page = Page::containing_address(victim_addr);
         let mut page_table_manager = self.page_table_manager.lock();
-        
+
         // Get the physical address of the victim page
         let phys_addr = page_table_manager.translate_addr(victim_addr)
             .ok_or(MemoryError::InvalidAddress)?;
-        
+
         // Read the page content
         let mut page_data = [0u8; PAGE_SIZE];
         unsafe {
-            let page_ptr = phys_addr.as_u64() as *const u8;
+            let page_ptr = phys_to_virt(phys_addr).as_ptr::<u8>();
             core::ptr::copy_nonoverlapping(page_ptr, page_data.as_mut_ptr(), PAGE_SIZE);
         }
-        
+
         // Swap out the page
-        let _swap_slot = swap_manager.swap_out(victim_addr, &page_data)
+        swap_manager.swap_out(victim_addr, &page_data)
             .map_err(|_| MemoryError::OutOfMemory)?;
-        
+
         // Unmap the page and free the frame
         if let Some(frame) = page_table_manager.unmap_page(victim_page) {
             let mut frame_allocator = self.frame_allocator.lock();
             let zone = MemoryZone::from_address(frame.start_address());
             frame_allocator.deallocate_frame(frame, zone);
         }
-        
+
         Ok(())
     }
 
+
     /// Handle copy-on-write page fault
     fn handle_copy_on_write(&self, addr: VirtAddr, region: &VirtualMemoryRegion) -> Result<(), MemoryError> {
         let page = Page::containing_address(addr);
@@ -2005,11 +2401,16 @@ impl MemoryManager {
         let new_frame = frame_allocator
             .allocate_frame()
             .ok_or(MemoryError::OutOfMemory)?;
+        // No reclaim with the page tables locked; the group may go over.
+        // The hierarchy is never taken under the frame allocator's lock.
+        drop(frame_allocator);
+        let _ = cgroup::try_charge_page(region.owner, new_frame.start_address().as_u64(), true);
+        frame_allocator = self.frame_allocator.lock();
 
         // Copy content from old page to new page
         unsafe {
-            let old_ptr = old_frame_addr.as_u64() as *const u8;
-            let new_ptr = new_frame.start_address().as_u64() as *mut u8;
+            let old_ptr = phys_to_virt(old_frame_addr).as_ptr::<u8>();
+            let new_ptr = phys_to_virt(new_frame.start_address()).as_mut_ptr::<u8>();
             core::ptr::copy_nonoverlapping(old_ptr, new_ptr, PAGE_SIZE);
         }
 
@@ -2153,6 +2554,8 @@ impl MemoryManager {
 
     /// Create a copy-on-write mapping (for fork)
     pub fn create_cow_mapping(&self, src_region: &VirtualMemoryRegion) -> Result<VirtualMemoryRegion, MemoryError> {
+        self.split_huge_pages(src_region.start, src_region.end());
+
         let mut cow_region = src_region.clone();
         cow_region.protection.copy_on_write = true;
         cow_region.protection.writable = false;
@@ -2175,6 +2578,9 @@ impl MemoryManager {
         parent_region: &VirtualMemoryRegion,
         child_region: &VirtualMemoryRegion,
     ) -> Result<(), MemoryError> {
+        self.split_huge_pages(parent_region.start, parent_region.end());
+        self.split_huge_pages(child_region.start, child_region.end());
+
         let mut page_table_manager = self.page_table_manager.lock();
 
         // Create COW flags (read-only, user accessible)
@@ -2204,6 +2610,8 @@ impl MemoryManager {
         src_size: usize,
         dst_start: VirtAddr,
     ) -> Result<(), MemoryError> {
+        self.split_huge_pages(src_start, src_start + src_size as u64);
+
         let mut page_table_manager = self.page_table_manager.lock();
         let mut frame_allocator = self.frame_allocator.lock();
 
@@ -2264,13 +2672,20 @@ impl MemoryManager {
         frame_allocator.get_memory_report()
     }
 
-    /// Initialize swap space with a storage device
-    pub fn init_swap_space(&self, device_id: u32, size_mb: u32) -> Result<(), &'static str> {
-        let mut swap_manager = self.swap_manager.lock();
-        swap_manager.set_swap_device(device_id);
-        
-        // In a real implementation, this would create a swap file or partition
-        crate::serial_println!("Initialized {}MB swap space on device {}", size_mb, device_id);
+    /// Start swapping to a whole storage device prepared with mkswap
+    pub fn init_swap_space(&self, device_id: u32) -> Result<(), swap::SwapError> {
+        let device = crate::drivers::storage::BlockDevice::new(device_id)
+            .map_err(|_| swap::SwapError::Io)?;
+        let range = crate::drivers::storage::BlockRange {
+            device_id,
+            sector_size: device.sector_size(),
+            start_sector: 0,
+            sector_count: device.total_sectors(),
+        };
+        let path = alloc::format!("device:{}", device_id);
+        swap::swapon(&path, swap::SwapBacking::Device(range), 0)?;
+
+        crate::serial_println!("Swapping to storage device {}", device_id);
         Ok(())
     }
 
@@ -2280,35 +2695,16 @@ impl MemoryManager {
         swap_manager.get_stats()
     }
 
-    /// Check if a page is currently swapped out
+    /// Check if the page containing `addr` is currently swapped out
     pub fn is_page_swapped(&self, addr: VirtAddr) -> bool {
-        let swap_manager = self.swap_manager.lock();
-        swap_manager.swap_entries.iter()
-            .any(|(_, entry)| entry.page_addr == addr)
+        let page_addr = Page::<Size4KiB>::containing_address(addr).start_address();
+        self.swap_manager.lock().slot_for(page_addr).is_some()
     }
 }
 
-/// ASLR seed using hardware RNG when available
-static ASLR_COUNTER: AtomicU64 = AtomicU64::new(0);
-
-/// Generate ASLR offset using hardware RNG
+/// Random page-aligned offset of up to `vm.mmap_rnd_bits` bits
 pub fn generate_aslr_offset() -> u64 {
-    // Use RDRAND instruction for hardware random number generation
-    let random_value = unsafe {
-        let mut value: u64 = 0;
-        // Try hardware RNG first
-        if core::arch::x86_64::_rdrand64_step(&mut value) == 1 {
-            value
-        } else {
-            // Fallback to TSC + counter if RDRAND not available
-            let tsc = core::arch::x86_64::_rdtsc();
-            let counter = ASLR_COUNTER.fetch_add(1, Ordering::SeqCst);
-            tsc.wrapping_mul(6364136223846793005).wrapping_add(counter)
-        }
-    };
-
-    // Apply entropy bits and align to page size
-    (random_value & ((1 << ASLR_ENTROPY_BITS) - 1)) * PAGE_SIZE as u64
+    aslr::random_pages(aslr::rnd_bits())
 }
 
 /// Memory error types
@@ -2331,6 +2727,8 @@ pub enum MemoryError {
     BuddyAllocationFailed,
     FragmentationLimitExceeded,
     PermissionDenied,
+    IoError,
+    Busy,
 }
 
 impl fmt::Display for MemoryError {
@@ -2353,6 +2751,8 @@ impl fmt::Display for MemoryError {
             MemoryError::BuddyAllocationFailed => write!(f, "Buddy allocation failed"),
             MemoryError::FragmentationLimitExceeded => write!(f, "Memory fragmentation limit exceeded"),
             MemoryError::PermissionDenied => write!(f, "Permission denied"),
+            MemoryError::IoError => write!(f, "I/O error on file-backed page"),
+            MemoryError::Busy => write!(f, "Page is in use and can't be moved"),
         }
     }
 }
@@ -2405,6 +2805,14 @@ lazy_static! {
     static ref MEMORY_MANAGER: RwLock<Option<MemoryManager>> = RwLock::new(None);
 }
 
+/// Where the bootloader mapped all of physical memory, as moved by KASLR
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// Kernel address of physical address `phys` in the direct map
+pub fn phys_to_virt(phys: PhysAddr) -> VirtAddr {
+    VirtAddr::new(phys.as_u64() + PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed))
+}
+
 /// Initialize the memory management system
 pub fn init_memory_management(
     memory_regions: &[MemoryRegion],
@@ -2412,6 +2820,7 @@ pub fn init_memory_management(
 ) -> Result<(), MemoryError> {
     // Determine physical memory offset (default to zero if not provided)
     let physical_memory_offset = VirtAddr::new(physical_memory_offset.unwrap_or(0));
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset.as_u64(), Ordering::Relaxed);
 
     // Get current page table
     let level_4_table = unsafe {
@@ -2425,6 +2834,9 @@ pub fn init_memory_management(
     let mapper = unsafe { OffsetPageTable::new(level_4_table, physical_memory_offset) };
     let page_table_manager = PageTableManager::new(mapper, physical_memory_offset);
 
+    // Discover NUMA nodes so the frame allocator can give each its zones
+    numa::init();
+
     // Create frame allocator with buddy system
     let frame_allocator = PhysicalFrameAllocator::init(memory_regions);
 
@@ -2437,6 +2849,11 @@ pub fn init_memory_management(
     // Store global instance
     *MEMORY_MANAGER.write() = Some(memory_manager);
 
+    // Kernel allocations now come from slabs
+    if let Some(memory_manager) = get_memory_manager() {
+        slab::init(memory_manager, physical_memory_offset.as_u64());
+    }
+
     Ok(())
 }
 
@@ -2472,6 +2889,14 @@ pub fn allocate_memory_with_guards(
 /// Deallocate memory region
 pub fn deallocate_memory(addr: VirtAddr) -> Result<(), MemoryError> {
     let mm = get_memory_manager().ok_or(MemoryError::OutOfMemory)?;
+    if let Some(region) = mm.find_region(addr).filter(|region| region.start == addr && region.region_type == MemoryRegionType::FileMapping) {
+        // Writes back dirty shared pages and drops page cache references
+        return file_mapping::unmap(region.start, region.size);
+    }
+    if let Some(region) = mm.find_region(addr).filter(|region| region.start == addr && region.region_type == MemoryRegionType::HugeTlb) {
+        // The pages go back to the huge page pool, not the buddy allocator
+        return huge_page::unmap(region.start, region.size);
+    }
     let mut region = mm.remove_region(addr)?;
     mm.unmap_region(&mut region)?;
     Ok(())
@@ -2498,8 +2923,16 @@ pub fn protect_memory(
 }
 
 /// Handle page fault (called from interrupt handler)
+///
+/// A fault in kernel mode may come from code holding the memory manager's
+/// locks, which the handler would then wait for forever. Such a fault fails
+/// with `Busy` instead.
 pub fn handle_page_fault(addr: VirtAddr, error_code: u64) -> Result<(), MemoryError> {
     let mm = get_memory_manager().ok_or(MemoryError::OutOfMemory)?;
+    let is_user = error_code & 0x4 != 0;
+    if !is_user && !mm.fault_locks_free() {
+        return Err(MemoryError::Busy);
+    }
     mm.handle_page_fault(addr, error_code)
 }
 
@@ -2558,6 +2991,68 @@ mod tests {
         assert!(!region.contains(VirtAddr::new(0x3500)));
     }
 
+    #[test_case]
+    fn test_slab_cache_geometry() {
+        static SMALL: slab::KmemCache = slab::KmemCache::new("test-96", 96, 32);
+        static LARGE: slab::KmemCache = slab::KmemCache::new("test-3000", 3000, 8);
+
+        // Objects follow the 32-byte slab header
+        let small = SMALL.stats();
+        assert_eq!(small.slab_size, PAGE_SIZE);
+        assert_eq!(small.objects_per_slab, (PAGE_SIZE - 32) / 96);
+
+        // Slabs grow until they hold enough objects
+        let large = LARGE.stats();
+        assert_eq!(large.slab_size, 8 * PAGE_SIZE);
+        assert_eq!(large.objects_per_slab, 10);
+        assert_eq!(large.active_objects, 0);
+
+        assert!(slab::stats().iter().any(|cache| cache.name == "task_struct"));
+    }
+
+    #[test_case]
+    fn test_oom_badness() {
+        let usage = oom::Usage { rss: 300, swap: 100 };
+
+        // init and exempt processes are never chosen
+        assert_eq!(oom::badness(1, usage, 0, 10_000), None);
+        assert_eq!(oom::badness(42, usage, oom::OOM_SCORE_ADJ_MIN, 10_000), None);
+
+        // The adjustment is in thousandths of all memory
+        assert_eq!(oom::badness(42, usage, 0, 10_000), Some(400));
+        assert_eq!(oom::badness(42, usage, 500, 10_000), Some(5400));
+        assert_eq!(oom::badness(42, usage, -999, 10_000), Some(1));
+
+        // Only root may lower the adjustment
+        assert_eq!(oom::set_score_adj(4242, 1001, true), Err(oom::AdjError::Invalid));
+        assert_eq!(oom::set_score_adj(4242, 200, false), Ok(0));
+        assert_eq!(oom::set_score_adj(4242, 100, false), Err(oom::AdjError::PermissionDenied));
+        assert_eq!(oom::set_score_adj(4242, -1000, true), Ok(200));
+        assert_eq!(oom::score_adj(4242), -1000);
+        oom::exit_process(4242);
+        assert_eq!(oom::score_adj(4242), 0);
+    }
+
+    #[test_case]
+    fn test_aslr_layout() {
+        for _ in 0..16 {
+            let offset = aslr::random_pages(aslr::MMAP_RND_BITS_MAX);
+            assert_eq!(offset % PAGE_SIZE as u64, 0);
+            assert!(offset < (PAGE_SIZE as u64) << aslr::MMAP_RND_BITS_MAX);
+        }
+        assert_eq!(aslr::random_pages(0), 0);
+
+        let layout = aslr::new_layout(4243);
+        assert!(layout.load_bias >= USER_SPACE_START as u64);
+        assert!(layout.load_bias < layout.mmap_base && layout.mmap_base < layout.stack_top);
+        assert!(layout.stack_top <= USER_SPACE_END as u64);
+        let brk = aslr::set_brk_base(4243, layout.load_bias + 0x1234);
+        assert_eq!(aslr::layout(4243).brk_base, brk);
+        assert!(brk >= layout.load_bias + PAGE_SIZE as u64);
+        aslr::exit_process(4243);
+        assert_eq!(aslr::layout(4243), aslr::Layout::FIXED);
+    }
+
     #[test_case]
     fn test_memory_zones() {
         assert_eq!(MemoryZone::from_address(PhysAddr::new(0x100000)), MemoryZone::Dma);
@@ -2588,6 +3083,26 @@ mod tests {
         assert!(!guard_protection.writable);
         assert!(!guard_protection.executable);
     }
+
+    #[test_case]
+    fn test_stack_reserve() {
+        // The default 8 MiB RLIMIT_STACK plus the gap, and capped when unlimited
+        assert_eq!(stack::reserve(4244), 8 * 1024 * 1024 + stack::STACK_GUARD_GAP);
+        let unlimited = crate::process::rlimit::Limit::UNLIMITED;
+        crate::process::rlimit::set(4244, crate::process::rlimit::RLIMIT_STACK, unlimited, true).unwrap();
+        assert_eq!(stack::reserve(4244), 128 * 1024 * 1024 + stack::STACK_GUARD_GAP);
+        crate::process::rlimit::exit_process(4244);
+    }
+
+    #[test_case]
+    fn test_kernel_guard_lookup() {
+        let guard = VirtAddr::new(0x7fff_0000_0000);
+        assert!(!stack::is_kernel_guard(guard + 8u64));
+        stack::register_kernel_guard(guard);
+        assert!(stack::is_kernel_guard(guard + 8u64));
+        assert!(!stack::is_kernel_guard(guard + PAGE_SIZE as u64));
+        assert_eq!(stack::overflowed_stack(guard), Some(guard + PAGE_SIZE as u64));
+    }
 }
 
 
@@ -2820,25 +3335,39 @@ pub fn check_memory_access(addr: usize, size: usize, write: bool, privilege_leve
         }
     }
 
-    // TODO: Check page table entries to verify actual permissions
-    // For now, we'll do basic range checking
-
     // Check if the memory manager is initialized
     if let Some(memory_manager) = get_memory_manager() {
-        let page_table_manager = memory_manager.page_table_manager.lock();
+        let first_page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(addr as u64));
+        let last_page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(end_addr as u64 - 1));
 
-        // Check if the pages are mapped
-        for offset in (0..size).step_by(4096) {
-            let check_addr = addr + offset;
-            let virt_addr = VirtAddr::new(check_addr as u64);
-            let page: Page<Size4KiB> = Page::containing_address(virt_addr);
+        for page in Page::range_inclusive(first_page, last_page) {
+            let region = memory_manager.find_region(page.start_address());
+            let flags = memory_manager.page_table_manager.lock().get_flags(page);
 
-            // Check if page is mapped
-            if page_table_manager.mapper.translate_page(page).is_err() {
-                return Ok(false);
+            match (flags, region) {
+                (Some(flags), region) => {
+                    if privilege_level == 3 && !flags.contains(PageTableFlags::USER_ACCESSIBLE) {
+                        return Ok(false);
+                    }
+                    // Copy-on-write pages are read-only until the first write
+                    let writable = flags.contains(PageTableFlags::WRITABLE)
+                        || region.map_or(false, |region| region.protection.writable);
+                    if write && !writable {
+                        return Ok(false);
+                    }
+                }
+                // Not present yet: allowed if the region will fault it in
+                (None, Some(region)) => {
+                    let protection = region.protection;
+                    if protection.guard_page
+                        || (privilege_level == 3 && !(protection.user_accessible && protection.readable))
+                        || (write && !protection.writable)
+                    {
+                        return Ok(false);
+                    }
+                }
+                (None, None) => return Ok(false),
             }
-
-            // TODO: Check page table flags for write permissions if write == true
         }
 
         Ok(true)
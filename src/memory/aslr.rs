@@ -0,0 +1,135 @@
+//! User Address Space Layout Randomisation
+//!
+//! Every exec gives the new image a layout of its own. Four bases move, each
+//! by a random number of pages:
+//! - the load bias of a position-independent executable, up from the
+//!   bottom of user space
+//! - the start of the heap, above the end of the loaded image
+//! - the mmap base, where searches for free address space begin
+//! - the stack top, down from the top of user space
+//!
+//! `vm.mmap_rnd_bits` sets how many bits of randomness the load bias, mmap
+//! base and stack get; the heap gets at most `BRK_RND_BITS`, as on Linux.
+//! `kernel.randomize_va_space` is 0 for a fixed layout, 1 to randomise all
+//! but the heap and 2 (the default) for everything. Forked children keep
+//! their parent's layout, since they share its addresses.
+//!
+//! User space is 1.75 GiB here, so the bits are capped well below Linux's:
+//! at `MMAP_RND_BITS_MAX` each base moves within 256 MiB.
+
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::RwLock;
+
+use super::{align_up, PAGE_SIZE, USER_SPACE_END, USER_SPACE_START};
+use crate::process::Pid;
+
+/// Fewest bits `vm.mmap_rnd_bits` may be set to
+pub const MMAP_RND_BITS_MIN: u64 = 8;
+
+/// Most bits `vm.mmap_rnd_bits` may be set to
+pub const MMAP_RND_BITS_MAX: u64 = 16;
+
+/// Bits of randomness of the heap start, at most
+pub const BRK_RND_BITS: u64 = 13;
+
+/// Lowest mmap base; the executable and its heap stay below it
+const MMAP_BASE: u64 = 0x0000_4000_0000;
+
+/// `kernel.randomize_va_space`
+pub static RANDOMIZE_VA_SPACE: AtomicU64 = AtomicU64::new(2);
+
+/// `vm.mmap_rnd_bits`
+pub static MMAP_RND_BITS: AtomicU64 = AtomicU64::new(MMAP_RND_BITS_MAX);
+
+/// Mixed into the TSC when the random pool isn't seeded yet
+static FALLBACK_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Where one process's image, heap, mappings and stack go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// Added to the addresses of a position-independent executable
+    pub load_bias: u64,
+    /// Start of the heap; 0 until the image is loaded
+    pub brk_base: u64,
+    /// Where searches for free address space begin
+    pub mmap_base: u64,
+    /// The stack is placed below this
+    pub stack_top: u64,
+}
+
+impl Layout {
+    /// The layout with randomisation off
+    pub const FIXED: Layout = Layout {
+        load_bias: USER_SPACE_START as u64,
+        brk_base: 0,
+        mmap_base: MMAP_BASE,
+        stack_top: USER_SPACE_END as u64,
+    };
+}
+
+/// Layouts of processes that have loaded an image
+static LAYOUTS: RwLock<BTreeMap<Pid, Layout>> = RwLock::new(BTreeMap::new());
+
+/// A random number of bytes, in whole pages, below `1 << bits` pages
+pub fn random_pages(bits: u64) -> u64 {
+    let mut bytes = [0u8; 8];
+    let value = if crate::security::secure_random_bytes(&mut bytes).is_ok() {
+        u64::from_le_bytes(bytes)
+    } else {
+        // Weak, but still different for every call and every boot
+        let tsc = unsafe { core::arch::x86_64::_rdtsc() };
+        tsc.wrapping_mul(6364136223846793005).wrapping_add(FALLBACK_COUNTER.fetch_add(1, Ordering::Relaxed))
+    };
+    (value & ((1u64 << bits) - 1)) * PAGE_SIZE as u64
+}
+
+/// Bits of randomness the load bias, mmap base and stack get now
+pub fn rnd_bits() -> u64 {
+    if RANDOMIZE_VA_SPACE.load(Ordering::Relaxed) == 0 {
+        return 0;
+    }
+    MMAP_RND_BITS.load(Ordering::Relaxed)
+}
+
+/// Give process `pid` a fresh layout for a new image
+pub fn new_layout(pid: Pid) -> Layout {
+    let bits = rnd_bits();
+    let layout = Layout {
+        load_bias: USER_SPACE_START as u64 + random_pages(bits),
+        brk_base: 0,
+        mmap_base: MMAP_BASE + random_pages(bits),
+        stack_top: USER_SPACE_END as u64 - random_pages(bits),
+    };
+    LAYOUTS.write().insert(pid, layout);
+    layout
+}
+
+/// Put the heap of process `pid` above its image, which ends at
+/// `image_end`, and return where it starts
+pub fn set_brk_base(pid: Pid, image_end: u64) -> u64 {
+    let bits = if RANDOMIZE_VA_SPACE.load(Ordering::Relaxed) >= 2 { rnd_bits().min(BRK_RND_BITS) } else { 0 };
+    let base = align_up(image_end as usize, PAGE_SIZE) as u64 + random_pages(bits);
+    if let Some(layout) = LAYOUTS.write().get_mut(&pid) {
+        layout.brk_base = base;
+    }
+    base
+}
+
+/// The layout of process `pid`
+pub fn layout(pid: Pid) -> Layout {
+    LAYOUTS.read().get(&pid).copied().unwrap_or(Layout::FIXED)
+}
+
+/// Give a new child its parent's layout
+pub fn inherit(parent: Pid, child: Pid) {
+    let mut table = LAYOUTS.write();
+    if let Some(&layout) = table.get(&parent) {
+        table.insert(child, layout);
+    }
+}
+
+/// Forget the layout of an exiting process
+pub fn exit_process(pid: Pid) {
+    LAYOUTS.write().remove(&pid);
+}
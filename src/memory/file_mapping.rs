@@ -0,0 +1,706 @@
+//! File-Backed Memory Mappings
+//!
+//! mmap of a regular file creates a `FileMapping` region whose pages are
+//! read from the file on first access. Mapped file pages live in a page
+//! cache keyed by device, inode number and page index, so every mapping of
+//! the same part of a file shares one frame, whichever open file it came
+//! through:
+//!
+//! - MAP_SHARED mappings map the cached frame itself. Stores set the dirty
+//!   bit in the page table, and dirty pages are written back to the file by
+//!   msync, by munmap and by a flusher thread every few seconds
+//! - MAP_PRIVATE mappings map the cached frame read-only and copy it to a
+//!   private frame on the first write; private copies are never written back
+//!
+//! The tail of the last page past the end of the file reads as zero and is
+//! not written back. Touching a page that lies wholly past the end of the
+//! file is an error, which the fault path turns into SIGBUS.
+//!
+//! Cached pages are dropped once no mapping uses them; the cache does not
+//! keep read() and write() coherent with mappings of the same file.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use x86_64::instructions::tlb;
+use x86_64::structures::paging::{Page, PageTableFlags, PhysFrame};
+use x86_64::VirtAddr;
+
+use super::{
+    align_up, get_memory_manager, huge_page, MemoryError, MemoryManager, MemoryProtection, MemoryRegionType,
+    MemoryZone, VirtualMemoryRegion, HUGE_PAGE_SIZE, PAGE_SIZE,
+};
+use crate::vfs::hugetlbfs::HugeTlbFile;
+use crate::vfs::{self, InodeOps, InodeType};
+
+/// Interval between the flusher's writeback passes
+const WRITEBACK_INTERVAL_NS: u64 = 5_000_000_000;
+
+/// Errors from mapping a file descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// The descriptor is not open
+    BadFd,
+    /// The descriptor's access mode doesn't allow the requested protection
+    Access,
+    /// The descriptor doesn't refer to a regular file
+    NoDevice,
+    /// Misaligned offset or empty length
+    InvalidArgument,
+    /// Setting up the region failed
+    Memory(MemoryError),
+}
+
+/// Device and inode number of a mapped file
+type FileId = (u64, u64);
+
+/// The file behind one mapping
+#[derive(Clone)]
+struct FileRegion {
+    inode: Arc<dyn InodeOps>,
+    id: FileId,
+    /// File offset of the first page
+    offset: u64,
+    /// Length of the mapping in bytes, page aligned
+    len: usize,
+    shared: bool,
+    /// Whether mprotect may add write access; false for shared mappings of
+    /// descriptors not open for writing
+    may_write: bool,
+}
+
+impl FileRegion {
+    /// File offset of `page`, for a mapping starting at `start`
+    fn offset_of(&self, start: VirtAddr, page: Page) -> u64 {
+        self.offset + (page.start_address() - start)
+    }
+
+    fn file_size(&self) -> Result<u64, MemoryError> {
+        self.inode.stat().map(|stat| stat.size).map_err(|_| MemoryError::IoError)
+    }
+}
+
+/// A file page in the page cache
+struct CachedPage {
+    frame: PhysFrame,
+    /// Page table entries mapping the frame
+    mappers: usize,
+}
+
+struct MappingTable {
+    /// File mappings by start address
+    regions: BTreeMap<VirtAddr, FileRegion>,
+    /// Page cache, by file and page index
+    cache: BTreeMap<(FileId, u64), CachedPage>,
+}
+
+static MAPPINGS: Mutex<MappingTable> = Mutex::new(MappingTable {
+    regions: BTreeMap::new(),
+    cache: BTreeMap::new(),
+});
+
+/// A frame's contents, through the direct map of physical memory
+unsafe fn frame_bytes<'a>(frame: PhysFrame) -> &'a mut [u8; PAGE_SIZE] {
+    &mut *super::phys_to_virt(frame.start_address()).as_mut_ptr::<[u8; PAGE_SIZE]>()
+}
+
+fn free_frame(mm: &MemoryManager, frame: PhysFrame) {
+    mm.deallocate_frame(frame, MemoryZone::from_address(frame.start_address()));
+}
+
+fn map_frame(mm: &MemoryManager, page: Page, frame: PhysFrame, flags: PageTableFlags) -> Result<(), MemoryError> {
+    let mut page_table_manager = mm.page_table_manager.lock();
+    let mut frame_allocator = mm.frame_allocator.lock();
+    page_table_manager
+        .map_page(page, frame, flags, &mut *frame_allocator)
+        .map_err(|_| MemoryError::MappingFailed)
+}
+
+/// Fill `frame` with the file page at `file_offset`, zeroing past the end
+/// of the file
+fn read_page(inode: &dyn InodeOps, file_offset: u64, file_size: u64, frame: PhysFrame) -> Result<(), MemoryError> {
+    let buf = unsafe { frame_bytes(frame) };
+    buf.fill(0);
+
+    let len = file_size.saturating_sub(file_offset).min(PAGE_SIZE as u64) as usize;
+    let mut done = 0;
+    while done < len {
+        match inode.read_at(file_offset + done as u64, &mut buf[done..len]) {
+            Ok(0) => break,
+            Ok(n) => done += n,
+            Err(_) => return Err(MemoryError::IoError),
+        }
+    }
+    Ok(())
+}
+
+/// Write `frame` to the file page at `file_offset`, clipped to the current
+/// end of the file
+fn write_page(inode: &dyn InodeOps, file_offset: u64, frame: PhysFrame) -> Result<(), MemoryError> {
+    let file_size = inode.stat().map(|stat| stat.size).map_err(|_| MemoryError::IoError)?;
+    let buf = unsafe { frame_bytes(frame) };
+
+    let len = file_size.saturating_sub(file_offset).min(PAGE_SIZE as u64) as usize;
+    let mut done = 0;
+    while done < len {
+        match inode.write_at(file_offset + done as u64, &buf[done..len]) {
+            Ok(0) | Err(_) => return Err(MemoryError::IoError),
+            Ok(n) => done += n,
+        }
+    }
+    Ok(())
+}
+
+/// Write `page` of a shared mapping back to the file if it's dirty;
+/// returns whether it was written
+fn writeback_page(mm: &MemoryManager, file: &FileRegion, page: Page, file_offset: u64) -> Result<bool, MemoryError> {
+    let mut page_table_manager = mm.page_table_manager.lock();
+    let Some(flags) = page_table_manager.get_flags(page) else {
+        return Ok(false);
+    };
+    if !flags.contains(PageTableFlags::DIRTY) {
+        return Ok(false);
+    }
+    let frame = page_table_manager
+        .translate_addr(page.start_address())
+        .map(PhysFrame::containing_address)
+        .ok_or(MemoryError::InvalidAddress)?;
+
+    // Clear the dirty bit first so stores made during the write are caught
+    // by the next pass
+    page_table_manager
+        .update_flags(page, flags - PageTableFlags::DIRTY)
+        .map_err(|_| MemoryError::ProtectionFailed)?;
+    tlb::flush(page.start_address());
+    drop(page_table_manager);
+
+    if let Err(err) = write_page(&*file.inode, file_offset, frame) {
+        let _ = mm.page_table_manager.lock().update_flags(page, flags);
+        return Err(err);
+    }
+    Ok(true)
+}
+
+/// Overlap of the mapping at `start` with `[from, to)`
+fn clip(start: VirtAddr, file: &FileRegion, from: VirtAddr, to: VirtAddr) -> Option<(VirtAddr, VirtAddr)> {
+    let lo = start.max(from);
+    let hi = (start + file.len as u64).min(to);
+    (lo < hi).then_some((lo, hi))
+}
+
+fn pages(from: VirtAddr, to: VirtAddr) -> impl Iterator<Item = Page> {
+    Page::range(Page::containing_address(from), Page::containing_address(to))
+}
+
+impl MappingTable {
+    /// Drop a reference to a cached page, freeing it with the last one
+    fn put_page(&mut self, mm: &MemoryManager, key: (FileId, u64)) {
+        let Some(cached) = self.cache.get_mut(&key) else { return };
+        cached.mappers -= 1;
+        if cached.mappers == 0 {
+            let frame = cached.frame;
+            self.cache.remove(&key);
+            free_frame(mm, frame);
+        }
+    }
+
+    /// Unmap one page of the mapping at `start`, writing it back first if
+    /// it's shared and dirty
+    fn release_page(&mut self, mm: &MemoryManager, start: VirtAddr, file: &FileRegion, page: Page) -> Result<(), MemoryError> {
+        let file_offset = file.offset_of(start, page);
+        let written = if file.shared { writeback_page(mm, file, page, file_offset) } else { Ok(false) };
+
+        let frame = mm.page_table_manager.lock().unmap_page(page);
+        if let Some(frame) = frame {
+            let key = (file.id, file_offset / PAGE_SIZE as u64);
+            if self.cache.get(&key).is_some_and(|cached| cached.frame == frame) {
+                self.put_page(mm, key);
+            } else {
+                // A private copy
+                free_frame(mm, frame);
+            }
+        }
+        written.map(|_| ())
+    }
+
+    /// Write back the dirty shared pages in `[from, to)`; returns the number
+    /// of pages written
+    fn writeback(&self, mm: &MemoryManager, from: VirtAddr, to: VirtAddr) -> Result<usize, MemoryError> {
+        let mut written = 0;
+        for (&start, file) in self.regions.iter().filter(|(_, file)| file.shared) {
+            let Some((lo, hi)) = clip(start, file, from, to) else { continue };
+            for page in pages(lo, hi) {
+                if writeback_page(mm, file, page, file.offset_of(start, page))? {
+                    written += 1;
+                }
+            }
+        }
+        Ok(written)
+    }
+}
+
+// ============================================================================
+// Mapping and unmapping
+// ============================================================================
+
+/// Map `length` bytes of `inode` starting at file offset `offset`
+///
+/// With `addr` the mapping must go exactly there, as for MAP_FIXED;
+/// otherwise the first free range is used. Nothing is read until the pages
+/// are touched.
+pub fn map_file(
+    inode: Arc<dyn InodeOps>,
+    offset: u64,
+    length: usize,
+    protection: MemoryProtection,
+    shared: bool,
+    addr: Option<VirtAddr>,
+) -> Result<VirtAddr, MemoryError> {
+    map(inode, offset, length, protection, shared, true, addr)
+}
+
+fn map(
+    inode: Arc<dyn InodeOps>,
+    offset: u64,
+    length: usize,
+    protection: MemoryProtection,
+    shared: bool,
+    may_write: bool,
+    addr: Option<VirtAddr>,
+) -> Result<VirtAddr, MemoryError> {
+    if length == 0 || offset % PAGE_SIZE as u64 != 0 {
+        return Err(MemoryError::InvalidAddress);
+    }
+    let mm = get_memory_manager().ok_or(MemoryError::OutOfMemory)?;
+    let len = align_up(length, PAGE_SIZE);
+    let stat = inode.stat().map_err(|_| MemoryError::IoError)?;
+
+    // Private pages start out read-only on the shared page cache, whatever
+    // protection mprotect gives them later
+    let protection = MemoryProtection { copy_on_write: !shared, ..protection };
+
+    let mut table = MAPPINGS.lock();
+    let start = match addr {
+        Some(addr) if !addr.is_aligned(PAGE_SIZE as u64) => return Err(MemoryError::InvalidAddress),
+        Some(addr) => addr,
+        None => mm.find_free_virtual_space(len).ok_or(MemoryError::NoVirtualSpace)?,
+    };
+    mm.add_region(VirtualMemoryRegion::new(start, len, MemoryRegionType::FileMapping, protection))?;
+    table.regions.insert(start, FileRegion { inode, id: (stat.dev, stat.ino), offset, len, shared, may_write });
+    drop(table);
+
+    if shared {
+        start_flusher();
+    }
+    Ok(start)
+}
+
+/// Map an open descriptor of the global file table, checking its access
+/// mode against the requested protection
+pub fn map_fd(
+    fd: i32,
+    offset: u64,
+    length: usize,
+    protection: MemoryProtection,
+    shared: bool,
+    addr: Option<VirtAddr>,
+) -> Result<VirtAddr, MapError> {
+    if length == 0 || offset % PAGE_SIZE as u64 != 0 {
+        return Err(MapError::InvalidArgument);
+    }
+
+    let (inode, flags, _) = vfs::get_vfs().file(fd).map_err(|_| MapError::BadFd)?;
+    let stat = inode.stat().map_err(|_| MapError::BadFd)?;
+    if stat.inode_type != InodeType::File {
+        return Err(MapError::NoDevice);
+    }
+    if !flags.is_readable() || (shared && protection.writable && !flags.is_writable()) {
+        return Err(MapError::Access);
+    }
+
+    // hugetlbfs files are backed by huge pages rather than the page cache
+    if let Some(file) = inode.as_any().and_then(|any| any.downcast_ref::<HugeTlbFile>()) {
+        if offset % HUGE_PAGE_SIZE as u64 != 0 {
+            return Err(MapError::InvalidArgument);
+        }
+        file.extend(offset + align_up(length, HUGE_PAGE_SIZE) as u64);
+        return huge_page::map_pages(file.pages(), offset, length, protection, shared, addr).map_err(MapError::Memory);
+    }
+
+    let may_write = !shared || flags.is_writable();
+    map(inode, offset, length, protection, shared, may_write, addr).map_err(MapError::Memory)
+}
+
+/// Whether any file mapping overlaps `[addr, addr + length)`
+pub fn overlaps(addr: VirtAddr, length: usize) -> bool {
+    let end = addr + align_up(length, PAGE_SIZE) as u64;
+    MAPPINGS.lock().regions.iter().any(|(&start, file)| clip(start, file, addr, end).is_some())
+}
+
+/// Unmap `[addr, addr + length)` from the file mappings it covers
+///
+/// Dirty shared pages are written back first. Mappings only partly inside
+/// the range are split, keeping the rest mapped; if a writeback fails the
+/// pages are still unmapped and the first error is returned.
+pub fn unmap(addr: VirtAddr, length: usize) -> Result<(), MemoryError> {
+    let mm = get_memory_manager().ok_or(MemoryError::OutOfMemory)?;
+    let end = addr + align_up(length, PAGE_SIZE) as u64;
+    let mut table = MAPPINGS.lock();
+
+    let starts: Vec<VirtAddr> = table
+        .regions
+        .iter()
+        .filter(|(&start, file)| clip(start, file, addr, end).is_some())
+        .map(|(&start, _)| start)
+        .collect();
+
+    let mut result = Ok(());
+    for start in starts {
+        let Some(file) = table.regions.get(&start).cloned() else { continue };
+        let Some((lo, hi)) = clip(start, &file, addr, end) else { continue };
+        let region = mm.remove_region(start)?;
+        table.regions.remove(&start);
+
+        for page in pages(lo, hi) {
+            let released = table.release_page(mm, start, &file, page);
+            if result.is_ok() {
+                result = released;
+            }
+        }
+
+        // Keep whatever lies outside the range
+        let region_end = start + file.len as u64;
+        if lo > start {
+            let len = (lo - start) as usize;
+            mm.add_region(VirtualMemoryRegion { size: len, ..region.clone() })?;
+            table.regions.insert(start, FileRegion { len, ..file.clone() });
+        }
+        if hi < region_end {
+            let len = (region_end - hi) as usize;
+            mm.add_region(VirtualMemoryRegion { start: hi, size: len, ..region.clone() })?;
+            table.regions.insert(hi, FileRegion { offset: file.offset_of(start, Page::containing_address(hi)), len, ..file });
+        }
+    }
+    result
+}
+
+/// Split the mapping containing `addr` so that one starts there, as the
+/// memory manager splits its region
+pub(super) fn split(addr: VirtAddr) {
+    let mut table = MAPPINGS.lock();
+    let Some((&start, file)) = table.regions.range(..addr).next_back() else { return };
+    if start + file.len as u64 <= addr {
+        return;
+    }
+
+    let head = (addr - start) as usize;
+    let tail = FileRegion { offset: file.offset + head as u64, len: file.len - head, ..file.clone() };
+    if let Some(file) = table.regions.get_mut(&start) {
+        file.len = head;
+    }
+    table.regions.insert(addr, tail);
+}
+
+/// Whether the mapping containing `addr` may be made writable
+pub(super) fn may_write(addr: VirtAddr) -> bool {
+    let table = MAPPINGS.lock();
+    table
+        .regions
+        .range(..=addr)
+        .next_back()
+        .map_or(true, |(&start, file)| addr >= start + file.len as u64 || file.may_write)
+}
+
+/// Drop the pages in `[from, to)` for madvise, writing dirty shared pages
+/// back first; they are read from the file again on the next access
+///
+/// With `punch` the mapping must be shared; the pages are dropped without
+/// writeback and the file range they cover is zeroed instead.
+pub(super) fn discard(from: VirtAddr, to: VirtAddr, punch: bool) -> Result<(), MemoryError> {
+    let mm = get_memory_manager().ok_or(MemoryError::OutOfMemory)?;
+    let mut table = MAPPINGS.lock();
+    let overlapping: Vec<(VirtAddr, FileRegion)> = table
+        .regions
+        .iter()
+        .filter(|(&start, file)| clip(start, file, from, to).is_some())
+        .map(|(&start, file)| (start, file.clone()))
+        .collect();
+
+    if punch && overlapping.iter().any(|(_, file)| !file.shared) {
+        return Err(MemoryError::InvalidAddress);
+    }
+
+    for (start, file) in overlapping {
+        let Some((lo, hi)) = clip(start, &file, from, to) else { continue };
+        // Writeback would only be overwritten by the zeroes
+        let release_as = FileRegion { shared: file.shared && !punch, ..file.clone() };
+        for page in pages(lo, hi) {
+            table.release_page(mm, start, &release_as, page)?;
+        }
+
+        if punch {
+            let file_size = file.file_size()?;
+            let zeroes = [0u8; PAGE_SIZE];
+            for page in pages(lo, hi) {
+                let file_offset = file.offset_of(start, page);
+                let len = file_size.saturating_sub(file_offset).min(PAGE_SIZE as u64) as usize;
+                // Other mappings of the page see the hole too
+                if let Some(cached) = table.cache.get(&(file.id, file_offset / PAGE_SIZE as u64)) {
+                    unsafe { frame_bytes(cached.frame).fill(0) };
+                }
+                if len > 0 {
+                    file.inode.write_at(file_offset, &zeroes[..len]).map_err(|_| MemoryError::IoError)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write back the dirty shared pages in `[addr, addr + length)`, for msync;
+/// returns the number of pages written
+pub fn sync(addr: VirtAddr, length: usize) -> Result<usize, MemoryError> {
+    let mm = get_memory_manager().ok_or(MemoryError::OutOfMemory)?;
+    let end = addr + align_up(length, PAGE_SIZE) as u64;
+    MAPPINGS.lock().writeback(mm, addr, end)
+}
+
+/// Write back every dirty shared page
+pub fn sync_all() -> Result<usize, MemoryError> {
+    let mm = get_memory_manager().ok_or(MemoryError::OutOfMemory)?;
+    MAPPINGS.lock().writeback(mm, VirtAddr::zero(), VirtAddr::new(u64::MAX))
+}
+
+// ============================================================================
+// Page faults
+// ============================================================================
+
+/// Take a reference to the cached frame of a file page, reading it in
+/// if it isn't cached
+///
+/// The mapping table is not held while the frame is allocated and read; if
+/// another fault cached the page meanwhile, its frame is used instead.
+fn get_page(mm: &MemoryManager, file: &FileRegion, index: u64, file_size: u64) -> Result<PhysFrame, MemoryError> {
+    let key = (file.id, index);
+    if let Some(cached) = MAPPINGS.lock().cache.get_mut(&key) {
+        cached.mappers += 1;
+        return Ok(cached.frame);
+    }
+
+    let frame = mm.allocate_frame_or_reclaim()?;
+    if let Err(err) = read_page(&*file.inode, index * PAGE_SIZE as u64, file_size, frame) {
+        free_frame(mm, frame);
+        return Err(err);
+    }
+
+    let mut table = MAPPINGS.lock();
+    if let Some(cached) = table.cache.get_mut(&key) {
+        cached.mappers += 1;
+        let cached = cached.frame;
+        drop(table);
+        free_frame(mm, frame);
+        return Ok(cached);
+    }
+    table.cache.insert(key, CachedPage { frame, mappers: 1 });
+    Ok(frame)
+}
+
+/// Give a private mapping its own copy of the cached page it maps
+fn break_cow(mm: &MemoryManager, file: &FileRegion, page: Page, index: u64, protection: MemoryProtection) -> Result<(), MemoryError> {
+    let protection = MemoryProtection { copy_on_write: false, ..protection };
+    let key = (file.id, index);
+    let old_frame = mm
+        .translate_addr(page.start_address())
+        .map(PhysFrame::containing_address)
+        .ok_or(MemoryError::InvalidAddress)?;
+
+    let cached = MAPPINGS.lock().cache.get(&key).is_some_and(|cached| cached.frame == old_frame);
+    if !cached {
+        // Already a private copy, write-protected by mprotect since
+        let mut page_table_manager = mm.page_table_manager.lock();
+        let kept = page_table_manager.get_flags(page).unwrap_or(PageTableFlags::empty())
+            & (PageTableFlags::ACCESSED | PageTableFlags::DIRTY);
+        page_table_manager
+            .update_flags(page, protection.to_page_table_flags() | kept)
+            .map_err(|_| MemoryError::ProtectionFailed)?;
+        tlb::flush(page.start_address());
+        return Ok(());
+    }
+
+    // Our mapping keeps the cached frame alive while it's copied
+    let new_frame = mm.allocate_frame_or_reclaim()?;
+    unsafe { frame_bytes(new_frame).copy_from_slice(frame_bytes(old_frame)) };
+
+    let mut table = MAPPINGS.lock();
+    if mm.translate_addr(page.start_address()).map(PhysFrame::containing_address) != Some(old_frame) {
+        // Another thread of the process copied it first
+        drop(table);
+        free_frame(mm, new_frame);
+        return Ok(());
+    }
+    mm.page_table_manager.lock().unmap_page(page);
+    table.put_page(mm, key);
+    drop(table);
+
+    map_frame(mm, page, new_frame, protection.to_page_table_flags())
+}
+
+/// Resolve a fault in a `FileMapping` region: a missing page, or a write to
+/// a private page still on the page cache
+///
+/// The mapping table is only held to look things up, never while frames
+/// are allocated or the file is read.
+pub(super) fn handle_fault(
+    mm: &MemoryManager,
+    addr: VirtAddr,
+    region: &VirtualMemoryRegion,
+    is_write: bool,
+    is_present: bool,
+) -> Result<(), MemoryError> {
+    if is_write && !region.protection.writable {
+        return Err(MemoryError::WriteViolation);
+    }
+
+    let page = Page::containing_address(addr);
+    let file = MAPPINGS.lock().regions.get(&region.start).cloned().ok_or(MemoryError::RegionNotFound)?;
+    let file_offset = file.offset_of(region.start, page);
+    let index = file_offset / PAGE_SIZE as u64;
+
+    if is_present {
+        return break_cow(mm, &file, page, index, region.protection);
+    }
+
+    let file_size = file.file_size()?;
+    if file_offset >= file_size {
+        return Err(MemoryError::InvalidAddress);
+    }
+
+    let (frame, cached) = if !file.shared && is_write {
+        // Copy straight into a private frame rather than caching first
+        let frame = mm.allocate_frame_or_reclaim()?;
+        let table = MAPPINGS.lock();
+        match table.cache.get(&(file.id, index)) {
+            Some(cached) => unsafe { frame_bytes(frame).copy_from_slice(frame_bytes(cached.frame)) },
+            None => {
+                drop(table);
+                if let Err(err) = read_page(&*file.inode, file_offset, file_size, frame) {
+                    free_frame(mm, frame);
+                    return Err(err);
+                }
+            }
+        }
+        (frame, false)
+    } else {
+        (get_page(mm, &file, index, file_size)?, true)
+    };
+
+    let protection = MemoryProtection { copy_on_write: region.protection.copy_on_write && cached, ..region.protection };
+    map_frame(mm, page, frame, protection.to_page_table_flags()).or_else(|err| {
+        if cached {
+            MAPPINGS.lock().put_page(mm, (file.id, index));
+        } else {
+            free_frame(mm, frame);
+        }
+        // Another thread of the process faulted it in first
+        if mm.translate_addr(addr).is_some() { Ok(()) } else { Err(err) }
+    })
+}
+
+// ============================================================================
+// Flusher
+// ============================================================================
+
+static FLUSHER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Start the flusher once the first shared mapping exists
+fn start_flusher() {
+    if FLUSHER_STARTED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    if crate::process::thread::create_kernel_thread("flush", crate::process::Priority::Low, 16 * 1024, flusher)
+        .is_err()
+    {
+        FLUSHER_STARTED.store(false, Ordering::Release);
+    }
+}
+
+fn flusher() {
+    loop {
+        let _ = crate::process::timers::sleep_ns(WRITEBACK_INTERVAL_NS);
+        let _ = sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::ramfs::RamFsInode;
+
+    /// A one-page ramfs file filled with `byte`
+    fn file(byte: u8) -> Arc<dyn InodeOps> {
+        let vfs = vfs::get_vfs();
+        let inode = RamFsInode::new_file(vfs.alloc_dev(), vfs.alloc_ino(), 0o644);
+        inode.write_at(0, &[byte; PAGE_SIZE]).unwrap();
+        inode
+    }
+
+    /// Resolve a fault at `addr` as the page fault handler would
+    fn fault(mm: &MemoryManager, addr: VirtAddr, is_write: bool) {
+        let region = mm.find_region(addr).unwrap();
+        let is_present = mm.translate_addr(addr).is_some();
+        handle_fault(mm, addr, &region, is_write, is_present).unwrap();
+    }
+
+    #[test_case]
+    fn test_private_mapping_copies_on_write() {
+        let mm = get_memory_manager().expect("memory manager");
+        let inode = file(b'a');
+        let shared = map_file(Arc::clone(&inode), 0, PAGE_SIZE, MemoryProtection::USER_DATA, true, None).unwrap();
+        let private = map_file(Arc::clone(&inode), 0, PAGE_SIZE, MemoryProtection::USER_DATA, false, None).unwrap();
+
+        // Both read the one cached frame
+        fault(mm, shared, false);
+        fault(mm, private, false);
+        assert_eq!(mm.translate_addr(shared), mm.translate_addr(private));
+
+        // The first write gives the private mapping its own copy
+        fault(mm, private, true);
+        assert_ne!(mm.translate_addr(shared), mm.translate_addr(private));
+        unsafe {
+            assert_eq!(private.as_ptr::<u8>().read_volatile(), b'a');
+            private.as_mut_ptr::<u8>().write_volatile(b'b');
+            assert_eq!(shared.as_ptr::<u8>().read_volatile(), b'a');
+        }
+
+        // and the copy never reaches the file
+        assert_eq!(sync(private, PAGE_SIZE), Ok(0));
+        let mut byte = [0u8; 1];
+        inode.read_at(0, &mut byte).unwrap();
+        assert_eq!(byte[0], b'a');
+
+        unmap(private, PAGE_SIZE).unwrap();
+        unmap(shared, PAGE_SIZE).unwrap();
+    }
+
+    #[test_case]
+    fn test_msync_writes_back_dirty_pages() {
+        let mm = get_memory_manager().expect("memory manager");
+        let inode = file(b'a');
+        let shared = map_file(Arc::clone(&inode), 0, PAGE_SIZE, MemoryProtection::USER_DATA, true, None).unwrap();
+
+        fault(mm, shared, true);
+        assert_eq!(sync(shared, PAGE_SIZE), Ok(0));
+        unsafe { (shared + 100u64).as_mut_ptr::<u8>().write_volatile(b'z') };
+
+        // The store left the page dirty; msync writes it once
+        assert_eq!(sync(shared, PAGE_SIZE), Ok(1));
+        let mut bytes = [0u8; 2];
+        inode.read_at(99, &mut bytes).unwrap();
+        assert_eq!(bytes, [b'a', b'z']);
+        assert_eq!(sync(shared, PAGE_SIZE), Ok(0));
+
+        unmap(shared, PAGE_SIZE).unwrap();
+    }
+}
@@ -0,0 +1,684 @@
+//! Huge Pages
+//!
+//! 2 MiB pages come in two kinds:
+//!
+//! - hugetlb: MAP_HUGETLB mappings and mappings of files on hugetlbfs are
+//!   `HugeTlb` regions, mapped with huge pages only. Their pages come from a
+//!   pool set aside with `set_pool_size`, as /proc/sys/vm/nr_hugepages, and
+//!   belong to the file rather than to the mapping; a MAP_HUGETLB mapping
+//!   gets an unnamed file of its own. Private mappings of a file copy each
+//!   page from the file when it is first touched. hugetlb pages are never
+//!   swapped.
+//! - Transparent huge pages (THP): anonymous user memory is mapped with a
+//!   huge page wherever a whole aligned 2 MiB block of a region is mapped at
+//!   once, or faulted in while nothing else in the block is mapped.
+//!   khugepaged collapses blocks that were filled with small pages into huge
+//!   pages in the background. A huge page is split back into small pages
+//!   when part of it must be treated on its own: at a region boundary, for
+//!   copy-on-write after fork, or when madvise discards part of it. THP
+//!   blocks are not swapped out.
+//!
+//! Which regions get transparent huge pages is decided by the THP mode, as
+//! /sys/kernel/mm/transparent_hugepage/enabled, together with each region's
+//! MADV_HUGEPAGE or MADV_NOHUGEPAGE advice.
+//!
+//! Lock order: the hugetlb mapping table, then a file's pages, then the
+//! memory manager's locks.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use spin::Mutex;
+use x86_64::structures::paging::{Page, PageTableFlags, PhysFrame, Size2MiB};
+use x86_64::VirtAddr;
+
+use super::{
+    align_up, get_memory_manager, MemoryError, MemoryManager, MemoryProtection, MemoryRegionType, MemoryZone,
+    VirtualMemoryRegion, HUGE_PAGE_SIZE, PAGE_SIZE,
+};
+
+/// Small pages in a huge page
+const PAGES_PER_HUGE_PAGE: usize = HUGE_PAGE_SIZE / PAGE_SIZE;
+
+/// Interval between khugepaged passes
+const KHUGEPAGED_INTERVAL_NS: u64 = 10_000_000_000;
+
+/// Blocks khugepaged collapses per pass at most
+const KHUGEPAGED_BLOCKS_PER_PASS: usize = 8;
+
+/// System-wide THP mode
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThpMode {
+    /// Every anonymous region not advised MADV_NOHUGEPAGE
+    Always = 0,
+    /// Only regions advised MADV_HUGEPAGE
+    Madvise = 1,
+    Never = 2,
+}
+
+/// A region's madvise advice on transparent huge pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThpAdvice {
+    Default,
+    /// MADV_HUGEPAGE
+    Always,
+    /// MADV_NOHUGEPAGE
+    Never,
+}
+
+/// Huge page counters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HugePageStats {
+    /// Pages in the hugetlb pool, free or in use
+    pub pool_total: usize,
+    /// Free pages in the hugetlb pool
+    pub pool_free: usize,
+    /// Transparent huge pages mapped at map or fault time
+    pub thp_fault_alloc: u64,
+    /// Transparent huge pages khugepaged made from small pages
+    pub thp_collapse_alloc: u64,
+    /// Huge pages split into small pages
+    pub thp_split: u64,
+}
+
+static THP_MODE: AtomicU8 = AtomicU8::new(ThpMode::Always as u8);
+static THP_FAULT_ALLOC: AtomicU64 = AtomicU64::new(0);
+static THP_COLLAPSE_ALLOC: AtomicU64 = AtomicU64::new(0);
+static THP_SPLIT: AtomicU64 = AtomicU64::new(0);
+
+/// The current THP mode
+pub fn thp_mode() -> ThpMode {
+    match THP_MODE.load(Ordering::Relaxed) {
+        0 => ThpMode::Always,
+        1 => ThpMode::Madvise,
+        _ => ThpMode::Never,
+    }
+}
+
+/// Set the THP mode; huge pages already mapped stay
+pub fn set_thp_mode(mode: ThpMode) {
+    THP_MODE.store(mode as u8, Ordering::Relaxed);
+    if mode != ThpMode::Never {
+        start_khugepaged();
+    }
+}
+
+/// Huge page pool and THP counters
+pub fn stats() -> HugePageStats {
+    let pool = POOL.lock();
+    HugePageStats {
+        pool_total: pool.total,
+        pool_free: pool.free.len(),
+        thp_fault_alloc: THP_FAULT_ALLOC.load(Ordering::Relaxed),
+        thp_collapse_alloc: THP_COLLAPSE_ALLOC.load(Ordering::Relaxed),
+        thp_split: THP_SPLIT.load(Ordering::Relaxed),
+    }
+}
+
+/// Whether `region` should get transparent huge pages
+pub(super) fn thp_allowed(region: &VirtualMemoryRegion) -> bool {
+    let anonymous = matches!(
+        region.region_type,
+        MemoryRegionType::UserData | MemoryRegionType::UserHeap | MemoryRegionType::UserStack
+    );
+    let wanted = match thp_mode() {
+        ThpMode::Always => region.thp != ThpAdvice::Never,
+        ThpMode::Madvise => region.thp == ThpAdvice::Always,
+        ThpMode::Never => false,
+    };
+    anonymous && wanted && !region.protection.copy_on_write
+}
+
+/// Whether the whole 2 MiB block containing `addr` lies in `region`
+pub(super) fn block_in_region(region: &VirtualMemoryRegion, addr: VirtAddr) -> bool {
+    let block = addr.align_down(HUGE_PAGE_SIZE as u64);
+    block >= region.start && block + HUGE_PAGE_SIZE as u64 <= region.end()
+}
+
+/// Count a transparent huge page mapped when its region was mapped
+pub(super) fn count_fault_alloc() {
+    THP_FAULT_ALLOC.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A huge page's contents, through the direct map of physical memory
+unsafe fn frame_bytes<'a>(frame: PhysFrame<Size2MiB>) -> &'a mut [u8] {
+    core::slice::from_raw_parts_mut(super::phys_to_virt(frame.start_address()).as_mut_ptr(), HUGE_PAGE_SIZE)
+}
+
+/// The 2 MiB blocks overlapping `[start, end)`
+fn blocks(start: VirtAddr, end: VirtAddr) -> impl Iterator<Item = VirtAddr> {
+    let first = start.align_down(HUGE_PAGE_SIZE as u64).as_u64();
+    (first..end.as_u64()).step_by(HUGE_PAGE_SIZE).map(VirtAddr::new)
+}
+
+// ============================================================================
+// hugetlb Pool
+// ============================================================================
+
+struct Pool {
+    free: Vec<PhysFrame<Size2MiB>>,
+    /// Pages in the pool, free or in use
+    total: usize,
+    /// Size last asked for; pages in use past it are freed when released
+    target: usize,
+}
+
+static POOL: Mutex<Pool> = Mutex::new(Pool { free: Vec::new(), total: 0, target: 0 });
+
+/// Grow or shrink the hugetlb pool to `pages` huge pages, as far as free
+/// memory and pages in use allow; returns the new pool size
+pub fn set_pool_size(pages: usize) -> usize {
+    let Some(mm) = get_memory_manager() else { return 0 };
+    let mut pool = POOL.lock();
+    pool.target = pages;
+
+    while pool.total < pages {
+        let Some(frame) = mm.frame_allocator.lock().allocate_huge_frame() else { break };
+        pool.free.push(frame);
+        pool.total += 1;
+    }
+    while pool.total > pages {
+        let Some(frame) = pool.free.pop() else { break };
+        mm.frame_allocator.lock().deallocate_huge_frame(frame);
+        pool.total -= 1;
+    }
+    pool.total
+}
+
+fn take_page() -> Option<PhysFrame<Size2MiB>> {
+    POOL.lock().free.pop()
+}
+
+fn release_page(frame: PhysFrame<Size2MiB>) {
+    let mut pool = POOL.lock();
+    if pool.total > pool.target {
+        if let Some(mm) = get_memory_manager() {
+            mm.frame_allocator.lock().deallocate_huge_frame(frame);
+            pool.total -= 1;
+            return;
+        }
+    }
+    pool.free.push(frame);
+}
+
+// ============================================================================
+// hugetlb Files and Mappings
+// ============================================================================
+
+/// The huge pages of a hugetlbfs file, by page index
+///
+/// Pages go back to the pool when they are truncated away or when the last
+/// file or mapping holding them goes away.
+pub struct HugePages {
+    frames: Mutex<BTreeMap<u64, PhysFrame<Size2MiB>>>,
+}
+
+impl HugePages {
+    pub fn new() -> Arc<Self> {
+        Arc::new(HugePages { frames: Mutex::new(BTreeMap::new()) })
+    }
+
+    /// Pages in use
+    pub fn resident(&self) -> usize {
+        self.frames.lock().len()
+    }
+
+    /// Page `index`, taken from the pool on first use and filled from the
+    /// same page of `source`, or zeroed
+    fn get(&self, index: u64, source: Option<&HugePages>) -> Result<PhysFrame<Size2MiB>, MemoryError> {
+        let mut frames = self.frames.lock();
+        if let Some(&frame) = frames.get(&index) {
+            return Ok(frame);
+        }
+
+        let frame = take_page().ok_or(MemoryError::OutOfMemory)?;
+        let bytes = unsafe { frame_bytes(frame) };
+        match source.and_then(|source| source.frames.lock().get(&index).copied()) {
+            Some(original) => bytes.copy_from_slice(unsafe { frame_bytes(original) }),
+            None => bytes.fill(0),
+        }
+        frames.insert(index, frame);
+        Ok(frame)
+    }
+
+    /// Copy out the bytes at `offset`; pages never touched read as zero
+    pub fn read(&self, offset: u64, buf: &mut [u8]) {
+        let frames = self.frames.lock();
+        let mut done = 0;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let within = (pos % HUGE_PAGE_SIZE as u64) as usize;
+            let len = (HUGE_PAGE_SIZE - within).min(buf.len() - done);
+            match frames.get(&(pos / HUGE_PAGE_SIZE as u64)) {
+                Some(&frame) => buf[done..done + len].copy_from_slice(unsafe { &frame_bytes(frame)[within..within + len] }),
+                None => buf[done..done + len].fill(0),
+            }
+            done += len;
+        }
+    }
+
+    /// Drop the pages from index `keep` on, unmapping them from every
+    /// shared mapping first
+    pub fn truncate(self: &Arc<Self>, keep: u64) {
+        let table = MAPPINGS.lock();
+        if let Some(mm) = get_memory_manager() {
+            for (&start, mapping) in table.iter().filter(|(_, mapping)| Arc::ptr_eq(&mapping.pages, self)) {
+                let first = keep.saturating_sub(mapping.index);
+                let mut page_table_manager = mm.page_table_manager.lock();
+                for i in first..(mapping.len / HUGE_PAGE_SIZE) as u64 {
+                    page_table_manager.unmap_huge_page(Page::containing_address(start + i * HUGE_PAGE_SIZE as u64));
+                }
+                drop(page_table_manager);
+                crate::smp::tlb_shootdown(start, start + mapping.len as u64);
+            }
+        }
+
+        let dropped = self.frames.lock().split_off(&keep);
+        drop(table);
+        dropped.into_values().for_each(release_page);
+    }
+}
+
+impl Drop for HugePages {
+    fn drop(&mut self) {
+        for (_, frame) in core::mem::take(self.frames.get_mut()) {
+            release_page(frame);
+        }
+    }
+}
+
+/// The pages behind one hugetlb mapping
+#[derive(Clone)]
+struct HugeRegion {
+    pages: Arc<HugePages>,
+    /// For private mappings of a file, the file's pages
+    source: Option<Arc<HugePages>>,
+    /// Page index of the first page
+    index: u64,
+    /// Length in bytes, a multiple of the huge page size
+    len: usize,
+}
+
+/// hugetlb mappings by start address
+static MAPPINGS: Mutex<BTreeMap<VirtAddr, HugeRegion>> = Mutex::new(BTreeMap::new());
+
+fn map(
+    pages: Arc<HugePages>,
+    source: Option<Arc<HugePages>>,
+    index: u64,
+    len: usize,
+    protection: MemoryProtection,
+    addr: Option<VirtAddr>,
+) -> Result<VirtAddr, MemoryError> {
+    let mm = get_memory_manager().ok_or(MemoryError::OutOfMemory)?;
+
+    // Fail now rather than on a later fault if the pool can't back it
+    let count = (len / HUGE_PAGE_SIZE) as u64;
+    let missing = {
+        let frames = pages.frames.lock();
+        (index..index + count).filter(|index| !frames.contains_key(index)).count()
+    };
+    if missing > POOL.lock().free.len() {
+        return Err(MemoryError::OutOfMemory);
+    }
+
+    let mut table = MAPPINGS.lock();
+    let start = match addr {
+        Some(addr) if !addr.is_aligned(HUGE_PAGE_SIZE as u64) => return Err(MemoryError::InvalidAddress),
+        Some(addr) => addr,
+        None => mm
+            .find_free_virtual_space(len + HUGE_PAGE_SIZE)
+            .ok_or(MemoryError::NoVirtualSpace)?
+            .align_up(HUGE_PAGE_SIZE as u64),
+    };
+    mm.add_region(VirtualMemoryRegion::new(start, len, MemoryRegionType::HugeTlb, protection))?;
+    table.insert(start, HugeRegion { pages, source, index, len });
+    Ok(start)
+}
+
+/// Map `length` bytes of a hugetlbfs file's pages from byte `offset`,
+/// which must be huge page aligned
+pub fn map_pages(
+    pages: Arc<HugePages>,
+    offset: u64,
+    length: usize,
+    protection: MemoryProtection,
+    shared: bool,
+    addr: Option<VirtAddr>,
+) -> Result<VirtAddr, MemoryError> {
+    if length == 0 || offset % HUGE_PAGE_SIZE as u64 != 0 {
+        return Err(MemoryError::InvalidAddress);
+    }
+    let index = offset / HUGE_PAGE_SIZE as u64;
+    let len = align_up(length, HUGE_PAGE_SIZE);
+    if shared {
+        map(pages, None, index, len, protection, addr)
+    } else {
+        map(HugePages::new(), Some(pages), index, len, protection, addr)
+    }
+}
+
+/// Map `length` bytes of anonymous memory with huge pages, for MAP_HUGETLB
+pub fn map_anonymous(length: usize, protection: MemoryProtection, addr: Option<VirtAddr>) -> Result<VirtAddr, MemoryError> {
+    if length == 0 {
+        return Err(MemoryError::InvalidAddress);
+    }
+    map(HugePages::new(), None, 0, align_up(length, HUGE_PAGE_SIZE), protection, addr)
+}
+
+/// Whether any hugetlb mapping overlaps `[addr, addr + length)`
+pub fn overlaps(addr: VirtAddr, length: usize) -> bool {
+    let end = addr + align_up(length, PAGE_SIZE) as u64;
+    MAPPINGS.lock().range(..end).any(|(&start, mapping)| start + mapping.len as u64 > addr)
+}
+
+/// Unmap `[addr, addr + length)` from the hugetlb mappings it covers
+///
+/// The range must start on a huge page boundary and is rounded up to whole
+/// huge pages. Mappings only partly inside it keep the rest.
+pub fn unmap(addr: VirtAddr, length: usize) -> Result<(), MemoryError> {
+    if !addr.is_aligned(HUGE_PAGE_SIZE as u64) {
+        return Err(MemoryError::InvalidAddress);
+    }
+    let mm = get_memory_manager().ok_or(MemoryError::OutOfMemory)?;
+    let end = addr + align_up(length, HUGE_PAGE_SIZE) as u64;
+    let mut table = MAPPINGS.lock();
+
+    let overlapping: Vec<(VirtAddr, HugeRegion)> = table
+        .range(..end)
+        .filter(|(&start, mapping)| start + mapping.len as u64 > addr)
+        .map(|(&start, mapping)| (start, mapping.clone()))
+        .collect();
+
+    for (start, mapping) in overlapping {
+        let region = mm.remove_region(start)?;
+        table.remove(&start);
+
+        let region_end = start + mapping.len as u64;
+        let (lo, hi) = (start.max(addr), region_end.min(end));
+        let mut page_table_manager = mm.page_table_manager.lock();
+        for block in blocks(lo, hi) {
+            page_table_manager.unmap_huge_page(Page::containing_address(block));
+        }
+        drop(page_table_manager);
+
+        // Keep whatever lies outside the range
+        if lo > start {
+            let len = (lo - start) as usize;
+            mm.add_region(VirtualMemoryRegion { size: len, ..region.clone() })?;
+            table.insert(start, HugeRegion { len, ..mapping.clone() });
+        }
+        if hi < region_end {
+            let len = (region_end - hi) as usize;
+            let index = mapping.index + (hi - start) / HUGE_PAGE_SIZE as u64;
+            mm.add_region(VirtualMemoryRegion { start: hi, size: len, ..region })?;
+            table.insert(hi, HugeRegion { index, len, ..mapping });
+        }
+    }
+    drop(table);
+
+    crate::smp::tlb_shootdown(addr, end);
+    Ok(())
+}
+
+/// Split the mapping containing `addr`, which must be huge page aligned, so
+/// that one starts there, as the memory manager splits its region
+pub(super) fn split(addr: VirtAddr) -> Result<(), MemoryError> {
+    if !addr.is_aligned(HUGE_PAGE_SIZE as u64) {
+        return Err(MemoryError::InvalidAddress);
+    }
+    let mut table = MAPPINGS.lock();
+    let Some((&start, mapping)) = table.range(..addr).next_back() else { return Ok(()) };
+    if start + mapping.len as u64 <= addr {
+        return Ok(());
+    }
+
+    let head = (addr - start) as usize;
+    let tail = HugeRegion {
+        index: mapping.index + (head / HUGE_PAGE_SIZE) as u64,
+        len: mapping.len - head,
+        ..mapping.clone()
+    };
+    if let Some(mapping) = table.get_mut(&start) {
+        mapping.len = head;
+    }
+    table.insert(addr, tail);
+    Ok(())
+}
+
+/// Map the huge page containing `addr` in a `HugeTlb` region
+pub(super) fn handle_fault(mm: &MemoryManager, addr: VirtAddr, region: &VirtualMemoryRegion) -> Result<(), MemoryError> {
+    let table = MAPPINGS.lock();
+    let (&start, mapping) = table
+        .range(..=addr)
+        .next_back()
+        .filter(|(&start, mapping)| addr < start + mapping.len as u64)
+        .ok_or(MemoryError::InvalidAddress)?;
+
+    let page: Page<Size2MiB> = Page::containing_address(addr);
+    let index = mapping.index + (page.start_address() - start) / HUGE_PAGE_SIZE as u64;
+    let frame = mapping.pages.get(index, mapping.source.as_deref())?;
+
+    let mut page_table_manager = mm.page_table_manager.lock();
+    if page_table_manager.huge_frame(addr).is_some() {
+        // Another CPU got here first
+        return Ok(());
+    }
+    let mut frame_allocator = mm.frame_allocator.lock();
+    page_table_manager
+        .map_huge_page(page, frame, region.protection.to_page_table_flags(), &mut *frame_allocator)
+        .map_err(|_| MemoryError::MappingFailed)
+}
+
+// ============================================================================
+// Transparent Huge Pages
+// ============================================================================
+
+impl MemoryManager {
+    /// Fault in the 2 MiB block around `addr` as one transparent huge page,
+    /// if its region wants them and nothing in the block is mapped yet
+    pub(super) fn huge_fault(&self, addr: VirtAddr, region: &VirtualMemoryRegion) -> bool {
+        if !thp_allowed(region) || !block_in_region(region, addr) {
+            return false;
+        }
+        start_khugepaged();
+
+        // A huge page would hide pages of the block that are in swap
+        let block = addr.align_down(HUGE_PAGE_SIZE as u64);
+        let swapped = self
+            .swap_manager
+            .lock()
+            .swap_entries
+            .values()
+            .any(|entry| entry.page_addr.align_down(HUGE_PAGE_SIZE as u64) == block);
+        if swapped {
+            return false;
+        }
+
+        let Some(frame) = self.frame_allocator.lock().allocate_huge_frame() else { return false };
+        unsafe { frame_bytes(frame).fill(0) };
+
+        let mut page_table_manager = self.page_table_manager.lock();
+        let mut frame_allocator = self.frame_allocator.lock();
+        let flags = region.protection.to_page_table_flags();
+        // Fails if part of the block already has a page table
+        match page_table_manager.map_huge_page(Page::containing_address(block), frame, flags, &mut *frame_allocator) {
+            Ok(()) => {
+                count_fault_alloc();
+                true
+            }
+            Err(_) => {
+                frame_allocator.deallocate_huge_frame(frame);
+                false
+            }
+        }
+    }
+
+    /// Split the transparent huge pages overlapping `[start, end)` into
+    /// small pages
+    pub(super) fn split_huge_pages(&self, start: VirtAddr, end: VirtAddr) {
+        // hugetlb pages are never split
+        let candidates: Vec<VirtAddr> = blocks(start, end).filter(|&block| !overlaps(block, HUGE_PAGE_SIZE)).collect();
+        if candidates.is_empty() {
+            return;
+        }
+
+        let mut page_table_manager = self.page_table_manager.lock();
+        let mut frame_allocator = self.frame_allocator.lock();
+        for block in candidates {
+            if page_table_manager.huge_frame(block).is_some()
+                && page_table_manager.split_huge_page(Page::containing_address(block), &mut *frame_allocator).is_ok()
+            {
+                THP_SPLIT.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Replace the small pages of the block at `block` with one huge page
+    /// holding their contents; pages not present are zero
+    fn collapse_block(&self, block: VirtAddr) -> Result<(), MemoryError> {
+        let region = self
+            .find_region(block)
+            .filter(|region| thp_allowed(region) && block_in_region(region, block))
+            .ok_or(MemoryError::InvalidAddress)?;
+        let swapped = self
+            .swap_manager
+            .lock()
+            .swap_entries
+            .values()
+            .any(|entry| entry.page_addr.align_down(HUGE_PAGE_SIZE as u64) == block);
+        if swapped {
+            return Err(MemoryError::InvalidAddress);
+        }
+
+        let huge = self.frame_allocator.lock().allocate_huge_frame().ok_or(MemoryError::OutOfMemory)?;
+        let free_huge = |mm: &Self| mm.frame_allocator.lock().deallocate_huge_frame(huge);
+        let mut page_table_manager = self.page_table_manager.lock();
+
+        // Every page must be private to this region, and one at least present
+        let mut present = Vec::new();
+        for i in 0..PAGES_PER_HUGE_PAGE {
+            let addr = block + (i * PAGE_SIZE) as u64;
+            if page_table_manager.huge_frame(addr).is_some() {
+                drop(page_table_manager);
+                free_huge(self);
+                return Err(MemoryError::RegionOverlap);
+            }
+            if let Some(phys_addr) = page_table_manager.translate_addr(addr) {
+                if self.is_frame_shared(phys_addr) {
+                    drop(page_table_manager);
+                    free_huge(self);
+                    return Err(MemoryError::InvalidAddress);
+                }
+                present.push(i);
+            }
+        }
+        if present.is_empty() {
+            drop(page_table_manager);
+            free_huge(self);
+            return Err(MemoryError::InvalidAddress);
+        }
+
+        // Unmap first so nothing writes to the old pages while they are copied
+        let old: Vec<(usize, PhysFrame, PageTableFlags)> = present
+            .into_iter()
+            .filter_map(|i| {
+                let page = Page::containing_address(block + (i * PAGE_SIZE) as u64);
+                let flags = page_table_manager.get_flags(page)?;
+                page_table_manager.unmap_page(page).map(|frame| (i, frame, flags))
+            })
+            .collect();
+        crate::smp::tlb_shootdown(block, block + HUGE_PAGE_SIZE as u64);
+
+        let bytes = unsafe { frame_bytes(huge) };
+        bytes.fill(0);
+        for &(i, frame, _) in &old {
+            let small = unsafe { core::slice::from_raw_parts(super::phys_to_virt(frame.start_address()).as_ptr::<u8>(), PAGE_SIZE) };
+            bytes[i * PAGE_SIZE..(i + 1) * PAGE_SIZE].copy_from_slice(small);
+        }
+
+        let flags = region.protection.to_page_table_flags();
+        let mut frame_allocator = self.frame_allocator.lock();
+        match page_table_manager.collapse_huge_page(Page::containing_address(block), huge, flags) {
+            Ok(table) => {
+                for (_, frame, _) in old {
+                    frame_allocator.deallocate_frame(frame, MemoryZone::from_address(frame.start_address()));
+                }
+                if let Some(table) = table {
+                    frame_allocator.deallocate_frame(table, MemoryZone::from_address(table.start_address()));
+                }
+                THP_COLLAPSE_ALLOC.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(_) => {
+                // Put the small pages back as they were
+                for (i, frame, flags) in old {
+                    let page = Page::containing_address(block + (i * PAGE_SIZE) as u64);
+                    let _ = page_table_manager.map_page(page, frame, flags, &mut *frame_allocator);
+                }
+                frame_allocator.deallocate_huge_frame(huge);
+                Err(MemoryError::MappingFailed)
+            }
+        }
+    }
+
+    /// Collapse up to `budget` blocks of small pages in regions that want
+    /// transparent huge pages; returns the number collapsed
+    pub fn collapse_huge_pages(&self, budget: usize) -> usize {
+        let candidates: Vec<VirtAddr> = {
+            let regions = self.regions.read();
+            regions
+                .values()
+                .filter(|region| thp_allowed(region))
+                .flat_map(|region| {
+                    let first = region.start.align_up(HUGE_PAGE_SIZE as u64);
+                    let last = region.end().align_down(HUGE_PAGE_SIZE as u64);
+                    blocks(first, last.max(first))
+                })
+                .collect()
+        };
+
+        let mut collapsed = 0;
+        for block in candidates {
+            if collapsed == budget {
+                break;
+            }
+            if self.collapse_block(block).is_ok() {
+                collapsed += 1;
+            }
+        }
+        collapsed
+    }
+}
+
+// ============================================================================
+// khugepaged
+// ============================================================================
+
+static KHUGEPAGED_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Start khugepaged once transparent huge pages are in use
+pub(super) fn start_khugepaged() {
+    if KHUGEPAGED_STARTED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    if crate::process::thread::create_kernel_thread("khugepaged", crate::process::Priority::Low, 16 * 1024, khugepaged)
+        .is_err()
+    {
+        KHUGEPAGED_STARTED.store(false, Ordering::Release);
+    }
+}
+
+fn khugepaged() {
+    loop {
+        let _ = crate::process::timers::sleep_ns(KHUGEPAGED_INTERVAL_NS);
+        if thp_mode() == ThpMode::Never {
+            continue;
+        }
+        if let Some(mm) = get_memory_manager() {
+            mm.collapse_huge_pages(KHUGEPAGED_BLOCKS_PER_PASS);
+        }
+    }
+}
@@ -0,0 +1,440 @@
+//! NUMA Topology and Memory Policy
+//!
+//! Nodes come from the ACPI SRAT, which gives the proximity domain of each
+//! processor and memory range; the SLIT gives the distances between them.
+//! Domains are numbered 0.. in order as nodes. Without an SRAT the machine
+//! is one node holding all memory.
+//!
+//! The frame allocator keeps the zones of each node apart. Where a page
+//! comes from is decided by a memory policy: the region's own, set with
+//! mbind, or else the allocating process's, set with set_mempolicy.
+//!
+//! - `Local` and `Default` take memory from the node of the CPU allocating.
+//! - `Preferred` takes it from one node while that has free memory.
+//! - `Bind` never takes it from outside a set of nodes.
+//! - `Interleave` spreads a region's pages over a set of nodes by address.
+//!
+//! Every policy but `Bind` falls back to the other nodes, nearest first.
+//! Pages already placed can be migrated: resident anonymous pages are
+//! copied to a frame on the new node and remapped. Frames shared after
+//! fork, page cache pages and hugetlb pages stay where they are.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::RwLock;
+use x86_64::structures::paging::{Page, Size2MiB};
+use x86_64::{PhysAddr, VirtAddr};
+
+use super::{MemoryError, MemoryManager, MemoryRegionType, VirtualMemoryRegion, HUGE_PAGE_SIZE, PAGE_SIZE};
+use crate::process::Pid;
+
+/// Most nodes supported; node sets are bitmasks in a u64
+pub const MAX_NUMNODES: usize = 64;
+
+/// SLIT distance of a node to itself
+pub const LOCAL_DISTANCE: u8 = 10;
+
+/// Distance between different nodes when there is no SLIT
+pub const REMOTE_DISTANCE: u8 = 20;
+
+struct Topology {
+    nodes: usize,
+    /// Physical ranges `[start, end)` by start, with their node
+    ranges: BTreeMap<u64, (u64, usize)>,
+    /// Node of each APIC ID the SRAT lists
+    cpus: BTreeMap<u32, usize>,
+    /// `distances[from * nodes + to]`, empty without a SLIT
+    distances: Vec<u8>,
+}
+
+static TOPOLOGY: RwLock<Topology> =
+    RwLock::new(Topology { nodes: 1, ranges: BTreeMap::new(), cpus: BTreeMap::new(), distances: Vec::new() });
+
+/// Build the node topology from the ACPI SRAT and SLIT
+///
+/// Called before the frame allocator is set up, which splits its zones by
+/// node.
+pub fn init() {
+    let Some(srat) = crate::acpi::srat().or_else(|| crate::acpi::parse_srat().ok()) else { return };
+
+    // Dense node IDs for the proximity domains, in domain order
+    let mut domains: Vec<u32> = srat
+        .memory
+        .iter()
+        .map(|memory| memory.proximity_domain)
+        .chain(srat.processors.iter().map(|cpu| cpu.proximity_domain))
+        .collect();
+    domains.sort_unstable();
+    domains.dedup();
+    domains.truncate(MAX_NUMNODES);
+    if domains.is_empty() {
+        return;
+    }
+    let node_of = |domain: u32| domains.binary_search(&domain).ok();
+
+    let mut topology = TOPOLOGY.write();
+    topology.nodes = domains.len();
+    for memory in &srat.memory {
+        if let Some(node) = node_of(memory.proximity_domain) {
+            topology.ranges.insert(memory.base_address, (memory.base_address + memory.length, node));
+        }
+    }
+    for cpu in &srat.processors {
+        if let Some(node) = node_of(cpu.proximity_domain) {
+            topology.cpus.insert(cpu.apic_id, node);
+        }
+    }
+
+    if let Some(slit) = crate::acpi::slit().or_else(|| crate::acpi::parse_slit().ok()) {
+        let nodes = domains.len();
+        let mut distances = Vec::with_capacity(nodes * nodes);
+        for &from in &domains {
+            for &to in &domains {
+                let default = if from == to { LOCAL_DISTANCE } else { REMOTE_DISTANCE };
+                distances.push(slit.distance(from as usize, to as usize).unwrap_or(default));
+            }
+        }
+        topology.distances = distances;
+    }
+}
+
+/// Number of nodes
+pub fn node_count() -> usize {
+    TOPOLOGY.read().nodes
+}
+
+/// Mask of all nodes
+pub fn online_mask() -> u64 {
+    match node_count() {
+        MAX_NUMNODES => u64::MAX,
+        nodes => (1 << nodes) - 1,
+    }
+}
+
+/// Node holding the physical address `addr`; memory the SRAT doesn't list
+/// counts as node 0
+pub fn node_of_addr(addr: PhysAddr) -> usize {
+    let addr = addr.as_u64();
+    let topology = TOPOLOGY.read();
+    match topology.ranges.range(..=addr).next_back() {
+        Some((_, &(end, node))) if addr < end => node,
+        _ => 0,
+    }
+}
+
+/// The parts of `[start, end)` on each node, in address order
+pub fn node_spans(start: u64, end: u64) -> Vec<(u64, u64, usize)> {
+    let topology = TOPOLOGY.read();
+    let mut spans = Vec::new();
+    let mut current = start;
+    while current < end {
+        let (span_end, node) = match topology.ranges.range(..=current).next_back() {
+            Some((_, &(range_end, node))) if current < range_end => (range_end.min(end), node),
+            // Up to the next listed range, as node 0
+            _ => (topology.ranges.range(current + 1..).next().map_or(end, |(&next, _)| next.min(end)), 0),
+        };
+        spans.push((current, span_end, node));
+        current = span_end;
+    }
+    spans
+}
+
+/// Node of the CPU we are running on
+pub fn local_node() -> usize {
+    let topology = TOPOLOGY.read();
+    if topology.nodes == 1 {
+        return 0;
+    }
+    topology.cpus.get(&crate::smp::get_apic_id()).copied().unwrap_or(0)
+}
+
+/// Relative distance from node `from` to node `to`
+pub fn distance(from: usize, to: usize) -> u8 {
+    let topology = TOPOLOGY.read();
+    let default = if from == to { LOCAL_DISTANCE } else { REMOTE_DISTANCE };
+    topology.distances.get(from * topology.nodes + to).copied().unwrap_or(default)
+}
+
+/// Whether `mask` is a non-empty set of existing nodes
+pub fn valid_mask(mask: u64) -> bool {
+    mask != 0 && mask & !online_mask() == 0
+}
+
+// ============================================================================
+// Memory Policy
+// ============================================================================
+
+/// Where a process's or region's pages are allocated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemPolicy {
+    /// The process's policy for a region; local allocation for a process
+    Default,
+    /// The node of the CPU allocating
+    Local,
+    /// This node while it has memory
+    Preferred(usize),
+    /// Only these nodes
+    Bind(u64),
+    /// Page by page over these nodes
+    Interleave(u64),
+}
+
+/// Policies of processes that set one
+static TASK_POLICIES: RwLock<BTreeMap<Pid, MemPolicy>> = RwLock::new(BTreeMap::new());
+
+/// The policy of process `pid`
+pub fn task_policy(pid: Pid) -> MemPolicy {
+    TASK_POLICIES.read().get(&pid).copied().unwrap_or(MemPolicy::Default)
+}
+
+/// Set the policy of process `pid`
+pub fn set_task_policy(pid: Pid, policy: MemPolicy) {
+    let mut table = TASK_POLICIES.write();
+    if policy == MemPolicy::Default {
+        table.remove(&pid);
+    } else {
+        table.insert(pid, policy);
+    }
+}
+
+/// Give a new child its parent's policy
+pub fn inherit(parent: Pid, child: Pid) {
+    let mut table = TASK_POLICIES.write();
+    if let Some(&policy) = table.get(&parent) {
+        table.insert(child, policy);
+    }
+}
+
+/// Forget the policy of an exiting process
+pub fn exit_process(pid: Pid) {
+    TASK_POLICIES.write().remove(&pid);
+}
+
+/// Nodes one allocation may use, in the order to try them
+#[derive(Debug, Clone, Copy)]
+pub struct Placement {
+    /// Node to try first
+    pub first: usize,
+    /// Nodes allowed at all
+    pub allowed: u64,
+}
+
+impl Placement {
+    /// The local node, then the rest nearest first
+    pub fn local() -> Self {
+        Placement { first: local_node(), allowed: online_mask() }
+    }
+
+    /// Node `node` only
+    pub fn on(node: usize) -> Self {
+        Placement { first: node, allowed: 1 << node }
+    }
+
+    /// `first` if allowed, then the other allowed nodes nearest to it first
+    pub fn nodes(&self) -> impl Iterator<Item = usize> {
+        let nodes = node_count();
+        let mut others = [0usize; MAX_NUMNODES];
+        let mut count = 0;
+        for node in (0..nodes).filter(|&node| node != self.first && self.allowed & (1 << node) != 0) {
+            others[count] = node;
+            count += 1;
+        }
+        let first = self.first;
+        others[..count].sort_unstable_by_key(|&node| distance(first, node));
+
+        let first_allowed = first < nodes && self.allowed & (1 << first) != 0;
+        first_allowed.then_some(first).into_iter().chain(others.into_iter().take(count))
+    }
+}
+
+/// The `n`th node of `mask`, counting from 0 and wrapping around
+fn nth_node(mask: u64, n: u64) -> usize {
+    let mut mask = mask;
+    for _ in 0..n % mask.count_ones().max(1) as u64 {
+        mask &= mask - 1;
+    }
+    mask.trailing_zeros() as usize
+}
+
+/// The node in `mask` nearest to `node`
+fn nearest_in(mask: u64, node: usize) -> usize {
+    (0..node_count())
+        .filter(|&candidate| mask & (1 << candidate) != 0)
+        .min_by_key(|&candidate| distance(node, candidate))
+        .unwrap_or(node)
+}
+
+/// Where to allocate the page at `addr` of a region with policy `policy`
+pub fn placement(policy: MemPolicy, addr: VirtAddr) -> Placement {
+    let policy = match policy {
+        MemPolicy::Default => task_policy(crate::process::current_pid()),
+        policy => policy,
+    };
+    match policy {
+        MemPolicy::Default | MemPolicy::Local => Placement::local(),
+        MemPolicy::Preferred(node) => Placement { first: node, allowed: online_mask() },
+        MemPolicy::Bind(mask) => Placement { first: nearest_in(mask, local_node()), allowed: mask },
+        MemPolicy::Interleave(mask) => {
+            Placement { first: nth_node(mask, addr.as_u64() / PAGE_SIZE as u64), allowed: online_mask() }
+        }
+    }
+}
+
+/// Where to allocate the page at `addr` of `region`
+pub fn placement_for(region: &VirtualMemoryRegion, addr: VirtAddr) -> Placement {
+    placement(region.mempolicy, addr)
+}
+
+/// Whether a page on `node` satisfies `policy` as mbind with MPOL_MF_MOVE
+/// would leave it
+fn satisfies(policy: MemPolicy, node: usize) -> bool {
+    match policy {
+        MemPolicy::Default | MemPolicy::Local => true,
+        MemPolicy::Preferred(preferred) => node == preferred,
+        MemPolicy::Bind(mask) | MemPolicy::Interleave(mask) => mask & (1 << node) != 0,
+    }
+}
+
+/// Where migrate_pages sends a page on `node`: the node at the same
+/// position in `new` as `node` has in `old`
+pub fn remap_node(node: usize, old: u64, new: u64) -> Option<usize> {
+    if old & (1 << node) == 0 || new == 0 {
+        return None;
+    }
+    let position = (old & ((1 << node) - 1)).count_ones();
+    Some(nth_node(new, position as u64))
+}
+
+// ============================================================================
+// Page Migration
+// ============================================================================
+
+impl MemoryManager {
+    /// Node of the page resident at `addr`
+    pub fn page_node(&self, addr: VirtAddr) -> Option<usize> {
+        self.translate_addr(addr).map(node_of_addr)
+    }
+
+    /// Move the page resident at `addr` to a frame on `node`, returning the
+    /// node it is on afterwards
+    ///
+    /// Only private anonymous pages can move; the whole huge page moves if
+    /// one maps `addr`.
+    pub fn move_page(&self, addr: VirtAddr, node: usize) -> Result<usize, MemoryError> {
+        let region = self
+            .find_region(addr)
+            .filter(|region| region.protection.user_accessible && !region.protection.guard_page)
+            .ok_or(MemoryError::InvalidAddress)?;
+        if node >= node_count() {
+            return Err(MemoryError::InvalidAddress);
+        }
+        // Page cache pages and the hugetlb pool stay where they are
+        if matches!(region.region_type, MemoryRegionType::FileMapping | MemoryRegionType::HugeTlb) {
+            return Err(MemoryError::Busy);
+        }
+
+        let mut page_table_manager = self.page_table_manager.lock();
+        if let Some(old) = page_table_manager.huge_frame(addr) {
+            if node_of_addr(old.start_address()) == node {
+                return Ok(node);
+            }
+            let flags = page_table_manager.get_flags(Page::containing_address(addr)).ok_or(MemoryError::InvalidAddress)?;
+            let mut frame_allocator = self.frame_allocator.lock();
+            let new = frame_allocator.allocate_huge_frame_placed(Placement::on(node)).ok_or(MemoryError::OutOfMemory)?;
+
+            let page = Page::<Size2MiB>::containing_address(addr);
+            page_table_manager.unmap_huge_page(page);
+            crate::smp::tlb_shootdown(page.start_address(), page.start_address() + HUGE_PAGE_SIZE as u64);
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    super::phys_to_virt(old.start_address()).as_ptr::<u8>(),
+                    super::phys_to_virt(new.start_address()).as_mut_ptr::<u8>(),
+                    HUGE_PAGE_SIZE,
+                );
+            }
+            page_table_manager
+                .map_huge_page(page, new, flags, &mut *frame_allocator)
+                .map_err(|_| MemoryError::MappingFailed)?;
+            frame_allocator.deallocate_huge_frame(old);
+            return Ok(node);
+        }
+
+        let old = page_table_manager.translate_addr(addr).ok_or(MemoryError::RegionNotFound)?;
+        if node_of_addr(old) == node {
+            return Ok(node);
+        }
+        // The other side of a fork maps it too
+        if self.is_frame_shared(old) {
+            return Err(MemoryError::Busy);
+        }
+
+        let page = Page::containing_address(addr);
+        let flags = page_table_manager.get_flags(page).ok_or(MemoryError::InvalidAddress)?;
+        let mut frame_allocator = self.frame_allocator.lock();
+        let new = frame_allocator.allocate_frames_placed(Placement::on(node), 0).ok_or(MemoryError::OutOfMemory)?;
+
+        // Unmap first so nothing writes to the old frame during the copy
+        let old = page_table_manager.unmap_page(page).ok_or(MemoryError::InvalidAddress)?;
+        crate::smp::tlb_shootdown(page.start_address(), page.start_address() + PAGE_SIZE as u64);
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                super::phys_to_virt(old.start_address()).as_ptr::<u8>(),
+                super::phys_to_virt(new.start_address()).as_mut_ptr::<u8>(),
+                PAGE_SIZE,
+            );
+        }
+        page_table_manager
+            .map_page(page, new, flags, &mut *frame_allocator)
+            .map_err(|_| MemoryError::MappingFailed)?;
+        frame_allocator.deallocate_frame(old, super::MemoryZone::from_address(old.start_address()));
+        Ok(node)
+    }
+
+    /// Move the resident pages of `[start, end)` to the node `target` picks
+    /// from their current node and address; returns how many pages could
+    /// not be moved
+    fn migrate_range(&self, start: VirtAddr, end: VirtAddr, target: impl Fn(usize, VirtAddr) -> Option<usize>) -> usize {
+        let mut failed = 0;
+        let mut addr = start;
+        while addr < end {
+            let step = if self.page_table_manager.lock().huge_frame(addr).is_some() {
+                HUGE_PAGE_SIZE as u64 - (addr.as_u64() % HUGE_PAGE_SIZE as u64)
+            } else {
+                PAGE_SIZE as u64
+            };
+            if let Some(node) = self.page_node(addr).and_then(|current| target(current, addr)) {
+                if self.move_page(addr, node).is_err() {
+                    failed += 1;
+                }
+            }
+            addr += step;
+        }
+        failed
+    }
+
+    /// Move the resident pages of `[start, end)` that `policy` wouldn't
+    /// have put where they are, for mbind with MPOL_MF_MOVE
+    pub fn migrate_to_policy(&self, start: VirtAddr, end: VirtAddr, policy: MemPolicy) -> usize {
+        self.migrate_range(start, end, |current, addr| {
+            (!satisfies(policy, current)).then(|| placement(policy, addr).first)
+        })
+    }
+
+    /// Move every user page on a node in `old` to the matching node in
+    /// `new`, for migrate_pages
+    pub fn migrate_nodes(&self, old: u64, new: u64) -> usize {
+        let regions: Vec<(VirtAddr, VirtAddr)> = self
+            .regions
+            .read()
+            .values()
+            .filter(|region| region.protection.user_accessible && !region.protection.guard_page)
+            .map(|region| (region.start, region.end()))
+            .collect();
+        regions
+            .into_iter()
+            .map(|(start, end)| {
+                self.migrate_range(start, end, |current, _| remap_node(current, old, new).filter(|&node| node != current))
+            })
+            .sum()
+    }
+}
@@ -0,0 +1,284 @@
+//! Out-of-Memory Killer
+//!
+//! When a page can't be found even after reclaim, the kernel kills the
+//! process whose death frees the most memory. Each candidate gets a badness
+//! score: its resident and swapped-out pages, shifted by its
+//! `oom_score_adj` in thousandths of all memory. The highest score loses.
+//!
+//! `oom_score_adj` runs from -1000, which exempts a process, to 1000, which
+//! makes it the first to go. It is set through `/proc/<pid>/oom_score_adj`
+//! or prctl, inherited across fork, and only root may lower it. The kernel
+//! and init are never chosen.
+//!
+//! The victim gets SIGKILL and its anonymous memory is released right away,
+//! so the allocation that ran out can be retried. File and hugetlb mappings
+//! are left to the normal exit path, since the allocation may come from
+//! the page cache with its locks held.
+//!
+//! A memory cgroup that can't reclaim below its `memory.max` has the same
+//! choice made among its own members only.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::RwLock;
+use x86_64::structures::paging::Page;
+use x86_64::VirtAddr;
+
+use super::{MemoryManager, MemoryRegionType, MemoryZone, VirtualMemoryRegion, HUGE_PAGE_SIZE, PAGE_SIZE};
+use crate::process::{Pid, ProcessState};
+
+/// Adjustment that exempts a process from the OOM killer
+pub const OOM_SCORE_ADJ_MIN: i16 = -1000;
+
+/// Adjustment that makes a process the preferred victim
+pub const OOM_SCORE_ADJ_MAX: i16 = 1000;
+
+/// 4 KiB frames in a huge page
+const HUGE_PAGE_FRAMES: usize = HUGE_PAGE_SIZE / PAGE_SIZE;
+
+/// Errors from changing `oom_score_adj`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdjError {
+    /// Outside `OOM_SCORE_ADJ_MIN..=OOM_SCORE_ADJ_MAX`
+    Invalid,
+    /// Lowering the adjustment needs root
+    PermissionDenied,
+}
+
+/// Adjustments of processes that set one
+static SCORE_ADJ: RwLock<BTreeMap<Pid, i16>> = RwLock::new(BTreeMap::new());
+
+/// Set while a victim is being chosen, so allocations made by the killer
+/// itself don't start another round
+static KILLING: AtomicBool = AtomicBool::new(false);
+
+/// Processes killed so far
+static KILLS: AtomicUsize = AtomicUsize::new(0);
+
+/// The `oom_score_adj` of process `pid`
+pub fn score_adj(pid: Pid) -> i16 {
+    SCORE_ADJ.read().get(&pid).copied().unwrap_or(0)
+}
+
+/// Set the `oom_score_adj` of process `pid`; `privileged` allows lowering it
+///
+/// Returns the previous value.
+pub fn set_score_adj(pid: Pid, adj: i32, privileged: bool) -> Result<i16, AdjError> {
+    if !(OOM_SCORE_ADJ_MIN as i32..=OOM_SCORE_ADJ_MAX as i32).contains(&adj) {
+        return Err(AdjError::Invalid);
+    }
+    let adj = adj as i16;
+
+    let mut table = SCORE_ADJ.write();
+    let old = table.get(&pid).copied().unwrap_or(0);
+    if adj < old && !privileged {
+        return Err(AdjError::PermissionDenied);
+    }
+    if adj == 0 {
+        table.remove(&pid);
+    } else {
+        table.insert(pid, adj);
+    }
+    Ok(old)
+}
+
+/// Give a new child its parent's adjustment
+pub fn inherit(parent: Pid, child: Pid) {
+    let mut table = SCORE_ADJ.write();
+    if let Some(&adj) = table.get(&parent) {
+        table.insert(child, adj);
+    }
+}
+
+/// Forget the adjustment of an exiting process
+pub fn exit_process(pid: Pid) {
+    SCORE_ADJ.write().remove(&pid);
+}
+
+/// Number of processes the OOM killer has killed
+pub fn kill_count() -> usize {
+    KILLS.load(Ordering::Relaxed)
+}
+
+/// Memory charged to one process, in pages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Pages mapped in memory
+    pub rss: usize,
+    /// Pages out in swap
+    pub swap: usize,
+}
+
+/// Memory charged to process `pid`
+pub fn usage(mm: &MemoryManager, pid: Pid) -> Usage {
+    let owned: Vec<(u64, u64)> = mm
+        .regions
+        .read()
+        .values()
+        .filter(|region| region.owner == pid)
+        .map(|region| (region.start.as_u64(), region.end().as_u64()))
+        .collect();
+    if owned.is_empty() {
+        return Usage::default();
+    }
+
+    let page_table_manager = mm.page_table_manager.lock();
+    let rss = owned
+        .iter()
+        .flat_map(|&(start, end)| (start..end).step_by(PAGE_SIZE))
+        .filter(|&addr| page_table_manager.translate_addr(VirtAddr::new(addr)).is_some())
+        .count();
+    drop(page_table_manager);
+
+    let swap = mm
+        .swap_manager
+        .lock()
+        .swap_entries
+        .values()
+        .filter(|entry| owned.iter().any(|&(start, end)| (start..end).contains(&entry.page_addr.as_u64())))
+        .count();
+
+    Usage { rss, swap }
+}
+
+/// Pages of RAM and swap the badness adjustment is a fraction of
+fn total_pages(mm: &MemoryManager) -> usize {
+    let ram: usize = mm.frame_allocator.lock().get_zone_stats().iter().map(|stats| stats.total_frames).sum();
+    ram + super::swap::totals().0 as usize
+}
+
+/// Badness of a process using `usage` with adjustment `adj`, out of
+/// `total` pages; `None` if it may not be killed
+pub fn badness(pid: Pid, usage: Usage, adj: i16, total: usize) -> Option<u64> {
+    if pid <= 1 || adj == OOM_SCORE_ADJ_MIN {
+        return None;
+    }
+    let points = (usage.rss + usage.swap) as i64 + adj as i64 * total as i64 / 1000;
+    // Anything that may be killed scores at least 1, so it can be chosen
+    Some(points.max(1) as u64)
+}
+
+/// The score shown in `/proc/<pid>/oom_score`, from 0 to 2000
+pub fn oom_score(mm: &MemoryManager, pid: Pid) -> u64 {
+    let total = total_pages(mm).max(1);
+    badness(pid, usage(mm, pid), score_adj(pid), total)
+        .map_or(0, |points| (points * 1000 / total as u64).min(2000))
+}
+
+/// Unmap and free the anonymous memory of process `pid`
+///
+/// Frames still shared with another process after fork only lose a
+/// reference. Returns the number of frames freed.
+fn release_process(mm: &MemoryManager, pid: Pid) -> usize {
+    let owned: Vec<VirtualMemoryRegion> = {
+        let mut regions = mm.regions.write();
+        let starts: Vec<_> = regions
+            .values()
+            .filter(|region| region.owner == pid)
+            .filter(|region| !matches!(region.region_type, MemoryRegionType::FileMapping | MemoryRegionType::HugeTlb))
+            .map(|region| region.start)
+            .collect();
+        starts.iter().filter_map(|start| regions.remove(start)).collect()
+    };
+
+    let mut freed = 0;
+    for region in &owned {
+        let mut page_table_manager = mm.page_table_manager.lock();
+        for page in region.pages() {
+            let addr = page.start_address();
+            if page_table_manager.huge_frame(addr).is_some() {
+                if let Some(frame) = page_table_manager.unmap_huge_page(Page::containing_address(addr)) {
+                    mm.frame_allocator.lock().deallocate_huge_frame(frame);
+                    freed += HUGE_PAGE_FRAMES;
+                }
+                continue;
+            }
+            let Some(phys_addr) = page_table_manager.translate_addr(addr) else { continue };
+            if mm.is_frame_shared(phys_addr) {
+                page_table_manager.unmap_page(page);
+                mm.decrement_frame_refcount(phys_addr);
+            } else if let Some(frame) = page_table_manager.unmap_page(page) {
+                mm.frame_allocator.lock().deallocate_frame(frame, MemoryZone::from_address(frame.start_address()));
+                freed += 1;
+            }
+        }
+        drop(page_table_manager);
+
+        // Swapped-out pages die with the process too
+        let mut swap_manager = mm.swap_manager.lock();
+        let slots: Vec<_> = swap_manager
+            .swap_entries
+            .iter()
+            .filter(|(_, entry)| region.contains(entry.page_addr))
+            .map(|(slot, _)| *slot)
+            .collect();
+        for slot in slots {
+            swap_manager.deallocate_slot(slot);
+        }
+    }
+    freed
+}
+
+/// Kill the process with the highest badness to free memory
+///
+/// Called when an allocation has failed even after reclaim; `reason` says
+/// which. Returns the victim, or `None` if nothing could be killed, in
+/// which case the allocation has to fail.
+pub fn out_of_memory(mm: &MemoryManager, reason: &str) -> Option<Pid> {
+    if KILLING.swap(true, Ordering::Acquire) {
+        return None;
+    }
+    let victim = select_and_kill(mm, None, reason);
+    KILLING.store(false, Ordering::Release);
+    victim
+}
+
+/// Kill the process among `candidates` with the highest badness
+///
+/// Called when a memory cgroup is at its limit even after reclaim, with the
+/// processes of the group.
+pub fn out_of_memory_in(mm: &MemoryManager, candidates: &[Pid], reason: &str) -> Option<Pid> {
+    if KILLING.swap(true, Ordering::Acquire) {
+        return None;
+    }
+    let victim = select_and_kill(mm, Some(candidates), reason);
+    KILLING.store(false, Ordering::Release);
+    victim
+}
+
+fn select_and_kill(mm: &MemoryManager, candidates: Option<&[Pid]>, reason: &str) -> Option<Pid> {
+    let process_manager = crate::process::get_process_manager();
+    let total = total_pages(mm).max(1);
+
+    let (victim, name, usage, adj, _) = process_manager
+        .list_processes()
+        .into_iter()
+        .filter(|(pid, _, _, _)| candidates.map_or(true, |candidates| candidates.contains(pid)))
+        .filter(|(_, _, state, _)| !matches!(state, ProcessState::Zombie | ProcessState::Terminated))
+        .filter_map(|(pid, name, _, _)| {
+            let usage = usage(mm, pid);
+            let adj = score_adj(pid);
+            badness(pid, usage, adj, total).map(|points| (pid, name, usage, adj, points))
+        })
+        .max_by_key(|&(pid, _, _, _, points)| (points, pid))?;
+
+    log_error!(
+        "oom",
+        "Out of memory ({}): Killed process {} ({}) rss:{}kB swap:{}kB oom_score_adj:{}",
+        reason,
+        victim,
+        name,
+        usage.rss * PAGE_SIZE / 1024,
+        usage.swap * PAGE_SIZE / 1024,
+        adj
+    );
+
+    let freed = release_process(mm, victim);
+    if let Err(err) = crate::process::integration::InterruptIntegration::deliver_signal(victim, 9) {
+        log_warn!("oom", "Could not deliver SIGKILL to process {}: {}", victim, err);
+    }
+    KILLS.fetch_add(1, Ordering::Relaxed);
+    log_info!("oom", "Reaped process {}, freed {}kB", victim, freed * PAGE_SIZE / 1024);
+    Some(victim)
+}
@@ -0,0 +1,607 @@
+//! Slab Allocator
+//!
+//! The kernel heap allocator. Small allocations come from object caches in
+//! the style of SLUB: each cache hands out objects of one size from slabs,
+//! blocks of pages from the buddy allocator carved into equal objects. Every
+//! cache keeps a small per-CPU stack of free objects, so most allocations
+//! and frees touch only the current CPU's lock and never the cache's slab
+//! lists, which are refilled and drained in batches.
+//!
+//! Generic allocations use the `kmalloc-N` size classes. Hot kernel objects
+//! have named caches of their own: an allocation whose layout is exactly a
+//! named cache's is served from it, so a `Box` or `Arc` of the object lands
+//! there without any change to the code holding it. As with SLUB cache
+//! aliasing, anything else of the same size and alignment shares the
+//! cache. The set of named caches is fixed, so a free always finds the
+//! cache its allocation came from.
+//!
+//! Allocations larger than a page size class take buddy pages directly.
+//! Before the memory manager is up, and whenever its frame allocator is
+//! busy (the frame allocator itself allocates while holding its lock), the
+//! early linked-list heap serves the allocation instead. Frees find their
+//! way back by address: anything inside the early heap goes back to it.
+//! Pages freed while the frame allocator is busy wait on a deferred list.
+
+use alloc::vec::Vec;
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::{align_of, size_of};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use linked_list_allocator::LockedHeap;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::PhysFrame;
+use x86_64::PhysAddr;
+
+use super::{numa, MemoryManager, MemoryZone, MAX_ORDER, PAGE_SIZE};
+use crate::performance::{CacheAligned, MAX_CPUS};
+
+/// Free objects each CPU keeps per cache
+const CPU_CACHE_SIZE: usize = 32;
+
+/// Objects moved between a CPU's stack and the slabs at once
+const BATCH: usize = CPU_CACHE_SIZE / 2;
+
+/// Completely free slabs a cache keeps before giving pages back
+const MIN_FREE_SLABS: usize = 2;
+
+/// Fewest objects worth a slab; bigger objects get bigger slabs
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+
+/// Largest slab, as a buddy order
+const MAX_SLAB_ORDER: usize = 3;
+
+/// Header at the start of every slab
+struct Slab {
+    /// First free object
+    free: *mut FreeObject,
+    /// Objects handed out
+    inuse: usize,
+    /// Neighbours on the cache's partial list
+    next: *mut Slab,
+    prev: *mut Slab,
+}
+
+/// A free object, linked through its own memory
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// A CPU's stack of free objects of one cache
+struct CpuCache {
+    objects: [*mut u8; CPU_CACHE_SIZE],
+    count: usize,
+}
+
+// Objects are plain memory; they belong to whichever CPU holds the lock
+unsafe impl Send for CpuCache {}
+
+impl CpuCache {
+    const fn new() -> Self {
+        Self { objects: [ptr::null_mut(); CPU_CACHE_SIZE], count: 0 }
+    }
+
+    fn pop(&mut self) -> Option<*mut u8> {
+        if self.count == 0 {
+            return None;
+        }
+        self.count -= 1;
+        Some(self.objects[self.count])
+    }
+
+    fn push(&mut self, object: *mut u8) -> bool {
+        if self.count == CPU_CACHE_SIZE {
+            return false;
+        }
+        self.objects[self.count] = object;
+        self.count += 1;
+        true
+    }
+}
+
+/// A cache's slabs that have free objects
+///
+/// Full slabs are on no list; a free into one puts it back.
+struct CacheNode {
+    partial: *mut Slab,
+    nr_partial: usize,
+    nr_full: usize,
+    /// Slabs on the partial list with no object in use
+    nr_free: usize,
+}
+
+unsafe impl Send for CacheNode {}
+
+impl CacheNode {
+    unsafe fn link(&mut self, slab: *mut Slab) {
+        (*slab).prev = ptr::null_mut();
+        (*slab).next = self.partial;
+        if !self.partial.is_null() {
+            (*self.partial).prev = slab;
+        }
+        self.partial = slab;
+        self.nr_partial += 1;
+    }
+
+    unsafe fn unlink(&mut self, slab: *mut Slab) {
+        if (*slab).prev.is_null() {
+            self.partial = (*slab).next;
+        } else {
+            (*(*slab).prev).next = (*slab).next;
+        }
+        if !(*slab).next.is_null() {
+            (*(*slab).next).prev = (*slab).prev;
+        }
+        self.nr_partial -= 1;
+    }
+}
+
+/// An object cache
+pub struct KmemCache {
+    name: &'static str,
+    /// Layout a named cache serves exactly
+    layout: (usize, usize),
+    /// Object size, including padding to `align`
+    size: usize,
+    align: usize,
+    /// Buddy order of each slab
+    order: usize,
+    /// Offset of the first object from the slab start
+    offset: usize,
+    /// Objects per slab
+    objects: usize,
+    cpu: [CacheAligned<Mutex<CpuCache>>; MAX_CPUS],
+    node: Mutex<CacheNode>,
+    allocations: AtomicU64,
+    frees: AtomicU64,
+    cpu_hits: AtomicU64,
+    slabs: AtomicUsize,
+}
+
+/// std's `ArcInner`, for the layout of an `Arc` allocation
+#[repr(C)]
+struct ArcInner<T> {
+    strong: AtomicUsize,
+    weak: AtomicUsize,
+    data: T,
+}
+
+impl KmemCache {
+    /// A cache of `size`-byte objects aligned to `align`
+    pub const fn new(name: &'static str, size: usize, align: usize) -> Self {
+        const CPU_INIT: CacheAligned<Mutex<CpuCache>> = CacheAligned::new(Mutex::new(CpuCache::new()));
+
+        let (requested_size, requested_align) = (size, align);
+        let align = if align < size_of::<FreeObject>() { size_of::<FreeObject>() } else { align };
+        let size = (size + align - 1) & !(align - 1);
+        let offset = (size_of::<Slab>() + align - 1) & !(align - 1);
+
+        let mut order = 0;
+        while order < MAX_SLAB_ORDER && ((PAGE_SIZE << order) - offset) / size < MIN_OBJECTS_PER_SLAB {
+            order += 1;
+        }
+
+        Self {
+            name,
+            layout: (requested_size, requested_align),
+            size,
+            align,
+            order,
+            offset,
+            objects: ((PAGE_SIZE << order) - offset) / size,
+            cpu: [CPU_INIT; MAX_CPUS],
+            node: Mutex::new(CacheNode { partial: ptr::null_mut(), nr_partial: 0, nr_full: 0, nr_free: 0 }),
+            allocations: AtomicU64::new(0),
+            frees: AtomicU64::new(0),
+            cpu_hits: AtomicU64::new(0),
+            slabs: AtomicUsize::new(0),
+        }
+    }
+
+    /// A cache for `Box<T>` allocations
+    pub const fn for_type<T>(name: &'static str) -> Self {
+        Self::new(name, size_of::<T>(), align_of::<T>())
+    }
+
+    /// A cache for `Arc<T>` allocations, which carry the reference counts
+    pub const fn for_arc<T>(name: &'static str) -> Self {
+        Self::new(name, size_of::<ArcInner<T>>(), align_of::<ArcInner<T>>())
+    }
+
+    /// Whether the cache can hold an allocation of `layout`
+    fn fits(&self, layout: Layout) -> bool {
+        layout.size() <= self.size && layout.align() <= self.align
+    }
+
+    /// Whether `layout` is exactly the layout the cache was made for
+    fn serves(&self, layout: Layout) -> bool {
+        self.objects > 0 && self.layout == (layout.size(), layout.align())
+    }
+
+    fn slab_size(&self) -> usize {
+        PAGE_SIZE << self.order
+    }
+
+    /// Allocate an object; null when no memory is left
+    pub fn alloc(&self) -> *mut u8 {
+        interrupts::without_interrupts(|| {
+            let cpu = cpu_index();
+            if let Some(object) = self.cpu[cpu].lock().pop() {
+                self.cpu_hits.fetch_add(1, Ordering::Relaxed);
+                self.allocations.fetch_add(1, Ordering::Relaxed);
+                return object;
+            }
+
+            // Refill from the slabs; no lock is held while a new slab's
+            // pages are allocated, as that may allocate in turn
+            let mut batch = [ptr::null_mut(); BATCH];
+            let taken = self.take_objects(&mut batch);
+            if taken == 0 {
+                return ptr::null_mut();
+            }
+
+            let mut overflow = [ptr::null_mut(); BATCH];
+            let mut spilled = 0;
+            {
+                let mut cache = self.cpu[cpu].lock();
+                for &object in &batch[1..taken] {
+                    if !cache.push(object) {
+                        overflow[spilled] = object;
+                        spilled += 1;
+                    }
+                }
+            }
+            self.return_objects(&overflow[..spilled]);
+
+            self.allocations.fetch_add(1, Ordering::Relaxed);
+            batch[0]
+        })
+    }
+
+    /// Free an object this cache allocated
+    ///
+    /// # Safety
+    ///
+    /// `object` must have come from `alloc` on this cache and not be in use.
+    pub unsafe fn free(&self, object: *mut u8) {
+        interrupts::without_interrupts(|| {
+            self.frees.fetch_add(1, Ordering::Relaxed);
+            let cpu = cpu_index();
+            let mut batch = [ptr::null_mut(); BATCH + 1];
+            {
+                let mut cache = self.cpu[cpu].lock();
+                if cache.push(object) {
+                    return;
+                }
+                // Full: send half of the stack and this object to the slabs
+                for slot in batch.iter_mut().take(BATCH) {
+                    *slot = cache.pop().unwrap_or(ptr::null_mut());
+                }
+            }
+            batch[BATCH] = object;
+            self.return_objects(&batch);
+        })
+    }
+
+    /// Take up to `out.len()` objects from the slabs, growing the cache if
+    /// it has none free
+    fn take_objects(&self, out: &mut [*mut u8]) -> usize {
+        // Objects too big for the largest slab
+        if self.objects == 0 {
+            return 0;
+        }
+        loop {
+            let taken = {
+                let mut node = self.node.lock();
+                let mut taken = 0;
+                while taken < out.len() && !node.partial.is_null() {
+                    let slab = node.partial;
+                    unsafe {
+                        if (*slab).inuse == 0 {
+                            node.nr_free -= 1;
+                        }
+                        while taken < out.len() && !(*slab).free.is_null() {
+                            out[taken] = (*slab).free as *mut u8;
+                            (*slab).free = (*(*slab).free).next;
+                            (*slab).inuse += 1;
+                            taken += 1;
+                        }
+                        if (*slab).free.is_null() {
+                            node.unlink(slab);
+                            node.nr_full += 1;
+                        }
+                    }
+                }
+                taken
+            };
+            if taken > 0 {
+                return taken;
+            }
+
+            let Some(slab) = self.new_slab() else { return 0 };
+            let mut node = self.node.lock();
+            unsafe { node.link(slab) };
+            node.nr_free += 1;
+        }
+    }
+
+    /// Put objects back in their slabs, giving surplus free slabs back to
+    /// the buddy allocator
+    fn return_objects(&self, objects: &[*mut u8]) {
+        let mut released = [ptr::null_mut::<Slab>(); BATCH + 1];
+        let mut count = 0;
+        {
+            let mut node = self.node.lock();
+            for &object in objects.iter().filter(|object| !object.is_null()) {
+                let slab = (object as usize & !(self.slab_size() - 1)) as *mut Slab;
+                unsafe {
+                    let was_full = (*slab).free.is_null();
+                    let free = object as *mut FreeObject;
+                    (*free).next = (*slab).free;
+                    (*slab).free = free;
+                    (*slab).inuse -= 1;
+                    if was_full {
+                        node.nr_full -= 1;
+                        node.link(slab);
+                    }
+                    if (*slab).inuse == 0 {
+                        if node.nr_free >= MIN_FREE_SLABS {
+                            node.unlink(slab);
+                            released[count] = slab;
+                            count += 1;
+                        } else {
+                            node.nr_free += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        for &slab in &released[..count] {
+            self.slabs.fetch_sub(1, Ordering::Relaxed);
+            free_pages(slab as usize, self.order);
+        }
+    }
+
+    /// Carve a fresh slab out of buddy pages
+    fn new_slab(&self) -> Option<*mut Slab> {
+        let base = alloc_pages(self.order)?;
+        let slab = base as *mut Slab;
+        let mut free: *mut FreeObject = ptr::null_mut();
+        unsafe {
+            for index in (0..self.objects).rev() {
+                let object = (base + self.offset + index * self.size) as *mut FreeObject;
+                object.write(FreeObject { next: free });
+                free = object;
+            }
+            slab.write(Slab { free, inuse: 0, next: ptr::null_mut(), prev: ptr::null_mut() });
+        }
+        self.slabs.fetch_add(1, Ordering::Relaxed);
+        Some(slab)
+    }
+
+    /// Statistics of the cache
+    pub fn stats(&self) -> SlabStats {
+        let allocations = self.allocations.load(Ordering::Relaxed);
+        let frees = self.frees.load(Ordering::Relaxed);
+        let slabs = self.slabs.load(Ordering::Relaxed);
+        SlabStats {
+            name: self.name,
+            object_size: self.size,
+            objects_per_slab: self.objects,
+            slab_size: self.slab_size(),
+            active_objects: allocations.saturating_sub(frees) as usize,
+            total_objects: slabs * self.objects,
+            slabs,
+            allocations,
+            frees,
+            cpu_cache_hits: self.cpu_hits.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Statistics of one cache, as /proc/slabinfo gives them
+#[derive(Debug, Clone)]
+pub struct SlabStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    pub slab_size: usize,
+    /// Objects handed out and not yet freed
+    pub active_objects: usize,
+    /// Objects the cache's slabs hold
+    pub total_objects: usize,
+    pub slabs: usize,
+    pub allocations: u64,
+    pub frees: u64,
+    /// Allocations served from a per-CPU stack
+    pub cpu_cache_hits: u64,
+}
+
+// ============================================================================
+// Caches
+// ============================================================================
+
+/// The generic size classes
+static KMALLOC_CACHES: [KmemCache; 12] = [
+    KmemCache::new("kmalloc-8", 8, 8),
+    KmemCache::new("kmalloc-16", 16, 16),
+    KmemCache::new("kmalloc-32", 32, 32),
+    KmemCache::new("kmalloc-64", 64, 64),
+    KmemCache::new("kmalloc-96", 96, 32),
+    KmemCache::new("kmalloc-128", 128, 128),
+    KmemCache::new("kmalloc-192", 192, 64),
+    KmemCache::new("kmalloc-256", 256, 256),
+    KmemCache::new("kmalloc-512", 512, 512),
+    KmemCache::new("kmalloc-1k", 1024, 1024),
+    KmemCache::new("kmalloc-2k", 2048, 2048),
+    KmemCache::new("kmalloc-4k", 4096, 4096),
+];
+
+/// Process control blocks, which the process table boxes
+pub static TASK_STRUCT_CACHE: KmemCache = KmemCache::for_type::<crate::process::ProcessControlBlock>("task_struct");
+
+/// ramfs inodes
+pub static INODE_CACHE: KmemCache = KmemCache::for_arc::<crate::vfs::ramfs::RamFsInode>("ramfs_inode_cache");
+
+/// Network packet buffers of the default size
+pub static SKBUFF_CACHE: KmemCache =
+    KmemCache::new("skbuff_data_cache", crate::net::buffer::DEFAULT_BUFFER_SIZE, 1);
+
+/// Caches that serve their exact layout ahead of the size classes
+static NAMED_CACHES: [&KmemCache; 3] = [&TASK_STRUCT_CACHE, &INODE_CACHE, &SKBUFF_CACHE];
+
+/// The cache serving allocations of `layout`, if any
+fn cache_for(layout: Layout) -> Option<&'static KmemCache> {
+    NAMED_CACHES
+        .iter()
+        .copied()
+        .find(|cache| cache.serves(layout))
+        .or_else(|| KMALLOC_CACHES.iter().find(|cache| cache.fits(layout)))
+}
+
+/// Statistics of every cache, size classes first
+pub fn stats() -> Vec<SlabStats> {
+    KMALLOC_CACHES.iter().chain(NAMED_CACHES.iter().copied()).map(KmemCache::stats).collect()
+}
+
+// ============================================================================
+// Page Supply
+// ============================================================================
+
+/// The memory manager whose frame allocator supplies slabs, once it exists
+static MEMORY_MANAGER: AtomicPtr<MemoryManager> = AtomicPtr::new(ptr::null_mut());
+
+/// Where physical memory is mapped
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// Set once slabs can be allocated
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Page blocks waiting for the frame allocator, linked through themselves
+struct DeferredPages {
+    next: *mut DeferredPages,
+    order: usize,
+}
+
+struct DeferredList(*mut DeferredPages);
+
+unsafe impl Send for DeferredList {}
+
+static DEFERRED: Mutex<DeferredList> = Mutex::new(DeferredList(ptr::null_mut()));
+
+/// Start taking slabs from `mm`'s frame allocator
+pub fn init(mm: &'static MemoryManager, physical_memory_offset: u64) {
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset, Ordering::Relaxed);
+    MEMORY_MANAGER.store(mm as *const MemoryManager as *mut MemoryManager, Ordering::Release);
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Index of the current CPU in the per-CPU arrays
+fn cpu_index() -> usize {
+    crate::smp::get_apic_id() as usize % MAX_CPUS
+}
+
+/// Allocate a block of `1 << order` pages, returning its virtual address
+///
+/// Fails rather than waits when the frame allocator is busy, as it is when
+/// it allocates itself.
+fn alloc_pages(order: usize) -> Option<usize> {
+    let mm = unsafe { MEMORY_MANAGER.load(Ordering::Acquire).as_ref() }?;
+    let frame = {
+        let mut frame_allocator = mm.frame_allocator.try_lock()?;
+        drain_deferred(&mut frame_allocator);
+        frame_allocator.allocate_frames_placed(numa::Placement::local(), order)?
+    };
+    Some((frame.start_address().as_u64() + PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed)) as usize)
+}
+
+/// Give a block from `alloc_pages` back, or defer it while the frame
+/// allocator is busy
+fn free_pages(addr: usize, order: usize) {
+    let mm = unsafe { MEMORY_MANAGER.load(Ordering::Acquire).as_ref() };
+    if let Some(mut frame_allocator) = mm.and_then(|mm| mm.frame_allocator.try_lock()) {
+        drain_deferred(&mut frame_allocator);
+        release(&mut frame_allocator, addr, order);
+        return;
+    }
+
+    let block = addr as *mut DeferredPages;
+    interrupts::without_interrupts(|| {
+        let mut deferred = DEFERRED.lock();
+        unsafe { block.write(DeferredPages { next: deferred.0, order }) };
+        deferred.0 = block;
+    });
+}
+
+fn release(frame_allocator: &mut super::PhysicalFrameAllocator, addr: usize, order: usize) {
+    let phys = PhysAddr::new(addr as u64 - PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed));
+    frame_allocator.deallocate_frames(PhysFrame::containing_address(phys), MemoryZone::from_address(phys), order);
+}
+
+fn drain_deferred(frame_allocator: &mut super::PhysicalFrameAllocator) {
+    let mut block = interrupts::without_interrupts(|| core::mem::replace(&mut DEFERRED.lock().0, ptr::null_mut()));
+    while !block.is_null() {
+        let DeferredPages { next, order } = unsafe { block.read() };
+        release(frame_allocator, block as usize, order);
+        block = next;
+    }
+}
+
+/// Buddy order of a page allocation of `layout`, if one can hold it
+fn page_order(layout: Layout) -> Option<usize> {
+    let pages = layout.size().div_ceil(PAGE_SIZE).next_power_of_two();
+    let order = pages.trailing_zeros() as usize;
+    (order <= MAX_ORDER && layout.align() <= PAGE_SIZE << order).then_some(order)
+}
+
+// ============================================================================
+// Global Allocator
+// ============================================================================
+
+/// The kernel's global allocator: slab caches over the buddy allocator,
+/// with the early heap underneath
+pub struct SlabAllocator {
+    heap: LockedHeap,
+}
+
+impl SlabAllocator {
+    pub const fn new() -> Self {
+        Self { heap: LockedHeap::empty() }
+    }
+
+    /// The early heap, which serves everything until `init`
+    pub fn heap(&self) -> &LockedHeap {
+        &self.heap
+    }
+
+    fn in_heap(ptr: *mut u8) -> bool {
+        let (start, end) = crate::memory_basic::early_heap_range();
+        (start..end).contains(&(ptr as usize))
+    }
+}
+
+unsafe impl GlobalAlloc for SlabAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if ENABLED.load(Ordering::Acquire) {
+            let object = match cache_for(layout) {
+                Some(cache) => cache.alloc(),
+                None => page_order(layout).and_then(alloc_pages).map_or(ptr::null_mut(), |addr| addr as *mut u8),
+            };
+            if !object.is_null() {
+                return object;
+            }
+        }
+        self.heap.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if Self::in_heap(ptr) {
+            self.heap.dealloc(ptr, layout);
+        } else if let Some(cache) = cache_for(layout) {
+            cache.free(ptr);
+        } else if let Some(order) = page_order(layout) {
+            free_pages(ptr as usize, order);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Stack Guard Pages and Growth
+//!
+//! Kernel stacks have a fixed size and an unmapped guard page below them, so
+//! running off the bottom faults instead of overwriting whatever was
+//! allocated next to the stack. The CPU can't push the page fault frame onto
+//! the exhausted stack either, so this escalates to a double fault; that runs
+//! on its own IST stack and uses `overflowed_stack` to report the thread.
+//! Kernel guard pages are also kept in a small lock-free table, so fault
+//! handlers can recognise them without touching the region table, whose
+//! lock the faulting code may hold.
+//!
+//! User stacks grow instead (MAP_GROWSDOWN). A fault just below a user stack
+//! extends it down to the faulting page. The stack must stay within the
+//! owner's RLIMIT_STACK and keep `STACK_GUARD_GAP` clear of the mapping
+//! below it; otherwise the fault is a guard page violation. The main stack
+//! starts small at the top of the layout. Other stacks are placed below the
+//! room it may grow into.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use x86_64::structures::paging::Page;
+use x86_64::VirtAddr;
+
+use super::{
+    align_down, align_up, get_memory_manager, MemoryError, MemoryManager, MemoryProtection, MemoryRegionType,
+    VirtualMemoryRegion, PAGE_SIZE, USER_SPACE_START,
+};
+use crate::process::rlimit::{self, RLIMIT_STACK};
+use crate::process::Pid;
+
+/// Unmapped space a growing stack keeps above the mapping below it, as
+/// Linux's default `stack_guard_gap`
+pub const STACK_GUARD_GAP: u64 = 256 * PAGE_SIZE as u64;
+
+/// Initial size of a process's main stack
+pub const INITIAL_STACK_SIZE: usize = 128 * 1024;
+
+/// Most room kept free for the main stack to grow into, whatever
+/// RLIMIT_STACK says; user space is small
+const MAX_STACK_RESERVE: u64 = 128 * 1024 * 1024;
+
+/// Most kernel guard pages `is_kernel_guard` knows about; the region table
+/// still covers any beyond that
+const MAX_KERNEL_GUARDS: usize = 256;
+
+/// Start addresses of kernel guard pages, 0 marking a free slot
+static KERNEL_GUARDS: [AtomicU64; MAX_KERNEL_GUARDS] = [const { AtomicU64::new(0) }; MAX_KERNEL_GUARDS];
+
+/// Record `page` as a kernel guard page for `is_kernel_guard`
+pub(super) fn register_kernel_guard(page: VirtAddr) {
+    for slot in &KERNEL_GUARDS {
+        if slot.compare_exchange(0, page.as_u64(), Ordering::AcqRel, Ordering::Relaxed).is_ok() {
+            return;
+        }
+    }
+}
+
+/// Whether `addr` is in a kernel guard page; takes no lock
+pub fn is_kernel_guard(addr: VirtAddr) -> bool {
+    let page = addr.align_down(PAGE_SIZE as u64).as_u64();
+    page != 0 && KERNEL_GUARDS.iter().any(|slot| slot.load(Ordering::Acquire) == page)
+}
+
+/// Whether faults below `region` grow it
+fn grows_down(region: &VirtualMemoryRegion) -> bool {
+    region.region_type == MemoryRegionType::UserStack && region.protection.user_accessible
+}
+
+/// Room below the stack top of process `pid` that other mappings leave to
+/// its main stack
+pub fn reserve(pid: Pid) -> u64 {
+    let limit = rlimit::get(pid, RLIMIT_STACK).map_or(MAX_STACK_RESERVE, |limit| limit.cur);
+    align_up(limit.min(MAX_STACK_RESERVE) as usize, PAGE_SIZE) as u64 + STACK_GUARD_GAP
+}
+
+/// Map the main stack of process `pid` right below `stack_top` and return
+/// its bottom
+///
+/// The first `INITIAL_STACK_SIZE` bytes are mapped up front for the
+/// arguments and environment; the rest is faulted in as it grows.
+pub fn map_main_stack(pid: Pid, stack_top: u64) -> Result<VirtAddr, MemoryError> {
+    let mm = get_memory_manager().ok_or(MemoryError::OutOfMemory)?;
+    let start = mm
+        .find_free_virtual_space_near(INITIAL_STACK_SIZE, VirtAddr::new(stack_top), true)
+        .ok_or(MemoryError::NoVirtualSpace)?;
+
+    let mut region =
+        VirtualMemoryRegion::new(start, INITIAL_STACK_SIZE, MemoryRegionType::UserStack, MemoryProtection::USER_DATA);
+    region.owner = pid;
+    mm.map_region(&mut region)?;
+    mm.add_region(region)?;
+    Ok(start)
+}
+
+/// Map a MAP_GROWSDOWN stack of `length` bytes, at `addr` if given, and
+/// return its start
+///
+/// Its pages are faulted in on first touch like other anonymous memory.
+pub fn map_growsdown(length: usize, protection: MemoryProtection, addr: Option<VirtAddr>) -> Result<VirtAddr, MemoryError> {
+    if length == 0 {
+        return Err(MemoryError::InvalidAddress);
+    }
+    let mm = get_memory_manager().ok_or(MemoryError::OutOfMemory)?;
+    let len = align_up(length, PAGE_SIZE);
+    let start = match addr {
+        Some(addr) if !addr.is_aligned(PAGE_SIZE as u64) => return Err(MemoryError::InvalidAddress),
+        Some(addr) => addr,
+        None => mm.find_free_virtual_space(len).ok_or(MemoryError::NoVirtualSpace)?,
+    };
+    mm.add_region(VirtualMemoryRegion::new(start, len, MemoryRegionType::UserStack, protection))?;
+    Ok(start)
+}
+
+/// Grow the user stack right above `addr`, which no region covers, down to
+/// the page holding it
+///
+/// Fails with `InvalidAddress` if no growable stack is right above, and with
+/// `GuardPageViolation` if growing would exceed the owner's RLIMIT_STACK or
+/// come within `STACK_GUARD_GAP` of the region below.
+pub(super) fn grow(mm: &MemoryManager, addr: VirtAddr) -> Result<(), MemoryError> {
+    let bottom = align_down(addr.as_u64() as usize, PAGE_SIZE) as u64;
+    if bottom < USER_SPACE_START as u64 {
+        return Err(MemoryError::InvalidAddress);
+    }
+
+    let mut regions = mm.regions.write();
+    let stack = match regions.range(addr..).next() {
+        Some((_, region)) if grows_down(region) => region.clone(),
+        _ => return Err(MemoryError::InvalidAddress),
+    };
+
+    let size = stack.end().as_u64() - bottom;
+    let limit = rlimit::get(stack.owner, RLIMIT_STACK).map_or(u64::MAX, |limit| limit.cur);
+    let below = regions.range(..addr).next_back().map(|(_, region)| region.end().as_u64());
+    if size > limit || below.is_some_and(|end| bottom < end + STACK_GUARD_GAP) {
+        return Err(MemoryError::GuardPageViolation);
+    }
+
+    regions.remove(&stack.start);
+    let start = VirtAddr::new(bottom);
+    regions.insert(start, VirtualMemoryRegion { start, size: size as usize, ..stack });
+    Ok(())
+}
+
+/// Turn the page at `addr`, part of a kernel stack, into a guard page
+///
+/// For stacks that weren't allocated with `allocate_region_with_guards`,
+/// such as the IST stacks in the kernel image. The page's frame is not
+/// reused.
+pub fn guard_kernel_page(addr: VirtAddr) -> Result<(), MemoryError> {
+    let mm = get_memory_manager().ok_or(MemoryError::OutOfMemory)?;
+    let page: Page = Page::containing_address(addr);
+    mm.add_region(VirtualMemoryRegion::new(
+        page.start_address(),
+        PAGE_SIZE,
+        MemoryRegionType::GuardPage,
+        MemoryProtection::GUARD_PAGE,
+    ))?;
+    if mm.page_table_manager.lock().unmap_page(page).is_none() {
+        // Mapped as part of a huge page; the region alone can't catch anything
+        let _ = mm.remove_region(page.start_address());
+        return Err(MemoryError::MappingFailed);
+    }
+    register_kernel_guard(page.start_address());
+    Ok(())
+}
+
+/// If `addr` is in a guard page, the bottom of the stack sitting on it
+///
+/// For the double fault handler: it doesn't wait for the region table,
+/// which the faulting code may hold.
+pub fn overflowed_stack(addr: VirtAddr) -> Option<VirtAddr> {
+    if is_kernel_guard(addr) {
+        return Some(addr.align_down(PAGE_SIZE as u64) + PAGE_SIZE as u64);
+    }
+    let mm = get_memory_manager()?;
+    let regions = mm.regions.try_read()?;
+    let (_, guard) = regions.range(..=addr).next_back()?;
+    (guard.region_type == MemoryRegionType::GuardPage && guard.contains(addr)).then(|| guard.end())
+}
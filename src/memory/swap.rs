@@ -0,0 +1,529 @@
+//! Swap Areas and Background Reclaim
+//!
+//! Swap partitions and swap files use the Linux mkswap layout: page 0 holds
+//! a `swap_header` with the last usable page and a bad-page list, and ends in
+//! the `SWAPSPACE2` signature. Every other page is one swap slot.
+//!
+//! Up to `MAX_SWAPFILES` areas may be active. Slots are taken from the
+//! highest-priority area with free space, rotating between areas of equal
+//! priority. A `SwapSlot` packs the area index with the page offset in it.
+//!
+//! Besides block devices and files, an area may be a compressed RAM device
+//! (see `zram`), whose freed slots release their memory right away.
+//!
+//! kswapd sleeps until free memory drops below the low watermark, then has
+//! the memory manager evict cold anonymous pages until the high watermark is
+//! reached again.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::zram::Zram;
+use super::{SwapSlot, PAGE_SIZE};
+use crate::drivers::storage::{self, BlockRange};
+use crate::process::wait_queue::WaitQueue;
+use crate::vfs::InodeOps;
+
+/// Most swap areas that may be active at once
+pub const MAX_SWAPFILES: usize = 32;
+
+/// swapon flag: use the priority in the low bits instead of the default
+pub const SWAP_FLAG_PREFER: i32 = 0x8000;
+/// Priority bits of the swapon flags
+pub const SWAP_FLAG_PRIO_MASK: i32 = 0x7fff;
+/// swapon flag: discard freed slots (accepted; nothing is discarded)
+pub const SWAP_FLAG_DISCARD: i32 = 0x10000;
+
+/// Signature in the last ten bytes of the header page
+const SWAP_MAGIC: &[u8; 10] = b"SWAPSPACE2";
+
+/// Header layout within page 0, after the 1024 boot bytes
+const HEADER_OFFSET: usize = 1024;
+const HEADER_VERSION: usize = HEADER_OFFSET;
+const HEADER_LAST_PAGE: usize = HEADER_OFFSET + 4;
+const HEADER_NR_BADPAGES: usize = HEADER_OFFSET + 8;
+const HEADER_BADPAGES: usize = HEADER_OFFSET + 512;
+
+/// Most bad pages the header has room for
+const MAX_BADPAGES: usize = (PAGE_SIZE - 10 - HEADER_BADPAGES) / 4;
+
+/// Bits of a `SwapSlot` holding the page offset within its area
+const SLOT_OFFSET_BITS: u32 = 24;
+
+/// Free frames below which kswapd starts reclaiming
+const LOW_WATERMARK_PAGES: usize = 256;
+/// Free frames kswapd reclaims up to before going back to sleep
+const HIGH_WATERMARK_PAGES: usize = 512;
+
+/// Errors from swap area management
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapError {
+    /// The area has no valid SWAPSPACE2 header
+    InvalidHeader,
+    /// The area is already active
+    Busy,
+    /// `MAX_SWAPFILES` areas are already active
+    TooManyAreas,
+    /// No active area has this path
+    NotActive,
+    /// Every active area is full
+    NoSpace,
+    /// Reading or writing the backing store failed
+    Io,
+}
+
+impl SwapSlot {
+    /// Slot for page `offset` of area `area`
+    pub fn new(area: usize, offset: u32) -> Self {
+        SwapSlot(((area as u32) << SLOT_OFFSET_BITS) | offset)
+    }
+
+    /// Index of the area holding this slot
+    pub fn area(self) -> usize {
+        (self.0 >> SLOT_OFFSET_BITS) as usize
+    }
+
+    /// Page offset of this slot within its area
+    pub fn offset(self) -> u32 {
+        self.0 & ((1 << SLOT_OFFSET_BITS) - 1)
+    }
+}
+
+/// Where an area's pages live
+pub enum SwapBacking {
+    /// A block device or partition
+    Device(BlockRange),
+    /// A regular file
+    File(Arc<dyn InodeOps>),
+    /// A compressed RAM device
+    Zram(Arc<Zram>),
+}
+
+impl SwapBacking {
+    /// Number of whole pages the backing store holds
+    fn size_pages(&self) -> Result<u64, SwapError> {
+        match self {
+            SwapBacking::Device(range) => {
+                Ok(range.sector_count * range.sector_size as u64 / PAGE_SIZE as u64)
+            }
+            SwapBacking::File(inode) => {
+                let stat = inode.stat().map_err(|_| SwapError::Io)?;
+                Ok(stat.size / PAGE_SIZE as u64)
+            }
+            SwapBacking::Zram(zram) => Ok(zram.size_pages()),
+        }
+    }
+
+    /// Can a newly allocated slot be written? Only a zram pool fills up
+    /// before its slots run out.
+    fn has_room(&self) -> bool {
+        match self {
+            SwapBacking::Zram(zram) => zram.has_room(),
+            _ => true,
+        }
+    }
+
+    /// Release the storage behind a freed slot
+    fn discard(&self, page: u64) {
+        if let SwapBacking::Zram(zram) = self {
+            zram.discard(page);
+        }
+    }
+
+    fn read_page(&self, page: u64, data: &mut [u8; PAGE_SIZE]) -> Result<(), SwapError> {
+        let read = match self {
+            SwapBacking::Device(range) => {
+                let sector = range.start_sector + page * (PAGE_SIZE as u64 / range.sector_size as u64);
+                storage::read_storage_sectors(range.device_id, sector, data).map_err(|_| SwapError::Io)?
+            }
+            SwapBacking::File(inode) => {
+                inode.read_at(page * PAGE_SIZE as u64, data).map_err(|_| SwapError::Io)?
+            }
+            SwapBacking::Zram(zram) => return zram.read_page(page, data),
+        };
+        if read == PAGE_SIZE { Ok(()) } else { Err(SwapError::Io) }
+    }
+
+    fn write_page(&self, page: u64, data: &[u8; PAGE_SIZE]) -> Result<(), SwapError> {
+        let written = match self {
+            SwapBacking::Device(range) => {
+                let sector = range.start_sector + page * (PAGE_SIZE as u64 / range.sector_size as u64);
+                storage::write_storage_sectors(range.device_id, sector, data).map_err(|_| SwapError::Io)?
+            }
+            SwapBacking::File(inode) => {
+                inode.write_at(page * PAGE_SIZE as u64, data).map_err(|_| SwapError::Io)?
+            }
+            SwapBacking::Zram(zram) => return zram.write_page(page, data),
+        };
+        if written == PAGE_SIZE { Ok(()) } else { Err(SwapError::Io) }
+    }
+}
+
+/// Usable size and bad pages from a mkswap header page
+pub fn parse_header(page: &[u8; PAGE_SIZE]) -> Result<(u32, Vec<u32>), SwapError> {
+    if &page[PAGE_SIZE - SWAP_MAGIC.len()..] != SWAP_MAGIC {
+        return Err(SwapError::InvalidHeader);
+    }
+
+    let word = |offset: usize| u32::from_le_bytes([page[offset], page[offset + 1], page[offset + 2], page[offset + 3]]);
+    if word(HEADER_VERSION) != 1 {
+        return Err(SwapError::InvalidHeader);
+    }
+
+    let last_page = word(HEADER_LAST_PAGE);
+    let nr_badpages = word(HEADER_NR_BADPAGES) as usize;
+    if last_page == 0 || nr_badpages > MAX_BADPAGES {
+        return Err(SwapError::InvalidHeader);
+    }
+
+    let bad_pages = (0..nr_badpages).map(|i| word(HEADER_BADPAGES + i * 4)).collect();
+    Ok((last_page, bad_pages))
+}
+
+/// A mkswap header page for an area whose last usable page is `last_page`
+pub fn make_header(last_page: u32) -> [u8; PAGE_SIZE] {
+    let mut page = [0u8; PAGE_SIZE];
+    page[HEADER_VERSION..HEADER_VERSION + 4].copy_from_slice(&1u32.to_le_bytes());
+    page[HEADER_LAST_PAGE..HEADER_LAST_PAGE + 4].copy_from_slice(&last_page.to_le_bytes());
+    page[PAGE_SIZE - SWAP_MAGIC.len()..].copy_from_slice(SWAP_MAGIC);
+    page
+}
+
+/// Usage summary of one active area
+#[derive(Debug, Clone)]
+pub struct SwapAreaInfo {
+    pub path: String,
+    pub is_file: bool,
+    pub priority: i16,
+    pub total_pages: u32,
+    pub used_pages: u32,
+}
+
+struct SwapArea {
+    path: String,
+    backing: SwapBacking,
+    priority: i16,
+    /// Slot bitmap, one bit per page; set means in use. Page 0 (the
+    /// header), bad pages and pages past the end are permanently set.
+    used: Vec<u64>,
+    /// Usable slots
+    total: u32,
+    /// Slots in use
+    in_use: u32,
+    /// Next-fit cursor
+    cursor: u32,
+    /// Set while swapoff is emptying the area
+    draining: bool,
+}
+
+impl SwapArea {
+    fn is_used(&self, page: u32) -> bool {
+        self.used[page as usize / 64] & (1 << (page % 64)) != 0
+    }
+
+    fn mark(&mut self, page: u32, used: bool) {
+        let word = &mut self.used[page as usize / 64];
+        if used {
+            *word |= 1 << (page % 64);
+        } else {
+            *word &= !(1 << (page % 64));
+        }
+    }
+
+    fn has_free_slot(&self) -> bool {
+        !self.draining && self.in_use < self.total && self.backing.has_room()
+    }
+
+    fn allocate(&mut self) -> Option<u32> {
+        if !self.has_free_slot() {
+            return None;
+        }
+        let pages = (self.used.len() * 64) as u32;
+        for step in 0..pages {
+            let page = (self.cursor + step) % pages;
+            if !self.is_used(page) {
+                self.mark(page, true);
+                self.in_use += 1;
+                self.cursor = page + 1;
+                return Some(page);
+            }
+        }
+        None
+    }
+}
+
+struct SwapTable {
+    areas: Vec<Option<SwapArea>>,
+    /// Priority given to the next area activated without SWAP_FLAG_PREFER
+    next_default_priority: i16,
+    /// Area the last slot was allocated from
+    last_area: usize,
+}
+
+static SWAP: Mutex<SwapTable> = Mutex::new(SwapTable {
+    areas: Vec::new(),
+    next_default_priority: -1,
+    last_area: 0,
+});
+
+// ============================================================================
+// Activation
+// ============================================================================
+
+/// Activate a swap area
+///
+/// `flags` takes the swapon(2) flags; without `SWAP_FLAG_PREFER` areas get
+/// decreasing negative priorities in activation order.
+pub fn swapon(path: &str, backing: SwapBacking, flags: i32) -> Result<(), SwapError> {
+    let mut header = [0u8; PAGE_SIZE];
+    backing.read_page(0, &mut header)?;
+    let (last_page, bad_pages) = parse_header(&header)?;
+
+    // Never trust the header beyond the real size of the backing store
+    let pages = (last_page as u64 + 1)
+        .min(backing.size_pages()?)
+        .min(1 << SLOT_OFFSET_BITS) as u32;
+    if pages < 2 {
+        return Err(SwapError::InvalidHeader);
+    }
+
+    let mut used = vec![0u64; (pages as usize + 63) / 64];
+    for page in (pages as usize)..used.len() * 64 {
+        used[page / 64] |= 1 << (page % 64);
+    }
+    used[0] |= 1;
+    let mut total = pages - 1;
+    for &bad in bad_pages.iter().filter(|&&bad| bad > 0 && bad < pages) {
+        let bit = 1 << (bad % 64);
+        if used[bad as usize / 64] & bit == 0 {
+            used[bad as usize / 64] |= bit;
+            total -= 1;
+        }
+    }
+
+    let mut table = SWAP.lock();
+    if table.areas.iter().flatten().any(|area| area.path == path) {
+        return Err(SwapError::Busy);
+    }
+
+    let priority = if flags & SWAP_FLAG_PREFER != 0 {
+        (flags & SWAP_FLAG_PRIO_MASK) as i16
+    } else {
+        let priority = table.next_default_priority;
+        table.next_default_priority = priority.saturating_sub(1);
+        priority
+    };
+
+    let area = SwapArea {
+        path: String::from(path),
+        backing,
+        priority,
+        used,
+        total,
+        in_use: 0,
+        cursor: 1,
+        draining: false,
+    };
+
+    match table.areas.iter().position(Option::is_none) {
+        Some(index) => table.areas[index] = Some(area),
+        None if table.areas.len() < MAX_SWAPFILES => table.areas.push(Some(area)),
+        None => return Err(SwapError::TooManyAreas),
+    }
+    drop(table);
+
+    start_kswapd();
+    Ok(())
+}
+
+/// Deactivate the swap area at `path`, first bringing every page stored in
+/// it back into memory
+pub fn swapoff(path: &str) -> Result<(), SwapError> {
+    let index = {
+        let mut table = SWAP.lock();
+        let index = table
+            .areas
+            .iter()
+            .position(|area| area.as_ref().map_or(false, |area| area.path == path && !area.draining))
+            .ok_or(SwapError::NotActive)?;
+        table.areas[index].as_mut().unwrap().draining = true;
+        index
+    };
+
+    let drained = super::get_memory_manager()
+        .ok_or(SwapError::NoSpace)
+        .and_then(|mm| mm.swap_in_area(index).map_err(|_| SwapError::NoSpace));
+
+    let mut table = SWAP.lock();
+    match drained {
+        Ok(()) => {
+            table.areas[index] = None;
+            Ok(())
+        }
+        Err(err) => {
+            // Not enough memory to take the pages back; keep swapping to it
+            if let Some(area) = table.areas[index].as_mut() {
+                area.draining = false;
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Active areas, highest priority first
+pub fn areas() -> Vec<SwapAreaInfo> {
+    let table = SWAP.lock();
+    let mut areas: Vec<SwapAreaInfo> = table
+        .areas
+        .iter()
+        .flatten()
+        .map(|area| SwapAreaInfo {
+            path: area.path.clone(),
+            is_file: matches!(area.backing, SwapBacking::File(_)),
+            priority: area.priority,
+            total_pages: area.total,
+            used_pages: area.in_use,
+        })
+        .collect();
+    areas.sort_by(|a, b| b.priority.cmp(&a.priority));
+    areas
+}
+
+/// Total and used slots over all active areas
+pub fn totals() -> (u32, u32) {
+    let table = SWAP.lock();
+    table
+        .areas
+        .iter()
+        .flatten()
+        .fold((0, 0), |(total, used), area| (total + area.total, used + area.in_use))
+}
+
+// ============================================================================
+// Slots
+// ============================================================================
+
+/// Take a free slot from the highest-priority area that has one
+///
+/// Areas of equal priority are used in turn.
+pub fn allocate_slot() -> Option<SwapSlot> {
+    let mut table = SWAP.lock();
+
+    let best = table
+        .areas
+        .iter()
+        .flatten()
+        .filter(|area| area.has_free_slot())
+        .map(|area| area.priority)
+        .max()?;
+
+    // Round-robin: start after the area that was allocated from last
+    let count = table.areas.len();
+    let start = table.last_area + 1;
+    for step in 0..count {
+        let index = (start + step) % count;
+        let Some(area) = table.areas[index].as_mut() else { continue };
+        if area.priority != best {
+            continue;
+        }
+        if let Some(page) = area.allocate() {
+            table.last_area = index;
+            return Some(SwapSlot::new(index, page));
+        }
+    }
+    None
+}
+
+/// Return a slot to its area
+pub fn free_slot(slot: SwapSlot) {
+    let mut table = SWAP.lock();
+    if let Some(Some(area)) = table.areas.get_mut(slot.area()) {
+        if slot.offset() != 0 && area.is_used(slot.offset()) {
+            area.mark(slot.offset(), false);
+            area.in_use = area.in_use.saturating_sub(1);
+            area.backing.discard(slot.offset() as u64);
+        }
+    }
+}
+
+/// Write a page to its slot
+pub fn write_page(slot: SwapSlot, data: &[u8; PAGE_SIZE]) -> Result<(), SwapError> {
+    with_backing(slot, |backing| backing.write_page(slot.offset() as u64, data))
+}
+
+/// Read a page back from its slot
+pub fn read_page(slot: SwapSlot, data: &mut [u8; PAGE_SIZE]) -> Result<(), SwapError> {
+    with_backing(slot, |backing| backing.read_page(slot.offset() as u64, data))
+}
+
+fn with_backing<T>(slot: SwapSlot, f: impl FnOnce(&SwapBacking) -> Result<T, SwapError>) -> Result<T, SwapError> {
+    let table = SWAP.lock();
+    match table.areas.get(slot.area()) {
+        Some(Some(area)) => f(&area.backing),
+        _ => Err(SwapError::NotActive),
+    }
+}
+
+// ============================================================================
+// kswapd
+// ============================================================================
+
+static KSWAPD_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// kswapd sleeps here between reclaim passes
+static KSWAPD_WAIT: WaitQueue = WaitQueue::new();
+
+/// Free frames in all zones
+fn free_pages() -> usize {
+    super::get_memory_manager().map_or(usize::MAX, |mm| {
+        mm.get_zone_stats().iter().map(|zone| zone.free_frames()).sum()
+    })
+}
+
+fn swap_available() -> bool {
+    SWAP.lock().areas.iter().flatten().any(SwapArea::has_free_slot)
+}
+
+/// Start kswapd once the first swap area is active
+fn start_kswapd() {
+    if KSWAPD_STARTED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    if crate::process::thread::create_kernel_thread("kswapd", crate::process::Priority::Low, 16 * 1024, kswapd)
+        .is_err()
+    {
+        KSWAPD_STARTED.store(false, Ordering::Release);
+    }
+}
+
+/// Wake kswapd if free memory is low; cheap enough for allocation paths
+pub fn wakeup_kswapd(free_frames: usize) {
+    if free_frames < LOW_WATERMARK_PAGES {
+        KSWAPD_WAIT.wake_all();
+    }
+}
+
+fn kswapd() {
+    loop {
+        let _ = KSWAPD_WAIT.wait_until(|| (free_pages() < LOW_WATERMARK_PAGES && swap_available()).then_some(()));
+        balance();
+    }
+}
+
+/// Evict pages until free memory reaches the high watermark, swap runs out,
+/// or nothing more can be evicted; returns the number of pages evicted
+pub fn balance() -> usize {
+    let Some(mm) = super::get_memory_manager() else { return 0 };
+    let mut evicted = 0;
+    while free_pages() < HIGH_WATERMARK_PAGES && swap_available() {
+        if mm.reclaim_page().is_err() {
+            break;
+        }
+        evicted += 1;
+    }
+    evicted
+}
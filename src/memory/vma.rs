@@ -0,0 +1,547 @@
+//! Region Protection, Advice and Locking
+//!
+//! mprotect, madvise and mlock work on address ranges that need not line up
+//! with regions. The regions at either end of a range are split at its
+//! boundaries first, and afterwards neighbours left identical by the change
+//! are merged back, so repeated calls don't fragment the region map. File
+//! mappings are split along with their file offsets but never merged.
+//!
+//! Protection changes rewrite the page table entries of resident pages,
+//! keeping their accessed and dirty bits, and shoot the range down from
+//! every CPU's TLB. Pages still shared copy-on-write stay read-only and
+//! become writable on their first write fault.
+//!
+//! Locked regions are never picked for swap-out. Locking faults in every
+//! page of the range unless only future faults are to be locked, and the
+//! total locked is held to a limit the caller derives from RLIMIT_MEMLOCK.
+//!
+//! mbind policies are per region as well, so setting one splits and merges
+//! regions the same way.
+//!
+//! Transparent huge pages are split wherever a range ends inside one;
+//! hugetlb regions can only be split on huge page boundaries.
+
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
+use x86_64::structures::paging::{Page, PageTableFlags, Size2MiB};
+use x86_64::VirtAddr;
+
+use super::{
+    align_up, file_mapping, huge_page, numa, MemoryError, MemoryManager, MemoryProtection, MemoryRegionType, MemoryZone,
+    VirtualMemoryRegion, HUGE_PAGE_SIZE, PAGE_SIZE,
+};
+
+/// Usage advice for madvise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    Normal,
+    Random,
+    Sequential,
+    /// Fault the range in now
+    WillNeed,
+    /// Drop the pages; anonymous memory reads back as zero and file pages
+    /// are read from the file again
+    DontNeed,
+    /// Anonymous pages may be dropped; they are dropped right away
+    Free,
+    /// Drop the pages of a shared file mapping and zero the file range
+    Remove,
+    /// Use transparent huge pages even in `Madvise` THP mode
+    HugePage,
+    /// Never use transparent huge pages
+    NoHugePage,
+}
+
+/// Page fault error code of a kernel read of a missing page
+const POPULATE_FAULT: u64 = 0;
+
+fn is_anonymous(region: &VirtualMemoryRegion) -> bool {
+    matches!(
+        region.region_type,
+        MemoryRegionType::UserData | MemoryRegionType::UserHeap | MemoryRegionType::UserStack
+    )
+}
+
+/// Whether user space may change the region at all
+fn is_user(region: &VirtualMemoryRegion) -> bool {
+    region.protection.user_accessible && !region.protection.guard_page
+}
+
+/// Whether `a` and `b` can become one region
+fn can_merge(a: &VirtualMemoryRegion, b: &VirtualMemoryRegion) -> bool {
+    let user_type = matches!(
+        a.region_type,
+        MemoryRegionType::UserCode | MemoryRegionType::UserData | MemoryRegionType::UserHeap | MemoryRegionType::UserStack
+    );
+    user_type
+        && a.end() == b.start
+        && a.region_type == b.region_type
+        && a.protection == b.protection
+        && a.locked == b.locked
+        && a.thp == b.thp
+        && a.mempolicy == b.mempolicy
+        && a.owner == b.owner
+        && a.mapped == b.mapped
+}
+
+/// `[start, start + len)` rounded out to whole pages
+fn page_range(start: VirtAddr, len: usize) -> Result<(VirtAddr, VirtAddr), MemoryError> {
+    if !start.is_aligned(PAGE_SIZE as u64) || len == 0 {
+        return Err(MemoryError::InvalidAddress);
+    }
+    let end = start
+        .as_u64()
+        .checked_add(align_up(len, PAGE_SIZE) as u64)
+        .and_then(|end| VirtAddr::try_new(end).ok())
+        .ok_or(MemoryError::InvalidAddress)?;
+    Ok((start, end))
+}
+
+impl MemoryManager {
+    /// The regions covering `[start, end)` in order; fails if part of the
+    /// range isn't mapped
+    fn covering_regions(&self, start: VirtAddr, end: VirtAddr) -> Result<Vec<VirtualMemoryRegion>, MemoryError> {
+        let regions = self.regions.read();
+        let mut covered = start;
+        let mut covering = Vec::new();
+
+        for region in regions.range(..end).map(|(_, region)| region).filter(|region| region.end() > start) {
+            if region.start > covered {
+                return Err(MemoryError::RegionNotFound);
+            }
+            covered = region.end();
+            covering.push(region.clone());
+        }
+
+        if covered < end {
+            return Err(MemoryError::RegionNotFound);
+        }
+        Ok(covering)
+    }
+
+    /// Split the region containing `addr`, if any, so that one starts there
+    fn split_region(&self, addr: VirtAddr) -> Result<(), MemoryError> {
+        let Some(region) = self.find_region(addr) else { return Ok(()) };
+        if region.start == addr {
+            return Ok(());
+        }
+        match region.region_type {
+            MemoryRegionType::FileMapping => file_mapping::split(addr),
+            MemoryRegionType::HugeTlb => huge_page::split(addr)?,
+            _ => self.split_huge_pages(addr, addr + 1u64),
+        }
+
+        let mut regions = self.regions.write();
+        let head = regions.get_mut(&region.start).ok_or(MemoryError::RegionNotFound)?;
+        let tail_size = (head.end() - addr) as usize;
+        head.size -= tail_size;
+        let tail = VirtualMemoryRegion { start: addr, size: tail_size, physical_start: None, ..head.clone() };
+        regions.insert(addr, tail);
+        Ok(())
+    }
+
+    /// Merge the regions in or next to `[start, end)` with identical
+    /// neighbours
+    fn merge_regions(&self, start: VirtAddr, end: VirtAddr) {
+        let mut regions = self.regions.write();
+        let starts: Vec<VirtAddr> = regions
+            .range(..=end)
+            .filter(|(_, region)| region.end() >= start)
+            .map(|(&start, _)| start)
+            .collect();
+
+        let mut current = None;
+        for next in starts {
+            let merge = match current.and_then(|current| regions.get(&current)) {
+                Some(region) => regions.get(&next).is_some_and(|next| can_merge(region, next)),
+                None => false,
+            };
+            if !merge {
+                current = Some(next);
+                continue;
+            }
+            if let (Some(current), Some(next)) = (current, regions.remove(&next)) {
+                if let Some(region) = regions.get_mut(&current) {
+                    region.size += next.size;
+                }
+            }
+        }
+    }
+
+    /// Fault in the missing pages of `[start, end)`
+    fn populate(&self, start: VirtAddr, end: VirtAddr) -> Result<(), MemoryError> {
+        for page in Page::range(Page::containing_address(start), Page::containing_address(end)) {
+            if self.translate_addr(page.start_address()).is_none() {
+                self.handle_page_fault(page.start_address(), POPULATE_FAULT)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop the pages of anonymous memory in `[start, end)`, resident or
+    /// swapped out; they read back as zero
+    fn discard_anonymous(&self, start: VirtAddr, end: VirtAddr) {
+        // Huge pages the range only partly covers lose just that part
+        self.split_huge_pages(start, start + 1u64);
+        self.split_huge_pages(end - 1u64, end);
+
+        let mut pages = Page::range(Page::containing_address(start), Page::containing_address(end));
+        while let Some(page) = pages.next() {
+            let huge = self.page_table_manager.lock().huge_frame(page.start_address()).is_some();
+            if huge {
+                let block = Page::<Size2MiB>::containing_address(page.start_address());
+                if let Some(frame) = self.page_table_manager.lock().unmap_huge_page(block) {
+                    self.frame_allocator.lock().deallocate_huge_frame(frame);
+                }
+                pages.nth(HUGE_PAGE_SIZE / PAGE_SIZE - 2);
+                continue;
+            }
+
+            let mut swap_manager = self.swap_manager.lock();
+            if let Some(slot) = swap_manager.slot_for(page.start_address()) {
+                swap_manager.deallocate_slot(slot);
+                continue;
+            }
+            drop(swap_manager);
+
+            let Some(frame) = self.page_table_manager.lock().unmap_page(page) else { continue };
+            // Frames still shared after fork belong to the other side too
+            if self.decrement_frame_refcount(frame.start_address()) == 0 {
+                self.deallocate_frame(frame, MemoryZone::from_address(frame.start_address()));
+            }
+        }
+    }
+
+    /// Rewrite the entries of the resident pages of `region` for its
+    /// current protection
+    fn reprotect_pages(&self, region: &VirtualMemoryRegion) -> Result<(), MemoryError> {
+        let flags = region.protection.to_page_table_flags();
+        let mut page_table_manager = self.page_table_manager.lock();
+        let mut pages = region.pages();
+        while let Some(page) = pages.next() {
+            let Some(old) = page_table_manager.get_flags(page) else { continue };
+            let kept = old & (PageTableFlags::ACCESSED | PageTableFlags::DIRTY);
+            if page_table_manager.huge_frame(page.start_address()).is_some() {
+                page_table_manager
+                    .update_huge_flags(Page::containing_address(page.start_address()), flags | kept)
+                    .map_err(|_| MemoryError::ProtectionFailed)?;
+                pages.nth(HUGE_PAGE_SIZE / PAGE_SIZE - 2);
+            } else {
+                page_table_manager
+                    .update_flags(page, flags | kept)
+                    .map_err(|_| MemoryError::ProtectionFailed)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Change the access rights of `[start, start + len)` to those of
+    /// `protection`
+    ///
+    /// Only the readable, writable and executable bits are taken; caching
+    /// and copy-on-write state stay with each region. Shared file mappings
+    /// of descriptors not open for writing can't be made writable.
+    pub fn mprotect(&self, start: VirtAddr, len: usize, protection: MemoryProtection) -> Result<(), MemoryError> {
+        let (start, end) = page_range(start, len)?;
+        for region in self.covering_regions(start, end)? {
+            if !is_user(&region) {
+                return Err(MemoryError::PermissionDenied);
+            }
+            if region.region_type == MemoryRegionType::FileMapping
+                && protection.writable
+                && !file_mapping::may_write(region.start.max(start))
+            {
+                return Err(MemoryError::PermissionDenied);
+            }
+        }
+
+        self.split_region(start)?;
+        self.split_region(end)?;
+
+        let changed: Vec<VirtualMemoryRegion> = {
+            let mut regions = self.regions.write();
+            regions
+                .range_mut(start..end)
+                .map(|(_, region)| {
+                    region.protection = MemoryProtection {
+                        readable: protection.readable,
+                        writable: protection.writable,
+                        executable: protection.executable,
+                        ..region.protection
+                    };
+                    region.clone()
+                })
+                .collect()
+        };
+
+        let result = changed.iter().try_for_each(|region| self.reprotect_pages(region));
+        crate::smp::tlb_shootdown(start, end);
+        self.merge_regions(start, end);
+        result
+    }
+
+    /// Act on usage advice for `[start, start + len)`
+    pub fn madvise(&self, start: VirtAddr, len: usize, advice: Advice) -> Result<(), MemoryError> {
+        let (start, end) = page_range(start, len)?;
+        let covering = self.covering_regions(start, end)?;
+
+        match advice {
+            // No readahead to tune
+            Advice::Normal | Advice::Random | Advice::Sequential => Ok(()),
+            Advice::WillNeed => {
+                // Only a hint; whatever can't be faulted in now will be later
+                let _ = self.populate(start, end);
+                Ok(())
+            }
+            Advice::DontNeed | Advice::Free | Advice::Remove => {
+                for region in &covering {
+                    let file = region.region_type == MemoryRegionType::FileMapping;
+                    let valid = match advice {
+                        Advice::Free => is_anonymous(region),
+                        Advice::Remove => file,
+                        _ => file || is_anonymous(region),
+                    };
+                    if !valid || region.locked {
+                        return Err(MemoryError::InvalidAddress);
+                    }
+                }
+
+                let mut result = Ok(());
+                for region in &covering {
+                    let lo = region.start.max(start);
+                    let hi = region.end().min(end);
+                    if region.region_type == MemoryRegionType::FileMapping {
+                        result = result.and(file_mapping::discard(lo, hi, advice == Advice::Remove));
+                    } else {
+                        self.discard_anonymous(lo, hi);
+                    }
+                }
+                crate::smp::tlb_shootdown(start, end);
+                result
+            }
+            Advice::HugePage | Advice::NoHugePage => {
+                let thp = if advice == Advice::HugePage {
+                    huge_page::ThpAdvice::Always
+                } else {
+                    huge_page::ThpAdvice::Never
+                };
+                self.split_region(start)?;
+                self.split_region(end)?;
+                for (_, region) in self.regions.write().range_mut(start..end) {
+                    region.thp = thp;
+                }
+                self.merge_regions(start, end);
+                if advice == Advice::HugePage {
+                    huge_page::start_khugepaged();
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Bytes of memory in locked regions
+    pub fn locked_bytes(&self) -> usize {
+        self.regions.read().values().filter(|region| region.locked).map(|region| region.size).sum()
+    }
+
+    /// Set or clear the lock on `[start, end)`
+    fn set_locked(&self, start: VirtAddr, end: VirtAddr, locked: bool) -> Result<(), MemoryError> {
+        self.split_region(start)?;
+        self.split_region(end)?;
+        for (_, region) in self.regions.write().range_mut(start..end) {
+            region.locked = locked;
+        }
+        self.merge_regions(start, end);
+        Ok(())
+    }
+
+    /// Lock `[start, start + len)` in memory, keeping the total locked
+    /// within `limit` bytes
+    ///
+    /// With `populate` every page is faulted in now; otherwise pages are
+    /// locked as they fault in (MLOCK_ONFAULT).
+    pub fn mlock(&self, start: VirtAddr, len: usize, populate: bool, limit: usize) -> Result<(), MemoryError> {
+        let (start, end) = page_range(start.align_down(PAGE_SIZE as u64), len)?;
+        let covering = self.covering_regions(start, end)?;
+        if covering.iter().any(|region| !is_user(region)) {
+            return Err(MemoryError::PermissionDenied);
+        }
+
+        let newly_locked: usize = covering
+            .iter()
+            .filter(|region| !region.locked)
+            .map(|region| (region.end().min(end) - region.start.max(start)) as usize)
+            .sum();
+        if self.locked_bytes() + newly_locked > limit {
+            return Err(MemoryError::OutOfMemory);
+        }
+
+        self.set_locked(start, end, true)?;
+        if populate {
+            self.populate(start, end)?;
+        }
+        Ok(())
+    }
+
+    /// Unlock `[start, start + len)`
+    pub fn munlock(&self, start: VirtAddr, len: usize) -> Result<(), MemoryError> {
+        let (start, end) = page_range(start.align_down(PAGE_SIZE as u64), len)?;
+        self.covering_regions(start, end)?;
+        self.set_locked(start, end, false)
+    }
+
+    /// Lock every user region, as mlockall; `future` also locks regions
+    /// added later
+    pub fn mlock_all(&self, current: bool, future: bool, populate: bool, limit: usize) -> Result<(), MemoryError> {
+        if current {
+            let user: Vec<(VirtAddr, VirtAddr)> = {
+                let mut regions = self.regions.write();
+                let total: usize = regions.values().filter(|region| is_user(region)).map(|region| region.size).sum();
+                if total > limit {
+                    return Err(MemoryError::OutOfMemory);
+                }
+                regions
+                    .values_mut()
+                    .filter(|region| is_user(region))
+                    .map(|region| {
+                        region.locked = true;
+                        (region.start, region.end())
+                    })
+                    .collect()
+            };
+            if populate {
+                for (start, end) in user {
+                    self.populate(start, end)?;
+                }
+            }
+        }
+        self.lock_future.store(future, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Unlock everything and stop locking new regions, as munlockall
+    pub fn munlock_all(&self) {
+        for region in self.regions.write().values_mut() {
+            region.locked = false;
+        }
+        self.lock_future.store(false, Ordering::Relaxed);
+    }
+
+    /// Give `[start, start + len)` its own NUMA memory policy, as mbind
+    ///
+    /// Only pages allocated from now on follow it; moving those already
+    /// resident is `migrate_to_policy`.
+    pub fn set_policy(&self, start: VirtAddr, len: usize, policy: numa::MemPolicy) -> Result<(), MemoryError> {
+        let (start, end) = page_range(start, len)?;
+        self.covering_regions(start, end)?;
+        self.split_region(start)?;
+        self.split_region(end)?;
+        for (_, region) in self.regions.write().range_mut(start..end) {
+            region.mempolicy = policy;
+        }
+        self.merge_regions(start, end);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::get_memory_manager;
+
+    const PAGE: u64 = PAGE_SIZE as u64;
+
+    /// A fresh four-page anonymous region
+    fn anonymous_region(mm: &MemoryManager) -> VirtAddr {
+        mm.allocate_region(4 * PAGE_SIZE, MemoryRegionType::UserData, MemoryProtection::USER_DATA)
+            .expect("anonymous region")
+            .start
+    }
+
+    /// The region holding `addr` as `(start, end)`
+    fn bounds(mm: &MemoryManager, addr: VirtAddr) -> (VirtAddr, VirtAddr) {
+        let region = mm.find_region(addr).expect("mapped");
+        (region.start, region.end())
+    }
+
+    fn release(mm: &MemoryManager, start: VirtAddr) {
+        let mut region = mm.remove_region(mm.find_region(start).expect("mapped").start).unwrap();
+        mm.unmap_region(&mut region).unwrap();
+    }
+
+    #[test_case]
+    fn test_mprotect_splits_and_merges() {
+        let mm = get_memory_manager().expect("memory manager");
+        let start = anonymous_region(mm);
+        let read_only = MemoryProtection { writable: false, ..MemoryProtection::USER_DATA };
+
+        // Protecting the middle leaves three regions
+        mm.mprotect(start + PAGE, 2 * PAGE_SIZE, read_only).unwrap();
+        assert_eq!(bounds(mm, start + PAGE), (start + PAGE, start + 3 * PAGE));
+        assert_eq!(bounds(mm, start).1, start + PAGE);
+        assert_eq!(bounds(mm, start + 3 * PAGE).0, start + 3 * PAGE);
+        assert!(mm.find_region(start).unwrap().protection.writable);
+        assert!(!mm.find_region(start + PAGE).unwrap().protection.writable);
+        assert!(mm.find_region(start + 3 * PAGE).unwrap().protection.writable);
+
+        // The resident pages follow their region
+        let flags = |addr: VirtAddr| mm.page_table_manager.lock().get_flags(Page::containing_address(addr)).unwrap();
+        assert!(flags(start).contains(PageTableFlags::WRITABLE));
+        assert!(!flags(start + PAGE).contains(PageTableFlags::WRITABLE));
+
+        // Protecting it back makes them one region again
+        mm.mprotect(start + PAGE, 2 * PAGE_SIZE, MemoryProtection::USER_DATA).unwrap();
+        let (lo, hi) = bounds(mm, start + PAGE);
+        assert!(lo <= start && hi >= start + 4 * PAGE);
+        assert!(flags(start + PAGE).contains(PageTableFlags::WRITABLE));
+
+        release(mm, start);
+    }
+
+    #[test_case]
+    fn test_madvise_dontneed_zero_fills() {
+        let mm = get_memory_manager().expect("memory manager");
+        let start = anonymous_region(mm);
+        unsafe {
+            core::ptr::write_bytes(start.as_mut_ptr::<u8>(), 0xab, 2 * PAGE_SIZE);
+        }
+
+        mm.madvise(start, PAGE_SIZE, Advice::DontNeed).unwrap();
+        assert!(mm.translate_addr(start).is_none());
+        assert!(mm.translate_addr(start + PAGE).is_some());
+
+        // The dropped page faults back in as zeros; the next one is kept
+        mm.handle_page_fault(start, POPULATE_FAULT).unwrap();
+        unsafe {
+            assert_eq!(start.as_ptr::<u8>().read_volatile(), 0);
+            assert_eq!((start + PAGE).as_ptr::<u8>().read_volatile(), 0xab);
+        }
+
+        release(mm, start);
+    }
+
+    #[test_case]
+    fn test_munlock_part_of_range() {
+        let mm = get_memory_manager().expect("memory manager");
+        let start = anonymous_region(mm);
+        let locked_before = mm.locked_bytes();
+
+        mm.mlock(start, 4 * PAGE_SIZE, false, usize::MAX).unwrap();
+        assert_eq!(mm.locked_bytes(), locked_before + 4 * PAGE_SIZE);
+
+        // Unlocking the upper half splits the region there
+        mm.munlock(start + 2 * PAGE, 2 * PAGE_SIZE).unwrap();
+        assert_eq!(bounds(mm, start + 2 * PAGE).0, start + 2 * PAGE);
+        assert!(mm.find_region(start).unwrap().locked);
+        assert!(!mm.find_region(start + 2 * PAGE).unwrap().locked);
+        assert_eq!(mm.locked_bytes(), locked_before + 2 * PAGE_SIZE);
+
+        // and unlocking the rest merges it back
+        mm.munlock(start, 2 * PAGE_SIZE).unwrap();
+        let (lo, hi) = bounds(mm, start);
+        assert!(lo <= start && hi >= start + 4 * PAGE);
+        assert_eq!(mm.locked_bytes(), locked_before);
+
+        release(mm, start);
+    }
+}
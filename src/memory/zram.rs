@@ -0,0 +1,373 @@
+//! Compressed RAM Swap
+//!
+//! A zram device is a swap area whose pages live in a kernel memory pool
+//! instead of on disk. Each page written to it is stored one of three ways:
+//!
+//! - pages filled with one repeated 64-bit word (most often zero) keep just
+//!   that word
+//! - pages that deflate to under `HUGE_PAGE_THRESHOLD` bytes keep the raw
+//!   DEFLATE stream
+//! - anything else is incompressible and kept as a plain copy
+//!
+//! Devices are activated through the normal swap path, so a zram area with
+//! a higher priority than the disk areas fills first and disk swap takes
+//! over once it is full. A device refuses new slots when its pool would
+//! exceed its memory limit.
+//!
+//! Pages are written while reclaiming memory, so the pool allocates
+//! fallibly: a write the heap can't satisfy fails with `SwapError::NoSpace`
+//! and reclaim moves on instead of aborting. The page table is allocated
+//! whole when the device is created, as Linux does when disksize is set.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use miniz_oxide::deflate::core::{
+    compress, create_comp_flags_from_zip_params, CompressorOxide, TDEFLFlush, TDEFLStatus,
+};
+use miniz_oxide::inflate::core::{decompress, inflate_flags, DecompressorOxide};
+use miniz_oxide::inflate::TINFLStatus;
+use spin::Mutex;
+
+use super::swap::{self, SwapBacking, SwapError};
+use super::PAGE_SIZE;
+
+/// Most zram devices that may exist at once
+pub const MAX_ZRAM_DEVICES: usize = 8;
+
+/// Compressed pages at least this large are stored uncompressed instead
+pub const HUGE_PAGE_THRESHOLD: usize = PAGE_SIZE * 3 / 4;
+
+/// Fast compression; swap-out latency matters more than ratio
+const COMPRESSION_LEVEL: i32 = 1;
+
+/// Negative window bits select a raw DEFLATE stream with no zlib wrapper
+const WINDOW_BITS: i32 = -15;
+
+/// How one page is held in the pool
+enum StoredPage {
+    /// Every 64-bit word of the page has this value
+    Same(u64),
+    /// Raw DEFLATE stream
+    Compressed(Box<[u8]>),
+    /// Incompressible page
+    Raw(Box<[u8]>),
+}
+
+impl StoredPage {
+    /// Pool bytes used by this page
+    fn pool_size(&self) -> usize {
+        match self {
+            StoredPage::Same(_) => 0,
+            StoredPage::Compressed(data) => data.len(),
+            StoredPage::Raw(_) => PAGE_SIZE,
+        }
+    }
+}
+
+/// Usage of one device, in the layout of zram's mm_stat
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZramStats {
+    /// Capacity in bytes
+    pub disksize: usize,
+    /// Uncompressed bytes of every stored page
+    pub orig_data_size: usize,
+    /// Bytes of compressed and incompressible page data
+    pub compr_data_size: usize,
+    /// Pool bytes in use, bookkeeping included
+    pub mem_used_total: usize,
+    /// Pool limit in bytes, or 0 for none
+    pub mem_limit: usize,
+    /// Highest `mem_used_total` seen
+    pub mem_used_max: usize,
+    /// Pages stored as a single repeated word
+    pub same_pages: usize,
+    /// Pages stored uncompressed
+    pub huge_pages: usize,
+    /// Pages currently stored
+    pub pages_stored: usize,
+}
+
+impl ZramStats {
+    /// Uncompressed size over pool size, or 0 with nothing stored
+    pub fn compression_ratio(&self) -> f32 {
+        if self.mem_used_total == 0 {
+            0.0
+        } else {
+            self.orig_data_size as f32 / self.mem_used_total as f32
+        }
+    }
+}
+
+/// Pool bookkeeping charged per stored page, on top of its data
+const ENTRY_OVERHEAD: usize = core::mem::size_of::<StoredPage>();
+
+/// A compressed RAM block device
+pub struct Zram {
+    index: usize,
+    disksize_pages: u32,
+    mem_limit: AtomicUsize,
+    /// Indexed by page; `None` for pages never written or discarded
+    pages: Mutex<Vec<Option<StoredPage>>>,
+    mem_used: AtomicUsize,
+    mem_used_max: AtomicUsize,
+}
+
+impl Zram {
+    /// Device path, as given to swapon
+    pub fn path(&self) -> String {
+        format!("/dev/zram{}", self.index)
+    }
+
+    /// Capacity in pages
+    pub fn size_pages(&self) -> u64 {
+        self.disksize_pages as u64
+    }
+
+    /// Set the pool limit in bytes; 0 removes it
+    pub fn set_mem_limit(&self, bytes: usize) {
+        self.mem_limit.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Can another page be stored without going over the pool limit?
+    ///
+    /// Assumes the worst case of an incompressible page, so a write to a slot
+    /// handed out while this holds never goes over the limit.
+    pub fn has_room(&self) -> bool {
+        let limit = self.mem_limit.load(Ordering::Relaxed);
+        limit == 0 || self.mem_used.load(Ordering::Relaxed) + PAGE_SIZE + ENTRY_OVERHEAD <= limit
+    }
+
+    pub fn read_page(&self, page: u64, data: &mut [u8; PAGE_SIZE]) -> Result<(), SwapError> {
+        let pages = self.pages.lock();
+        match pages.get(page as usize).and_then(Option::as_ref) {
+            // Never-written pages read as zeroes, like a fresh device
+            None => data.fill(0),
+            Some(StoredPage::Same(word)) => {
+                for chunk in data.chunks_exact_mut(8) {
+                    chunk.copy_from_slice(&word.to_ne_bytes());
+                }
+            }
+            Some(StoredPage::Compressed(stream)) => inflate_page(stream, data)?,
+            Some(StoredPage::Raw(raw)) => data.copy_from_slice(&raw[..]),
+        }
+        Ok(())
+    }
+
+    pub fn write_page(&self, page: u64, data: &[u8; PAGE_SIZE]) -> Result<(), SwapError> {
+        if page >= self.size_pages() {
+            return Err(SwapError::Io);
+        }
+
+        let mut stream = [0u8; HUGE_PAGE_THRESHOLD];
+        let stored = match same_filled(data) {
+            Some(word) => StoredPage::Same(word),
+            None => match deflate_page(data, &mut stream) {
+                Some(len) => StoredPage::Compressed(try_boxed(&stream[..len])?),
+                None => StoredPage::Raw(try_boxed(data)?),
+            },
+        };
+
+        let added = stored.pool_size() + ENTRY_OVERHEAD;
+        let removed = self.pages.lock()[page as usize]
+            .replace(stored)
+            .map_or(0, |old| old.pool_size() + ENTRY_OVERHEAD);
+
+        let used = self.mem_used.fetch_add(added, Ordering::Relaxed) + added;
+        self.mem_used.fetch_sub(removed, Ordering::Relaxed);
+        self.mem_used_max.fetch_max(used - removed, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Drop a page whose swap slot was freed
+    pub fn discard(&self, page: u64) {
+        if let Some(old) = self.pages.lock().get_mut(page as usize).and_then(Option::take) {
+            self.mem_used.fetch_sub(old.pool_size() + ENTRY_OVERHEAD, Ordering::Relaxed);
+        }
+    }
+
+    /// Write a mkswap header covering the whole device to page 0
+    pub fn mkswap(&self) -> Result<(), SwapError> {
+        self.write_page(0, &swap::make_header(self.disksize_pages - 1))
+    }
+
+    pub fn stats(&self) -> ZramStats {
+        let pages = self.pages.lock();
+        let mut stats = ZramStats {
+            disksize: self.disksize_pages as usize * PAGE_SIZE,
+            mem_used_total: self.mem_used.load(Ordering::Relaxed),
+            mem_limit: self.mem_limit.load(Ordering::Relaxed),
+            mem_used_max: self.mem_used_max.load(Ordering::Relaxed),
+            pages_stored: pages.iter().flatten().count(),
+            ..ZramStats::default()
+        };
+        for stored in pages.iter().flatten() {
+            stats.orig_data_size += PAGE_SIZE;
+            stats.compr_data_size += stored.pool_size();
+            match stored {
+                StoredPage::Same(_) => stats.same_pages += 1,
+                StoredPage::Raw(_) => stats.huge_pages += 1,
+                StoredPage::Compressed(_) => {}
+            }
+        }
+        stats
+    }
+}
+
+/// The repeated word of a same-filled page
+fn same_filled(data: &[u8; PAGE_SIZE]) -> Option<u64> {
+    let mut words = data.chunks_exact(8).map(|chunk| u64::from_ne_bytes(chunk.try_into().unwrap()));
+    let first = words.next()?;
+    words.all(|word| word == first).then_some(first)
+}
+
+// ============================================================================
+// Compression
+// ============================================================================
+
+/// The compressor and decompressor state are tens of kilobytes each, too
+/// large to build for every page, so one of each is shared
+static COMPRESSOR: Mutex<Option<Box<CompressorOxide>>> = Mutex::new(None);
+static DECOMPRESSOR: Mutex<Option<Box<DecompressorOxide>>> = Mutex::new(None);
+
+/// Copy `bytes` into a new pool allocation, failing instead of aborting when
+/// the heap is exhausted
+fn try_boxed(bytes: &[u8]) -> Result<Box<[u8]>, SwapError> {
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(bytes.len()).map_err(|_| SwapError::NoSpace)?;
+    buf.extend_from_slice(bytes);
+    Ok(buf.into_boxed_slice())
+}
+
+/// Deflate a page into `out`, returning the stream length, or None if it
+/// does not shrink below the huge threshold
+fn deflate_page(data: &[u8; PAGE_SIZE], out: &mut [u8; HUGE_PAGE_THRESHOLD]) -> Option<usize> {
+    let mut compressor = COMPRESSOR.lock();
+    let compressor = compressor.get_or_insert_with(|| {
+        Box::new(CompressorOxide::new(create_comp_flags_from_zip_params(COMPRESSION_LEVEL, WINDOW_BITS, 0)))
+    });
+    compressor.reset();
+
+    let (status, consumed, written) = compress(compressor, data, out, TDEFLFlush::Finish);
+
+    // Okay means the output filled up before the page was done
+    if status != TDEFLStatus::Done || consumed != PAGE_SIZE {
+        return None;
+    }
+    Some(written)
+}
+
+fn inflate_page(stream: &[u8], data: &mut [u8; PAGE_SIZE]) -> Result<(), SwapError> {
+    let mut decompressor = DECOMPRESSOR.lock();
+    let decompressor = decompressor.get_or_insert_with(|| Box::new(DecompressorOxide::new()));
+    decompressor.init();
+
+    let (status, _, written) = decompress(
+        decompressor,
+        stream,
+        data,
+        0,
+        inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF,
+    );
+    if status == TINFLStatus::Done && written == PAGE_SIZE { Ok(()) } else { Err(SwapError::Io) }
+}
+
+// ============================================================================
+// Devices
+// ============================================================================
+
+static DEVICES: Mutex<Vec<Option<Arc<Zram>>>> = Mutex::new(Vec::new());
+
+/// Create a zram device of `disksize` bytes, returning its index
+///
+/// `mem_limit` caps the pool in bytes; 0 leaves it unlimited.
+pub fn add_device(disksize: usize, mem_limit: usize) -> Result<usize, SwapError> {
+    let pages = disksize / PAGE_SIZE;
+    if pages < 2 || pages > u32::MAX as usize {
+        return Err(SwapError::InvalidHeader);
+    }
+
+    let mut table = Vec::new();
+    table.try_reserve_exact(pages).map_err(|_| SwapError::NoSpace)?;
+    table.resize_with(pages, || None);
+
+    let mut devices = DEVICES.lock();
+    let index = match devices.iter().position(Option::is_none) {
+        Some(index) => index,
+        None if devices.len() < MAX_ZRAM_DEVICES => {
+            devices.push(None);
+            devices.len() - 1
+        }
+        None => return Err(SwapError::TooManyAreas),
+    };
+
+    devices[index] = Some(Arc::new(Zram {
+        index,
+        disksize_pages: pages as u32,
+        mem_limit: AtomicUsize::new(mem_limit),
+        pages: Mutex::new(table),
+        mem_used: AtomicUsize::new(0),
+        mem_used_max: AtomicUsize::new(0),
+    }));
+    Ok(index)
+}
+
+/// Destroy a zram device that is not in use as swap
+pub fn remove_device(index: usize) -> Result<(), SwapError> {
+    let mut devices = DEVICES.lock();
+    let device = devices.get(index).cloned().flatten().ok_or(SwapError::NotActive)?;
+    if swap::areas().iter().any(|area| area.path == device.path()) {
+        return Err(SwapError::Busy);
+    }
+    devices[index] = None;
+    Ok(())
+}
+
+/// The device at `/dev/zramN`
+pub fn lookup(path: &str) -> Option<Arc<Zram>> {
+    let index: usize = path.strip_prefix("/dev/zram")?.parse().ok()?;
+    DEVICES.lock().get(index).cloned().flatten()
+}
+
+/// Usage of every device, by index
+pub fn stats() -> Vec<(usize, ZramStats)> {
+    let devices: Vec<Arc<Zram>> = DEVICES.lock().iter().flatten().cloned().collect();
+    devices.iter().map(|device| (device.index, device.stats())).collect()
+}
+
+/// Usage of all devices combined
+pub fn total_stats() -> ZramStats {
+    stats().into_iter().fold(ZramStats::default(), |mut total, (_, device)| {
+        total.disksize += device.disksize;
+        total.orig_data_size += device.orig_data_size;
+        total.compr_data_size += device.compr_data_size;
+        total.mem_used_total += device.mem_used_total;
+        total.mem_limit += device.mem_limit;
+        total.mem_used_max += device.mem_used_max;
+        total.same_pages += device.same_pages;
+        total.huge_pages += device.huge_pages;
+        total.pages_stored += device.pages_stored;
+        total
+    })
+}
+
+/// Create a zram device, format it and start swapping to it at `priority`
+///
+/// Give it a higher priority than any disk area so it acts as the first
+/// tier; returns the device index.
+pub fn enable_swap(disksize: usize, mem_limit: usize, priority: i16) -> Result<usize, SwapError> {
+    let index = add_device(disksize, mem_limit)?;
+    let device = lookup(&format!("/dev/zram{}", index)).ok_or(SwapError::NotActive)?;
+
+    let flags = swap::SWAP_FLAG_PREFER | (priority.max(0) as i32 & swap::SWAP_FLAG_PRIO_MASK);
+    let result = device
+        .mkswap()
+        .and_then(|()| swap::swapon(&device.path(), SwapBacking::Zram(device.clone()), flags));
+    if result.is_err() {
+        let _ = remove_device(index);
+    }
+    result.map(|()| index)
+}
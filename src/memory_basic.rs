@@ -3,6 +3,7 @@
 //! Simple memory management without heap allocation (for incremental development)
 
 use bootloader::bootinfo::MemoryRegion;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 /// Page size constants
 pub const PAGE_SIZE: usize = 4096;
@@ -83,6 +84,34 @@ pub fn get_memory_stats() -> Result<MemoryStats, &'static str> {
     }
 }
 
+/// Virtual bounds of the early heap
+static EARLY_HEAP_START: AtomicUsize = AtomicUsize::new(0);
+static EARLY_HEAP_END: AtomicUsize = AtomicUsize::new(0);
+
+/// Physical start of the early heap
+static EARLY_HEAP_PHYS: AtomicU64 = AtomicU64::new(0);
+
+fn record_heap(virt_start: usize, phys_start: u64, size: usize) {
+    EARLY_HEAP_PHYS.store(phys_start, Ordering::Relaxed);
+    EARLY_HEAP_START.store(virt_start, Ordering::Relaxed);
+    EARLY_HEAP_END.store(virt_start + size, Ordering::Release);
+}
+
+/// Virtual address range `[start, end)` of the early heap; empty before
+/// it is set up
+pub fn early_heap_range() -> (usize, usize) {
+    let end = EARLY_HEAP_END.load(Ordering::Acquire);
+    (EARLY_HEAP_START.load(Ordering::Relaxed), end)
+}
+
+/// Physical address range `[start, end)` of the early heap, which the
+/// frame allocator must leave alone
+pub fn early_heap_phys_range() -> Option<(u64, u64)> {
+    let (start, end) = early_heap_range();
+    let phys = EARLY_HEAP_PHYS.load(Ordering::Relaxed);
+    (end > start).then(|| (phys, phys + (end - start) as u64))
+}
+
 /// Initialize the kernel heap allocator using memory from the bootloader memory map
 pub fn init_heap(allocator: &linked_list_allocator::LockedHeap) -> Result<(), &'static str> {
     // Use the static heap region defined in memory constants
@@ -90,6 +119,8 @@ pub fn init_heap(allocator: &linked_list_allocator::LockedHeap) -> Result<(), &'
     unsafe {
         allocator.lock().init(KERNEL_HEAP_START, KERNEL_HEAP_SIZE);
     }
+    // Identity-mapped
+    record_heap(KERNEL_HEAP_START, KERNEL_HEAP_START as u64, KERNEL_HEAP_SIZE);
     Ok(())
 }
 
@@ -133,6 +164,7 @@ pub fn init_heap_from_memory_map(
             unsafe {
                 allocator.lock().init(virt_start as usize, heap_size);
             }
+            record_heap(virt_start as usize, phys_start, heap_size);
             return Ok(());
         }
     }
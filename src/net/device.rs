@@ -8,6 +8,7 @@ use super::{NetworkAddress, NetworkResult, NetworkError, PacketBuffer, NetworkIn
 use alloc::{vec::Vec, vec, string::{String, ToString}, boxed::Box};
 use spin::RwLock;
 use lazy_static::lazy_static;
+use core::sync::atomic::{AtomicU32, Ordering};
 
 /// Get current time in milliseconds
 fn current_time_ms() -> u64 {
@@ -761,11 +762,15 @@ pub fn device_manager() -> &'static DeviceManager {
     &DEVICE_MANAGER
 }
 
+/// Number of the next virtual ethernet pair, which its MAC addresses carry
+static NEXT_VETH_PAIR: AtomicU32 = AtomicU32::new(0);
+
 /// Create virtual ethernet pair
 pub fn create_veth_pair(name1: &str, name2: &str) -> NetworkResult<()> {
-    // Generate MAC addresses
-    let mac1 = NetworkAddress::Mac([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
-    let mac2 = NetworkAddress::Mac([0x02, 0x00, 0x00, 0x00, 0x00, 0x02]);
+    // Generate locally administered MAC addresses, unique to the pair
+    let [_, a, b, c] = NEXT_VETH_PAIR.fetch_add(1, Ordering::Relaxed).to_be_bytes();
+    let mac1 = NetworkAddress::Mac([0x02, 0x00, a, b, c, 0x01]);
+    let mac2 = NetworkAddress::Mac([0x02, 0x00, a, b, c, 0x02]);
     
     // Create devices
     let mut veth1 = VirtualEthernetDevice::new(name1.to_string(), mac1);
@@ -7,6 +7,12 @@
 //! - UDP datagram handling
 //! - Socket interface
 //! - Network device abstraction
+//!
+//! Each network namespace has a stack of its own, with its own interfaces,
+//! routes, ARP cache and sockets; `network_stack` is the one of the initial
+//! namespace. A new namespace starts with just a loopback interface. Virtual
+//! ethernet pairs connect them: `create_veth_pair` puts one end in each
+//! namespace, and both ends go away with either namespace.
 
 pub mod ethernet;
 pub mod ip;
@@ -21,11 +27,13 @@ pub mod dns;
 pub mod buffer;
 pub mod dma;
 
-use alloc::{vec::Vec, vec, collections::BTreeMap, string::{String, ToString}};
+use alloc::{vec::Vec, vec, collections::BTreeMap, string::{String, ToString}, sync::Arc};
 use spin::{RwLock, Mutex};
 use lazy_static::lazy_static;
 use core::fmt;
 
+use crate::process::namespace::{self, NsId};
+
 /// Type alias for IPv4 address as a 4-byte array
 pub type Ipv4Address = [u8; 4];
 
@@ -887,11 +895,14 @@ pub struct NetworkStats {
 
 lazy_static! {
     static ref NETWORK_STACK: NetworkStack = NetworkStack::new();
+    /// Stacks of the network namespaces other than the initial one
+    static ref NAMESPACE_STACKS: RwLock<BTreeMap<NsId, Arc<NetworkStack>>> = RwLock::new(BTreeMap::new());
+    /// Peer of each end of the virtual ethernet pairs between namespaces
+    static ref VETH_PEERS: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
 }
 
-/// Initialize the network stack
-pub fn init() -> NetworkResult<()> {
-    // Create loopback interface
+/// Bring up a loopback interface with 127.0.0.1 on `stack`
+fn add_loopback(stack: &NetworkStack) -> NetworkResult<()> {
     let loopback = NetworkInterface {
         name: "lo".to_string(),
         mac_address: NetworkAddress::mac([0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
@@ -907,8 +918,8 @@ pub fn init() -> NetworkResult<()> {
         stats: InterfaceStats::default(),
     };
 
-    NETWORK_STACK.add_interface(loopback)?;
-    NETWORK_STACK.set_interface_state("lo", true)?;
+    stack.add_interface(loopback)?;
+    stack.set_interface_state("lo", true)?;
 
     // Add loopback route
     let loopback_route = RouteEntry {
@@ -918,16 +929,97 @@ pub fn init() -> NetworkResult<()> {
         interface: "lo".to_string(),
         metric: 0,
     };
-    NETWORK_STACK.add_route(loopback_route)?;
+    stack.add_route(loopback_route)
+}
 
-    Ok(())
+/// Initialize the network stack
+pub fn init() -> NetworkResult<()> {
+    add_loopback(&NETWORK_STACK)
 }
 
-/// Get the global network stack
+/// Get the network stack of the initial network namespace
 pub fn network_stack() -> &'static NetworkStack {
     &NETWORK_STACK
 }
 
+/// Run `f` on the stack of network namespace `ns`
+pub fn with_namespace_stack<R>(ns: NsId, f: impl FnOnce(&NetworkStack) -> R) -> Option<R> {
+    if ns == namespace::INIT_NS {
+        return Some(f(&NETWORK_STACK));
+    }
+    let stack = NAMESPACE_STACKS.read().get(&ns).cloned()?;
+    Some(f(&stack))
+}
+
+/// Run `f` on the stack of the caller's network namespace
+pub fn with_current_stack<R>(f: impl FnOnce(&NetworkStack) -> R) -> R {
+    let ns = namespace::of(crate::process::current_pid()).net;
+    let stack = NAMESPACE_STACKS.read().get(&ns).cloned();
+    match stack {
+        Some(stack) => f(&stack),
+        None => f(&NETWORK_STACK),
+    }
+}
+
+/// Give network namespace `ns` a stack of its own
+pub fn create_namespace(ns: NsId) {
+    let stack = NetworkStack::new();
+    let _ = add_loopback(&stack);
+    NAMESPACE_STACKS.write().insert(ns, Arc::new(stack));
+}
+
+/// Drop the stack of a network namespace that is gone, with the virtual
+/// ethernet pairs that have an end in it
+pub fn destroy_namespace(ns: NsId) {
+    let Some(stack) = NAMESPACE_STACKS.write().remove(&ns) else { return };
+    for interface in stack.list_interfaces() {
+        let Some(peer) = VETH_PEERS.write().remove(&interface.name) else { continue };
+        VETH_PEERS.write().remove(&peer);
+
+        let _ = NETWORK_STACK.remove_interface(&peer);
+        for other in NAMESPACE_STACKS.read().values() {
+            let _ = other.remove_interface(&peer);
+        }
+        let _ = device::device_manager().unregister_device(&interface.name);
+        let _ = device::device_manager().unregister_device(&peer);
+    }
+}
+
+/// Create a virtual ethernet pair with end `name1` in network namespace
+/// `ns1` and `name2` in `ns2`
+pub fn create_veth_pair(name1: &str, ns1: NsId, name2: &str, ns2: NsId) -> NetworkResult<()> {
+    let exists = |ns| ns == namespace::INIT_NS || NAMESPACE_STACKS.read().contains_key(&ns);
+    if !exists(ns1) || !exists(ns2) {
+        return Err(NetworkError::InvalidArgument);
+    }
+
+    device::create_veth_pair(name1, name2)?;
+    let mut added = Vec::new();
+    for (name, ns) in [(name1, ns1), (name2, ns2)] {
+        let result = device::device_manager()
+            .create_interface(name)
+            .ok_or(NetworkError::InvalidArgument)
+            .and_then(|interface| {
+                with_namespace_stack(ns, |stack| stack.add_interface(interface)).unwrap_or(Err(NetworkError::InvalidArgument))
+            });
+        if let Err(err) = result {
+            // Undo the half-made pair
+            for (name, ns) in added {
+                let _ = with_namespace_stack(ns, |stack| stack.remove_interface(name));
+            }
+            let _ = device::device_manager().unregister_device(name1);
+            let _ = device::device_manager().unregister_device(name2);
+            return Err(err);
+        }
+        added.push((name, ns));
+    }
+
+    let mut peers = VETH_PEERS.write();
+    peers.insert(name1.to_string(), name2.to_string());
+    peers.insert(name2.to_string(), name1.to_string());
+    Ok(())
+}
+
 // =============================================================================
 // Wrapper functions for legacy API compatibility
 // =============================================================================
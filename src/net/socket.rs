@@ -528,27 +528,28 @@ pub enum SocketDomain {
     Unix,
 }
 
-/// High-level socket operations
+/// High-level socket operations, on the stack of the caller's network
+/// namespace
 pub struct SocketManager;
 
 impl SocketManager {
     /// Create a TCP socket
     pub fn tcp_socket() -> NetworkResult<u32> {
-        super::network_stack().create_socket(SocketType::Stream, Protocol::TCP)
+        super::with_current_stack(|stack| stack.create_socket(SocketType::Stream, Protocol::TCP))
     }
 
     /// Create a UDP socket
     pub fn udp_socket() -> NetworkResult<u32> {
-        super::network_stack().create_socket(SocketType::Datagram, Protocol::UDP)
+        super::with_current_stack(|stack| stack.create_socket(SocketType::Datagram, Protocol::UDP))
     }
 
     /// Create a raw socket
     pub fn raw_socket(protocol: Protocol) -> NetworkResult<u32> {
-        super::network_stack().create_socket(SocketType::Raw, protocol)
+        super::with_current_stack(|stack| stack.create_socket(SocketType::Raw, protocol))
     }
 
     /// Close a socket
     pub fn close_socket(socket_id: u32) -> NetworkResult<()> {
-        super::network_stack().close_socket(socket_id)
+        super::with_current_stack(|stack| stack.close_socket(socket_id))
     }
 }
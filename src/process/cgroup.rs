@@ -0,0 +1,766 @@
+//! Control Groups (cgroup v2)
+//!
+//! Processes are organised in one hierarchy of groups. Every process starts
+//! in the root group and children join the group of their parent; root can
+//! move a process to another group at any time. Groups are managed through
+//! the cgroup2 filesystem, `crate::vfs::cgroupfs`.
+//!
+//! Three controllers bound what a group and its descendants use together.
+//! A group's parent enables them for it through `cgroup.subtree_control`;
+//! disabling one again drops the limits set below.
+//! - cpu: a group is a group of the fair scheduling class. `cpu.weight` sets
+//!   its shares, which it competes with next to every other group, and
+//!   `cpu.max` a bandwidth its members and descendants share.
+//! - memory: anonymous pages a member faults in are charged to the innermost
+//!   group of its chain the controller is enabled for, and every ancestor;
+//!   with none, the page goes uncharged. A charge past `memory.max` first
+//!   reclaims the pages of the group to swap and then has the OOM killer pick
+//!   a victim among its members. Charges stay with the group when a process
+//!   moves.
+//! - pids: `pids.max` bounds the processes in a group's subtree; creating
+//!   one more fails, moving one in does not.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::Pid;
+use crate::scheduler::{self, fair};
+
+/// Group identifier, also the group's id in the fair scheduling class
+pub type CgroupId = fair::GroupId;
+
+/// Group every process starts in
+pub const ROOT_CGROUP: CgroupId = fair::ROOT_GROUP;
+
+/// Controller bits of `cgroup.controllers` and `cgroup.subtree_control`
+pub const CONTROLLER_CPU: u8 = 1 << 0;
+pub const CONTROLLER_MEMORY: u8 = 1 << 1;
+pub const CONTROLLER_PIDS: u8 = 1 << 2;
+
+/// Every controller, with its name
+pub const CONTROLLERS: [(u8, &str); 3] = [
+    (CONTROLLER_CPU, "cpu"),
+    (CONTROLLER_MEMORY, "memory"),
+    (CONTROLLER_PIDS, "pids"),
+];
+
+/// `cpu.weight` of a group unless set otherwise
+pub const DEFAULT_CPU_WEIGHT: u64 = 100;
+
+/// Range of `cpu.weight`
+pub const MIN_CPU_WEIGHT: u64 = 1;
+pub const MAX_CPU_WEIGHT: u64 = 10_000;
+
+/// Page size memory is charged in
+const PAGE_SIZE: u64 = 4096;
+
+/// Errors from cgroup operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgroupError {
+    /// No such group or process
+    NotFound,
+    /// A group of that name already exists
+    AlreadyExists,
+    /// The group still has members or children, or a child still uses the
+    /// controller
+    Busy,
+    /// Malformed value, or a controller the parent doesn't enable
+    InvalidArgument,
+    /// `pids.max` of the group or an ancestor is reached
+    LimitReached,
+}
+
+/// Counters of `memory.events`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryEvents {
+    /// Charges that hit `memory.max`
+    pub max: u64,
+    /// Times reclaim could not bring the group below `memory.max`
+    pub oom: u64,
+    /// Members the OOM killer killed for the group
+    pub oom_kill: u64,
+}
+
+struct Cgroup {
+    name: String,
+    parent: Option<CgroupId>,
+    children: BTreeMap<String, CgroupId>,
+    /// Processes directly in this group
+    procs: BTreeSet<Pid>,
+    /// Controllers enabled for the children
+    subtree_control: u8,
+    cpu_weight: u64,
+    /// Pages charged to the group and its descendants
+    memory_pages: u64,
+    /// `memory.max` in pages
+    memory_max: Option<u64>,
+    memory_events: MemoryEvents,
+    /// Processes in the group and its descendants
+    pids: u64,
+    pids_max: Option<u64>,
+    /// Forks refused by `pids.max`
+    pids_events_max: u64,
+}
+
+impl Cgroup {
+    fn new(name: String, parent: Option<CgroupId>) -> Self {
+        Self {
+            name,
+            parent,
+            children: BTreeMap::new(),
+            procs: BTreeSet::new(),
+            subtree_control: 0,
+            cpu_weight: DEFAULT_CPU_WEIGHT,
+            memory_pages: 0,
+            memory_max: None,
+            memory_events: MemoryEvents::default(),
+            pids: 0,
+            pids_max: None,
+            pids_events_max: 0,
+        }
+    }
+}
+
+struct Hierarchy {
+    groups: BTreeMap<CgroupId, Cgroup>,
+    /// Group of every process not in the root group
+    membership: BTreeMap<Pid, CgroupId>,
+    next_id: CgroupId,
+}
+
+impl Hierarchy {
+    fn group_of(&self, pid: Pid) -> CgroupId {
+        self.membership.get(&pid).copied().unwrap_or(ROOT_CGROUP)
+    }
+
+    /// `id` and its ancestors, innermost first
+    fn chain(&self, id: CgroupId) -> Vec<CgroupId> {
+        let mut chain = Vec::new();
+        let mut next = Some(id);
+        while let Some(id) = next {
+            chain.push(id);
+            next = self.groups.get(&id).and_then(|group| group.parent);
+        }
+        chain
+    }
+
+    /// `id` and all its descendants
+    fn subtree(&self, id: CgroupId) -> Vec<CgroupId> {
+        let mut subtree = Vec::new();
+        let mut pending = Vec::from([id]);
+        while let Some(id) = pending.pop() {
+            if let Some(group) = self.groups.get(&id) {
+                subtree.push(id);
+                pending.extend(group.children.values().copied());
+            }
+        }
+        subtree
+    }
+
+    fn add_pids(&mut self, id: CgroupId, delta: i64) {
+        for id in self.chain(id) {
+            if let Some(group) = self.groups.get_mut(&id) {
+                group.pids = group.pids.saturating_add_signed(delta);
+            }
+        }
+    }
+
+    /// Whether the parent of group `id` enables `controller` for it
+    fn enables(&self, id: CgroupId, controller: u8) -> bool {
+        self.groups.get(&id)
+            .and_then(|group| group.parent)
+            .and_then(|parent| self.groups.get(&parent))
+            .is_some_and(|parent| parent.subtree_control & controller != 0)
+    }
+
+    /// Group the pages of process `pid` are charged to: the innermost one of
+    /// its chain the memory controller is enabled for
+    fn memory_group(&self, pid: Pid) -> Option<CgroupId> {
+        self.chain(self.group_of(pid)).into_iter().find(|&id| self.enables(id, CONTROLLER_MEMORY))
+    }
+
+    fn set_member(&mut self, pid: Pid, id: CgroupId) {
+        let old = self.group_of(pid);
+        if let Some(group) = self.groups.get_mut(&old) {
+            group.procs.remove(&pid);
+        }
+        if let Some(group) = self.groups.get_mut(&id) {
+            group.procs.insert(pid);
+        }
+        if id == ROOT_CGROUP {
+            self.membership.remove(&pid);
+        } else {
+            self.membership.insert(pid, id);
+        }
+    }
+
+    /// Put new process `pid` in group `id`, unless that passes `pids.max`
+    fn add_process(&mut self, pid: Pid, id: CgroupId) -> Result<(), CgroupError> {
+        let full = self.chain(id).into_iter().find(|id| {
+            self.groups.get(id).is_some_and(|group| group.pids_max.is_some_and(|max| group.pids >= max))
+        });
+        if let Some(full) = full {
+            if let Some(group) = self.groups.get_mut(&full) {
+                group.pids_events_max += 1;
+            }
+            return Err(CgroupError::LimitReached);
+        }
+        self.add_pids(id, 1);
+        self.set_member(pid, id);
+        Ok(())
+    }
+
+    /// Take process `pid` out of its group
+    fn remove_process(&mut self, pid: Pid) {
+        let id = self.group_of(pid);
+        self.add_pids(id, -1);
+        if let Some(group) = self.groups.get_mut(&id) {
+            group.procs.remove(&pid);
+        }
+        self.membership.remove(&pid);
+    }
+
+    /// Charge a page to group `id` and its ancestors
+    ///
+    /// Fails with the innermost group whose `memory.max` it would pass,
+    /// without charging anything; with `force` the charge is made anyway.
+    fn charge(&mut self, id: CgroupId, force: bool) -> Result<(), CgroupId> {
+        let chain = self.chain(id);
+        let full = chain.iter().copied().find(|id| {
+            self.groups.get(id).is_some_and(|group| group.memory_max.is_some_and(|max| group.memory_pages >= max))
+        });
+        if let Some(full) = full {
+            if let Some(group) = self.groups.get_mut(&full) {
+                group.memory_events.max += 1;
+            }
+            if !force {
+                return Err(full);
+            }
+        }
+        for id in chain {
+            if let Some(group) = self.groups.get_mut(&id) {
+                group.memory_pages += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Return a page charged to group `id` and its ancestors
+    fn uncharge(&mut self, id: CgroupId) {
+        for id in self.chain(id) {
+            if let Some(group) = self.groups.get_mut(&id) {
+                group.memory_pages = group.memory_pages.saturating_sub(1);
+            }
+        }
+    }
+
+    /// Return the charges of the frames freed since the last call
+    fn drain_uncharges(&mut self) {
+        let freed = core::mem::take(&mut *FREED.lock());
+        for id in freed {
+            self.uncharge(id);
+        }
+    }
+}
+
+static HIERARCHY: Mutex<Hierarchy> = Mutex::new(Hierarchy {
+    groups: BTreeMap::new(),
+    membership: BTreeMap::new(),
+    next_id: ROOT_CGROUP + 1,
+});
+
+/// Group each charged frame is charged to, by physical address
+static CHARGES: Mutex<BTreeMap<u64, CgroupId>> = Mutex::new(BTreeMap::new());
+
+/// Groups of the frames freed whose charges are still to be returned
+///
+/// Frames are freed with the frame allocator locked, so only this and
+/// `CHARGES` may be taken there, never the hierarchy.
+static FREED: Mutex<Vec<CgroupId>> = Mutex::new(Vec::new());
+
+/// Run `f` on the hierarchy, creating the root group on first use and
+/// returning the charges of freed frames first
+fn with_hierarchy<R>(f: impl FnOnce(&mut Hierarchy) -> R) -> R {
+    let mut hierarchy = HIERARCHY.lock();
+    if hierarchy.groups.is_empty() {
+        hierarchy.groups.insert(ROOT_CGROUP, Cgroup::new(String::new(), None));
+    }
+    hierarchy.drain_uncharges();
+    f(&mut hierarchy)
+}
+
+/// Read `f` of group `id`
+fn read_group<R>(id: CgroupId, f: impl FnOnce(&Cgroup) -> R) -> Option<R> {
+    with_hierarchy(|hierarchy| hierarchy.groups.get(&id).map(f))
+}
+
+// ============================================================================
+// Hierarchy and membership
+// ============================================================================
+
+/// Whether group `id` exists
+pub fn exists(id: CgroupId) -> bool {
+    read_group(id, |_| ()).is_some()
+}
+
+/// Group of process `pid`
+pub fn cgroup_of(pid: Pid) -> CgroupId {
+    with_hierarchy(|hierarchy| hierarchy.group_of(pid))
+}
+
+/// Path of group `id` from the root, "/" for the root itself
+pub fn path(id: CgroupId) -> Option<String> {
+    with_hierarchy(|hierarchy| {
+        hierarchy.groups.get(&id)?;
+        let names: Vec<&str> = hierarchy.chain(id)
+            .iter()
+            .rev()
+            .filter_map(|id| hierarchy.groups.get(id))
+            .filter(|group| group.parent.is_some())
+            .map(|group| group.name.as_str())
+            .collect();
+        Some(alloc::format!("/{}", names.join("/")))
+    })
+}
+
+/// Child `name` of group `parent`
+pub fn child(parent: CgroupId, name: &str) -> Option<CgroupId> {
+    read_group(parent, |group| group.children.get(name).copied()).flatten()
+}
+
+/// Children of group `id` with their names
+pub fn children(id: CgroupId) -> Vec<(String, CgroupId)> {
+    read_group(id, |group| group.children.iter().map(|(name, &id)| (name.clone(), id)).collect())
+        .unwrap_or_default()
+}
+
+/// Processes directly in group `id`
+pub fn procs(id: CgroupId) -> Vec<Pid> {
+    read_group(id, |group| group.procs.iter().copied().collect()).unwrap_or_default()
+}
+
+/// Processes in group `id` and its descendants
+pub fn subtree_procs(id: CgroupId) -> Vec<Pid> {
+    with_hierarchy(|hierarchy| {
+        hierarchy.subtree(id)
+            .iter()
+            .filter_map(|id| hierarchy.groups.get(id))
+            .flat_map(|group| group.procs.iter().copied())
+            .collect()
+    })
+}
+
+/// Create group `name` below `parent`
+pub fn create(parent: CgroupId, name: &str) -> Result<CgroupId, CgroupError> {
+    if name.is_empty() || name.contains('/') || name.starts_with("cgroup.") || name == "." || name == ".." {
+        return Err(CgroupError::InvalidArgument);
+    }
+    // Names with a controller prefix would shadow the interface files
+    if CONTROLLERS.iter().any(|&(_, controller)| name.starts_with(controller) && name[controller.len()..].starts_with('.')) {
+        return Err(CgroupError::InvalidArgument);
+    }
+
+    let id = with_hierarchy(|hierarchy| {
+        let group = hierarchy.groups.get(&parent).ok_or(CgroupError::NotFound)?;
+        if group.children.contains_key(name) {
+            return Err(CgroupError::AlreadyExists);
+        }
+        let id = hierarchy.next_id;
+        hierarchy.next_id += 1;
+        hierarchy.groups.insert(id, Cgroup::new(String::from(name), Some(parent)));
+        if let Some(group) = hierarchy.groups.get_mut(&parent) {
+            group.children.insert(String::from(name), id);
+        }
+        Ok(id)
+    })?;
+
+    fair::add_group(id, parent);
+    let _ = fair::set_group_shares(id, weight_to_shares(DEFAULT_CPU_WEIGHT));
+    Ok(id)
+}
+
+/// Remove the empty group `name` below `parent`
+///
+/// Pages still charged to it are charged to the parent from then on.
+pub fn remove(parent: CgroupId, name: &str) -> Result<(), CgroupError> {
+    let id = with_hierarchy(|hierarchy| {
+        let id = *hierarchy.groups.get(&parent)
+            .and_then(|group| group.children.get(name))
+            .ok_or(CgroupError::NotFound)?;
+        let group = &hierarchy.groups[&id];
+        if !group.procs.is_empty() || !group.children.is_empty() {
+            return Err(CgroupError::Busy);
+        }
+        hierarchy.groups.remove(&id);
+        if let Some(group) = hierarchy.groups.get_mut(&parent) {
+            group.children.remove(name);
+        }
+        Ok(id)
+    })?;
+
+    for group in CHARGES.lock().values_mut() {
+        if *group == id {
+            *group = parent;
+        }
+    }
+    fair::remove_group(id);
+    Ok(())
+}
+
+/// Move process `pid` to group `id`
+pub fn attach(pid: Pid, id: CgroupId) -> Result<(), CgroupError> {
+    with_hierarchy(|hierarchy| {
+        if !hierarchy.groups.contains_key(&id) {
+            return Err(CgroupError::NotFound);
+        }
+        let old = hierarchy.group_of(pid);
+        if old == id {
+            return Ok(());
+        }
+        hierarchy.add_pids(old, -1);
+        hierarchy.add_pids(id, 1);
+        hierarchy.set_member(pid, id);
+        Ok(())
+    })?;
+
+    // Kernel-only processes have no scheduler entity
+    let _ = scheduler::set_process_group(pid, id);
+    Ok(())
+}
+
+/// Put a new process in the group of its parent
+///
+/// Fails if that would take the group or an ancestor past `pids.max`. The
+/// scheduler gives the child its parent's fair-class group by itself.
+pub fn fork(parent: Option<Pid>, child: Pid) -> Result<(), CgroupError> {
+    with_hierarchy(|hierarchy| {
+        let id = parent.map_or(ROOT_CGROUP, |parent| hierarchy.group_of(parent));
+        hierarchy.add_process(child, id)
+    })
+}
+
+/// Take an exiting process out of its group
+pub fn exit_process(pid: Pid) {
+    with_hierarchy(|hierarchy| hierarchy.remove_process(pid));
+}
+
+// ============================================================================
+// Controllers
+// ============================================================================
+
+/// Controllers available in group `id`: every one in the root, and those its
+/// parent enables elsewhere
+pub fn controllers(id: CgroupId) -> u8 {
+    with_hierarchy(|hierarchy| {
+        match hierarchy.groups.get(&id).map(|group| group.parent) {
+            Some(None) => CONTROLLERS.iter().fold(0, |all, &(bit, _)| all | bit),
+            Some(Some(parent)) => hierarchy.groups.get(&parent).map_or(0, |parent| parent.subtree_control),
+            None => 0,
+        }
+    })
+}
+
+/// Controllers group `id` enables for its children
+pub fn subtree_control(id: CgroupId) -> u8 {
+    read_group(id, |group| group.subtree_control).unwrap_or(0)
+}
+
+/// Enable and disable controllers for the children of group `id`
+///
+/// Only controllers available in the group can be enabled, and none that a
+/// child still enables for its own children can be disabled. Disabling one
+/// resets the limits it held in the children.
+pub fn set_subtree_control(id: CgroupId, enable: u8, disable: u8) -> Result<(), CgroupError> {
+    let available = controllers(id);
+    if enable & !available != 0 || enable & disable != 0 {
+        return Err(CgroupError::InvalidArgument);
+    }
+
+    let children = with_hierarchy(|hierarchy| {
+        let group = hierarchy.groups.get(&id).ok_or(CgroupError::NotFound)?;
+        let children: Vec<CgroupId> = group.children.values().copied().collect();
+        let disabling = disable & group.subtree_control;
+        let busy = children.iter()
+            .filter_map(|child| hierarchy.groups.get(child))
+            .any(|child| child.subtree_control & disabling != 0);
+        if busy {
+            return Err(CgroupError::Busy);
+        }
+
+        for child in &children {
+            let Some(child) = hierarchy.groups.get_mut(child) else { continue };
+            if disabling & CONTROLLER_CPU != 0 {
+                child.cpu_weight = DEFAULT_CPU_WEIGHT;
+            }
+            if disabling & CONTROLLER_MEMORY != 0 {
+                child.memory_max = None;
+            }
+            if disabling & CONTROLLER_PIDS != 0 {
+                child.pids_max = None;
+            }
+        }
+        if let Some(group) = hierarchy.groups.get_mut(&id) {
+            group.subtree_control = (group.subtree_control | enable) & !disable;
+        }
+        Ok(if disabling & CONTROLLER_CPU != 0 { children } else { Vec::new() })
+    })?;
+
+    for child in children {
+        let _ = fair::set_group_shares(child, weight_to_shares(DEFAULT_CPU_WEIGHT));
+        let _ = fair::set_group_bandwidth(child, None, fair::DEFAULT_BANDWIDTH_PERIOD_NS);
+    }
+    Ok(())
+}
+
+/// Fair-class shares of a group with `cpu.weight` `weight`; the default
+/// weight competes like a nice 0 process
+fn weight_to_shares(weight: u64) -> u64 {
+    (weight * fair::NICE_0_LOAD / DEFAULT_CPU_WEIGHT).max(2)
+}
+
+/// `cpu.weight` of group `id`
+pub fn cpu_weight(id: CgroupId) -> Option<u64> {
+    read_group(id, |group| group.cpu_weight)
+}
+
+/// Set `cpu.weight` of group `id`, from 1 to 10000
+pub fn set_cpu_weight(id: CgroupId, weight: u64) -> Result<(), CgroupError> {
+    if !(MIN_CPU_WEIGHT..=MAX_CPU_WEIGHT).contains(&weight) || id == ROOT_CGROUP {
+        return Err(CgroupError::InvalidArgument);
+    }
+    with_hierarchy(|hierarchy| {
+        let group = hierarchy.groups.get_mut(&id).ok_or(CgroupError::NotFound)?;
+        group.cpu_weight = weight;
+        Ok(())
+    })?;
+    fair::set_group_shares(id, weight_to_shares(weight)).map_err(|_| CgroupError::InvalidArgument)
+}
+
+/// `cpu.max` of group `id`: quota, `None` for no limit, and period in
+/// microseconds
+pub fn cpu_max(id: CgroupId) -> (Option<u64>, u64) {
+    let (quota_ns, period_ns) = fair::group_bandwidth(id);
+    (quota_ns.map(|quota| quota / 1000), period_ns / 1000)
+}
+
+/// Set `cpu.max` of group `id`
+pub fn set_cpu_max(id: CgroupId, quota_us: Option<u64>, period_us: u64) -> Result<(), CgroupError> {
+    if !exists(id) {
+        return Err(CgroupError::NotFound);
+    }
+    let quota_ns = quota_us.map(|quota| quota.saturating_mul(1000));
+    fair::set_group_bandwidth(id, quota_ns, period_us.saturating_mul(1000)).map_err(|_| CgroupError::InvalidArgument)
+}
+
+/// CPU time accounting of group `id` and its descendants
+pub fn cpu_stats(id: CgroupId) -> fair::GroupCpuStats {
+    fair::group_cpu_stats(id)
+}
+
+/// Memory charged to group `id` and its descendants, in bytes
+pub fn memory_current(id: CgroupId) -> Option<u64> {
+    read_group(id, |group| group.memory_pages * PAGE_SIZE)
+}
+
+/// `memory.max` of group `id` in bytes, `None` for no limit
+pub fn memory_max(id: CgroupId) -> Option<u64> {
+    read_group(id, |group| group.memory_max.map(|pages| pages * PAGE_SIZE)).flatten()
+}
+
+/// Set `memory.max` of group `id`, rounded down to whole pages
+///
+/// The parent must enable the memory controller for the group. The caller
+/// brings the group back under a lowered limit, see
+/// `crate::memory::MemoryManager::enforce_cgroup_limit`.
+pub fn set_memory_max(id: CgroupId, max: Option<u64>) -> Result<(), CgroupError> {
+    if id == ROOT_CGROUP {
+        return Err(CgroupError::InvalidArgument);
+    }
+    with_hierarchy(|hierarchy| {
+        if !hierarchy.groups.contains_key(&id) {
+            return Err(CgroupError::NotFound);
+        }
+        if !hierarchy.enables(id, CONTROLLER_MEMORY) {
+            return Err(CgroupError::InvalidArgument);
+        }
+        let group = hierarchy.groups.get_mut(&id).ok_or(CgroupError::NotFound)?;
+        group.memory_max = max.map(|bytes| bytes / PAGE_SIZE);
+        Ok(())
+    })
+}
+
+/// Whether group `id` holds more memory than its `memory.max`
+pub fn memory_over_limit(id: CgroupId) -> bool {
+    read_group(id, |group| group.memory_max.is_some_and(|max| group.memory_pages > max)).unwrap_or(false)
+}
+
+/// `memory.events` of group `id`
+pub fn memory_events(id: CgroupId) -> MemoryEvents {
+    read_group(id, |group| group.memory_events).unwrap_or_default()
+}
+
+/// Charge the frame at `phys`, just allocated for process `pid`, to the
+/// innermost group of its chain with the memory controller and every ancestor
+///
+/// Fails with the innermost group whose `memory.max` it would pass, without
+/// charging anything; with `force` the charge is made anyway. Must not be
+/// called with the frame allocator locked.
+pub fn try_charge_page(pid: Pid, phys: u64, force: bool) -> Result<(), CgroupId> {
+    with_hierarchy(|hierarchy| {
+        let Some(id) = hierarchy.memory_group(pid) else {
+            return Ok(());
+        };
+        hierarchy.charge(id, force)?;
+        CHARGES.lock().insert(phys, id);
+        Ok(())
+    })
+}
+
+/// Return the charge of the frame at `phys` as it is freed
+///
+/// The frame allocator calls this with its lock held, so the charge is only
+/// queued here and returned the next time the hierarchy is used.
+pub fn uncharge_page(phys: u64) {
+    if let Some(id) = CHARGES.lock().remove(&phys) {
+        FREED.lock().push(id);
+    }
+}
+
+/// Count an out-of-memory event in group `id`, and a kill if there was one
+pub fn record_oom(id: CgroupId, killed: bool) {
+    with_hierarchy(|hierarchy| {
+        if let Some(group) = hierarchy.groups.get_mut(&id) {
+            group.memory_events.oom += 1;
+            if killed {
+                group.memory_events.oom_kill += 1;
+            }
+        }
+    });
+}
+
+/// Processes in group `id` and its descendants
+pub fn pids_current(id: CgroupId) -> Option<u64> {
+    read_group(id, |group| group.pids)
+}
+
+/// `pids.max` of group `id`, `None` for no limit
+pub fn pids_max(id: CgroupId) -> Option<u64> {
+    read_group(id, |group| group.pids_max).flatten()
+}
+
+/// Set `pids.max` of group `id`
+pub fn set_pids_max(id: CgroupId, max: Option<u64>) -> Result<(), CgroupError> {
+    if id == ROOT_CGROUP {
+        return Err(CgroupError::InvalidArgument);
+    }
+    with_hierarchy(|hierarchy| {
+        let group = hierarchy.groups.get_mut(&id).ok_or(CgroupError::NotFound)?;
+        group.pids_max = max;
+        Ok(())
+    })
+}
+
+/// Forks group `id` refused for its `pids.max`
+pub fn pids_events_max(id: CgroupId) -> u64 {
+    read_group(id, |group| group.pids_events_max).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Root, a child `a` of it and a grandchild `b` below `a`
+    fn hierarchy() -> (Hierarchy, CgroupId, CgroupId) {
+        let (a, b) = (ROOT_CGROUP + 1, ROOT_CGROUP + 2);
+        let mut hierarchy = Hierarchy {
+            groups: BTreeMap::new(),
+            membership: BTreeMap::new(),
+            next_id: ROOT_CGROUP + 3,
+        };
+        hierarchy.groups.insert(ROOT_CGROUP, Cgroup::new(String::new(), None));
+        hierarchy.groups.insert(a, Cgroup::new(String::from("a"), Some(ROOT_CGROUP)));
+        hierarchy.groups.insert(b, Cgroup::new(String::from("b"), Some(a)));
+        (hierarchy, a, b)
+    }
+
+    fn pages(hierarchy: &Hierarchy, id: CgroupId) -> u64 {
+        hierarchy.groups[&id].memory_pages
+    }
+
+    #[test]
+    fn test_pids_accounting() {
+        let (mut hierarchy, a, b) = hierarchy();
+        hierarchy.add_process(1, b).unwrap();
+        hierarchy.add_process(2, a).unwrap();
+        assert_eq!(hierarchy.groups[&b].pids, 1);
+        assert_eq!(hierarchy.groups[&a].pids, 2);
+        assert_eq!(hierarchy.groups[&ROOT_CGROUP].pids, 2);
+
+        // The limit of an ancestor bounds the whole subtree
+        hierarchy.groups.get_mut(&a).unwrap().pids_max = Some(2);
+        assert_eq!(hierarchy.add_process(3, b), Err(CgroupError::LimitReached));
+        assert_eq!(hierarchy.groups[&a].pids_events_max, 1);
+        assert_eq!(hierarchy.groups[&b].pids, 1);
+        assert_eq!(hierarchy.group_of(3), ROOT_CGROUP);
+
+        hierarchy.remove_process(2);
+        assert_eq!(hierarchy.groups[&a].pids, 1);
+        assert!(!hierarchy.groups[&a].procs.contains(&2));
+        hierarchy.add_process(3, b).unwrap();
+        assert_eq!(hierarchy.group_of(3), b);
+
+        hierarchy.remove_process(1);
+        hierarchy.remove_process(3);
+        for id in [ROOT_CGROUP, a, b] {
+            assert_eq!(hierarchy.groups[&id].pids, 0);
+        }
+    }
+
+    #[test]
+    fn test_memory_charges() {
+        let (mut hierarchy, a, b) = hierarchy();
+        hierarchy.groups.get_mut(&a).unwrap().memory_max = Some(2);
+
+        hierarchy.charge(b, false).unwrap();
+        hierarchy.charge(a, false).unwrap();
+        assert_eq!((pages(&hierarchy, b), pages(&hierarchy, a)), (1, 2));
+        assert_eq!(pages(&hierarchy, ROOT_CGROUP), 2);
+
+        // A full ancestor refuses the charge without charging anything
+        assert_eq!(hierarchy.charge(b, false), Err(a));
+        assert_eq!((pages(&hierarchy, b), pages(&hierarchy, a)), (1, 2));
+        assert_eq!(hierarchy.groups[&a].memory_events.max, 1);
+
+        hierarchy.charge(b, true).unwrap();
+        assert_eq!((pages(&hierarchy, b), pages(&hierarchy, a)), (2, 3));
+        assert_eq!(hierarchy.groups[&a].memory_events.max, 2);
+
+        hierarchy.uncharge(b);
+        hierarchy.uncharge(b);
+        hierarchy.uncharge(a);
+        for id in [ROOT_CGROUP, a, b] {
+            assert_eq!(pages(&hierarchy, id), 0);
+        }
+        // Uncharging never wraps
+        hierarchy.uncharge(b);
+        assert_eq!(pages(&hierarchy, a), 0);
+    }
+
+    #[test]
+    fn test_memory_controller_gating() {
+        let (mut hierarchy, a, b) = hierarchy();
+        hierarchy.add_process(1, b).unwrap();
+        assert_eq!(hierarchy.memory_group(1), None);
+
+        // Enabled in the root only, so `a` takes the charges of `b`
+        hierarchy.groups.get_mut(&ROOT_CGROUP).unwrap().subtree_control = CONTROLLER_MEMORY;
+        assert_eq!(hierarchy.memory_group(1), Some(a));
+        assert!(!hierarchy.enables(b, CONTROLLER_MEMORY));
+
+        hierarchy.groups.get_mut(&a).unwrap().subtree_control = CONTROLLER_MEMORY | CONTROLLER_PIDS;
+        assert_eq!(hierarchy.memory_group(1), Some(b));
+        assert!(!hierarchy.enables(b, CONTROLLER_CPU));
+    }
+}
@@ -6,6 +6,7 @@
 use super::{CpuContext, Pid};
 use core::arch::{asm, naked_asm};
 use core::arch::x86_64::__cpuid;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 /// FPU/SSE state structure
 #[derive(Debug, Clone)]
@@ -92,17 +93,72 @@ pub struct ContextSwitcher {
     fpu_lazy_switching: bool,
     /// Current process that owns the FPU
     fpu_owner: Option<Pid>,
-    /// Context switch statistics
-    switch_count: u64,
+}
+
+/// Context switches on all CPUs
+static SWITCH_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Switch from the running context to `target`
+///
+/// The registers of the caller are saved into `current`, and the call
+/// returns once something switches back to it, possibly on another CPU.
+/// A context that never ran starts at its entry point instead. Nothing here
+/// is shared between CPUs but the switch counter.
+///
+/// # Safety
+///
+/// Interrupts must be disabled, and both contexts must stay where they are
+/// until `current` is resumed.
+pub unsafe fn switch_context(current: *mut ProcessContext, target: *const ProcessContext) {
+    SWITCH_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    // FPU state is switched eagerly, since kernel code uses SSE too
+    save_fpu(&mut (*current).fpu);
+    restore_fpu(&(*target).fpu);
+
+    // Switch page tables if necessary
+    if (*current).page_table != (*target).page_table && (*target).page_table != 0 {
+        asm!("mov cr3, {}", in(reg) (*target).page_table);
+    }
+
+    // Interrupts from user mode must land on the target's kernel stack
+    if (*target).kernel_stack != 0 {
+        crate::gdt::set_kernel_stack(x86_64::VirtAddr::new((*target).kernel_stack));
+    }
+
+    context_switch_asm(&mut (*current).cpu, &(*target).cpu);
+}
+
+/// Save FPU/SSE state with FXSAVE, or FSAVE on processors without SSE
+pub(super) unsafe fn save_fpu(fpu_state: &mut FpuState) {
+    if has_sse() {
+        asm!("fxsave [{}]", in(reg) fpu_state as *mut FpuState);
+    } else {
+        asm!("fsave [{}]", in(reg) fpu_state as *mut FpuState);
+    }
+}
+
+/// Restore FPU/SSE state saved by `save_fpu`
+pub(super) unsafe fn restore_fpu(fpu_state: &FpuState) {
+    if has_sse() {
+        asm!("fxrstor [{}]", in(reg) fpu_state as *const FpuState);
+    } else {
+        asm!("frstor [{}]", in(reg) fpu_state as *const FpuState);
+    }
+}
+
+/// Check if processor has SSE support
+fn has_sse() -> bool {
+    // Check CPUID for SSE support using the intrinsic to avoid clobbering RBX
+    unsafe { (__cpuid(1).edx & (1 << 25)) != 0 }
 }
 
 impl ContextSwitcher {
     /// Create a new context switcher
     pub const fn new() -> Self {
         Self {
-            fpu_lazy_switching: true,
+            fpu_lazy_switching: false,
             fpu_owner: None,
-            switch_count: 0,
         }
     }
 
@@ -119,203 +175,13 @@ impl ContextSwitcher {
         Ok(())
     }
 
-    /// Switch context from current process to target process
-    pub unsafe fn switch_context(
-        &mut self,
-        current_context: &mut ProcessContext,
-        target_context: &ProcessContext,
-        target_pid: Pid,
-    ) -> Result<(), &'static str> {
-        self.switch_count += 1;
-
-        // Save current CPU context
-        self.save_cpu_context(&mut current_context.cpu);
-
-        // Handle FPU context switching
-        if self.fpu_lazy_switching {
-            // Lazy FPU switching - only save/restore when necessary
-            if self.fpu_owner.is_some() {
-                // Clear TS bit to allow FPU access for next process
-                self.clear_task_switched_flag();
-            }
-        } else {
-            // Always save/restore FPU state
-            self.save_fpu_context(&mut current_context.fpu)?;
-            self.restore_fpu_context(&target_context.fpu)?;
-            self.fpu_owner = Some(target_pid);
-        }
-
-        // Switch page tables if necessary
-        if current_context.page_table != target_context.page_table {
-            self.switch_page_table(target_context.page_table);
-        }
-
-        // Switch to kernel stack if necessary
-        if current_context.kernel_stack != target_context.kernel_stack {
-            self.switch_kernel_stack(target_context.kernel_stack);
-        }
-
-        // Restore target CPU context and jump to it
-        self.restore_cpu_context(&target_context.cpu);
-
-        Ok(())
-    }
-
-    /// Save current CPU context
-    unsafe fn save_cpu_context(&self, context: &mut CpuContext) {
-        asm!(
-            "mov {rax}, rax",
-            "mov {rbx}, rbx",
-            "mov {rcx}, rcx",
-            "mov {rdx}, rdx",
-            "mov {rsi}, rsi",
-            "mov {rdi}, rdi",
-            "mov {rbp}, rbp",
-            "mov {r8}, r8",
-            "mov {r9}, r9",
-            "mov {r10}, r10",
-            "mov {r11}, r11",
-            "mov {r12}, r12",
-            "mov {r13}, r13",
-            "mov {r14}, r14",
-            "mov {r15}, r15",
-            rax = out(reg) context.rax,
-            rbx = out(reg) context.rbx,
-            rcx = out(reg) context.rcx,
-            rdx = out(reg) context.rdx,
-            rsi = out(reg) context.rsi,
-            rdi = out(reg) context.rdi,
-            rbp = out(reg) context.rbp,
-            r8 = out(reg) context.r8,
-            r9 = out(reg) context.r9,
-            r10 = out(reg) context.r10,
-            r11 = out(reg) context.r11,
-            r12 = out(reg) context.r12,
-            r13 = out(reg) context.r13,
-            r14 = out(reg) context.r14,
-            r15 = out(reg) context.r15,
-        );
-
-        // Save stack pointer
-        asm!("mov {0:r}, rsp", out(reg) context.rsp);
-
-        // Save flags
-        asm!("pushf; pop {0:r}", out(reg) context.rflags);
-
-        // Save segment registers
-        asm!("mov {0:x}, cs", out(reg) context.cs);
-        asm!("mov {0:x}, ds", out(reg) context.ds);
-        asm!("mov {0:x}, es", out(reg) context.es);
-        asm!("mov {0:x}, fs", out(reg) context.fs);
-        asm!("mov {0:x}, gs", out(reg) context.gs);
-        asm!("mov {0:x}, ss", out(reg) context.ss);
-    }
-
-    /// Restore CPU context
-    unsafe fn restore_cpu_context(&self, context: &CpuContext) {
-        // Restore segment registers
-        asm!(
-            "mov ds, {0:x}",
-            "mov es, {1:x}",
-            "mov fs, {2:x}",
-            "mov gs, {3:x}",
-            in(reg) context.ds,
-            in(reg) context.es,
-            in(reg) context.fs,
-            in(reg) context.gs,
-        );
-
-        // Restore general purpose registers
-        asm!(
-            "mov rax, {rax}",
-            "mov rbx, {rbx}",
-            "mov rcx, {rcx}",
-            "mov rdx, {rdx}",
-            "mov rsi, {rsi}",
-            "mov rdi, {rdi}",
-            "mov rbp, {rbp}",
-            "mov r8, {r8}",
-            "mov r9, {r9}",
-            "mov r10, {r10}",
-            "mov r11, {r11}",
-            "mov r12, {r12}",
-            "mov r13, {r13}",
-            "mov r14, {r14}",
-            "mov r15, {r15}",
-            rax = in(reg) context.rax,
-            rbx = in(reg) context.rbx,
-            rcx = in(reg) context.rcx,
-            rdx = in(reg) context.rdx,
-            rsi = in(reg) context.rsi,
-            rdi = in(reg) context.rdi,
-            rbp = in(reg) context.rbp,
-            r8 = in(reg) context.r8,
-            r9 = in(reg) context.r9,
-            r10 = in(reg) context.r10,
-            r11 = in(reg) context.r11,
-            r12 = in(reg) context.r12,
-            r13 = in(reg) context.r13,
-            r14 = in(reg) context.r14,
-            r15 = in(reg) context.r15,
-        );
-
-        // Restore stack pointer and flags
-        asm!(
-            "mov rsp, {}",
-            "push {}",
-            "popf",
-            in(reg) context.rsp,
-            in(reg) context.rflags,
-        );
-    }
-
-    /// Save FPU/SSE context
-    unsafe fn save_fpu_context(&self, fpu_state: &mut FpuState) -> Result<(), &'static str> {
-        // Check if we have SSE support
-        if self.has_sse() {
-            // Use FXSAVE to save FPU and SSE state
-            asm!(
-                "fxsave [{}]",
-                in(reg) fpu_state as *mut FpuState,
-            );
-        } else {
-            // Fall back to FSAVE for older processors
-            asm!(
-                "fsave [{}]",
-                in(reg) fpu_state as *mut FpuState,
-            );
-        }
-
-        Ok(())
-    }
-
-    /// Restore FPU/SSE context
-    unsafe fn restore_fpu_context(&self, fpu_state: &FpuState) -> Result<(), &'static str> {
-        // Check if we have SSE support
-        if self.has_sse() {
-            // Use FXRSTOR to restore FPU and SSE state
-            asm!(
-                "fxrstor [{}]",
-                in(reg) fpu_state as *const FpuState,
-            );
-        } else {
-            // Fall back to FRSTOR for older processors
-            asm!(
-                "frstor [{}]",
-                in(reg) fpu_state as *const FpuState,
-            );
-        }
-
-        Ok(())
-    }
-
     /// Initialize FPU
     unsafe fn init_fpu(&self) -> Result<(), &'static str> {
         // Initialize FPU
         asm!("finit");
 
         // Enable FPU and SSE if available
-        if self.has_sse() {
+        if has_sse() {
             // Enable SSE and FXSAVE/FXRSTOR
             let mut cr4: u64;
             asm!("mov {0:r}, cr4", out(reg) cr4);
@@ -329,12 +195,6 @@ impl ContextSwitcher {
         Ok(())
     }
 
-    /// Check if processor has SSE support
-    fn has_sse(&self) -> bool {
-        // Check CPUID for SSE support using the intrinsic to avoid clobbering RBX
-        unsafe { (__cpuid(1).edx & (1 << 25)) != 0 }
-    }
-
     /// Check if processor has XSAVE support
     fn has_xsave(&self) -> bool {
         unsafe { (__cpuid(1).ecx & (1 << 26)) != 0 }
@@ -365,26 +225,6 @@ impl ContextSwitcher {
         asm!("clts");
     }
 
-    /// Switch page table
-    unsafe fn switch_page_table(&self, page_table_phys: u64) {
-        if page_table_phys != 0 {
-            asm!(
-                "mov cr3, {}",
-                in(reg) page_table_phys,
-            );
-        }
-    }
-
-    /// Switch kernel stack
-    unsafe fn switch_kernel_stack(&self, kernel_stack: u64) {
-        if kernel_stack != 0 {
-            // Update TSS kernel stack pointer
-            // This would typically involve updating the TSS structure
-            // For now, we'll just note the stack change
-            // In a full implementation, this would update the appropriate TSS field
-        }
-    }
-
     /// Handle FPU exception (for lazy switching)
     pub unsafe fn handle_fpu_exception(&mut self, current_pid: Pid, context: &ProcessContext) -> Result<(), &'static str> {
         if self.fpu_lazy_switching {
@@ -400,7 +240,7 @@ impl ContextSwitcher {
             }
 
             // Restore FPU state for current process
-            self.restore_fpu_context(&context.fpu)?;
+            restore_fpu(&context.fpu);
             self.fpu_owner = Some(current_pid);
         }
 
@@ -409,7 +249,7 @@ impl ContextSwitcher {
 
     /// Get context switch statistics
     pub fn get_switch_count(&self) -> u64 {
-        self.switch_count
+        SWITCH_COUNT.load(Ordering::Relaxed)
     }
 
     /// Enable or disable FPU lazy switching
@@ -428,7 +268,9 @@ pub struct ContextSwitcherStats {
 }
 
 /// Assembly function for low-level context switch
-/// This would typically be implemented in assembly for maximum efficiency
+///
+/// Saves the caller's registers into `old_context` as if the call had already
+/// returned, so resuming it continues right after the call.
 #[unsafe(naked)]
 pub unsafe extern "C" fn context_switch_asm(
     _old_context: *mut CpuContext,
@@ -443,7 +285,8 @@ pub unsafe extern "C" fn context_switch_asm(
         mov [rdi + 0x20], rsi
         mov [rdi + 0x28], rdi
         mov [rdi + 0x30], rbp
-        mov [rdi + 0x38], rsp
+        lea rax, [rsp + 8]
+        mov [rdi + 0x38], rax
         mov [rdi + 0x40], r8
         mov [rdi + 0x48], r9
         mov [rdi + 0x50], r10
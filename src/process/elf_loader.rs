@@ -5,16 +5,18 @@
 //! - ASLR (Address Space Layout Randomization)
 //! - NX bit enforcement (No-Execute protection)
 //! - W^X enforcement (Write XOR Execute)
-//! - Stack guard pages
+//! - Stacks that grow on demand, with a guard gap below
 //! - Robust error handling
 
 use alloc::vec::Vec;
 use x86_64::{VirtAddr, PhysAddr};
 use crate::memory::{
     MemoryRegionType, MemoryProtection, VirtualMemoryRegion, MemoryError,
-    allocate_memory, allocate_memory_with_guards, protect_memory,
+    allocate_memory, protect_memory,
     translate_addr, align_up, PAGE_SIZE, USER_SPACE_START, USER_SPACE_END,
 };
+use crate::memory::aslr::{self, Layout};
+use crate::memory::stack;
 use crate::process::Pid;
 use core::fmt;
 
@@ -354,15 +356,14 @@ impl ElfLoader {
     }
 
     /// Calculate base address with optional ASLR
-    fn calculate_base_address(&self, elf_type: u16) -> VirtAddr {
-        let base = VirtAddr::new(USER_SPACE_START as u64);
-
-        if self.enable_aslr {
-            // Use ASLR offset from memory module
-            let offset = crate::memory::generate_aslr_offset();
-            VirtAddr::new(base.as_u64() + offset)
+    ///
+    /// Only position-independent executables can take the random load bias
+    /// of `layout`; others are linked for a fixed address.
+    fn calculate_base_address(&self, elf_type: u16, layout: &Layout) -> VirtAddr {
+        if self.enable_aslr && elf_type == elf_constants::ET_DYN {
+            VirtAddr::new(layout.load_bias)
         } else {
-            base
+            VirtAddr::new(USER_SPACE_START as u64)
         }
     }
 
@@ -433,6 +434,10 @@ impl ElfLoader {
             physical_start: translate_addr(region_start).map(|p| p),
             reference_count: 1,
             aslr_offset: base_address.as_u64(),
+            locked: false,
+            thp: crate::memory::huge_page::ThpAdvice::Default,
+            mempolicy: crate::memory::numa::MemPolicy::Default,
+            owner: crate::process::current_pid(),
         })
     }
 
@@ -440,7 +445,7 @@ impl ElfLoader {
     pub fn load_elf_binary(
         &self,
         binary_data: &[u8],
-        _process_id: Pid,
+        process_id: Pid,
     ) -> Result<LoadedBinary, ElfLoaderError> {
         // Parse and validate header
         let elf_header = self.parse_elf_header(binary_data)?;
@@ -450,8 +455,10 @@ impl ElfLoader {
         let program_headers = self.parse_program_headers(binary_data, &elf_header)?;
         self.validate_program_headers(&program_headers, binary_data.len())?;
 
-        // Calculate base address with ASLR
-        let base_address = self.calculate_base_address(elf_header.e_type);
+        // Calculate base address with ASLR; the new image gets a fresh
+        // layout for its heap, mappings and stack too
+        let layout = aslr::new_layout(process_id);
+        let base_address = self.calculate_base_address(elf_header.e_type, &layout);
 
         // Load all PT_LOAD segments
         let mut code_regions = Vec::new();
@@ -481,6 +488,7 @@ impl ElfLoader {
         }
 
         // Allocate heap (8KB initial size, after loaded segments)
+        aslr::set_brk_base(process_id, max_addr);
         let heap_size = 8 * 1024;
         let heap_start = allocate_memory(
             heap_size,
@@ -488,15 +496,11 @@ impl ElfLoader {
             MemoryProtection::USER_DATA,
         ).map_err(|_| ElfLoaderError::MemoryAllocationFailed)?;
 
-        // Allocate stack with guard pages (8MB)
-        let stack_size = 8 * 1024 * 1024;
-        let stack_bottom = allocate_memory_with_guards(
-            stack_size,
-            MemoryRegionType::UserStack,
-            MemoryProtection::USER_DATA,
-        ).map_err(|_| ElfLoaderError::MemoryAllocationFailed)?;
+        // Allocate the stack; it grows on faults below it, up to RLIMIT_STACK
+        let stack_bottom = stack::map_main_stack(process_id, layout.stack_top)
+            .map_err(|_| ElfLoaderError::MemoryAllocationFailed)?;
 
-        let stack_top = VirtAddr::new(stack_bottom.as_u64() + stack_size as u64);
+        let stack_top = VirtAddr::new(stack_bottom.as_u64() + stack::INITIAL_STACK_SIZE as u64);
 
         // Calculate entry point
         let entry_point = VirtAddr::new(base_address.as_u64() + elf_header.e_entry);
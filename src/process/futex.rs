@@ -0,0 +1,289 @@
+//! Futexes
+//!
+//! Waiters queue on the futex word they wait for and block in
+//! `crate::scheduler` until a wake, a signal or their timeout. Private futexes
+//! are keyed by process and address; shared ones by the physical address of
+//! the word, so processes mapping the same page meet on the same queue.
+//!
+//! The compare-and-enqueue in `wait` and every wake run under the table lock,
+//! which is what keeps a wake between the user's check and the syscall from
+//! being lost. Wakeups may come from the timer interrupt, so the table is only
+//! locked with interrupts off.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use x86_64::VirtAddr;
+
+use super::{Pid, current_pid};
+use crate::interrupts::without_interrupts;
+use crate::scheduler;
+use crate::time;
+
+/// Bitset matching every waiter
+pub const BITSET_MATCH_ANY: u32 = u32::MAX;
+
+/// Errors from futex operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexError {
+    /// The futex word did not hold the expected value
+    WouldBlock,
+    /// The timeout passed before a wake
+    TimedOut,
+    /// A signal arrived for the waiter
+    Interrupted,
+    /// The futex word is not mapped
+    Fault,
+    /// Misaligned futex word or empty bitset
+    InvalidArgument,
+}
+
+/// Identity of a futex word
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FutexKey {
+    /// Word visible to one process only
+    Private { pid: Pid, addr: u64 },
+    /// Word that may be mapped by several processes
+    Shared { phys: u64 },
+}
+
+impl FutexKey {
+    /// Key of the word at `addr` in the current address space
+    pub fn new(pid: Pid, addr: u64, private: bool) -> Result<Self, FutexError> {
+        if addr == 0 {
+            return Err(FutexError::Fault);
+        }
+        if addr % 4 != 0 {
+            return Err(FutexError::InvalidArgument);
+        }
+        if private {
+            return Ok(FutexKey::Private { pid, addr });
+        }
+        crate::memory::translate_addr(VirtAddr::new(addr))
+            .map(|phys| FutexKey::Shared { phys: phys.as_u64() })
+            .ok_or(FutexError::Fault)
+    }
+}
+
+struct Waiter {
+    pid: Pid,
+    bitset: u32,
+    /// Set by the waker before it unblocks `pid`
+    woken: Arc<AtomicBool>,
+}
+
+static FUTEXES: Mutex<BTreeMap<FutexKey, VecDeque<Waiter>>> = Mutex::new(BTreeMap::new());
+
+/// Block the current process while the word at `word` holds `expected`
+///
+/// Only wakes whose bitset intersects `bitset` end the wait. `deadline_ns` is
+/// on the monotonic clock.
+pub fn wait(
+    key: FutexKey,
+    word: *const u32,
+    expected: u32,
+    bitset: u32,
+    deadline_ns: Option<u64>,
+) -> Result<(), FutexError> {
+    if bitset == 0 {
+        return Err(FutexError::InvalidArgument);
+    }
+
+    // Touch the word first so that the read under the lock cannot fault
+    let current = unsafe { core::ptr::read_volatile(word) };
+    if current != expected {
+        return Err(FutexError::WouldBlock);
+    }
+
+    let pid = current_pid();
+    let woken = Arc::new(AtomicBool::new(false));
+
+    without_interrupts(|| {
+        let mut futexes = FUTEXES.lock();
+        if unsafe { core::ptr::read_volatile(word) } != expected {
+            return Err(FutexError::WouldBlock);
+        }
+        futexes.entry(key).or_default().push_back(Waiter {
+            pid,
+            bitset,
+            woken: woken.clone(),
+        });
+        Ok(())
+    })?;
+
+    let wakeup = deadline_ns.map(|deadline| super::timers::arm_wakeup(pid, deadline));
+
+    let result = loop {
+        if woken.load(Ordering::Acquire) {
+            break Ok(());
+        }
+        if deadline_ns.map_or(false, |deadline| time::uptime_ns() >= deadline) {
+            break Err(FutexError::TimedOut);
+        }
+        if pid != 0 && super::ipc::has_pending_signals(pid) {
+            break Err(FutexError::Interrupted);
+        }
+
+        // Kernel context (pid 0) has no schedulable entity; idle until the
+        // next interrupt instead.
+        if pid == 0 || scheduler::block_current().is_err() {
+            idle();
+        }
+    };
+
+    if let Some(wakeup) = wakeup {
+        super::timers::cancel_wakeup(wakeup);
+    }
+
+    match result {
+        // A waker that dequeued us before we could leave consumed its wake on
+        // us, so report it rather than drop it
+        Err(_) if !dequeue(&woken) => Ok(()),
+        result => result,
+    }
+}
+
+/// Wake up to `count` waiters on `key` whose bitset intersects `bitset`
+///
+/// Returns the number of processes woken.
+pub fn wake(key: FutexKey, count: usize, bitset: u32) -> Result<usize, FutexError> {
+    if bitset == 0 {
+        return Err(FutexError::InvalidArgument);
+    }
+
+    let pids = without_interrupts(|| {
+        let mut futexes = FUTEXES.lock();
+        let mut pids = Vec::new();
+        if let Some(queue) = futexes.get_mut(&key) {
+            take_waiters(queue, count, bitset, &mut pids);
+            if queue.is_empty() {
+                futexes.remove(&key);
+            }
+        }
+        pids
+    });
+
+    Ok(unblock(pids))
+}
+
+/// Wake up to `wake_count` waiters on `key` and move up to `requeue_count` of
+/// the rest to `target`
+///
+/// With `expected` set, fails with `WouldBlock` unless the word at `word`
+/// holds that value. Returns the number of waiters woken plus requeued.
+pub fn requeue(
+    key: FutexKey,
+    target: FutexKey,
+    wake_count: usize,
+    requeue_count: usize,
+    expected: Option<(*const u32, u32)>,
+) -> Result<usize, FutexError> {
+    if let Some((word, value)) = expected {
+        if unsafe { core::ptr::read_volatile(word) } != value {
+            return Err(FutexError::WouldBlock);
+        }
+    }
+
+    let (pids, moved) = without_interrupts(|| {
+        let mut futexes = FUTEXES.lock();
+        if let Some((word, value)) = expected {
+            if unsafe { core::ptr::read_volatile(word) } != value {
+                return Err(FutexError::WouldBlock);
+            }
+        }
+
+        let mut pids = Vec::new();
+        let mut moved = VecDeque::new();
+        if let Some(queue) = futexes.get_mut(&key) {
+            take_waiters(queue, wake_count, BITSET_MATCH_ANY, &mut pids);
+            let n = requeue_count.min(queue.len());
+            moved.extend(queue.drain(..n));
+            if queue.is_empty() {
+                futexes.remove(&key);
+            }
+        }
+
+        let count = moved.len();
+        if count > 0 {
+            futexes.entry(target).or_default().extend(moved);
+        }
+        Ok((pids, count))
+    })?;
+
+    Ok(unblock(pids) + moved)
+}
+
+/// Drop the waiters and private futexes of an exiting process
+pub fn exit_process(pid: Pid) {
+    without_interrupts(|| {
+        let mut futexes = FUTEXES.lock();
+        futexes.retain(|key, queue| {
+            if matches!(key, FutexKey::Private { pid: owner, .. } if *owner == pid) {
+                return false;
+            }
+            queue.retain(|waiter| waiter.pid != pid);
+            !queue.is_empty()
+        });
+    });
+}
+
+/// Number of processes waiting on `key`
+pub fn waiters(key: FutexKey) -> usize {
+    without_interrupts(|| FUTEXES.lock().get(&key).map_or(0, |queue| queue.len()))
+}
+
+/// Move up to `count` matching waiters out of `queue`, marking them woken
+fn take_waiters(queue: &mut VecDeque<Waiter>, count: usize, bitset: u32, pids: &mut Vec<Pid>) {
+    queue.retain(|waiter| {
+        if pids.len() < count && waiter.bitset & bitset != 0 {
+            waiter.woken.store(true, Ordering::Release);
+            pids.push(waiter.pid);
+            false
+        } else {
+            true
+        }
+    });
+}
+
+/// Remove the waiter owning `woken` from whichever queue it is on
+///
+/// Returns false if a waker already took it.
+fn dequeue(woken: &Arc<AtomicBool>) -> bool {
+    without_interrupts(|| {
+        let mut futexes = FUTEXES.lock();
+        let found = futexes.iter_mut().find_map(|(key, queue)| {
+            let index = queue.iter().position(|waiter| Arc::ptr_eq(&waiter.woken, woken))?;
+            queue.remove(index);
+            Some((*key, queue.is_empty()))
+        });
+        match found {
+            Some((key, true)) => {
+                futexes.remove(&key);
+                true
+            }
+            Some(_) => true,
+            None => false,
+        }
+    })
+}
+
+fn unblock(pids: Vec<Pid>) -> usize {
+    let count = pids.len();
+    for pid in pids {
+        if pid != 0 {
+            let _ = scheduler::unblock_process(pid);
+        }
+    }
+    count
+}
+
+/// Wait for the next interrupt, or spin if interrupts are masked
+fn idle() {
+    if x86_64::instructions::interrupts::are_enabled() {
+        x86_64::instructions::hlt();
+    } else {
+        core::hint::spin_loop();
+    }
+}
@@ -32,24 +32,14 @@ impl TimerIntegration {
         let thread_manager = super::thread::get_thread_manager();
         thread_manager.wake_sleeping_threads();
 
-        // Update scheduler tick
-        let process_manager = get_process_manager();
-        {
-            let mut scheduler = process_manager.scheduler.lock();
-            scheduler.tick();
-        }
-
+        // Preemption itself is up to `crate::scheduler`, which the timer
+        // interrupt ticks directly
         self.time_slice_counter += 1;
-
-        // Check if we should perform scheduling
         if self.time_slice_counter >= self.schedule_frequency {
             self.time_slice_counter = 0;
-
-            // Trigger process scheduling
-            process_manager.schedule()
-        } else {
-            Ok(None)
         }
+
+        Ok(None)
     }
 
     /// Set scheduling frequency
@@ -337,7 +327,18 @@ impl InterruptIntegration {
     }
 
     /// Handle signal delivery to process
+    ///
+    /// A traced process stops for its tracer instead, for any signal but
+    /// SIGKILL, and gets the signal only if the tracer passes it on.
     pub fn deliver_signal(pid: Pid, signal: u32) -> Result<(), &'static str> {
+        if signal != 9 && super::ptrace::signal_stop(pid, signal) {
+            return Ok(());
+        }
+        Self::deliver_untraced(pid, signal)
+    }
+
+    /// Deliver a signal without stopping a tracee for its tracer
+    pub fn deliver_untraced(pid: Pid, signal: u32) -> Result<(), &'static str> {
         let process_manager = get_process_manager();
         let ipc_manager = super::ipc::get_ipc_manager();
 
@@ -366,6 +367,9 @@ impl InterruptIntegration {
             18 => super::ipc::Signal::SIGCONT,
             19 => super::ipc::Signal::SIGSTOP,
             20 => super::ipc::Signal::SIGTSTP,
+            26 => super::ipc::Signal::SIGVTALRM,
+            27 => super::ipc::Signal::SIGPROF,
+            31 => super::ipc::Signal::SIGSYS,
             _ => return Err("Invalid signal number"),
         };
 
@@ -44,6 +44,45 @@ pub enum Signal {
     SIGCONT = 18,   // Continue
     SIGSTOP = 19,   // Stop (cannot be caught)
     SIGTSTP = 20,   // Terminal stop
+    SIGVTALRM = 26, // Virtual alarm clock
+    SIGPROF = 27,   // Profiling alarm clock
+    SIGSYS = 31,    // Bad system call
+}
+
+impl Signal {
+    /// Signal for a Linux signal number
+    pub fn from_number(signo: i32) -> Option<Signal> {
+        Some(match signo {
+            1 => Signal::SIGHUP,
+            2 => Signal::SIGINT,
+            3 => Signal::SIGQUIT,
+            4 => Signal::SIGILL,
+            5 => Signal::SIGTRAP,
+            6 => Signal::SIGABRT,
+            7 => Signal::SIGBUS,
+            8 => Signal::SIGFPE,
+            9 => Signal::SIGKILL,
+            10 => Signal::SIGUSR1,
+            11 => Signal::SIGSEGV,
+            12 => Signal::SIGUSR2,
+            13 => Signal::SIGPIPE,
+            14 => Signal::SIGALRM,
+            15 => Signal::SIGTERM,
+            17 => Signal::SIGCHLD,
+            18 => Signal::SIGCONT,
+            19 => Signal::SIGSTOP,
+            20 => Signal::SIGTSTP,
+            26 => Signal::SIGVTALRM,
+            27 => Signal::SIGPROF,
+            31 => Signal::SIGSYS,
+            _ => return None,
+        })
+    }
+}
+
+/// Bit for a signal in a signal mask
+pub fn signal_bit(signal: Signal) -> u64 {
+    1u64 << (signal as u8)
 }
 
 /// Signal disposition
@@ -589,6 +628,16 @@ impl IpcManager {
         Ok(msgq_id)
     }
 
+    /// Remove message queue, dropping the messages still in it
+    pub fn remove_message_queue(&self, msgq_id: IpcId) -> Result<(), &'static str> {
+        if self.message_queues.write().remove(&msgq_id).is_some() {
+            self.msgq_count.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        } else {
+            Err("Message queue not found")
+        }
+    }
+
     /// Send message to queue
     pub fn send_message(
         &self,
@@ -624,24 +673,40 @@ impl IpcManager {
 
     /// Send signal to process
     pub fn send_signal(&self, target_pid: Pid, signal: Signal, sender_pid: Pid) -> Result<(), &'static str> {
+        self.send_signal_with_data(target_pid, signal, sender_pid, 0)
+    }
+
+    /// Send signal to process carrying a data word (sigev_value, sigqueue)
+    pub fn send_signal_with_data(
+        &self,
+        target_pid: Pid,
+        signal: Signal,
+        sender_pid: Pid,
+        data: u64,
+    ) -> Result<(), &'static str> {
         let signal_info = SignalInfo {
             signal,
             sender: sender_pid,
             timestamp: get_system_time(),
-            data: 0,
+            data,
         };
 
-        let mut signal_states = self.signal_states.write();
-        if let Some(state) = signal_states.get_mut(&target_pid) {
-            // Check if signal is blocked
-            let signal_bit = 1u64 << (signal as u8);
-            if state.mask & signal_bit == 0 {
+        {
+            let mut signal_states = self.signal_states.write();
+            let state = signal_states.get_mut(&target_pid).ok_or("Target process not found")?;
+
+            // Blocked signals stay pending until unblocked or taken through
+            // signalfd; standard signals are not queued twice
+            if !state.pending.iter().any(|info| info.signal == signal) {
                 state.pending.push(signal_info);
             }
-            Ok(())
-        } else {
-            Err("Target process not found")
         }
+
+        // Interrupt blocking waits and wake signalfd pollers
+        crate::vfs::poll::notify_event(crate::vfs::poll::EventSource::Signals(target_pid));
+        let _ = crate::scheduler::unblock_process(target_pid);
+
+        Ok(())
     }
 
     /// Set signal handler
@@ -660,6 +725,25 @@ impl IpcManager {
         }
     }
 
+    /// Send a signal the target can't block or ignore, as for a fault
+    ///
+    /// The signal is unblocked and an ignored disposition reset to the
+    /// default. Returns the disposition it is delivered with.
+    pub fn force_signal(&self, target_pid: Pid, signal: Signal, data: u64) -> Result<SignalDisposition, &'static str> {
+        let disposition = {
+            let mut signal_states = self.signal_states.write();
+            let state = signal_states.get_mut(&target_pid).ok_or("Target process not found")?;
+            state.mask &= !signal_bit(signal);
+            let disposition = state.handlers.entry(signal).or_insert(SignalDisposition::Default);
+            if *disposition == SignalDisposition::Ignore {
+                *disposition = SignalDisposition::Default;
+            }
+            *disposition
+        };
+        self.send_signal_with_data(target_pid, signal, target_pid, data)?;
+        Ok(disposition)
+    }
+
     /// Set signal mask
     pub fn set_signal_mask(&self, pid: Pid, mask: u64) -> Result<u64, &'static str> {
         let mut signal_states = self.signal_states.write();
@@ -672,18 +756,59 @@ impl IpcManager {
         }
     }
 
-    /// Get pending signals for process
+    /// Take the pending signals that can be delivered now
+    ///
+    /// Signals blocked by the mask stay queued.
     pub fn get_pending_signals(&self, pid: Pid) -> Vec<SignalInfo> {
         let mut signal_states = self.signal_states.write();
         if let Some(state) = signal_states.get_mut(&pid) {
-            let pending = state.pending.clone();
-            state.pending.clear();
-            pending
+            let mask = state.mask;
+            let (blocked, deliverable) = core::mem::take(&mut state.pending)
+                .into_iter()
+                .partition(|info| mask & signal_bit(info.signal) != 0);
+            state.pending = blocked;
+            deliverable
         } else {
             Vec::new()
         }
     }
 
+    /// Check whether a process has deliverable, non-ignored signals without consuming them
+    pub fn has_pending_signals(&self, pid: Pid) -> bool {
+        let signal_states = self.signal_states.read();
+        signal_states.get(&pid).map_or(false, |state| {
+            state.pending.iter().any(|info| {
+                state.mask & signal_bit(info.signal) == 0
+                    && state.handlers.get(&info.signal) != Some(&SignalDisposition::Ignore)
+            })
+        })
+    }
+
+    /// Check whether any signal in `set` is pending, blocked or not
+    pub fn has_signals_in(&self, pid: Pid, set: u64) -> bool {
+        let signal_states = self.signal_states.read();
+        signal_states.get(&pid).map_or(false, |state| {
+            state.pending.iter().any(|info| set & signal_bit(info.signal) != 0)
+        })
+    }
+
+    /// Remove up to `max` pending signals that are in `set`, oldest first
+    pub fn dequeue_signals(&self, pid: Pid, set: u64, max: usize) -> Vec<SignalInfo> {
+        let mut signal_states = self.signal_states.write();
+        let mut taken = Vec::new();
+        if let Some(state) = signal_states.get_mut(&pid) {
+            let mut i = 0;
+            while i < state.pending.len() && taken.len() < max {
+                if set & signal_bit(state.pending[i].signal) != 0 {
+                    taken.push(state.pending.remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        taken
+    }
+
     /// Initialize signal state for new process
     pub fn init_process_signals(&self, pid: Pid) -> Result<(), &'static str> {
         let mut signal_states = self.signal_states.write();
@@ -808,4 +933,9 @@ pub fn set_signal_handler(
     disposition: SignalDisposition,
 ) -> Result<(), &'static str> {
     IPC_MANAGER.set_signal_handler(pid, signal, disposition)
-}
\ No newline at end of file
+}
+
+/// Check whether a process has undelivered signals
+pub fn has_pending_signals(pid: Pid) -> bool {
+    IPC_MANAGER.has_pending_signals(pid)
+}
@@ -3,13 +3,13 @@
 //! This module provides comprehensive process management functionality for RustOS,
 //! including process control blocks, scheduling, system calls, and context switching.
 
+use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 use alloc::string::{String, ToString};
 use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
-use spin::{Mutex, RwLock};
+use spin::RwLock;
 
-pub mod scheduler;
 pub mod syscalls;
 pub mod context;
 pub mod sync;
@@ -18,6 +18,14 @@ pub mod thread;
 pub mod ipc;
 pub mod elf_loader;
 pub mod dynamic_linker;
+pub mod wait_queue;
+pub mod timers;
+pub mod futex;
+pub mod rlimit;
+pub mod cgroup;
+pub mod namespace;
+pub mod seccomp;
+pub mod ptrace;
 
 /// Process ID type
 pub type Pid = u32;
@@ -46,6 +54,7 @@ pub enum ProcessState {
 
 /// Process priority levels
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
 pub enum Priority {
     /// Real-time priority (highest)
     RealTime = 0,
@@ -175,7 +184,7 @@ pub struct ProcessControlBlock {
     pub memory: MemoryInfo,
     /// Process name
     pub name: [u8; 32],
-    /// CPU time used (in ticks)
+    /// CPU time used (in nanoseconds)
     pub cpu_time: u64,
     /// Time when process was created
     pub creation_time: u64,
@@ -291,6 +300,41 @@ impl FileDescriptor {
         }
     }
 
+    /// Create a descriptor for an entry of the VFS open-file table
+    pub fn open_file(fd: i32, flags: u32) -> Self {
+        Self {
+            fd_type: FileDescriptorType::OpenFile { fd },
+            flags,
+            offset: 0,
+        }
+    }
+
+    /// Entry of the VFS open-file table behind this descriptor, for pipes
+    pub fn open_file_fd(&self) -> Option<i32> {
+        match &self.fd_type {
+            FileDescriptorType::OpenFile { fd } => Some(*fd),
+            _ => None,
+        }
+    }
+
+    /// Copy of this descriptor for a forked child
+    ///
+    /// An entry of the VFS open-file table is duplicated there, keeping its
+    /// close-on-exec flag, so that either process closing its copy leaves the
+    /// other's open.
+    pub fn fork_copy(&self) -> Option<Self> {
+        let Some(fd) = self.open_file_fd() else {
+            return Some(self.clone());
+        };
+        let vfs = crate::vfs::get_vfs();
+        let cloexec = vfs.cloexec(fd).ok()?;
+        let fd = vfs.dup_min(fd, 0, cloexec).ok()?;
+        Some(Self {
+            fd_type: FileDescriptorType::OpenFile { fd },
+            ..self.clone()
+        })
+    }
+
     /// Get the VFS inode if this is a VFS file
     pub fn inode(&self) -> Option<&crate::fs::Inode> {
         match &self.fd_type {
@@ -318,6 +362,8 @@ pub enum FileDescriptorType {
     VfsFile { inode: crate::fs::Inode },
     Socket { socket_id: u32 },
     Pipe { pipe_id: u32 },
+    /// An entry of the `crate::vfs` open-file table, numbered there
+    OpenFile { fd: i32 },
 }
 
 /// Scheduling-specific information
@@ -444,19 +490,19 @@ impl ProcessControlBlock {
 }
 
 /// Process Manager - central coordinator for all process operations
+///
+/// Run state (ready, running, blocked) belongs to `crate::scheduler`; the
+/// manager registers processes with it and asks it what is running.
 pub struct ProcessManager {
-    /// All processes in the system
-    processes: RwLock<BTreeMap<Pid, ProcessControlBlock>>,
-    /// Currently running process ID
-    current_process: AtomicU32,
+    /// All processes in the system, boxed so control blocks come from the
+    /// task_struct slab cache
+    processes: RwLock<BTreeMap<Pid, Box<ProcessControlBlock>>>,
     /// Next PID to allocate
     next_pid: AtomicU32,
     /// Process count
     process_count: AtomicUsize,
-    /// Scheduler instance
-    scheduler: Mutex<scheduler::Scheduler>,
     /// System call dispatcher
-    syscall_dispatcher: Mutex<syscalls::SyscallDispatcher>,
+    syscall_dispatcher: syscalls::SyscallDispatcher,
 }
 
 impl ProcessManager {
@@ -464,33 +510,26 @@ impl ProcessManager {
     pub const fn new() -> Self {
         Self {
             processes: RwLock::new(BTreeMap::new()),
-            current_process: AtomicU32::new(0),
             next_pid: AtomicU32::new(1),
             process_count: AtomicUsize::new(0),
-            scheduler: Mutex::new(scheduler::Scheduler::new()),
-            syscall_dispatcher: Mutex::new(syscalls::SyscallDispatcher::new()),
+            syscall_dispatcher: syscalls::SyscallDispatcher::new(),
         }
     }
 
     /// Initialize the process manager with kernel process
+    ///
+    /// The kernel process (PID 0) is not scheduled: it is whatever runs on a
+    /// CPU while the scheduler has nothing else for it.
     pub fn init(&self) -> Result<(), &'static str> {
         // Create kernel process (PID 0)
         let kernel_pcb = ProcessControlBlock::new(0, None, "kernel");
 
         {
             let mut processes = self.processes.write();
-            processes.insert(0, kernel_pcb);
+            processes.insert(0, Box::new(kernel_pcb));
         }
 
         self.process_count.store(1, Ordering::SeqCst);
-        self.current_process.store(0, Ordering::SeqCst);
-
-        // Initialize scheduler
-        {
-            let mut scheduler = self.scheduler.lock();
-            scheduler.init()?;
-            scheduler.add_process(0, Priority::RealTime)?;
-        }
 
         Ok(())
     }
@@ -503,36 +542,60 @@ impl ProcessManager {
             return Err("Maximum process count exceeded");
         }
 
+        if cgroup::fork(parent_pid, pid).is_err() {
+            return Err("Cgroup process limit reached");
+        }
+
+        if namespace::fork(parent_pid, pid).is_err() {
+            cgroup::exit_process(pid);
+            return Err("PID namespace init has exited");
+        }
+
         let mut pcb = ProcessControlBlock::new(pid, parent_pid, name);
         pcb.priority = priority;
 
         {
             let mut processes = self.processes.write();
-            processes.insert(pid, pcb);
+            processes.insert(pid, Box::new(pcb));
         }
 
         self.process_count.fetch_add(1, Ordering::SeqCst);
 
         // Add to scheduler
-        {
-            let mut scheduler = self.scheduler.lock();
-            scheduler.add_process(pid, priority)?;
+        if let Err(e) = crate::scheduler::add_process(pid, parent_pid, priority, name) {
+            self.processes.write().remove(&pid);
+            self.process_count.fetch_sub(1, Ordering::SeqCst);
+            cgroup::exit_process(pid);
+            namespace::release_pid(pid);
+            return Err(e);
         }
 
         // Initialize IPC state for new process
         let ipc_manager = ipc::get_ipc_manager();
         ipc_manager.init_process_signals(pid)?;
 
+        if let Some(parent) = parent_pid {
+            rlimit::inherit(parent, pid);
+            crate::memory::numa::inherit(parent, pid);
+            crate::memory::oom::inherit(parent, pid);
+            crate::memory::aslr::inherit(parent, pid);
+            seccomp::inherit(parent, pid);
+        }
+
         Ok(pid)
     }
 
     /// Terminate a process
+    ///
+    /// A process terminating itself keeps running until it calls
+    /// `crate::scheduler::schedule`, which never returns to it.
     pub fn terminate_process(&self, pid: Pid, exit_status: i32) -> Result<(), &'static str> {
         {
             let mut processes = self.processes.write();
             if let Some(pcb) = processes.get_mut(&pid) {
                 pcb.set_state(ProcessState::Zombie);
                 pcb.exit_status = Some(exit_status);
+                pcb.cpu_time = crate::scheduler::cpu_time_ns(pid).unwrap_or(pcb.cpu_time);
             } else {
                 return Err("Process not found");
             }
@@ -544,25 +607,103 @@ impl ProcessManager {
         // Cleanup IPC resources
         let ipc_manager = ipc::get_ipc_manager();
         ipc_manager.cleanup_process_ipc(pid)?;
-
-        // Remove from scheduler
-        {
-            let mut scheduler = self.scheduler.lock();
-            scheduler.remove_process(pid)?;
+        timers::exit_process(pid);
+        futex::exit_process(pid);
+        rlimit::exit_process(pid);
+        crate::memory::numa::exit_process(pid);
+        crate::memory::oom::exit_process(pid);
+        crate::memory::aslr::exit_process(pid);
+        seccomp::exit_process(pid);
+        cgroup::exit_process(pid);
+        self.close_open_files(pid, false);
+        crate::vfs::lock::release_process(pid);
+
+        // The rest of a PID namespace dies with its init, and tracees asking
+        // for it with their tracer
+        let victims = namespace::exit_process(pid).into_iter()
+            .chain(ptrace::exit_process(pid, exit_status));
+        for victim in victims {
+            let _ = integration::InterruptIntegration::deliver_signal(victim, 9);
         }
 
+        // Take it off the run queues
+        crate::scheduler::terminate_process(pid)?;
+
         Ok(())
     }
 
+    /// Close the descriptors of process `pid` that are entries of the VFS
+    /// open-file table, every one or only those marked close-on-exec
+    ///
+    /// Closing the last descriptor of a pipe end is what lets the other side
+    /// see EOF or EPIPE.
+    pub fn close_open_files(&self, pid: Pid, cloexec_only: bool) {
+        let vfs = crate::vfs::get_vfs();
+        let closing: Vec<i32> = {
+            let mut processes = self.processes.write();
+            let Some(pcb) = processes.get_mut(&pid) else {
+                return;
+            };
+            let mut closing = Vec::new();
+            pcb.file_descriptors.retain(|_, file_desc| {
+                let Some(fd) = file_desc.open_file_fd() else {
+                    return true;
+                };
+                if cloexec_only && !vfs.cloexec(fd).unwrap_or(false) {
+                    return true;
+                }
+                closing.push(fd);
+                false
+            });
+            closing
+        };
+        for fd in closing {
+            let _ = vfs.close(fd);
+        }
+    }
+
+    /// Drop a terminated process for good once its exit status is collected
+    pub fn reap_process(&self, pid: Pid) {
+        if self.processes.write().remove(&pid).is_some() {
+            self.process_count.fetch_sub(1, Ordering::SeqCst);
+        }
+        crate::scheduler::remove_process(pid);
+        namespace::release_pid(pid);
+    }
+
     /// Get process information
     pub fn get_process(&self, pid: Pid) -> Option<ProcessControlBlock> {
-        let processes = self.processes.read();
-        processes.get(&pid).cloned()
+        let mut pcb = {
+            let processes = self.processes.read();
+            processes.get(&pid).map(|pcb| (**pcb).clone())?
+        };
+        pcb.state = Self::run_state(pid, pcb.state);
+        Some(pcb)
+    }
+
+    /// State of a process as the scheduler sees it; the control block's own
+    /// copy only counts once the process has exited
+    fn run_state(pid: Pid, recorded: ProcessState) -> ProcessState {
+        match recorded {
+            ProcessState::Zombie | ProcessState::Dead => recorded,
+            _ => crate::scheduler::process_state(pid).unwrap_or(recorded),
+        }
     }
 
-    /// Get current running process ID
+    /// Id of process `pid` in the PID namespace of `viewer`, if `viewer` can
+    /// see it
+    pub fn pid_in_namespace_of(&self, viewer: Pid, pid: Pid) -> Option<Pid> {
+        namespace::pid_in(namespace::of(viewer).pid, pid)
+    }
+
+    /// The process `viewer` knows by id `local`
+    pub fn pid_from_namespace_of(&self, viewer: Pid, local: Pid) -> Option<Pid> {
+        namespace::pid_from(namespace::of(viewer).pid, local)
+    }
+
+    /// Get the ID of the process running on this CPU
     pub fn current_process(&self) -> Pid {
-        self.current_process.load(Ordering::SeqCst)
+        crate::scheduler::current_pid()
     }
 
     /// Get process count
@@ -570,64 +711,94 @@ impl ProcessManager {
         self.process_count.load(Ordering::SeqCst)
     }
 
-    /// Schedule next process (called by timer interrupt)
-    pub fn schedule(&self) -> Result<Option<Pid>, &'static str> {
-        let mut scheduler = self.scheduler.lock();
-        scheduler.schedule()
-    }
-
-    /// Update current process
-    pub fn set_current_process(&self, pid: Pid) {
-        self.current_process.store(pid, Ordering::SeqCst);
+    /// CPU time consumed by a process in nanoseconds, including its current
+    /// time slice if it is running
+    pub fn cpu_time_ns(&self, pid: Pid) -> Option<u64> {
+        let charged = self.processes.read().get(&pid)?.cpu_time;
+        Some(crate::scheduler::cpu_time_ns(pid).unwrap_or(charged))
     }
 
     /// Handle system call
     pub fn handle_syscall(&self, syscall_number: u64, args: &[u64]) -> Result<u64, &'static str> {
-        let mut dispatcher = self.syscall_dispatcher.lock();
-        dispatcher.dispatch(syscall_number, args, self)
+        self.syscall_dispatcher.dispatch(syscall_number, args, self)
     }
 
-    /// Block current process
+    /// Block a process
     pub fn block_process(&self, pid: Pid) -> Result<(), &'static str> {
-        {
-            let mut processes = self.processes.write();
-            if let Some(pcb) = processes.get_mut(&pid) {
-                pcb.set_state(ProcessState::Blocked);
-            } else {
-                return Err("Process not found");
-            }
-        }
-
-        // Remove from scheduler ready queue
-        {
-            let mut scheduler = self.scheduler.lock();
-            scheduler.block_process(pid)?;
+        if !self.processes.read().contains_key(&pid) {
+            return Err("Process not found");
         }
 
-        Ok(())
+        crate::scheduler::block_process(pid)
     }
 
     /// Unblock a process
     pub fn unblock_process(&self, pid: Pid) -> Result<(), &'static str> {
+        if !self.processes.read().contains_key(&pid) {
+            return Err("Process not found");
+        }
+
+        crate::scheduler::unblock_process(pid)
+    }
+
+    /// Change the priority of a process
+    pub fn set_priority(&self, pid: Pid, priority: Priority) -> Result<(), &'static str> {
         {
             let mut processes = self.processes.write();
-            if let Some(pcb) = processes.get_mut(&pid) {
-                pcb.set_state(ProcessState::Ready);
-            } else {
-                return Err("Process not found");
-            }
+            let pcb = processes.get_mut(&pid).ok_or("Process not found")?;
+            pcb.priority = priority;
         }
 
-        // Add back to scheduler
-        {
-            let mut scheduler = self.scheduler.lock();
-            let priority = {
-                let processes = self.processes.read();
-                processes.get(&pid).map(|p| p.priority).unwrap_or(Priority::Normal)
-            };
-            scheduler.add_process(pid, priority)?;
+        crate::scheduler::set_process_priority(pid, priority)
+    }
+
+    /// Set the nice value of a process, which also decides its coarse
+    /// priority unless it is real-time
+    pub fn set_nice(&self, pid: Pid, nice: i8) -> Result<(), &'static str> {
+        crate::scheduler::set_nice(pid, nice)?;
+
+        let priority = crate::scheduler::get_process_priority(pid).ok_or("Process not found")?;
+        let mut processes = self.processes.write();
+        let pcb = processes.get_mut(&pid).ok_or("Process not found")?;
+        pcb.priority = priority;
+        Ok(())
+    }
+
+    /// Set the CPUs a process may run on, one bit each
+    ///
+    /// The kernel's own flow (PID 0) is not scheduled and only records it.
+    pub fn set_cpu_affinity(&self, pid: Pid, cpu_mask: u64) -> Result<(), &'static str> {
+        if pid != 0 {
+            crate::scheduler::set_process_affinity(pid, cpu_mask)?;
         }
 
+        let mut processes = self.processes.write();
+        let pcb = processes.get_mut(&pid).ok_or("Process not found")?;
+        pcb.sched_info.cpu_affinity = cpu_mask;
+        Ok(())
+    }
+
+    /// CPUs a process may run on, one bit each
+    pub fn cpu_affinity(&self, pid: Pid) -> Option<u64> {
+        crate::scheduler::get_process_affinity(pid)
+            .or_else(|| self.processes.read().get(&pid).map(|pcb| pcb.sched_info.cpu_affinity))
+    }
+
+    /// Set the scheduling policy of a process, see
+    /// `crate::scheduler::set_scheduler`, and the coarse priority it implies
+    pub fn set_sched_policy(
+        &self,
+        pid: Pid,
+        policy: crate::scheduler::SchedPolicy,
+        rt_priority: u8,
+        params: Option<crate::scheduler::deadline::DeadlineParams>,
+    ) -> Result<(), &'static str> {
+        crate::scheduler::set_scheduler(pid, policy, rt_priority, params)?;
+
+        let priority = crate::scheduler::get_process_priority(pid).ok_or("Process not found")?;
+        let mut processes = self.processes.write();
+        let pcb = processes.get_mut(&pid).ok_or("Process not found")?;
+        pcb.priority = priority;
         Ok(())
     }
 
@@ -635,7 +806,7 @@ impl ProcessManager {
     pub fn list_processes(&self) -> Vec<(Pid, String, ProcessState, Priority)> {
         let processes = self.processes.read();
         processes.iter().map(|(&pid, pcb)| {
-            (pid, pcb.name_str().to_string(), pcb.state, pcb.priority)
+            (pid, pcb.name_str().to_string(), Self::run_state(pid, pcb.state), pcb.priority)
         }).collect()
     }
 
@@ -774,8 +945,6 @@ pub fn tick_system_time() {
     // This function is kept for compatibility with existing code
 }
 
-use core::sync::atomic::AtomicU64;
-
 /// Get the currently running process ID
 ///
 /// Returns the PID of the process currently executing on this CPU.
@@ -0,0 +1,559 @@
+//! Namespaces
+//!
+//! Every process belongs to one namespace of each kind, which decides what
+//! it sees of the system:
+//! - mnt: the mount table (`crate::vfs`)
+//! - uts: the host and domain name
+//! - ipc: System V message queues, semaphores and shared memory
+//!   (`crate::linux_compat::ipc_ops`)
+//! - pid: process ids. PID namespaces nest: a process has an id in its own
+//!   namespace and in every ancestor, and only sees the processes of its
+//!   namespace and those below. The first process in a new one is its init,
+//!   with id 1; when init exits the rest of the namespace is killed.
+//! - net: the network stack, with its own interfaces, routes and sockets
+//!   (`crate::net`)
+//!
+//! Children share the namespaces of their parent unless cloned with one of
+//! the `CLONE_NEW*` flags. `unshare` moves a process into new namespaces,
+//! except that a new PID namespace only takes the children it creates from
+//! then on, and `setns` into existing ones. A namespace goes away with the
+//! last process using it; the ones the system starts with, `INIT_NS` of
+//! every kind, never do.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::Pid;
+
+/// Namespace identifier, unique across kinds
+pub type NsId = u64;
+
+/// Namespace of every kind the system starts with
+pub const INIT_NS: NsId = 0;
+
+/// `clone`/`unshare` flags creating namespaces, as in the Linux ABI
+pub const CLONE_NEWNS: u64 = 0x0002_0000;
+pub const CLONE_NEWUTS: u64 = 0x0400_0000;
+pub const CLONE_NEWIPC: u64 = 0x0800_0000;
+pub const CLONE_NEWPID: u64 = 0x2000_0000;
+pub const CLONE_NEWNET: u64 = 0x4000_0000;
+
+/// Every `CLONE_NEW*` flag of a supported kind
+pub const CLONE_NEW_MASK: u64 = CLONE_NEWNS | CLONE_NEWUTS | CLONE_NEWIPC | CLONE_NEWPID | CLONE_NEWNET;
+
+/// Deepest nesting of PID namespaces below the initial one, as Linux
+pub const MAX_PID_NS_LEVEL: u32 = 32;
+
+/// Longest host or domain name
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Kinds of namespaces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NsKind {
+    Mnt,
+    Uts,
+    Ipc,
+    Pid,
+    Net,
+}
+
+impl NsKind {
+    pub const ALL: [NsKind; 5] = [NsKind::Mnt, NsKind::Uts, NsKind::Ipc, NsKind::Pid, NsKind::Net];
+
+    /// Name in `/proc/<pid>/ns`
+    pub fn name(self) -> &'static str {
+        match self {
+            NsKind::Mnt => "mnt",
+            NsKind::Uts => "uts",
+            NsKind::Ipc => "ipc",
+            NsKind::Pid => "pid",
+            NsKind::Net => "net",
+        }
+    }
+
+    /// The `CLONE_NEW*` flag for the kind
+    pub fn clone_flag(self) -> u64 {
+        match self {
+            NsKind::Mnt => CLONE_NEWNS,
+            NsKind::Uts => CLONE_NEWUTS,
+            NsKind::Ipc => CLONE_NEWIPC,
+            NsKind::Pid => CLONE_NEWPID,
+            NsKind::Net => CLONE_NEWNET,
+        }
+    }
+
+    /// The kind a single `CLONE_NEW*` flag stands for
+    pub fn from_clone_flag(flag: u64) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.clone_flag() == flag)
+    }
+}
+
+/// The namespaces of a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NsSet {
+    pub mnt: NsId,
+    pub uts: NsId,
+    pub ipc: NsId,
+    /// PID namespace the process has its ids in
+    pub pid: NsId,
+    /// PID namespace its children are created in
+    pub pid_for_children: NsId,
+    pub net: NsId,
+}
+
+impl NsSet {
+    pub const INIT: Self = NsSet {
+        mnt: INIT_NS,
+        uts: INIT_NS,
+        ipc: INIT_NS,
+        pid: INIT_NS,
+        pid_for_children: INIT_NS,
+        net: INIT_NS,
+    };
+
+    /// The namespace of `kind`; for PID, the one the process is in
+    pub fn get(&self, kind: NsKind) -> NsId {
+        match kind {
+            NsKind::Mnt => self.mnt,
+            NsKind::Uts => self.uts,
+            NsKind::Ipc => self.ipc,
+            NsKind::Pid => self.pid,
+            NsKind::Net => self.net,
+        }
+    }
+
+    fn set(&mut self, kind: NsKind, id: NsId) {
+        match kind {
+            NsKind::Mnt => self.mnt = id,
+            NsKind::Uts => self.uts = id,
+            NsKind::Ipc => self.ipc = id,
+            NsKind::Pid => self.pid = id,
+            NsKind::Net => self.net = id,
+        }
+    }
+
+    /// Every namespace referenced, each one a reference count holds
+    fn ids(&self) -> [NsId; 6] {
+        [self.mnt, self.uts, self.ipc, self.pid, self.pid_for_children, self.net]
+    }
+}
+
+/// Errors from namespace operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NsError {
+    /// No such namespace of that kind
+    NotFound,
+    /// A PID namespace outside the caller's
+    InvalidArgument,
+    /// PID namespaces nested past `MAX_PID_NS_LEVEL`
+    TooDeep,
+    /// The init of the PID namespace has exited
+    Defunct,
+}
+
+/// A PID namespace below the initial one
+struct PidNamespace {
+    parent: NsId,
+    /// Depth below the initial namespace, from 1
+    level: u32,
+    next_pid: Pid,
+    /// Ids of the processes in the namespace and below, by global pid
+    to_local: BTreeMap<Pid, Pid>,
+    to_global: BTreeMap<Pid, Pid>,
+    /// Global pid of its init, once started
+    init: Option<Pid>,
+    /// Init has exited, so no process may join any more
+    defunct: bool,
+}
+
+/// Host and domain name of a UTS namespace
+#[derive(Clone)]
+struct UtsNamespace {
+    hostname: String,
+    domainname: String,
+}
+
+impl UtsNamespace {
+    fn initial() -> Self {
+        UtsNamespace { hostname: String::from("localhost"), domainname: String::from("(none)") }
+    }
+}
+
+struct Namespaces {
+    /// Namespaces of the processes that are not in `NsSet::INIT`
+    sets: BTreeMap<Pid, NsSet>,
+    /// Kind and reference count of every namespace but the initial ones
+    live: BTreeMap<NsId, (NsKind, usize)>,
+    pid: BTreeMap<NsId, PidNamespace>,
+    /// Names of the UTS namespaces, the initial one once renamed
+    uts: BTreeMap<NsId, UtsNamespace>,
+    next_id: NsId,
+}
+
+static NAMESPACES: Mutex<Namespaces> = Mutex::new(Namespaces {
+    sets: BTreeMap::new(),
+    live: BTreeMap::new(),
+    pid: BTreeMap::new(),
+    uts: BTreeMap::new(),
+    next_id: INIT_NS + 1,
+});
+
+impl Namespaces {
+    fn set_of(&self, pid: Pid) -> NsSet {
+        self.sets.get(&pid).copied().unwrap_or(NsSet::INIT)
+    }
+
+    /// Whether `id` names a namespace of `kind`
+    fn is(&self, id: NsId, kind: NsKind) -> bool {
+        id == INIT_NS || self.live.get(&id).is_some_and(|&(live_kind, _)| live_kind == kind)
+    }
+
+    fn pid_level(&self, id: NsId) -> u32 {
+        self.pid.get(&id).map_or(0, |ns| ns.level)
+    }
+
+    fn uts_of(&self, id: NsId) -> UtsNamespace {
+        self.uts.get(&id).cloned().unwrap_or_else(UtsNamespace::initial)
+    }
+
+    /// Create a namespace of `kind` from `from`, the one the creator is in.
+    /// It has no references yet.
+    fn create(&mut self, kind: NsKind, from: NsId) -> Result<NsId, NsError> {
+        let level = self.pid_level(from) + 1;
+        if kind == NsKind::Pid && level > MAX_PID_NS_LEVEL {
+            return Err(NsError::TooDeep);
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.live.insert(id, (kind, 0));
+        match kind {
+            NsKind::Pid => {
+                self.get(from);
+                self.pid.insert(id, PidNamespace {
+                    parent: from,
+                    level,
+                    next_pid: 1,
+                    to_local: BTreeMap::new(),
+                    to_global: BTreeMap::new(),
+                    init: None,
+                    defunct: false,
+                });
+            }
+            NsKind::Uts => {
+                let names = self.uts_of(from);
+                self.uts.insert(id, names);
+            }
+            NsKind::Mnt | NsKind::Ipc | NsKind::Net => {}
+        }
+        Ok(id)
+    }
+
+    fn get(&mut self, id: NsId) {
+        if let Some((_, refs)) = self.live.get_mut(&id) {
+            *refs += 1;
+        }
+    }
+
+    /// Drop a reference to `id`, adding it to `released` if it was the last
+    fn put(&mut self, id: NsId, released: &mut Vec<(NsKind, NsId)>) {
+        let Some((kind, refs)) = self.live.get_mut(&id) else { return };
+        *refs = refs.saturating_sub(1);
+        if *refs > 0 {
+            return;
+        }
+
+        let kind = *kind;
+        self.live.remove(&id);
+        match kind {
+            NsKind::Pid => {
+                if let Some(ns) = self.pid.remove(&id) {
+                    self.put(ns.parent, released);
+                }
+            }
+            NsKind::Uts => {
+                self.uts.remove(&id);
+            }
+            NsKind::Mnt | NsKind::Ipc | NsKind::Net => {}
+        }
+        released.push((kind, id));
+    }
+
+    /// Move `pid` to the namespaces of `set`
+    fn assign(&mut self, pid: Pid, set: NsSet, released: &mut Vec<(NsKind, NsId)>) {
+        let old = self.set_of(pid);
+        for id in set.ids() {
+            self.get(id);
+        }
+        for id in old.ids() {
+            self.put(id, released);
+        }
+        if set == NsSet::INIT {
+            self.sets.remove(&pid);
+        } else {
+            self.sets.insert(pid, set);
+        }
+    }
+
+    /// Give `pid` an id in PID namespace `ns` and its ancestors, up to but
+    /// not including `until`
+    fn number(&mut self, ns: NsId, until: NsId, pid: Pid) -> Result<(), NsError> {
+        let mut id = ns;
+        while id != until && id != INIT_NS {
+            let level = self.pid.get(&id).ok_or(NsError::NotFound)?;
+            if level.defunct {
+                return Err(NsError::Defunct);
+            }
+            id = level.parent;
+        }
+
+        let mut id = ns;
+        while id != until && id != INIT_NS {
+            let Some(level) = self.pid.get_mut(&id) else { break };
+            let local = level.next_pid;
+            level.next_pid += 1;
+            level.to_local.insert(pid, local);
+            level.to_global.insert(local, pid);
+            level.init.get_or_insert(pid);
+            id = level.parent;
+        }
+        Ok(())
+    }
+
+    /// Drop the ids of `pid` in PID namespace `ns` and its ancestors
+    fn unnumber(&mut self, ns: NsId, pid: Pid) {
+        let mut id = ns;
+        while let Some(level) = self.pid.get_mut(&id) {
+            if let Some(local) = level.to_local.remove(&pid) {
+                level.to_global.remove(&local);
+            }
+            id = level.parent;
+        }
+    }
+
+    /// Whether PID namespace `ns` is `ancestor` or nested below it
+    fn pid_within(&self, ns: NsId, ancestor: NsId) -> bool {
+        let mut id = ns;
+        loop {
+            if id == ancestor {
+                return true;
+            }
+            match self.pid.get(&id) {
+                Some(level) => id = level.parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Put `pid` in new namespaces of the kinds in `flags`. With `clone` a new
+    /// PID namespace is the one `pid` is in, otherwise that of its children.
+    fn new_namespaces(
+        &mut self,
+        pid: Pid,
+        flags: u64,
+        clone: bool,
+        created: &mut Vec<(NsKind, NsId, NsId)>,
+        released: &mut Vec<(NsKind, NsId)>,
+    ) -> Result<(), NsError> {
+        let mut set = self.set_of(pid);
+
+        // The only kind that can fail goes first, before anything changes
+        if flags & CLONE_NEWPID != 0 {
+            let id = self.create(NsKind::Pid, set.pid)?;
+            if clone {
+                self.number(id, set.pid, pid)?;
+                set.pid = id;
+            }
+            set.pid_for_children = id;
+        }
+
+        for kind in [NsKind::Mnt, NsKind::Uts, NsKind::Ipc, NsKind::Net] {
+            if flags & kind.clone_flag() != 0 {
+                let from = set.get(kind);
+                let id = self.create(kind, from)?;
+                set.set(kind, id);
+                created.push((kind, from, id));
+            }
+        }
+
+        self.assign(pid, set, released);
+        Ok(())
+    }
+}
+
+/// Fill in the namespaces just created
+fn set_up(created: &[(NsKind, NsId, NsId)]) {
+    for &(kind, from, id) in created {
+        match kind {
+            NsKind::Mnt => crate::vfs::get_vfs().copy_mount_namespace(from, id),
+            NsKind::Net => crate::net::create_namespace(id),
+            NsKind::Uts | NsKind::Ipc | NsKind::Pid => {}
+        }
+    }
+}
+
+/// Free what the namespaces just released held elsewhere
+fn tear_down(released: &[(NsKind, NsId)]) {
+    for &(kind, id) in released {
+        match kind {
+            NsKind::Mnt => crate::vfs::get_vfs().drop_mount_namespace(id),
+            NsKind::Net => crate::net::destroy_namespace(id),
+            NsKind::Ipc => crate::linux_compat::ipc_ops::release_namespace(id),
+            NsKind::Uts | NsKind::Pid => {}
+        }
+    }
+}
+
+/// The namespaces of `pid`
+pub fn of(pid: Pid) -> NsSet {
+    NAMESPACES.lock().set_of(pid)
+}
+
+/// Put a new process in the namespaces of its parent, with an id in each of
+/// its PID namespaces
+pub fn fork(parent: Option<Pid>, child: Pid) -> Result<(), NsError> {
+    let Some(parent) = parent else { return Ok(()) };
+    let mut namespaces = NAMESPACES.lock();
+    let parent_set = namespaces.set_of(parent);
+    let set = NsSet { pid: parent_set.pid_for_children, ..parent_set };
+    if set == NsSet::INIT {
+        return Ok(());
+    }
+
+    namespaces.number(set.pid, INIT_NS, child)?;
+    let mut released = Vec::new();
+    namespaces.assign(child, set, &mut released);
+    Ok(())
+}
+
+/// Give the new process `child` of a `clone` the namespaces its `flags` ask
+/// for; with `CLONE_NEWPID` it is the init of a new PID namespace
+pub fn clone_into(child: Pid, flags: u64) -> Result<(), NsError> {
+    let mut created = Vec::new();
+    let mut released = Vec::new();
+    NAMESPACES.lock().new_namespaces(child, flags & CLONE_NEW_MASK, true, &mut created, &mut released)?;
+    set_up(&created);
+    tear_down(&released);
+    Ok(())
+}
+
+/// Move `pid` into new namespaces of the kinds in `flags`, as `unshare`
+pub fn unshare(pid: Pid, flags: u64) -> Result<(), NsError> {
+    let mut created = Vec::new();
+    let mut released = Vec::new();
+    NAMESPACES.lock().new_namespaces(pid, flags & CLONE_NEW_MASK, false, &mut created, &mut released)?;
+    set_up(&created);
+    tear_down(&released);
+    Ok(())
+}
+
+/// Move `pid` into namespace `id` of `kind`, as `setns`. A PID namespace is
+/// only for the children `pid` creates afterwards, and must be its own or
+/// one below it.
+pub fn setns(pid: Pid, kind: NsKind, id: NsId) -> Result<(), NsError> {
+    let mut released = Vec::new();
+    {
+        let mut namespaces = NAMESPACES.lock();
+        if !namespaces.is(id, kind) {
+            return Err(NsError::NotFound);
+        }
+
+        let mut set = namespaces.set_of(pid);
+        if kind == NsKind::Pid {
+            if !namespaces.pid_within(id, set.pid) {
+                return Err(NsError::InvalidArgument);
+            }
+            set.pid_for_children = id;
+        } else {
+            set.set(kind, id);
+        }
+        namespaces.assign(pid, set, &mut released);
+    }
+    tear_down(&released);
+    Ok(())
+}
+
+/// Leave the namespaces of an exiting process, except for its ids, which
+/// stay until it is reaped. Returns the processes to kill when it was the
+/// init of its PID namespace.
+pub fn exit_process(pid: Pid) -> Vec<Pid> {
+    let mut victims = Vec::new();
+    let mut released = Vec::new();
+    {
+        let mut namespaces = NAMESPACES.lock();
+        let set = namespaces.set_of(pid);
+        if let Some(level) = namespaces.pid.get_mut(&set.pid) {
+            if level.init == Some(pid) {
+                level.defunct = true;
+                victims.extend(level.to_local.keys().copied().filter(|&other| other != pid));
+            }
+        }
+        namespaces.assign(pid, NsSet { pid: set.pid, ..NsSet::INIT }, &mut released);
+    }
+    tear_down(&released);
+    victims
+}
+
+/// Drop the ids of a reaped process
+pub fn release_pid(pid: Pid) {
+    let mut released = Vec::new();
+    {
+        let mut namespaces = NAMESPACES.lock();
+        let set = namespaces.set_of(pid);
+        namespaces.unnumber(set.pid, pid);
+        namespaces.assign(pid, NsSet::INIT, &mut released);
+    }
+    tear_down(&released);
+}
+
+/// Id of process `pid` in PID namespace `ns`, if it can be seen from there
+pub fn pid_in(ns: NsId, pid: Pid) -> Option<Pid> {
+    if ns == INIT_NS {
+        return Some(pid);
+    }
+    NAMESPACES.lock().pid.get(&ns)?.to_local.get(&pid).copied()
+}
+
+/// The process with id `local` in PID namespace `ns`
+pub fn pid_from(ns: NsId, local: Pid) -> Option<Pid> {
+    if ns == INIT_NS {
+        return Some(local);
+    }
+    NAMESPACES.lock().pid.get(&ns)?.to_global.get(&local).copied()
+}
+
+/// Host name of UTS namespace `ns`
+pub fn hostname(ns: NsId) -> String {
+    NAMESPACES.lock().uts_of(ns).hostname
+}
+
+/// Domain name of UTS namespace `ns`
+pub fn domainname(ns: NsId) -> String {
+    NAMESPACES.lock().uts_of(ns).domainname
+}
+
+/// Rename UTS namespace `ns`
+pub fn set_hostname(ns: NsId, name: &str) -> Result<(), NsError> {
+    update_uts(ns, name, |uts, name| uts.hostname = name)
+}
+
+/// Change the domain name of UTS namespace `ns`
+pub fn set_domainname(ns: NsId, name: &str) -> Result<(), NsError> {
+    update_uts(ns, name, |uts, name| uts.domainname = name)
+}
+
+fn update_uts(ns: NsId, name: &str, update: impl FnOnce(&mut UtsNamespace, String)) -> Result<(), NsError> {
+    if name.len() > MAX_NAME_LEN {
+        return Err(NsError::InvalidArgument);
+    }
+    let mut namespaces = NAMESPACES.lock();
+    if !namespaces.is(ns, NsKind::Uts) {
+        return Err(NsError::NotFound);
+    }
+    let mut uts = namespaces.uts_of(ns);
+    update(&mut uts, String::from(name));
+    namespaces.uts.insert(ns, uts);
+    Ok(())
+}
@@ -0,0 +1,1073 @@
+//! Process tracing
+//!
+//! A tracer, a debugger or strace, attaches to a tracee and sees it stop
+//! before a signal is delivered, around system calls if asked, after each
+//! instruction when single-stepping, and at fork, exec and exit. While the
+//! tracee is stopped the tracer reads and writes its memory and registers,
+//! then resumes it, passing the signal it stopped for on or not. Stops are
+//! reported to the tracer by wait4, as stopped children are, after a
+//! SIGCHLD.
+//!
+//! The registers of a stopped tracee are a `ProcessContext` kept here. At a
+//! syscall stop they are the call's: the number in `orig_rax`, arguments in
+//! the argument registers, and `rax` holding -ENOSYS on entry and the result
+//! on exit. Changes made at the entry stop change the call about to run,
+//! and an `orig_rax` of -1 skips it, returning `rax`; changes to `rax` at
+//! the exit stop change the result. At breakpoint and single-step stops,
+//! and signal stops raised by those traps, they are the user registers the
+//! trap saved, and every change takes effect when the tracee resumes. At
+//! other stops they are those of the tracee's last trap or system call.
+//!
+//! SIGKILL is never held back. A tracee exiting under a tracer that isn't
+//! its parent has its exit status reported to the tracer as well. Tracees
+//! of an exiting tracer are let go, or killed if it set
+//! `PTRACE_O_EXITKILL`.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+use x86_64::VirtAddr;
+
+use super::context::{FpuState, ProcessContext};
+use super::{Pid, ProcessState};
+use crate::interrupts::TrapFrame;
+
+/// `ptrace` requests, as in the Linux ABI
+pub const PTRACE_TRACEME: i64 = 0;
+pub const PTRACE_PEEKTEXT: i64 = 1;
+pub const PTRACE_PEEKDATA: i64 = 2;
+pub const PTRACE_PEEKUSER: i64 = 3;
+pub const PTRACE_POKETEXT: i64 = 4;
+pub const PTRACE_POKEDATA: i64 = 5;
+pub const PTRACE_POKEUSER: i64 = 6;
+pub const PTRACE_CONT: i64 = 7;
+pub const PTRACE_KILL: i64 = 8;
+pub const PTRACE_SINGLESTEP: i64 = 9;
+pub const PTRACE_GETREGS: i64 = 12;
+pub const PTRACE_SETREGS: i64 = 13;
+pub const PTRACE_GETFPREGS: i64 = 14;
+pub const PTRACE_SETFPREGS: i64 = 15;
+pub const PTRACE_ATTACH: i64 = 16;
+pub const PTRACE_DETACH: i64 = 17;
+pub const PTRACE_SYSCALL: i64 = 24;
+pub const PTRACE_SETOPTIONS: i64 = 0x4200;
+pub const PTRACE_GETEVENTMSG: i64 = 0x4201;
+pub const PTRACE_GETSIGINFO: i64 = 0x4202;
+pub const PTRACE_SEIZE: i64 = 0x4206;
+pub const PTRACE_INTERRUPT: i64 = 0x4207;
+
+/// `PTRACE_SETOPTIONS` options
+pub const PTRACE_O_TRACESYSGOOD: u32 = 0x1;
+pub const PTRACE_O_TRACEFORK: u32 = 0x2;
+pub const PTRACE_O_TRACEVFORK: u32 = 0x4;
+pub const PTRACE_O_TRACECLONE: u32 = 0x8;
+pub const PTRACE_O_TRACEEXEC: u32 = 0x10;
+pub const PTRACE_O_TRACEEXIT: u32 = 0x40;
+pub const PTRACE_O_EXITKILL: u32 = 0x10_0000;
+
+const SUPPORTED_OPTIONS: u32 = PTRACE_O_TRACESYSGOOD
+    | PTRACE_O_TRACEFORK
+    | PTRACE_O_TRACEVFORK
+    | PTRACE_O_TRACECLONE
+    | PTRACE_O_TRACEEXEC
+    | PTRACE_O_TRACEEXIT
+    | PTRACE_O_EXITKILL;
+
+/// Events, reported in bits 16..24 of the wait status
+pub const PTRACE_EVENT_FORK: u32 = 1;
+pub const PTRACE_EVENT_VFORK: u32 = 2;
+pub const PTRACE_EVENT_CLONE: u32 = 3;
+pub const PTRACE_EVENT_EXEC: u32 = 4;
+pub const PTRACE_EVENT_EXIT: u32 = 6;
+pub const PTRACE_EVENT_STOP: u32 = 128;
+
+/// Offset of `u_debugreg` in the Linux `struct user`
+pub const USER_DEBUGREG_OFFSET: u64 = 848;
+
+/// `si_code` of a single-step SIGTRAP
+const TRAP_TRACE: i32 = 2;
+
+const SIGKILL: u32 = 9;
+const SIGTRAP: u32 = 5;
+const SIGSTOP: u32 = 19;
+
+/// Trap flag of RFLAGS
+const RFLAGS_TF: u64 = 1 << 8;
+
+/// RFLAGS bits a tracer may change: CF, PF, AF, ZF, SF, TF, DF, OF and AC
+const USER_RFLAGS: u64 = 0x4_0dd5;
+
+/// `orig_rax` outside a system call, and the value that skips one
+const NO_SYSCALL: u64 = u64::MAX;
+
+/// General registers in the layout of the Linux `struct user_regs_struct`,
+/// which is also the start of the user area
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UserRegs {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub orig_rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub eflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+    pub fs_base: u64,
+    pub gs_base: u64,
+    pub ds: u64,
+    pub es: u64,
+    pub fs: u64,
+    pub gs: u64,
+}
+
+/// Number of words in `UserRegs`
+const USER_REGS_WORDS: usize = core::mem::size_of::<UserRegs>() / 8;
+
+impl UserRegs {
+    fn new(context: &ProcessContext, orig_rax: u64) -> Self {
+        let cpu = &context.cpu;
+        Self {
+            r15: cpu.r15,
+            r14: cpu.r14,
+            r13: cpu.r13,
+            r12: cpu.r12,
+            rbp: cpu.rbp,
+            rbx: cpu.rbx,
+            r11: cpu.r11,
+            r10: cpu.r10,
+            r9: cpu.r9,
+            r8: cpu.r8,
+            rax: cpu.rax,
+            rcx: cpu.rcx,
+            rdx: cpu.rdx,
+            rsi: cpu.rsi,
+            rdi: cpu.rdi,
+            orig_rax,
+            rip: cpu.rip,
+            cs: cpu.cs as u64,
+            eflags: cpu.rflags,
+            rsp: cpu.rsp,
+            ss: cpu.ss as u64,
+            fs_base: 0,
+            gs_base: 0,
+            ds: cpu.ds as u64,
+            es: cpu.es as u64,
+            fs: cpu.fs as u64,
+            gs: cpu.gs as u64,
+        }
+    }
+
+    /// Write the registers back; segments, their bases and the system
+    /// flags stay as they are
+    fn apply(&self, context: &mut ProcessContext, orig_rax: &mut u64) {
+        let cpu = &mut context.cpu;
+        cpu.r15 = self.r15;
+        cpu.r14 = self.r14;
+        cpu.r13 = self.r13;
+        cpu.r12 = self.r12;
+        cpu.rbp = self.rbp;
+        cpu.rbx = self.rbx;
+        cpu.r11 = self.r11;
+        cpu.r10 = self.r10;
+        cpu.r9 = self.r9;
+        cpu.r8 = self.r8;
+        cpu.rax = self.rax;
+        cpu.rcx = self.rcx;
+        cpu.rdx = self.rdx;
+        cpu.rsi = self.rsi;
+        cpu.rdi = self.rdi;
+        cpu.rip = self.rip;
+        cpu.rflags = (cpu.rflags & !USER_RFLAGS) | (self.eflags & USER_RFLAGS);
+        cpu.rsp = self.rsp;
+        *orig_rax = self.orig_rax;
+    }
+
+    fn words(&mut self) -> &mut [u64; USER_REGS_WORDS] {
+        // SAFETY: `UserRegs` is `repr(C)` and made of u64s only
+        unsafe { &mut *(self as *mut Self as *mut [u64; USER_REGS_WORDS]) }
+    }
+}
+
+/// `ptrace` failures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PtraceError {
+    /// No such process, or not a stopped tracee of the caller
+    NoSuchProcess,
+    /// Not allowed to trace the process
+    PermissionDenied,
+    /// Bad request, option or signal
+    InvalidArgument,
+    /// Bad address or user-area offset
+    Io,
+}
+
+/// Why a tracee is stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// A signal is about to be delivered
+    Signal(u32),
+    /// A system call is about to run
+    SyscallEntry,
+    /// A system call has returned
+    SyscallExit,
+    /// One instruction ran under `PTRACE_SINGLESTEP`
+    Step,
+    /// A `PTRACE_EVENT_*`
+    Event(u32),
+}
+
+/// How a tracee was last resumed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    Continue,
+    Syscall,
+    SingleStep,
+}
+
+struct Tracee {
+    tracer: Pid,
+    /// Attached with `PTRACE_SEIZE` rather than ATTACH or TRACEME
+    seized: bool,
+    options: u32,
+    resume: Resume,
+    stop: Option<Stop>,
+    /// The stop was reported by wait4
+    reported: bool,
+    regs: ProcessContext,
+    orig_rax: u64,
+    event_msg: u64,
+    /// Signals that arrived while stopped, each to stop for in turn
+    pending: Vec<u32>,
+}
+
+impl Tracee {
+    fn new(tracer: Pid, seized: bool, options: u32) -> Self {
+        Self {
+            tracer,
+            seized,
+            options,
+            resume: Resume::Continue,
+            stop: None,
+            reported: false,
+            regs: ProcessContext::default(),
+            orig_rax: NO_SYSCALL,
+            event_msg: 0,
+            pending: Vec::new(),
+        }
+    }
+}
+
+static TRACEES: Mutex<BTreeMap<Pid, Tracee>> = Mutex::new(BTreeMap::new());
+
+/// Number of tracees, so untraced syscalls skip the lock
+static TRACED: AtomicUsize = AtomicUsize::new(0);
+
+/// Exits of tracees not yet collected by their tracer: tracer, tracee and
+/// exit status. Parents collect their children's exits themselves.
+static EXITS: Mutex<Vec<(Pid, Pid, i32)>> = Mutex::new(Vec::new());
+
+/// Tracer of `pid`, if traced
+pub fn tracer_of(pid: Pid) -> Option<Pid> {
+    if TRACED.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    TRACEES.lock().get(&pid).map(|tracee| tracee.tracer)
+}
+
+/// Whether `pid` is stopped for its tracer
+pub fn is_stopped(pid: Pid) -> bool {
+    if TRACED.load(Ordering::Relaxed) == 0 {
+        return false;
+    }
+    TRACEES.lock().get(&pid).map_or(false, |tracee| tracee.stop.is_some())
+}
+
+/// Whether `tracer` traces any process or has an exit left to collect
+pub fn has_tracees(tracer: Pid) -> bool {
+    TRACEES.lock().values().any(|tracee| tracee.tracer == tracer)
+        || EXITS.lock().iter().any(|&(by, _, _)| by == tracer)
+}
+
+/// Make the parent of `pid` its tracer
+pub fn traceme(pid: Pid) -> Result<(), PtraceError> {
+    let parent = super::get_process_manager()
+        .get_process(pid)
+        .and_then(|pcb| pcb.parent_pid)
+        .ok_or(PtraceError::PermissionDenied)?;
+    start_tracing(pid, Tracee::new(parent, false, 0))
+}
+
+/// Attach `tracer` to `pid`
+///
+/// `PTRACE_ATTACH` stops the tracee with SIGSTOP; `PTRACE_SEIZE` leaves it
+/// running and sets `options` straight away.
+pub fn attach(tracer: Pid, pid: Pid, seize: bool, options: u32) -> Result<(), PtraceError> {
+    if options & !SUPPORTED_OPTIONS != 0 {
+        return Err(PtraceError::InvalidArgument);
+    }
+    if pid == tracer || pid == 0 {
+        return Err(PtraceError::PermissionDenied);
+    }
+
+    let process_manager = super::get_process_manager();
+    let target = process_manager.get_process(pid).ok_or(PtraceError::NoSuchProcess)?;
+    let uid = process_manager.get_process(tracer).map_or(0, |pcb| pcb.uid);
+    if uid != 0 && uid != target.uid {
+        return Err(PtraceError::PermissionDenied);
+    }
+    if matches!(target.state, ProcessState::Zombie | ProcessState::Terminated | ProcessState::Dead) {
+        return Err(PtraceError::PermissionDenied);
+    }
+
+    start_tracing(pid, Tracee::new(tracer, seize, options))?;
+    if !seize {
+        let _ = super::integration::InterruptIntegration::deliver_signal(pid, SIGSTOP);
+    }
+    Ok(())
+}
+
+fn start_tracing(pid: Pid, tracee: Tracee) -> Result<(), PtraceError> {
+    let mut tracees = TRACEES.lock();
+    if tracees.contains_key(&pid) {
+        return Err(PtraceError::PermissionDenied);
+    }
+    tracees.insert(pid, tracee);
+    TRACED.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+fn stop_tracing(tracees: &mut BTreeMap<Pid, Tracee>, pid: Pid) -> Option<Tracee> {
+    let tracee = tracees.remove(&pid)?;
+    TRACED.fetch_sub(1, Ordering::Relaxed);
+    Some(tracee)
+}
+
+/// Stop a running seized tracee with `PTRACE_EVENT_STOP`
+pub fn interrupt(tracer: Pid, pid: Pid) -> Result<(), PtraceError> {
+    match TRACEES.lock().get(&pid) {
+        Some(tracee) if tracee.tracer == tracer && tracee.seized => {
+            if tracee.stop.is_some() {
+                return Ok(());
+            }
+        }
+        Some(tracee) if tracee.tracer == tracer => return Err(PtraceError::Io),
+        _ => return Err(PtraceError::NoSuchProcess),
+    }
+    enter_stop(pid, Stop::Event(PTRACE_EVENT_STOP), None);
+    Ok(())
+}
+
+/// Resume a stopped tracee
+///
+/// `signal` is delivered if the tracee stopped for a signal, and ignored
+/// at other stops. A signal that arrived while it was stopped stops it
+/// again straight away.
+pub fn resume(tracer: Pid, pid: Pid, how: Resume, signal: u32) -> Result<(), PtraceError> {
+    if signal > 64 {
+        return Err(PtraceError::InvalidArgument);
+    }
+
+    let (inject, next) = with_stopped(tracer, pid, |tracee| {
+        let inject = match tracee.stop {
+            Some(Stop::Signal(_)) => signal,
+            _ => 0,
+        };
+        tracee.resume = how;
+        tracee.stop = None;
+        let next = if tracee.pending.is_empty() {
+            None
+        } else {
+            let next = tracee.pending.remove(0);
+            tracee.stop = Some(Stop::Signal(next));
+            tracee.reported = false;
+            Some(next)
+        };
+        (inject, next)
+    })?;
+
+    if inject != 0 {
+        let _ = super::integration::InterruptIntegration::deliver_untraced(pid, inject);
+    }
+    match next {
+        Some(_) => notify(tracer, pid),
+        None => {
+            let _ = crate::scheduler::unblock_process(pid);
+        }
+    }
+    Ok(())
+}
+
+/// Stop tracing a stopped tracee and resume it, delivering `signal` as
+/// `resume` does
+pub fn detach(tracer: Pid, pid: Pid, signal: u32) -> Result<(), PtraceError> {
+    if signal > 64 {
+        return Err(PtraceError::InvalidArgument);
+    }
+
+    let tracee = {
+        let mut tracees = TRACEES.lock();
+        match tracees.get(&pid) {
+            Some(tracee) if tracee.tracer == tracer && tracee.stop.is_some() => {}
+            _ => return Err(PtraceError::NoSuchProcess),
+        }
+        stop_tracing(&mut tracees, pid)
+    };
+
+    if let Some(Stop::Signal(_)) = tracee.and_then(|tracee| tracee.stop) {
+        if signal != 0 {
+            let _ = super::integration::InterruptIntegration::deliver_untraced(pid, signal);
+        }
+    }
+    let _ = crate::scheduler::unblock_process(pid);
+    Ok(())
+}
+
+/// Kill a tracee, stopped or not
+pub fn kill(tracer: Pid, pid: Pid) -> Result<(), PtraceError> {
+    if tracer_of(pid) != Some(tracer) {
+        return Err(PtraceError::NoSuchProcess);
+    }
+    let _ = super::integration::InterruptIntegration::deliver_signal(pid, SIGKILL);
+    Ok(())
+}
+
+pub fn set_options(tracer: Pid, pid: Pid, options: u32) -> Result<(), PtraceError> {
+    if options & !SUPPORTED_OPTIONS != 0 {
+        return Err(PtraceError::InvalidArgument);
+    }
+    with_stopped(tracer, pid, |tracee| tracee.options = options)
+}
+
+/// Message of the last event: the new process for fork, vfork and clone,
+/// the exit status for exit
+pub fn event_msg(tracer: Pid, pid: Pid) -> Result<u64, PtraceError> {
+    with_stopped(tracer, pid, |tracee| tracee.event_msg)
+}
+
+/// `si_signo` and `si_code` of the signal a tracee is stopped for
+pub fn siginfo(tracer: Pid, pid: Pid) -> Result<(u32, i32), PtraceError> {
+    with_stopped(tracer, pid, |tracee| match tracee.stop {
+        Some(Stop::Signal(signal)) => (signal, 0),
+        Some(Stop::Step) => (SIGTRAP, TRAP_TRACE),
+        Some(stop) => (SIGTRAP, stop_signal(stop, tracee.options) as i32),
+        None => (0, 0),
+    })
+}
+
+pub fn regs(tracer: Pid, pid: Pid) -> Result<UserRegs, PtraceError> {
+    with_stopped(tracer, pid, |tracee| UserRegs::new(&tracee.regs, tracee.orig_rax))
+}
+
+pub fn set_regs(tracer: Pid, pid: Pid, regs: &UserRegs) -> Result<(), PtraceError> {
+    with_stopped(tracer, pid, |tracee| regs.apply(&mut tracee.regs, &mut tracee.orig_rax))
+}
+
+pub fn fpregs(tracer: Pid, pid: Pid) -> Result<FpuState, PtraceError> {
+    with_stopped(tracer, pid, |tracee| tracee.regs.fpu.clone())
+}
+
+pub fn set_fpregs(tracer: Pid, pid: Pid, fpu: &FpuState) -> Result<(), PtraceError> {
+    with_stopped(tracer, pid, |tracee| tracee.regs.fpu = fpu.clone())
+}
+
+/// Read a word of the user area
+///
+/// The general registers are there; the debug registers read as 0 since
+/// hardware breakpoints aren't supported.
+pub fn peek_user(tracer: Pid, pid: Pid, offset: u64) -> Result<u64, PtraceError> {
+    let index = user_index(offset)?;
+    with_stopped(tracer, pid, |tracee| {
+        let mut regs = UserRegs::new(&tracee.regs, tracee.orig_rax);
+        index.map_or(0, |index| regs.words()[index])
+    })
+}
+
+/// Write a word of the user area; of the debug registers only clearing is
+/// allowed
+pub fn poke_user(tracer: Pid, pid: Pid, offset: u64, value: u64) -> Result<(), PtraceError> {
+    let index = user_index(offset)?;
+    with_stopped(tracer, pid, |tracee| match index {
+        Some(index) => {
+            let mut regs = UserRegs::new(&tracee.regs, tracee.orig_rax);
+            regs.words()[index] = value;
+            regs.apply(&mut tracee.regs, &mut tracee.orig_rax);
+            Ok(())
+        }
+        None if value == 0 => Ok(()),
+        None => Err(PtraceError::Io),
+    })?
+}
+
+/// Word index of a user-area offset, `None` for a debug register
+fn user_index(offset: u64) -> Result<Option<usize>, PtraceError> {
+    if offset % 8 != 0 {
+        return Err(PtraceError::Io);
+    }
+    let index = (offset / 8) as usize;
+    if index < USER_REGS_WORDS {
+        Ok(Some(index))
+    } else if (USER_DEBUGREG_OFFSET..USER_DEBUGREG_OFFSET + 64).contains(&offset) {
+        Ok(None)
+    } else {
+        Err(PtraceError::Io)
+    }
+}
+
+/// Read a word of a stopped tracee's memory
+pub fn peek(tracer: Pid, pid: Pid, addr: u64) -> Result<u64, PtraceError> {
+    with_stopped(tracer, pid, |_| ())?;
+    let mut word = [0u8; 8];
+    crate::memory::user_space::UserSpaceMemory::copy_from_user(addr, &mut word)
+        .map_err(|_| PtraceError::Io)?;
+    Ok(u64::from_le_bytes(word))
+}
+
+/// Write a word of a stopped tracee's memory
+///
+/// Read-only mappings such as program text are written through too, for
+/// software breakpoints.
+pub fn poke(tracer: Pid, pid: Pid, addr: u64, word: u64) -> Result<(), PtraceError> {
+    use crate::memory::user_space::UserSpaceMemory;
+
+    with_stopped(tracer, pid, |_| ())?;
+    let bytes = word.to_le_bytes();
+    if UserSpaceMemory::copy_to_user(addr, &bytes).is_ok() {
+        return Ok(());
+    }
+
+    let memory_manager = crate::memory::get_memory_manager().ok_or(PtraceError::Io)?;
+    let end = addr.checked_add(7).ok_or(PtraceError::Io)?;
+    let region = memory_manager.find_region(VirtAddr::new(addr)).ok_or(PtraceError::Io)?;
+    if !region.protection.user_accessible || !region.contains(VirtAddr::new(end)) {
+        return Err(PtraceError::Io);
+    }
+
+    let page_size = crate::memory::PAGE_SIZE as u64;
+    let start = addr & !(page_size - 1);
+    let size = ((end + 1 - start + page_size - 1) & !(page_size - 1)) as usize;
+    let writable = crate::memory::MemoryProtection { writable: true, ..region.protection };
+    memory_manager
+        .protect_region(VirtAddr::new(start), size, writable)
+        .map_err(|_| PtraceError::Io)?;
+    let result = UserSpaceMemory::copy_to_user(addr, &bytes);
+    let _ = memory_manager.protect_region(VirtAddr::new(start), size, region.protection);
+    result.map_err(|_| PtraceError::Io)
+}
+
+/// Next stop or exit of a tracee of `tracer` not yet reported, with its
+/// wait status; `target` limits it to one tracee
+pub fn wait(tracer: Pid, target: Option<Pid>) -> Option<(Pid, i32)> {
+    let wanted = |pid: Pid| target.map_or(true, |target| target == pid);
+
+    {
+        let mut exits = EXITS.lock();
+        if let Some(index) = exits.iter().position(|&(by, pid, _)| by == tracer && wanted(pid)) {
+            let (_, pid, status) = exits.remove(index);
+            return Some((pid, status));
+        }
+    }
+
+    let mut tracees = TRACEES.lock();
+    tracees
+        .iter_mut()
+        .filter(|(pid, tracee)| tracee.tracer == tracer && !tracee.reported && wanted(**pid))
+        .find_map(|(pid, tracee)| {
+            let stop = tracee.stop?;
+            tracee.reported = true;
+            Some((*pid, wait_status(stop, tracee.options)))
+        })
+}
+
+/// Wait status of a stopped tracee, as `WIFSTOPPED` reads it
+fn wait_status(stop: Stop, options: u32) -> i32 {
+    let event = match stop {
+        Stop::Event(event) => event,
+        _ => 0,
+    };
+    (0x7f | (stop_signal(stop, options) << 8) | (event << 16)) as i32
+}
+
+/// Signal a stop is reported as; syscall stops set bit 7 under
+/// `PTRACE_O_TRACESYSGOOD`
+fn stop_signal(stop: Stop, options: u32) -> u32 {
+    match stop {
+        Stop::Signal(signal) => signal,
+        Stop::SyscallEntry | Stop::SyscallExit if options & PTRACE_O_TRACESYSGOOD != 0 => SIGTRAP | 0x80,
+        _ => SIGTRAP,
+    }
+}
+
+/// Stop a tracee for `signal` instead of delivering it
+///
+/// Returns false if `pid` isn't traced and the signal should be delivered.
+pub fn signal_stop(pid: Pid, signal: u32) -> bool {
+    match hold_signal(pid, signal) {
+        Some(held) => held,
+        None => enter_stop(pid, Stop::Signal(signal), None),
+    }
+}
+
+/// Stop the calling tracee for `signal`, raised by a trap from user mode
+/// with registers `frame`
+///
+/// Returns false if it isn't traced and the signal should be delivered.
+pub fn trap_signal_stop(frame: &mut TrapFrame, signal: u32) -> bool {
+    let pid = super::current_pid();
+    match hold_signal(pid, signal) {
+        Some(held) => held,
+        None => trap_stop(pid, Stop::Signal(signal), frame),
+    }
+}
+
+/// Whether a signal stop for `signal` is settled without stopping: false
+/// if `pid` isn't traced, true if it is stopped already and the signal is
+/// kept for the next stop
+fn hold_signal(pid: Pid, signal: u32) -> Option<bool> {
+    if TRACED.load(Ordering::Relaxed) == 0 || !(1..=64).contains(&signal) {
+        return Some(false);
+    }
+    let mut tracees = TRACEES.lock();
+    match tracees.get_mut(&pid) {
+        None => Some(false),
+        Some(tracee) if tracee.stop.is_some() => {
+            if !tracee.pending.contains(&signal) {
+                tracee.pending.push(signal);
+            }
+            Some(true)
+        }
+        Some(_) => None,
+    }
+}
+
+/// Syscall-entry stop of the calling process, under `PTRACE_SYSCALL`
+///
+/// The tracer may change the number and arguments; returns the result if
+/// it skipped the call.
+pub fn syscall_entry(nr: &mut u64, args: &mut [u64; 6], user_ip: u64) -> Option<i64> {
+    if TRACED.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    let pid = super::current_pid();
+    let mut regs = match TRACEES.lock().get(&pid) {
+        Some(tracee) => tracee.regs.clone(),
+        None => return None,
+    };
+    regs.cpu.rax = -38i64 as u64;
+    regs.cpu.rdi = args[0];
+    regs.cpu.rsi = args[1];
+    regs.cpu.rdx = args[2];
+    regs.cpu.r10 = args[3];
+    regs.cpu.r8 = args[4];
+    regs.cpu.r9 = args[5];
+    regs.cpu.rip = user_ip;
+    regs.cpu.rcx = user_ip;
+    if !resumed_with(pid, Resume::Syscall) {
+        // Kept for the event and signal stops the call may make
+        if let Some(tracee) = TRACEES.lock().get_mut(&pid) {
+            tracee.regs = regs;
+            tracee.orig_rax = *nr;
+        }
+        return None;
+    }
+    enter_stop(pid, Stop::SyscallEntry, Some((regs, *nr)));
+
+    let tracees = TRACEES.lock();
+    let tracee = tracees.get(&pid)?;
+    let cpu = &tracee.regs.cpu;
+    *nr = tracee.orig_rax;
+    *args = [cpu.rdi, cpu.rsi, cpu.rdx, cpu.r10, cpu.r8, cpu.r9];
+    (tracee.orig_rax == NO_SYSCALL).then_some(cpu.rax as i64)
+}
+
+/// Syscall-exit stop of the calling process, under `PTRACE_SYSCALL`;
+/// returns the result as the tracer left it
+pub fn syscall_exit(result: i64) -> i64 {
+    if TRACED.load(Ordering::Relaxed) == 0 {
+        return result;
+    }
+    let pid = super::current_pid();
+    let (mut regs, orig_rax) = match TRACEES.lock().get(&pid) {
+        Some(tracee) if tracee.resume == Resume::Syscall => (tracee.regs.clone(), tracee.orig_rax),
+        _ => return result,
+    };
+    regs.cpu.rax = result as u64;
+    enter_stop(pid, Stop::SyscallExit, Some((regs, orig_rax)));
+
+    TRACEES.lock().get(&pid).map_or(result, |tracee| tracee.regs.cpu.rax as i64)
+}
+
+/// RFLAGS to return to user mode with: the trap flag is set while the
+/// calling process is single-stepped
+pub fn return_flags(flags: u64) -> u64 {
+    if TRACED.load(Ordering::Relaxed) != 0 && resumed_with(super::current_pid(), Resume::SingleStep) {
+        flags | RFLAGS_TF
+    } else {
+        flags
+    }
+}
+
+/// Single-step trap of the calling process; returns false if it isn't
+/// being single-stepped
+pub fn single_step_trap(frame: &mut TrapFrame) -> bool {
+    let pid = super::current_pid();
+    TRACED.load(Ordering::Relaxed) != 0
+        && resumed_with(pid, Resume::SingleStep)
+        && trap_stop(pid, Stop::Step, frame)
+}
+
+/// Stop the calling tracee `pid` with the user registers of a trap, and
+/// return to user mode with the registers its tracer left
+fn trap_stop(pid: Pid, stop: Stop, frame: &mut TrapFrame) -> bool {
+    let mut regs = trap_context(frame);
+    // SAFETY: the FPU holds the registers of the trapping process
+    unsafe { super::context::save_fpu(&mut regs.fpu) };
+    if !enter_stop(pid, stop, Some((regs, NO_SYSCALL))) {
+        return false;
+    }
+
+    if let Some(tracee) = TRACEES.lock().get(&pid) {
+        apply_trap_context(&tracee.regs, frame);
+        // SAFETY: the state was saved from this FPU and at most edited
+        unsafe { super::context::restore_fpu(&tracee.regs.fpu) };
+    }
+    true
+}
+
+/// Registers of a trap from user mode, without the FPU
+fn trap_context(frame: &TrapFrame) -> ProcessContext {
+    let mut context = ProcessContext::default();
+    let cpu = &mut context.cpu;
+    cpu.r15 = frame.r15;
+    cpu.r14 = frame.r14;
+    cpu.r13 = frame.r13;
+    cpu.r12 = frame.r12;
+    cpu.r11 = frame.r11;
+    cpu.r10 = frame.r10;
+    cpu.r9 = frame.r9;
+    cpu.r8 = frame.r8;
+    cpu.rbp = frame.rbp;
+    cpu.rdi = frame.rdi;
+    cpu.rsi = frame.rsi;
+    cpu.rdx = frame.rdx;
+    cpu.rcx = frame.rcx;
+    cpu.rbx = frame.rbx;
+    cpu.rax = frame.rax;
+    cpu.rip = frame.rip;
+    cpu.cs = frame.cs as u16;
+    cpu.rflags = frame.rflags;
+    cpu.rsp = frame.rsp;
+    cpu.ss = frame.ss as u16;
+    context
+}
+
+/// Write registers back to the trap they were taken from; segments and
+/// system flags stay as the trap saved them
+fn apply_trap_context(context: &ProcessContext, frame: &mut TrapFrame) {
+    let cpu = &context.cpu;
+    frame.r15 = cpu.r15;
+    frame.r14 = cpu.r14;
+    frame.r13 = cpu.r13;
+    frame.r12 = cpu.r12;
+    frame.r11 = cpu.r11;
+    frame.r10 = cpu.r10;
+    frame.r9 = cpu.r9;
+    frame.r8 = cpu.r8;
+    frame.rbp = cpu.rbp;
+    frame.rdi = cpu.rdi;
+    frame.rsi = cpu.rsi;
+    frame.rdx = cpu.rdx;
+    frame.rcx = cpu.rcx;
+    frame.rbx = cpu.rbx;
+    frame.rax = cpu.rax;
+    frame.rip = cpu.rip;
+    frame.rflags = (frame.rflags & !USER_RFLAGS) | (cpu.rflags & USER_RFLAGS);
+    frame.rsp = cpu.rsp;
+}
+
+/// `parent` forked, vforked or cloned `child`
+///
+/// Under the matching option the child starts out traced by the same
+/// tracer and stopped, and the parent stops for the event.
+pub fn fork_event(parent: Pid, child: Pid, event: u32) {
+    if TRACED.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let option = match event {
+        PTRACE_EVENT_FORK => PTRACE_O_TRACEFORK,
+        PTRACE_EVENT_VFORK => PTRACE_O_TRACEVFORK,
+        _ => PTRACE_O_TRACECLONE,
+    };
+    let (tracer, seized, options) = match TRACEES.lock().get(&parent) {
+        Some(tracee) if tracee.options & option != 0 => (tracee.tracer, tracee.seized, tracee.options),
+        _ => return,
+    };
+
+    if start_tracing(child, Tracee::new(tracer, seized, options)).is_ok() {
+        let stop = if seized { Stop::Event(PTRACE_EVENT_STOP) } else { Stop::Signal(SIGSTOP) };
+        enter_stop(child, stop, None);
+    }
+
+    // The tracer knows the child by its id in the tracer's namespace
+    let local = super::get_process_manager().pid_in_namespace_of(tracer, child).unwrap_or(0);
+    event_stop(parent, event, local as u64);
+}
+
+/// The calling tracee `pid` executed a new program
+///
+/// Stops with `PTRACE_EVENT_EXEC` under `PTRACE_O_TRACEEXEC`, otherwise
+/// takes a SIGTRAP unless seized.
+pub fn exec_event(pid: Pid) {
+    if TRACED.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let (tracer, options, seized) = match TRACEES.lock().get(&pid) {
+        Some(tracee) => (tracee.tracer, tracee.options, tracee.seized),
+        None => return,
+    };
+
+    if options & PTRACE_O_TRACEEXEC != 0 {
+        let local = super::get_process_manager().pid_in_namespace_of(tracer, pid).unwrap_or(0);
+        event_stop(pid, PTRACE_EVENT_EXEC, local as u64);
+    } else if !seized {
+        signal_stop(pid, SIGTRAP);
+    }
+}
+
+/// The calling tracee `pid` is about to exit with `status`; stops with
+/// `PTRACE_EVENT_EXIT` under `PTRACE_O_TRACEEXIT`
+pub fn exit_event(pid: Pid, status: i32) {
+    if TRACED.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let traced = TRACEES
+        .lock()
+        .get(&pid)
+        .map_or(false, |tracee| tracee.options & PTRACE_O_TRACEEXIT != 0);
+    if traced {
+        event_stop(pid, PTRACE_EVENT_EXIT, status as u32 as u64);
+    }
+}
+
+/// Forget a process that exited with `exit_status`
+///
+/// Its tracer is told if it isn't the parent, and its tracees are let go.
+/// Returns the tracees to kill under `PTRACE_O_EXITKILL`.
+pub fn exit_process(pid: Pid, exit_status: i32) -> Vec<Pid> {
+    if TRACED.load(Ordering::Relaxed) == 0 {
+        EXITS.lock().retain(|&(by, _, _)| by != pid);
+        return Vec::new();
+    }
+    let parent = super::get_process_manager().get_process(pid).and_then(|pcb| pcb.parent_pid);
+
+    let mut tracees = TRACEES.lock();
+    let tracer = stop_tracing(&mut tracees, pid)
+        .map(|tracee| tracee.tracer)
+        .filter(|&tracer| Some(tracer) != parent);
+    let orphans: Vec<(Pid, bool)> = tracees
+        .iter()
+        .filter(|(_, tracee)| tracee.tracer == pid)
+        .map(|(&orphan, tracee)| (orphan, tracee.options & PTRACE_O_EXITKILL != 0))
+        .collect();
+    for &(orphan, _) in &orphans {
+        stop_tracing(&mut tracees, orphan);
+    }
+    drop(tracees);
+
+    {
+        let mut exits = EXITS.lock();
+        exits.retain(|&(by, _, _)| by != pid);
+        if let Some(tracer) = tracer {
+            exits.push((tracer, pid, exit_status));
+        }
+    }
+    if let Some(tracer) = tracer {
+        notify(tracer, pid);
+    }
+
+    let mut victims = Vec::new();
+    for (orphan, exit_kill) in orphans {
+        if exit_kill {
+            victims.push(orphan);
+        } else {
+            let _ = crate::scheduler::unblock_process(orphan);
+        }
+    }
+    victims
+}
+
+fn resumed_with(pid: Pid, how: Resume) -> bool {
+    TRACEES.lock().get(&pid).map_or(false, |tracee| tracee.resume == how)
+}
+
+/// Run `f` on `pid` if it is a stopped tracee of `tracer`
+fn with_stopped<R>(tracer: Pid, pid: Pid, f: impl FnOnce(&mut Tracee) -> R) -> Result<R, PtraceError> {
+    match TRACEES.lock().get_mut(&pid) {
+        Some(tracee) if tracee.tracer == tracer && tracee.stop.is_some() => Ok(f(tracee)),
+        _ => Err(PtraceError::NoSuchProcess),
+    }
+}
+
+fn event_stop(pid: Pid, event: u32, msg: u64) {
+    if let Some(tracee) = TRACEES.lock().get_mut(&pid) {
+        tracee.event_msg = msg;
+    }
+    enter_stop(pid, Stop::Event(event), None);
+}
+
+/// Put `pid` in `stop` with registers `regs` and `orig_rax`, or those of
+/// its last trap or system call, and tell its tracer
+///
+/// A tracee stopping itself blocks here until resumed; another is taken
+/// off the CPU. Returns false if `pid` isn't traced.
+fn enter_stop(pid: Pid, stop: Stop, regs: Option<(ProcessContext, u64)>) -> bool {
+    let current = pid == super::current_pid();
+    let (regs, orig_rax) = match (regs, TRACEES.lock().get(&pid)) {
+        (_, None) => return false,
+        (Some(regs), Some(_)) => regs,
+        (None, Some(tracee)) => (tracee.regs.clone(), tracee.orig_rax),
+    };
+    if !current {
+        let _ = crate::scheduler::block_process(pid);
+    }
+
+    let tracer = match TRACEES.lock().get_mut(&pid) {
+        Some(tracee) => {
+            tracee.stop = Some(stop);
+            tracee.reported = false;
+            tracee.regs = regs;
+            tracee.orig_rax = orig_rax;
+            tracee.tracer
+        }
+        None => {
+            // Detached meanwhile
+            if !current {
+                let _ = crate::scheduler::unblock_process(pid);
+            }
+            return false;
+        }
+    };
+    notify(tracer, pid);
+
+    if current {
+        wait_resumed(pid);
+    }
+    true
+}
+
+/// Block the calling tracee until its tracer resumes or detaches it
+fn wait_resumed(pid: Pid) {
+    loop {
+        if matches!(crate::scheduler::process_state(pid), Some(ProcessState::Terminated) | None) {
+            // Killed while stopped; the scheduler never comes back to it
+            crate::scheduler::schedule();
+            return;
+        }
+        if !is_stopped(pid) || crate::scheduler::block_current().is_err() {
+            return;
+        }
+    }
+}
+
+/// Wake the tracer of `tracee` with SIGCHLD to collect a stop or exit
+fn notify(tracer: Pid, tracee: Pid) {
+    let _ = super::ipc::get_ipc_manager().send_signal(tracer, super::ipc::Signal::SIGCHLD, tracee);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_regs_match_linux_layout() {
+        assert_eq!(core::mem::size_of::<UserRegs>(), 27 * 8);
+        assert_eq!(core::mem::offset_of!(UserRegs, orig_rax), 15 * 8);
+        assert_eq!(core::mem::offset_of!(UserRegs, rip), 16 * 8);
+        assert_eq!(core::mem::size_of::<FpuState>(), 512);
+    }
+
+    #[test]
+    fn user_area_offsets() {
+        assert_eq!(user_index(0), Ok(Some(0)));
+        assert_eq!(user_index(15 * 8), Ok(Some(15)));
+        assert_eq!(user_index(27 * 8), Err(PtraceError::Io));
+        assert_eq!(user_index(4), Err(PtraceError::Io));
+        assert_eq!(user_index(USER_DEBUGREG_OFFSET + 7 * 8), Ok(None));
+        assert_eq!(user_index(USER_DEBUGREG_OFFSET + 8 * 8), Err(PtraceError::Io));
+    }
+
+    #[test]
+    fn stop_statuses() {
+        assert_eq!(wait_status(Stop::Signal(SIGSTOP), 0), 0x137f);
+        assert_eq!(wait_status(Stop::SyscallEntry, 0), 0x057f);
+        assert_eq!(wait_status(Stop::SyscallExit, PTRACE_O_TRACESYSGOOD), 0x857f);
+        assert_eq!(wait_status(Stop::Event(PTRACE_EVENT_EXEC), 0), 0x4_057f);
+        assert_eq!(wait_status(Stop::Event(PTRACE_EVENT_STOP), 0), 0x80_057f);
+    }
+
+    #[test]
+    fn breakpoint_stop_takes_rewound_rip() {
+        const TRACER: Pid = 0xFFFF_0050;
+        const PID: Pid = 0xFFFF_0051;
+
+        // int3 at 0x40_1000 traps with rip past it
+        let mut frame = TrapFrame {
+            rip: 0x40_1001,
+            rsp: 0x7fff_f000,
+            rax: 3,
+            cs: 0x23,
+            ss: 0x1b,
+            rflags: 0x202,
+            ..TrapFrame::default()
+        };
+        let mut tracee = Tracee::new(TRACER, false, 0);
+        tracee.stop = Some(Stop::Signal(SIGTRAP));
+        tracee.regs = trap_context(&frame);
+        TRACEES.lock().insert(PID, tracee);
+
+        assert_eq!(peek_user(TRACER, PID, 16 * 8), Ok(0x40_1001));
+        assert_eq!(poke_user(TRACER, PID, 16 * 8, 0x40_1000), Ok(()));
+        let mut user = regs(TRACER, PID).unwrap();
+        user.rax = 9;
+        user.eflags |= 0x3000;
+        set_regs(TRACER, PID, &user).unwrap();
+
+        let tracee = TRACEES.lock().remove(&PID).unwrap();
+        apply_trap_context(&tracee.regs, &mut frame);
+        assert_eq!(frame.rip, 0x40_1000);
+        assert_eq!(frame.rax, 9);
+        assert_eq!(frame.rsp, 0x7fff_f000);
+        // The tracer can't raise IOPL or change segments
+        assert_eq!(frame.rflags, 0x202);
+        assert_eq!((frame.cs, frame.ss), (0x23, 0x1b));
+    }
+
+    #[test]
+    fn set_regs_keeps_system_flags() {
+        let mut context = ProcessContext::default();
+        context.cpu.rflags = 0x202;
+        let mut orig_rax = NO_SYSCALL;
+        let mut regs = UserRegs::new(&context, orig_rax);
+        regs.eflags = 0x3000 | RFLAGS_TF | 0x1;
+        regs.orig_rax = 39;
+        regs.words()[10] = 7;
+        regs.apply(&mut context, &mut orig_rax);
+        assert_eq!(context.cpu.rflags, 0x202 | RFLAGS_TF | 0x1);
+        assert_eq!(context.cpu.rax, 7);
+        assert_eq!(orig_rax, 39);
+    }
+}
@@ -0,0 +1,104 @@
+//! Per-Process Resource Limits
+//!
+//! Every process has a soft and a hard limit for each `RLIMIT_*` resource.
+//! Only processes that differ from the defaults have an entry here; children
+//! copy their parent's limits when they are created. Raising a hard limit is
+//! reserved to root.
+
+use alloc::collections::BTreeMap;
+use spin::RwLock;
+
+use super::Pid;
+
+/// Number of resources with limits
+pub const RLIM_NLIMITS: usize = 16;
+
+/// Limit value meaning "no limit"
+pub const RLIM_INFINITY: u64 = !0;
+
+/// Resource indices, as in the Linux ABI
+pub const RLIMIT_STACK: usize = 3;
+pub const RLIMIT_NPROC: usize = 6;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_MEMLOCK: usize = 8;
+
+/// Default locked-memory limit, as Linux since 5.16
+const DEFAULT_MEMLOCK: u64 = 8 * 1024 * 1024;
+
+/// A soft and hard limit pair
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    /// Enforced value
+    pub cur: u64,
+    /// Ceiling an unprivileged process may raise `cur` to
+    pub max: u64,
+}
+
+impl Limit {
+    pub const UNLIMITED: Self = Limit { cur: RLIM_INFINITY, max: RLIM_INFINITY };
+
+    pub const fn new(cur: u64, max: u64) -> Self {
+        Limit { cur, max }
+    }
+}
+
+/// Errors from changing a limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitError {
+    /// Unknown resource, or soft limit above hard limit
+    Invalid,
+    /// Raising the hard limit needs root
+    PermissionDenied,
+}
+
+type Limits = [Limit; RLIM_NLIMITS];
+
+/// Limits of processes that don't use the defaults
+static LIMITS: RwLock<BTreeMap<Pid, Limits>> = RwLock::new(BTreeMap::new());
+
+fn default_limits() -> Limits {
+    let mut limits = [Limit::UNLIMITED; RLIM_NLIMITS];
+    limits[RLIMIT_STACK] = Limit::new(8 * 1024 * 1024, RLIM_INFINITY);
+    limits[RLIMIT_NPROC] = Limit::new(4096, 16384);
+    limits[RLIMIT_NOFILE] = Limit::new(1024, 4096);
+    limits[RLIMIT_MEMLOCK] = Limit::new(DEFAULT_MEMLOCK, DEFAULT_MEMLOCK);
+    limits
+}
+
+/// The limit on `resource` for `pid`
+pub fn get(pid: Pid, resource: usize) -> Option<Limit> {
+    if resource >= RLIM_NLIMITS {
+        return None;
+    }
+    Some(LIMITS.read().get(&pid).map_or_else(|| default_limits()[resource], |limits| limits[resource]))
+}
+
+/// Set the limit on `resource` for `pid`; `privileged` allows raising the
+/// hard limit
+pub fn set(pid: Pid, resource: usize, limit: Limit, privileged: bool) -> Result<Limit, LimitError> {
+    if resource >= RLIM_NLIMITS || limit.cur > limit.max {
+        return Err(LimitError::Invalid);
+    }
+
+    let mut table = LIMITS.write();
+    let limits = table.entry(pid).or_insert_with(default_limits);
+    let old = limits[resource];
+    if limit.max > old.max && !privileged {
+        return Err(LimitError::PermissionDenied);
+    }
+    limits[resource] = limit;
+    Ok(old)
+}
+
+/// Give a new child its parent's limits
+pub fn inherit(parent: Pid, child: Pid) {
+    let mut table = LIMITS.write();
+    if let Some(&limits) = table.get(&parent) {
+        table.insert(child, limits);
+    }
+}
+
+/// Forget the limits of an exiting process
+pub fn exit_process(pid: Pid) {
+    LIMITS.write().remove(&pid);
+}
@@ -0,0 +1,639 @@
+//! Seccomp
+//!
+//! Restricts the system calls a process may make, checked on every entry
+//! before the call is dispatched. A process starts unrestricted and can
+//! only tighten its own restrictions:
+//! - strict mode allows read, write, exit and rt_sigreturn; any other call
+//!   kills the process with SIGKILL
+//! - filter mode runs a stack of classic BPF programs over `SeccompData`,
+//!   the call's number, architecture, instruction pointer and arguments.
+//!   Every program in the stack runs, newest first, and the most
+//!   restrictive action returned wins.
+//!
+//! Installing a filter needs root or `no_new_privs`, so an unprivileged
+//! process can't trick a privileged program it executes. Filters, the mode
+//! and `no_new_privs` are inherited across fork and kept across exec; none
+//! of them can be removed.
+//!
+//! The state is kept per process. There are no user threads yet:
+//! `clone(CLONE_THREAD)` only checks its flags and creates nothing, so a
+//! process is always a single thread. Hence `SECCOMP_RET_KILL_THREAD` ends
+//! the whole process like `SECCOMP_RET_KILL_PROCESS`, and `TSYNC` has no
+//! other threads to synchronize. Threads, once added, must look up the
+//! state of their thread group rather than their own id.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::RwLock;
+
+use super::Pid;
+
+/// `seccomp` operations, as in the Linux ABI
+pub const SECCOMP_SET_MODE_STRICT: u32 = 0;
+pub const SECCOMP_SET_MODE_FILTER: u32 = 1;
+pub const SECCOMP_GET_ACTION_AVAIL: u32 = 2;
+
+/// `SECCOMP_SET_MODE_FILTER` flags. A process has no other threads, so
+/// `TSYNC` always succeeds, and there is no speculation to control.
+pub const SECCOMP_FILTER_FLAG_TSYNC: u32 = 1 << 0;
+pub const SECCOMP_FILTER_FLAG_LOG: u32 = 1 << 1;
+pub const SECCOMP_FILTER_FLAG_SPEC_ALLOW: u32 = 1 << 2;
+
+/// Filter return values, as in the Linux ABI. The top 16 bits are the
+/// action, the bottom 16 its data.
+pub const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+pub const SECCOMP_RET_KILL_THREAD: u32 = 0x0000_0000;
+pub const SECCOMP_RET_TRAP: u32 = 0x0003_0000;
+pub const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+pub const SECCOMP_RET_LOG: u32 = 0x7ffc_0000;
+pub const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+pub const SECCOMP_RET_ACTION_FULL: u32 = 0xffff_0000;
+pub const SECCOMP_RET_DATA: u32 = 0x0000_ffff;
+
+/// `AUDIT_ARCH_X86_64`, the only architecture calls are made with
+pub const AUDIT_ARCH_X86_64: u32 = 0xc000_003e;
+
+/// Longest single program, as Linux `BPF_MAXINSNS`
+pub const BPF_MAXINSNS: usize = 4096;
+
+/// Most instructions a call may run through a process's filters, counting
+/// 4 extra per filter for its overhead, as Linux
+pub const MAX_INSNS_PER_PATH: usize = 32768;
+
+/// Scratch memory words of a program
+const BPF_MEMWORDS: usize = 16;
+
+/// Calls allowed in strict mode: read, write, rt_sigreturn and exit
+const STRICT_SYSCALLS: [i32; 4] = [0, 1, 15, 60];
+
+/// Classic BPF opcodes, as in `linux/filter.h`
+pub mod bpf {
+    // Instruction classes
+    pub const LD: u16 = 0x00;
+    pub const LDX: u16 = 0x01;
+    pub const ST: u16 = 0x02;
+    pub const STX: u16 = 0x03;
+    pub const ALU: u16 = 0x04;
+    pub const JMP: u16 = 0x05;
+    pub const RET: u16 = 0x06;
+    pub const MISC: u16 = 0x07;
+
+    // Load sizes and modes
+    pub const W: u16 = 0x00;
+    pub const IMM: u16 = 0x00;
+    pub const ABS: u16 = 0x20;
+    pub const MEM: u16 = 0x60;
+    pub const LEN: u16 = 0x80;
+
+    // ALU operations
+    pub const ADD: u16 = 0x00;
+    pub const SUB: u16 = 0x10;
+    pub const MUL: u16 = 0x20;
+    pub const DIV: u16 = 0x30;
+    pub const OR: u16 = 0x40;
+    pub const AND: u16 = 0x50;
+    pub const LSH: u16 = 0x60;
+    pub const RSH: u16 = 0x70;
+    pub const NEG: u16 = 0x80;
+    pub const MOD: u16 = 0x90;
+    pub const XOR: u16 = 0xa0;
+
+    // Jumps
+    pub const JA: u16 = 0x00;
+    pub const JEQ: u16 = 0x10;
+    pub const JGT: u16 = 0x20;
+    pub const JGE: u16 = 0x30;
+    pub const JSET: u16 = 0x40;
+
+    // Operand sources
+    pub const K: u16 = 0x00;
+    pub const X: u16 = 0x08;
+    pub const A: u16 = 0x10;
+
+    // Register moves
+    pub const TAX: u16 = 0x00;
+    pub const TXA: u16 = 0x80;
+}
+
+/// One classic BPF instruction, `struct sock_filter`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl SockFilter {
+    /// Instruction without jump targets, like `BPF_STMT`
+    pub const fn stmt(code: u16, k: u32) -> Self {
+        Self { code, jt: 0, jf: 0, k }
+    }
+
+    /// Conditional jump, like `BPF_JUMP`
+    pub const fn jump(code: u16, k: u32, jt: u8, jf: u8) -> Self {
+        Self { code, jt, jf, k }
+    }
+}
+
+/// A program as passed by user space, `struct sock_fprog`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SockFprog {
+    pub len: u16,
+    pub filter: *const SockFilter,
+}
+
+/// What a filter sees of a call, `struct seccomp_data`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeccompData {
+    pub nr: i32,
+    pub arch: u32,
+    pub instruction_pointer: u64,
+    pub args: [u64; 6],
+}
+
+/// Size of `SeccompData`, what `BPF_LEN` loads
+const DATA_LEN: u32 = core::mem::size_of::<SeccompData>() as u32;
+
+impl SeccompData {
+    /// Data of call `nr` made from `instruction_pointer`
+    pub fn new(nr: u64, args: [u64; 6], instruction_pointer: u64) -> Self {
+        Self { nr: nr as i32, arch: AUDIT_ARCH_X86_64, instruction_pointer, args }
+    }
+
+    /// The 32-bit word at byte `offset`, which must be aligned and in bounds
+    fn word(&self, offset: u32) -> u32 {
+        let offset = offset as usize;
+        match offset {
+            0 => self.nr as u32,
+            4 => self.arch,
+            8 => self.instruction_pointer as u32,
+            12 => (self.instruction_pointer >> 32) as u32,
+            _ => {
+                let arg = self.args[(offset - 16) / 8];
+                if offset % 8 == 0 { arg as u32 } else { (arg >> 32) as u32 }
+            }
+        }
+    }
+}
+
+/// Seccomp mode of a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Disabled,
+    Strict,
+    Filter,
+}
+
+impl Mode {
+    /// Number used by `PR_GET_SECCOMP` and `/proc/<pid>/status`
+    pub fn number(self) -> u32 {
+        match self {
+            Mode::Disabled => 0,
+            Mode::Strict => 1,
+            Mode::Filter => 2,
+        }
+    }
+}
+
+/// Errors from changing a process's seccomp state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeccompError {
+    /// Bad flags, an invalid program, or a mode the process can't switch to
+    InvalidArgument,
+    /// The filters would exceed `MAX_INSNS_PER_PATH`
+    TooLarge,
+    /// Installing a filter needs root or `no_new_privs`
+    PermissionDenied,
+}
+
+/// One installed program, linked to those installed before it
+///
+/// Children share the chain of their parent, and new filters are added in
+/// front, so a chain is never modified once built.
+#[derive(Debug)]
+struct Filter {
+    prog: Vec<SockFilter>,
+    /// Log every action but `ALLOW`, from `SECCOMP_FILTER_FLAG_LOG`
+    log: bool,
+    prev: Option<Arc<Filter>>,
+}
+
+impl Filter {
+    /// Filters from this one back to the first installed
+    fn chain(&self) -> impl Iterator<Item = &Filter> {
+        let mut next = Some(self);
+        core::iter::from_fn(move || {
+            let filter = next?;
+            next = filter.prev.as_deref();
+            Some(filter)
+        })
+    }
+}
+
+/// Seccomp state of a process
+#[derive(Debug, Clone, Default)]
+struct State {
+    mode: Mode,
+    filter: Option<Arc<Filter>>,
+    no_new_privs: bool,
+}
+
+/// State of processes that changed it from the default
+static STATES: RwLock<BTreeMap<Pid, State>> = RwLock::new(BTreeMap::new());
+
+/// The seccomp mode of process `pid`
+pub fn mode(pid: Pid) -> Mode {
+    STATES.read().get(&pid).map_or(Mode::Disabled, |state| state.mode)
+}
+
+/// Number of filters installed for process `pid`
+pub fn filter_count(pid: Pid) -> usize {
+    STATES.read().get(&pid).and_then(|state| state.filter.as_ref()).map_or(0, |filter| filter.chain().count())
+}
+
+/// Whether process `pid` has set `no_new_privs`
+pub fn no_new_privs(pid: Pid) -> bool {
+    STATES.read().get(&pid).map_or(false, |state| state.no_new_privs)
+}
+
+/// Set `no_new_privs` for process `pid`; it can't be cleared again
+pub fn set_no_new_privs(pid: Pid) {
+    STATES.write().entry(pid).or_default().no_new_privs = true;
+}
+
+/// Put process `pid` in strict mode
+pub fn set_strict(pid: Pid) -> Result<(), SeccompError> {
+    let mut states = STATES.write();
+    let state = states.entry(pid).or_default();
+    if state.mode == Mode::Filter {
+        return Err(SeccompError::InvalidArgument);
+    }
+    state.mode = Mode::Strict;
+    Ok(())
+}
+
+/// Add `prog` to the filters of process `pid`
+///
+/// `privileged` stands in for `no_new_privs` when the caller is root.
+pub fn install_filter(pid: Pid, prog: Vec<SockFilter>, flags: u32, privileged: bool) -> Result<(), SeccompError> {
+    let known = SECCOMP_FILTER_FLAG_TSYNC | SECCOMP_FILTER_FLAG_LOG | SECCOMP_FILTER_FLAG_SPEC_ALLOW;
+    if flags & !known != 0 {
+        return Err(SeccompError::InvalidArgument);
+    }
+    validate(&prog)?;
+
+    let mut states = STATES.write();
+    let state = states.entry(pid).or_default();
+    if !state.no_new_privs && !privileged {
+        return Err(SeccompError::PermissionDenied);
+    }
+    if state.mode == Mode::Strict {
+        return Err(SeccompError::InvalidArgument);
+    }
+
+    let installed: usize = state.filter.as_ref().map_or(0, |filter| filter.chain().map(|f| f.prog.len() + 4).sum());
+    if installed + prog.len() > MAX_INSNS_PER_PATH {
+        return Err(SeccompError::TooLarge);
+    }
+
+    state.filter = Some(Arc::new(Filter {
+        prog,
+        log: flags & SECCOMP_FILTER_FLAG_LOG != 0,
+        prev: state.filter.take(),
+    }));
+    state.mode = Mode::Filter;
+    Ok(())
+}
+
+/// Whether filters may return `action`, for `SECCOMP_GET_ACTION_AVAIL`
+pub fn action_available(action: u32) -> bool {
+    matches!(
+        action,
+        SECCOMP_RET_KILL_PROCESS
+            | SECCOMP_RET_KILL_THREAD
+            | SECCOMP_RET_TRAP
+            | SECCOMP_RET_ERRNO
+            | SECCOMP_RET_LOG
+            | SECCOMP_RET_ALLOW
+    )
+}
+
+/// Give a forked child the seccomp state of its parent
+pub fn inherit(parent: Pid, child: Pid) {
+    let mut states = STATES.write();
+    if let Some(state) = states.get(&parent).cloned() {
+        states.insert(child, state);
+    }
+}
+
+/// Forget an exiting process
+pub fn exit_process(pid: Pid) {
+    STATES.write().remove(&pid);
+}
+
+/// Check a call of the current process against its seccomp state
+///
+/// Returns `None` if the call may go ahead, otherwise the value to return
+/// in its place. A call that kills the process doesn't return.
+pub fn check_syscall(nr: u64, args: [u64; 6], instruction_pointer: u64) -> Option<i64> {
+    let pid = super::current_pid();
+    let (mode, filter) = match STATES.read().get(&pid) {
+        Some(state) => (state.mode, state.filter.clone()),
+        None => return None,
+    };
+    let data = SeccompData::new(nr, args, instruction_pointer);
+
+    match mode {
+        Mode::Disabled => None,
+        Mode::Strict if STRICT_SYSCALLS.contains(&data.nr) => None,
+        Mode::Strict => {
+            log(pid, &data, SECCOMP_RET_KILL_THREAD);
+            kill(pid, 9)
+        }
+        Mode::Filter => {
+            let (ret, log_action) = filter.map_or((SECCOMP_RET_ALLOW, false), |filter| evaluate(&filter, &data));
+            let action = ret & SECCOMP_RET_ACTION_FULL;
+            let errno = (ret & SECCOMP_RET_DATA) as i64;
+            match action {
+                SECCOMP_RET_ALLOW => None,
+                SECCOMP_RET_LOG => {
+                    log(pid, &data, ret);
+                    None
+                }
+                SECCOMP_RET_ERRNO => {
+                    if log_action {
+                        log(pid, &data, ret);
+                    }
+                    Some(-errno)
+                }
+                SECCOMP_RET_TRAP => {
+                    if log_action {
+                        log(pid, &data, ret);
+                    }
+                    trap(pid, nr, ret & SECCOMP_RET_DATA)
+                }
+                // Kill actions, and anything else a filter may return
+                _ => {
+                    log(pid, &data, ret);
+                    kill(pid, 31)
+                }
+            }
+        }
+    }
+}
+
+/// Run every filter of a chain over `data`
+///
+/// Returns the most restrictive result, and whether the filter that
+/// returned it asked for its actions to be logged. Actions compare as
+/// signed numbers, so `KILL_PROCESS` beats everything and `ALLOW` nothing.
+fn evaluate(filter: &Arc<Filter>, data: &SeccompData) -> (u32, bool) {
+    let mut result = (SECCOMP_RET_ALLOW, false);
+    for filter in filter.chain() {
+        let ret = run(&filter.prog, data);
+        if ((ret & SECCOMP_RET_ACTION_FULL) as i32) < ((result.0 & SECCOMP_RET_ACTION_FULL) as i32) {
+            result = (ret, filter.log);
+        }
+    }
+    result
+}
+
+/// Note a call that a filter logged or stopped
+fn log(pid: Pid, data: &SeccompData, ret: u32) {
+    crate::serial_println!(
+        "seccomp: pid={} syscall={} arch={:#x} ip={:#x} code={:#x}",
+        pid,
+        data.nr,
+        data.arch,
+        data.instruction_pointer,
+        ret
+    );
+}
+
+/// Send SIGSYS for a trapped call, carrying the filter's data
+///
+/// The call fails with ENOSYS if the process handles the signal; without
+/// a handler the signal kills it.
+fn trap(pid: Pid, nr: u64, data: u32) -> Option<i64> {
+    let ipc_manager = super::ipc::get_ipc_manager();
+    let info = (nr << 16) | data as u64;
+    match ipc_manager.force_signal(pid, super::ipc::Signal::SIGSYS, info) {
+        Ok(super::ipc::SignalDisposition::Handler(_)) => Some(-38),
+        _ => kill(pid, 31),
+    }
+}
+
+/// End the current process as killed by `signal`
+fn kill(pid: Pid, signal: i32) -> Option<i64> {
+    let _ = super::get_process_manager().terminate_process(pid, -signal);
+    // Never returns: the scheduler drops terminated processes
+    crate::scheduler::schedule();
+    Some(-38)
+}
+
+/// Check a program the way Linux does before installing it
+///
+/// Loads must stay inside `SeccompData`, jumps inside the program, scratch
+/// memory inside its 16 words, and the last instruction must return.
+/// Jumps only go forward, so every valid program ends.
+fn validate(prog: &[SockFilter]) -> Result<(), SeccompError> {
+    use bpf::*;
+
+    if prog.is_empty() || prog.len() > BPF_MAXINSNS {
+        return Err(SeccompError::InvalidArgument);
+    }
+
+    let len = prog.len();
+    let in_range = |pc: usize, offset: usize| pc + 1 + offset < len;
+
+    for (pc, insn) in prog.iter().enumerate() {
+        let k = insn.k;
+        let valid = match insn.code {
+            c if c == LD | W | ABS => k % 4 == 0 && k < DATA_LEN,
+            c if c == LD | W | LEN || c == LDX | W | LEN => true,
+            c if c == LD | IMM || c == LDX | IMM => true,
+            c if c == LD | MEM || c == LDX | MEM || c == ST || c == STX => (k as usize) < BPF_MEMWORDS,
+            c if c == MISC | TAX || c == MISC | TXA => true,
+            c if c == RET | K || c == RET | A => true,
+            c if c == ALU | NEG => true,
+            c if c & 0x07 == ALU => {
+                let op = c & 0xf0;
+                let known = matches!(op, ADD | SUB | MUL | DIV | OR | AND | LSH | RSH | MOD | XOR);
+                let from_k = c & X == 0;
+                known && c & !(0xf0 | X) == ALU
+                    && !(from_k && (op == DIV || op == MOD) && k == 0)
+                    && !(from_k && (op == LSH || op == RSH) && k >= 32)
+            }
+            c if c == JMP | JA => in_range(pc, k as usize),
+            c if c & 0x07 == JMP => {
+                let op = c & 0xf0;
+                matches!(op, JEQ | JGT | JGE | JSET)
+                    && c & !(0xf0 | X) == JMP
+                    && in_range(pc, insn.jt as usize)
+                    && in_range(pc, insn.jf as usize)
+            }
+            _ => false,
+        };
+        if !valid {
+            return Err(SeccompError::InvalidArgument);
+        }
+    }
+
+    match prog[len - 1].code {
+        c if c == RET | K || c == RET | A => Ok(()),
+        _ => Err(SeccompError::InvalidArgument),
+    }
+}
+
+/// Run a validated program over `data`
+fn run(prog: &[SockFilter], data: &SeccompData) -> u32 {
+    use bpf::*;
+
+    let mut a: u32 = 0;
+    let mut x: u32 = 0;
+    let mut mem = [0u32; BPF_MEMWORDS];
+    let mut pc = 0;
+
+    loop {
+        let insn = prog[pc];
+        let k = insn.k;
+        pc += 1;
+
+        match insn.code & 0x07 {
+            LD => {
+                a = match insn.code & 0xe0 {
+                    ABS => data.word(k),
+                    LEN => DATA_LEN,
+                    MEM => mem[k as usize],
+                    _ => k,
+                }
+            }
+            LDX => {
+                x = match insn.code & 0xe0 {
+                    LEN => DATA_LEN,
+                    MEM => mem[k as usize],
+                    _ => k,
+                }
+            }
+            ST => mem[k as usize] = a,
+            STX => mem[k as usize] = x,
+            ALU => {
+                let operand = if insn.code & X != 0 { x } else { k };
+                a = match insn.code & 0xf0 {
+                    ADD => a.wrapping_add(operand),
+                    SUB => a.wrapping_sub(operand),
+                    MUL => a.wrapping_mul(operand),
+                    // Dividing by a zero X ends the program, returning 0
+                    DIV | MOD if operand == 0 => return 0,
+                    DIV => a / operand,
+                    MOD => a % operand,
+                    OR => a | operand,
+                    AND => a & operand,
+                    LSH => a.checked_shl(operand).unwrap_or(0),
+                    RSH => a.checked_shr(operand).unwrap_or(0),
+                    XOR => a ^ operand,
+                    _ => a.wrapping_neg(),
+                }
+            }
+            JMP => {
+                let operand = if insn.code & X != 0 { x } else { k };
+                let taken = match insn.code & 0xf0 {
+                    JA => {
+                        pc += k as usize;
+                        continue;
+                    }
+                    JEQ => a == operand,
+                    JGT => a > operand,
+                    JGE => a >= operand,
+                    _ => a & operand != 0,
+                };
+                pc += usize::from(if taken { insn.jt } else { insn.jf });
+            }
+            RET => return if insn.code & A != 0 { a } else { k },
+            _ => {
+                if insn.code & 0xf8 == TXA {
+                    a = x;
+                } else {
+                    x = a;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::bpf::*;
+
+    /// Allow everything but `getpid`, which fails with EPERM
+    fn deny_getpid() -> Vec<SockFilter> {
+        alloc::vec![
+            SockFilter::stmt(LD | W | ABS, 4),
+            SockFilter::jump(JMP | JEQ | K, AUDIT_ARCH_X86_64, 1, 0),
+            SockFilter::stmt(RET | K, SECCOMP_RET_KILL_PROCESS),
+            SockFilter::stmt(LD | W | ABS, 0),
+            SockFilter::jump(JMP | JEQ | K, 39, 0, 1),
+            SockFilter::stmt(RET | K, SECCOMP_RET_ERRNO | 1),
+            SockFilter::stmt(RET | K, SECCOMP_RET_ALLOW),
+        ]
+    }
+
+    #[test]
+    fn test_filter_matches_syscall_number() {
+        let prog = deny_getpid();
+        assert_eq!(validate(&prog), Ok(()));
+        assert_eq!(run(&prog, &SeccompData::new(39, [0; 6], 0)), SECCOMP_RET_ERRNO | 1);
+        assert_eq!(run(&prog, &SeccompData::new(0, [0; 6], 0)), SECCOMP_RET_ALLOW);
+    }
+
+    #[test]
+    fn test_filter_reads_argument_halves() {
+        let prog = alloc::vec![
+            SockFilter::stmt(LD | W | ABS, 16 + 4),
+            SockFilter::jump(JMP | JEQ | K, 0x1234, 0, 1),
+            SockFilter::stmt(RET | K, SECCOMP_RET_TRAP),
+            SockFilter::stmt(RET | K, SECCOMP_RET_ALLOW),
+        ];
+        assert_eq!(validate(&prog), Ok(()));
+        let data = SeccompData::new(1, [0x1234_0000_0000, 0, 0, 0, 0, 0], 0);
+        assert_eq!(run(&prog, &data), SECCOMP_RET_TRAP);
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_programs() {
+        let out_of_bounds = [SockFilter::stmt(LD | W | ABS, DATA_LEN), SockFilter::stmt(RET | A, 0)];
+        let unaligned = [SockFilter::stmt(LD | W | ABS, 2), SockFilter::stmt(RET | A, 0)];
+        let no_return = [SockFilter::stmt(LD | IMM, 0)];
+        let jump_past_end = [SockFilter::jump(JMP | JEQ | K, 0, 1, 0), SockFilter::stmt(RET | A, 0)];
+        let divide_by_zero = [SockFilter::stmt(ALU | DIV | K, 0), SockFilter::stmt(RET | A, 0)];
+        let programs: [&[SockFilter]; 6] = [&out_of_bounds, &unaligned, &no_return, &jump_past_end, &divide_by_zero, &[]];
+        for prog in programs {
+            assert_eq!(validate(prog), Err(SeccompError::InvalidArgument));
+        }
+    }
+
+    #[test]
+    fn test_most_restrictive_action_wins() {
+        let allow = Arc::new(Filter {
+            prog: alloc::vec![SockFilter::stmt(RET | K, SECCOMP_RET_ALLOW)],
+            log: false,
+            prev: None,
+        });
+        let errno = Arc::new(Filter { prog: deny_getpid(), log: true, prev: Some(allow) });
+        let kill = Arc::new(Filter {
+            prog: alloc::vec![SockFilter::stmt(RET | K, SECCOMP_RET_KILL_PROCESS)],
+            log: false,
+            prev: Some(errno.clone()),
+        });
+        assert_eq!(evaluate(&errno, &SeccompData::new(39, [0; 6], 0)), (SECCOMP_RET_ERRNO | 1, true));
+        assert_eq!(evaluate(&errno, &SeccompData::new(0, [0; 6], 0)), (SECCOMP_RET_ALLOW, false));
+        assert_eq!(evaluate(&kill, &SeccompData::new(39, [0; 6], 0)).0, SECCOMP_RET_KILL_PROCESS);
+    }
+}
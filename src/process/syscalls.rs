@@ -8,6 +8,7 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use alloc::vec;
 use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 /// System call numbers
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -44,6 +45,7 @@ pub enum SyscallNumber {
     Sbrk = 23,
     MProtect = 24,      // Change memory protection
     Madvise = 25,       // Give advice about memory usage
+    Msync = 26,         // Write back shared file mappings
 
     // Process communication
     Pipe = 30,
@@ -111,6 +113,7 @@ impl From<u64> for SyscallNumber {
             23 => SyscallNumber::Sbrk,
             24 => SyscallNumber::MProtect,
             25 => SyscallNumber::Madvise,
+            26 => SyscallNumber::Msync,
             30 => SyscallNumber::Pipe,
             31 => SyscallNumber::Signal,
             32 => SyscallNumber::Kill,
@@ -175,6 +178,8 @@ pub enum SyscallError {
     InvalidExecutable = 0xFFFFFFFFFFFFFFF3,
     FileTooLarge = 0xFFFFFFFFFFFFFFF2,
     NotFound = 0xFFFFFFFFFFFFFFF1,
+    Interrupted = 0xFFFFFFFFFFFFFFF0,
+    TimedOut = 0xFFFFFFFFFFFFFFEF,
 }
 
 /// File open flags
@@ -202,31 +207,34 @@ impl From<u64> for OpenFlags {
 }
 
 /// System call dispatcher
+///
+/// Shared by every CPU without a lock: a call may block in the scheduler,
+/// and the dispatcher must stay usable while it does.
 pub struct SyscallDispatcher {
     /// System call statistics
-    syscall_count: [u64; 64],
+    syscall_count: [AtomicU64; 64],
     /// Total system calls handled
-    total_syscalls: u64,
+    total_syscalls: AtomicU64,
 }
 
 impl SyscallDispatcher {
     /// Create a new system call dispatcher
     pub const fn new() -> Self {
         Self {
-            syscall_count: [0; 64],
-            total_syscalls: 0,
+            syscall_count: [const { AtomicU64::new(0) }; 64],
+            total_syscalls: AtomicU64::new(0),
         }
     }
 
     /// Dispatch a system call
-    pub fn dispatch(&mut self, syscall_number: u64, args: &[u64], process_manager: &ProcessManager) -> Result<u64, &'static str> {
-        self.total_syscalls += 1;
+    pub fn dispatch(&self, syscall_number: u64, args: &[u64], process_manager: &ProcessManager) -> Result<u64, &'static str> {
+        self.total_syscalls.fetch_add(1, Ordering::Relaxed);
 
         let syscall = SyscallNumber::from(syscall_number);
 
         // Update statistics
         if (syscall_number as usize) < self.syscall_count.len() {
-            self.syscall_count[syscall_number as usize] += 1;
+            self.syscall_count[syscall_number as usize].fetch_add(1, Ordering::Relaxed);
         }
 
         let current_pid = process_manager.current_process();
@@ -258,6 +266,7 @@ impl SyscallDispatcher {
             SyscallNumber::Sbrk => self.sys_sbrk(args, process_manager, current_pid),
             SyscallNumber::MProtect => self.sys_mprotect(args, process_manager, current_pid),
             SyscallNumber::Madvise => self.sys_madvise(args, process_manager, current_pid),
+            SyscallNumber::Msync => self.sys_msync(args, process_manager, current_pid),
             SyscallNumber::Pipe => self.sys_pipe(args, process_manager, current_pid),
             SyscallNumber::Signal => self.sys_signal(args, process_manager, current_pid),
             SyscallNumber::Kill => self.sys_kill(args, process_manager, current_pid),
@@ -297,7 +306,11 @@ impl SyscallDispatcher {
         let exit_status = args.get(0).copied().unwrap_or(0) as i32;
 
         match process_manager.terminate_process(current_pid, exit_status) {
-            Ok(()) => SyscallResult::Success(0),
+            Ok(()) => {
+                // Never returns: the scheduler drops terminated processes
+                crate::scheduler::schedule();
+                SyscallResult::Success(0)
+            }
             Err(_) => SyscallResult::Error(SyscallError::ProcessNotFound),
         }
     }
@@ -321,8 +334,15 @@ impl SyscallDispatcher {
         let integration_manager = get_integration_manager();
         match integration_manager.fork_process(current_pid) {
             Ok(child_pid) => {
+                // The child gets its own copy of every descriptor
+                let file_descriptors: BTreeMap<u32, super::FileDescriptor> = parent_process.file_descriptors
+                    .iter()
+                    .filter_map(|(&fd, file_desc)| Some((fd, file_desc.fork_copy()?)))
+                    .collect();
+
                 // Verify child process was created successfully
-                if let Some(mut child_process) = process_manager.get_process(child_pid) {
+                let mut processes = process_manager.processes.write();
+                if let Some(child_process) = processes.get_mut(&child_pid) {
                     // Ensure parent-child relationship is properly set
                     if child_process.parent_pid != Some(current_pid) {
                         // Fix parent-child relationship if not set correctly
@@ -330,7 +350,7 @@ impl SyscallDispatcher {
                     }
 
                     // Copy file descriptors from parent to child
-                    child_process.file_descriptors = parent_process.file_descriptors.clone();
+                    child_process.file_descriptors = file_descriptors;
                     child_process.file_offsets = parent_process.file_offsets.clone();
 
                     // Copy signal handlers from parent to child
@@ -344,6 +364,10 @@ impl SyscallDispatcher {
                     SyscallResult::Success(child_pid as u64)
                 } else {
                     // Child process creation failed
+                    drop(processes);
+                    for vfs_fd in file_descriptors.values().filter_map(|file_desc| file_desc.open_file_fd()) {
+                        let _ = crate::vfs::vfs_close(vfs_fd);
+                    }
                     SyscallResult::Error(SyscallError::OutOfMemory)
                 }
             }
@@ -523,6 +547,8 @@ impl SyscallDispatcher {
         // Clear signal handlers (reset to default)
         process.signal_handlers.clear();
 
+        process_manager.close_open_files(current_pid, true);
+
         // Success - return 0
         SyscallResult::Success(0)
     }
@@ -563,7 +589,7 @@ impl SyscallDispatcher {
                 if matches!(child.state, ProcessState::Terminated) {
                     // Reap the child process
                     let exit_code = child.exit_code.unwrap_or(0);
-                    process_manager.processes.write().remove(&child_pid);
+                    process_manager.reap_process(child_pid);
                     return SyscallResult::Success(((child_pid as u64) << 32) | (exit_code as u64));
                 }
             }
@@ -614,33 +640,27 @@ impl SyscallDispatcher {
             return SyscallResult::Success(0);
         }
 
-        // Block the process temporarily
-        match process_manager.block_process(current_pid) {
-            Ok(()) => {
-                // Calculate wake-up time using the time subsystem
-                let current_time_ms = crate::time::uptime_ms();
-                let wake_time = current_time_ms + sleep_time_ms;
-
-                // Store wake-up time in process control block
-                {
-                    let mut processes = process_manager.processes.write();
-                    if let Some(pcb) = processes.get_mut(&current_pid) {
-                        pcb.wake_time = Some(wake_time);
-                    }
-                }
+        let deadline_ns = crate::time::uptime_ns() + sleep_time_ms * 1_000_000;
 
-                // TODO: Schedule a timer callback to wake the process
-                // Note: Timer callback system needs update to support closures with captures
-                // For now, process will need to be woken by scheduler or other mechanism
-                // let pid_copy = current_pid;
-                // crate::time::schedule_timer(sleep_time_ms * 1000, move || {
-                //     let pm = super::get_process_manager();
-                //     let _ = pm.unblock_process(pid_copy);
-                // });
+        // Record the wake-up time in the process control block
+        {
+            let mut processes = process_manager.processes.write();
+            match processes.get_mut(&current_pid) {
+                Some(pcb) => pcb.wake_time = Some(deadline_ns / 1_000_000),
+                None => return SyscallResult::Error(SyscallError::ProcessNotFound),
+            }
+        }
 
-                SyscallResult::Success(0)
-            },
-            Err(_) => SyscallResult::Error(SyscallError::ProcessNotFound),
+        // Blocks until the timer queue wakes us or a signal arrives
+        let result = super::timers::sleep_until(deadline_ns);
+
+        if let Some(pcb) = process_manager.processes.write().get_mut(&current_pid) {
+            pcb.wake_time = None;
+        }
+
+        match result {
+            Ok(()) => SyscallResult::Success(0),
+            Err(_) => SyscallResult::Error(SyscallError::Interrupted),
         }
     }
 
@@ -689,14 +709,21 @@ impl SyscallDispatcher {
         let fd = args.get(0).copied().unwrap_or(0) as u32;
 
         // Get process and close file descriptor
-        if let Some(mut process) = process_manager.get_process(current_pid) {
-            if process.file_descriptors.remove(&fd).is_some() {
-                SyscallResult::Success(0)
-            } else {
-                SyscallResult::Error(SyscallError::InvalidFileDescriptor)
-            }
-        } else {
-            SyscallResult::Error(SyscallError::ProcessNotFound)
+        let removed = match process_manager.processes.write().get_mut(&current_pid) {
+            Some(process) => process.file_descriptors.remove(&fd),
+            None => return SyscallResult::Error(SyscallError::ProcessNotFound),
+        };
+
+        match removed {
+            // Dropping a pipe end is what lets the other side see EOF
+            Some(file_desc) => match file_desc.open_file_fd() {
+                Some(vfs_fd) => match crate::vfs::vfs_close(vfs_fd) {
+                    Ok(()) => SyscallResult::Success(0),
+                    Err(e) => SyscallResult::Error(vfs_error_to_syscall(e)),
+                },
+                None => SyscallResult::Success(0),
+            },
+            None => SyscallResult::Error(SyscallError::InvalidFileDescriptor),
         }
     }
 
@@ -723,6 +750,22 @@ impl SyscallDispatcher {
                 }
             }
 
+            // Pipes live in the VFS file table
+            let vfs_fd = process.file_descriptors.get(&fd).and_then(|file_desc| file_desc.open_file_fd());
+            if let Some(vfs_fd) = vfs_fd {
+                let mut buffer = vec![0u8; count];
+                return match crate::vfs::vfs_read(vfs_fd, &mut buffer) {
+                    Ok(bytes_read) => {
+                        if self.copy_to_user(buffer_ptr, &buffer[..bytes_read]).is_ok() {
+                            SyscallResult::Success(bytes_read as u64)
+                        } else {
+                            SyscallResult::Error(SyscallError::InvalidAddress)
+                        }
+                    }
+                    Err(e) => SyscallResult::Error(vfs_error_to_syscall(e)),
+                };
+            }
+
             // Handle regular files
             if let Some(file_desc) = process.file_descriptors.get_mut(&fd) {
                 let mut buffer = vec![0u8; count];
@@ -767,6 +810,20 @@ impl SyscallDispatcher {
                 return SyscallResult::Success(count as u64);
             }
 
+            // Pipes live in the VFS file table
+            let vfs_fd = process.file_descriptors.get(&fd).and_then(|file_desc| file_desc.open_file_fd());
+            if let Some(vfs_fd) = vfs_fd {
+                let mut buffer = vec![0u8; count];
+                if self.copy_from_user(buffer_ptr, &mut buffer).is_err() {
+                    return SyscallResult::Error(SyscallError::InvalidAddress);
+                }
+
+                return match crate::vfs::vfs_write(vfs_fd, &buffer) {
+                    Ok(bytes_written) => SyscallResult::Success(bytes_written as u64),
+                    Err(e) => SyscallResult::Error(vfs_error_to_syscall(e)),
+                };
+            }
+
             // Handle regular files
             if let Some(file_desc) = process.file_descriptors.get_mut(&fd) {
                 // Copy from user buffer
@@ -903,12 +960,12 @@ impl SyscallDispatcher {
     fn sys_mmap(&self, args: &[u64], _process_manager: &ProcessManager, _current_pid: Pid) -> SyscallResult {
         use crate::memory::{allocate_memory, MemoryRegionType, MemoryProtection};
 
-        let _addr = args.get(0).copied().unwrap_or(0);
+        let addr = args.get(0).copied().unwrap_or(0);
         let length = args.get(1).copied().unwrap_or(0);
         let prot = args.get(2).copied().unwrap_or(0);
-        let _flags = args.get(3).copied().unwrap_or(0);
+        let flags = args.get(3).copied().unwrap_or(0);
         let fd = args.get(4).copied().unwrap_or(0) as i32;
-        let _offset = args.get(5).copied().unwrap_or(0);
+        let offset = args.get(5).copied().unwrap_or(0);
 
         if length == 0 {
             return SyscallResult::Error(SyscallError::InvalidArgument);
@@ -930,6 +987,15 @@ impl SyscallDispatcher {
             guard_page: false,
         };
 
+        // Anonymous huge pages come from the hugetlb pool
+        const MAP_HUGETLB: u64 = 0x40000;
+        if fd == -1 && flags & MAP_HUGETLB != 0 {
+            return match crate::memory::huge_page::map_anonymous(length as usize, protection, None) {
+                Ok(virt_addr) => SyscallResult::Success(virt_addr.as_u64()),
+                Err(_) => SyscallResult::Error(SyscallError::OutOfMemory),
+            };
+        }
+
         // Determine memory region type
         let region_type = if fd == -1 {
             // Anonymous mapping
@@ -939,8 +1005,8 @@ impl SyscallDispatcher {
                 MemoryRegionType::UserData
             }
         } else {
-            // File mapping (not implemented)
-            return SyscallResult::Error(SyscallError::OperationNotSupported);
+            // File mapping, demand-faulted from the page cache
+            return self.mmap_file(addr, length, protection, flags, fd, offset);
         };
 
         // Allocate memory
@@ -950,19 +1016,74 @@ impl SyscallDispatcher {
         }
     }
 
+    /// Map `length` bytes of the file open on `fd` for sys_mmap
+    fn mmap_file(
+        &self,
+        addr: u64,
+        length: u64,
+        protection: crate::memory::MemoryProtection,
+        flags: u64,
+        fd: i32,
+        offset: u64,
+    ) -> SyscallResult {
+        use crate::memory::file_mapping::{self, MapError};
+        use crate::memory::MemoryError;
+        use x86_64::VirtAddr;
+
+        const MAP_SHARED: u64 = 0x01;
+        const MAP_FIXED: u64 = 0x10;
+
+        let fixed = if flags & MAP_FIXED != 0 {
+            match VirtAddr::try_new(addr) {
+                Ok(addr) => Some(addr),
+                Err(_) => return SyscallResult::Error(SyscallError::InvalidAddress),
+            }
+        } else {
+            None
+        };
+
+        match file_mapping::map_fd(fd, offset, length as usize, protection, flags & MAP_SHARED != 0, fixed) {
+            Ok(virt_addr) => SyscallResult::Success(virt_addr.as_u64()),
+            Err(MapError::BadFd) => SyscallResult::Error(SyscallError::InvalidFileDescriptor),
+            Err(MapError::Access) => SyscallResult::Error(SyscallError::PermissionDenied),
+            Err(MapError::NoDevice) => SyscallResult::Error(SyscallError::OperationNotSupported),
+            Err(MapError::Memory(MemoryError::RegionOverlap)) => SyscallResult::Error(SyscallError::InvalidAddress),
+            Err(MapError::InvalidArgument | MapError::Memory(MemoryError::InvalidAddress)) => {
+                SyscallResult::Error(SyscallError::InvalidArgument)
+            }
+            Err(MapError::Memory(_)) => SyscallResult::Error(SyscallError::OutOfMemory),
+        }
+    }
+
     /// sys_munmap - Unmap memory using production memory manager
     fn sys_munmap(&self, args: &[u64], _process_manager: &ProcessManager, _current_pid: Pid) -> SyscallResult {
-        use crate::memory::deallocate_memory;
+        use crate::memory::{deallocate_memory, file_mapping, huge_page};
         use x86_64::VirtAddr;
 
         let addr = args.get(0).copied().unwrap_or(0);
-        let _length = args.get(1).copied().unwrap_or(0);
+        let length = args.get(1).copied().unwrap_or(0);
 
         if addr == 0 {
             return SyscallResult::Error(SyscallError::InvalidArgument);
         }
 
         let virt_addr = VirtAddr::new(addr);
+
+        // File mappings may be unmapped in part
+        if length != 0 && file_mapping::overlaps(virt_addr, length as usize) {
+            return match file_mapping::unmap(virt_addr, length as usize) {
+                Ok(()) => SyscallResult::Success(0),
+                Err(_) => SyscallResult::Error(SyscallError::IoError),
+            };
+        }
+        // hugetlb pages go back to the pool
+        if length != 0 && huge_page::overlaps(virt_addr, length as usize) {
+            return match huge_page::unmap(virt_addr, length as usize) {
+                Ok(()) => SyscallResult::Success(0),
+                Err(_) => SyscallResult::Error(SyscallError::InvalidArgument),
+            };
+        }
+
         match deallocate_memory(virt_addr) {
             Ok(()) => SyscallResult::Success(0),
             Err(_) => SyscallResult::Error(SyscallError::InvalidArgument),
@@ -1072,22 +1193,60 @@ impl SyscallDispatcher {
     // Inter-process communication
 
     /// sys_pipe - Create a pipe
-    fn sys_pipe(&self, args: &[u64], _process_manager: &ProcessManager, _current_pid: Pid) -> SyscallResult {
+    ///
+    /// Both ends get descriptors in the process's own table, pointing at the
+    /// pipe's entries in the VFS open-file table.
+    fn sys_pipe(&self, args: &[u64], process_manager: &ProcessManager, current_pid: Pid) -> SyscallResult {
         let pipefd_ptr = args.get(0).copied().unwrap_or(0);
         
         if pipefd_ptr == 0 {
             return SyscallResult::Error(SyscallError::InvalidArgument);
         }
 
-        // Use production IPC pipe creation
-        match crate::ipc::create_pipe(4096) { // 4KB pipe buffer
-            Ok(pipe_id) => {
-                // In real implementation, would write pipe FDs to user memory
-                // Return pipe ID for now
-                SyscallResult::Success(pipe_id as u64)
+        let (read_fd, write_fd) = match crate::vfs::vfs_pipe(0) {
+            Ok(fds) => fds,
+            Err(e) => return SyscallResult::Error(vfs_error_to_syscall(e)),
+        };
+
+        let reserved = match process_manager.processes.write().get_mut(&current_pid) {
+            Some(process) => {
+                let mut reserve = |vfs_fd: i32| {
+                    let mut next_fd = 3; // Start after stdin/stdout/stderr
+                    while process.file_descriptors.contains_key(&next_fd) {
+                        next_fd += 1;
+                    }
+                    process.file_descriptors.insert(next_fd, super::FileDescriptor::open_file(vfs_fd, 0));
+                    next_fd
+                };
+                Some((reserve(read_fd), reserve(write_fd)))
+            }
+            None => None,
+        };
+        let (read_end, write_end) = match reserved {
+            Some(ends) => ends,
+            None => {
+                let _ = crate::vfs::vfs_close(read_fd);
+                let _ = crate::vfs::vfs_close(write_fd);
+                return SyscallResult::Error(SyscallError::ProcessNotFound);
             }
-            Err(_) => SyscallResult::Error(SyscallError::OperationNotSupported)
+        };
+
+        // int pipefd[2]
+        let mut fds = [0u8; 8];
+        fds[..4].copy_from_slice(&(read_end as i32).to_ne_bytes());
+        fds[4..].copy_from_slice(&(write_end as i32).to_ne_bytes());
+
+        if self.copy_to_user(pipefd_ptr, &fds).is_err() {
+            if let Some(process) = process_manager.processes.write().get_mut(&current_pid) {
+                process.file_descriptors.remove(&read_end);
+                process.file_descriptors.remove(&write_end);
+            }
+            let _ = crate::vfs::vfs_close(read_fd);
+            let _ = crate::vfs::vfs_close(write_fd);
+            return SyscallResult::Error(SyscallError::InvalidAddress);
         }
+
+        SyscallResult::Success(0)
     }
 
     /// sys_signal - Set signal handler
@@ -1333,8 +1492,8 @@ impl SyscallDispatcher {
             }
         }
 
-        // Notify scheduler of priority change using process/scheduler module
-        match super::scheduler::set_process_priority(target_pid, new_priority) {
+        // Notify scheduler of priority change
+        match crate::scheduler::set_process_priority(target_pid, new_priority) {
             Ok(()) => SyscallResult::Success(0),
             Err(_) => SyscallResult::Error(SyscallError::InvalidArgument),
         }
@@ -1398,23 +1557,146 @@ impl SyscallDispatcher {
     }
 
     /// sys_mprotect - Change memory protection
-    fn sys_mprotect(&self, _args: &[u64], _process_manager: &ProcessManager, _current_pid: Pid) -> SyscallResult {
-        // TODO: Implement mprotect() for dynamic memory protection changes
-        // Critical for dynamic linker (making GOT writable, then read-only)
-        SyscallResult::Error(SyscallError::OperationNotSupported)
+    fn sys_mprotect(&self, args: &[u64], _process_manager: &ProcessManager, _current_pid: Pid) -> SyscallResult {
+        use crate::memory::{get_memory_manager, MemoryError, MemoryProtection};
+        use x86_64::VirtAddr;
+
+        const PROT_READ: u64 = 0x1;
+        const PROT_WRITE: u64 = 0x2;
+        const PROT_EXEC: u64 = 0x4;
+
+        let addr = args.get(0).copied().unwrap_or(0);
+        let length = args.get(1).copied().unwrap_or(0);
+        let prot = args.get(2).copied().unwrap_or(0);
+
+        if prot & !(PROT_READ | PROT_WRITE | PROT_EXEC) != 0 {
+            return SyscallResult::Error(SyscallError::InvalidArgument);
+        }
+        let Ok(virt_addr) = VirtAddr::try_new(addr) else {
+            return SyscallResult::Error(SyscallError::InvalidAddress);
+        };
+        let Some(mm) = get_memory_manager() else {
+            return SyscallResult::Error(SyscallError::OutOfMemory);
+        };
+
+        // Write and execute imply read on x86_64
+        let protection = MemoryProtection {
+            readable: prot != 0,
+            writable: prot & PROT_WRITE != 0,
+            executable: prot & PROT_EXEC != 0,
+            ..MemoryProtection::USER_DATA
+        };
+        match mm.mprotect(virt_addr, length as usize, protection) {
+            Ok(()) => SyscallResult::Success(0),
+            Err(MemoryError::PermissionDenied) => SyscallResult::Error(SyscallError::PermissionDenied),
+            Err(MemoryError::RegionNotFound) => SyscallResult::Error(SyscallError::OutOfMemory),
+            Err(_) => SyscallResult::Error(SyscallError::InvalidArgument),
+        }
     }
 
     /// sys_madvise - Give advice about memory usage
-    fn sys_madvise(&self, _args: &[u64], _process_manager: &ProcessManager, _current_pid: Pid) -> SyscallResult {
-        // TODO: Implement madvise() for memory usage hints
-        SyscallResult::Error(SyscallError::OperationNotSupported)
+    fn sys_madvise(&self, args: &[u64], _process_manager: &ProcessManager, _current_pid: Pid) -> SyscallResult {
+        use crate::memory::{get_memory_manager, vma::Advice, MemoryError};
+        use x86_64::VirtAddr;
+
+        let addr = args.get(0).copied().unwrap_or(0);
+        let length = args.get(1).copied().unwrap_or(0);
+
+        let advice = match args.get(2).copied().unwrap_or(0) {
+            0 => Advice::Normal,
+            1 => Advice::Random,
+            2 => Advice::Sequential,
+            3 => Advice::WillNeed,
+            4 => Advice::DontNeed,
+            8 => Advice::Free,
+            9 => Advice::Remove,
+            14 => Advice::HugePage,
+            15 => Advice::NoHugePage,
+            // MADV_MERGEABLE, MADV_UNMERGEABLE
+            12 | 13 => return SyscallResult::Success(0),
+            _ => return SyscallResult::Error(SyscallError::InvalidArgument),
+        };
+        let Ok(virt_addr) = VirtAddr::try_new(addr) else {
+            return SyscallResult::Error(SyscallError::InvalidAddress);
+        };
+        let Some(mm) = get_memory_manager() else {
+            return SyscallResult::Error(SyscallError::OutOfMemory);
+        };
+
+        match mm.madvise(virt_addr, length as usize, advice) {
+            Ok(()) => SyscallResult::Success(0),
+            Err(MemoryError::RegionNotFound) => SyscallResult::Error(SyscallError::OutOfMemory),
+            Err(MemoryError::IoError) => SyscallResult::Error(SyscallError::IoError),
+            Err(_) => SyscallResult::Error(SyscallError::InvalidArgument),
+        }
+    }
+
+    /// sys_msync - Write back dirty pages of shared file mappings
+    fn sys_msync(&self, args: &[u64], _process_manager: &ProcessManager, _current_pid: Pid) -> SyscallResult {
+        use crate::memory::{file_mapping, PAGE_SIZE};
+        use x86_64::VirtAddr;
+
+        const MS_ASYNC: u64 = 1;
+        const MS_INVALIDATE: u64 = 2;
+        const MS_SYNC: u64 = 4;
+
+        let addr = args.get(0).copied().unwrap_or(0);
+        let length = args.get(1).copied().unwrap_or(0);
+        let flags = args.get(2).copied().unwrap_or(0);
+
+        if flags & !(MS_ASYNC | MS_INVALIDATE | MS_SYNC) != 0
+            || flags & (MS_ASYNC | MS_SYNC) == (MS_ASYNC | MS_SYNC)
+            || addr % PAGE_SIZE as u64 != 0
+        {
+            return SyscallResult::Error(SyscallError::InvalidArgument);
+        }
+        let Ok(virt_addr) = VirtAddr::try_new(addr) else {
+            return SyscallResult::Error(SyscallError::InvalidAddress);
+        };
+
+        // MS_ASYNC leaves the pages to the periodic flusher
+        if flags & MS_SYNC == 0 {
+            return SyscallResult::Success(0);
+        }
+        match file_mapping::sync(virt_addr, length as usize) {
+            Ok(_) => SyscallResult::Success(0),
+            Err(_) => SyscallResult::Error(SyscallError::IoError),
+        }
     }
 
     /// sys_futex - Fast userspace mutex
-    fn sys_futex(&self, _args: &[u64], _process_manager: &ProcessManager, _current_pid: Pid) -> SyscallResult {
-        // TODO: Implement futex() for efficient thread synchronization
-        // Critical for pthread and libc threading support
-        SyscallResult::Error(SyscallError::OperationNotSupported)
+    ///
+    /// Takes the Linux futex(2) arguments: uaddr, op, val, timeout (or the
+    /// requeue limit), uaddr2, val3.
+    fn sys_futex(&self, args: &[u64], _process_manager: &ProcessManager, _current_pid: Pid) -> SyscallResult {
+        use crate::linux_compat::{thread_ops, LinuxError};
+
+        let uaddr = args.get(0).copied().unwrap_or(0);
+        let uaddr2 = args.get(4).copied().unwrap_or(0);
+        for addr in [uaddr, uaddr2] {
+            if addr != 0 && (addr < 0x400000 || addr >= 0xFFFFFFFF00000000) {
+                return SyscallResult::Error(SyscallError::InvalidAddress);
+            }
+        }
+
+        let result = thread_ops::futex(
+            uaddr as *mut i32,
+            args.get(1).copied().unwrap_or(0) as i32,
+            args.get(2).copied().unwrap_or(0) as i32,
+            args.get(3).copied().unwrap_or(0) as *const crate::linux_compat::types::TimeSpec,
+            uaddr2 as *mut i32,
+            args.get(5).copied().unwrap_or(0) as i32,
+        );
+
+        match result {
+            Ok(n) => SyscallResult::Success(n as u64),
+            Err(LinuxError::EAGAIN) => SyscallResult::Error(SyscallError::ResourceBusy),
+            Err(LinuxError::ETIMEDOUT) => SyscallResult::Error(SyscallError::TimedOut),
+            Err(LinuxError::EINTR) => SyscallResult::Error(SyscallError::Interrupted),
+            Err(LinuxError::EFAULT) => SyscallResult::Error(SyscallError::InvalidAddress),
+            Err(LinuxError::ENOSYS) => SyscallResult::Error(SyscallError::OperationNotSupported),
+            Err(_) => SyscallResult::Error(SyscallError::InvalidArgument),
+        }
     }
 
     /// sys_socket - Create socket
@@ -1533,8 +1815,9 @@ impl SyscallDispatcher {
     }
 
     /// Get system call statistics
-    pub fn get_stats(&self) -> (u64, &[u64; 64]) {
-        (self.total_syscalls, &self.syscall_count)
+    pub fn get_stats(&self) -> (u64, [u64; 64]) {
+        let counts = core::array::from_fn(|i| self.syscall_count[i].load(Ordering::Relaxed));
+        (self.total_syscalls.load(Ordering::Relaxed), counts)
     }
 
     // Helper methods for user-space memory operations
@@ -1579,6 +1862,23 @@ fn copy_str_to_buf(dest: &mut [u8], src: &str) {
     dest[copy_len] = 0; // Null terminator
 }
 
+/// Map a VFS error onto the closest syscall error
+fn vfs_error_to_syscall(err: crate::vfs::VfsError) -> SyscallError {
+    use crate::vfs::VfsError;
+
+    match err {
+        VfsError::NotFound => SyscallError::FileNotFound,
+        VfsError::PermissionDenied | VfsError::ReadOnly => SyscallError::PermissionDenied,
+        VfsError::BadFileDescriptor => SyscallError::InvalidFileDescriptor,
+        VfsError::TooManyFiles | VfsError::NoSpace => SyscallError::OutOfMemory,
+        VfsError::WouldBlock => SyscallError::ResourceBusy,
+        VfsError::NotSupported => SyscallError::OperationNotSupported,
+        VfsError::InvalidArgument | VfsError::InvalidSeek => SyscallError::InvalidArgument,
+        VfsError::Interrupted => SyscallError::Interrupted,
+        _ => SyscallError::IoError,
+    }
+}
+
 /// System call handler entry point (called from assembly)
 #[no_mangle]
 pub extern "C" fn syscall_handler(
@@ -8,7 +8,7 @@ use alloc::collections::{BTreeMap, VecDeque};
 use alloc::vec::Vec;
 use alloc::vec;
 use alloc::string::{String, ToString};
-use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use spin::{Mutex, RwLock};
 
 /// Thread ID type
@@ -92,7 +92,7 @@ pub struct ThreadControlBlock {
     pub stack_size: usize,
     /// Thread name
     pub name: [u8; 32],
-    /// CPU time used (in ticks)
+    /// CPU time used (in nanoseconds)
     pub cpu_time: u64,
     /// Time when thread was created
     pub creation_time: u64,
@@ -239,6 +239,8 @@ pub struct ThreadManager {
     sync_objects: Mutex<SyncObjectManager>,
     /// Current running thread ID (per CPU - simplified to single CPU for now)
     current_thread: AtomicU32,
+    /// Monotonic time (ns) at which the current thread was switched in
+    switched_in_at: AtomicU64,
 }
 
 impl ThreadManager {
@@ -253,6 +255,7 @@ impl ThreadManager {
             sleeping_threads: Mutex::new(Vec::new()),
             sync_objects: Mutex::new(SyncObjectManager::new()),
             current_thread: AtomicU32::new(0), // Start with kernel thread (TID 0)
+            switched_in_at: AtomicU64::new(0),
         }
     }
 
@@ -572,12 +575,13 @@ impl ThreadManager {
         ready_queue.pop_front()
     }
 
-    /// Allocate kernel stack
+    /// Allocate kernel stack, with an unmapped guard page below it so an
+    /// overflow faults instead of corrupting the memory beneath
     fn allocate_stack(&self, size: usize) -> Result<u64, &'static str> {
         use crate::memory::{get_memory_manager, MemoryRegionType, MemoryProtection};
 
         let memory_manager = get_memory_manager().ok_or("Memory manager not initialized")?;
-        let region = memory_manager.allocate_region(
+        let region = memory_manager.allocate_region_with_guards(
             size,
             MemoryRegionType::KernelStack,
             MemoryProtection::KERNEL_DATA,
@@ -586,12 +590,13 @@ impl ThreadManager {
         Ok(region.start.as_u64())
     }
 
-    /// Allocate user stack
+    /// Allocate user stack; unlike the main stack it doesn't grow, and a
+    /// guard page stops it
     fn allocate_user_stack(&self, size: usize) -> Result<u64, &'static str> {
         use crate::memory::{get_memory_manager, MemoryRegionType, MemoryProtection};
 
         let memory_manager = get_memory_manager().ok_or("Memory manager not initialized")?;
-        let region = memory_manager.allocate_region(
+        let region = memory_manager.allocate_region_with_guards(
             size,
             MemoryRegionType::UserStack,
             MemoryProtection::USER_DATA,
@@ -619,8 +624,40 @@ impl ThreadManager {
     }
 
     /// Set current running thread ID (called by scheduler during context switch)
+    ///
+    /// The outgoing thread is charged for the CPU time it used.
     pub fn set_current_thread(&self, tid: Tid) {
-        self.current_thread.store(tid as u32, Ordering::SeqCst);
+        let now = crate::time::uptime_ns();
+        let previous = self.current_thread.swap(tid as u32, Ordering::SeqCst) as Tid;
+        if previous == tid {
+            return;
+        }
+
+        let ran_for = now.saturating_sub(self.switched_in_at.swap(now, Ordering::SeqCst));
+        if let Some(tcb) = self.threads.write().get_mut(&previous) {
+            tcb.cpu_time += ran_for;
+        }
+    }
+
+    /// CPU time consumed by a thread in nanoseconds, including its current
+    /// time slice if it is running
+    pub fn cpu_time_ns(&self, tid: Tid) -> Option<u64> {
+        let charged = self.threads.read().get(&tid)?.cpu_time;
+        if tid == self.current_thread() {
+            let running = crate::time::uptime_ns().saturating_sub(self.switched_in_at.load(Ordering::SeqCst));
+            Some(charged + running)
+        } else {
+            Some(charged)
+        }
+    }
+
+    /// Run `f` on the thread whose kernel stack starts at `stack_bottom`
+    ///
+    /// Gives up rather than wait for the thread table, so fault handlers can
+    /// use it.
+    pub fn with_thread_on_stack<R>(&self, stack_bottom: u64, f: impl FnOnce(&ThreadControlBlock) -> R) -> Option<R> {
+        let threads = self.threads.try_read()?;
+        threads.values().find(|tcb| tcb.kernel_stack == stack_bottom).map(f)
     }
 
     /// Get current thread from CPU context (attempts to determine from stack or registers)
@@ -861,16 +898,17 @@ where
 }
 
 /// Sleep current thread
+///
+/// Sleeps go through the process sleep queue, so the scheduler runs
+/// something else meanwhile.
 pub fn sleep_ms(duration_ms: u64) -> Result<(), &'static str> {
-    // Get the current thread ID from the thread manager
-    let current_tid = THREAD_MANAGER.current_thread();
-    THREAD_MANAGER.sleep_thread(current_tid, duration_ms)
+    super::timers::sleep_ns(duration_ms.saturating_mul(1_000_000))
+        .map_err(|_| "Sleep interrupted by a signal")
 }
 
 /// Yield current thread
 pub fn yield_thread() {
-    // In a real implementation, this would trigger a reschedule
-    // by calling the scheduler directly
+    crate::scheduler::yield_cpu();
 }
 
 /// Join on a thread (wait for it to complete)
@@ -0,0 +1,457 @@
+//! Process Sleeps and Interval Timers
+//!
+//! Sleeping processes, POSIX per-process timers and the ITIMER_REAL/alarm
+//! timer share one `hrtimer::DeadlineQueue` on the monotonic clock, as
+//! timerfds do. Expired sleeps wake the sleep queue; expired timers deliver
+//! their signal to the owning process.
+//!
+//! ITIMER_VIRTUAL and ITIMER_PROF count down the CPU time the scheduler
+//! charges a process instead, checked on every tick it runs for. CPU time
+//! isn't split into user and system time, so both see all of it.
+//!
+//! The callback runs from the timer interrupt, so the queue lock is only held
+//! elsewhere with interrupts disabled.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::ipc::{self, Signal, signal_bit};
+use super::wait_queue::{WaitError, WaitQueue};
+use super::Pid;
+use crate::interrupts::without_interrupts;
+use crate::hrtimer::{self, DeadlineKey, DeadlineQueue};
+use crate::time;
+use crate::vfs::timerfd::{TimerClock, TimerSpec};
+
+/// Per-process timer identifier
+pub type TimerId = i32;
+
+/// Slot of the timer shared by alarm() and ITIMER_REAL; never handed out by
+/// `create`
+pub const REAL_TIMER: TimerId = -1;
+
+/// Most POSIX timers a single process may own
+pub const MAX_TIMERS_PER_PROCESS: usize = 64;
+
+/// Errors from timer operations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerError {
+    /// No timer with this id belongs to the process
+    InvalidTimer,
+    /// The process already owns `MAX_TIMERS_PER_PROCESS` timers
+    TooManyTimers,
+}
+
+/// What happens when an armed deadline passes
+#[derive(Debug, Clone, Copy)]
+enum Expiry {
+    Sleep,
+    Timer(Pid, TimerId),
+    Wakeup(Pid),
+}
+
+#[derive(Debug)]
+struct PosixTimer {
+    clock: TimerClock,
+    /// Signal to deliver, or None for SIGEV_NONE
+    signal: Option<Signal>,
+    /// sigev_value passed along with the signal
+    value: u64,
+    interval_ns: u64,
+    armed: Option<DeadlineKey>,
+    /// Expirations missed by the last delivered signal
+    overrun: u32,
+}
+
+struct TimerQueue {
+    armed: DeadlineQueue<Expiry>,
+    timers: BTreeMap<(Pid, TimerId), PosixTimer>,
+}
+
+static QUEUE: Mutex<TimerQueue> = Mutex::new(TimerQueue {
+    armed: DeadlineQueue::new(expire_timers),
+    timers: BTreeMap::new(),
+});
+
+/// Processes blocked in `sleep_until`
+static SLEEPERS: WaitQueue = WaitQueue::new();
+
+/// Interval timers on the CPU time of a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CpuTimer {
+    /// ITIMER_VIRTUAL, delivering SIGVTALRM
+    Virtual,
+    /// ITIMER_PROF, delivering SIGPROF
+    Prof,
+}
+
+impl CpuTimer {
+    fn signal(self) -> Signal {
+        match self {
+            CpuTimer::Virtual => Signal::SIGVTALRM,
+            CpuTimer::Prof => Signal::SIGPROF,
+        }
+    }
+}
+
+/// Armed CPU-time timer: expiry in CPU time of its process, and interval
+#[derive(Debug, Clone, Copy)]
+struct CpuDeadline {
+    expires_ns: u64,
+    interval_ns: u64,
+}
+
+/// Armed CPU-time timers; the tick checks them, so they are only locked
+/// with interrupts disabled
+static CPU_TIMERS: Mutex<BTreeMap<(Pid, CpuTimer), CpuDeadline>> = Mutex::new(BTreeMap::new());
+
+/// Convert an expiration time to a monotonic deadline
+///
+/// With `absolute` set, `value_ns` is a time on `clock`; otherwise a delay.
+pub fn deadline_for(clock: TimerClock, value_ns: u64, absolute: bool) -> u64 {
+    let now = time::uptime_ns();
+    if absolute {
+        now.saturating_add(value_ns.saturating_sub(clock.now_ns()))
+    } else {
+        now.saturating_add(value_ns)
+    }
+}
+
+// ============================================================================
+// Sleeping
+// ============================================================================
+
+/// Block the current process until the monotonic clock reaches `deadline_ns`
+///
+/// Returns `Interrupted` if a signal arrives first.
+pub fn sleep_until(deadline_ns: u64) -> Result<(), WaitError> {
+    if time::uptime_ns() >= deadline_ns {
+        return Ok(());
+    }
+
+    let key = without_interrupts(|| {
+        let armed = &mut QUEUE.lock().armed;
+        let key = armed.insert(deadline_ns, Expiry::Sleep);
+        armed.rearm();
+        key
+    });
+
+    let result = SLEEPERS.wait_until(|| (time::uptime_ns() >= deadline_ns).then_some(()));
+
+    // An interrupted sleep leaves its deadline queued
+    without_interrupts(|| {
+        let armed = &mut QUEUE.lock().armed;
+        if armed.remove(&key).is_some() {
+            armed.rearm();
+        }
+    });
+    result
+}
+
+/// Block the current process for `duration_ns` nanoseconds
+pub fn sleep_ns(duration_ns: u64) -> Result<(), WaitError> {
+    sleep_until(time::uptime_ns().saturating_add(duration_ns))
+}
+
+/// Handle of a wakeup armed with `arm_wakeup`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WakeupHandle(DeadlineKey);
+
+/// Unblock `pid` through the scheduler once the monotonic clock reaches
+/// `deadline_ns`
+///
+/// For timed waits on queues of their own, such as futexes, which recheck
+/// the deadline when they wake.
+pub fn arm_wakeup(pid: Pid, deadline_ns: u64) -> WakeupHandle {
+    without_interrupts(|| {
+        let armed = &mut QUEUE.lock().armed;
+        let key = armed.insert(deadline_ns, Expiry::Wakeup(pid));
+        armed.rearm();
+        WakeupHandle(key)
+    })
+}
+
+/// Disarm a wakeup that has not fired yet
+pub fn cancel_wakeup(handle: WakeupHandle) {
+    without_interrupts(|| {
+        let armed = &mut QUEUE.lock().armed;
+        if armed.remove(&handle.0).is_some() {
+            armed.rearm();
+        }
+    });
+}
+
+// ============================================================================
+// POSIX timers
+// ============================================================================
+
+/// Create a disarmed timer owned by `pid`
+///
+/// `signal` is delivered with `value` on every expiration; None creates a
+/// timer that is only observed through `gettime`. Without a `value` the
+/// timer id is passed instead, as for a NULL sigevent.
+pub fn create(pid: Pid, clock: TimerClock, signal: Option<Signal>, value: Option<u64>) -> Result<TimerId, TimerError> {
+    without_interrupts(|| {
+        let mut queue = QUEUE.lock();
+        let owned: Vec<TimerId> = queue
+            .timers
+            .range((pid, 0)..=(pid, TimerId::MAX))
+            .map(|(&(_, id), _)| id)
+            .collect();
+        if owned.len() >= MAX_TIMERS_PER_PROCESS {
+            return Err(TimerError::TooManyTimers);
+        }
+
+        // Ids are handed out lowest-free first
+        let mut id = 0;
+        for &taken in &owned {
+            if taken != id {
+                break;
+            }
+            id += 1;
+        }
+
+        queue.timers.insert((pid, id), PosixTimer {
+            clock,
+            signal,
+            value: value.unwrap_or(id as u64),
+            interval_ns: 0,
+            armed: None,
+            overrun: 0,
+        });
+        Ok(id)
+    })
+}
+
+/// Arm or disarm a timer, returning its previous setting
+pub fn settime(pid: Pid, id: TimerId, spec: TimerSpec, absolute: bool) -> Result<TimerSpec, TimerError> {
+    without_interrupts(|| {
+        let mut queue = QUEUE.lock();
+        let TimerQueue { armed, timers } = &mut *queue;
+        let timer = timers.get_mut(&(pid, id)).ok_or(TimerError::InvalidTimer)?;
+        let old = current(timer);
+
+        if let Some(key) = timer.armed.take() {
+            armed.remove(&key);
+        }
+        timer.interval_ns = spec.interval_ns;
+        timer.overrun = 0;
+
+        if spec.value_ns != 0 {
+            let deadline = deadline_for(timer.clock, spec.value_ns, absolute);
+            timer.armed = Some(armed.insert(deadline, Expiry::Timer(pid, id)));
+        }
+
+        armed.rearm();
+        Ok(old)
+    })
+}
+
+/// Time until a timer's next expiration and its reload interval
+pub fn gettime(pid: Pid, id: TimerId) -> Result<TimerSpec, TimerError> {
+    without_interrupts(|| {
+        QUEUE.lock().timers.get(&(pid, id)).map(current).ok_or(TimerError::InvalidTimer)
+    })
+}
+
+/// Expirations missed by the most recently delivered signal
+pub fn getoverrun(pid: Pid, id: TimerId) -> Result<u32, TimerError> {
+    without_interrupts(|| {
+        QUEUE.lock().timers.get(&(pid, id)).map(|timer| timer.overrun).ok_or(TimerError::InvalidTimer)
+    })
+}
+
+/// Disarm and delete a timer
+pub fn delete(pid: Pid, id: TimerId) -> Result<(), TimerError> {
+    without_interrupts(|| {
+        let mut queue = QUEUE.lock();
+        let timer = queue.timers.remove(&(pid, id)).ok_or(TimerError::InvalidTimer)?;
+        if let Some(key) = timer.armed {
+            queue.armed.remove(&key);
+            queue.armed.rearm();
+        }
+        Ok(())
+    })
+}
+
+/// Set the ITIMER_REAL/alarm timer of `pid`, returning the previous setting
+pub fn set_real_timer(pid: Pid, spec: TimerSpec) -> TimerSpec {
+    without_interrupts(|| {
+        QUEUE.lock().timers.entry((pid, REAL_TIMER)).or_insert(PosixTimer {
+            clock: TimerClock::Monotonic,
+            signal: Some(Signal::SIGALRM),
+            value: 0,
+            interval_ns: 0,
+            armed: None,
+            overrun: 0,
+        });
+    });
+    settime(pid, REAL_TIMER, spec, false).unwrap_or_default()
+}
+
+/// Current setting of the ITIMER_REAL/alarm timer of `pid`
+pub fn get_real_timer(pid: Pid) -> TimerSpec {
+    gettime(pid, REAL_TIMER).unwrap_or_default()
+}
+
+/// Set a CPU-time timer of `pid`, returning its previous setting
+pub fn set_cpu_timer(pid: Pid, which: CpuTimer, spec: TimerSpec) -> TimerSpec {
+    let now = cpu_time(pid);
+    without_interrupts(|| {
+        let mut timers = CPU_TIMERS.lock();
+        let old = timers.remove(&(pid, which)).map_or(TimerSpec::default(), |deadline| cpu_spec(deadline, now));
+        if spec.value_ns != 0 {
+            timers.insert((pid, which), CpuDeadline {
+                expires_ns: now.saturating_add(spec.value_ns),
+                interval_ns: spec.interval_ns,
+            });
+        }
+        old
+    })
+}
+
+/// Current setting of a CPU-time timer of `pid`
+pub fn get_cpu_timer(pid: Pid, which: CpuTimer) -> TimerSpec {
+    let now = cpu_time(pid);
+    without_interrupts(|| {
+        CPU_TIMERS.lock().get(&(pid, which)).map_or(TimerSpec::default(), |&deadline| cpu_spec(deadline, now))
+    })
+}
+
+/// Tick of `pid` on its CPU: signal it for each of its CPU-time timers that
+/// expired
+///
+/// Called from the timer interrupt, before the tick may switch away.
+pub fn cpu_tick(pid: Pid) {
+    let mut signals = Vec::new();
+    {
+        let mut timers = CPU_TIMERS.lock();
+        if timers.range((pid, CpuTimer::Virtual)..=(pid, CpuTimer::Prof)).next().is_none() {
+            return;
+        }
+        let Some(now) = crate::scheduler::cpu_time_ns(pid) else {
+            return;
+        };
+        for which in [CpuTimer::Virtual, CpuTimer::Prof] {
+            let Some(deadline) = timers.get_mut(&(pid, which)) else { continue };
+            if deadline.expires_ns > now {
+                continue;
+            }
+            if deadline.interval_ns > 0 {
+                deadline.expires_ns = hrtimer::periodic_next(deadline.expires_ns, deadline.interval_ns, now).1;
+            } else {
+                timers.remove(&(pid, which));
+            }
+            signals.push(which.signal());
+        }
+    }
+    for signal in signals {
+        let _ = ipc::get_ipc_manager().send_signal(pid, signal, pid);
+    }
+}
+
+/// CPU time `pid` has used
+fn cpu_time(pid: Pid) -> u64 {
+    crate::scheduler::cpu_time_ns(pid).unwrap_or(0)
+}
+
+fn cpu_spec(deadline: CpuDeadline, now: u64) -> TimerSpec {
+    TimerSpec {
+        interval_ns: deadline.interval_ns,
+        value_ns: deadline.expires_ns.saturating_sub(now).max(1),
+    }
+}
+
+/// Drop every timer owned by an exiting process
+pub fn exit_process(pid: Pid) {
+    without_interrupts(|| {
+        CPU_TIMERS.lock().retain(|&(owner, _), _| owner != pid);
+        let mut queue = QUEUE.lock();
+        let TimerQueue { armed, timers } = &mut *queue;
+        timers.retain(|&(owner, _), timer| {
+            if owner != pid {
+                return true;
+            }
+            if let Some(key) = timer.armed {
+                armed.remove(&key);
+            }
+            false
+        });
+        armed.retain(|_, expiry| !matches!(expiry, Expiry::Wakeup(owner) if *owner == pid));
+        armed.rearm();
+    });
+}
+
+fn current(timer: &PosixTimer) -> TimerSpec {
+    let value_ns = timer
+        .armed
+        .map(|(deadline, _)| deadline.saturating_sub(time::uptime_ns()).max(1))
+        .unwrap_or(0);
+    TimerSpec {
+        interval_ns: timer.interval_ns,
+        value_ns,
+    }
+}
+
+// ============================================================================
+// Expiry
+// ============================================================================
+
+/// Timer callback: wake due sleepers and signal the owners of due timers
+fn expire_timers() {
+    let now = time::uptime_ns();
+    let mut woke_sleepers = false;
+    let mut wakeups = Vec::new();
+    let mut signals = Vec::new();
+
+    {
+        let mut queue = QUEUE.lock();
+        let TimerQueue { armed, timers } = &mut *queue;
+        armed.fired();
+
+        while let Some((key, expiry)) = armed.pop_due(now) {
+            match expiry {
+                Expiry::Sleep => woke_sleepers = true,
+                Expiry::Wakeup(pid) => wakeups.push(pid),
+                Expiry::Timer(pid, id) => {
+                    let Some(timer) = timers.get_mut(&(pid, id)) else { continue };
+                    if timer.armed != Some(key) {
+                        continue;
+                    }
+
+                    let overruns = if timer.interval_ns > 0 {
+                        let (overruns, next) = hrtimer::periodic_next(key.0, timer.interval_ns, now);
+                        timer.armed = Some(armed.insert(next, Expiry::Timer(pid, id)));
+                        overruns
+                    } else {
+                        timer.armed = None;
+                        0
+                    };
+
+                    if let Some(signal) = timer.signal {
+                        // A signal still queued from the previous expiration
+                        // absorbs this one as an overrun
+                        if ipc::get_ipc_manager().has_signals_in(pid, signal_bit(signal)) {
+                            timer.overrun = timer.overrun.saturating_add(overruns.saturating_add(1).min(u32::MAX as u64) as u32);
+                        } else {
+                            timer.overrun = overruns.min(u32::MAX as u64) as u32;
+                            signals.push((pid, signal, timer.value));
+                        }
+                    }
+                }
+            }
+        }
+        armed.rearm();
+    }
+
+    if woke_sleepers {
+        SLEEPERS.wake_all();
+    }
+    for pid in wakeups {
+        let _ = crate::scheduler::unblock_process(pid);
+    }
+    for (pid, signal, value) in signals {
+        let _ = ipc::get_ipc_manager().send_signal_with_data(pid, signal, pid, value);
+    }
+}
@@ -0,0 +1,127 @@
+//! Process Wait Queues
+//!
+//! A wait queue parks processes until some condition becomes true. Objects that
+//! can block their callers (pipes, FIFOs, timers, locks) own a `WaitQueue` and
+//! call `wake_one`/`wake_all` whenever their state changes; the blocked side
+//! re-checks its condition after every wakeup.
+//!
+//! Blocking and waking go through `crate::scheduler`. Wakers may run in the
+//! timer interrupt, so the waiter list is only locked with interrupts off.
+
+use super::{Pid, current_pid};
+use crate::interrupts::without_interrupts;
+use crate::scheduler;
+use alloc::collections::VecDeque;
+use spin::Mutex;
+
+/// Reasons a wait ended without its condition becoming true
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitError {
+    /// A signal arrived for the waiting process
+    Interrupted,
+}
+
+/// Queue of processes waiting on a single event source
+#[derive(Debug)]
+pub struct WaitQueue {
+    waiters: Mutex<VecDeque<Pid>>,
+}
+
+impl WaitQueue {
+    /// Create an empty wait queue
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Block the current process until `condition` yields a value
+    ///
+    /// The process is queued before the condition is evaluated, so a wakeup
+    /// racing with the check marks the wakeup pending rather than being lost,
+    /// and callers may race freely with wakers. Pending signals abort the wait
+    /// with `Interrupted`.
+    pub fn wait_until<T, F>(&self, mut condition: F) -> Result<T, WaitError>
+    where
+        F: FnMut() -> Option<T>,
+    {
+        let pid = current_pid();
+
+        loop {
+            without_interrupts(|| {
+                let mut waiters = self.waiters.lock();
+                if !waiters.contains(&pid) {
+                    waiters.push_back(pid);
+                }
+            });
+
+            if let Some(value) = condition() {
+                self.remove(pid);
+                return Ok(value);
+            }
+
+            if pid != 0 && super::ipc::has_pending_signals(pid) {
+                self.remove(pid);
+                return Err(WaitError::Interrupted);
+            }
+
+            // Kernel context (pid 0) has no schedulable entity; idle until the
+            // next interrupt instead.
+            if pid == 0 || scheduler::block_current().is_err() {
+                idle();
+            }
+        }
+    }
+
+    /// Wake the longest-waiting process
+    pub fn wake_one(&self) -> bool {
+        let pid = without_interrupts(|| self.waiters.lock().pop_front());
+        match pid {
+            Some(pid) => {
+                wake(pid);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wake every waiting process
+    pub fn wake_all(&self) -> usize {
+        let pids: VecDeque<Pid> = without_interrupts(|| core::mem::take(&mut *self.waiters.lock()));
+        let count = pids.len();
+        for pid in pids {
+            wake(pid);
+        }
+        count
+    }
+
+    /// Check whether any process is waiting
+    pub fn has_waiters(&self) -> bool {
+        without_interrupts(|| !self.waiters.lock().is_empty())
+    }
+
+    fn remove(&self, pid: Pid) {
+        without_interrupts(|| self.waiters.lock().retain(|&p| p != pid));
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn wake(pid: Pid) {
+    if pid != 0 {
+        let _ = scheduler::unblock_process(pid);
+    }
+}
+
+/// Wait for the next interrupt, or spin if interrupts are masked
+fn idle() {
+    if x86_64::instructions::interrupts::are_enabled() {
+        x86_64::instructions::hlt();
+    } else {
+        core::hint::spin_loop();
+    }
+}
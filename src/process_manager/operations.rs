@@ -121,7 +121,7 @@ pub fn wait(
         drop(table);
 
         // Yield CPU to allow children to exit
-        crate::process::scheduler::yield_cpu();
+        crate::scheduler::yield_cpu();
 
         // In a real implementation, we would block the process here
         // and wake it up when a child exits via signal
@@ -162,7 +162,7 @@ pub fn waitpid(
         drop(table);
 
         // Yield CPU to allow child to exit
-        crate::process::scheduler::yield_cpu();
+        crate::scheduler::yield_cpu();
 
         // In real implementation, would block here
         break;
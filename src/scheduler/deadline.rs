@@ -0,0 +1,315 @@
+//! Deadline Scheduling Class
+//!
+//! SCHED_DEADLINE processes declare a runtime they need every period, to be
+//! done within a relative deadline of the period's start. They run ahead of
+//! every other class, earliest absolute deadline first, and each is held to
+//! its reservation by a constant bandwidth server: a process that used up
+//! its runtime is throttled until its next period, where the runtime is
+//! replenished and the deadline moves a period on. A process waking too late
+//! to finish its remaining runtime by its current deadline gets a fresh one.
+//!
+//! Admission control keeps the sum of runtime/period over all deadline
+//! processes within `BW_LIMIT_PERCENT` of the online CPUs, so that the
+//! reservations stay feasible and other classes keep some CPU time.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::Pid;
+
+/// Smallest runtime a deadline process may ask for
+pub const MIN_RUNTIME_NS: u64 = 1 << 10;
+
+/// Share of every online CPU deadline processes may reserve together
+pub const BW_LIMIT_PERCENT: u64 = 95;
+
+/// Fixed-point shift of bandwidths, runtime/period
+const BW_SHIFT: u32 = 20;
+
+/// Bandwidth reserved by all admitted deadline processes
+static TOTAL_BW: AtomicU64 = AtomicU64::new(0);
+
+/// Reservation of a deadline process
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeadlineParams {
+    /// Execution time needed every period
+    pub runtime_ns: u64,
+    /// Time from the start of a period by which the runtime must be done
+    pub deadline_ns: u64,
+    /// Length of a period
+    pub period_ns: u64,
+}
+
+impl DeadlineParams {
+    /// Validated parameters; a zero period means one equal to the deadline
+    pub fn new(runtime_ns: u64, deadline_ns: u64, period_ns: u64) -> Result<Self, &'static str> {
+        let period_ns = if period_ns == 0 { deadline_ns } else { period_ns };
+        if runtime_ns < MIN_RUNTIME_NS {
+            return Err("Deadline runtime too small");
+        }
+        if runtime_ns > deadline_ns || deadline_ns > period_ns {
+            return Err("Deadline parameters must satisfy runtime <= deadline <= period");
+        }
+        Ok(Self { runtime_ns, deadline_ns, period_ns })
+    }
+
+    /// Fraction of a CPU reserved, in `BW_SHIFT` fixed point
+    fn bandwidth(&self) -> u64 {
+        if self.period_ns == 0 {
+            return 0;
+        }
+        ((self.runtime_ns as u128) << BW_SHIFT).div_ceil(self.period_ns as u128) as u64
+    }
+}
+
+/// Reserve bandwidth for `new` in place of `old` on `cpus` CPUs
+///
+/// Either may be `None` for a process entering or leaving the class.
+pub fn admit(old: Option<&DeadlineParams>, new: Option<&DeadlineParams>, cpus: u32) -> Result<(), &'static str> {
+    let old_bw = old.map_or(0, |params| params.bandwidth());
+    let new_bw = new.map_or(0, |params| params.bandwidth());
+    let limit = ((1u64 << BW_SHIFT) * BW_LIMIT_PERCENT / 100) * cpus.max(1) as u64;
+
+    TOTAL_BW.fetch_update(Ordering::AcqRel, Ordering::Acquire, |total| {
+        let total = total.saturating_sub(old_bw) + new_bw;
+        (new_bw <= old_bw || total <= limit).then_some(total)
+    }).map(|_| ()).map_err(|_| "Deadline bandwidth exhausted")
+}
+
+/// Fraction of the system reserved by deadline processes, in percent of one
+/// CPU
+pub fn reserved_percent() -> u64 {
+    (TOTAL_BW.load(Ordering::Acquire) * 100) >> BW_SHIFT
+}
+
+/// Deadline-class state of a process
+#[derive(Debug, Clone, Default)]
+pub struct DeadlineEntity {
+    /// Admitted reservation
+    pub params: DeadlineParams,
+    /// Absolute deadline of the current period
+    pub abs_deadline: u64,
+    /// Runtime left in the current period
+    pub remaining: i64,
+    /// Out of runtime until the next period
+    pub throttled: bool,
+    /// When the running time was last charged
+    pub exec_start: u64,
+}
+
+impl DeadlineEntity {
+    /// Create an entity with `params` that has not run yet
+    pub fn new(params: DeadlineParams) -> Self {
+        Self { params, ..Self::default() }
+    }
+
+    /// Start a fresh period at `now`
+    fn renew(&mut self, now: u64) {
+        self.abs_deadline = now + self.params.deadline_ns;
+        self.remaining = self.params.runtime_ns as i64;
+    }
+
+    /// Constant bandwidth server rule for a process becoming runnable
+    ///
+    /// The current deadline is kept only if the remaining runtime still fits
+    /// before it at the reserved bandwidth.
+    pub fn wake(&mut self, now: u64) {
+        if self.throttled {
+            return;
+        }
+        if self.abs_deadline <= now {
+            self.renew(now);
+            return;
+        }
+        let left = (self.abs_deadline - now) as u128;
+        let remaining = self.remaining.max(0) as u128;
+        if remaining * self.params.period_ns as u128 > left * self.params.runtime_ns as u128 {
+            self.renew(now);
+        }
+    }
+
+    /// Charge the time since the entity was last charged
+    ///
+    /// Returns true once the runtime of the period is used up.
+    pub fn update(&mut self, now: u64) -> bool {
+        let delta = now.saturating_sub(self.exec_start);
+        self.exec_start = now;
+        self.remaining -= delta as i64;
+        if self.remaining <= 0 {
+            self.throttled = true;
+        }
+        self.throttled
+    }
+
+    /// Start of the period after the one whose deadline is `abs_deadline`
+    pub fn replenish_at(&self) -> u64 {
+        self.abs_deadline - self.params.deadline_ns + self.params.period_ns
+    }
+
+    /// Hand out the runtime of the next periods until some is left
+    pub fn replenish(&mut self) {
+        while self.remaining <= 0 {
+            self.abs_deadline += self.params.period_ns;
+            self.remaining += self.params.runtime_ns as i64;
+        }
+        self.throttled = false;
+    }
+}
+
+/// Per-CPU run queue of the deadline class
+#[derive(Debug, Default)]
+pub struct DlRunQueue {
+    /// Runnable entities by (absolute deadline, pid)
+    timeline: BTreeSet<(u64, Pid)>,
+    /// Timeline key of every queued entity
+    keys: BTreeMap<Pid, u64>,
+    /// Throttled entities by (replenishment time, pid)
+    throttled: BTreeSet<(u64, Pid)>,
+}
+
+impl DlRunQueue {
+    /// Create an empty run queue
+    pub const fn new() -> Self {
+        Self {
+            timeline: BTreeSet::new(),
+            keys: BTreeMap::new(),
+            throttled: BTreeSet::new(),
+        }
+    }
+
+    /// Number of runnable entities
+    pub fn len(&self) -> usize {
+        self.timeline.len()
+    }
+
+    /// Whether no entity is runnable
+    pub fn is_empty(&self) -> bool {
+        self.timeline.is_empty()
+    }
+
+    /// Whether `pid` is queued or throttled here
+    pub fn contains(&self, pid: Pid) -> bool {
+        self.keys.contains_key(&pid) || self.throttled.iter().any(|&(_, p)| p == pid)
+    }
+
+    /// Whether an entity waits here for its replenishment
+    pub fn has_throttled(&self) -> bool {
+        !self.throttled.is_empty()
+    }
+
+    /// Earliest absolute deadline among the runnable entities
+    pub fn earliest_deadline(&self) -> Option<u64> {
+        self.timeline.first().map(|&(deadline, _)| deadline)
+    }
+
+    /// Queue a runnable entity, or park a throttled one until it is
+    /// replenished
+    pub fn enqueue(&mut self, pid: Pid, entity: &DeadlineEntity) {
+        self.remove(pid);
+        if entity.throttled {
+            self.throttled.insert((entity.replenish_at(), pid));
+        } else {
+            self.timeline.insert((entity.abs_deadline, pid));
+            self.keys.insert(pid, entity.abs_deadline);
+        }
+    }
+
+    /// Take `pid` off the queue; returns whether it was there
+    pub fn remove(&mut self, pid: Pid) -> bool {
+        if let Some(deadline) = self.keys.remove(&pid) {
+            self.timeline.remove(&(deadline, pid));
+            return true;
+        }
+        let parked = self.throttled.iter().find(|&&(_, p)| p == pid).copied();
+        parked.map_or(false, |entry| self.throttled.remove(&entry))
+    }
+
+    /// Take the usable entity with the earliest deadline off the queue
+    ///
+    /// `usable` works as for `FairRunQueue::pick`.
+    pub fn pick<F>(&mut self, mut usable: F) -> Option<Pid>
+    where
+        F: FnMut(Pid) -> Option<bool>,
+    {
+        let mut stale = Vec::new();
+        let mut picked = None;
+        for &(_, pid) in &self.timeline {
+            match usable(pid) {
+                None => stale.push(pid),
+                Some(false) => {}
+                Some(true) => {
+                    picked = Some(pid);
+                    break;
+                }
+            }
+        }
+        for pid in stale {
+            self.remove(pid);
+        }
+        let pid = picked?;
+        self.remove(pid);
+        Some(pid)
+    }
+
+    /// Throttled entities whose next period has begun by `now`
+    pub fn take_due(&mut self, now: u64) -> Vec<Pid> {
+        let mut due = Vec::new();
+        while let Some(&(at, pid)) = self.throttled.first() {
+            if at > now {
+                break;
+            }
+            self.throttled.remove(&(at, pid));
+            due.push(pid);
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    #[test]
+    fn test_params_validation() {
+        let params = DeadlineParams::new(MS, 4 * MS, 0).unwrap();
+        assert_eq!(params.period_ns, 4 * MS);
+        assert!(DeadlineParams::new(MIN_RUNTIME_NS, MIN_RUNTIME_NS, MIN_RUNTIME_NS).is_ok());
+
+        assert!(DeadlineParams::new(MIN_RUNTIME_NS - 1, MS, MS).is_err());
+        assert!(DeadlineParams::new(2 * MS, MS, 4 * MS).is_err());
+        assert!(DeadlineParams::new(MS, 4 * MS, 2 * MS).is_err());
+    }
+
+    #[test]
+    fn test_bandwidth_rounds_up() {
+        let half = DeadlineParams::new(5 * MS, 10 * MS, 10 * MS).unwrap();
+        assert_eq!(half.bandwidth(), 1 << (BW_SHIFT - 1));
+        let third = DeadlineParams::new(MS, 3 * MS, 3 * MS).unwrap();
+        assert_eq!(third.bandwidth(), (1 << BW_SHIFT) / 3 + 1);
+        assert_eq!(DeadlineParams::default().bandwidth(), 0);
+    }
+
+    #[test]
+    fn test_admission_control() {
+        let half = DeadlineParams::new(5 * MS, 10 * MS, 10 * MS).unwrap();
+        let quarter = DeadlineParams::new(MS, 4 * MS, 4 * MS).unwrap();
+
+        admit(None, Some(&half), 1).unwrap();
+        assert_eq!(reserved_percent(), 50);
+        // A second half would take the CPU past BW_LIMIT_PERCENT
+        assert!(admit(None, Some(&half), 1).is_err());
+        assert_eq!(reserved_percent(), 50);
+        // but fits on two
+        admit(None, Some(&half), 2).unwrap();
+        admit(Some(&half), None, 2).unwrap();
+
+        // Shrinking a reservation always succeeds
+        admit(Some(&half), Some(&quarter), 1).unwrap();
+        assert_eq!(reserved_percent(), 25);
+        admit(Some(&quarter), None, 1).unwrap();
+        assert_eq!(reserved_percent(), 0);
+    }
+}
@@ -0,0 +1,750 @@
+//! Fair Scheduling Class
+//!
+//! Processes that are not real-time share the CPU in proportion to their
+//! weight, which follows from their nice value through the table Linux uses:
+//! one nice step is worth about 10% of CPU time against a competitor.
+//!
+//! Selection is EEVDF-style. An entity accrues virtual runtime, its real
+//! runtime scaled by `NICE_0_LOAD / weight`, and asks for each slice by
+//! setting a virtual deadline one weighted slice past its vruntime. Among the
+//! entities that are owed service, those at or below the queue's weighted
+//! average vruntime, the one with the earliest deadline runs.
+//!
+//! Sleeper fairness comes from lag: an entity that blocks keeps how far it
+//! was behind or ahead of the average, bounded to a couple of slices, and is
+//! placed by it when it wakes. An interactive process that gave up the CPU
+//! early therefore runs ahead of batch work, but cannot bank credit by
+//! sleeping long. Lag also carries an entity across CPUs when it migrates.
+//!
+//! Group scheduling hooks: every entity belongs to a group, `ROOT_GROUP`
+//! unless moved. A group's shares are split between its members runnable on
+//! the same CPU, so the group as a whole competes with the weight of its
+//! shares however many processes it has.
+//!
+//! A group may also be held to a bandwidth: a quota of runtime per period,
+//! counted across all CPUs together with its descendant groups. Once the
+//! quota is used up its members are not picked until the period ends.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::{Mutex, RwLock};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use super::Pid;
+
+/// Weight of a nice 0 entity
+pub const NICE_0_LOAD: u64 = 1024;
+
+/// Highest priority nice value
+pub const MIN_NICE: i8 = -20;
+
+/// Lowest priority nice value
+pub const MAX_NICE: i8 = 19;
+
+/// Real runtime an entity asks for per deadline, in nanoseconds
+pub const BASE_SLICE_NS: u64 = 3_000_000;
+
+/// Group identifier; groups other than the root are created by their users
+pub type GroupId = u32;
+
+/// Group every entity starts in; it has no shares of its own
+pub const ROOT_GROUP: GroupId = 0;
+
+/// Weight for each nice value from -20 to 19
+const SCHED_PRIO_TO_WEIGHT: [u64; 40] = [
+    /* -20 */ 88761, 71755, 56483, 46273, 36291,
+    /* -15 */ 29154, 23254, 18705, 14949, 11916,
+    /* -10 */ 9548, 7620, 6100, 4904, 3906,
+    /*  -5 */ 3121, 2501, 1991, 1586, 1277,
+    /*   0 */ 1024, 820, 655, 526, 423,
+    /*   5 */ 335, 272, 215, 172, 137,
+    /*  10 */ 110, 87, 70, 56, 45,
+    /*  15 */ 36, 29, 23, 18, 15,
+];
+
+/// Smallest weight a group member is scaled down to
+const MIN_WEIGHT: u64 = 2;
+
+/// Weight of a SCHED_IDLE entity, below that of nice 19
+pub const WEIGHT_IDLEPRIO: u64 = 3;
+
+/// Weight of an entity with `nice`, which is clamped to the valid range
+pub fn nice_to_weight(nice: i8) -> u64 {
+    SCHED_PRIO_TO_WEIGHT[(nice.clamp(MIN_NICE, MAX_NICE) - MIN_NICE) as usize]
+}
+
+/// Convert real time to virtual time for an entity of `weight`
+fn to_virtual(delta_ns: u64, weight: u64) -> u64 {
+    ((delta_ns as u128 * NICE_0_LOAD as u128) / weight.max(1) as u128) as u64
+}
+
+/// Fair-class state of a process
+#[derive(Debug, Clone)]
+pub struct FairEntity {
+    /// Nice value, -20 to 19
+    pub nice: i8,
+    /// Weight derived from `nice`
+    pub weight: u64,
+    /// Group the entity is scheduled in
+    pub group: GroupId,
+    /// Virtual runtime in nanoseconds
+    pub vruntime: u64,
+    /// Virtual time by which the current slice is due
+    pub deadline: u64,
+    /// Average vruntime minus `vruntime` when the entity last left a queue
+    pub vlag: i64,
+    /// When the entity's running time was last charged
+    pub exec_start: u64,
+    /// SCHED_BATCH: never preempts on wakeup
+    pub batch: bool,
+    /// SCHED_IDLE: runs at `WEIGHT_IDLEPRIO` whatever its nice value
+    pub idle: bool,
+}
+
+impl FairEntity {
+    /// Create an entity with `nice` in `group`
+    pub fn new(nice: i8, group: GroupId) -> Self {
+        let nice = nice.clamp(MIN_NICE, MAX_NICE);
+        Self {
+            nice,
+            weight: nice_to_weight(nice),
+            group,
+            vruntime: 0,
+            deadline: 0,
+            vlag: 0,
+            exec_start: 0,
+            batch: false,
+            idle: false,
+        }
+    }
+
+    /// Change the nice value and with it the weight
+    pub fn set_nice(&mut self, nice: i8) {
+        self.nice = nice.clamp(MIN_NICE, MAX_NICE);
+        self.update_weight();
+    }
+
+    /// Switch between SCHED_NORMAL, SCHED_BATCH and SCHED_IDLE behaviour
+    pub fn set_policy(&mut self, batch: bool, idle: bool) {
+        self.batch = batch;
+        self.idle = idle;
+        self.update_weight();
+    }
+
+    fn update_weight(&mut self) {
+        self.weight = if self.idle { WEIGHT_IDLEPRIO } else { nice_to_weight(self.nice) };
+    }
+
+    /// Virtual length of one slice for this entity
+    fn vslice(&self) -> u64 {
+        to_virtual(BASE_SLICE_NS, self.weight)
+    }
+
+    /// Whether the entity used up the slice its deadline was set for
+    pub fn slice_expired(&self) -> bool {
+        self.vruntime >= self.deadline
+    }
+
+    /// Ask for the next slice
+    pub fn renew_deadline(&mut self) {
+        self.deadline = self.vruntime + self.vslice();
+    }
+}
+
+/// Bookkeeping for an entity in a run queue
+#[derive(Debug, Clone, Copy)]
+struct Queued {
+    vruntime: u64,
+    deadline: u64,
+    /// Weight the entity was accounted with, after group scaling
+    weight: u64,
+    /// Weight from its nice value alone
+    base_weight: u64,
+    group: GroupId,
+}
+
+/// Per-CPU run queue of the fair class
+///
+/// Holds the runnable entities ordered by virtual deadline and the entity
+/// running on the CPU, which counts towards the average but sits outside
+/// the timeline.
+#[derive(Debug)]
+pub struct FairRunQueue {
+    /// Runnable entities by (virtual deadline, pid)
+    timeline: BTreeMap<(u64, Pid), Queued>,
+    /// Timeline key of every queued entity
+    keys: BTreeMap<Pid, (u64, Pid)>,
+    /// The running entity
+    curr: Option<(Pid, Queued)>,
+    /// Sum of the weights in the timeline
+    load: u64,
+    /// Sum of (vruntime - base) * weight over the timeline
+    weighted_vruntime: i128,
+    /// Reference point for `weighted_vruntime`, moved along with the average
+    /// to keep it small
+    base: u64,
+    /// Nice weight of the members of each group on this queue, running or not
+    group_load: BTreeMap<GroupId, u64>,
+}
+
+impl FairRunQueue {
+    /// Create an empty run queue
+    pub const fn new() -> Self {
+        Self {
+            timeline: BTreeMap::new(),
+            keys: BTreeMap::new(),
+            curr: None,
+            load: 0,
+            weighted_vruntime: 0,
+            base: 0,
+            group_load: BTreeMap::new(),
+        }
+    }
+
+    /// Number of queued entities, not counting the running one
+    pub fn len(&self) -> usize {
+        self.timeline.len()
+    }
+
+    /// Whether no entity is queued
+    pub fn is_empty(&self) -> bool {
+        self.timeline.is_empty()
+    }
+
+    /// Whether `pid` is queued here
+    pub fn contains(&self, pid: Pid) -> bool {
+        self.keys.contains_key(&pid)
+    }
+
+    /// Total weight of the queued and running entities
+    pub fn load(&self) -> u64 {
+        self.load + self.curr.map_or(0, |(_, curr)| curr.weight)
+    }
+
+    /// Weighted average vruntime of the queued and running entities
+    pub fn avg_vruntime(&self) -> u64 {
+        let (sum, load) = self.weighted_sum();
+        if load == 0 {
+            return self.curr.map_or(self.base, |(_, curr)| curr.vruntime);
+        }
+        (self.base as i128 + sum.div_euclid(load as i128)).max(0) as u64
+    }
+
+    fn weighted_sum(&self) -> (i128, u64) {
+        let mut sum = self.weighted_vruntime;
+        let mut load = self.load;
+        if let Some((_, curr)) = self.curr {
+            sum += (curr.vruntime as i128 - self.base as i128) * curr.weight as i128;
+            load += curr.weight;
+        }
+        (sum, load)
+    }
+
+    /// Whether an entity at `vruntime` is owed service
+    pub fn is_eligible(&self, vruntime: u64) -> bool {
+        let (sum, load) = self.weighted_sum();
+        (vruntime as i128 - self.base as i128) * load as i128 <= sum
+    }
+
+    /// Weight `entity` competes with here once group shares are applied
+    fn effective_weight(&self, entity: &FairEntity) -> u64 {
+        if entity.group == ROOT_GROUP {
+            return entity.weight;
+        }
+        let group_load = self.group_load.get(&entity.group).copied().unwrap_or(0).max(entity.weight);
+        ((entity.weight as u128 * group_shares(entity.group) as u128 / group_load as u128) as u64)
+            .max(MIN_WEIGHT)
+    }
+
+    fn add_group_load(&mut self, group: GroupId, weight: u64) {
+        if group != ROOT_GROUP {
+            *self.group_load.entry(group).or_insert(0) += weight;
+        }
+    }
+
+    fn sub_group_load(&mut self, group: GroupId, weight: u64) {
+        if let Some(load) = self.group_load.get_mut(&group) {
+            *load = load.saturating_sub(weight);
+            if *load == 0 {
+                self.group_load.remove(&group);
+            }
+        }
+    }
+
+    /// Queue a runnable entity, replacing any entry it already has
+    pub fn enqueue(&mut self, pid: Pid, entity: &FairEntity) {
+        self.remove(pid);
+        self.add_group_load(entity.group, entity.weight);
+
+        let queued = Queued {
+            vruntime: entity.vruntime,
+            deadline: entity.deadline,
+            weight: self.effective_weight(entity),
+            base_weight: entity.weight,
+            group: entity.group,
+        };
+        let key = (entity.deadline, pid);
+        self.load += queued.weight;
+        self.weighted_vruntime += (queued.vruntime as i128 - self.base as i128) * queued.weight as i128;
+        self.timeline.insert(key, queued);
+        self.keys.insert(pid, key);
+    }
+
+    /// Take `pid` off the timeline; returns whether it was queued
+    pub fn remove(&mut self, pid: Pid) -> bool {
+        let Some(key) = self.keys.remove(&pid) else {
+            return false;
+        };
+        if let Some(queued) = self.timeline.remove(&key) {
+            self.load -= queued.weight;
+            self.weighted_vruntime -= (queued.vruntime as i128 - self.base as i128) * queued.weight as i128;
+            self.sub_group_load(queued.group, queued.base_weight);
+        }
+        true
+    }
+
+    /// Move `base` to the current average so the weighted sum stays small
+    fn rebase(&mut self) {
+        let avg = self.avg_vruntime();
+        let delta = avg as i128 - self.base as i128;
+        self.weighted_vruntime -= delta * self.load as i128;
+        self.base = avg;
+    }
+
+    /// Take the next entity to run off the timeline
+    ///
+    /// `usable` is asked about each candidate in deadline order: `None` drops
+    /// a stale entry, `Some(false)` skips an entry that cannot run yet. The
+    /// earliest deadline among eligible entries wins; if none is eligible,
+    /// the earliest deadline overall.
+    pub fn pick<F>(&mut self, mut usable: F) -> Option<Pid>
+    where
+        F: FnMut(Pid) -> Option<bool>,
+    {
+        let mut stale = Vec::new();
+        let mut chosen = None;
+        let mut fallback = None;
+
+        for (&(_, pid), queued) in &self.timeline {
+            match usable(pid) {
+                None => stale.push(pid),
+                Some(false) => {}
+                Some(true) if chosen.is_none() => {
+                    if self.is_eligible(queued.vruntime) {
+                        chosen = Some(pid);
+                    } else {
+                        fallback.get_or_insert(pid);
+                    }
+                }
+                Some(true) => {}
+            }
+        }
+
+        for pid in stale {
+            self.remove(pid);
+        }
+        let pid = chosen.or(fallback)?;
+        self.remove(pid);
+        self.rebase();
+        Some(pid)
+    }
+
+    /// Take an entity off the far end of the timeline for another CPU
+    pub fn steal<F>(&mut self, mut movable: F) -> Option<Pid>
+    where
+        F: FnMut(Pid) -> bool,
+    {
+        let pid = self.timeline.keys().rev().map(|&(_, pid)| pid).find(|&pid| movable(pid))?;
+        self.remove(pid);
+        Some(pid)
+    }
+
+    /// Make `entity`, just picked, the running one
+    pub fn set_curr(&mut self, pid: Pid, entity: &mut FairEntity, now: u64) {
+        self.put_curr();
+        entity.exec_start = now;
+        self.add_group_load(entity.group, entity.weight);
+        let curr = Queued {
+            vruntime: entity.vruntime,
+            deadline: entity.deadline,
+            weight: self.effective_weight(entity),
+            base_weight: entity.weight,
+            group: entity.group,
+        };
+        self.curr = Some((pid, curr));
+    }
+
+    /// The running entity leaves the CPU
+    pub fn put_curr(&mut self) {
+        if let Some((_, curr)) = self.curr.take() {
+            self.sub_group_load(curr.group, curr.base_weight);
+        }
+    }
+
+    /// The running entity, if any
+    pub fn curr(&self) -> Option<Pid> {
+        self.curr.map(|(pid, _)| pid)
+    }
+
+    /// Charge the running entity for the time since it was last charged
+    pub fn update_curr(&mut self, entity: &mut FairEntity, now: u64) {
+        let delta = now.saturating_sub(entity.exec_start);
+        entity.exec_start = now;
+
+        let weight = self.effective_weight(entity);
+        entity.vruntime += to_virtual(delta, weight);
+        if let Some((_, curr)) = self.curr.as_mut() {
+            curr.vruntime = entity.vruntime;
+            curr.deadline = entity.deadline;
+            curr.weight = weight;
+            curr.base_weight = entity.weight;
+        }
+    }
+
+    /// Position an entity joining this queue by the lag it left with
+    ///
+    /// A new entity starts at the average with half a slice, so that it runs
+    /// soon without jumping the queue.
+    pub fn place(&self, entity: &mut FairEntity, initial: bool) {
+        let avg = self.avg_vruntime();
+        entity.vruntime = (avg as i128 - entity.vlag as i128).max(0) as u64;
+        entity.vlag = 0;
+        let vslice = entity.vslice();
+        entity.deadline = entity.vruntime + if initial { vslice / 2 } else { vslice };
+    }
+
+    /// Record how far an entity leaving this queue is from its average
+    pub fn save_lag(&self, entity: &mut FairEntity) {
+        let limit = to_virtual(2 * BASE_SLICE_NS, entity.weight) as i128;
+        let lag = self.avg_vruntime() as i128 - entity.vruntime as i128;
+        entity.vlag = lag.clamp(-limit, limit) as i64;
+    }
+
+    /// Whether a newly queued `entity` should preempt the running one
+    pub fn should_preempt(&self, entity: &FairEntity) -> bool {
+        if entity.batch || entity.idle {
+            return false;
+        }
+        match self.curr {
+            None => true,
+            Some((_, curr)) => self.is_eligible(entity.vruntime) && entity.deadline < curr.deadline,
+        }
+    }
+}
+
+impl Default for FairRunQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Shares of each group other than the root; unset groups have
+/// `NICE_0_LOAD`
+///
+/// Read from the timer interrupt, so only written with interrupts off.
+static GROUP_SHARES: RwLock<BTreeMap<GroupId, u64>> = RwLock::new(BTreeMap::new());
+
+/// Set the weight a group competes with as a whole
+pub fn set_group_shares(group: GroupId, shares: u64) -> Result<(), &'static str> {
+    if group == ROOT_GROUP {
+        return Err("The root group has no shares");
+    }
+    if shares < MIN_WEIGHT {
+        return Err("Group shares too small");
+    }
+    without_interrupts(|| {
+        GROUP_SHARES.write().insert(group, shares);
+    });
+    Ok(())
+}
+
+/// Weight a group competes with as a whole
+pub fn group_shares(group: GroupId) -> u64 {
+    GROUP_SHARES.read().get(&group).copied().unwrap_or(NICE_0_LOAD)
+}
+
+/// Forget the shares and bandwidth of a group that no longer exists
+pub fn remove_group(group: GroupId) {
+    without_interrupts(|| {
+        GROUP_SHARES.write().remove(&group);
+        GROUP_BANDWIDTH.lock().remove(&group);
+    });
+}
+
+/// Period of a group bandwidth unless set otherwise
+pub const DEFAULT_BANDWIDTH_PERIOD_NS: u64 = 100_000_000;
+
+/// Shortest bandwidth period and quota
+pub const MIN_BANDWIDTH_NS: u64 = 1_000_000;
+
+/// CPU time accounting of a group
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupCpuStats {
+    /// Runtime of the group and its descendants
+    pub usage_ns: u64,
+    /// Bandwidth periods that have passed with a quota set
+    pub nr_periods: u64,
+    /// Periods in which the quota ran out
+    pub nr_throttled: u64,
+    /// Time spent throttled
+    pub throttled_ns: u64,
+}
+
+/// Bandwidth state of a group other than the root
+#[derive(Debug, Clone)]
+struct GroupBandwidth {
+    /// Group whose bandwidth this one's runtime also counts against
+    parent: GroupId,
+    /// Runtime allowed per period, `None` for no limit
+    quota_ns: Option<u64>,
+    period_ns: u64,
+    period_start: u64,
+    /// Runtime used in the current period
+    runtime_ns: u64,
+    /// When the quota ran out, while it stays out
+    throttled_since: Option<u64>,
+    stats: GroupCpuStats,
+}
+
+impl GroupBandwidth {
+    /// Start a new period if the current one is over
+    fn refresh(&mut self, now: u64) {
+        let elapsed = now.saturating_sub(self.period_start);
+        if elapsed < self.period_ns {
+            return;
+        }
+        let periods = elapsed / self.period_ns;
+        self.period_start += periods * self.period_ns;
+        self.runtime_ns = 0;
+        if self.quota_ns.is_some() {
+            self.stats.nr_periods += periods;
+        }
+        self.unthrottle(now);
+    }
+
+    fn unthrottle(&mut self, now: u64) {
+        if let Some(since) = self.throttled_since.take() {
+            self.stats.throttled_ns += now.saturating_sub(since);
+        }
+    }
+}
+
+/// Bandwidth of the groups registered with `add_group`
+///
+/// Charged from the timer interrupt, so only locked with interrupts off.
+static GROUP_BANDWIDTH: Mutex<BTreeMap<GroupId, GroupBandwidth>> = Mutex::new(BTreeMap::new());
+
+/// Register a new group below `parent`, with no bandwidth limit
+pub fn add_group(group: GroupId, parent: GroupId) {
+    if group == ROOT_GROUP {
+        return;
+    }
+    let now = crate::time::uptime_ns();
+    without_interrupts(|| {
+        GROUP_BANDWIDTH.lock().insert(group, GroupBandwidth {
+            parent,
+            quota_ns: None,
+            period_ns: DEFAULT_BANDWIDTH_PERIOD_NS,
+            period_start: now,
+            runtime_ns: 0,
+            throttled_since: None,
+            stats: GroupCpuStats::default(),
+        });
+    });
+}
+
+/// Let a group run for `quota_ns` every `period_ns`, or without limit for
+/// a `None` quota
+pub fn set_group_bandwidth(group: GroupId, quota_ns: Option<u64>, period_ns: u64) -> Result<(), &'static str> {
+    if group == ROOT_GROUP {
+        return Err("The root group has no bandwidth limit");
+    }
+    if period_ns < MIN_BANDWIDTH_NS || quota_ns.is_some_and(|quota| quota < MIN_BANDWIDTH_NS) {
+        return Err("Bandwidth period or quota too small");
+    }
+    let now = crate::time::uptime_ns();
+    without_interrupts(|| {
+        let mut bandwidth = GROUP_BANDWIDTH.lock();
+        let entry = bandwidth.get_mut(&group).ok_or("No such group")?;
+        entry.refresh(now);
+        entry.quota_ns = quota_ns;
+        entry.period_ns = period_ns;
+        // A raised or lifted quota lets the group back on straight away
+        if quota_ns.map_or(true, |quota| entry.runtime_ns < quota) {
+            entry.unthrottle(now);
+        }
+        Ok(())
+    })
+}
+
+/// Quota and period of a group; the quota is `None` without a limit
+pub fn group_bandwidth(group: GroupId) -> (Option<u64>, u64) {
+    without_interrupts(|| {
+        GROUP_BANDWIDTH.lock()
+            .get(&group)
+            .map_or((None, DEFAULT_BANDWIDTH_PERIOD_NS), |entry| (entry.quota_ns, entry.period_ns))
+    })
+}
+
+/// CPU time accounting of a group
+pub fn group_cpu_stats(group: GroupId) -> GroupCpuStats {
+    let now = crate::time::uptime_ns();
+    without_interrupts(|| {
+        GROUP_BANDWIDTH.lock().get_mut(&group).map_or(GroupCpuStats::default(), |entry| {
+            entry.refresh(now);
+            let mut stats = entry.stats;
+            if let Some(since) = entry.throttled_since {
+                stats.throttled_ns += now.saturating_sub(since);
+            }
+            stats
+        })
+    })
+}
+
+/// Charge `delta_ns` of execution by a member of `group` to it and its
+/// ancestors
+///
+/// Returns true if one of them is out of quota for the current period.
+/// Interrupts must be disabled.
+pub fn charge_group(group: GroupId, delta_ns: u64, now: u64) -> bool {
+    let mut bandwidth = GROUP_BANDWIDTH.lock();
+    let mut throttled = false;
+    let mut group = group;
+    while let Some(entry) = bandwidth.get_mut(&group) {
+        entry.refresh(now);
+        entry.stats.usage_ns += delta_ns;
+        if let Some(quota) = entry.quota_ns {
+            entry.runtime_ns += delta_ns;
+            if entry.runtime_ns >= quota && entry.throttled_since.is_none() {
+                entry.throttled_since = Some(now);
+                entry.stats.nr_throttled += 1;
+            }
+        }
+        throttled |= entry.throttled_since.is_some();
+        group = entry.parent;
+    }
+    throttled
+}
+
+/// Whether `group` or one of its ancestors is out of quota at `now`
+///
+/// Interrupts must be disabled.
+pub fn group_throttled(group: GroupId, now: u64) -> bool {
+    let mut bandwidth = GROUP_BANDWIDTH.lock();
+    let mut group = group;
+    while let Some(entry) = bandwidth.get_mut(&group) {
+        entry.refresh(now);
+        if entry.throttled_since.is_some() {
+            return true;
+        }
+        group = entry.parent;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(vruntime: u64, deadline: u64, weight: u64) -> FairEntity {
+        let mut entity = FairEntity::new(0, ROOT_GROUP);
+        entity.vruntime = vruntime;
+        entity.deadline = deadline;
+        entity.weight = weight;
+        entity
+    }
+
+    #[test]
+    fn test_weights_and_virtual_time() {
+        assert_eq!(nice_to_weight(0), NICE_0_LOAD);
+        assert_eq!(nice_to_weight(-20), 88761);
+        assert_eq!(nice_to_weight(100), 15);
+        // Twice the weight accrues half the virtual time
+        assert_eq!(to_virtual(1000, 2 * NICE_0_LOAD), 500);
+        assert_eq!(to_virtual(1000, NICE_0_LOAD / 2), 2000);
+    }
+
+    #[test]
+    fn test_weighted_average_and_eligibility() {
+        let mut rq = FairRunQueue::new();
+        rq.enqueue(1, &entity(0, 10, 1024));
+        rq.enqueue(2, &entity(400, 20, 3072));
+
+        assert_eq!(rq.load(), 4096);
+        assert_eq!(rq.avg_vruntime(), 300);
+        assert!(rq.is_eligible(300));
+        assert!(!rq.is_eligible(301));
+
+        assert!(rq.remove(2));
+        assert_eq!(rq.avg_vruntime(), 0);
+        assert!(!rq.remove(2));
+    }
+
+    #[test]
+    fn test_pick_prefers_eligible_deadline() {
+        let mut rq = FairRunQueue::new();
+        rq.enqueue(1, &entity(0, 100, 1024));
+        // Earlier deadline, but ahead of the average
+        rq.enqueue(2, &entity(1000, 50, 1024));
+        rq.enqueue(3, &entity(0, 10, 1024));
+
+        // 3 is gone, so it is dropped rather than picked
+        assert_eq!(rq.pick(|pid| (pid != 3).then_some(true)), Some(1));
+        assert!(!rq.contains(3));
+        assert_eq!(rq.pick(|_| Some(true)), Some(2));
+        assert_eq!(rq.pick(|_| Some(true)), None);
+    }
+
+    #[test]
+    fn test_lag_is_kept_and_bounded() {
+        let mut rq = FairRunQueue::new();
+        rq.enqueue(1, &entity(1000, 0, 1024));
+
+        let mut sleeper = entity(400, 0, 1024);
+        rq.save_lag(&mut sleeper);
+        assert_eq!(sleeper.vlag, 600);
+        rq.place(&mut sleeper, false);
+        assert_eq!(sleeper.vruntime, 400);
+        assert_eq!(sleeper.deadline, 400 + to_virtual(BASE_SLICE_NS, 1024));
+        assert_eq!(sleeper.vlag, 0);
+
+        // No more than two slices of credit
+        let mut long_sleeper = entity(0, 0, 1024);
+        let mut far = FairRunQueue::new();
+        far.enqueue(1, &entity(1_000_000_000, 0, 1024));
+        far.save_lag(&mut long_sleeper);
+        assert_eq!(long_sleeper.vlag, to_virtual(2 * BASE_SLICE_NS, 1024) as i64);
+
+        // A new entity starts at the average with half a slice
+        let mut new = entity(0, 0, 1024);
+        rq.place(&mut new, true);
+        assert_eq!(new.vruntime, 1000);
+        assert_eq!(new.deadline, 1000 + to_virtual(BASE_SLICE_NS, 1024) / 2);
+    }
+
+    #[test]
+    fn test_update_curr_charges_virtual_time() {
+        let mut rq = FairRunQueue::new();
+        let mut curr = entity(0, 0, 2048);
+        rq.set_curr(1, &mut curr, 5000);
+        rq.update_curr(&mut curr, 6000);
+        assert_eq!(curr.vruntime, 500);
+        assert_eq!(rq.curr(), Some(1));
+        assert_eq!(rq.avg_vruntime(), 500);
+        rq.put_curr();
+        assert_eq!(rq.curr(), None);
+    }
+
+    #[test]
+    fn test_group_members_split_shares() {
+        let mut rq = FairRunQueue::new();
+        let group = 0xFFFF_0007;
+        let a = FairEntity::new(0, group);
+        let b = FairEntity::new(0, group);
+        rq.enqueue(1, &a);
+        rq.enqueue(2, &b);
+        // Requeued once both count, the group weighs its shares in total
+        rq.enqueue(1, &a);
+        assert_eq!(rq.load(), group_shares(group));
+    }
+}
@@ -1,46 +1,50 @@
 //! Preemptive Scheduler for RustOS
 //!
 //! This module implements a sophisticated preemptive scheduler with:
-//! - Priority-based scheduling with multiple priority levels
-//! - Time slicing for fair CPU allocation
+//! - Earliest-deadline-first reservations for SCHED_DEADLINE (see `deadline`)
+//! - Strict-priority SCHED_FIFO and SCHED_RR with RT throttling (see `rt`)
+//! - Weighted fair sharing by nice value for everything else (see `fair`)
 //! - SMP support for multi-core systems
-//! - Real-time task support
 //! - Load balancing across CPU cores
+//!
+//! It is the only scheduler in the kernel and owns the run state of every
+//! process. `crate::process` creates and reaps processes; everything that
+//! waits (futexes, sleeps, wait queues for I/O) blocks and wakes them here.
+//! Switches go through `crate::process::context::switch_context`, and the
+//! timer interrupt preempts processes it catches in user mode.
+//!
+//! Lock order: a CPU's run queue may be locked before the process table,
+//! never the other way round. Both are only taken with interrupts disabled.
 
-use alloc::{collections::VecDeque, vec::Vec};
-use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use core::arch::naked_asm;
+use alloc::{boxed::Box, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use spin::{Mutex, RwLock};
 use lazy_static::lazy_static;
-use x86_64::VirtAddr;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::process::context::{self, ProcessContext};
 
-/// Process ID type
-pub type Pid = u32;
+pub mod deadline;
+pub mod fair;
+pub mod rt;
 
-/// Thread ID type
-pub type Tid = u64;
+use deadline::{DeadlineEntity, DeadlineParams, DlRunQueue};
+use fair::{FairEntity, FairRunQueue};
+use rt::RtRunQueue;
+
+pub use crate::process::{Pid, Priority, ProcessState};
 
 /// CPU ID type
 pub type CpuId = u32;
 
-/// Process priority levels
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-#[repr(u8)]
-pub enum Priority {
-    /// Real-time priority (highest)
-    RealTime = 0,
-    /// High priority
-    High = 1,
-    /// Normal priority (default)
-    Normal = 2,
-    /// Low priority
-    Low = 3,
-    /// Idle priority (lowest)
-    Idle = 4,
-}
+/// Size of the kernel stack every process runs its kernel code on
+const KERNEL_STACK_SIZE: usize = 16 * 1024;
 
 impl Priority {
     /// Get time slice duration in milliseconds for this priority
+    ///
+    /// Only real-time processes run in fixed slices; the others get theirs
+    /// from the fair class.
     pub fn time_slice_ms(&self) -> u64 {
         match self {
             Priority::RealTime => 100,  // 100ms for real-time
@@ -55,127 +59,61 @@ impl Priority {
     pub const fn count() -> usize {
         5
     }
-}
 
-/// Process state
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ProcessState {
-    /// Process is ready to run
-    Ready,
-    /// Process is currently running
-    Running,
-    /// Process is blocked waiting for I/O or event
-    Blocked,
-    /// Process is sleeping
-    Sleeping,
-    /// Process has terminated
-    Terminated,
-    /// Process is being created
-    Creating,
-}
-
-/// FPU/SSE/AVX state structure (512 bytes for FXSAVE/FXRSTOR)
-#[derive(Debug, Clone)]
-#[repr(C, align(16))]
-pub struct FpuState {
-    /// FPU control word
-    pub fcw: u16,
-    /// FPU status word
-    pub fsw: u16,
-    /// FPU tag word
-    pub ftw: u8,
-    /// Reserved
-    pub reserved1: u8,
-    /// FPU instruction pointer offset
-    pub fop: u16,
-    /// FPU instruction pointer segment
-    pub fip: u32,
-    /// FPU data pointer offset
-    pub fdp: u32,
-    /// FPU data pointer segment
-    pub fds: u32,
-    /// MXCSR register
-    pub mxcsr: u32,
-    /// MXCSR mask
-    pub mxcsr_mask: u32,
-    /// ST0-ST7 registers (8 * 16 bytes)
-    pub st_regs: [u8; 128],
-    /// XMM0-XMM15 registers (16 * 16 bytes)
-    pub xmm_regs: [u8; 256],
-    /// Padding to align to 512 bytes
-    pub padding: [u8; 96],
-}
+    /// Nice value a process of this priority starts with
+    pub fn nice(&self) -> i8 {
+        match self {
+            Priority::RealTime | Priority::Normal => 0,
+            Priority::High => -10,
+            Priority::Low => 10,
+            Priority::Idle => 19,
+        }
+    }
 
-impl Default for FpuState {
-    fn default() -> Self {
-        Self {
-            fcw: 0x037F,
-            fsw: 0,
-            ftw: 0xFF,
-            reserved1: 0,
-            fop: 0,
-            fip: 0,
-            fdp: 0,
-            fds: 0,
-            mxcsr: 0x1F80,
-            mxcsr_mask: 0xFFFF,
-            st_regs: [0; 128],
-            xmm_regs: [0; 256],
-            padding: [0; 96],
+    /// Coarse priority reported for a fair-class process with `nice`
+    pub fn from_nice(nice: i8) -> Self {
+        match nice {
+            n if n <= -5 => Priority::High,
+            n if n <= 5 => Priority::Normal,
+            n if n <= 15 => Priority::Low,
+            _ => Priority::Idle,
         }
     }
 }
 
-/// CPU registers state for context switching
-#[derive(Debug, Clone, Copy)]
-#[repr(C)]
-pub struct CpuState {
-    // General purpose registers
-    pub rax: u64,
-    pub rbx: u64,
-    pub rcx: u64,
-    pub rdx: u64,
-    pub rsi: u64,
-    pub rdi: u64,
-    pub rbp: u64,
-    pub rsp: u64,
-    pub r8: u64,
-    pub r9: u64,
-    pub r10: u64,
-    pub r11: u64,
-    pub r12: u64,
-    pub r13: u64,
-    pub r14: u64,
-    pub r15: u64,
-    
-    // Control registers
-    pub rip: u64,
-    pub rflags: u64,
-    pub cs: u64,
-    pub ss: u64,
-    
-    // Segment registers
-    pub ds: u64,
-    pub es: u64,
-    pub fs: u64,
-    pub gs: u64,
+/// Scheduling policy of a process, as set by sched_setscheduler(2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedPolicy {
+    /// Fair class
+    Normal,
+    /// Real-time, runs until it blocks, yields or is preempted
+    Fifo,
+    /// Real-time, takes turns with its priority level
+    RoundRobin,
+    /// Fair class, never preempts on wakeup
+    Batch,
+    /// Fair class at the lowest possible weight
+    Idle,
+    /// Earliest deadline first with a runtime reservation
+    Deadline,
 }
 
-impl Default for CpuState {
-    fn default() -> Self {
-        Self {
-            rax: 0, rbx: 0, rcx: 0, rdx: 0,
-            rsi: 0, rdi: 0, rbp: 0, rsp: 0,
-            r8: 0, r9: 0, r10: 0, r11: 0,
-            r12: 0, r13: 0, r14: 0, r15: 0,
-            rip: 0, rflags: 0x200, // Enable interrupts
-            cs: 0x08, ss: 0x10,    // Kernel code/data segments
-            ds: 0x10, es: 0x10, fs: 0x10, gs: 0x10,
-        }
+impl SchedPolicy {
+    /// Whether the policy runs ahead of the fair class
+    pub fn is_realtime(&self) -> bool {
+        matches!(self, SchedPolicy::Fifo | SchedPolicy::RoundRobin | SchedPolicy::Deadline)
     }
 }
 
-/// Process Control Block (PCB)
+/// Scheduling classes, in the order they are served
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Class {
+    Deadline,
+    Rt,
+    Fair,
+}
+
+/// Scheduling state of a process
 #[derive(Debug)]
 pub struct Process {
     /// Process ID
@@ -184,58 +122,79 @@ pub struct Process {
     pub parent_pid: Option<Pid>,
     /// Process priority
     pub priority: Priority,
+    /// Scheduling policy
+    pub policy: SchedPolicy,
+    /// Real-time priority, 1 to 99 under SCHED_FIFO and SCHED_RR, else 0
+    pub rt_priority: u8,
     /// Current state
     pub state: ProcessState,
-    /// CPU state for context switching
-    pub cpu_state: CpuState,
-    /// FPU/SSE/AVX state
-    pub fpu_state: FpuState,
-    /// Whether FPU state is valid/dirty
-    pub fpu_state_valid: bool,
-    /// Virtual memory space base
-    pub memory_base: VirtAddr,
-    /// Stack pointer
-    pub stack_pointer: VirtAddr,
-    /// Stack size in bytes
-    pub stack_size: usize,
-    /// Time when process was created
+    /// Registers saved while switched out; boxed so the address handed to a
+    /// context switch stays valid when the table grows
+    pub context: Box<ProcessContext>,
+    /// Base of the kernel stack region
+    pub kernel_stack: u64,
+    /// Time when process was created (in nanoseconds)
     pub creation_time: u64,
-    /// Total CPU time used (in microseconds)
+    /// CPU time used up to the last switch (in nanoseconds)
     pub cpu_time_used: u64,
-    /// Last time this process was scheduled
+    /// Last time this process was scheduled (in nanoseconds)
     pub last_scheduled: u64,
     /// CPU affinity mask (which CPUs this process can run on)
     pub cpu_affinity: u64,
     /// Current CPU this process is running on
     pub current_cpu: Option<CpuId>,
+    /// Fair-class state; unused while the process is real-time
+    pub fair: FairEntity,
+    /// Deadline-class state; unused under other policies
+    pub dl: DeadlineEntity,
+    /// A CPU is still on this process's stack; no other CPU may resume it
+    /// until that one has switched away
+    pub on_cpu: bool,
+    /// A wakeup arrived while the process was still running, so its next
+    /// attempt to block returns at once
+    pub wakeup_pending: bool,
+    /// Reaped while still on a CPU; dropped once that CPU switches away
+    pub reaped: bool,
     /// Process name
     pub name: [u8; 32],
 }
 
 impl Process {
     /// Create a new process
-    pub fn new(pid: Pid, parent_pid: Option<Pid>, priority: Priority, name: &str) -> Self {
+    pub fn new(
+        pid: Pid,
+        parent_pid: Option<Pid>,
+        priority: Priority,
+        name: &str,
+        context: Box<ProcessContext>,
+        kernel_stack: u64,
+    ) -> Self {
         let mut process_name = [0u8; 32];
         let name_bytes = name.as_bytes();
         let copy_len = core::cmp::min(name_bytes.len(), 31);
         process_name[..copy_len].copy_from_slice(&name_bytes[..copy_len]);
 
+        let realtime = priority == Priority::RealTime;
+
         Self {
             pid,
             parent_pid,
             priority,
-            state: ProcessState::Creating,
-            cpu_state: CpuState::default(),
-            fpu_state: FpuState::default(),
-            fpu_state_valid: false,
-            memory_base: VirtAddr::new(0),
-            stack_pointer: VirtAddr::new(0),
-            stack_size: 0,
-            creation_time: get_system_time(),
+            policy: if realtime { SchedPolicy::RoundRobin } else { SchedPolicy::Normal },
+            rt_priority: if realtime { rt::DEFAULT_RT_PRIO } else { 0 },
+            state: ProcessState::Ready,
+            context,
+            kernel_stack,
+            creation_time: crate::time::uptime_ns(),
             cpu_time_used: 0,
             last_scheduled: 0,
             cpu_affinity: u64::MAX, // Can run on any CPU by default
             current_cpu: None,
+            fair: FairEntity::new(priority.nice(), fair::ROOT_GROUP),
+            dl: DeadlineEntity::default(),
+            on_cpu: false,
+            wakeup_pending: false,
+            reaped: false,
             name: process_name,
         }
     }
@@ -258,6 +217,25 @@ impl Process {
     pub fn set_cpu_affinity(&mut self, cpu_mask: u64) {
         self.cpu_affinity = cpu_mask;
     }
+
+    /// Whether `cpu_id` may resume the process now
+    fn can_resume_on(&self, cpu_id: CpuId) -> bool {
+        self.can_run_on_cpu(cpu_id) && (!self.on_cpu || self.current_cpu == Some(cpu_id))
+    }
+
+    /// Whether the process is scheduled ahead of the fair class
+    pub fn is_realtime(&self) -> bool {
+        self.policy.is_realtime()
+    }
+
+    /// Class the process is scheduled in
+    fn class(&self) -> Class {
+        match self.policy {
+            SchedPolicy::Deadline => Class::Deadline,
+            SchedPolicy::Fifo | SchedPolicy::RoundRobin => Class::Rt,
+            SchedPolicy::Normal | SchedPolicy::Batch | SchedPolicy::Idle => Class::Fair,
+        }
+    }
 }
 
 /// Per-CPU scheduler state
@@ -267,8 +245,12 @@ pub struct CpuScheduler {
     pub cpu_id: CpuId,
     /// Currently running process
     pub current_process: Option<Pid>,
-    /// Ready queues for each priority level
-    pub ready_queues: [VecDeque<Pid>; Priority::count()],
+    /// Ready deadline processes, and throttled ones awaiting their runtime
+    pub dl: DlRunQueue,
+    /// Ready SCHED_FIFO and SCHED_RR processes
+    pub rt: RtRunQueue,
+    /// Ready processes of the fair class
+    pub fair: FairRunQueue,
     /// Time slice remaining for current process (in microseconds)
     pub time_slice_remaining: u64,
     /// Total processes scheduled on this CPU
@@ -277,6 +259,19 @@ pub struct CpuScheduler {
     pub utilization: u8,
     /// Idle time in microseconds
     pub idle_time: u64,
+    /// The current process should give up the CPU at the next chance
+    pub need_resched: bool,
+    /// Context of the CPU's own flow, which runs when no process does
+    idle_context: Box<ProcessContext>,
+    /// Process this CPU last switched away from, still marked `on_cpu`
+    prev_process: Option<Pid>,
+    /// `prev_process` is runnable but no longer allowed on this CPU, and
+    /// goes to another once it is off it
+    migrate_prev: bool,
+    /// Class the current process was picked in
+    curr_class: Option<Class>,
+    /// The current process asked to go to the back of its queue
+    yielding: bool,
 }
 
 impl CpuScheduler {
@@ -285,44 +280,49 @@ impl CpuScheduler {
         Self {
             cpu_id,
             current_process: None,
-            ready_queues: [
-                VecDeque::new(), VecDeque::new(), VecDeque::new(),
-                VecDeque::new(), VecDeque::new()
-            ],
+            dl: DlRunQueue::new(),
+            rt: RtRunQueue::new(),
+            fair: FairRunQueue::new(),
             time_slice_remaining: 0,
             total_scheduled: 0,
             utilization: 0,
             idle_time: 0,
+            need_resched: false,
+            idle_context: Box::new(ProcessContext::default()),
+            prev_process: None,
+            migrate_prev: false,
+            curr_class: None,
+            yielding: false,
         }
     }
 
-    /// Add a process to the ready queue
-    pub fn enqueue_process(&mut self, pid: Pid, priority: Priority) {
-        self.ready_queues[priority as usize].push_back(pid);
+    /// Add a ready process to the back of the queue of its class
+    ///
+    /// Fair-class processes must already be placed on this CPU's timeline;
+    /// throttled deadline processes wait here for their next period.
+    pub fn enqueue_process(&mut self, process: &Process) {
+        match process.class() {
+            Class::Deadline => self.dl.enqueue(process.pid, &process.dl),
+            Class::Rt => self.rt.enqueue(process.pid, process.rt_priority, false),
+            Class::Fair => self.fair.enqueue(process.pid, &process.fair),
+        }
     }
 
-    /// Get the next process to run
-    pub fn dequeue_next_process(&mut self) -> Option<(Pid, Priority)> {
-        // Check each priority level from highest to lowest
-        for (priority_idx, queue) in self.ready_queues.iter_mut().enumerate() {
-            if let Some(pid) = queue.pop_front() {
-                let priority = match priority_idx {
-                    0 => Priority::RealTime,
-                    1 => Priority::High,
-                    2 => Priority::Normal,
-                    3 => Priority::Low,
-                    4 => Priority::Idle,
-                    _ => Priority::Normal,
-                };
-                return Some((pid, priority));
-            }
-        }
-        None
+    /// Drop every queue entry of `pid`
+    fn dequeue_process(&mut self, pid: Pid) {
+        self.dl.remove(pid);
+        self.rt.remove(pid);
+        self.fair.remove(pid);
+    }
+
+    /// Whether any queue of this CPU holds `pid`
+    fn is_queued(&self, pid: Pid) -> bool {
+        self.dl.contains(pid) || self.rt.contains(pid) || self.fair.contains(pid)
     }
 
     /// Get the number of ready processes
     pub fn ready_process_count(&self) -> usize {
-        self.ready_queues.iter().map(|q| q.len()).sum()
+        self.dl.len() + self.rt.len() + self.fair.len()
     }
 
     /// Get the total number of processes (ready + current)
@@ -343,304 +343,609 @@ impl CpuScheduler {
     }
 }
 
+/// A switch `pick_next` decided on
+struct Switch {
+    prev: *mut ProcessContext,
+    next: *const ProcessContext,
+}
+
 /// Global scheduler state
 pub struct GlobalScheduler {
     /// All processes in the system
-    pub processes: RwLock<Vec<Process>>,
+    processes: RwLock<Vec<Process>>,
     /// Per-CPU schedulers
     pub cpu_schedulers: Vec<Mutex<CpuScheduler>>,
-    /// Next process ID to assign
-    pub next_pid: AtomicU64,
+    /// Process running on each CPU, 0 for none; readable without a lock
+    running: Vec<AtomicU32>,
     /// Total number of processes
     pub process_count: AtomicUsize,
     /// System boot time
     pub boot_time: u64,
     /// Load balancing enabled
-    pub load_balancing_enabled: bool,
+    pub load_balancing_enabled: AtomicBool,
 }
 
 impl GlobalScheduler {
     /// Create a new global scheduler
     pub fn new(num_cpus: usize) -> Self {
         let mut cpu_schedulers = Vec::with_capacity(num_cpus);
+        let mut running = Vec::with_capacity(num_cpus);
         for cpu_id in 0..num_cpus {
             cpu_schedulers.push(Mutex::new(CpuScheduler::new(cpu_id as CpuId)));
+            running.push(AtomicU32::new(0));
         }
 
         Self {
             processes: RwLock::new(Vec::new()),
             cpu_schedulers,
-            next_pid: AtomicU64::new(1),
+            running,
             process_count: AtomicUsize::new(0),
             boot_time: get_system_time(),
-            load_balancing_enabled: true,
+            load_balancing_enabled: AtomicBool::new(true),
         }
     }
 
-    /// Create a new process with advanced scheduling features
-    pub fn create_process(&self, parent_pid: Option<Pid>, priority: Priority, name: &str) -> Result<Pid, &'static str> {
-        let pid = self.next_pid.fetch_add(1, Ordering::SeqCst) as Pid;
-        let mut process = Process::new(pid, parent_pid, priority, name);
-        process.state = ProcessState::Ready;
-
-        // Inherit CPU affinity from parent if available
+    /// Start scheduling a process `crate::process` created
+    ///
+    /// The process gets a kernel stack of its own and first runs
+    /// `process_entry` on it.
+    pub fn add_process(&self, pid: Pid, parent_pid: Option<Pid>, priority: Priority, name: &str) -> Result<(), &'static str> {
+        let kernel_stack = allocate_kernel_stack()?;
+        let stack_top = kernel_stack + KERNEL_STACK_SIZE as u64;
+
+        let mut context = Box::new(context::create_process_context(
+            process_entry as usize as u64,
+            stack_top - 8, // As if `process_entry` had been called
+            stack_top,
+            0,
+        ));
+        // Interrupts stay off until the process reaches user mode
+        context.cpu.rflags = 0x2;
+
+        let mut process = Process::new(pid, parent_pid, priority, name, context, kernel_stack);
+
+        // Inherit CPU affinity and group from parent if available, and the
+        // exact nice value and policy unless the child was given a priority
+        // of its own. Deadline reservations are not inherited.
         if let Some(parent_pid) = parent_pid {
-            if let Some(parent_affinity) = self.with_process(parent_pid, |p| p.cpu_affinity) {
+            let parent = self.with_process(parent_pid, |p| {
+                (p.cpu_affinity, p.priority, p.policy, p.rt_priority, p.fair.clone())
+            });
+            if let Some((parent_affinity, parent_priority, parent_policy, parent_rt_priority, parent_fair)) = parent {
                 process.cpu_affinity = parent_affinity;
+                process.fair.group = parent_fair.group;
+                if parent_priority == priority && parent_policy != SchedPolicy::Deadline {
+                    process.policy = parent_policy;
+                    process.rt_priority = parent_rt_priority;
+                    process.fair.set_nice(parent_fair.nice);
+                    process.fair.set_policy(parent_fair.batch, parent_fair.idle);
+                }
             }
         }
 
-        // Add to process table
-        {
-            let mut processes = self.processes.write();
-            processes.push(process);
-        }
+        without_interrupts(|| self.processes.write().push(process));
+        self.process_count.fetch_add(1, Ordering::SeqCst);
 
         // Schedule on least loaded CPU that matches affinity
         let cpu_id = self.find_best_cpu_for_process(pid);
-        {
+        self.enqueue_on(cpu_id, pid, true);
+
+        Ok(())
+    }
+
+    /// Queue a ready process on `cpu_id`
+    ///
+    /// Fair-class processes are placed on that CPU's timeline first, by the
+    /// lag they left their last queue with, or as new ones with `initial`;
+    /// deadline processes get a fresh deadline if their old one is out of
+    /// reach. Asks the CPU to reschedule if the process should run before
+    /// the current one.
+    fn enqueue_on(&self, cpu_id: CpuId, pid: Pid, initial: bool) {
+        let preempt = without_interrupts(|| {
             let mut cpu_scheduler = self.cpu_schedulers[cpu_id as usize].lock();
-            cpu_scheduler.enqueue_process(pid, priority);
-        }
+            let mut processes = self.processes.write();
 
-        self.process_count.fetch_add(1, Ordering::SeqCst);
+            let current = cpu_scheduler.current_process
+                .and_then(|current| processes.iter().find(|p| p.pid == current))
+                .map(|current| (current.class(), current.rt_priority, current.dl.abs_deadline));
+            let Some(process) = processes.iter_mut().find(|p| p.pid == pid) else {
+                return false;
+            };
 
-        Ok(pid)
+            match process.class() {
+                Class::Deadline => process.dl.wake(crate::time::uptime_ns()),
+                Class::Rt => {}
+                Class::Fair => cpu_scheduler.fair.place(&mut process.fair, initial),
+            }
+            cpu_scheduler.enqueue_process(process);
+
+            // A higher class always preempts; within a class the earlier
+            // deadline, the higher real-time priority or, for the fair
+            // class, an eligible earlier virtual deadline
+            let preempt = match current {
+                None => true,
+                Some(_) if process.class() == Class::Deadline && process.dl.throttled => false,
+                Some((class, rt_priority, abs_deadline)) => match process.class().cmp(&class) {
+                    core::cmp::Ordering::Less => true,
+                    core::cmp::Ordering::Greater => false,
+                    core::cmp::Ordering::Equal => match class {
+                        Class::Deadline => process.dl.abs_deadline < abs_deadline,
+                        Class::Rt => process.rt_priority > rt_priority,
+                        Class::Fair => cpu_scheduler.fair.should_preempt(&process.fair),
+                    },
+                },
+            };
+            if preempt {
+                cpu_scheduler.need_resched = true;
+            }
+            preempt
+        });
+        if preempt {
+            self.kick(cpu_id);
+        }
     }
 
     /// Find the best CPU for a process considering affinity and load
     fn find_best_cpu_for_process(&self, pid: Pid) -> CpuId {
         let process_affinity = self.with_process(pid, |p| p.cpu_affinity).unwrap_or(u64::MAX);
-        
-        let mut best_cpu = 0;
+        let online = ONLINE_CPUS.load(Ordering::Acquire);
+
+        let mut best_cpu = None;
         let mut min_load = usize::MAX;
-        
+
         for (cpu_id, cpu_scheduler_mutex) in self.cpu_schedulers.iter().enumerate() {
             // Check if process can run on this CPU
-            if cpu_id >= 64 || (process_affinity & (1 << cpu_id)) == 0 {
+            if cpu_id >= 64 || (process_affinity & online & (1 << cpu_id)) == 0 {
                 continue;
             }
+            best_cpu.get_or_insert(cpu_id);
 
             if let Some(cpu_scheduler) = cpu_scheduler_mutex.try_lock() {
-                let load = cpu_scheduler.ready_process_count();
+                let load = cpu_scheduler.process_count();
                 if load < min_load {
                     min_load = load;
-                    best_cpu = cpu_id;
+                    best_cpu = Some(cpu_id);
                 }
             }
         }
-        
-        best_cpu as CpuId
+
+        best_cpu.unwrap_or(0) as CpuId
     }
 
-    /// Terminate a process and clean up its scheduling state
+    /// Mark a process terminated; it never runs again
+    ///
+    /// Entries left in ready queues are dropped when they come up. A process
+    /// terminating itself must still call `schedule` to leave the CPU.
     pub fn terminate_process(&self, pid: Pid) -> Result<(), &'static str> {
-        // Remove from all CPU ready queues
-        for cpu_scheduler_mutex in &self.cpu_schedulers {
-            if let Some(mut cpu_scheduler) = cpu_scheduler_mutex.try_lock() {
-                // Remove from all priority queues
-                for queue in &mut cpu_scheduler.ready_queues {
-                    queue.retain(|&p| p != pid);
-                }
-                
-                // Clear if it's the current process
-                if cpu_scheduler.current_process == Some(pid) {
-                    cpu_scheduler.current_process = None;
-                    cpu_scheduler.time_slice_remaining = 0;
-                }
-            }
+        let (running_on, reservation) = self.with_process_mut(pid, |process| {
+            let running_on = process.current_cpu.filter(|_| process.state == ProcessState::Running);
+            let reservation = (process.policy == SchedPolicy::Deadline && process.state != ProcessState::Terminated)
+                .then_some(process.dl.params);
+            process.state = ProcessState::Terminated;
+            process.wakeup_pending = false;
+            (running_on, reservation)
+        }).ok_or("Process not found")?;
+
+        if let Some(cpu_id) = running_on {
+            self.request_resched(cpu_id);
         }
 
-        // Update process state
-        self.with_process_mut(pid, |process| {
-            process.state = ProcessState::Terminated;
-            process.current_cpu = None;
-        });
+        // Hand a deadline process's bandwidth back
+        if let Some(params) = reservation {
+            let _ = deadline::admit(Some(&params), None, online_cpu_count());
+        }
 
         self.process_count.fetch_sub(1, Ordering::SeqCst);
         Ok(())
     }
 
-    /// Block a process (remove from ready queues)
+    /// Free a terminated process's scheduling state
+    pub fn remove_process(&self, pid: Pid) {
+        let removed = without_interrupts(|| {
+            let mut processes = self.processes.write();
+            let index = processes.iter().position(|p| p.pid == pid)?;
+            let process = &mut processes[index];
+            if process.state != ProcessState::Terminated {
+                return None;
+            }
+            if process.on_cpu {
+                // Its CPU is still on the kernel stack; `finish_switch` drops it
+                process.reaped = true;
+                return None;
+            }
+            Some(processes.swap_remove(index))
+        });
+
+        if let Some(process) = removed {
+            free_kernel_stack(process.kernel_stack);
+        }
+    }
+
+    /// Block a process; it stops running at its next switch
     pub fn block_process(&self, pid: Pid) -> Result<(), &'static str> {
-        // Remove from all CPU ready queues
-        for cpu_scheduler_mutex in &self.cpu_schedulers {
-            if let Some(mut cpu_scheduler) = cpu_scheduler_mutex.try_lock() {
-                for queue in &mut cpu_scheduler.ready_queues {
-                    queue.retain(|&p| p != pid);
-                }
+        let running_on = self.with_process_mut(pid, |process| {
+            let running_on = process.current_cpu.filter(|_| process.state == ProcessState::Running);
+            if matches!(process.state, ProcessState::Ready | ProcessState::Running) {
+                process.state = ProcessState::Blocked;
             }
+            running_on
+        }).ok_or("Process not found")?;
+
+        if let Some(cpu_id) = running_on {
+            self.request_resched(cpu_id);
         }
 
-        // Update process state
-        self.with_process_mut(pid, |process| {
-            process.state = ProcessState::Blocked;
-        });
+        Ok(())
+    }
 
+    /// Block the process running on `cpu_id` and switch away from it
+    ///
+    /// Returns at once if the process was woken since it last blocked.
+    fn block_current(&self, cpu_id: CpuId) -> Result<(), &'static str> {
+        let pid = self.running.get(cpu_id as usize).map_or(0, |p| p.load(Ordering::Relaxed));
+        if pid == 0 {
+            return Err("No process running on this CPU");
+        }
+
+        let must_switch = self.with_process_mut(pid, |process| {
+            if core::mem::take(&mut process.wakeup_pending) {
+                false
+            } else {
+                process.state = ProcessState::Blocked;
+                true
+            }
+        }).ok_or("Process not found")?;
+
+        if must_switch {
+            self.switch(cpu_id);
+        }
         Ok(())
     }
 
-    /// Unblock a process (add back to ready queue)
+    /// Make a blocked process ready again
+    ///
+    /// Waking a process that has not blocked yet makes its next block return
+    /// straight away, so a wakeup racing with the sleeper is never lost.
     pub fn unblock_process(&self, pid: Pid) -> Result<(), &'static str> {
-        let (priority, cpu_id) = self.with_process_mut(pid, |process| {
-            process.state = ProcessState::Ready;
-            (process.priority, self.find_best_cpu_for_process(pid))
+        let woken = self.with_process_mut(pid, |process| match process.state {
+            ProcessState::Blocked | ProcessState::Sleeping => {
+                process.state = ProcessState::Ready;
+                true
+            }
+            ProcessState::Ready | ProcessState::Running => {
+                process.wakeup_pending = true;
+                false
+            }
+            _ => false,
         }).ok_or("Process not found")?;
 
-        // Add to appropriate CPU's ready queue
-        if let Some(mut cpu_scheduler) = self.cpu_schedulers[cpu_id as usize].try_lock() {
-            cpu_scheduler.enqueue_process(pid, priority);
+        if woken {
+            let cpu_id = self.find_best_cpu_for_process(pid);
+            self.enqueue_on(cpu_id, pid, false);
         }
 
         Ok(())
     }
 
-    /// Schedule the next process on the given CPU with real scheduling algorithms
-    pub fn schedule(&self, cpu_id: CpuId) -> Option<Pid> {
+    /// Change how a process is scheduled
+    ///
+    /// A queued process is taken off its queue around the change, so that
+    /// it lands in the right class with the queue's sums kept consistent.
+    fn change_params<F>(&self, pid: Pid, change: F) -> Result<(), &'static str>
+    where
+        F: FnOnce(&mut Process),
+    {
+        let queued_on = without_interrupts(|| {
+            let mut queued_on = None;
+            for cpu_scheduler_mutex in &self.cpu_schedulers {
+                let mut cpu_scheduler = cpu_scheduler_mutex.lock();
+                if !cpu_scheduler.is_queued(pid) {
+                    continue;
+                }
+                if let Some(process) = self.processes.write().iter_mut().find(|p| p.pid == pid) {
+                    if cpu_scheduler.fair.contains(pid) {
+                        cpu_scheduler.fair.save_lag(&mut process.fair);
+                    }
+                }
+                cpu_scheduler.dequeue_process(pid);
+                queued_on.get_or_insert(cpu_scheduler.cpu_id);
+            }
+            queued_on
+        });
+
+        let (state, current_cpu, affinity) = self.with_process_mut(pid, |process| {
+            change(process);
+            (process.state, process.current_cpu, process.cpu_affinity)
+        }).ok_or("Process not found")?;
+
+        match state {
+            ProcessState::Ready => {
+                let cpu_id = queued_on
+                    .filter(|&cpu_id| cpu_id < 64 && affinity & (1 << cpu_id) != 0)
+                    .unwrap_or_else(|| self.find_best_cpu_for_process(pid));
+                self.enqueue_on(cpu_id, pid, false);
+            }
+            // Let a running process's CPU pick again under the new parameters
+            ProcessState::Running => {
+                if let Some(cpu_id) = current_cpu {
+                    self.request_resched(cpu_id);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Ask `cpu_id` to switch away from its current process
+    fn request_resched(&self, cpu_id: CpuId) {
+        if let Some(cpu_scheduler) = self.cpu_schedulers.get(cpu_id as usize) {
+            without_interrupts(|| cpu_scheduler.lock().need_resched = true);
+            self.kick(cpu_id);
+        }
+    }
+
+    /// Make another CPU act on `need_resched` now rather than at its next
+    /// tick
+    ///
+    /// Must not be called with a CPU lock held.
+    fn kick(&self, cpu_id: CpuId) {
+        let online = cpu_id < 64 && ONLINE_CPUS.load(Ordering::Acquire) & (1 << cpu_id) != 0;
+        if online && cpu_id != get_current_cpu_id() {
+            let _ = crate::smp::send_ipi(cpu_id, crate::smp::RESCHEDULE_VECTOR);
+        }
+    }
+
+    /// Switch the calling CPU, `cpu_id`, to the next process to run
+    ///
+    /// Returns once the caller is switched back in, possibly on another CPU,
+    /// or straight away if it stays. Interrupts must be disabled.
+    fn switch(&self, cpu_id: CpuId) {
+        if let Some(switch) = self.pick_next(cpu_id) {
+            unsafe {
+                context::switch_context(switch.prev, switch.next);
+            }
+            self.finish_switch(get_current_cpu_id());
+        }
+    }
+
+    /// Second half of a switch, run by the process switched to
+    ///
+    /// Until here the CPU was still on the previous process's stack, so that
+    /// process could not be resumed elsewhere.
+    fn finish_switch(&self, cpu_id: CpuId) {
+        let (prev, migrate) = {
+            let mut cpu_scheduler = self.cpu_schedulers[cpu_id as usize].lock();
+            (cpu_scheduler.prev_process.take(), core::mem::take(&mut cpu_scheduler.migrate_prev))
+        };
+        if let Some(pid) = prev {
+            let (reaped, ready) = self.with_process_mut(pid, |process| {
+                process.on_cpu = false;
+                (process.reaped, process.state == ProcessState::Ready)
+            }).unwrap_or((false, false));
+
+            if reaped {
+                self.remove_process(pid);
+            } else if migrate && ready {
+                let target = self.find_best_cpu_for_process(pid);
+                self.enqueue_on(target, pid, false);
+            }
+        }
+    }
+
+    /// Decide what `cpu_id` runs next, with real scheduling algorithms
+    ///
+    /// Returns the switch to make, or `None` if the CPU carries on with what
+    /// it runs now.
+    fn pick_next(&self, cpu_id: CpuId) -> Option<Switch> {
         if cpu_id as usize >= self.cpu_schedulers.len() {
             return None;
         }
 
         let mut cpu_scheduler = self.cpu_schedulers[cpu_id as usize].lock();
-        let current_time = get_system_time();
-        
-        // Handle current process time slice expiration or preemption
-        if let Some(current_pid) = cpu_scheduler.current_process {
-            let should_preempt = self.should_preempt_process(current_pid, cpu_id, &cpu_scheduler);
-            
-            if cpu_scheduler.time_slice_remaining == 0 || should_preempt {
-                // Move current process back to ready queue if still runnable
-                self.with_process_mut(current_pid, |process| {
-                    if process.state == ProcessState::Running {
-                        process.state = ProcessState::Ready;
-                        // Apply priority boost for interactive processes
-                        if process.cpu_time_used < 100_000 { // Less than 100ms CPU time
-                            let boosted_priority = match process.priority {
-                                Priority::Low => Priority::Normal,
-                                Priority::Normal => Priority::High,
-                                _ => process.priority,
-                            };
-                            cpu_scheduler.enqueue_process(current_pid, boosted_priority);
-                        } else {
-                            cpu_scheduler.enqueue_process(current_pid, process.priority);
+        let current_time = crate::time::uptime_ns();
+        let prev = cpu_scheduler.current_process;
+        cpu_scheduler.need_resched = false;
+
+        // Charge the outgoing process and move it back to its queue if still
+        // runnable
+        let ran_as = cpu_scheduler.curr_class.take();
+        let yielding = core::mem::take(&mut cpu_scheduler.yielding);
+        let prev_context = match prev {
+            Some(current_pid) => {
+                let mut processes = self.processes.write();
+                let process = processes.iter_mut().find(|p| p.pid == current_pid)?;
+                process.cpu_time_used += current_time.saturating_sub(process.last_scheduled);
+                process.last_scheduled = current_time;
+
+                match ran_as {
+                    Some(Class::Fair) => cpu_scheduler.fair.update_curr(&mut process.fair, current_time),
+                    Some(Class::Deadline) => {
+                        process.dl.update(current_time);
+                    }
+                    Some(Class::Rt) | None => {}
+                }
+                cpu_scheduler.fair.put_curr();
+
+                if process.state == ProcessState::Running && !process.can_run_on_cpu(cpu_id) {
+                    // Its affinity changed while it ran; another CPU takes
+                    // it once this one is off its stack
+                    process.state = ProcessState::Ready;
+                    cpu_scheduler.migrate_prev = true;
+                    if ran_as == Some(Class::Fair) {
+                        cpu_scheduler.fair.save_lag(&mut process.fair);
+                    }
+                } else if process.state == ProcessState::Running {
+                    process.state = ProcessState::Ready;
+                    match process.class() {
+                        Class::Deadline => {
+                            if ran_as != Some(Class::Deadline) {
+                                process.dl.wake(current_time);
+                            } else if yielding {
+                                // Yielding gives up the rest of the period
+                                process.dl.remaining = 0;
+                                process.dl.throttled = true;
+                            }
+                            cpu_scheduler.enqueue_process(process);
+                        }
+                        Class::Rt => {
+                            // A preempted process resumes its turn first; one
+                            // that yielded or used up its RR slice goes last
+                            let turn_over = yielding
+                                || (process.policy == SchedPolicy::RoundRobin && cpu_scheduler.time_slice_remaining == 0);
+                            let head = ran_as == Some(Class::Rt) && !turn_over;
+                            cpu_scheduler.rt.enqueue(current_pid, process.rt_priority, head);
+                        }
+                        Class::Fair => {
+                            if ran_as != Some(Class::Fair) {
+                                // Became fair-class while running
+                                cpu_scheduler.fair.place(&mut process.fair, false);
+                            } else if process.fair.slice_expired() {
+                                process.fair.renew_deadline();
+                            }
+                            cpu_scheduler.enqueue_process(process);
                         }
                     }
-                });
-                cpu_scheduler.current_process = None;
+                } else if ran_as == Some(Class::Fair) {
+                    // Keep its lag for when it wakes
+                    cpu_scheduler.fair.save_lag(&mut process.fair);
+                }
+                &mut *process.context as *mut ProcessContext
             }
-        }
+            None => &mut *cpu_scheduler.idle_context as *mut ProcessContext,
+        };
 
         // Load balancing: check if we should steal work from other CPUs
-        if cpu_scheduler.current_process.is_none() && self.load_balancing_enabled {
+        if cpu_scheduler.ready_process_count() == 0 && self.load_balancing_enabled.load(Ordering::Relaxed) {
             self.try_load_balance(cpu_id, &mut cpu_scheduler);
         }
 
-        // Get next process to run using priority-based scheduling
-        if cpu_scheduler.current_process.is_none() {
-            if let Some((next_pid, priority)) = self.select_next_process(&mut cpu_scheduler) {
-                // Update process state
-                self.with_process_mut(next_pid, |process| {
-                    process.state = ProcessState::Running;
-                    process.current_cpu = Some(cpu_id);
-                    process.last_scheduled = current_time;
-                });
-
-                // Set time slice based on priority and process behavior
-                let base_time_slice = priority.time_slice_ms() * 1000; // Convert to microseconds
-                let adjusted_time_slice = self.calculate_time_slice(next_pid, base_time_slice);
-                
-                cpu_scheduler.current_process = Some(next_pid);
-                cpu_scheduler.time_slice_remaining = adjusted_time_slice;
-                cpu_scheduler.total_scheduled += 1;
+        // Deadline processes first, then real-time ones, then the fair class
+        let next = {
+            let mut processes = self.processes.write();
+            self.select_next_process(&mut cpu_scheduler, &mut processes).and_then(|next_pid| {
+                let process = processes.iter_mut().find(|p| p.pid == next_pid)?;
+                process.state = ProcessState::Running;
+                process.current_cpu = Some(cpu_id);
+                process.on_cpu = true;
+                process.last_scheduled = current_time;
+
+                let class = process.class();
+                let slice_ns = match class {
+                    Class::Deadline => {
+                        process.dl.exec_start = current_time;
+                        process.dl.remaining.max(0) as u64
+                    }
+                    Class::Rt => rt::RR_TIMESLICE_NS,
+                    Class::Fair => {
+                        cpu_scheduler.fair.set_curr(next_pid, &mut process.fair, current_time);
+                        fair::BASE_SLICE_NS
+                    }
+                };
+                Some((next_pid, class, slice_ns / 1000, &*process.context as *const ProcessContext))
+            })
+        };
 
-                return Some(next_pid);
+        let (next_pid, next_context) = match next {
+            Some((next_pid, class, slice_us, next_context)) => {
+                cpu_scheduler.curr_class = Some(class);
+                cpu_scheduler.time_slice_remaining = slice_us;
+                cpu_scheduler.total_scheduled += 1;
+                (next_pid, next_context)
             }
+            None => (0, &*cpu_scheduler.idle_context as *const ProcessContext),
+        };
+
+        cpu_scheduler.current_process = (next_pid != 0).then_some(next_pid);
+        self.running[cpu_id as usize].store(next_pid, Ordering::Relaxed);
+
+        if prev.unwrap_or(0) == next_pid {
+            return None;
         }
+        cpu_scheduler.prev_process = prev;
 
-        cpu_scheduler.current_process
+        Some(Switch { prev: prev_context, next: next_context })
     }
 
-    /// Check if current process should be preempted by higher priority process
-    fn should_preempt_process(&self, current_pid: Pid, cpu_id: CpuId, cpu_scheduler: &CpuScheduler) -> bool {
-        // Check for higher priority processes in ready queue
-        let current_priority = self.with_process(current_pid, |process| process.priority);
-        
-        if let Some(current_priority) = current_priority {
-            // Check each priority level higher than current
-            for priority_level in 0..(current_priority as usize) {
-                if !cpu_scheduler.ready_queues[priority_level].is_empty() {
-                    return true; // Higher priority process available
+    /// Check if a queued process should run before the current one
+    fn should_preempt_process(&self, current_pid: Pid, cpu_scheduler: &CpuScheduler) -> bool {
+        let rt_ready = !cpu_scheduler.rt.is_empty() && !cpu_scheduler.rt.is_throttled();
+        match cpu_scheduler.curr_class {
+            Some(Class::Deadline) => {
+                let current_deadline = self.with_process(current_pid, |process| process.dl.abs_deadline);
+                match (cpu_scheduler.dl.earliest_deadline(), current_deadline) {
+                    (Some(earliest), Some(current)) => earliest < current,
+                    _ => false,
                 }
             }
+            Some(Class::Rt) => {
+                let current_priority = self.with_process(current_pid, |process| process.rt_priority).unwrap_or(0);
+                !cpu_scheduler.dl.is_empty()
+                    || (rt_ready && cpu_scheduler.rt.highest_priority().map_or(false, |p| p > current_priority))
+            }
+            Some(Class::Fair) | None => !cpu_scheduler.dl.is_empty() || rt_ready,
         }
-        
-        false
     }
 
     /// Select next process using advanced scheduling algorithms
-    fn select_next_process(&self, cpu_scheduler: &mut CpuScheduler) -> Option<(Pid, Priority)> {
-        // Priority-based scheduling with round-robin within each priority level
-        for (priority_idx, queue) in cpu_scheduler.ready_queues.iter_mut().enumerate() {
-            if !queue.is_empty() {
-                // For real-time processes, use FIFO
-                if priority_idx == 0 { // RealTime
-                    if let Some(pid) = queue.pop_front() {
-                        return Some((pid, Priority::RealTime));
-                    }
-                } else {
-                    // For other priorities, use round-robin
-                    if let Some(pid) = queue.pop_front() {
-                        let priority = match priority_idx {
-                            1 => Priority::High,
-                            2 => Priority::Normal,
-                            3 => Priority::Low,
-                            4 => Priority::Idle,
-                            _ => Priority::Normal,
-                        };
-                        return Some((pid, priority));
-                    }
-                }
+    ///
+    /// Queue entries whose process is no longer ready (it blocked, exited,
+    /// changed class or was picked elsewhere in the meantime) are dropped
+    /// here rather than hunted down when the state changes.
+    fn select_next_process(&self, cpu_scheduler: &mut CpuScheduler, processes: &mut [Process]) -> Option<Pid> {
+        let cpu_id = cpu_scheduler.cpu_id;
+        let usable = |pid: Pid, class: Class| {
+            let process = processes.iter().find(|p| p.pid == pid)?;
+            if process.state != ProcessState::Ready || process.class() != class {
+                return None;
             }
-        }
-        None
-    }
+            // A process still being switched away from elsewhere waits
+            Some(process.can_resume_on(cpu_id))
+        };
 
-    /// Calculate adaptive time slice based on process behavior
-    fn calculate_time_slice(&self, pid: Pid, base_time_slice: u64) -> u64 {
-        self.with_process(pid, |process| {
-            // Adjust time slice based on process behavior
-            let cpu_usage_ratio = if process.last_scheduled > 0 {
-                let total_time = get_system_time() - process.creation_time;
-                if total_time > 0 {
-                    (process.cpu_time_used * 100) / total_time
-                } else {
-                    0
-                }
-            } else {
-                0
-            };
+        // Earliest deadline first
+        if let Some(pid) = cpu_scheduler.dl.pick(|pid| usable(pid, Class::Deadline)) {
+            return Some(pid);
+        }
 
-            // Interactive processes (low CPU usage) get longer time slices
-            if cpu_usage_ratio < 10 {
-                base_time_slice * 2 // Double time slice for interactive processes
-            } else if cpu_usage_ratio > 80 {
-                base_time_slice / 2 // Half time slice for CPU-intensive processes
-            } else {
-                base_time_slice
+        // Then the highest real-time priority, unless real-time processes
+        // used up their share of this period
+        let rt_throttled = cpu_scheduler.rt.is_throttled();
+        if !rt_throttled {
+            if let Some(pid) = cpu_scheduler.rt.pick(|pid| usable(pid, Class::Rt)) {
+                return Some(pid);
             }
-        }).unwrap_or(base_time_slice)
+        }
+
+        // Then the earliest eligible virtual deadline of the fair class,
+        // passing over groups out of bandwidth
+        let now = crate::time::uptime_ns();
+        let fair_usable = |pid: Pid| {
+            let usable = usable(pid, Class::Fair)?;
+            let group = processes.iter().find(|p| p.pid == pid)?.fair.group;
+            Some(usable && !fair::group_throttled(group, now))
+        };
+        if let Some(pid) = cpu_scheduler.fair.pick(fair_usable) {
+            return Some(pid);
+        }
+
+        // Throttling is there for the other classes; with none of them
+        // ready the CPU still serves real-time processes rather than idle
+        if rt_throttled {
+            return cpu_scheduler.rt.pick(|pid| usable(pid, Class::Rt));
+        }
+        None
     }
 
     /// Try to steal work from other CPUs for load balancing
     fn try_load_balance(&self, cpu_id: CpuId, cpu_scheduler: &mut CpuScheduler) {
-        if !self.load_balancing_enabled {
+        if !self.load_balancing_enabled.load(Ordering::Relaxed) {
             return;
         }
 
         // Find the most loaded CPU
         let mut max_load = 0;
         let mut source_cpu = None;
-        
+
         for (other_cpu_id, other_scheduler_mutex) in self.cpu_schedulers.iter().enumerate() {
             if other_cpu_id == cpu_id as usize {
                 continue;
@@ -655,102 +960,140 @@ impl GlobalScheduler {
             }
         }
 
-        // Steal a process from the most loaded CPU
+        // Steal a fair-class process from the most loaded CPU; real-time
+        // ones stay where they are
         if let Some(source_cpu_id) = source_cpu {
             if let Some(mut source_scheduler) = self.cpu_schedulers[source_cpu_id].try_lock() {
-                // Try to steal from lower priority queues first
-                for priority_idx in (1..Priority::count()).rev() { // Skip RealTime (0)
-                    if let Some(stolen_pid) = source_scheduler.ready_queues[priority_idx].pop_back() {
-                        // Check if process can run on this CPU
-                        let can_migrate = self.with_process(stolen_pid, |process| {
-                            process.can_run_on_cpu(cpu_id)
-                        }).unwrap_or(false);
-
-                        if can_migrate {
-                            let priority = match priority_idx {
-                                1 => Priority::High,
-                                2 => Priority::Normal,
-                                3 => Priority::Low,
-                                4 => Priority::Idle,
-                                _ => Priority::Normal,
-                            };
-                            cpu_scheduler.enqueue_process(stolen_pid, priority);
-                            break;
-                        } else {
-                            // Put it back if it can't run on this CPU
-                            source_scheduler.ready_queues[priority_idx].push_back(stolen_pid);
-                        }
-                    }
-                }
+                self.migrate_fair(&mut source_scheduler, cpu_scheduler);
             }
         }
     }
 
+    /// Move the queued fair-class process with the latest deadline that may
+    /// run on `target` there, carrying its lag over
+    ///
+    /// The caller holds both CPU locks.
+    fn migrate_fair(&self, source: &mut CpuScheduler, target: &mut CpuScheduler) -> bool {
+        let target_cpu = target.cpu_id;
+        let mut processes = self.processes.write();
+
+        let stolen = source.fair.steal(|pid| {
+            processes.iter().find(|p| p.pid == pid).map_or(false, |process| {
+                process.state == ProcessState::Ready && process.can_run_on_cpu(target_cpu)
+            })
+        });
+        let Some(process) = stolen.and_then(|pid| processes.iter_mut().find(|p| p.pid == pid)) else {
+            return false;
+        };
+
+        source.fair.save_lag(&mut process.fair);
+        target.fair.place(&mut process.fair, false);
+        target.enqueue_process(process);
+        true
+    }
+
     /// Handle timer tick for scheduling with advanced time slicing
+    ///
+    /// Only decides whether the current process should be preempted; the
+    /// switch itself happens in `preempt`.
     pub fn timer_tick(&self, cpu_id: CpuId, elapsed_us: u64) {
         if cpu_id as usize >= self.cpu_schedulers.len() {
             return;
         }
 
-        let mut cpu_scheduler = self.cpu_schedulers[cpu_id as usize].lock();
-        let current_time = get_system_time();
-        
+        // The interrupted code may be inspecting this CPU's queues
+        let Some(mut cpu_scheduler) = self.cpu_schedulers[cpu_id as usize].try_lock() else {
+            return;
+        };
+        let current_time = crate::time::uptime_ns();
+
+        // A new RT throttling period lets waiting real-time processes back on
+        if cpu_scheduler.rt.refresh(current_time) && !cpu_scheduler.rt.is_empty() {
+            cpu_scheduler.need_resched = true;
+        }
+
+        // Deadline processes whose next period began get their runtime back
+        for pid in cpu_scheduler.dl.take_due(current_time) {
+            let replenished = self.with_process_mut(pid, |process| {
+                if process.state != ProcessState::Ready || process.class() != Class::Deadline {
+                    return false;
+                }
+                process.dl.replenish();
+                cpu_scheduler.dl.enqueue(pid, &process.dl);
+                true
+            }).unwrap_or(false);
+            if replenished {
+                cpu_scheduler.need_resched = true;
+            }
+        }
+
         if let Some(current_pid) = cpu_scheduler.current_process {
-            // Update process CPU time and statistics
-            self.with_process_mut(current_pid, |process| {
-                process.cpu_time_used += elapsed_us;
-                
-                // Update process priority based on behavior (aging)
-                let time_since_creation = current_time - process.creation_time;
-                if time_since_creation > 1_000_000 { // After 1 second
-                    let cpu_usage_ratio = (process.cpu_time_used * 100) / time_since_creation;
-                    
-                    // Demote CPU-intensive processes
-                    if cpu_usage_ratio > 90 && process.priority > Priority::RealTime {
-                        match process.priority {
-                            Priority::High => process.priority = Priority::Normal,
-                            Priority::Normal => process.priority = Priority::Low,
-                            _ => {}
-                        }
+            match cpu_scheduler.curr_class {
+                // A fair-class process is due for a switch once it ran past
+                // its virtual deadline, or its group ran out of bandwidth
+                Some(Class::Fair) => {
+                    let slice_expired = self.with_process_mut(current_pid, |process| {
+                        cpu_scheduler.fair.update_curr(&mut process.fair, current_time);
+                        let throttled = fair::charge_group(process.fair.group, elapsed_us * 1000, current_time);
+                        process.fair.slice_expired() || throttled
+                    }).unwrap_or(false);
+                    if slice_expired {
+                        cpu_scheduler.need_resched = true;
                     }
-                    // Promote interactive processes
-                    else if cpu_usage_ratio < 10 && process.priority < Priority::High {
-                        match process.priority {
-                            Priority::Low => process.priority = Priority::Normal,
-                            Priority::Normal => process.priority = Priority::High,
-                            _ => {}
+                }
+                // A deadline process runs until its runtime is used up
+                Some(Class::Deadline) => {
+                    let throttled = self.with_process_mut(current_pid, |process| {
+                        process.dl.update(current_time)
+                    }).unwrap_or(false);
+                    if throttled {
+                        cpu_scheduler.need_resched = true;
+                    }
+                }
+                // Real-time processes count towards RT throttling; RR ones
+                // also take turns in fixed slices
+                Some(Class::Rt) => {
+                    if cpu_scheduler.rt.account(elapsed_us * 1000) {
+                        cpu_scheduler.need_resched = true;
+                    }
+                    let round_robin = self.with_process(current_pid, |process| {
+                        process.policy == SchedPolicy::RoundRobin
+                    }).unwrap_or(false);
+                    if round_robin {
+                        if cpu_scheduler.time_slice_remaining > elapsed_us {
+                            cpu_scheduler.time_slice_remaining -= elapsed_us;
+                        } else {
+                            cpu_scheduler.time_slice_remaining = 0;
+                            cpu_scheduler.need_resched = true;
                         }
                     }
                 }
-            });
-
-            // Decrement time slice with precision
-            if cpu_scheduler.time_slice_remaining > elapsed_us {
-                cpu_scheduler.time_slice_remaining -= elapsed_us;
-            } else {
-                cpu_scheduler.time_slice_remaining = 0;
+                None => {}
             }
 
-            // Check for preemption by higher priority processes
-            if self.should_preempt_process(current_pid, cpu_id, &cpu_scheduler) {
-                cpu_scheduler.time_slice_remaining = 0; // Force preemption
+            // Check for preemption by a higher class or priority
+            if self.should_preempt_process(current_pid, &cpu_scheduler) {
+                cpu_scheduler.need_resched = true;
             }
         } else {
             // CPU is idle
             cpu_scheduler.idle_time += elapsed_us;
+            if cpu_scheduler.ready_process_count() > 0 {
+                cpu_scheduler.need_resched = true;
+            }
         }
 
         // Update CPU utilization with exponential moving average
         let active_time = if cpu_scheduler.current_process.is_some() { elapsed_us } else { 0 };
         let utilization_sample = if elapsed_us > 0 { (active_time * 100) / elapsed_us } else { 0 };
-        
+
         // Smooth utilization calculation
         let old_utilization = cpu_scheduler.utilization as u64;
         let new_utilization = ((old_utilization * 7) + utilization_sample) / 8; // 7/8 old + 1/8 new
         cpu_scheduler.utilization = new_utilization.min(100) as u8;
 
         // Periodic load balancing (every 100ms)
-        if current_time % 100_000 == 0 && self.load_balancing_enabled {
+        if get_system_time() % 100_000 == 0 && self.load_balancing_enabled.load(Ordering::Relaxed) {
             drop(cpu_scheduler); // Release lock before load balancing
             self.periodic_load_balance();
         }
@@ -759,9 +1102,13 @@ impl GlobalScheduler {
     /// Periodic load balancing across all CPUs
     fn periodic_load_balance(&self) {
         let mut cpu_loads = Vec::new();
-        
+        let online = ONLINE_CPUS.load(Ordering::Acquire);
+
         // Collect load information from all CPUs
         for (cpu_id, cpu_scheduler_mutex) in self.cpu_schedulers.iter().enumerate() {
+            if cpu_id >= 64 || online & (1 << cpu_id) == 0 {
+                continue;
+            }
             if let Some(cpu_scheduler) = cpu_scheduler_mutex.try_lock() {
                 cpu_loads.push((cpu_id, cpu_scheduler.ready_process_count(), cpu_scheduler.utilization));
             }
@@ -789,103 +1136,92 @@ impl GlobalScheduler {
         }
 
         // Try to acquire locks on both schedulers
-        if let (Some(mut source_scheduler), Some(mut target_scheduler)) = (
+        let wake_target = if let (Some(mut source_scheduler), Some(mut target_scheduler)) = (
             self.cpu_schedulers[source_cpu].try_lock(),
             self.cpu_schedulers[target_cpu].try_lock()
         ) {
-            // Move one process from source to target (prefer lower priority)
-            for priority_idx in (1..Priority::count()).rev() { // Skip RealTime
-                if let Some(migrated_pid) = source_scheduler.ready_queues[priority_idx].pop_back() {
-                    // Check CPU affinity
-                    let can_migrate = self.with_process(migrated_pid, |process| {
-                        process.can_run_on_cpu(target_cpu as CpuId)
-                    }).unwrap_or(false);
-
-                    if can_migrate {
-                        let priority = match priority_idx {
-                            1 => Priority::High,
-                            2 => Priority::Normal,
-                            3 => Priority::Low,
-                            4 => Priority::Idle,
-                            _ => Priority::Normal,
-                        };
-                        target_scheduler.enqueue_process(migrated_pid, priority);
-                        break;
-                    } else {
-                        // Put it back if it can't migrate
-                        source_scheduler.ready_queues[priority_idx].push_back(migrated_pid);
-                    }
-                }
+            // Move one fair-class process from source to target
+            let idle = target_scheduler.current_process.is_none();
+            let moved = self.migrate_fair(&mut source_scheduler, &mut target_scheduler);
+            if moved && idle {
+                target_scheduler.need_resched = true;
             }
-        }
-    }
-
-    /// Find the least loaded CPU
-    fn find_least_loaded_cpu(&self) -> CpuId {
-        let mut min_load = usize::MAX;
-        let mut best_cpu = 0;
+            moved && idle
+        } else {
+            false
+        };
 
-        for (cpu_id, cpu_scheduler_mutex) in self.cpu_schedulers.iter().enumerate() {
-            let cpu_scheduler = cpu_scheduler_mutex.lock();
-            let load = cpu_scheduler.ready_process_count();
-            if load < min_load {
-                min_load = load;
-                best_cpu = cpu_id;
-            }
+        // An idle target may have stopped its tick and would not notice
+        if wake_target {
+            self.kick(target_cpu as CpuId);
         }
+    }
 
-        best_cpu as CpuId
+    /// Whether `cpu_id` has nothing its tick would act on: no process queued
+    /// and no deadline process waiting for its next period
+    fn can_stop_tick(&self, cpu_id: CpuId) -> bool {
+        self.cpu_schedulers.get(cpu_id as usize).map_or(false, |cpu_scheduler| {
+            let cpu_scheduler = cpu_scheduler.lock();
+            !cpu_scheduler.need_resched
+                && cpu_scheduler.ready_process_count() == 0
+                && !cpu_scheduler.dl.has_throttled()
+        })
     }
 
     /// Find a process by PID and execute an operation on it
     fn with_process_mut<F, R>(&self, pid: Pid, f: F) -> Option<R>
-    where 
+    where
         F: FnOnce(&mut Process) -> R,
     {
-        // Get mutable access to the processes vector
-        let mut processes = self.processes.write();
-        processes.iter_mut()
-            .find(|p| p.pid == pid)
-            .map(|process| f(process))
+        // The timer interrupt takes this lock too
+        without_interrupts(|| {
+            let mut processes = self.processes.write();
+            processes.iter_mut()
+                .find(|p| p.pid == pid)
+                .map(|process| f(process))
+        })
     }
 
     /// Find a process by PID and execute a read-only operation on it
     fn with_process<F, R>(&self, pid: Pid, f: F) -> Option<R>
-    where 
+    where
         F: FnOnce(&Process) -> R,
     {
-        // Get read access to the processes vector
-        let processes = self.processes.read();
-        processes.iter()
-            .find(|p| p.pid == pid)
-            .map(|process| f(process))
+        without_interrupts(|| {
+            let processes = self.processes.read();
+            processes.iter()
+                .find(|p| p.pid == pid)
+                .map(|process| f(process))
+        })
     }
 
     /// Get scheduler statistics
     pub fn get_stats(&self) -> SchedulerStats {
-        let processes = self.processes.read();
-        let process_count = processes.len();
-        
-        let mut stats_by_state = [0usize; 6];
-        let mut stats_by_priority = [0usize; 5];
-        
-        for process in processes.iter() {
-            let state_idx = match process.state {
-                ProcessState::Ready => 0,
-                ProcessState::Running => 1,
-                ProcessState::Blocked => 2,
-                ProcessState::Sleeping => 3,
-                ProcessState::Terminated => 4,
-                ProcessState::Creating => 5,
-            };
-            stats_by_state[state_idx] += 1;
-            stats_by_priority[process.priority as usize] += 1;
-        }
+        let (process_count, stats_by_state, stats_by_priority) = without_interrupts(|| {
+            let processes = self.processes.read();
+
+            let mut stats_by_state = [0usize; 5];
+            let mut stats_by_priority = [0usize; 5];
+
+            for process in processes.iter() {
+                let state_idx = match process.state {
+                    ProcessState::Ready => 0,
+                    ProcessState::Running => 1,
+                    ProcessState::Blocked => 2,
+                    ProcessState::Sleeping => 3,
+                    ProcessState::Terminated | ProcessState::Zombie | ProcessState::Dead => 4,
+                };
+                stats_by_state[state_idx] += 1;
+                stats_by_priority[process.priority as usize] += 1;
+            }
+
+            (processes.len(), stats_by_state, stats_by_priority)
+        });
 
         let mut cpu_utilizations = Vec::new();
         for cpu_scheduler_mutex in &self.cpu_schedulers {
-            let cpu_scheduler = cpu_scheduler_mutex.lock();
-            cpu_utilizations.push(cpu_scheduler.utilization);
+            let utilization = without_interrupts(|| cpu_scheduler_mutex.lock().utilization);
+            cpu_utilizations.push(utilization);
         }
 
         SchedulerStats {
@@ -895,7 +1231,6 @@ impl GlobalScheduler {
             blocked_processes: stats_by_state[2],
             sleeping_processes: stats_by_state[3],
             terminated_processes: stats_by_state[4],
-            creating_processes: stats_by_state[5],
             realtime_processes: stats_by_priority[0],
             high_priority_processes: stats_by_priority[1],
             normal_priority_processes: stats_by_priority[2],
@@ -916,7 +1251,6 @@ pub struct SchedulerStats {
     pub blocked_processes: usize,
     pub sleeping_processes: usize,
     pub terminated_processes: usize,
-    pub creating_processes: usize,
     pub realtime_processes: usize,
     pub high_priority_processes: usize,
     pub normal_priority_processes: usize,
@@ -939,20 +1273,52 @@ lazy_static! {
     };
 }
 
+/// Set once `init` has run; until then no CPU switches
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// CPUs that take processes, one bit each; the boot CPU always does
+///
+/// Application processors join once their local timer runs, which may be
+/// before `init`.
+static ONLINE_CPUS: AtomicU64 = AtomicU64::new(1);
+
+/// Number of CPUs taking part in scheduling
+fn online_cpu_count() -> u32 {
+    ONLINE_CPUS.load(Ordering::Acquire).count_ones()
+}
+
+fn is_initialized() -> bool {
+    INITIALIZED.load(Ordering::Acquire)
+}
+
 /// Initialize the scheduler subsystem
+///
+/// The flow calling this becomes the boot CPU's idle context: it runs
+/// whenever that CPU has no process to run, and hands the CPU to processes
+/// when it calls `schedule` or enters `idle_loop`.
 pub fn init() -> Result<(), &'static str> {
     // Force initialization of the global scheduler
     lazy_static::initialize(&GLOBAL_SCHEDULER);
-    
-    // Create init process (PID 1)
-    GLOBAL_SCHEDULER.create_process(None, Priority::High, "init")?;
+    INITIALIZED.store(true, Ordering::Release);
 
     Ok(())
 }
 
+/// Let `cpu_id` take processes
+pub fn cpu_online(cpu_id: CpuId) {
+    if cpu_id < 64 {
+        ONLINE_CPUS.fetch_or(1 << cpu_id, Ordering::AcqRel);
+    }
+}
+
 /// Create a new process
 pub fn create_process(parent_pid: Option<Pid>, priority: Priority, name: &str) -> Result<Pid, &'static str> {
-    GLOBAL_SCHEDULER.create_process(parent_pid, priority, name)
+    crate::process::get_process_manager().create_process(name, parent_pid, priority)
+}
+
+/// Start scheduling a process; called by `crate::process` when creating one
+pub fn add_process(pid: Pid, parent_pid: Option<Pid>, priority: Priority, name: &str) -> Result<(), &'static str> {
+    GLOBAL_SCHEDULER.add_process(pid, parent_pid, priority, name)
 }
 
 /// Terminate a process
@@ -960,66 +1326,247 @@ pub fn terminate_process(pid: Pid) -> Result<(), &'static str> {
     GLOBAL_SCHEDULER.terminate_process(pid)
 }
 
+/// Free a terminated process once its exit status is collected
+pub fn remove_process(pid: Pid) {
+    GLOBAL_SCHEDULER.remove_process(pid)
+}
+
 /// Block a process
 pub fn block_process(pid: Pid) -> Result<(), &'static str> {
     GLOBAL_SCHEDULER.block_process(pid)
 }
 
+/// Block the calling process until `unblock_process` wakes it
+///
+/// Returns straight away if it was woken since it last blocked, so callers
+/// recheck what they wait for and block again if need be. Fails when no
+/// process is running, as in the kernel's own flow, which cannot block.
+pub fn block_current() -> Result<(), &'static str> {
+    if !is_initialized() {
+        return Err("Scheduler not initialized");
+    }
+    without_interrupts(|| GLOBAL_SCHEDULER.block_current(get_current_cpu_id()))
+}
+
 /// Unblock a process
 pub fn unblock_process(pid: Pid) -> Result<(), &'static str> {
     GLOBAL_SCHEDULER.unblock_process(pid)
 }
 
 /// Change process priority
+///
+/// `Priority::RealTime` makes a fair-class process SCHED_RR at
+/// `rt::DEFAULT_RT_PRIO` and leaves real-time ones as they are. Any other
+/// priority returns a real-time process to SCHED_NORMAL and resets the nice
+/// value to the one it stands for.
 pub fn set_process_priority(pid: Pid, new_priority: Priority) -> Result<(), &'static str> {
-    // Remove from current queue
-    let old_priority = GLOBAL_SCHEDULER.with_process(pid, |p| p.priority).ok_or("Process not found")?;
-    
-    // Update priority
-    GLOBAL_SCHEDULER.with_process_mut(pid, |process| {
-        process.priority = new_priority;
-    }).ok_or("Process not found")?;
-
-    // Move between queues if process is ready
-    let process_state = GLOBAL_SCHEDULER.with_process(pid, |p| p.state).unwrap_or(ProcessState::Terminated);
-    
-    if process_state == ProcessState::Ready {
-        // Remove from old priority queue
-        for cpu_scheduler_mutex in &GLOBAL_SCHEDULER.cpu_schedulers {
-            if let Some(mut cpu_scheduler) = cpu_scheduler_mutex.try_lock() {
-                if cpu_scheduler.ready_queues[old_priority as usize].iter().position(|&p| p == pid).is_some() {
-                    cpu_scheduler.ready_queues[old_priority as usize].retain(|&p| p != pid);
-                    cpu_scheduler.enqueue_process(pid, new_priority);
-                    break;
-                }
-            }
+    let policy = get_sched_policy(pid).ok_or("Process not found")?;
+    match (new_priority, policy.is_realtime()) {
+        (Priority::RealTime, true) => Ok(()),
+        (Priority::RealTime, false) => set_scheduler(pid, SchedPolicy::RoundRobin, rt::DEFAULT_RT_PRIO, None),
+        (_, true) => {
+            set_scheduler(pid, SchedPolicy::Normal, 0, None)?;
+            set_nice(pid, new_priority.nice())
         }
+        (_, false) => set_nice(pid, new_priority.nice()),
     }
+}
 
-    Ok(())
+/// Set the scheduling policy of a process, as sched_setscheduler(2) does
+///
+/// `rt_priority` must be 1 to 99 for SCHED_FIFO and SCHED_RR and 0 for the
+/// others. SCHED_DEADLINE takes `params` and is refused once the admitted
+/// reservations would exceed `deadline::BW_LIMIT_PERCENT` of the online
+/// CPUs.
+pub fn set_scheduler(
+    pid: Pid,
+    policy: SchedPolicy,
+    rt_priority: u8,
+    params: Option<DeadlineParams>,
+) -> Result<(), &'static str> {
+    let valid_priority = match policy {
+        SchedPolicy::Fifo | SchedPolicy::RoundRobin => (rt::MIN_RT_PRIO..=rt::MAX_RT_PRIO).contains(&rt_priority),
+        _ => rt_priority == 0,
+    };
+    if !valid_priority {
+        return Err("Invalid real-time priority for policy");
+    }
+    let params = match (policy, params) {
+        (SchedPolicy::Deadline, Some(params)) => Some(params),
+        (SchedPolicy::Deadline, None) => return Err("SCHED_DEADLINE needs deadline parameters"),
+        _ => None,
+    };
+
+    let old = GLOBAL_SCHEDULER
+        .with_process(pid, |process| (process.policy == SchedPolicy::Deadline).then_some(process.dl.params))
+        .ok_or("Process not found")?;
+    if old.is_some() || params.is_some() {
+        deadline::admit(old.as_ref(), params.as_ref(), online_cpu_count())?;
+    }
+
+    let result = GLOBAL_SCHEDULER.change_params(pid, |process| {
+        process.policy = policy;
+        process.rt_priority = rt_priority;
+        if old != params {
+            process.dl = params.map(DeadlineEntity::new).unwrap_or_default();
+        }
+        process.fair.set_policy(policy == SchedPolicy::Batch, policy == SchedPolicy::Idle);
+        process.priority = if policy.is_realtime() {
+            Priority::RealTime
+        } else {
+            Priority::from_nice(process.fair.nice)
+        };
+    });
+    if result.is_err() && (old.is_some() || params.is_some()) {
+        let _ = deadline::admit(params.as_ref(), old.as_ref(), online_cpu_count());
+    }
+    result
+}
+
+/// Get the scheduling policy of a process
+pub fn get_sched_policy(pid: Pid) -> Option<SchedPolicy> {
+    GLOBAL_SCHEDULER.with_process(pid, |process| process.policy)
+}
+
+/// Get the real-time priority of a process, 0 outside SCHED_FIFO and SCHED_RR
+pub fn get_rt_priority(pid: Pid) -> Option<u8> {
+    GLOBAL_SCHEDULER.with_process(pid, |process| process.rt_priority)
+}
+
+/// Get the reservation of a SCHED_DEADLINE process
+pub fn get_deadline_params(pid: Pid) -> Option<DeadlineParams> {
+    GLOBAL_SCHEDULER
+        .with_process(pid, |process| (process.policy == SchedPolicy::Deadline).then_some(process.dl.params))
+        .flatten()
+}
+
+/// Length of the turns a process gets, as sched_rr_get_interval(2) reports
+///
+/// Zero for SCHED_FIFO, which runs until it gives up the CPU.
+pub fn rr_interval_ns(pid: Pid) -> Option<u64> {
+    GLOBAL_SCHEDULER.with_process(pid, |process| match process.policy {
+        SchedPolicy::RoundRobin => rt::RR_TIMESLICE_NS,
+        SchedPolicy::Fifo => 0,
+        SchedPolicy::Deadline => process.dl.params.runtime_ns,
+        SchedPolicy::Normal | SchedPolicy::Batch | SchedPolicy::Idle => fair::BASE_SLICE_NS,
+    })
+}
+
+/// Set the nice value of a process, -20 (most CPU) to 19 (least)
+///
+/// Fair-class processes also take the coarse priority matching it.
+pub fn set_nice(pid: Pid, nice: i8) -> Result<(), &'static str> {
+    if !(fair::MIN_NICE..=fair::MAX_NICE).contains(&nice) {
+        return Err("Nice value out of range");
+    }
+    GLOBAL_SCHEDULER.change_params(pid, |process| {
+        process.fair.set_nice(nice);
+        if !process.is_realtime() {
+            process.priority = Priority::from_nice(nice);
+        }
+    })
+}
+
+/// Get the nice value of a process
+pub fn get_nice(pid: Pid) -> Option<i8> {
+    GLOBAL_SCHEDULER.with_process(pid, |process| process.fair.nice)
+}
+
+/// Move a process to a fair-class group; see `fair::set_group_shares`
+pub fn set_process_group(pid: Pid, group: fair::GroupId) -> Result<(), &'static str> {
+    GLOBAL_SCHEDULER.change_params(pid, |process| process.fair.group = group)
+}
+
+/// Get the fair-class group of a process
+pub fn get_process_group(pid: Pid) -> Option<fair::GroupId> {
+    GLOBAL_SCHEDULER.with_process(pid, |process| process.fair.group)
+}
+
+/// Get the priority the scheduler currently gives a process
+pub fn get_process_priority(pid: Pid) -> Option<Priority> {
+    GLOBAL_SCHEDULER.with_process(pid, |process| process.priority)
 }
 
 /// Schedule the next process on the current CPU
+///
+/// Returns the process running once the caller gets the CPU back, which is
+/// at once if nothing else is ready. A caller that is no longer runnable
+/// only gets it back when woken; a terminated one never does.
 pub fn schedule() -> Option<Pid> {
+    if !is_initialized() {
+        return None;
+    }
+
+    without_interrupts(|| {
+        GLOBAL_SCHEDULER.switch(get_current_cpu_id());
+    });
+
+    match current_pid() {
+        0 => None,
+        pid => Some(pid),
+    }
+}
+
+/// Switch away from the process the timer interrupted if its time is up
+///
+/// Called by the timer interrupt handler once the interrupt is acknowledged.
+/// Only user mode is preempted: kernel code gives up the CPU where it blocks
+/// or yields, so it is never switched out while holding a lock.
+pub fn preempt(from_user: bool) {
+    if !from_user || !is_initialized() {
+        return;
+    }
+
     let cpu_id = get_current_cpu_id();
-    let next_pid = GLOBAL_SCHEDULER.schedule(cpu_id);
-    
-    // Update thread manager with the main thread of the scheduled process
-    // For simplicity, we assume each process has a main thread with TID = PID
-    if let Some(pid) = next_pid {
-        let thread_manager = crate::process::thread::get_thread_manager();
-        thread_manager.set_current_thread(pid);
-    }
-    
-    next_pid
+    let need_resched = GLOBAL_SCHEDULER.cpu_schedulers.get(cpu_id as usize)
+        .and_then(|cpu_scheduler| cpu_scheduler.try_lock())
+        .map_or(false, |cpu_scheduler| cpu_scheduler.need_resched);
+
+    if need_resched {
+        GLOBAL_SCHEDULER.switch(cpu_id);
+    }
 }
 
 /// Handle timer tick for scheduling
 pub fn timer_tick(elapsed_us: u64) {
+    if !is_initialized() {
+        return;
+    }
+
     let cpu_id = get_current_cpu_id();
+    let pid = current_pid();
+    if pid != 0 {
+        crate::process::timers::cpu_tick(pid);
+    }
     GLOBAL_SCHEDULER.timer_tick(cpu_id, elapsed_us);
 }
 
+/// Process running on the current CPU, or 0 for the kernel's own flow
+pub fn current_pid() -> Pid {
+    if !is_initialized() {
+        return 0;
+    }
+
+    let cpu_id = get_current_cpu_id();
+    GLOBAL_SCHEDULER.running.get(cpu_id as usize).map_or(0, |pid| pid.load(Ordering::Relaxed))
+}
+
+/// Scheduling state of a process
+pub fn process_state(pid: Pid) -> Option<ProcessState> {
+    GLOBAL_SCHEDULER.with_process(pid, |process| process.state)
+}
+
+/// CPU time a process has used in nanoseconds, including its current slice
+pub fn cpu_time_ns(pid: Pid) -> Option<u64> {
+    GLOBAL_SCHEDULER.with_process(pid, |process| {
+        if process.state == ProcessState::Running {
+            process.cpu_time_used + crate::time::uptime_ns().saturating_sub(process.last_scheduled)
+        } else {
+            process.cpu_time_used
+        }
+    })
+}
+
 /// Get scheduler statistics
 pub fn get_scheduler_stats() -> SchedulerStats {
     GLOBAL_SCHEDULER.get_stats()
@@ -1035,162 +1582,73 @@ fn get_system_time() -> u64 {
     crate::time::uptime_us()
 }
 
-/// Context switch between processes (real assembly implementation)
-#[unsafe(naked)]
-pub unsafe extern "C" fn context_switch(old_state: *mut CpuState, new_state: *const CpuState) {
-    use core::arch::naked_asm;
-    
-    naked_asm!(
-        r#"
-        // Save current CPU state to old_state (RDI)
-        // General purpose registers
-        mov [rdi + 0x00], rax
-        mov [rdi + 0x08], rbx
-        mov [rdi + 0x10], rcx
-        mov [rdi + 0x18], rdx
-        mov [rdi + 0x20], rsi
-        mov [rdi + 0x28], rdi
-        mov [rdi + 0x30], rbp
-        mov [rdi + 0x38], rsp
-        mov [rdi + 0x40], r8
-        mov [rdi + 0x48], r9
-        mov [rdi + 0x50], r10
-        mov [rdi + 0x58], r11
-        mov [rdi + 0x60], r12
-        mov [rdi + 0x68], r13
-        mov [rdi + 0x70], r14
-        mov [rdi + 0x78], r15
-
-        // Save RIP (return address from stack)
-        mov rax, [rsp]
-        mov [rdi + 0x80], rax
-
-        // Save RFLAGS
-        pushf
-        pop rax
-        mov [rdi + 0x88], rax
-
-        // Save segment registers
-        mov ax, cs
-        mov [rdi + 0x90], rax
-        mov ax, ss
-        mov [rdi + 0x98], rax
-        mov ax, ds
-        mov [rdi + 0xA0], rax
-        mov ax, es
-        mov [rdi + 0xA8], rax
-        mov ax, fs
-        mov [rdi + 0xB0], rax
-        mov ax, gs
-        mov [rdi + 0xB8], rax
-
-        // Load new CPU state from new_state (RSI)
-        // Restore general purpose registers
-        mov rax, [rsi + 0x00]
-        mov rbx, [rsi + 0x08]
-        mov rcx, [rsi + 0x10]
-        mov rdx, [rsi + 0x18]
-        mov rbp, [rsi + 0x30]
-        mov rsp, [rsi + 0x38]
-        mov r8,  [rsi + 0x40]
-        mov r9,  [rsi + 0x48]
-        mov r10, [rsi + 0x50]
-        mov r11, [rsi + 0x58]
-        mov r12, [rsi + 0x60]
-        mov r13, [rsi + 0x68]
-        mov r14, [rsi + 0x70]
-        mov r15, [rsi + 0x78]
-
-        // Restore RFLAGS
-        push qword ptr [rsi + 0x88]
-        popf
-
-        // Restore segment registers (data segments only, CS/SS handled by iret)
-        mov ax, [rsi + 0xA0]
-        mov ds, ax
-        mov ax, [rsi + 0xA8]
-        mov es, ax
-        mov ax, [rsi + 0xB0]
-        mov fs, ax
-        mov ax, [rsi + 0xB8]
-        mov gs, ax
-
-        // Push return address and jump to new process
-        push qword ptr [rsi + 0x80]
-
-        // Restore RSI and RDI last
-        mov rdi, [rsi + 0x28]
-        mov rsi, [rsi + 0x20]
-
-        // Return to new process
-        ret
-        "#
-    );
-}
+/// Allocate a kernel stack, with an unmapped guard page below it so an
+/// overflow faults instead of corrupting the memory beneath
+fn allocate_kernel_stack() -> Result<u64, &'static str> {
+    use crate::memory::{get_memory_manager, MemoryRegionType, MemoryProtection};
 
-/// Save FPU/SSE state
-pub unsafe fn save_fpu_state(fpu_state: *mut FpuState) {
-    use core::arch::asm;
-    
-    // Check if SSE is supported (assume it is for modern x86_64)
-    asm!(
-        "fxsave [{}]",
-        in(reg) fpu_state,
-        options(nostack, preserves_flags)
-    );
-}
+    let memory_manager = get_memory_manager().ok_or("Memory manager not initialized")?;
+    let region = memory_manager.allocate_region_with_guards(
+        KERNEL_STACK_SIZE,
+        MemoryRegionType::KernelStack,
+        MemoryProtection::KERNEL_DATA,
+    ).map_err(|_| "Failed to allocate kernel stack")?;
 
-/// Restore FPU/SSE state
-pub unsafe fn restore_fpu_state(fpu_state: *const FpuState) {
-    use core::arch::asm;
-    
-    // Check if SSE is supported (assume it is for modern x86_64)
-    asm!(
-        "fxrstor [{}]",
-        in(reg) fpu_state,
-        options(nostack, preserves_flags)
-    );
+    Ok(region.start.as_u64())
 }
 
-/// Initialize FPU for the current CPU
-pub unsafe fn init_fpu() {
-    use core::arch::asm;
-    
-    // Initialize FPU
-    asm!("finit", options(nostack, preserves_flags));
-    
-    // Enable SSE and FXSAVE/FXRSTOR
-    let mut cr4: u64;
-    asm!("mov {}, cr4", out(reg) cr4, options(nostack, preserves_flags));
-    cr4 |= (1 << 9) | (1 << 10); // OSFXSR and OSXMMEXCPT
-    asm!("mov cr4, {}", in(reg) cr4, options(nostack, preserves_flags));
-    
-    // Clear task switched flag
-    asm!("clts", options(nostack, preserves_flags));
+fn free_kernel_stack(base: u64) {
+    if let Err(e) = crate::memory::deallocate_memory(x86_64::VirtAddr::new(base)) {
+        crate::serial_println!("Failed to free kernel stack at {:#x}: {:?}", base, e);
+    }
 }
 
-/// Complete context switch with FPU state
-pub unsafe fn context_switch_with_fpu(
-    old_cpu_state: *mut CpuState,
-    old_fpu_state: *mut FpuState,
-    new_cpu_state: *const CpuState,
-    new_fpu_state: *const FpuState,
-) {
-    // Save current FPU state
-    save_fpu_state(old_fpu_state);
-    
-    // Perform CPU context switch
-    context_switch(old_cpu_state, new_cpu_state);
-    
-    // Restore new FPU state
-    restore_fpu_state(new_fpu_state);
+/// First code a process runs, on its own kernel stack
+///
+/// Enters user mode at the entry point and stack `crate::process` set up.
+/// A process with no program to run exits straight away.
+extern "C" fn process_entry() -> ! {
+    GLOBAL_SCHEDULER.finish_switch(get_current_cpu_id());
+
+    let pid = current_pid();
+    let process_manager = crate::process::get_process_manager();
+    let (entry_point, user_stack) = process_manager.get_process(pid)
+        .map(|pcb| (pcb.context.rip, pcb.context.rsp))
+        .unwrap_or((0, 0));
+
+    if entry_point != 0 {
+        unsafe { crate::usermode::switch_to_user_mode(entry_point, user_stack) }
+    }
+
+    let _ = process_manager.terminate_process(pid, 0);
+    loop {
+        // Never returns: the scheduler drops terminated processes
+        schedule();
+    }
 }
 
 /// Set CPU affinity for a process
+///
+/// The mask must allow at least one online CPU. A queued process moves to
+/// an allowed CPU straight away; a running one is switched out of a CPU it
+/// may no longer use and queued on another.
 pub fn set_process_affinity(pid: Pid, cpu_mask: u64) -> Result<(), &'static str> {
-    GLOBAL_SCHEDULER.with_process_mut(pid, |process| {
-        process.set_cpu_affinity(cpu_mask);
-    }).ok_or("Process not found")
+    if cpu_mask & ONLINE_CPUS.load(Ordering::Acquire) == 0 {
+        return Err("No online CPU in affinity mask");
+    }
+    GLOBAL_SCHEDULER.change_params(pid, |process| process.set_cpu_affinity(cpu_mask))?;
+
+    // A caller that moved itself off this CPU leaves it now
+    let cpu_id = get_current_cpu_id();
+    if pid == current_pid() && (cpu_id >= 64 || cpu_mask & (1 << cpu_id) == 0) {
+        schedule();
+    }
+    Ok(())
+}
+
+/// CPUs that currently take processes, one bit each
+pub fn online_cpu_mask() -> u64 {
+    ONLINE_CPUS.load(Ordering::Acquire)
 }
 
 /// Get CPU affinity for a process
@@ -1213,23 +1671,20 @@ pub fn migrate_process_to_cpu(pid: Pid, target_cpu: CpuId) -> Result<(), &'stati
         return Err("Process cannot migrate to target CPU");
     }
 
-    // Remove from current CPU's ready queue
-    let process_priority = GLOBAL_SCHEDULER.with_process(pid, |process| process.priority);
-    if let Some(priority) = process_priority {
-        for (cpu_id, cpu_scheduler_mutex) in GLOBAL_SCHEDULER.cpu_schedulers.iter().enumerate() {
-            if let Some(mut cpu_scheduler) = cpu_scheduler_mutex.try_lock() {
-                if cpu_scheduler.ready_queues[priority as usize].iter().position(|&p| p == pid).is_some() {
-                    cpu_scheduler.ready_queues[priority as usize].retain(|&p| p != pid);
-                    break;
+    // Take it off whichever queue holds it, keeping its lag, and place it
+    // on the target's
+    without_interrupts(|| {
+        for cpu_scheduler_mutex in &GLOBAL_SCHEDULER.cpu_schedulers {
+            let mut cpu_scheduler = cpu_scheduler_mutex.lock();
+            if cpu_scheduler.fair.contains(pid) {
+                if let Some(process) = GLOBAL_SCHEDULER.processes.write().iter_mut().find(|p| p.pid == pid) {
+                    cpu_scheduler.fair.save_lag(&mut process.fair);
                 }
             }
+            cpu_scheduler.dequeue_process(pid);
         }
-
-        // Add to target CPU's ready queue
-        if let Some(mut target_scheduler) = GLOBAL_SCHEDULER.cpu_schedulers[target_cpu as usize].try_lock() {
-            target_scheduler.enqueue_process(pid, priority);
-        }
-    }
+    });
+    GLOBAL_SCHEDULER.enqueue_on(target_cpu, pid, false);
 
     Ok(())
 }
@@ -1247,22 +1702,65 @@ pub fn get_cpu_loads() -> Vec<(CpuId, usize, u8)> {
             ));
         }
     }
-    
+
     loads
 }
 
 /// Enable or disable load balancing
 pub fn set_load_balancing(enabled: bool) {
-    // This would require making load_balancing_enabled mutable
-    // For now, it's a compile-time setting
+    GLOBAL_SCHEDULER.load_balancing_enabled.store(enabled, Ordering::Relaxed);
 }
 
 /// Yield CPU time to allow other processes to run
+///
+/// A real-time process goes to the back of its priority level; a deadline
+/// process gives up what is left of its runtime until its next period.
 pub fn yield_cpu() {
-    // Trigger a reschedule by setting time slice to 0
-    let cpu_id = get_current_cpu_id();
-    if let Some(mut cpu_scheduler) = GLOBAL_SCHEDULER.cpu_schedulers[cpu_id as usize].try_lock() {
-        cpu_scheduler.time_slice_remaining = 0;
+    if !is_initialized() {
+        return;
+    }
+    without_interrupts(|| {
+        if let Some(cpu_scheduler) = GLOBAL_SCHEDULER.cpu_schedulers.get(get_current_cpu_id() as usize) {
+            cpu_scheduler.lock().yielding = true;
+        }
+    });
+    schedule();
+}
+
+/// Idle loop of a CPU with nothing to run
+///
+/// Runs ready processes once the CPU is online, and otherwise halts until
+/// the next interrupt, charging the time spent halted to the CPU's idle
+/// time. With nothing queued the tick stops for the halt, since only a
+/// timer or another CPU can bring work then. Application processors enter
+/// it once they are up.
+pub fn idle_loop() -> ! {
+    loop {
+        x86_64::instructions::interrupts::disable();
+
+        let cpu_id = get_current_cpu_id();
+        let online = cpu_id < 64 && ONLINE_CPUS.load(Ordering::Acquire) & (1 << cpu_id) != 0;
+        let has_work = is_initialized() && online && GLOBAL_SCHEDULER.cpu_schedulers.get(cpu_id as usize)
+            .map_or(false, |cpu_scheduler| cpu_scheduler.lock().need_resched);
+        if has_work {
+            GLOBAL_SCHEDULER.switch(cpu_id);
+            continue;
+        }
+
+        let tickless = is_initialized() && online
+            && GLOBAL_SCHEDULER.can_stop_tick(cpu_id)
+            && crate::hrtimer::stop_tick();
+
+        // Enabling interrupts and halting is atomic, so a wakeup's tick
+        // cannot slip in between
+        let start = crate::time::uptime_ns();
+        x86_64::instructions::interrupts::enable_and_hlt();
+        crate::smp::account_idle(crate::time::uptime_ns().saturating_sub(start));
+
+        if tickless {
+            x86_64::instructions::interrupts::disable();
+            crate::hrtimer::restart_tick();
+        }
     }
 }
 
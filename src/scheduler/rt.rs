@@ -0,0 +1,289 @@
+//! Real-Time Scheduling Class
+//!
+//! SCHED_FIFO and SCHED_RR processes run ahead of the fair class in strict
+//! priority order, 99 the highest and 1 the lowest. FIFO processes keep the
+//! CPU until they block, yield or are preempted by a higher priority; RR
+//! processes of equal priority also take turns every `RR_TIMESLICE_NS`.
+//!
+//! RT throttling bounds how much of each period a CPU spends on this class,
+//! `RT_RUNTIME_NS` of `RT_PERIOD_NS` by default, so a runaway real-time loop
+//! cannot lock everything else out. Once a CPU is throttled its real-time
+//! processes only run if nothing else is ready until the period ends.
+
+use alloc::collections::VecDeque;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::Pid;
+
+/// Highest real-time priority
+pub const MAX_RT_PRIO: u8 = 99;
+
+/// Lowest real-time priority
+pub const MIN_RT_PRIO: u8 = 1;
+
+/// Real-time priority given to processes made real-time through the coarse
+/// `Priority::RealTime`
+pub const DEFAULT_RT_PRIO: u8 = 50;
+
+/// Turn length of SCHED_RR processes
+pub const RR_TIMESLICE_NS: u64 = 100_000_000;
+
+/// Default RT throttling period
+pub const RT_PERIOD_NS: u64 = 1_000_000_000;
+
+/// Default real-time runtime allowed per period
+pub const RT_RUNTIME_NS: u64 = 950_000_000;
+
+/// Runtime allowed per period, `u64::MAX` for no throttling
+static RT_RUNTIME: AtomicU64 = AtomicU64::new(RT_RUNTIME_NS);
+
+/// Length of a throttling period
+static RT_PERIOD: AtomicU64 = AtomicU64::new(RT_PERIOD_NS);
+
+/// Set how much of each period a CPU may spend on real-time processes
+///
+/// `None` turns throttling off.
+pub fn set_bandwidth(runtime_ns: Option<u64>, period_ns: u64) -> Result<(), &'static str> {
+    if period_ns == 0 {
+        return Err("RT period must not be zero");
+    }
+    let runtime_ns = runtime_ns.unwrap_or(u64::MAX);
+    if runtime_ns != u64::MAX && runtime_ns > period_ns {
+        return Err("RT runtime exceeds its period");
+    }
+    RT_PERIOD.store(period_ns, Ordering::Relaxed);
+    RT_RUNTIME.store(runtime_ns, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Current (runtime, period); runtime is `None` when throttling is off
+pub fn bandwidth() -> (Option<u64>, u64) {
+    let runtime = RT_RUNTIME.load(Ordering::Relaxed);
+    ((runtime != u64::MAX).then_some(runtime), RT_PERIOD.load(Ordering::Relaxed))
+}
+
+/// Per-CPU run queue of the real-time class
+#[derive(Debug)]
+pub struct RtRunQueue {
+    /// One queue per priority, indexed by priority
+    queues: [VecDeque<Pid>; MAX_RT_PRIO as usize + 1],
+    /// Bit `p` set while `queues[p]` is not empty
+    bitmap: u128,
+    /// Real-time runtime used in the current throttling period
+    rt_time: u64,
+    /// Start of the current throttling period
+    period_start: u64,
+    /// The CPU used up its real-time runtime for this period
+    throttled: bool,
+}
+
+impl RtRunQueue {
+    /// Create an empty run queue
+    pub fn new() -> Self {
+        Self {
+            queues: core::array::from_fn(|_| VecDeque::new()),
+            bitmap: 0,
+            rt_time: 0,
+            period_start: 0,
+            throttled: false,
+        }
+    }
+
+    /// Number of queued processes
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(|queue| queue.len()).sum()
+    }
+
+    /// Whether no process is queued
+    pub fn is_empty(&self) -> bool {
+        self.bitmap == 0
+    }
+
+    /// Whether `pid` is queued here
+    pub fn contains(&self, pid: Pid) -> bool {
+        self.queues.iter().any(|queue| queue.contains(&pid))
+    }
+
+    /// Highest priority with a queued process
+    pub fn highest_priority(&self) -> Option<u8> {
+        (self.bitmap != 0).then(|| (127 - self.bitmap.leading_zeros()) as u8)
+    }
+
+    /// Queue `pid` at `priority`, at the head to resume a preempted turn
+    pub fn enqueue(&mut self, pid: Pid, priority: u8, head: bool) {
+        self.remove(pid);
+        let priority = priority.clamp(MIN_RT_PRIO, MAX_RT_PRIO);
+        let queue = &mut self.queues[priority as usize];
+        if head {
+            queue.push_front(pid);
+        } else {
+            queue.push_back(pid);
+        }
+        self.bitmap |= 1 << priority;
+    }
+
+    /// Take `pid` off its queue; returns whether it was queued
+    pub fn remove(&mut self, pid: Pid) -> bool {
+        for priority in MIN_RT_PRIO..=MAX_RT_PRIO {
+            if self.bitmap & (1 << priority) == 0 {
+                continue;
+            }
+            let queue = &mut self.queues[priority as usize];
+            if let Some(index) = queue.iter().position(|&p| p == pid) {
+                queue.remove(index);
+                if queue.is_empty() {
+                    self.bitmap &= !(1 << priority);
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Take the first usable process of the highest priority off its queue
+    ///
+    /// `usable` works as for `FairRunQueue::pick`: `None` drops a stale
+    /// entry, `Some(false)` skips one that cannot run yet.
+    pub fn pick<F>(&mut self, mut usable: F) -> Option<Pid>
+    where
+        F: FnMut(Pid) -> Option<bool>,
+    {
+        for priority in (MIN_RT_PRIO..=MAX_RT_PRIO).rev() {
+            if self.bitmap & (1 << priority) == 0 {
+                continue;
+            }
+            let queue = &mut self.queues[priority as usize];
+            let mut picked = None;
+            let mut index = 0;
+            while index < queue.len() {
+                match usable(queue[index]) {
+                    None => {
+                        queue.remove(index);
+                    }
+                    Some(false) => index += 1,
+                    Some(true) => {
+                        picked = queue.remove(index);
+                        break;
+                    }
+                }
+            }
+            if queue.is_empty() {
+                self.bitmap &= !(1 << priority);
+            }
+            if picked.is_some() {
+                return picked;
+            }
+        }
+        None
+    }
+
+    /// Whether the CPU used up its real-time runtime for this period
+    pub fn is_throttled(&self) -> bool {
+        self.throttled
+    }
+
+    /// Charge `delta_ns` of real-time execution
+    ///
+    /// Returns true if that throttled the CPU.
+    pub fn account(&mut self, delta_ns: u64) -> bool {
+        let runtime = RT_RUNTIME.load(Ordering::Relaxed);
+        self.rt_time = self.rt_time.saturating_add(delta_ns);
+        if !self.throttled && runtime != u64::MAX && self.rt_time > runtime {
+            self.throttled = true;
+            return true;
+        }
+        false
+    }
+
+    /// Start a new throttling period if the current one is over
+    ///
+    /// Returns true if that lifted throttling.
+    pub fn refresh(&mut self, now: u64) -> bool {
+        let period = RT_PERIOD.load(Ordering::Relaxed);
+        if now.saturating_sub(self.period_start) < period {
+            return false;
+        }
+        self.period_start = now;
+        self.rt_time = 0;
+        core::mem::take(&mut self.throttled)
+    }
+}
+
+impl Default for RtRunQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitmap_tracks_priorities() {
+        let mut rq = RtRunQueue::new();
+        assert!(rq.is_empty());
+        assert_eq!(rq.highest_priority(), None);
+
+        rq.enqueue(1, 10, false);
+        rq.enqueue(2, MAX_RT_PRIO, false);
+        // Out-of-range priorities are clamped
+        rq.enqueue(3, 0, false);
+        assert_eq!(rq.highest_priority(), Some(MAX_RT_PRIO));
+        assert_eq!(rq.len(), 3);
+
+        assert!(rq.remove(2));
+        assert_eq!(rq.highest_priority(), Some(10));
+        // Requeueing moves a process rather than adding it twice
+        rq.enqueue(1, 20, false);
+        assert_eq!(rq.len(), 2);
+        assert_eq!(rq.highest_priority(), Some(20));
+        assert!(rq.remove(1));
+        assert_eq!(rq.highest_priority(), Some(MIN_RT_PRIO));
+        assert!(!rq.remove(1));
+    }
+
+    #[test]
+    fn test_pick_order() {
+        let mut rq = RtRunQueue::new();
+        rq.enqueue(1, 50, false);
+        rq.enqueue(2, 50, false);
+        rq.enqueue(3, 50, true);
+        rq.enqueue(4, 60, false);
+        rq.enqueue(5, 70, false);
+
+        // 5 is stale and dropped, 4 can't run yet and stays
+        let mut usable = |pid: Pid| match pid {
+            5 => None,
+            4 => Some(false),
+            _ => Some(true),
+        };
+        assert_eq!(rq.pick(&mut usable), Some(3));
+        assert_eq!(rq.pick(&mut usable), Some(1));
+        assert!(!rq.contains(5));
+        assert!(rq.contains(4));
+        assert_eq!(rq.highest_priority(), Some(60));
+    }
+
+    #[test]
+    fn test_throttling() {
+        let mut rq = RtRunQueue::new();
+        assert!(!rq.account(RT_RUNTIME_NS));
+        assert!(rq.account(1));
+        assert!(rq.is_throttled());
+        // Only the first charge over the limit reports throttling
+        assert!(!rq.account(1));
+
+        // The period has not ended yet
+        assert!(!rq.refresh(RT_PERIOD_NS - 1));
+        assert!(rq.refresh(RT_PERIOD_NS));
+        assert!(!rq.is_throttled());
+        assert!(!rq.account(RT_RUNTIME_NS));
+    }
+
+    #[test]
+    fn test_bandwidth_validation() {
+        assert!(set_bandwidth(Some(1), 0).is_err());
+        assert!(set_bandwidth(Some(2), 1).is_err());
+    }
+}
@@ -599,36 +599,90 @@ pub fn validate_process_isolation(source_pid: Pid, target_pid: Pid, operation: &
 }
 
 /// Sandboxing mechanism for process isolation
+///
+/// Restrictions on system calls are enforced by a seccomp filter, which the
+/// process and its descendants keep for good; denied calls fail with EPERM.
 pub fn create_sandbox(pid: Pid, restrictions: SandboxRestrictions) -> Result<(), &'static str> {
-    let mut contexts = SECURITY_CONTEXTS.write();
-    
-    if let Some(ctx) = contexts.get_mut(&pid) {
+    {
+        let mut contexts = SECURITY_CONTEXTS.write();
+        let ctx = contexts.get_mut(&pid).ok_or("Process context not found")?;
+
         // Apply sandbox restrictions
         if restrictions.disable_network {
             ctx.capabilities.cap_net_admin = false;
         }
-        
-        if restrictions.disable_filesystem {
-            // Would integrate with filesystem to restrict access
-        }
-        
+
         if restrictions.disable_ipc {
             ctx.capabilities.cap_ipc_owner = false;
         }
-        
+
         if restrictions.memory_limit > 0 {
             // Would integrate with memory manager to set limits
         }
-        
-        audit_event(AuditEvent::SecurityViolation { 
-            pid, 
-            details: "Sandbox created" 
-        });
-        
-        Ok(())
+    }
+
+    crate::process::seccomp::set_no_new_privs(pid);
+    crate::process::seccomp::install_filter(pid, sandbox_filter(&restrictions), 0, true)
+        .map_err(|_| "Sandbox filter rejected")?;
+
+    audit_event(AuditEvent::SecurityViolation {
+        pid,
+        details: "Sandbox created"
+    });
+
+    Ok(())
+}
+
+/// Calls that create sockets or use them
+const NETWORK_SYSCALLS: &[u32] = &[41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 288, 299, 307];
+
+/// System V and POSIX IPC calls
+const IPC_SYSCALLS: &[u32] = &[29, 30, 31, 64, 65, 66, 67, 68, 69, 70, 71, 220, 240, 241, 242, 243, 244, 245];
+
+/// Calls that open or change files by path; open descriptors keep working
+const FILESYSTEM_SYSCALLS: &[u32] = &[
+    2, 76, 82, 83, 84, 85, 86, 87, 88, 90, 92, 94, 133, 257, 258, 259, 260, 263, 264, 265, 266, 268, 316, 437,
+];
+
+/// Seccomp program enforcing the call restrictions of a sandbox
+///
+/// Denied calls are checked first; if `allowed_syscalls` is set, any call
+/// not in it is denied too.
+fn sandbox_filter(restrictions: &SandboxRestrictions) -> Vec<crate::process::seccomp::SockFilter> {
+    use crate::process::seccomp::bpf::*;
+    use crate::process::seccomp::{SockFilter, AUDIT_ARCH_X86_64, SECCOMP_RET_ALLOW, SECCOMP_RET_ERRNO, SECCOMP_RET_KILL_PROCESS};
+
+    const EPERM: u32 = 1;
+    let deny = SockFilter::stmt(RET | K, SECCOMP_RET_ERRNO | EPERM);
+
+    let mut prog = vec![
+        SockFilter::stmt(LD | W | ABS, 4),
+        SockFilter::jump(JMP | JEQ | K, AUDIT_ARCH_X86_64, 1, 0),
+        SockFilter::stmt(RET | K, SECCOMP_RET_KILL_PROCESS),
+        SockFilter::stmt(LD | W | ABS, 0),
+    ];
+
+    let denied = [
+        (restrictions.disable_network, NETWORK_SYSCALLS),
+        (restrictions.disable_ipc, IPC_SYSCALLS),
+        (restrictions.disable_filesystem, FILESYSTEM_SYSCALLS),
+    ];
+    for nr in denied.iter().filter(|(disabled, _)| *disabled).flat_map(|(_, calls)| calls.iter()) {
+        prog.push(SockFilter::jump(JMP | JEQ | K, *nr, 0, 1));
+        prog.push(deny);
+    }
+
+    if restrictions.allowed_syscalls.is_empty() {
+        prog.push(SockFilter::stmt(RET | K, SECCOMP_RET_ALLOW));
     } else {
-        Err("Process context not found")
+        for &nr in &restrictions.allowed_syscalls {
+            prog.push(SockFilter::jump(JMP | JEQ | K, nr as u32, 0, 1));
+            prog.push(SockFilter::stmt(RET | K, SECCOMP_RET_ALLOW));
+        }
+        prog.push(deny);
     }
+
+    prog
 }
 
 /// Sandbox restrictions configuration
@@ -838,7 +892,7 @@ fn collect_entropy(state: &mut RngState) -> Result<(), &'static str> {
     let mut entropy_collected = 0;
 
     // Try RDRAND instruction
-    if let Ok(random_vals) = try_rdrand(8) {
+    if let Ok(random_vals) = try_rdrand::<8>() {
         for (i, val) in random_vals.iter().enumerate() {
             if i < state.pool.len() {
                 state.pool[i] ^= *val;
@@ -848,7 +902,7 @@ fn collect_entropy(state: &mut RngState) -> Result<(), &'static str> {
     }
 
     // Try RDSEED instruction
-    if let Ok(seed_vals) = try_rdseed(4) {
+    if let Ok(seed_vals) = try_rdseed::<4>() {
         for (i, val) in seed_vals.iter().enumerate() {
             if i + 8 < state.pool.len() {
                 state.pool[i + 8] ^= *val;
@@ -879,10 +933,13 @@ fn collect_entropy(state: &mut RngState) -> Result<(), &'static str> {
 }
 
 /// Try to use RDRAND instruction with retry logic
-fn try_rdrand(count: usize) -> Result<Vec<u32>, &'static str> {
-    let mut values = Vec::with_capacity(count);
+///
+/// Doesn't allocate, so the pool can be seeded before the heap exists
+/// (KASLR runs that early).
+fn try_rdrand<const N: usize>() -> Result<[u32; N], &'static str> {
+    let mut values = [0u32; N];
 
-    for _ in 0..count {
+    for slot in values.iter_mut() {
         let mut val = 0u32;
         let mut attempts = 0;
         let mut success = false;
@@ -907,7 +964,7 @@ fn try_rdrand(count: usize) -> Result<Vec<u32>, &'static str> {
         }
 
         if success {
-            values.push(val);
+            *slot = val;
         } else {
             return Err("RDRAND failed after retries");
         }
@@ -947,10 +1004,10 @@ fn is_rdrand_supported() -> bool {
 }
 
 /// Try to use RDSEED instruction with proper retry logic
-fn try_rdseed(count: usize) -> Result<Vec<u32>, &'static str> {
-    let mut values = Vec::with_capacity(count);
+fn try_rdseed<const N: usize>() -> Result<heapless::Vec<u32, N>, &'static str> {
+    let mut values = heapless::Vec::new();
 
-    for _ in 0..count {
+    for _ in 0..N {
         let mut val = 0u32;
         let mut attempts = 0;
         let mut success = false;
@@ -982,7 +1039,7 @@ fn try_rdseed(count: usize) -> Result<Vec<u32>, &'static str> {
         }
 
         if success {
-            values.push(val);
+            let _ = values.push(val);
         } else if values.is_empty() {
             return Err("RDSEED failed after retries");
         } else {
@@ -1024,10 +1081,10 @@ fn is_rdseed_supported() -> bool {
 }
 
 /// Collect timing-based entropy
-fn collect_timing_entropy() -> Vec<u32> {
-    let mut values = Vec::with_capacity(4);
+fn collect_timing_entropy() -> [u32; 4] {
+    let mut values = [0u32; 4];
 
-    for _ in 0..4 {
+    for value in values.iter_mut() {
         let start = unsafe {
             #[cfg(target_arch = "x86_64")]
             {
@@ -1056,7 +1113,7 @@ fn collect_timing_entropy() -> Vec<u32> {
             }
         };
 
-        values.push((end.wrapping_sub(start) ^ sum as u64) as u32);
+        *value = (end.wrapping_sub(start) ^ sum as u64) as u32;
     }
 
     values
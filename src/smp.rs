@@ -2,8 +2,9 @@
 //!
 //! Real multiprocessor support using APIC and x86_64 features
 
-use core::sync::atomic::{AtomicU32, AtomicBool, Ordering};
+use core::sync::atomic::{AtomicU32, AtomicBool, AtomicPtr, Ordering};
 use x86_64::VirtAddr;
+use x86_64::structures::tss::TaskStateSegment;
 use spin::Mutex;
 
 /// Maximum number of CPUs supported
@@ -48,6 +49,96 @@ static LOCAL_APIC_BASE: AtomicU64 = AtomicU64::new(0);
 
 use core::sync::atomic::AtomicU64;
 
+/// IA32_GS_BASE, the GS base used in kernel mode
+const IA32_GS_BASE: u32 = 0xC000_0101;
+
+/// Per-CPU area, reached through the GS base of the CPU it belongs to
+///
+/// Unlike `CPU_DATA` it needs no lock: each entry is written by its own CPU,
+/// apart from the setup `start_all_aps` does before the CPU runs.
+#[repr(C)]
+pub struct PerCpu {
+    cpu_id: AtomicU32,
+    apic_id: AtomicU32,
+    /// Nanoseconds spent in the idle loop
+    idle_ns: AtomicU64,
+    /// Top of the stack double faults switch to
+    double_fault_stack: AtomicU64,
+    /// Task state segment of an application processor; null on the boot CPU
+    tss: AtomicPtr<TaskStateSegment>,
+}
+
+impl PerCpu {
+    const fn new() -> Self {
+        Self {
+            cpu_id: AtomicU32::new(0),
+            apic_id: AtomicU32::new(0),
+            idle_ns: AtomicU64::new(0),
+            double_fault_stack: AtomicU64::new(0),
+            tss: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    /// Index of this CPU in the CPU table
+    pub fn cpu_id(&self) -> u32 {
+        self.cpu_id.load(Ordering::Relaxed)
+    }
+
+    /// Local APIC ID of this CPU
+    pub fn apic_id(&self) -> u32 {
+        self.apic_id.load(Ordering::Relaxed)
+    }
+
+    /// Time this CPU has spent idle, in nanoseconds
+    pub fn idle_ns(&self) -> u64 {
+        self.idle_ns.load(Ordering::Relaxed)
+    }
+
+    /// Task state segment of this CPU, or null for the boot CPU's static one
+    pub fn tss(&self) -> *mut TaskStateSegment {
+        self.tss.load(Ordering::Relaxed)
+    }
+}
+
+static PER_CPU: [PerCpu; MAX_CPUS] = [const { PerCpu::new() }; MAX_CPUS];
+
+/// Point the GS base of the calling CPU at its per-CPU area
+fn set_this_cpu(cpu_id: u32) {
+    let cpu = &PER_CPU[cpu_id as usize];
+    cpu.cpu_id.store(cpu_id, Ordering::Relaxed);
+    cpu.apic_id.store(get_apic_id(), Ordering::Relaxed);
+    unsafe { write_msr(IA32_GS_BASE, cpu as *const PerCpu as u64) };
+}
+
+/// Per-CPU area of the calling CPU
+///
+/// Reloading GS clears its base, which the GDT setup and context switches
+/// do. A base outside the per-CPU array is therefore repaired from the APIC
+/// ID first.
+pub fn this_cpu() -> Option<&'static PerCpu> {
+    if !is_initialized() {
+        return None;
+    }
+
+    let base = unsafe { read_msr(IA32_GS_BASE) } as usize;
+    let first = PER_CPU.as_ptr() as usize;
+    let offset = base.wrapping_sub(first);
+    if offset < core::mem::size_of_val(&PER_CPU) && offset % core::mem::size_of::<PerCpu>() == 0 {
+        return Some(&PER_CPU[offset / core::mem::size_of::<PerCpu>()]);
+    }
+
+    let cpu_id = cpu_by_apic_id(get_apic_id())?;
+    set_this_cpu(cpu_id);
+    Some(&PER_CPU[cpu_id as usize])
+}
+
+/// Charge `ns` nanoseconds of idle time to the calling CPU
+pub fn account_idle(ns: u64) {
+    if let Some(cpu) = this_cpu() {
+        cpu.idle_ns.fetch_add(ns, Ordering::Relaxed);
+    }
+}
+
 /// APIC register offsets
 mod apic_regs {
     pub const APIC_ID: u32 = 0x20;
@@ -90,8 +181,11 @@ pub fn init() -> Result<(), &'static str> {
         tss_selector: 0, // Will be set by GDT
     };
     
+    drop(cpu_data);
+    
     CPU_COUNT.store(1, Ordering::Release);
     ONLINE_CPUS.store(1, Ordering::Release);
+    set_this_cpu(0);
     INITIALIZED.store(true, Ordering::Release);
     
     Ok(())
@@ -112,17 +206,21 @@ pub fn get_apic_id() -> u32 {
 
 /// Get current CPU ID
 pub fn current_cpu() -> u32 {
-    let apic_id = get_apic_id();
-    let cpu_data = CPU_DATA.lock();
-    
-    for i in 0..CPU_COUNT.load(Ordering::Acquire) as usize {
-        if cpu_data[i].apic_id == apic_id {
-            return cpu_data[i].cpu_id;
-        }
+    if let Some(cpu) = this_cpu() {
+        return cpu.cpu_id();
     }
-    
+
     // Default to 0 if not found (shouldn't happen)
-    0
+    cpu_by_apic_id(get_apic_id()).unwrap_or(0)
+}
+
+/// Find the CPU ID of the processor with local APIC ID `apic_id`
+fn cpu_by_apic_id(apic_id: u32) -> Option<u32> {
+    let cpu_data = CPU_DATA.lock();
+    cpu_data[..CPU_COUNT.load(Ordering::Acquire) as usize]
+        .iter()
+        .find(|cpu| cpu.apic_id == apic_id)
+        .map(|cpu| cpu.cpu_id)
 }
 
 /// Get number of CPUs
@@ -194,12 +292,11 @@ pub fn eoi() {
 }
 
 /// Get Local APIC base address
-fn get_apic_base() -> Option<VirtAddr> {
+pub fn get_apic_base() -> Option<VirtAddr> {
     let phys = LOCAL_APIC_BASE.load(Ordering::Acquire);
     if phys != 0 {
-        // In production, this should be properly mapped by memory manager
-        // For now, return identity-mapped address
-        Some(VirtAddr::new(phys))
+        // Reached through the physical memory mapping, like the APIC driver
+        Some(VirtAddr::new(crate::kaslr::physmap_offset() + phys))
     } else {
         None
     }
@@ -277,7 +374,8 @@ pub fn detect_cpus_from_acpi() -> Result<u32, &'static str> {
     let madt = crate::acpi::madt().ok_or("MADT not available - cannot detect CPUs")?;
 
     let mut cpu_data = CPU_DATA.lock();
-    let mut detected_count = 0u32;
+    // The BSP keeps CPU ID 0 wherever the MADT lists it
+    let mut detected_count = 1u32;
 
     for processor in &madt.processors {
         // Check if processor is enabled (bit 0) or can be enabled (bit 1)
@@ -303,7 +401,7 @@ pub fn detect_cpus_from_acpi() -> Result<u32, &'static str> {
             cpu_data[detected_count as usize] = CpuData {
                 cpu_id: detected_count,
                 apic_id,
-                online: detected_count == 0, // Only BSP is initially online
+                online: false, // Only BSP is initially online
                 idle_time: 0,
                 kernel_stack: VirtAddr::zero(),
                 tss_selector: 0,
@@ -312,12 +410,6 @@ pub fn detect_cpus_from_acpi() -> Result<u32, &'static str> {
         }
     }
 
-    // Ensure we have at least the BSP registered
-    if detected_count == 0 {
-        detected_count = 1;
-        cpu_data[0].online = true;
-    }
-
     CPU_COUNT.store(detected_count, Ordering::Release);
 
     Ok(detected_count)
@@ -457,7 +549,9 @@ pub fn get_cpu_data(cpu_id: u32) -> Option<CpuData> {
     let cpu_count_val = CPU_COUNT.load(Ordering::Acquire);
     if cpu_id < cpu_count_val {
         let cpu_data = CPU_DATA.lock();
-        Some(cpu_data[cpu_id as usize])
+        let mut data = cpu_data[cpu_id as usize];
+        data.idle_time = PER_CPU[cpu_id as usize].idle_ns();
+        Some(data)
     } else {
         None
     }
@@ -532,3 +626,329 @@ fn delay_microseconds(us: u64) {
         core::hint::spin_loop();
     }
 }
+
+// =============================================================================
+// Application Processor Bring-up
+// =============================================================================
+
+/// Physical address the AP trampoline is copied to; APs start executing
+/// here in real mode. The frame allocator never hands out the first MiB.
+pub const TRAMPOLINE_PHYS: u64 = 0x8000;
+
+/// Size of each AP's kernel stack
+const AP_STACK_SIZE: usize = 64 * 1024;
+
+/// Size of each AP's double fault stack
+const AP_DOUBLE_FAULT_STACK_SIZE: usize = 5 * 4096;
+
+/// How long to wait for a started AP to report in, in milliseconds
+const AP_STARTUP_TIMEOUT_MS: u64 = 1000;
+
+/// What the BSP leaves in the trampoline for the AP it is starting
+///
+/// The AP adopts the BSP's control registers and page tables and jumps to
+/// `entry` on `stack` with `cpu` as argument. Lives at offset 8 of the
+/// trampoline; see the assembly below.
+#[repr(C)]
+struct TrampolineParams {
+    cr0: u64,
+    cr3: u64,
+    cr4: u64,
+    efer: u64,
+    stack: u64,
+    entry: u64,
+    cpu: u64,
+}
+
+/// Offset of `TrampolineParams` in the trampoline
+const TRAMPOLINE_PARAMS_OFFSET: u64 = 8;
+
+// The AP trampoline. It is assembled into the kernel image and copied to
+// TRAMPOLINE_PHYS; every absolute address in it is relative to that copy, and
+// the data at its start sits at fixed offsets for the code to find. The
+// AP goes from real mode to protected mode to long mode on a GDT of its own,
+// then calls the kernel entry point once paging is on and never comes back.
+core::arch::global_asm!(
+    ".pushsection .text.ap_trampoline, \"ax\"",
+    ".global ap_trampoline_start",
+    ".global ap_trampoline_end",
+    ".code16",
+    "ap_trampoline_start:",
+    "    jmp 2f",
+    // TrampolineParams, filled in by the BSP
+    "    .org 8",
+    "    .fill 7, 8, 0",
+    // Temporary GDT
+    "    .org 64",
+    "    .quad 0",
+    "    .quad 0x00cf9a000000ffff", // 0x08: 32-bit code
+    "    .quad 0x00cf92000000ffff", // 0x10: data
+    "    .quad 0x00af9a000000ffff", // 0x18: 64-bit code
+    // GDT pointer
+    "    .word 4 * 8 - 1",
+    "    .long {base} + 64",
+    "2:",
+    "    cli",
+    "    cld",
+    "    xor ax, ax",
+    "    mov ds, ax",
+    "    lgdt [{base} + 96]",
+    "    mov eax, cr0",
+    "    or eax, 1",
+    "    mov cr0, eax",
+    // jmp dword 0x08:protected_mode
+    "    .byte 0x66, 0xea",
+    "    .long {base} + (3f - ap_trampoline_start)",
+    "    .word 0x08",
+    ".code32",
+    "3:",
+    "    mov ax, 0x10",
+    "    mov ds, ax",
+    "    mov es, ax",
+    "    mov ss, ax",
+    // PCIDE can't be set outside long mode
+    "    mov eax, [{params} + 16]",
+    "    and eax, ~(1 << 17)",
+    "    mov cr4, eax",
+    "    mov eax, [{params} + 8]",
+    "    mov cr3, eax",
+    // EFER without LMA, which the CPU sets itself
+    "    mov ecx, 0xC0000080",
+    "    mov eax, [{params} + 24]",
+    "    mov edx, [{params} + 28]",
+    "    and eax, ~(1 << 10)",
+    "    wrmsr",
+    "    mov eax, [{params}]",
+    "    mov cr0, eax",
+    // jmp 0x18:long_mode
+    "    .byte 0xea",
+    "    .long {base} + (4f - ap_trampoline_start)",
+    "    .word 0x18",
+    ".code64",
+    "4:",
+    "    mov rax, [{params} + 16]",
+    "    mov cr4, rax",
+    "    xor eax, eax",
+    "    mov ds, ax",
+    "    mov es, ax",
+    "    mov ss, ax",
+    "    mov rsp, [{params} + 32]",
+    "    mov rdi, [{params} + 48]",
+    "    mov rax, [{params} + 40]",
+    "    call rax",
+    "5:",
+    "    hlt",
+    "    jmp 5b",
+    "ap_trampoline_end:",
+    ".popsection",
+    base = const TRAMPOLINE_PHYS,
+    params = const TRAMPOLINE_PHYS + TRAMPOLINE_PARAMS_OFFSET,
+);
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_end: u8;
+}
+
+/// Start every CPU the MADT lists and wait for each to come online
+///
+/// Called once by the BSP after its own GDT, IDT and APIC are set up. The APs
+/// are started one at a time, since they share the trampoline. Returns the
+/// number of online CPUs.
+pub fn start_all_aps() -> Result<u32, &'static str> {
+    use x86_64::registers::control::{Cr0, Cr3, Cr4};
+    use x86_64::registers::model_specific::Efer;
+
+    let count = detect_cpus_from_acpi()?;
+    if count <= 1 {
+        return Ok(online_cpus());
+    }
+
+    let cr3 = Cr3::read().0.start_address().as_u64();
+    if cr3 >= 1 << 32 {
+        return Err("Kernel page tables above 4 GiB are out of reach of the trampoline");
+    }
+
+    // Copy the trampoline in and make it reachable at its physical address,
+    // where the AP still is when it turns paging on
+    let code = unsafe {
+        let start = &raw const ap_trampoline_start;
+        let end = &raw const ap_trampoline_end;
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    };
+    let low = (crate::kaslr::physmap_offset() + TRAMPOLINE_PHYS) as *mut u8;
+    unsafe { core::ptr::copy_nonoverlapping(code.as_ptr(), low, code.len()) };
+
+    let identity = VirtAddr::new(TRAMPOLINE_PHYS);
+    let mapped_here = crate::memory::translate_addr(identity).is_none();
+    if mapped_here {
+        let flags = crate::memory::MemoryFlags::PRESENT | crate::memory::MemoryFlags::WRITABLE;
+        crate::memory::map_physical_memory(TRAMPOLINE_PHYS as usize, TRAMPOLINE_PHYS as usize, flags)?;
+    } else if crate::memory::translate_addr(identity).map(|phys| phys.as_u64()) != Some(TRAMPOLINE_PHYS) {
+        return Err("Trampoline address is mapped elsewhere");
+    }
+
+    let params = unsafe { &mut *(low.add(TRAMPOLINE_PARAMS_OFFSET as usize) as *mut TrampolineParams) };
+    params.cr0 = Cr0::read_raw();
+    params.cr3 = cr3;
+    params.cr4 = Cr4::read_raw();
+    params.efer = Efer::read_raw();
+    params.entry = ap_main as usize as u64;
+
+    for cpu_id in 1..count {
+        if let Err(e) = start_one_ap(cpu_id, params) {
+            crate::serial_println!("CPU {} did not come up: {}", cpu_id, e);
+        }
+    }
+
+    if mapped_here {
+        let _ = crate::memory::unmap_page(TRAMPOLINE_PHYS as usize);
+    }
+    Ok(online_cpus())
+}
+
+/// Give CPU `cpu_id` its stacks, start it and wait for it to come online
+fn start_one_ap(cpu_id: u32, params: &mut TrampolineParams) -> Result<(), &'static str> {
+    use crate::memory::{get_memory_manager, MemoryProtection, MemoryRegionType};
+
+    let mm = get_memory_manager().ok_or("Memory manager not initialized")?;
+    let stack = mm
+        .allocate_region_with_guards(AP_STACK_SIZE, MemoryRegionType::KernelStack, MemoryProtection::KERNEL_DATA)
+        .map_err(|_| "Failed to allocate kernel stack")?;
+    let df_stack = mm
+        .allocate_region_with_guards(
+            AP_DOUBLE_FAULT_STACK_SIZE,
+            MemoryRegionType::KernelStack,
+            MemoryProtection::KERNEL_DATA,
+        )
+        .map_err(|_| "Failed to allocate double fault stack")?;
+
+    let stack_top = stack.end();
+    set_cpu_kernel_stack(cpu_id, stack_top);
+    PER_CPU[cpu_id as usize].double_fault_stack.store(df_stack.end().as_u64(), Ordering::Release);
+
+    // The AP reads these with caching on but before seeing any barrier of
+    // ours; volatile keeps them from being sunk past the SIPI
+    unsafe {
+        core::ptr::write_volatile(&mut params.stack, stack_top.as_u64());
+        core::ptr::write_volatile(&mut params.cpu, cpu_id as u64);
+    }
+    core::sync::atomic::fence(Ordering::SeqCst);
+
+    start_ap(cpu_id, TRAMPOLINE_PHYS)?;
+    for _ in 0..AP_STARTUP_TIMEOUT_MS {
+        if is_cpu_online(cpu_id) {
+            return Ok(());
+        }
+        delay_microseconds(1000);
+    }
+    Err("Timed out waiting for the CPU")
+}
+
+/// Long mode entry point of an application processor
+///
+/// Sets up what each CPU has of its own, reports in and goes idle.
+extern "C" fn ap_main(cpu_id: u64) -> ! {
+    let cpu_id = cpu_id as u32;
+
+    let tss = crate::gdt::init_ap(VirtAddr::new(PER_CPU[cpu_id as usize].double_fault_stack.load(Ordering::Acquire)));
+    PER_CPU[cpu_id as usize].tss.store(tss, Ordering::Relaxed);
+    crate::interrupts::load_idt();
+    set_this_cpu(cpu_id);
+    if crate::syscall_fast::is_supported() {
+        crate::syscall_fast::init();
+    }
+    enable_local_apic();
+
+    mark_cpu_online(cpu_id);
+    // Without its own timer tick the CPU could never preempt what it runs
+    if crate::hrtimer::init_cpu().or_else(|_| crate::time::start_local_apic_timer()).is_ok() {
+        crate::scheduler::cpu_online(cpu_id);
+    }
+    crate::scheduler::idle_loop()
+}
+
+/// Software-enable the calling CPU's local APIC and let it take interrupts
+/// of every priority
+fn enable_local_apic() {
+    if let Some(base) = get_apic_base() {
+        unsafe {
+            write_apic(base, apic_regs::APIC_TPR, 0);
+            // Vector 255 for spurious interrupts, as the APIC driver uses, and enable (bit 8)
+            write_apic(base, apic_regs::APIC_SPURIOUS, 0x1FF);
+        }
+    }
+}
+
+// =============================================================================
+// Rescheduling
+// =============================================================================
+
+/// Vector of the IPI asking a CPU to check whether it should switch process
+pub const RESCHEDULE_VECTOR: u8 = 0xFC;
+
+// =============================================================================
+// TLB Shootdown
+// =============================================================================
+
+/// Vector of the TLB shootdown IPI
+pub const TLB_SHOOTDOWN_VECTOR: u8 = 0xFD;
+
+/// Ranges longer than this many pages are flushed with a full TLB flush
+const FULL_FLUSH_PAGES: u64 = 32;
+
+/// Serializes shootdowns; the range below belongs to the holder
+static SHOOTDOWN_LOCK: Mutex<()> = Mutex::new(());
+static SHOOTDOWN_START: AtomicU64 = AtomicU64::new(0);
+static SHOOTDOWN_END: AtomicU64 = AtomicU64::new(0);
+/// CPUs that have not yet flushed the current range
+static SHOOTDOWN_PENDING: AtomicU32 = AtomicU32::new(0);
+
+fn flush_local(start: u64, end: u64) {
+    use x86_64::instructions::tlb;
+
+    if (end - start) / 4096 > FULL_FLUSH_PAGES {
+        tlb::flush_all();
+    } else {
+        for addr in (start & !0xFFF..end).step_by(4096) {
+            tlb::flush(VirtAddr::new(addr));
+        }
+    }
+}
+
+/// Flush `[start, end)` from the TLB of every online CPU, after a change to
+/// page table entries that may be cached elsewhere
+///
+/// The calling CPU flushes directly; the others are sent an IPI and waited
+/// for, so no CPU can use a stale translation once this returns.
+pub fn tlb_shootdown(start: VirtAddr, end: VirtAddr) {
+    flush_local(start.as_u64(), end.as_u64());
+
+    let others = online_cpus().saturating_sub(1);
+    if others == 0 || get_apic_base().is_none() {
+        return;
+    }
+
+    let _guard = SHOOTDOWN_LOCK.lock();
+    SHOOTDOWN_START.store(start.as_u64(), Ordering::Relaxed);
+    SHOOTDOWN_END.store(end.as_u64(), Ordering::Relaxed);
+    SHOOTDOWN_PENDING.store(others, Ordering::Release);
+    if broadcast_ipi(TLB_SHOOTDOWN_VECTOR).is_err() {
+        return;
+    }
+
+    // Bounded, like IPI delivery, in case a CPU marked online never answers
+    for _ in 0..1_000_000 {
+        if SHOOTDOWN_PENDING.load(Ordering::Acquire) == 0 {
+            return;
+        }
+        core::hint::spin_loop();
+    }
+}
+
+/// TLB shootdown IPI handler body
+pub fn handle_tlb_shootdown() {
+    flush_local(SHOOTDOWN_START.load(Ordering::Relaxed), SHOOTDOWN_END.load(Ordering::Relaxed));
+    SHOOTDOWN_PENDING.fetch_sub(1, Ordering::AcqRel);
+    eoi();
+}
@@ -1177,7 +1177,7 @@ fn shrink_process_heap(pid: Pid, size: u64) -> Result<(), &'static str> {
 }
 
 /// Memory map
-fn sys_mmap(_addr: u64, length: u64, prot: i32, flags: i32, fd: i32, _offset: u64) -> SyscallResult {
+fn sys_mmap(_addr: u64, length: u64, prot: i32, flags: i32, fd: i32, offset: u64) -> SyscallResult {
     // Security validation
     if length == 0 {
         return Err(SyscallError::InvalidArgument);
@@ -1208,8 +1208,15 @@ fn sys_mmap(_addr: u64, length: u64, prot: i32, flags: i32, fd: i32, _offset: u6
     let is_anonymous = (flags & 0x20) != 0;
 
     if !is_anonymous && fd >= 0 {
-        // File-backed mapping - not yet implemented
-        return Err(SyscallError::NotSupported);
+        // File-backed mapping, demand-faulted from the page cache
+        let shared = (flags & 0x01) != 0;
+        return match crate::memory::file_mapping::map_fd(fd, offset, length as usize, protection, shared, None) {
+            Ok(virt_addr) => Ok(virt_addr.as_u64()),
+            Err(crate::memory::file_mapping::MapError::BadFd) => Err(SyscallError::BadFileDescriptor),
+            Err(crate::memory::file_mapping::MapError::Access) => Err(SyscallError::PermissionDenied),
+            Err(crate::memory::file_mapping::MapError::Memory(_)) => Err(SyscallError::OutOfMemory),
+            Err(_) => Err(SyscallError::InvalidArgument),
+        };
     }
 
     // For anonymous mappings
@@ -1236,11 +1243,8 @@ fn sys_mmap(_addr: u64, length: u64, prot: i32, flags: i32, fd: i32, _offset: u6
 
 /// Sleep for specified microseconds
 fn sys_sleep(microseconds: u64) -> SyscallResult {
-    // Use production sleep implementation
-    let milliseconds = microseconds / 1000;
-    if milliseconds > 0 {
-        crate::time::sleep_ms(milliseconds);
-    }
+    // Block on the process timer queue rather than spinning
+    crate::process::timers::sleep_ns(microseconds * 1000).map_err(|_| SyscallError::Interrupted)?;
     Ok(0)
 }
 
@@ -1297,24 +1301,10 @@ fn sys_setpriority(priority: i32) -> SyscallResult {
         }
     }
 
-    // Update priority in process control block
-    match process_manager.get_process(current_pid) {
-        Some(mut process) => {
-            // Convert scheduler::Priority to process::Priority
-            process.priority = match new_priority {
-                crate::scheduler::Priority::RealTime => crate::process::Priority::RealTime,
-                crate::scheduler::Priority::High => crate::process::Priority::High,
-                crate::scheduler::Priority::Normal => crate::process::Priority::Normal,
-                crate::scheduler::Priority::Low => crate::process::Priority::Low,
-                crate::scheduler::Priority::Idle => crate::process::Priority::Idle,
-            };
-
-            // Notify scheduler of priority change
-            crate::scheduler::update_process_priority(current_pid, new_priority);
-
-            Ok(0)
-        },
-        None => Err(SyscallError::InvalidSyscall)
+    // Update the control block; the process manager tells the scheduler
+    match process_manager.set_priority(current_pid, new_priority) {
+        Ok(()) => Ok(0),
+        Err(_) => Err(SyscallError::InvalidSyscall)
     }
 }
 
@@ -186,6 +186,15 @@ pub unsafe extern "C" fn syscall_entry() {
         "pop r11",           // User RFLAGS
         "pop rcx",           // User RIP
 
+        // A single-stepping tracee gets the trap flag back
+        "push rax",
+        "push rcx",
+        "mov rdi, r11",
+        "call {return_flags}",
+        "mov r11, rax",
+        "pop rcx",
+        "pop rax",
+
         // Return to user mode with SYSRET
         // SYSRET will:
         // - Load RIP from RCX
@@ -195,17 +204,25 @@ pub unsafe extern "C" fn syscall_entry() {
         // - Set CPL to 3
         "sysretq",
 
-        syscall_handler = sym syscall_handler_wrapper
+        syscall_handler = sym syscall_handler_wrapper,
+        return_flags = sym syscall_return_flags,
     );
 }
 
+/// User RFLAGS for SYSRET, with the trap flag set for a single-stepping tracee
+extern "C" fn syscall_return_flags(flags: u64) -> u64 {
+    crate::process::ptrace::return_flags(flags)
+}
+
 /// Wrapper function that handles syscall dispatch
 ///
 /// This is called from the syscall_entry assembly code.
-/// It reads arguments from registers and dispatches to the syscall handler.
+/// It reads arguments from registers and dispatches to the syscall handler,
+/// along with the user return address SYSCALL left in RCX.
 #[no_mangle]
 extern "C" fn syscall_handler_wrapper() -> i64 {
     let syscall_num: u64;
+    let user_ip: u64;
     let arg1: u64;
     let arg2: u64;
     let arg3: u64;
@@ -223,6 +240,7 @@ extern "C" fn syscall_handler_wrapper() -> i64 {
             "mov {arg4}, r10",
             "mov {arg5}, r8",
             "mov {arg6}, r9",
+            "mov {user_ip}, rcx",
             syscall_num = out(reg) syscall_num,
             arg1 = out(reg) arg1,
             arg2 = out(reg) arg2,
@@ -230,6 +248,7 @@ extern "C" fn syscall_handler_wrapper() -> i64 {
             arg4 = out(reg) arg4,
             arg5 = out(reg) arg5,
             arg6 = out(reg) arg6,
+            user_ip = out(reg) user_ip,
             options(nostack, preserves_flags)
         );
     }
@@ -237,12 +256,8 @@ extern "C" fn syscall_handler_wrapper() -> i64 {
     // Dispatch to the syscall handler
     crate::syscall_handler::dispatch_syscall(
         syscall_num,
-        arg1,
-        arg2,
-        arg3,
-        arg4,
-        arg5,
-        arg6,
+        [arg1, arg2, arg3, arg4, arg5, arg6],
+        user_ip,
     )
 }
 
@@ -49,8 +49,25 @@ pub enum LinuxSyscall {
 /// - r8: arg5
 /// - r9: arg6
 ///
+/// `user_ip` is the address the call was made from. A tracer asking for
+/// syscall stops sees the call first and may change or skip it; the caller's
+/// seccomp filters run next and may fail the call or kill the caller instead.
+/// The tracer sees the result last.
+///
 /// Return value in rax
-pub fn dispatch_syscall(
+pub fn dispatch_syscall(mut syscall_num: u64, mut args: [u64; 6], user_ip: u64) -> i64 {
+    if let Some(result) = crate::process::ptrace::syscall_entry(&mut syscall_num, &mut args, user_ip) {
+        return crate::process::ptrace::syscall_exit(result);
+    }
+    let result = match crate::process::seccomp::check_syscall(syscall_num, args, user_ip) {
+        Some(result) => result,
+        None => invoke_syscall(syscall_num, args[0], args[1], args[2], args[3], args[4], args[5]),
+    };
+    crate::process::ptrace::syscall_exit(result)
+}
+
+/// Run the handler for a syscall that passed the entry checks
+fn invoke_syscall(
     syscall_num: u64,
     arg1: u64,
     arg2: u64,
@@ -75,6 +92,7 @@ pub fn dispatch_syscall(
         10 => syscall_mprotect(arg1 as *mut u8, arg2 as usize, arg3 as i32),
         11 => syscall_munmap(arg1 as *mut u8, arg2 as usize),
         12 => syscall_brk(arg1 as *mut u8),
+        26 => syscall_msync(arg1 as *mut u8, arg2 as usize, arg3 as i32),
 
         // Process operations
         57 => syscall_fork(),
@@ -82,6 +100,21 @@ pub fn dispatch_syscall(
         60 => syscall_exit(arg1 as i32),
         61 => syscall_wait4(arg1 as i32, arg2 as *mut i32, arg3 as i32, arg4 as *mut u8),
 
+        // Namespace operations
+        56 => syscall_clone(arg1, arg2 as *mut u8, arg3 as *mut i32, arg4 as *mut i32, arg5),
+        63 => syscall_uname(arg1 as *mut u8),
+        170 => syscall_sethostname(arg1 as *const u8, arg2 as usize),
+        171 => syscall_setdomainname(arg1 as *const u8, arg2 as usize),
+        272 => syscall_unshare(arg1 as i32),
+        308 => syscall_setns(arg1 as i32, arg2 as i32),
+
+        // Seccomp
+        157 => syscall_prctl(arg1 as i32, arg2, arg3, arg4, arg5),
+        317 => syscall_seccomp(arg1 as u32, arg2 as u32, arg3),
+
+        // Tracing
+        101 => syscall_ptrace(arg1 as i64, arg2 as i32, arg3, arg4),
+
         // IPC operations
         29 => syscall_shmget(arg1 as i32, arg2 as usize, arg3 as i32),
         30 => syscall_shmat(arg1 as i32, arg2 as *const u8, arg3 as i32),
@@ -178,6 +211,13 @@ fn syscall_munmap(addr: *mut u8, length: usize) -> i64 {
     }
 }
 
+fn syscall_msync(addr: *mut u8, length: usize, flags: i32) -> i64 {
+    match crate::linux_compat::memory_ops::msync(addr, length, flags) {
+        Ok(_) => 0,
+        Err(e) => -(e as i64),
+    }
+}
+
 fn syscall_brk(addr: *mut u8) -> i64 {
     match crate::linux_compat::memory_ops::brk(addr) {
         Ok(new_brk) => new_brk as i64,
@@ -211,6 +251,69 @@ fn syscall_wait4(pid: i32, wstatus: *mut i32, options: i32, rusage: *mut u8) ->
     }
 }
 
+fn syscall_clone(flags: u64, stack: *mut u8, parent_tid: *mut i32, child_tid: *mut i32, tls: u64) -> i64 {
+    match crate::linux_compat::thread_ops::clone(flags, stack, parent_tid, child_tid, tls) {
+        Ok(pid) => pid as i64,
+        Err(e) => -(e as i64),
+    }
+}
+
+fn syscall_prctl(option: i32, arg2: u64, arg3: u64, arg4: u64, arg5: u64) -> i64 {
+    match crate::linux_compat::process_ops::prctl(option, arg2, arg3, arg4, arg5) {
+        Ok(ret) => ret as i64,
+        Err(e) => -(e as i64),
+    }
+}
+
+fn syscall_seccomp(operation: u32, flags: u32, args: u64) -> i64 {
+    match crate::linux_compat::process_ops::seccomp(operation, flags, args) {
+        Ok(_) => 0,
+        Err(e) => -(e as i64),
+    }
+}
+
+fn syscall_ptrace(request: i64, pid: i32, addr: u64, data: u64) -> i64 {
+    match crate::linux_compat::process_ops::ptrace(request, pid, addr, data) {
+        Ok(ret) => ret as i64,
+        Err(e) => -(e as i64),
+    }
+}
+
+fn syscall_uname(buf: *mut u8) -> i64 {
+    match crate::linux_compat::sysinfo_ops::uname(buf as *mut crate::linux_compat::sysinfo_ops::UtsName) {
+        Ok(_) => 0,
+        Err(e) => -(e as i64),
+    }
+}
+
+fn syscall_sethostname(name: *const u8, len: usize) -> i64 {
+    match crate::linux_compat::sysinfo_ops::sethostname(name, len) {
+        Ok(_) => 0,
+        Err(e) => -(e as i64),
+    }
+}
+
+fn syscall_setdomainname(name: *const u8, len: usize) -> i64 {
+    match crate::linux_compat::sysinfo_ops::setdomainname(name, len) {
+        Ok(_) => 0,
+        Err(e) => -(e as i64),
+    }
+}
+
+fn syscall_unshare(flags: i32) -> i64 {
+    match crate::linux_compat::fs_ops::unshare(flags) {
+        Ok(_) => 0,
+        Err(e) => -(e as i64),
+    }
+}
+
+fn syscall_setns(fd: i32, nstype: i32) -> i64 {
+    match crate::linux_compat::fs_ops::setns(fd, nstype) {
+        Ok(_) => 0,
+        Err(e) => -(e as i64),
+    }
+}
+
 fn syscall_msgget(key: i32, msgflg: i32) -> i64 {
     match crate::linux_compat::ipc_ops::msgget(key, msgflg) {
         Ok(id) => id as i64,
@@ -323,7 +426,18 @@ pub extern "x86-interrupt" fn syscall_0x80_handler(mut stack_frame: InterruptSta
     }
 
     // Dispatch the syscall
-    let result = dispatch_syscall(syscall_num, arg1, arg2, arg3, arg4, arg5, arg6);
+    let result = dispatch_syscall(
+        syscall_num,
+        [arg1, arg2, arg3, arg4, arg5, arg6],
+        stack_frame.instruction_pointer.as_u64(),
+    );
+
+    // A single-stepping tracee traps on the instruction after the call
+    unsafe {
+        stack_frame.as_mut().update(|frame| {
+            frame.cpu_flags = crate::process::ptrace::return_flags(frame.cpu_flags);
+        });
+    }
 
     // Write result back to RAX for return to caller
     unsafe {
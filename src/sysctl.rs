@@ -0,0 +1,80 @@
+//! Kernel Tunables (sysctl)
+//!
+//! Each tunable is a number kept in an atomic by the subsystem it belongs
+//! to, listed here under its dotted Linux name with the range it accepts.
+//! They show up as files under `/proc/sys`, `kernel.randomize_va_space` as
+//! `/proc/sys/kernel/randomize_va_space`. Anyone may read them; only root
+//! may change them.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::memory::aslr;
+
+/// One tunable
+pub struct Sysctl {
+    /// Dotted name, as `sysctl -a` prints it
+    pub name: &'static str,
+    value: &'static AtomicU64,
+    min: u64,
+    max: u64,
+}
+
+impl Sysctl {
+    /// Current value
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    /// Change the value; `privileged` is whether the caller is root
+    pub fn set(&self, value: u64, privileged: bool) -> Result<(), SysctlError> {
+        if !privileged {
+            return Err(SysctlError::PermissionDenied);
+        }
+        if !(self.min..=self.max).contains(&value) {
+            return Err(SysctlError::Invalid);
+        }
+        self.value.store(value, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Errors from changing a tunable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SysctlError {
+    /// No tunable of that name
+    NotFound,
+    /// Value out of range
+    Invalid,
+    /// Only root may change tunables
+    PermissionDenied,
+}
+
+static TABLE: &[Sysctl] = &[
+    Sysctl { name: "kernel.randomize_va_space", value: &aslr::RANDOMIZE_VA_SPACE, min: 0, max: 2 },
+    Sysctl {
+        name: "vm.mmap_rnd_bits",
+        value: &aslr::MMAP_RND_BITS,
+        min: aslr::MMAP_RND_BITS_MIN,
+        max: aslr::MMAP_RND_BITS_MAX,
+    },
+];
+
+/// All tunables
+pub fn entries() -> &'static [Sysctl] {
+    TABLE
+}
+
+/// The tunable called `name`
+pub fn find(name: &str) -> Option<&'static Sysctl> {
+    TABLE.iter().find(|entry| entry.name == name)
+}
+
+/// Value of the tunable called `name`
+pub fn get(name: &str) -> Option<u64> {
+    find(name).map(Sysctl::get)
+}
+
+/// Set the tunable called `name`
+pub fn set(name: &str, value: u64, privileged: bool) -> Result<(), SysctlError> {
+    find(name).ok_or(SysctlError::NotFound)?.set(value, privileged)
+}
@@ -2,8 +2,12 @@
 //!
 //! Provides real timer functionality using x86_64 hardware timers including
 //! HPET, APIC timer, and PIT with proper hardware abstraction.
+//!
+//! The periodic timer picked here only drives the tick until each CPU moves
+//! to its one-shot local APIC timer under `crate::hrtimer`, which software
+//! timers scheduled here expire from as well.
 
-use core::sync::atomic::{AtomicU64, AtomicBool, Ordering};
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicBool, Ordering};
 use x86_64::instructions::port::Port;
 use x86_64::{VirtAddr, PhysAddr};
 use alloc::vec::Vec;
@@ -14,6 +18,8 @@ use lazy_static::lazy_static;
 const PIT_FREQUENCY: u32 = 1193182;
 /// Target timer frequency in Hz
 const TIMER_FREQUENCY: u32 = 1000; // 1kHz for better precision
+/// Time between two timer interrupts in microseconds
+pub const TICK_US: u64 = 1_000_000 / TIMER_FREQUENCY as u64;
 /// PIT divisor for desired frequency
 const PIT_DIVISOR: u16 = (PIT_FREQUENCY / TIMER_FREQUENCY) as u16;
 
@@ -135,12 +141,13 @@ impl HardwareTimer for PitTimer {
     
     fn disable(&mut self) {
         self.enabled = false;
-        // Disable PIT by setting maximum divisor
+        // Leave channel 0 in one-shot mode: it raises IRQ 0 once more at
+        // terminal count and then stays quiet
         unsafe {
             let mut cmd = Port::<u8>::new(0x43);
             let mut data = Port::<u8>::new(0x40);
             
-            cmd.write(0x36);
+            cmd.write(0x30);
             data.write(0xFF);
             data.write(0xFF);
         }
@@ -167,6 +174,121 @@ impl HardwareTimer for PitTimer {
     }
 }
 
+/// Initial count the boot CPU calibrated its local APIC timer to
+///
+/// Every local APIC timer runs off the same bus clock, so application
+/// processors reuse it instead of calibrating against the PIT again.
+static APIC_INITIAL_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// Whether the boot CPU's timer interrupt comes from its local APIC
+static LOCAL_APIC_TICK: AtomicBool = AtomicBool::new(false);
+
+/// Whether the boot CPU's timer interrupt comes from its local APIC, and so
+/// is acknowledged there rather than at the PIC
+pub fn local_apic_tick() -> bool {
+    LOCAL_APIC_TICK.load(Ordering::Relaxed)
+}
+
+/// Start the calling CPU's local APIC timer at the kernel tick rate
+///
+/// Application processors call this once online; the scheduler preempts on
+/// the resulting interrupts.
+pub fn start_local_apic_timer() -> Result<(), &'static str> {
+    let base = crate::smp::get_apic_base().ok_or("Local APIC not available")?;
+    let mut timer = ApicTimer::new();
+    timer.set_base_address(base);
+
+    let initial_count = match APIC_INITIAL_COUNT.load(Ordering::Relaxed) {
+        0 => timer.calibrate_with_tsc()?,
+        count => count,
+    };
+    timer.write_register(0x3E0, 0x03);
+    timer.write_register(0x320, 32 | (1 << 17));
+    timer.write_register(0x380, initial_count);
+    Ok(())
+}
+
+/// MSR the local APIC timer fires at in TSC-deadline mode
+const IA32_TSC_DEADLINE: u32 = 0x6E0;
+
+/// Whether local APIC timers in one-shot operation are armed with a TSC
+/// deadline rather than a count
+static TSC_DEADLINE_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the CPU's local APIC timer has a TSC-deadline mode
+fn tsc_deadline_supported() -> bool {
+    unsafe { core::arch::x86_64::__cpuid(1).ecx & (1 << 24) != 0 }
+}
+
+/// Put the calling CPU's local APIC timer into one-shot operation on
+/// `vector`
+///
+/// The timer uses TSC-deadline mode where the CPU has it and counts down
+/// otherwise. Nothing fires until `program_local_apic_timer` arms it.
+pub fn start_local_apic_oneshot(vector: u8) -> Result<(), &'static str> {
+    let base = crate::smp::get_apic_base().ok_or("Local APIC not available")?;
+    get_tsc_frequency().ok_or("TSC not calibrated")?;
+    let mut timer = ApicTimer::new();
+    timer.set_base_address(base);
+
+    if APIC_INITIAL_COUNT.load(Ordering::Relaxed) == 0 {
+        timer.calibrate_with_tsc()?;
+    }
+    let tsc_deadline = tsc_deadline_supported();
+    TSC_DEADLINE_MODE.store(tsc_deadline, Ordering::Relaxed);
+
+    timer.write_register(0x380, 0);
+    timer.write_register(0x3E0, 0x03);
+    let mode = if tsc_deadline { 0b10 << 17 } else { 0 };
+    timer.write_register(0x320, vector as u32 | mode);
+    // The mode switch must be visible before the deadline MSR is written
+    core::sync::atomic::fence(Ordering::SeqCst);
+    Ok(())
+}
+
+/// Arm the calling CPU's one-shot local APIC timer to fire at `deadline_ns`
+/// of uptime, or disarm it with `None`
+///
+/// A deadline already passed fires straight away; one beyond the reach of
+/// the count fires early. A saturated deadline is never reached and disarms.
+pub fn program_local_apic_timer(deadline_ns: Option<u64>) {
+    let deadline_ns = deadline_ns.filter(|&deadline| deadline != u64::MAX);
+    if TSC_DEADLINE_MODE.load(Ordering::Relaxed) {
+        let tsc = deadline_ns
+            .map(tsc_at)
+            .filter(|&tsc| tsc != u64::MAX)
+            .map_or(0, |tsc| tsc.max(1));
+        let mut msr = x86_64::registers::model_specific::Msr::new(IA32_TSC_DEADLINE);
+        unsafe { msr.write(tsc) };
+        return;
+    }
+
+    let Some(base) = crate::smp::get_apic_base() else {
+        return;
+    };
+    let mut timer = ApicTimer::new();
+    timer.set_base_address(base);
+    let count = deadline_ns.map_or(0, |deadline| {
+        let delta = deadline.saturating_sub(uptime_ns()) as u128;
+        let hz = APIC_INITIAL_COUNT.load(Ordering::Relaxed) as u128 * TIMER_FREQUENCY as u128;
+        (delta * hz / 1_000_000_000).clamp(1, u32::MAX as u128) as u32
+    });
+    timer.write_register(0x380, count);
+}
+
+/// Stop the periodic timer picked at boot, once the boot CPU ticks from its
+/// one-shot local APIC timer instead
+pub fn stop_periodic_timer() {
+    let mut timer_manager = TIMER_MANAGER.lock();
+    // A periodic local APIC timer was already switched to one-shot mode
+    if timer_manager.get_active_timer_type() == Some(TimerType::ApicTimer) {
+        return;
+    }
+    if let Some(timer) = timer_manager.get_active_timer() {
+        timer.disable();
+    }
+}
+
 /// APIC Timer implementation
 pub struct ApicTimer {
     frequency: u32,
@@ -259,6 +381,37 @@ impl ApicTimer {
         }
     }
     
+    /// Calibrate against the TSC alone, which unlike the PIT's channel 2 can
+    /// be used by several CPUs at once
+    fn calibrate_with_tsc(&self) -> Result<u32, &'static str> {
+        let tsc_freq = get_tsc_frequency().ok_or("TSC not calibrated")?;
+        let calibration_ms = 10;
+
+        self.write_register(0x3E0, 0x03);
+        self.write_register(0x380, 0xFFFFFFFF);
+        let start_apic_count = self.read_register(0x390);
+        let target_tsc = read_tsc() + (tsc_freq * calibration_ms) / 1000;
+        while read_tsc() < target_tsc {
+            core::hint::spin_loop();
+        }
+        let end_apic_count = self.read_register(0x390);
+        self.write_register(0x380, 0);
+
+        let apic_freq = start_apic_count.saturating_sub(end_apic_count) as u64 * 1000 / calibration_ms;
+        let initial_count = (apic_freq / self.frequency as u64) as u32;
+        if initial_count == 0 {
+            return Err("APIC timer calibration failed");
+        }
+        APIC_INITIAL_COUNT.store(initial_count, Ordering::Relaxed);
+        Ok(initial_count)
+    }
+
+    /// Load the count that sets the tick rate, keeping it for other CPUs
+    fn set_initial_count(&self, initial_count: u32) {
+        APIC_INITIAL_COUNT.store(initial_count, Ordering::Relaxed);
+        self.write_register(0x380, initial_count);
+    }
+
     fn write_register(&self, offset: u32, value: u32) {
         if let Some(base) = self.base_address {
             unsafe {
@@ -292,7 +445,7 @@ impl HardwareTimer for ApicTimer {
         
         // Calibrate timer for desired frequency
         let initial_count = self.calibrate_timer_count()?;
-        self.write_register(0x380, initial_count);
+        self.set_initial_count(initial_count);
         
         // Configure timer LVT (Local Vector Table)
         // Vector 32 (timer interrupt), periodic mode
@@ -308,7 +461,7 @@ impl HardwareTimer for ApicTimer {
         if self.enabled && self.base_address.is_some() {
             // Recalibrate timer with new frequency
             let initial_count = self.calibrate_timer_count()?;
-            self.write_register(0x380, initial_count);
+            self.set_initial_count(initial_count);
         }
         
         Ok(())
@@ -323,7 +476,7 @@ impl HardwareTimer for ApicTimer {
         if self.base_address.is_some() {
             // Enable timer by setting calibrated initial count
             if let Ok(initial_count) = self.calibrate_timer_count() {
-                self.write_register(0x380, initial_count);
+                self.set_initial_count(initial_count);
             } else {
                 // Fallback to reasonable default if calibration fails
                 self.write_register(0x380, 0x10000);
@@ -542,6 +695,7 @@ impl TimerManager {
                 match self.apic_timer.init() {
                     Ok(()) => {
                         self.active_timer = Some(TimerType::ApicTimer);
+                        LOCAL_APIC_TICK.store(true, Ordering::Relaxed);
                         return Ok(());
                     }
                     Err(e) => initialization_errors.push(("APIC Timer", e)),
@@ -722,14 +876,19 @@ pub fn timer_tick() {
         // Process scheduling occurred, new_pid is the scheduled process
         let _ = new_pid;
     }
+}
 
-    // Process any scheduled software timers
-    process_scheduled_timers();
+/// Count ticks the boot CPU skipped while its tick was stopped
+pub fn account_skipped_ticks(ticks: u64) {
+    TICKS.fetch_add(ticks, Ordering::Relaxed);
 }
 
 /// Update timer statistics and handle timer-specific operations
 fn update_timer_statistics() {
-    let timer_manager = TIMER_MANAGER.lock();
+    // The interrupted code may hold the lock; statistics can skip a tick
+    let Some(timer_manager) = TIMER_MANAGER.try_lock() else {
+        return;
+    };
     
     if let Some(timer_type) = timer_manager.get_active_timer_type() {
         match timer_type {
@@ -841,6 +1000,14 @@ pub fn read_tsc() -> u64 {
     }
 }
 
+/// TSC value at `uptime_ns` of uptime, saturating at `u64::MAX`
+pub fn tsc_at(uptime_ns: u64) -> u64 {
+    let tsc_freq = TSC_FREQUENCY.load(Ordering::Relaxed) as u128;
+    let boot_tsc = BOOT_TSC.load(Ordering::Relaxed);
+    let ticks = (uptime_ns as u128 * tsc_freq / 1_000_000_000).min(u64::MAX as u128) as u64;
+    boot_tsc.saturating_add(ticks)
+}
+
 /// Calibrate TSC frequency using hardware timers
 fn calibrate_tsc() {
     // Use PIT as the most reliable reference for TSC calibration
@@ -1023,6 +1190,12 @@ pub fn get_system_time_ms() -> u64 {
     (boot_time * 1000) + uptime_ms
 }
 
+/// Get wall-clock time in nanoseconds since the Unix epoch
+pub fn realtime_ns() -> u64 {
+    let boot_time = BOOT_TIME.load(Ordering::Relaxed);
+    boot_time * 1_000_000_000 + uptime_ns()
+}
+
 /// Set system time (Unix timestamp in seconds)
 pub fn set_system_time(timestamp: u64) {
     let uptime_sec = uptime_ms() / 1000;
@@ -1030,6 +1203,14 @@ pub fn set_system_time(timestamp: u64) {
     BOOT_TIME.store(boot_time, Ordering::Relaxed);
 }
 
+/// Set wall-clock time from nanoseconds since the Unix epoch
+///
+/// The boot time is kept in whole seconds, so sub-second precision is lost.
+pub fn set_realtime_ns(ns: u64) {
+    let boot_time = ns.saturating_sub(uptime_ns()) / 1_000_000_000;
+    BOOT_TIME.store(boot_time, Ordering::Relaxed);
+}
+
 /// Initialize system time from RTC (Real-Time Clock)
 pub fn init_system_time_from_rtc() -> Result<(), &'static str> {
     // Read time from CMOS RTC
@@ -1230,104 +1411,24 @@ impl Timer {
 pub type TimerCallback = fn();
 
 /// Timer ID for managing scheduled timers
-pub type TimerId = u64;
-
-/// Scheduled timer entry
-#[derive(Debug, Clone)]
-struct ScheduledTimer {
-    id: TimerId,
-    target_time_us: u64,
-    callback: TimerCallback,
-    periodic: bool,
-    interval_us: u64,
-}
-
-lazy_static! {
-    static ref SCHEDULED_TIMERS: Mutex<Vec<ScheduledTimer>> = Mutex::new(Vec::new());
-    static ref NEXT_TIMER_ID: AtomicU64 = AtomicU64::new(1);
-}
+pub type TimerId = crate::hrtimer::HrTimerId;
 
 /// Schedule a one-shot timer
+///
+/// The callback runs from the timer interrupt of the calling CPU.
 pub fn schedule_timer(delay_us: u64, callback: TimerCallback) -> TimerId {
-    let id = NEXT_TIMER_ID.fetch_add(1, Ordering::Relaxed);
-    let target_time = uptime_us() + delay_us;
-    
-    let timer = ScheduledTimer {
-        id,
-        target_time_us: target_time,
-        callback,
-        periodic: false,
-        interval_us: 0,
-    };
-    
-    SCHEDULED_TIMERS.lock().push(timer);
-    id
+    crate::hrtimer::start(uptime_ns().saturating_add(delay_us.saturating_mul(1000)), 0, callback)
 }
 
 /// Schedule a periodic timer
 pub fn schedule_periodic_timer(interval_us: u64, callback: TimerCallback) -> TimerId {
-    let id = NEXT_TIMER_ID.fetch_add(1, Ordering::Relaxed);
-    let target_time = uptime_us() + interval_us;
-    
-    let timer = ScheduledTimer {
-        id,
-        target_time_us: target_time,
-        callback,
-        periodic: true,
-        interval_us,
-    };
-    
-    SCHEDULED_TIMERS.lock().push(timer);
-    id
+    let interval_ns = interval_us.max(1).saturating_mul(1000);
+    crate::hrtimer::start(uptime_ns().saturating_add(interval_ns), interval_ns, callback)
 }
 
 /// Cancel a scheduled timer
 pub fn cancel_timer(timer_id: TimerId) -> bool {
-    let mut timers = SCHEDULED_TIMERS.lock();
-    if let Some(pos) = timers.iter().position(|t| t.id == timer_id) {
-        timers.remove(pos);
-        true
-    } else {
-        false
-    }
-}
-
-/// Process scheduled timers (called from timer interrupt)
-pub fn process_scheduled_timers() {
-    // Don't process timers if system isn't fully initialized
-    if !TIMER_INITIALIZED.load(Ordering::Relaxed) {
-        return;
-    }
-    
-    let current_time = uptime_us();
-    let mut timers = SCHEDULED_TIMERS.lock();
-    let mut expired_timers = Vec::new();
-    
-    // Find expired timers
-    let mut i = 0;
-    while i < timers.len() {
-        if timers[i].target_time_us <= current_time {
-            let timer = timers.remove(i);
-            expired_timers.push(timer);
-        } else {
-            i += 1;
-        }
-    }
-    
-    // Release the lock before calling callbacks to avoid deadlocks
-    drop(timers);
-    
-    // Process expired timers
-    for mut timer in expired_timers {
-        // Call the callback (be careful about panic handling in interrupt context)
-        (timer.callback)();
-        
-        // Reschedule if periodic
-        if timer.periodic {
-            timer.target_time_us = current_time + timer.interval_us;
-            SCHEDULED_TIMERS.lock().push(timer);
-        }
-    }
+    crate::hrtimer::cancel(timer_id)
 }
 
 /// Get active timer type
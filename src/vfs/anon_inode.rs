@@ -0,0 +1,73 @@
+//! Anonymous Inodes
+//!
+//! Pipes, eventfd, timerfd, signalfd and epoll instances are file objects
+//! with no directory entry. They share the same answers for every
+//! namespace and metadata operation, provided here.
+
+use super::{get_vfs, InodeType, Stat};
+
+/// Allocate an inode number for an anonymous file object
+pub fn alloc_ino() -> u64 {
+    get_vfs().alloc_ino()
+}
+
+/// Metadata for an anonymous file object
+pub fn stat(ino: u64, inode_type: InodeType, size: u64) -> Stat {
+    Stat {
+        ino,
+        inode_type,
+        size,
+        mode: 0o600,
+        ..Stat::default()
+    }
+}
+
+/// Implement the `InodeOps` methods that make no sense for an anonymous
+/// file object
+macro_rules! anon_inode_ops {
+    () => {
+        fn truncate(&self, _size: u64) -> $crate::vfs::VfsResult<()> {
+            Err($crate::vfs::VfsError::InvalidArgument)
+        }
+
+        fn sync(&self) -> $crate::vfs::VfsResult<()> {
+            Err($crate::vfs::VfsError::InvalidArgument)
+        }
+
+        fn lookup(&self, _name: &str) -> $crate::vfs::VfsResult<alloc::sync::Arc<dyn $crate::vfs::InodeOps>> {
+            Err($crate::vfs::VfsError::NotDirectory)
+        }
+
+        fn create(
+            &self,
+            _name: &str,
+            _inode_type: $crate::vfs::InodeType,
+            _mode: u32,
+        ) -> $crate::vfs::VfsResult<alloc::sync::Arc<dyn $crate::vfs::InodeOps>> {
+            Err($crate::vfs::VfsError::NotDirectory)
+        }
+
+        fn unlink(&self, _name: &str) -> $crate::vfs::VfsResult<()> {
+            Err($crate::vfs::VfsError::NotDirectory)
+        }
+
+        fn link(&self, _name: &str, _target: alloc::sync::Arc<dyn $crate::vfs::InodeOps>) -> $crate::vfs::VfsResult<()> {
+            Err($crate::vfs::VfsError::NotDirectory)
+        }
+
+        fn rename(
+            &self,
+            _old_name: &str,
+            _new_dir: alloc::sync::Arc<dyn $crate::vfs::InodeOps>,
+            _new_name: &str,
+        ) -> $crate::vfs::VfsResult<()> {
+            Err($crate::vfs::VfsError::NotDirectory)
+        }
+
+        fn readdir(&self) -> $crate::vfs::VfsResult<alloc::vec::Vec<$crate::vfs::DirEntry>> {
+            Err($crate::vfs::VfsError::NotDirectory)
+        }
+    };
+}
+
+pub(crate) use anon_inode_ops;
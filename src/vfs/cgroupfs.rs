@@ -0,0 +1,506 @@
+//! Control Group Filesystem (cgroup2)
+//!
+//! A directory per control group, mirroring the hierarchy kept by
+//! `crate::process::cgroup`. `mkdir` creates a group and `rmdir` removes
+//! an empty one; both need root, as does every write. Nothing is stored:
+//! reads format the current value and writes go straight to the hierarchy.
+//!
+//! Every group has:
+//! - `cgroup.procs`: its processes, one per line; writing a pid moves that
+//!   process here, and 0 the writer
+//! - `cgroup.controllers`: the controllers available to it
+//! - `cgroup.subtree_control`: those enabled for its children, changed by
+//!   writing "+name" and "-name" words
+//! - `cgroup.events`: whether the subtree has any processes
+//!
+//! Groups other than the root also have the files of every controller their
+//! parent enables:
+//! - `cpu.weight` (1..=10000, default 100), `cpu.max` ("$QUOTA $PERIOD" in
+//!   microseconds, "max" for no quota) and `cpu.stat`
+//! - `memory.current`, `memory.max` (bytes or "max", with an optional
+//!   K, M or G suffix) and `memory.events`
+//! - `pids.current`, `pids.max` and `pids.events`
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use super::{DirEntry, InodeOps, InodeType, Stat, StatFs, SuperblockOps, VfsError, VfsResult, CGROUP_DEV};
+use crate::process::cgroup::{self, CgroupError, CgroupId, ROOT_CGROUP};
+use crate::process::{self, Pid, ProcessState};
+
+/// CGROUP2_SUPER_MAGIC
+const CGROUP2_SUPER_MAGIC: u64 = 0x6367_7270;
+
+/// Inode number of the directory of group `id`; files are derived from it
+/// so they stay stable between lookups
+fn dir_ino(id: CgroupId) -> u64 {
+    ((id as u64 + 1) << 8) | 1
+}
+
+/// Caller's uid
+fn current_uid() -> u32 {
+    process::get_process_manager().get_process(process::current_pid()).map_or(0, |pcb| pcb.uid)
+}
+
+/// Whether process `pid` is alive
+fn process_alive(pid: Pid) -> bool {
+    process::get_process_manager()
+        .get_process(pid)
+        .is_some_and(|pcb| !matches!(pcb.state, ProcessState::Zombie | ProcessState::Terminated))
+}
+
+fn cgroup_error(err: CgroupError) -> VfsError {
+    match err {
+        CgroupError::NotFound => VfsError::NotFound,
+        CgroupError::AlreadyExists => VfsError::AlreadyExists,
+        CgroupError::Busy => VfsError::Busy,
+        CgroupError::InvalidArgument => VfsError::InvalidArgument,
+        CgroupError::LimitReached => VfsError::WouldBlock,
+    }
+}
+
+/// Interface files of a group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CgroupFile {
+    Procs,
+    Controllers,
+    SubtreeControl,
+    Events,
+    CpuWeight,
+    CpuMax,
+    CpuStat,
+    MemoryCurrent,
+    MemoryMax,
+    MemoryEvents,
+    PidsCurrent,
+    PidsMax,
+    PidsEvents,
+}
+
+impl CgroupFile {
+    const ALL: [CgroupFile; 13] = [
+        CgroupFile::Procs,
+        CgroupFile::Controllers,
+        CgroupFile::SubtreeControl,
+        CgroupFile::Events,
+        CgroupFile::CpuWeight,
+        CgroupFile::CpuMax,
+        CgroupFile::CpuStat,
+        CgroupFile::MemoryCurrent,
+        CgroupFile::MemoryMax,
+        CgroupFile::MemoryEvents,
+        CgroupFile::PidsCurrent,
+        CgroupFile::PidsMax,
+        CgroupFile::PidsEvents,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            CgroupFile::Procs => "cgroup.procs",
+            CgroupFile::Controllers => "cgroup.controllers",
+            CgroupFile::SubtreeControl => "cgroup.subtree_control",
+            CgroupFile::Events => "cgroup.events",
+            CgroupFile::CpuWeight => "cpu.weight",
+            CgroupFile::CpuMax => "cpu.max",
+            CgroupFile::CpuStat => "cpu.stat",
+            CgroupFile::MemoryCurrent => "memory.current",
+            CgroupFile::MemoryMax => "memory.max",
+            CgroupFile::MemoryEvents => "memory.events",
+            CgroupFile::PidsCurrent => "pids.current",
+            CgroupFile::PidsMax => "pids.max",
+            CgroupFile::PidsEvents => "pids.events",
+        }
+    }
+
+    /// Controller the file belongs to, `None` for the core files
+    fn controller(self) -> Option<u8> {
+        match self {
+            CgroupFile::Procs | CgroupFile::Controllers | CgroupFile::SubtreeControl | CgroupFile::Events => None,
+            CgroupFile::CpuWeight | CgroupFile::CpuMax | CgroupFile::CpuStat => Some(cgroup::CONTROLLER_CPU),
+            CgroupFile::MemoryCurrent | CgroupFile::MemoryMax | CgroupFile::MemoryEvents => Some(cgroup::CONTROLLER_MEMORY),
+            CgroupFile::PidsCurrent | CgroupFile::PidsMax | CgroupFile::PidsEvents => Some(cgroup::CONTROLLER_PIDS),
+        }
+    }
+
+    fn writable(self) -> bool {
+        matches!(
+            self,
+            CgroupFile::Procs
+                | CgroupFile::SubtreeControl
+                | CgroupFile::CpuWeight
+                | CgroupFile::CpuMax
+                | CgroupFile::MemoryMax
+                | CgroupFile::PidsMax
+        )
+    }
+
+    fn mode(self) -> u32 {
+        if self.writable() { 0o644 } else { 0o444 }
+    }
+
+    fn ino(self, id: CgroupId) -> u64 {
+        ((id as u64 + 1) << 8) | (self as u64 + 2)
+    }
+
+    /// Whether group `id` has this file
+    fn present_in(self, id: CgroupId) -> bool {
+        match self.controller() {
+            None => true,
+            Some(bit) => id != ROOT_CGROUP && cgroup::controllers(id) & bit != 0,
+        }
+    }
+}
+
+/// Controller names of `mask`, space separated
+fn controller_names(mask: u8) -> String {
+    cgroup::CONTROLLERS
+        .iter()
+        .filter(|&&(bit, _)| mask & bit != 0)
+        .map(|&(_, name)| name)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// "max" for no limit, or the limit
+fn limit(value: Option<u64>) -> String {
+    value.map_or(String::from("max"), |value| value.to_string())
+}
+
+/// Parse "max" or a number with an optional K, M or G suffix
+fn parse_limit(text: &str, suffixes: bool) -> VfsResult<Option<u64>> {
+    if text == "max" {
+        return Ok(None);
+    }
+    let (digits, shift) = match text.as_bytes().last() {
+        Some(b'K' | b'k') if suffixes => (&text[..text.len() - 1], 10),
+        Some(b'M' | b'm') if suffixes => (&text[..text.len() - 1], 20),
+        Some(b'G' | b'g') if suffixes => (&text[..text.len() - 1], 30),
+        _ => (text, 0),
+    };
+    let value = digits.parse::<u64>().map_err(|_| VfsError::InvalidArgument)?;
+    value.checked_mul(1u64 << shift).map(Some).ok_or(VfsError::InvalidArgument)
+}
+
+/// `/sys/fs/cgroup/<path>/<file>`
+struct CgroupFileInode {
+    id: CgroupId,
+    file: CgroupFile,
+}
+
+impl CgroupFileInode {
+    fn contents(&self) -> VfsResult<String> {
+        let id = self.id;
+        if !cgroup::exists(id) {
+            return Err(VfsError::NotFound);
+        }
+        Ok(match self.file {
+            CgroupFile::Procs => cgroup::procs(id)
+                .into_iter()
+                .filter(|&pid| process_alive(pid))
+                .map(|pid| format!("{}\n", pid))
+                .collect(),
+            CgroupFile::Controllers => format!("{}\n", controller_names(cgroup::controllers(id))),
+            CgroupFile::SubtreeControl => format!("{}\n", controller_names(cgroup::subtree_control(id))),
+            CgroupFile::Events => {
+                let populated = !cgroup::subtree_procs(id).is_empty();
+                format!("populated {}\n", populated as u8)
+            }
+            CgroupFile::CpuWeight => format!("{}\n", cgroup::cpu_weight(id).unwrap_or(cgroup::DEFAULT_CPU_WEIGHT)),
+            CgroupFile::CpuMax => {
+                let (quota, period) = cgroup::cpu_max(id);
+                format!("{} {}\n", limit(quota), period)
+            }
+            CgroupFile::CpuStat => {
+                let stats = cgroup::cpu_stats(id);
+                format!(
+                    "usage_usec {}\nnr_periods {}\nnr_throttled {}\nthrottled_usec {}\n",
+                    stats.usage_ns / 1000,
+                    stats.nr_periods,
+                    stats.nr_throttled,
+                    stats.throttled_ns / 1000,
+                )
+            }
+            CgroupFile::MemoryCurrent => format!("{}\n", cgroup::memory_current(id).unwrap_or(0)),
+            CgroupFile::MemoryMax => format!("{}\n", limit(cgroup::memory_max(id))),
+            CgroupFile::MemoryEvents => {
+                let events = cgroup::memory_events(id);
+                format!("max {}\noom {}\noom_kill {}\n", events.max, events.oom, events.oom_kill)
+            }
+            CgroupFile::PidsCurrent => format!("{}\n", cgroup::pids_current(id).unwrap_or(0)),
+            CgroupFile::PidsMax => format!("{}\n", limit(cgroup::pids_max(id))),
+            CgroupFile::PidsEvents => format!("max {}\n", cgroup::pids_events_max(id)),
+        })
+    }
+
+    fn write(&self, text: &str) -> VfsResult<()> {
+        let id = self.id;
+        match self.file {
+            CgroupFile::Procs => {
+                let pid = match text.parse::<Pid>().map_err(|_| VfsError::InvalidArgument)? {
+                    0 => process::current_pid(),
+                    pid => pid,
+                };
+                if !process_alive(pid) {
+                    return Err(VfsError::NotFound);
+                }
+                cgroup::attach(pid, id).map_err(cgroup_error)
+            }
+            CgroupFile::SubtreeControl => {
+                let (mut enable, mut disable) = (0, 0);
+                for word in text.split_whitespace() {
+                    let (set, name) = if let Some(name) = word.strip_prefix('+') {
+                        (&mut enable, name)
+                    } else if let Some(name) = word.strip_prefix('-') {
+                        (&mut disable, name)
+                    } else {
+                        return Err(VfsError::InvalidArgument);
+                    };
+                    let &(bit, _) = cgroup::CONTROLLERS
+                        .iter()
+                        .find(|&&(_, controller)| controller == name)
+                        .ok_or(VfsError::InvalidArgument)?;
+                    *set |= bit;
+                }
+                cgroup::set_subtree_control(id, enable, disable).map_err(cgroup_error)
+            }
+            CgroupFile::CpuWeight => {
+                let weight = text.parse::<u64>().map_err(|_| VfsError::InvalidArgument)?;
+                cgroup::set_cpu_weight(id, weight).map_err(cgroup_error)
+            }
+            CgroupFile::CpuMax => {
+                let mut words = text.split_whitespace();
+                let quota = parse_limit(words.next().ok_or(VfsError::InvalidArgument)?, false)?;
+                let period = match words.next() {
+                    Some(period) => period.parse::<u64>().map_err(|_| VfsError::InvalidArgument)?,
+                    None => cgroup::cpu_max(id).1,
+                };
+                if words.next().is_some() {
+                    return Err(VfsError::InvalidArgument);
+                }
+                cgroup::set_cpu_max(id, quota, period).map_err(cgroup_error)
+            }
+            CgroupFile::MemoryMax => {
+                cgroup::set_memory_max(id, parse_limit(text, true)?).map_err(cgroup_error)?;
+                if let Some(mm) = crate::memory::get_memory_manager() {
+                    mm.enforce_cgroup_limit(id);
+                }
+                Ok(())
+            }
+            CgroupFile::PidsMax => cgroup::set_pids_max(id, parse_limit(text, false)?).map_err(cgroup_error),
+            _ => Err(VfsError::PermissionDenied),
+        }
+    }
+}
+
+impl InodeOps for CgroupFileInode {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let contents = self.contents()?;
+        let bytes = contents.as_bytes();
+        if offset >= bytes.len() as u64 {
+            return Ok(0);
+        }
+        let len = buf.len().min(bytes.len() - offset as usize);
+        buf[..len].copy_from_slice(&bytes[offset as usize..offset as usize + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if !self.file.writable() || current_uid() != 0 {
+            return Err(VfsError::PermissionDenied);
+        }
+        if !cgroup::exists(self.id) {
+            return Err(VfsError::NotFound);
+        }
+        let text = core::str::from_utf8(buf).map_err(|_| VfsError::InvalidArgument)?;
+        self.write(text.trim())?;
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        if !cgroup::exists(self.id) {
+            return Err(VfsError::NotFound);
+        }
+        Ok(Stat {
+            dev: CGROUP_DEV,
+            ino: self.file.ino(self.id),
+            inode_type: InodeType::File,
+            mode: self.file.mode(),
+            nlink: 1,
+            ..Stat::default()
+        })
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult<()> {
+        // O_TRUNC on open is harmless; the value is replaced by the write
+        Ok(())
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn lookup(&self, _name: &str) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType, _mode: u32) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn unlink(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn link(&self, _name: &str, _target: Arc<dyn InodeOps>) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: Arc<dyn InodeOps>, _new_name: &str) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::File
+    }
+}
+
+/// The directory of one group, the root of the mount for the root group
+pub struct CgroupDir {
+    id: CgroupId,
+}
+
+impl InodeOps for CgroupDir {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        if !cgroup::exists(self.id) {
+            return Err(VfsError::NotFound);
+        }
+        Ok(Stat {
+            dev: CGROUP_DEV,
+            ino: dir_ino(self.id),
+            inode_type: InodeType::Directory,
+            mode: 0o755,
+            nlink: 2 + cgroup::children(self.id).len() as u32,
+            ..Stat::default()
+        })
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult<()> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn InodeOps>> {
+        if !cgroup::exists(self.id) {
+            return Err(VfsError::NotFound);
+        }
+        if let Some(file) = CgroupFile::ALL.into_iter().find(|file| file.name() == name) {
+            if !file.present_in(self.id) {
+                return Err(VfsError::NotFound);
+            }
+            return Ok(Arc::new(CgroupFileInode { id: self.id, file }));
+        }
+        let id = cgroup::child(self.id, name).ok_or(VfsError::NotFound)?;
+        Ok(Arc::new(CgroupDir { id }))
+    }
+
+    fn create(&self, name: &str, inode_type: InodeType, _mode: u32) -> VfsResult<Arc<dyn InodeOps>> {
+        if inode_type != InodeType::Directory || current_uid() != 0 {
+            return Err(VfsError::PermissionDenied);
+        }
+        let id = cgroup::create(self.id, name).map_err(cgroup_error)?;
+        Ok(Arc::new(CgroupDir { id }))
+    }
+
+    fn unlink(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn rmdir(&self, name: &str) -> VfsResult<()> {
+        if current_uid() != 0 {
+            return Err(VfsError::PermissionDenied);
+        }
+        cgroup::remove(self.id, name).map_err(cgroup_error)
+    }
+
+    fn link(&self, _name: &str, _target: Arc<dyn InodeOps>) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: Arc<dyn InodeOps>, _new_name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        if !cgroup::exists(self.id) {
+            return Err(VfsError::NotFound);
+        }
+        let mut entries: Vec<DirEntry> = CgroupFile::ALL
+            .into_iter()
+            .filter(|file| file.present_in(self.id))
+            .map(|file| DirEntry { ino: file.ino(self.id), name: file.name().to_string(), inode_type: InodeType::File })
+            .collect();
+        entries.extend(
+            cgroup::children(self.id)
+                .into_iter()
+                .map(|(name, id)| DirEntry { ino: dir_ino(id), name, inode_type: InodeType::Directory }),
+        );
+        Ok(entries)
+    }
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::Directory
+    }
+}
+
+/// cgroup2 superblock
+///
+/// Every mount shows the one hierarchy.
+pub struct CgroupFs {
+    root: Arc<CgroupDir>,
+}
+
+impl CgroupFs {
+    pub fn new() -> Self {
+        Self { root: Arc::new(CgroupDir { id: ROOT_CGROUP }) }
+    }
+}
+
+impl SuperblockOps for CgroupFs {
+    fn root(&self) -> Arc<dyn InodeOps> {
+        Arc::clone(&self.root) as Arc<dyn InodeOps>
+    }
+
+    fn sync_fs(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn statfs(&self) -> VfsResult<StatFs> {
+        Ok(StatFs {
+            fs_type: CGROUP2_SUPER_MAGIC,
+            block_size: 4096,
+            total_blocks: 0,
+            free_blocks: 0,
+            avail_blocks: 0,
+            total_inodes: 0,
+            free_inodes: 0,
+            max_name_len: 255,
+        })
+    }
+}
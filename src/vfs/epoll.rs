@@ -0,0 +1,241 @@
+//! epoll Instances
+//!
+//! An epoll instance is a file holding an interest list of other open files.
+//! Readiness is evaluated by polling each registered file, so the interest
+//! list behaves level-triggered. Each entry also carries a flag set whenever
+//! its file reports an event through `poll::notify_event`; EPOLLET entries
+//! are only reported while that flag is set, and EPOLLONESHOT disarms an
+//! entry once reported.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::anon_inode::{self, anon_inode_ops};
+use super::poll::{EventSource, POLLERR, POLLHUP, POLLIN};
+use super::{InodeOps, InodeType, Stat, VfsError, VfsResult};
+use crate::interrupts::without_interrupts;
+
+/// Report only changes in readiness
+pub const EPOLLET: u32 = 1 << 31;
+/// Disable the entry after one report
+pub const EPOLLONESHOT: u32 = 1 << 30;
+
+/// Readiness bits (shared with poll) that an entry may ask for
+const EVENT_BITS: u32 = 0xffff;
+
+struct Interest {
+    file: Weak<dyn InodeOps>,
+    events: u32,
+    data: u64,
+    /// Set when the file reports an event, cleared when the entry is
+    /// checked for EPOLLET
+    pending: Arc<AtomicBool>,
+    /// Cleared after an EPOLLONESHOT report until re-armed with MOD
+    armed: bool,
+}
+
+impl Interest {
+    fn wanted(&self) -> u16 {
+        (self.events & EVENT_BITS) as u16 | POLLERR | POLLHUP
+    }
+}
+
+/// Event flag of one interest list entry
+struct Watch {
+    /// Source the entry's file reports events through
+    source: EventSource,
+    /// The epoll instance holding the entry
+    owner: EventSource,
+    flag: Weak<AtomicBool>,
+}
+
+/// Flags of every interest list entry. `mark_event` runs from the timer
+/// interrupt, so this is only locked with interrupts disabled.
+static WATCHES: Mutex<Vec<Watch>> = Mutex::new(Vec::new());
+
+/// Register an event flag for an entry of `owner` watching `source`
+///
+/// The flag starts set so that readiness at registration time is reported.
+fn watch(source: EventSource, owner: EventSource) -> Arc<AtomicBool> {
+    let flag = Arc::new(AtomicBool::new(true));
+    without_interrupts(|| {
+        let mut watches = WATCHES.lock();
+        watches.retain(|watch| watch.flag.strong_count() > 0);
+        watches.push(Watch { source, owner, flag: Arc::downgrade(&flag) });
+    });
+    flag
+}
+
+/// Set the event flag of every entry watching `source`
+///
+/// An epoll instance with a marked entry may have become ready itself, so
+/// entries watching it are marked in turn.
+pub(super) fn mark_event(source: EventSource) {
+    without_interrupts(|| {
+        let watches = WATCHES.lock();
+        let mut sources = vec![source];
+        let mut next = 0;
+        while next < sources.len() {
+            let current = sources[next];
+            next += 1;
+            for watch in watches.iter().filter(|watch| watch.source == current) {
+                let Some(flag) = watch.flag.upgrade() else { continue };
+                flag.store(true, Ordering::Release);
+                if !sources.contains(&watch.owner) {
+                    sources.push(watch.owner);
+                }
+            }
+        }
+    });
+}
+
+/// epoll instance
+pub struct Epoll {
+    ino: u64,
+    interest: Mutex<BTreeMap<i32, Interest>>,
+}
+
+impl Epoll {
+    /// Create an empty epoll instance
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            ino: anon_inode::alloc_ino(),
+            interest: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// Register `file` under descriptor number `fd`
+    pub fn add(&self, fd: i32, file: &Arc<dyn InodeOps>, events: u32, data: u64) -> VfsResult<()> {
+        let mut interest = self.interest.lock();
+        if interest.get(&fd).map_or(false, |entry| entry.file.strong_count() > 0) {
+            return Err(VfsError::AlreadyExists);
+        }
+        interest.insert(fd, Interest {
+            file: Arc::downgrade(file),
+            events,
+            data,
+            pending: watch(file.event_source(), EventSource::object(self)),
+            armed: true,
+        });
+        Ok(())
+    }
+
+    /// Change the events and user data of a registered descriptor
+    pub fn modify(&self, fd: i32, events: u32, data: u64) -> VfsResult<()> {
+        let mut interest = self.interest.lock();
+        let entry = interest.get_mut(&fd).ok_or(VfsError::NotFound)?;
+        entry.events = events;
+        entry.data = data;
+        entry.pending.store(true, Ordering::Release);
+        entry.armed = true;
+        Ok(())
+    }
+
+    /// Remove a registered descriptor
+    pub fn remove(&self, fd: i32) -> VfsResult<()> {
+        self.interest.lock().remove(&fd).map(|_| ()).ok_or(VfsError::NotFound)
+    }
+
+    /// Collect up to `max` ready entries as (events, data) pairs
+    ///
+    /// Entries whose file has been closed are dropped from the list.
+    pub fn collect(&self, max: usize) -> Vec<(u32, u64)> {
+        let mut ready = Vec::new();
+        let mut interest = self.interest.lock();
+        interest.retain(|_, entry| entry.file.strong_count() > 0);
+
+        for entry in interest.values_mut() {
+            if ready.len() >= max {
+                break;
+            }
+            if !entry.armed {
+                continue;
+            }
+            let Some(file) = entry.file.upgrade() else { continue };
+
+            // Clear the flag before polling so that an event arriving in
+            // between is reported next time rather than lost
+            if entry.events & EPOLLET != 0 && !entry.pending.swap(false, Ordering::AcqRel) {
+                continue;
+            }
+            let mask = file.poll() & entry.wanted();
+            if mask == 0 {
+                continue;
+            }
+
+            if entry.events & EPOLLONESHOT != 0 {
+                entry.armed = false;
+            }
+            ready.push((mask as u32, entry.data));
+        }
+
+        ready
+    }
+}
+
+impl InodeOps for Epoll {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::InvalidArgument)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::InvalidArgument)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(anon_inode::stat(self.ino, InodeType::File, 0))
+    }
+
+    anon_inode_ops!();
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::File
+    }
+
+    /// An epoll instance is readable while any entry would be reported
+    fn poll(&self) -> u16 {
+        let interest = self.interest.lock();
+        let any_ready = interest.values().any(|entry| {
+            let reportable = entry.armed
+                && (entry.events & EPOLLET == 0 || entry.pending.load(Ordering::Acquire));
+            reportable && entry.file.upgrade().map_or(false, |file| file.poll() & entry.wanted() != 0)
+        });
+        if any_ready { POLLIN } else { 0 }
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::eventfd::EventFd;
+
+    #[test]
+    fn test_edge_triggered_reports_each_event_once() {
+        let epoll = Epoll::new();
+        let file: Arc<dyn InodeOps> = EventFd::new(1, false);
+        epoll.add(3, &file, EPOLLET | POLLIN as u32, 7).unwrap();
+
+        // Readiness at registration is reported once
+        assert_eq!(epoll.collect(8), vec![(POLLIN as u32, 7)]);
+        assert!(epoll.collect(8).is_empty());
+
+        // A further write is a new event even though the mask is unchanged
+        file.write_at(0, &1u64.to_ne_bytes()).unwrap();
+        assert_eq!(epoll.collect(8), vec![(POLLIN as u32, 7)]);
+        assert!(epoll.collect(8).is_empty());
+
+        // Level-triggered entries keep reporting
+        epoll.modify(3, POLLIN as u32, 7).unwrap();
+        assert_eq!(epoll.collect(8).len(), 1);
+        assert_eq!(epoll.collect(8).len(), 1);
+    }
+}
@@ -0,0 +1,113 @@
+//! Event File Descriptors
+//!
+//! An eventfd is a 64-bit counter. Writes add to it; reads return and clear
+//! it, or in semaphore mode return 1 and decrement it.
+
+use alloc::sync::Arc;
+use core::any::Any;
+use spin::Mutex;
+
+use super::anon_inode::{self, anon_inode_ops};
+use super::poll::{self, EventSource, POLLIN, POLLOUT};
+use super::{InodeOps, InodeType, Stat, VfsError, VfsResult};
+
+/// Largest value the counter can hold
+const EVENTFD_MAX: u64 = u64::MAX - 1;
+
+/// eventfd counter object
+pub struct EventFd {
+    ino: u64,
+    count: Mutex<u64>,
+    semaphore: bool,
+}
+
+impl EventFd {
+    /// Create an eventfd with the given initial counter value
+    pub fn new(initval: u64, semaphore: bool) -> Arc<Self> {
+        Arc::new(Self {
+            ino: anon_inode::alloc_ino(),
+            count: Mutex::new(initval),
+            semaphore,
+        })
+    }
+
+    /// Current counter value
+    pub fn count(&self) -> u64 {
+        *self.count.lock()
+    }
+}
+
+impl InodeOps for EventFd {
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if buf.len() < 8 {
+            return Err(VfsError::InvalidArgument);
+        }
+
+        let value = {
+            let mut count = self.count.lock();
+            if *count == 0 {
+                return Err(VfsError::WouldBlock);
+            }
+            if self.semaphore {
+                *count -= 1;
+                1
+            } else {
+                core::mem::take(&mut *count)
+            }
+        };
+
+        buf[..8].copy_from_slice(&value.to_ne_bytes());
+        poll::notify_event(EventSource::object(self));
+        Ok(8)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if buf.len() < 8 {
+            return Err(VfsError::InvalidArgument);
+        }
+
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&buf[..8]);
+        let value = u64::from_ne_bytes(bytes);
+        if value == u64::MAX {
+            return Err(VfsError::InvalidArgument);
+        }
+
+        {
+            let mut count = self.count.lock();
+            if value > EVENTFD_MAX - *count {
+                return Err(VfsError::WouldBlock);
+            }
+            *count += value;
+        }
+
+        poll::notify_event(EventSource::object(self));
+        Ok(8)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(anon_inode::stat(self.ino, InodeType::File, 0))
+    }
+
+    anon_inode_ops!();
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::File
+    }
+
+    fn poll(&self) -> u16 {
+        let count = *self.count.lock();
+        let mut events = 0;
+        if count > 0 {
+            events |= POLLIN;
+        }
+        if count < EVENTFD_MAX {
+            events |= POLLOUT;
+        }
+        events
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
@@ -4,8 +4,12 @@
 
 use alloc::sync::Arc;
 use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicU64, Ordering};
 use super::{InodeOps, OpenFlags, VfsResult, VfsError};
 
+/// Source of open file description identifiers
+static NEXT_DESCRIPTION: AtomicU64 = AtomicU64::new(1);
+
 /// Open file descriptor
 pub struct FileDescriptor {
     /// Inode this descriptor refers to
@@ -14,15 +18,38 @@ pub struct FileDescriptor {
     pub flags: OpenFlags,
     /// Current file offset
     pub offset: u64,
+    /// Close-on-exec (FD_CLOEXEC); belongs to this descriptor only
+    pub cloexec: bool,
+    /// Open file description this descriptor was opened or duplicated from
+    pub description: u64,
+    /// Process (or negated process group) receiving SIGIO/SIGURG
+    pub owner: i32,
 }
 
 impl FileDescriptor {
     /// Create a new file descriptor
+    ///
+    /// `OpenFlags::CLOEXEC` in `flags` sets the close-on-exec flag.
     pub fn new(inode: Arc<dyn InodeOps>, flags: OpenFlags) -> Self {
         Self {
             inode,
-            flags,
+            flags: OpenFlags::new(flags.bits() & !OpenFlags::CLOEXEC),
             offset: 0,
+            cloexec: flags.has_flag(OpenFlags::CLOEXEC),
+            description: NEXT_DESCRIPTION.fetch_add(1, Ordering::Relaxed),
+            owner: 0,
+        }
+    }
+
+    /// Another descriptor for the same open file description
+    fn duplicate(&self, cloexec: bool) -> Self {
+        Self {
+            inode: Arc::clone(&self.inode),
+            flags: self.flags,
+            offset: self.offset,
+            cloexec,
+            description: self.description,
+            owner: self.owner,
         }
     }
 }
@@ -86,42 +113,62 @@ impl OpenFileTable {
 
     /// Remove a file descriptor
     pub fn remove(&mut self, fd: i32) -> VfsResult<()> {
-        self.files.remove(&fd).ok_or(VfsError::BadFileDescriptor)?;
+        self.take(fd)?;
         Ok(())
     }
 
+    /// Remove a file descriptor, returning it
+    pub fn take(&mut self, fd: i32) -> VfsResult<FileDescriptor> {
+        self.files.remove(&fd).ok_or(VfsError::BadFileDescriptor)
+    }
+
+    /// Whether any descriptor still refers to an open file description
+    pub fn description_open(&self, description: u64) -> bool {
+        self.files.values().any(|file| file.description == description)
+    }
+
+    /// Every descriptor sharing an open file description
+    pub fn description_mut(&mut self, description: u64) -> impl Iterator<Item = &mut FileDescriptor> + '_ {
+        self.files.values_mut().filter(move |file| file.description == description)
+    }
+
     /// Duplicate a file descriptor
     pub fn duplicate(&mut self, fd: i32) -> VfsResult<i32> {
-        let file = self.get(fd)?;
-        let new_file = FileDescriptor {
-            inode: Arc::clone(&file.inode),
-            flags: file.flags,
-            offset: file.offset,
-        };
-
+        let new_file = self.get(fd)?.duplicate(false);
         self.insert(new_file)
     }
 
+    /// Duplicate a file descriptor to the lowest free number >= `min_fd`
+    pub fn duplicate_from(&mut self, fd: i32, min_fd: i32, cloexec: bool) -> VfsResult<i32> {
+        if min_fd < 0 || min_fd >= Self::MAX_FILES {
+            return Err(VfsError::InvalidArgument);
+        }
+
+        let new_file = self.get(fd)?.duplicate(cloexec);
+        let newfd = (min_fd..Self::MAX_FILES)
+            .find(|candidate| !self.files.contains_key(candidate))
+            .ok_or(VfsError::TooManyFiles)?;
+        self.insert_at(newfd, new_file)?;
+        Ok(newfd)
+    }
+
     /// Duplicate a file descriptor to a specific fd number
-    pub fn duplicate_to(&mut self, oldfd: i32, newfd: i32) -> VfsResult<i32> {
+    ///
+    /// Returns the descriptor previously open as `newfd`, if any.
+    pub fn duplicate_to(&mut self, oldfd: i32, newfd: i32, cloexec: bool) -> VfsResult<Option<FileDescriptor>> {
         if oldfd == newfd {
             // Verify oldfd exists
             self.get(oldfd)?;
-            return Ok(newfd);
+            return Ok(None);
         }
 
-        let file = self.get(oldfd)?;
-        let new_file = FileDescriptor {
-            inode: Arc::clone(&file.inode),
-            flags: file.flags,
-            offset: file.offset,
-        };
+        let new_file = self.get(oldfd)?.duplicate(cloexec);
 
         // Close newfd if it exists
-        let _ = self.remove(newfd);
+        let replaced = self.take(newfd).ok();
 
         self.insert_at(newfd, new_file)?;
-        Ok(newfd)
+        Ok(replaced)
     }
 
     /// Allocate a new file descriptor number
@@ -0,0 +1,266 @@
+//! Huge Page Filesystem (hugetlbfs)
+//!
+//! Files whose memory is 2 MiB pages from the huge page pool. Like Linux
+//! hugetlbfs, files are sized with ftruncate and filled through mmap;
+//! read works but write does not. The filesystem is a single flat
+//! directory.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use spin::RwLock;
+
+use super::{DirEntry, InodeOps, InodeType, Stat, StatFs, SuperblockOps, VfsError, VfsResult};
+use crate::memory::huge_page::{self, HugePages};
+use crate::memory::HUGE_PAGE_SIZE;
+
+/// HUGETLBFS_MAGIC
+const HUGETLBFS_MAGIC: u64 = 0x958458f6;
+
+/// A file on hugetlbfs
+pub struct HugeTlbFile {
+    dev: u64,
+    ino: u64,
+    mode: u32,
+    size: RwLock<u64>,
+    nlink: RwLock<u32>,
+    pages: Arc<HugePages>,
+}
+
+impl HugeTlbFile {
+    fn new(dev: u64, ino: u64, mode: u32) -> Arc<Self> {
+        Arc::new(Self { dev, ino, mode, size: RwLock::new(0), nlink: RwLock::new(1), pages: HugePages::new() })
+    }
+
+    /// The file's huge pages, for mmap
+    pub fn pages(&self) -> Arc<HugePages> {
+        Arc::clone(&self.pages)
+    }
+
+    /// Grow the file to at least `size` bytes, as mapping past its end does
+    pub fn extend(&self, size: u64) {
+        let mut current = self.size.write();
+        *current = (*current).max(size);
+    }
+}
+
+impl InodeOps for HugeTlbFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let size = *self.size.read();
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        self.pages.read(offset, &mut buf[..len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        // hugetlbfs files are written through mappings only
+        Err(VfsError::InvalidArgument)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat {
+            dev: self.dev,
+            ino: self.ino,
+            inode_type: InodeType::File,
+            size: *self.size.read(),
+            blksize: HUGE_PAGE_SIZE as u64,
+            blocks: (self.pages.resident() * HUGE_PAGE_SIZE / 512) as u64,
+            mode: self.mode,
+            nlink: *self.nlink.read(),
+            ..Stat::default()
+        })
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult<()> {
+        let mut current = self.size.write();
+        if size < *current {
+            self.pages.truncate(size.div_ceil(HUGE_PAGE_SIZE as u64));
+        }
+        *current = size;
+        Ok(())
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn lookup(&self, _name: &str) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType, _mode: u32) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn unlink(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn link(&self, _name: &str, _target: Arc<dyn InodeOps>) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: Arc<dyn InodeOps>, _new_name: &str) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::File
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+/// The root directory of a hugetlbfs mount
+pub struct HugeTlbDir {
+    dev: u64,
+    entries: RwLock<BTreeMap<String, Arc<HugeTlbFile>>>,
+}
+
+impl InodeOps for HugeTlbDir {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat {
+            dev: self.dev,
+            ino: 1,
+            inode_type: InodeType::Directory,
+            size: self.entries.read().len() as u64,
+            mode: 0o755,
+            nlink: 2,
+            ..Stat::default()
+        })
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult<()> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn InodeOps>> {
+        self.entries
+            .read()
+            .get(name)
+            .map(|file| Arc::clone(file) as Arc<dyn InodeOps>)
+            .ok_or(VfsError::NotFound)
+    }
+
+    fn create(&self, name: &str, inode_type: InodeType, mode: u32) -> VfsResult<Arc<dyn InodeOps>> {
+        if name.len() > 255 {
+            return Err(VfsError::NameTooLong);
+        }
+        if name.contains('/') || name == "." || name == ".." {
+            return Err(VfsError::InvalidArgument);
+        }
+        if inode_type != InodeType::File {
+            return Err(VfsError::NotSupported);
+        }
+
+        let mut entries = self.entries.write();
+        if entries.contains_key(name) {
+            return Err(VfsError::AlreadyExists);
+        }
+        let file = HugeTlbFile::new(self.dev, super::get_vfs().alloc_ino(), mode);
+        entries.insert(String::from(name), Arc::clone(&file));
+        Ok(file as Arc<dyn InodeOps>)
+    }
+
+    fn unlink(&self, name: &str) -> VfsResult<()> {
+        let file = self.entries.write().remove(name).ok_or(VfsError::NotFound)?;
+        *file.nlink.write() -= 1;
+        // The pages stay with the file until its last mapping goes
+        Ok(())
+    }
+
+    fn link(&self, _name: &str, _target: Arc<dyn InodeOps>) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    fn rename(&self, old_name: &str, new_dir: Arc<dyn InodeOps>, new_name: &str) -> VfsResult<()> {
+        let same_dir = new_dir
+            .as_any()
+            .and_then(|dir| dir.downcast_ref::<HugeTlbDir>())
+            .is_some_and(|dir| core::ptr::eq(dir, self));
+        if !same_dir {
+            return Err(VfsError::CrossDevice);
+        }
+
+        let mut entries = self.entries.write();
+        let file = entries.remove(old_name).ok_or(VfsError::NotFound)?;
+        entries.insert(String::from(new_name), file);
+        Ok(())
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        Ok(self
+            .entries
+            .read()
+            .iter()
+            .map(|(name, file)| DirEntry { ino: file.ino, name: name.clone(), inode_type: InodeType::File })
+            .collect())
+    }
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::Directory
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+/// hugetlbfs superblock
+pub struct HugeTlbFs {
+    root: Arc<HugeTlbDir>,
+}
+
+impl HugeTlbFs {
+    pub fn new() -> Self {
+        let dev = super::get_vfs().alloc_dev();
+        Self { root: Arc::new(HugeTlbDir { dev, entries: RwLock::new(BTreeMap::new()) }) }
+    }
+}
+
+impl SuperblockOps for HugeTlbFs {
+    fn root(&self) -> Arc<dyn InodeOps> {
+        Arc::clone(&self.root) as Arc<dyn InodeOps>
+    }
+
+    fn sync_fs(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn statfs(&self) -> VfsResult<StatFs> {
+        let stats = huge_page::stats();
+        Ok(StatFs {
+            fs_type: HUGETLBFS_MAGIC,
+            block_size: HUGE_PAGE_SIZE as u64,
+            total_blocks: stats.pool_total as u64,
+            free_blocks: stats.pool_free as u64,
+            avail_blocks: stats.pool_free as u64,
+            total_inodes: 0,
+            free_inodes: 0,
+            max_name_len: 255,
+        })
+    }
+}
@@ -0,0 +1,343 @@
+//! Advisory File Locks
+//!
+//! Two independent kinds of advisory lock are kept per inode:
+//!
+//! - POSIX byte-range locks (fcntl F_SETLK/F_SETLKW), owned by a process.
+//!   A process's locks on an inode are dropped when it closes any descriptor
+//!   for that inode, or exits. Blocking requests are checked for deadlock
+//!   against the chain of processes they would wait on.
+//! - BSD whole-file locks (flock), owned by an open file description and
+//!   dropped when its last descriptor is closed.
+//!
+//! Neither kind restricts reads or writes; they only exclude other lockers.
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::{InodeOps, VfsError, VfsResult};
+use crate::process::wait_queue::WaitQueue;
+use crate::process::Pid;
+
+/// Last byte of a lock that extends to end of file and beyond
+pub const OFFSET_MAX: u64 = u64::MAX;
+
+/// Lock mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockKind {
+    /// Read lock; any number may overlap
+    Shared,
+    /// Write lock; excludes every other lock
+    Exclusive,
+}
+
+/// A POSIX byte-range lock covering `start..=end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordLock {
+    pub owner: Pid,
+    pub kind: LockKind,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl RecordLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start <= end && start <= self.end
+    }
+
+    /// Parts of this lock left after removing `start..=end`
+    fn subtract(&self, start: u64, end: u64) -> impl Iterator<Item = RecordLock> {
+        let (left, right) = if self.overlaps(start, end) {
+            let left = (self.start < start).then(|| RecordLock { end: start - 1, ..*self });
+            let right = (self.end > end).then(|| RecordLock { start: end + 1, ..*self });
+            (left, right)
+        } else {
+            (Some(*self), None)
+        };
+        left.into_iter().chain(right)
+    }
+}
+
+fn conflicts(held: LockKind, wanted: LockKind) -> bool {
+    held == LockKind::Exclusive || wanted == LockKind::Exclusive
+}
+
+#[derive(Debug, Default)]
+struct InodeLocks {
+    records: Vec<RecordLock>,
+    /// flock locks by open file description
+    flocks: BTreeMap<u64, LockKind>,
+}
+
+impl InodeLocks {
+    fn is_empty(&self) -> bool {
+        self.records.is_empty() && self.flocks.is_empty()
+    }
+
+    fn record_conflict(&self, owner: Pid, kind: LockKind, start: u64, end: u64) -> Option<RecordLock> {
+        self.records
+            .iter()
+            .find(|lock| lock.owner != owner && lock.overlaps(start, end) && conflicts(lock.kind, kind))
+            .copied()
+    }
+
+    /// Replace `owner`'s locks over `start..=end` with `kind`, or remove them
+    ///
+    /// Returns true if an existing lock of `owner` was replaced, which may
+    /// unblock other lockers.
+    fn apply_record(&mut self, owner: Pid, kind: Option<LockKind>, start: u64, end: u64) -> bool {
+        let (mut mine, others): (Vec<_>, Vec<_>) = core::mem::take(&mut self.records)
+            .into_iter()
+            .partition(|lock| lock.owner == owner);
+
+        let replaced = mine.iter().any(|lock| lock.overlaps(start, end));
+
+        mine = mine.iter().flat_map(|lock| lock.subtract(start, end)).collect();
+        if let Some(kind) = kind {
+            mine.push(RecordLock { owner, kind, start, end });
+        }
+
+        // Coalesce adjacent and overlapping ranges of the same kind
+        mine.sort_by_key(|lock| lock.start);
+        let mut merged: Vec<RecordLock> = Vec::with_capacity(mine.len());
+        for lock in mine {
+            match merged.last_mut() {
+                Some(last) if last.kind == lock.kind && lock.start <= last.end.saturating_add(1) => {
+                    last.end = last.end.max(lock.end);
+                }
+                _ => merged.push(lock),
+            }
+        }
+
+        self.records = others;
+        self.records.extend(merged);
+        replaced
+    }
+
+    fn flock_conflict(&self, description: u64, kind: LockKind) -> bool {
+        self.flocks
+            .iter()
+            .any(|(&holder, &held)| holder != description && conflicts(held, kind))
+    }
+}
+
+struct LockTable {
+    /// Locks by inode address
+    inodes: BTreeMap<usize, InodeLocks>,
+    /// Process each blocked F_SETLKW caller is waiting on
+    waits_for: BTreeMap<Pid, Pid>,
+}
+
+impl LockTable {
+    fn prune(&mut self, key: usize) {
+        if self.inodes.get(&key).map_or(false, InodeLocks::is_empty) {
+            self.inodes.remove(&key);
+        }
+    }
+
+    /// Would `owner` waiting on `blocker` close a cycle of waiters?
+    fn would_deadlock(&self, owner: Pid, blocker: Pid) -> bool {
+        let mut current = blocker;
+        // Every hop visits a distinct waiter, so the chain is bounded
+        for _ in 0..=self.waits_for.len() {
+            if current == owner {
+                return true;
+            }
+            match self.waits_for.get(&current) {
+                Some(&next) => current = next,
+                None => return false,
+            }
+        }
+        false
+    }
+}
+
+static LOCKS: Mutex<LockTable> = Mutex::new(LockTable {
+    inodes: BTreeMap::new(),
+    waits_for: BTreeMap::new(),
+});
+
+/// Callers blocked in F_SETLKW or flock
+static LOCK_WAIT: WaitQueue = WaitQueue::new();
+
+/// Locks are keyed by inode identity; an inode stays alive while any
+/// descriptor, and so any lock, refers to it
+fn inode_key(inode: &Arc<dyn InodeOps>) -> usize {
+    Arc::as_ptr(inode) as *const () as usize
+}
+
+/// Run `attempt` once, or until it completes if `wait` is set
+fn acquire<F>(wait: bool, mut attempt: F) -> VfsResult<()>
+where
+    F: FnMut() -> Option<VfsResult<()>>,
+{
+    if !wait {
+        return attempt().unwrap_or(Err(VfsError::WouldBlock));
+    }
+    LOCK_WAIT
+        .wait_until(attempt)
+        .unwrap_or(Err(VfsError::Interrupted))
+}
+
+// ============================================================================
+// POSIX record locks
+// ============================================================================
+
+/// First lock that would prevent `owner` from locking `start..=end`
+pub fn test_record(
+    inode: &Arc<dyn InodeOps>,
+    owner: Pid,
+    kind: LockKind,
+    start: u64,
+    end: u64,
+) -> Option<RecordLock> {
+    let table = LOCKS.lock();
+    table
+        .inodes
+        .get(&inode_key(inode))
+        .and_then(|locks| locks.record_conflict(owner, kind, start, end))
+}
+
+/// Lock `start..=end` for `owner`, or unlock it when `kind` is None
+///
+/// A conflicting lock fails with `WouldBlock` unless `wait` is set, in which
+/// case the caller sleeps until the range is free. Waiting on a process that
+/// is itself waiting on `owner` fails with `Deadlock`.
+pub fn set_record(
+    inode: &Arc<dyn InodeOps>,
+    owner: Pid,
+    kind: Option<LockKind>,
+    start: u64,
+    end: u64,
+    wait: bool,
+) -> VfsResult<()> {
+    if start > end {
+        return Err(VfsError::InvalidArgument);
+    }
+    let key = inode_key(inode);
+
+    let Some(kind) = kind else {
+        let mut table = LOCKS.lock();
+        if let Some(locks) = table.inodes.get_mut(&key) {
+            locks.apply_record(owner, None, start, end);
+        }
+        table.prune(key);
+        drop(table);
+        LOCK_WAIT.wake_all();
+        return Ok(());
+    };
+
+    let result = acquire(wait, || {
+        let mut table = LOCKS.lock();
+        let blocker = table
+            .inodes
+            .get(&key)
+            .and_then(|locks| locks.record_conflict(owner, kind, start, end));
+
+        match blocker {
+            None => {
+                table.waits_for.remove(&owner);
+                let released = table.inodes.entry(key).or_default().apply_record(owner, Some(kind), start, end);
+                drop(table);
+                if released {
+                    LOCK_WAIT.wake_all();
+                }
+                Some(Ok(()))
+            }
+            Some(_) if !wait => None,
+            Some(blocker) if table.would_deadlock(owner, blocker.owner) => Some(Err(VfsError::Deadlock)),
+            Some(blocker) => {
+                table.waits_for.insert(owner, blocker.owner);
+                None
+            }
+        }
+    });
+
+    LOCKS.lock().waits_for.remove(&owner);
+    result
+}
+
+// ============================================================================
+// flock locks
+// ============================================================================
+
+/// Take, convert or (with `kind` None) drop the flock lock of an open file
+/// description
+///
+/// Converting an existing lock releases it first, so other waiters may get
+/// in between, as on Linux.
+pub fn flock(inode: &Arc<dyn InodeOps>, description: u64, kind: Option<LockKind>, wait: bool) -> VfsResult<()> {
+    let key = inode_key(inode);
+
+    let released = {
+        let mut table = LOCKS.lock();
+        let released = table
+            .inodes
+            .get_mut(&key)
+            .and_then(|locks| locks.flocks.remove(&description))
+            .is_some();
+        table.prune(key);
+        released
+    };
+    if released {
+        LOCK_WAIT.wake_all();
+    }
+
+    let Some(kind) = kind else { return Ok(()) };
+
+    acquire(wait, || {
+        let mut table = LOCKS.lock();
+        let locks = table.inodes.entry(key).or_default();
+        if locks.flock_conflict(description, kind) {
+            table.prune(key);
+            return None;
+        }
+        locks.flocks.insert(description, kind);
+        Some(Ok(()))
+    })
+}
+
+// ============================================================================
+// Release
+// ============================================================================
+
+/// Drop the locks a close releases
+///
+/// Closing any descriptor for an inode drops the closing process's record
+/// locks on it; closing the last descriptor of an open file description
+/// drops that description's flock lock.
+pub fn file_closed(inode: &Arc<dyn InodeOps>, owner: Pid, description: u64, last_reference: bool) {
+    let key = inode_key(inode);
+    let released = {
+        let mut table = LOCKS.lock();
+        let Some(locks) = table.inodes.get_mut(&key) else { return };
+
+        let before = locks.records.len();
+        locks.records.retain(|lock| lock.owner != owner);
+        let mut released = locks.records.len() != before;
+
+        if last_reference {
+            released |= locks.flocks.remove(&description).is_some();
+        }
+        table.prune(key);
+        released
+    };
+
+    if released {
+        LOCK_WAIT.wake_all();
+    }
+}
+
+/// Drop every record lock held by an exiting process
+pub fn release_process(pid: Pid) {
+    {
+        let mut table = LOCKS.lock();
+        table.waits_for.remove(&pid);
+        for locks in table.inodes.values_mut() {
+            locks.records.retain(|lock| lock.owner != pid);
+        }
+        table.inodes.retain(|_, locks| !locks.is_empty());
+    }
+    LOCK_WAIT.wake_all();
+}
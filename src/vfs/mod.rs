@@ -13,16 +13,32 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use alloc::boxed::Box;
 use alloc::collections::BTreeMap;
+use core::any::Any;
 use core::sync::atomic::{AtomicU64, Ordering};
 use spin::{Mutex, RwLock};
 
+use crate::process::namespace::{self, NsId};
+
 pub mod ramfs;
 pub mod file_descriptor;
+pub mod pipe;
+pub mod poll;
+pub mod anon_inode;
+pub mod eventfd;
+pub mod timerfd;
+pub mod signalfd;
+pub mod epoll;
+pub mod lock;
+pub mod xattr;
+pub mod hugetlbfs;
+pub mod procfs;
+pub mod cgroupfs;
 
 #[cfg(test)]
 pub mod examples;
 
 pub use file_descriptor::{FileDescriptor, OpenFileTable};
+pub use pipe::{Pipe, PipeSegment, PIPE_BUF};
 
 /// VFS error type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -57,6 +73,20 @@ pub enum VfsError {
     ReadOnly,
     /// Operation not supported
     NotSupported,
+    /// Operation would block on a non-blocking descriptor
+    WouldBlock,
+    /// Write to a pipe with no readers
+    BrokenPipe,
+    /// Blocking operation interrupted by a signal
+    Interrupted,
+    /// No such device (FIFO opened for writing without a reader)
+    NoDevice,
+    /// Blocking lock request would deadlock
+    Deadlock,
+    /// No such extended attribute
+    NoData,
+    /// Resource is in use
+    Busy,
 }
 
 pub type VfsResult<T> = Result<T, VfsError>;
@@ -96,6 +126,11 @@ impl OpenFlags {
     pub const APPEND: u32 = 0x800;
     pub const NONBLOCK: u32 = 0x1000;
     pub const DIRECTORY: u32 = 0x10000;
+    /// Descriptor flag rather than a status flag; see `FileDescriptor::cloexec`
+    pub const CLOEXEC: u32 = 0x80000;
+
+    /// Status flags F_SETFL may change
+    pub const SETFL_MASK: u32 = Self::APPEND | Self::NONBLOCK;
 
     pub const fn new(bits: u32) -> Self {
         Self { bits }
@@ -132,6 +167,8 @@ pub enum SeekFrom {
 /// File statistics
 #[derive(Debug, Clone, Copy)]
 pub struct Stat {
+    /// Device of the filesystem holding the inode
+    pub dev: u64,
     /// Inode number
     pub ino: u64,
     /// File type
@@ -163,6 +200,7 @@ pub struct Stat {
 impl Default for Stat {
     fn default() -> Self {
         Self {
+            dev: ANON_DEV,
             ino: 0,
             inode_type: InodeType::File,
             size: 0,
@@ -225,11 +263,75 @@ pub trait InodeOps: Send + Sync {
     /// Rename an entry
     fn rename(&self, old_name: &str, new_dir: Arc<dyn InodeOps>, new_name: &str) -> VfsResult<()>;
 
+    /// Remove an empty subdirectory
+    ///
+    /// Filesystems whose directories are never empty of files, like
+    /// cgroupfs, decide for themselves what counts as empty.
+    fn rmdir(&self, name: &str) -> VfsResult<()> {
+        let inode = self.lookup(name)?;
+        if inode.inode_type() != InodeType::Directory {
+            return Err(VfsError::NotDirectory);
+        }
+        if !inode.readdir()?.is_empty() {
+            return Err(VfsError::NotSupported); // Should be ENOTEMPTY
+        }
+        self.unlink(name)
+    }
+
     /// Read directory entries
     fn readdir(&self) -> VfsResult<Vec<DirEntry>>;
 
     /// Get the inode type
     fn inode_type(&self) -> InodeType;
+
+    /// Get the pipe behind this inode, if it is an open pipe or FIFO end
+    fn as_pipe(&self) -> Option<Arc<Pipe>> {
+        None
+    }
+
+    /// Report readiness as a mask of `poll::POLL*` bits
+    ///
+    /// Regular files and directories never block.
+    fn poll(&self) -> u16 {
+        poll::POLLIN | poll::POLLOUT
+    }
+
+    /// Source whose `poll::notify_event` reports readiness changes of this
+    /// inode
+    ///
+    /// Defaults to the inode itself.
+    fn event_source(&self) -> poll::EventSource {
+        poll::EventSource::object(self)
+    }
+
+    /// Access the concrete file object, for syscalls that operate on one
+    /// kind of file (timerfd_settime, epoll_ctl, ...)
+    fn as_any(&self) -> Option<&dyn Any> {
+        None
+    }
+
+    /// Get the value of an extended attribute
+    ///
+    /// Namespace permissions are checked by `xattr::get` before this is
+    /// called; the xattr methods only store and fetch.
+    fn getxattr(&self, _name: &str) -> VfsResult<Vec<u8>> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Create or replace an extended attribute
+    fn setxattr(&self, _name: &str, _value: &[u8], _mode: xattr::SetMode) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// List the names of all extended attributes
+    fn listxattr(&self) -> VfsResult<Vec<String>> {
+        Err(VfsError::NotSupported)
+    }
+
+    /// Remove an extended attribute
+    fn removexattr(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::NotSupported)
+    }
 }
 
 /// Superblock operations trait
@@ -267,7 +369,20 @@ pub struct StatFs {
     pub max_name_len: u64,
 }
 
+/// Device of anonymous inodes: pipes, eventfds, epoll instances, ...
+pub const ANON_DEV: u64 = 0;
+
+/// Device of procfs
+pub const PROC_DEV: u64 = 1;
+
+/// Device of cgroupfs
+pub const CGROUP_DEV: u64 = 2;
+
+/// First device handed out by `Vfs::alloc_dev`
+const FIRST_DYNAMIC_DEV: u64 = 16;
+
 /// VFS mount point
+#[derive(Clone)]
 struct MountPoint {
     /// Mount path
     path: String,
@@ -277,21 +392,26 @@ struct MountPoint {
 
 /// Global VFS state
 pub struct Vfs {
-    /// Mounted filesystems
-    mounts: RwLock<Vec<MountPoint>>,
+    /// Mounted filesystems of each mount namespace. A new namespace starts
+    /// with a copy of the table of the one it came from; later mounts and
+    /// unmounts on either side stay there.
+    mounts: RwLock<BTreeMap<NsId, Vec<MountPoint>>>,
     /// Global open file table
     file_table: Mutex<OpenFileTable>,
     /// Next inode number
     next_ino: AtomicU64,
+    /// Next device number
+    next_dev: AtomicU64,
 }
 
 impl Vfs {
     /// Create a new VFS instance
     pub const fn new() -> Self {
         Self {
-            mounts: RwLock::new(Vec::new()),
+            mounts: RwLock::new(BTreeMap::new()),
             file_table: Mutex::new(OpenFileTable::new()),
             next_ino: AtomicU64::new(1),
+            next_dev: AtomicU64::new(FIRST_DYNAMIC_DEV),
         }
     }
 
@@ -302,11 +422,20 @@ impl Vfs {
         let root_sb = Arc::new(root_fs);
 
         // Mount at "/"
-        let mut mounts = self.mounts.write();
+        let mut tables = self.mounts.write();
+        let mounts = tables.entry(namespace::INIT_NS).or_default();
         mounts.push(MountPoint {
             path: String::from("/"),
             sb: root_sb,
         });
+        mounts.push(MountPoint {
+            path: String::from("/proc"),
+            sb: Arc::new(procfs::ProcFs::new()),
+        });
+        mounts.push(MountPoint {
+            path: String::from("/sys/fs/cgroup"),
+            sb: Arc::new(cgroupfs::CgroupFs::new()),
+        });
 
         Ok(())
     }
@@ -316,9 +445,32 @@ impl Vfs {
         self.next_ino.fetch_add(1, Ordering::SeqCst)
     }
 
+    /// Allocate a device number for a new filesystem instance
+    pub fn alloc_dev(&self) -> u64 {
+        self.next_dev.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// Mount namespace of the caller
+    fn mount_namespace() -> NsId {
+        namespace::of(crate::process::current_pid()).mnt
+    }
+
+    /// Start mount namespace `to` with a copy of the mounts of `from`
+    pub fn copy_mount_namespace(&self, from: NsId, to: NsId) {
+        let mut tables = self.mounts.write();
+        let mounts = tables.get(&from).cloned().unwrap_or_default();
+        tables.insert(to, mounts);
+    }
+
+    /// Forget the mounts of a mount namespace that is gone
+    pub fn drop_mount_namespace(&self, ns: NsId) {
+        self.mounts.write().remove(&ns);
+    }
+
     /// Mount a filesystem at the given path
     pub fn mount(&self, path: &str, sb: Arc<dyn SuperblockOps>) -> VfsResult<()> {
-        let mut mounts = self.mounts.write();
+        let mut tables = self.mounts.write();
+        let mounts = tables.entry(Self::mount_namespace()).or_default();
 
         // Check if path already mounted
         if mounts.iter().any(|m| m.path == path) {
@@ -333,17 +485,41 @@ impl Vfs {
         Ok(())
     }
 
+    /// Unmount the filesystem mounted at the given path
+    ///
+    /// The root filesystem and filesystems with others mounted below them
+    /// stay.
+    pub fn umount(&self, path: &str) -> VfsResult<()> {
+        let path = if path.len() > 1 { path.trim_end_matches('/') } else { path };
+        let mut tables = self.mounts.write();
+        let mounts = tables.get_mut(&Self::mount_namespace()).ok_or(VfsError::NotFound)?;
+        let index = mounts.iter().position(|m| m.path == path).ok_or(VfsError::InvalidArgument)?;
+
+        let below = |m: &MountPoint| m.path.strip_prefix(path).is_some_and(|rest| rest.starts_with('/'));
+        if path == "/" || mounts.iter().any(below) {
+            return Err(VfsError::Busy);
+        }
+
+        mounts.remove(index);
+        Ok(())
+    }
+
     /// Resolve a path to an inode
     fn resolve_path(&self, path: &str) -> VfsResult<Arc<dyn InodeOps>> {
         if path.is_empty() {
             return Err(VfsError::InvalidArgument);
         }
 
-        let mounts = self.mounts.read();
+        let tables = self.mounts.read();
+        let mounts = tables.get(&Self::mount_namespace()).ok_or(VfsError::NotFound)?;
 
         // Find the mount point (longest matching prefix)
         let mount = mounts.iter()
-            .filter(|m| path.starts_with(&m.path))
+            .filter(|m| {
+                // "/proc" covers "/proc/1" but not "/procedures"
+                path.strip_prefix(m.path.as_str())
+                    .is_some_and(|rest| m.path.ends_with('/') || rest.is_empty() || rest.starts_with('/'))
+            })
             .max_by_key(|m| m.path.len())
             .ok_or(VfsError::NotFound)?;
 
@@ -428,6 +604,12 @@ impl Vfs {
             return Err(VfsError::NotDirectory);
         }
 
+        // FIFOs are opened through the pipe that backs them
+        if inode.inode_type() == InodeType::Fifo {
+            let end = pipe::open_fifo(&inode, flags)?;
+            return self.install(end, flags);
+        }
+
         // Truncate if requested
         if flags.has_flag(OpenFlags::TRUNC) && flags.is_writable() {
             inode.truncate(0)?;
@@ -440,46 +622,133 @@ impl Vfs {
         Ok(fd)
     }
 
+    /// Install an already-open inode in the file table
+    pub fn install(&self, inode: Arc<dyn InodeOps>, flags: OpenFlags) -> VfsResult<i32> {
+        let mut file_table = self.file_table.lock();
+        file_table.insert(FileDescriptor::new(inode, flags))
+    }
+
+    /// Create an anonymous pipe, returning its (read, write) descriptors
+    pub fn pipe(&self, flags: u32) -> VfsResult<(i32, i32)> {
+        let (reader, writer) = pipe::create_pipe();
+        let mut file_table = self.file_table.lock();
+        let read_fd = file_table.insert(FileDescriptor::new(
+            reader,
+            OpenFlags::new(OpenFlags::RDONLY | flags),
+        ))?;
+        match file_table.insert(FileDescriptor::new(writer, OpenFlags::new(OpenFlags::WRONLY | flags))) {
+            Ok(write_fd) => Ok((read_fd, write_fd)),
+            Err(e) => {
+                let _ = file_table.remove(read_fd);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get the inode, flags and offset behind a file descriptor
+    pub fn file(&self, fd: i32) -> VfsResult<(Arc<dyn InodeOps>, OpenFlags, u64)> {
+        let file_table = self.file_table.lock();
+        let file_desc = file_table.get(fd)?;
+        Ok((Arc::clone(&file_desc.inode), file_desc.flags, file_desc.offset))
+    }
+
+    /// Set the offset of a file descriptor without validation
+    pub fn set_offset(&self, fd: i32, offset: u64) -> VfsResult<()> {
+        let mut file_table = self.file_table.lock();
+        file_table.get_mut(fd)?.offset = offset;
+        Ok(())
+    }
+
     /// Close a file descriptor
     pub fn close(&self, fd: i32) -> VfsResult<()> {
-        let mut file_table = self.file_table.lock();
-        file_table.remove(fd)
+        let (file, last_reference) = {
+            let mut file_table = self.file_table.lock();
+            let file = file_table.take(fd)?;
+            let last_reference = !file_table.description_open(file.description);
+            (file, last_reference)
+        };
+        self.release_locks(&file, last_reference);
+        Ok(())
+    }
+
+    /// Drop the advisory locks released by closing `file`
+    fn release_locks(&self, file: &FileDescriptor, last_reference: bool) {
+        let owner = crate::process::current_pid();
+        lock::file_closed(&file.inode, owner, file.description, last_reference);
     }
 
     /// Read from a file descriptor
+    ///
+    /// Objects that report `WouldBlock` (eventfd, timerfd, ...) block until
+    /// readable unless the descriptor is non-blocking.
     pub fn read(&self, fd: i32, buf: &mut [u8]) -> VfsResult<usize> {
-        let mut file_table = self.file_table.lock();
-        let file_desc = file_table.get_mut(fd)?;
+        loop {
+            let mut file_table = self.file_table.lock();
+            let file_desc = file_table.get_mut(fd)?;
 
-        if !file_desc.flags.is_readable() {
-            return Err(VfsError::PermissionDenied);
-        }
+            if !file_desc.flags.is_readable() {
+                return Err(VfsError::PermissionDenied);
+            }
+
+            let nonblock = file_desc.flags.has_flag(OpenFlags::NONBLOCK);
 
-        let bytes_read = file_desc.inode.read_at(file_desc.offset, buf)?;
-        file_desc.offset += bytes_read as u64;
+            // Pipes may block, so never hold the file table across them
+            if let Some(pipe) = file_desc.inode.as_pipe() {
+                drop(file_table);
+                return pipe.read(buf, nonblock);
+            }
 
-        Ok(bytes_read)
+            match file_desc.inode.read_at(file_desc.offset, buf) {
+                Ok(bytes_read) => {
+                    file_desc.offset += bytes_read as u64;
+                    return Ok(bytes_read);
+                }
+                Err(VfsError::WouldBlock) if !nonblock => {
+                    let inode = Arc::clone(&file_desc.inode);
+                    drop(file_table);
+                    poll::wait_ready(inode.as_ref(), poll::POLLIN)?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
     }
 
     /// Write to a file descriptor
     pub fn write(&self, fd: i32, buf: &[u8]) -> VfsResult<usize> {
-        let mut file_table = self.file_table.lock();
-        let file_desc = file_table.get_mut(fd)?;
+        loop {
+            let mut file_table = self.file_table.lock();
+            let file_desc = file_table.get_mut(fd)?;
 
-        if !file_desc.flags.is_writable() {
-            return Err(VfsError::PermissionDenied);
-        }
+            if !file_desc.flags.is_writable() {
+                return Err(VfsError::PermissionDenied);
+            }
 
-        // Handle append mode
-        if file_desc.flags.has_flag(OpenFlags::APPEND) {
-            let stat = file_desc.inode.stat()?;
-            file_desc.offset = stat.size;
-        }
+            let nonblock = file_desc.flags.has_flag(OpenFlags::NONBLOCK);
+
+            if let Some(pipe) = file_desc.inode.as_pipe() {
+                drop(file_table);
+                return pipe.write(buf, nonblock);
+            }
 
-        let bytes_written = file_desc.inode.write_at(file_desc.offset, buf)?;
-        file_desc.offset += bytes_written as u64;
+            // Handle append mode
+            if file_desc.flags.has_flag(OpenFlags::APPEND) {
+                let stat = file_desc.inode.stat()?;
+                file_desc.offset = stat.size;
+            }
 
-        Ok(bytes_written)
+            match file_desc.inode.write_at(file_desc.offset, buf) {
+                Ok(bytes_written) => {
+                    file_desc.offset += bytes_written as u64;
+                    return Ok(bytes_written);
+                }
+                Err(VfsError::WouldBlock) if !nonblock => {
+                    let inode = Arc::clone(&file_desc.inode);
+                    drop(file_table);
+                    poll::wait_ready(inode.as_ref(), poll::POLLOUT)?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
     }
 
     /// Seek in a file descriptor
@@ -529,23 +798,20 @@ impl Vfs {
         Ok(())
     }
 
+    /// Create a special file (FIFO, socket or device node)
+    pub fn mknod(&self, path: &str, inode_type: InodeType, mode: u32) -> VfsResult<()> {
+        if inode_type == InodeType::Directory {
+            return Err(VfsError::InvalidArgument);
+        }
+        let (parent, filename) = self.resolve_parent(path)?;
+        parent.create(&filename, inode_type, mode)?;
+        Ok(())
+    }
+
     /// Remove a directory
     pub fn rmdir(&self, path: &str) -> VfsResult<()> {
         let (parent, dirname) = self.resolve_parent(path)?;
-        let inode = parent.lookup(&dirname)?;
-
-        // Verify it's a directory
-        if inode.inode_type() != InodeType::Directory {
-            return Err(VfsError::NotDirectory);
-        }
-
-        // Verify it's empty
-        let entries = inode.readdir()?;
-        if !entries.is_empty() {
-            return Err(VfsError::NotSupported); // Should be ENOTEMPTY
-        }
-
-        parent.unlink(&dirname)
+        parent.rmdir(&dirname)
     }
 
     /// Remove a file
@@ -565,6 +831,15 @@ impl Vfs {
         inode.readdir()
     }
 
+    /// Get the readiness mask of a file descriptor
+    pub fn poll(&self, fd: i32) -> VfsResult<u16> {
+        let inode = {
+            let file_table = self.file_table.lock();
+            Arc::clone(&file_table.get(fd)?.inode)
+        };
+        Ok(inode.poll())
+    }
+
     /// Sync a file descriptor
     pub fn fsync(&self, fd: i32) -> VfsResult<()> {
         let file_table = self.file_table.lock();
@@ -580,8 +855,79 @@ impl Vfs {
 
     /// Duplicate a file descriptor to a specific fd number
     pub fn dup2(&self, oldfd: i32, newfd: i32) -> VfsResult<i32> {
+        self.dup3(oldfd, newfd, false)
+    }
+
+    /// Duplicate a file descriptor to a specific fd number, setting its
+    /// close-on-exec flag
+    pub fn dup3(&self, oldfd: i32, newfd: i32, cloexec: bool) -> VfsResult<i32> {
+        let (replaced, last_reference) = {
+            let mut file_table = self.file_table.lock();
+            let replaced = file_table.duplicate_to(oldfd, newfd, cloexec)?;
+            let last_reference = replaced
+                .as_ref()
+                .map_or(false, |file| !file_table.description_open(file.description));
+            (replaced, last_reference)
+        };
+        if let Some(file) = replaced {
+            self.release_locks(&file, last_reference);
+        }
+        Ok(newfd)
+    }
+
+    /// Duplicate a file descriptor to the lowest free number >= `min_fd`
+    pub fn dup_min(&self, fd: i32, min_fd: i32, cloexec: bool) -> VfsResult<i32> {
         let mut file_table = self.file_table.lock();
-        file_table.duplicate_to(oldfd, newfd)
+        file_table.duplicate_from(fd, min_fd, cloexec)
+    }
+
+    /// Get the close-on-exec flag of a file descriptor
+    pub fn cloexec(&self, fd: i32) -> VfsResult<bool> {
+        let file_table = self.file_table.lock();
+        Ok(file_table.get(fd)?.cloexec)
+    }
+
+    /// Set the close-on-exec flag of a file descriptor
+    pub fn set_cloexec(&self, fd: i32, cloexec: bool) -> VfsResult<()> {
+        let mut file_table = self.file_table.lock();
+        file_table.get_mut(fd)?.cloexec = cloexec;
+        Ok(())
+    }
+
+    /// Replace the changeable status flags (`OpenFlags::SETFL_MASK`) of an
+    /// open file description
+    ///
+    /// Every descriptor duplicated from the same open sees the change.
+    pub fn set_status_flags(&self, fd: i32, flags: u32) -> VfsResult<()> {
+        let mut file_table = self.file_table.lock();
+        let description = file_table.get(fd)?.description;
+        for file in file_table.description_mut(description) {
+            let kept = file.flags.bits() & !OpenFlags::SETFL_MASK;
+            file.flags = OpenFlags::new(kept | (flags & OpenFlags::SETFL_MASK));
+        }
+        Ok(())
+    }
+
+    /// Get the SIGIO owner of an open file description
+    pub fn owner(&self, fd: i32) -> VfsResult<i32> {
+        let file_table = self.file_table.lock();
+        Ok(file_table.get(fd)?.owner)
+    }
+
+    /// Set the SIGIO owner of an open file description
+    pub fn set_owner(&self, fd: i32, owner: i32) -> VfsResult<()> {
+        let mut file_table = self.file_table.lock();
+        let description = file_table.get(fd)?.description;
+        for file in file_table.description_mut(description) {
+            file.owner = owner;
+        }
+        Ok(())
+    }
+
+    /// Get the open file description identifier behind a file descriptor
+    pub fn description(&self, fd: i32) -> VfsResult<u64> {
+        let file_table = self.file_table.lock();
+        Ok(file_table.get(fd)?.description)
     }
 }
 
@@ -640,6 +986,21 @@ pub fn vfs_mkdir(path: &str, mode: u32) -> VfsResult<()> {
     VFS.mkdir(path, mode)
 }
 
+/// Create a special file
+pub fn vfs_mknod(path: &str, inode_type: InodeType, mode: u32) -> VfsResult<()> {
+    VFS.mknod(path, inode_type, mode)
+}
+
+/// Create a named pipe
+pub fn vfs_mkfifo(path: &str, mode: u32) -> VfsResult<()> {
+    VFS.mknod(path, InodeType::Fifo, mode)
+}
+
+/// Create an anonymous pipe
+pub fn vfs_pipe(flags: u32) -> VfsResult<(i32, i32)> {
+    VFS.pipe(flags)
+}
+
 /// Remove a directory
 pub fn vfs_rmdir(path: &str) -> VfsResult<()> {
     VFS.rmdir(path)
@@ -655,6 +1016,11 @@ pub fn vfs_readdir(path: &str) -> VfsResult<Vec<DirEntry>> {
     VFS.readdir(path)
 }
 
+/// Get the readiness mask of a file descriptor
+pub fn vfs_poll(fd: i32) -> VfsResult<u16> {
+    VFS.poll(fd)
+}
+
 /// Sync a file descriptor
 pub fn vfs_fsync(fd: i32) -> VfsResult<()> {
     VFS.fsync(fd)
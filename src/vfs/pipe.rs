@@ -0,0 +1,588 @@
+//! Pipes and FIFOs
+//!
+//! Anonymous pipes and named FIFOs share one implementation. Pipe data is held
+//! as a queue of reference-counted page segments so that `splice` can move
+//! pages between pipes and `tee` can share them without copying.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::anon_inode::{self, anon_inode_ops};
+use super::poll::{self, EventSource, POLLERR, POLLHUP, POLLIN, POLLOUT};
+use super::{InodeOps, InodeType, OpenFlags, Stat, VfsError, VfsResult};
+use crate::process::wait_queue::{WaitError, WaitQueue};
+
+/// Writes of at most this many bytes are never interleaved with other writers
+pub const PIPE_BUF: usize = 4096;
+
+/// Default pipe capacity (16 pages, matching Linux)
+pub const PIPE_CAPACITY: usize = 16 * PIPE_BUF;
+
+/// A window into a shared page of pipe data
+#[derive(Clone)]
+pub struct PipeSegment {
+    page: Arc<Vec<u8>>,
+    offset: usize,
+    len: usize,
+}
+
+impl PipeSegment {
+    /// Wrap a freshly filled page
+    pub fn new(page: Vec<u8>) -> Self {
+        let len = page.len();
+        Self {
+            page: Arc::new(page),
+            offset: 0,
+            len,
+        }
+    }
+
+    /// Bytes covered by this segment
+    pub fn data(&self) -> &[u8] {
+        &self.page[self.offset..self.offset + self.len]
+    }
+
+    /// Drop the first `len` bytes of the segment
+    pub fn advance(&mut self, len: usize) {
+        self.offset += len;
+        self.len -= len;
+    }
+
+    /// Split off the first `len` bytes, leaving the rest in `self`
+    fn split_front(&mut self, len: usize) -> Self {
+        let front = Self {
+            page: Arc::clone(&self.page),
+            offset: self.offset,
+            len,
+        };
+        self.advance(len);
+        front
+    }
+}
+
+/// Mutable pipe state
+struct PipeState {
+    segments: VecDeque<PipeSegment>,
+    len: usize,
+    capacity: usize,
+    readers: usize,
+    writers: usize,
+}
+
+impl PipeState {
+    fn free(&self) -> usize {
+        self.capacity - self.len
+    }
+
+    fn push(&mut self, segment: PipeSegment) {
+        self.len += segment.len;
+        self.segments.push_back(segment);
+    }
+
+    /// Remove up to `max` bytes worth of segments from the front
+    fn take(&mut self, max: usize) -> Vec<PipeSegment> {
+        let mut taken = Vec::new();
+        let mut remaining = max;
+
+        while remaining > 0 {
+            let Some(front) = self.segments.front_mut() else { break };
+            if front.len <= remaining {
+                let segment = self.segments.pop_front().unwrap();
+                remaining -= segment.len;
+                self.len -= segment.len;
+                taken.push(segment);
+            } else {
+                let segment = front.split_front(remaining);
+                self.len -= remaining;
+                remaining = 0;
+                taken.push(segment);
+            }
+        }
+
+        taken
+    }
+
+    /// Clone references to up to `max` bytes from the front
+    fn peek(&self, max: usize) -> Vec<PipeSegment> {
+        let mut shared = Vec::new();
+        let mut remaining = max;
+
+        for segment in self.segments.iter() {
+            if remaining == 0 {
+                break;
+            }
+            let mut segment = segment.clone();
+            if segment.len > remaining {
+                segment.len = remaining;
+            }
+            remaining -= segment.len;
+            shared.push(segment);
+        }
+
+        shared
+    }
+}
+
+/// Shared pipe object referenced by both ends
+pub struct Pipe {
+    /// Inode number reported by fstat
+    ino: u64,
+    /// Inode number of the FIFO node this pipe backs, if any
+    fifo_ino: Option<u64>,
+    state: Mutex<PipeState>,
+    /// Readers waiting for data
+    read_wait: WaitQueue,
+    /// Writers waiting for space
+    write_wait: WaitQueue,
+    /// FIFO openers waiting for the other side
+    open_wait: WaitQueue,
+}
+
+impl Pipe {
+    fn new(fifo_ino: Option<u64>) -> Arc<Self> {
+        Arc::new(Self {
+            ino: fifo_ino.unwrap_or_else(anon_inode::alloc_ino),
+            fifo_ino,
+            state: Mutex::new(PipeState {
+                segments: VecDeque::new(),
+                len: 0,
+                capacity: PIPE_CAPACITY,
+                readers: 0,
+                writers: 0,
+            }),
+            read_wait: WaitQueue::new(),
+            write_wait: WaitQueue::new(),
+            open_wait: WaitQueue::new(),
+        })
+    }
+
+    /// Bytes currently buffered
+    pub fn len(&self) -> usize {
+        self.state.lock().len
+    }
+
+    /// Check whether a read would make progress without blocking
+    pub fn readable(&self) -> bool {
+        let state = self.state.lock();
+        state.len > 0 || state.writers == 0
+    }
+
+    /// Check whether a write of up to PIPE_BUF bytes would not block
+    pub fn writable(&self) -> bool {
+        let state = self.state.lock();
+        state.readers == 0 || state.free() >= PIPE_BUF
+    }
+
+    /// Check whether every write end has been closed
+    pub fn hung_up(&self) -> bool {
+        self.state.lock().writers == 0
+    }
+
+    /// Read without blocking
+    ///
+    /// Returns `Ok(0)` at end of file and `WouldBlock` if the pipe is empty
+    /// but still has writers.
+    pub fn try_read(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let read = {
+            let mut state = self.state.lock();
+            if state.len == 0 {
+                return if state.writers == 0 { Ok(0) } else { Err(VfsError::WouldBlock) };
+            }
+
+            let mut copied = 0;
+            for segment in state.take(buf.len()) {
+                let data = segment.data();
+                buf[copied..copied + data.len()].copy_from_slice(data);
+                copied += data.len();
+            }
+            copied
+        };
+
+        self.wake_writers();
+        Ok(read)
+    }
+
+    /// Read, blocking until data arrives unless `nonblock` is set
+    pub fn read(&self, buf: &mut [u8], nonblock: bool) -> VfsResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        if nonblock {
+            return self.try_read(buf);
+        }
+
+        self.read_wait
+            .wait_until(|| match self.try_read(buf) {
+                Err(VfsError::WouldBlock) => None,
+                result => Some(result),
+            })
+            .map_err(wait_error)?
+    }
+
+    /// Write without blocking
+    ///
+    /// Writes of up to PIPE_BUF bytes either complete in full or not at all.
+    fn try_write(&self, buf: &[u8]) -> VfsResult<usize> {
+        let written = {
+            let mut state = self.state.lock();
+            if state.readers == 0 {
+                return Err(VfsError::BrokenPipe);
+            }
+
+            let free = state.free();
+            if free == 0 || (buf.len() <= PIPE_BUF && free < buf.len()) {
+                return Err(VfsError::WouldBlock);
+            }
+
+            let count = core::cmp::min(free, buf.len());
+            for chunk in buf[..count].chunks(PIPE_BUF) {
+                state.push(PipeSegment::new(chunk.to_vec()));
+            }
+            count
+        };
+
+        self.wake_readers();
+        Ok(written)
+    }
+
+    /// Write, blocking for space unless `nonblock` is set
+    ///
+    /// Writing to a pipe with no readers raises SIGPIPE and fails with
+    /// `BrokenPipe`.
+    pub fn write(&self, buf: &[u8], nonblock: bool) -> VfsResult<usize> {
+        let mut total = 0;
+
+        while total < buf.len() {
+            let remaining = &buf[total..];
+            match self.try_write(remaining) {
+                Ok(count) => total += count,
+                Err(VfsError::WouldBlock) if nonblock => {
+                    return if total > 0 { Ok(total) } else { Err(VfsError::WouldBlock) };
+                }
+                Err(VfsError::WouldBlock) => {
+                    let needed = if remaining.len() <= PIPE_BUF { remaining.len() } else { 1 };
+                    let waited = self.write_wait.wait_until(|| {
+                        let state = self.state.lock();
+                        (state.readers == 0 || state.free() >= needed).then_some(())
+                    });
+                    if let Err(err) = waited {
+                        return if total > 0 { Ok(total) } else { Err(wait_error(err)) };
+                    }
+                }
+                Err(VfsError::BrokenPipe) => {
+                    raise_sigpipe();
+                    return if total > 0 { Ok(total) } else { Err(VfsError::BrokenPipe) };
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(total)
+    }
+
+    /// Append pre-built segments, blocking for space unless `nonblock` is set
+    ///
+    /// Used by splice and vmsplice; each segment is queued whole so the pages
+    /// are never copied.
+    pub fn push_segments(&self, segments: Vec<PipeSegment>, nonblock: bool) -> VfsResult<usize> {
+        let mut total = 0;
+
+        for segment in segments {
+            let wanted = segment.len;
+            let pushed = self.write_wait.wait_until(|| {
+                let mut state = self.state.lock();
+                if state.readers == 0 {
+                    return Some(Err(VfsError::BrokenPipe));
+                }
+                if state.free() >= wanted {
+                    state.push(segment.clone());
+                    return Some(Ok(()));
+                }
+                if nonblock {
+                    return Some(Err(VfsError::WouldBlock));
+                }
+                None
+            });
+
+            let err = match pushed {
+                Ok(Ok(())) => {
+                    total += wanted;
+                    self.wake_readers();
+                    continue;
+                }
+                Ok(Err(err)) => err,
+                Err(err) => wait_error(err),
+            };
+
+            if err == VfsError::BrokenPipe {
+                raise_sigpipe();
+            }
+            return if total > 0 { Ok(total) } else { Err(err) };
+        }
+
+        Ok(total)
+    }
+
+    /// Remove up to `max` bytes of segments, blocking for data unless `nonblock`
+    ///
+    /// An empty vector means end of file.
+    pub fn pop_segments(&self, max: usize, nonblock: bool) -> VfsResult<Vec<PipeSegment>> {
+        let taken = self
+            .read_wait
+            .wait_until(|| {
+                let mut state = self.state.lock();
+                if state.len > 0 {
+                    Some(Ok(state.take(max)))
+                } else if state.writers == 0 {
+                    Some(Ok(Vec::new()))
+                } else if nonblock {
+                    Some(Err(VfsError::WouldBlock))
+                } else {
+                    None
+                }
+            })
+            .map_err(wait_error)??;
+
+        if !taken.is_empty() {
+            self.wake_writers();
+        }
+        Ok(taken)
+    }
+
+    /// Share up to `max` bytes of segments without consuming them
+    pub fn peek_segments(&self, max: usize, nonblock: bool) -> VfsResult<Vec<PipeSegment>> {
+        self.read_wait
+            .wait_until(|| {
+                let state = self.state.lock();
+                if state.len > 0 {
+                    Some(Ok(state.peek(max)))
+                } else if state.writers == 0 {
+                    Some(Ok(Vec::new()))
+                } else if nonblock {
+                    Some(Err(VfsError::WouldBlock))
+                } else {
+                    None
+                }
+            })
+            .map_err(wait_error)?
+    }
+
+    /// Return unconsumed segments to the front of the pipe
+    ///
+    /// Used when the destination of a splice accepted fewer bytes than were
+    /// taken out of the pipe.
+    pub fn unpop_segments(&self, segments: Vec<PipeSegment>) {
+        let mut state = self.state.lock();
+        for segment in segments.into_iter().rev() {
+            state.len += segment.len;
+            state.segments.push_front(segment);
+        }
+        drop(state);
+        self.wake_readers();
+    }
+
+    fn wake_readers(&self) {
+        self.read_wait.wake_all();
+        poll::notify_event(EventSource::object(self));
+    }
+
+    fn wake_writers(&self) {
+        self.write_wait.wake_all();
+        poll::notify_event(EventSource::object(self));
+    }
+
+    fn attach(&self, readable: bool, writable: bool) {
+        {
+            let mut state = self.state.lock();
+            if readable {
+                state.readers += 1;
+            }
+            if writable {
+                state.writers += 1;
+            }
+        }
+        self.open_wait.wake_all();
+    }
+
+    fn detach(&self, readable: bool, writable: bool) {
+        let idle = {
+            let mut state = self.state.lock();
+            if readable {
+                state.readers -= 1;
+            }
+            if writable {
+                state.writers -= 1;
+            }
+            if state.readers == 0 && state.writers == 0 {
+                // Data in a FIFO does not outlive its last opener
+                state.segments.clear();
+                state.len = 0;
+                true
+            } else {
+                false
+            }
+        };
+
+        self.wake_readers();
+        self.wake_writers();
+
+        if idle {
+            if let Some(ino) = self.fifo_ino {
+                let mut fifos = FIFO_TABLE.lock();
+                if fifos.get(&ino).map_or(false, |p| core::ptr::eq(p.as_ref(), self)) {
+                    fifos.remove(&ino);
+                }
+            }
+        }
+    }
+}
+
+/// One open end of a pipe or FIFO
+pub struct PipeEnd {
+    pipe: Arc<Pipe>,
+    readable: bool,
+    writable: bool,
+}
+
+impl PipeEnd {
+    fn new(pipe: Arc<Pipe>, readable: bool, writable: bool) -> Arc<Self> {
+        pipe.attach(readable, writable);
+        Arc::new(Self { pipe, readable, writable })
+    }
+}
+
+impl Drop for PipeEnd {
+    fn drop(&mut self) {
+        self.pipe.detach(self.readable, self.writable);
+    }
+}
+
+// Reads and writes through the inode block like those of a blocking
+// descriptor; the file table goes through `as_pipe` to honour O_NONBLOCK.
+impl InodeOps for PipeEnd {
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if !self.readable {
+            return Err(VfsError::BadFileDescriptor);
+        }
+        self.pipe.read(buf, false)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if !self.writable {
+            return Err(VfsError::BadFileDescriptor);
+        }
+        self.pipe.write(buf, false)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat {
+            blksize: PIPE_BUF as u64,
+            ..anon_inode::stat(self.pipe.ino, InodeType::Fifo, self.pipe.len() as u64)
+        })
+    }
+
+    anon_inode_ops!();
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::Fifo
+    }
+
+    fn as_pipe(&self) -> Option<Arc<Pipe>> {
+        Some(Arc::clone(&self.pipe))
+    }
+
+    /// Both ends report the events of the shared pipe
+    fn event_source(&self) -> EventSource {
+        EventSource::object(self.pipe.as_ref())
+    }
+
+    fn poll(&self) -> u16 {
+        let state = self.pipe.state.lock();
+        let mut events = 0;
+        if self.readable {
+            if state.len > 0 {
+                events |= POLLIN;
+            }
+            if state.writers == 0 {
+                events |= POLLHUP;
+            }
+        }
+        if self.writable {
+            if state.readers == 0 {
+                events |= POLLERR;
+            } else if state.free() >= PIPE_BUF {
+                events |= POLLOUT;
+            }
+        }
+        events
+    }
+}
+
+/// Pipes backing currently-open FIFO nodes, keyed by FIFO inode number
+static FIFO_TABLE: Mutex<BTreeMap<u64, Arc<Pipe>>> = Mutex::new(BTreeMap::new());
+
+/// Create an anonymous pipe, returning its (read, write) ends
+pub fn create_pipe() -> (Arc<dyn InodeOps>, Arc<dyn InodeOps>) {
+    let pipe = Pipe::new(None);
+    let reader = PipeEnd::new(Arc::clone(&pipe), true, false);
+    let writer = PipeEnd::new(pipe, false, true);
+    (reader as Arc<dyn InodeOps>, writer as Arc<dyn InodeOps>)
+}
+
+/// Open a FIFO node, returning the pipe end to install in the file table
+///
+/// Read-only opens wait for a writer and write-only opens wait for a reader,
+/// unless O_NONBLOCK is set. A non-blocking write-only open with no reader
+/// fails with `NoDevice` (ENXIO). Read-write opens never block.
+pub fn open_fifo(node: &Arc<dyn InodeOps>, flags: OpenFlags) -> VfsResult<Arc<dyn InodeOps>> {
+    let ino = node.stat()?.ino;
+    let pipe = {
+        let mut fifos = FIFO_TABLE.lock();
+        Arc::clone(fifos.entry(ino).or_insert_with(|| Pipe::new(Some(ino))))
+    };
+
+    let readable = flags.is_readable();
+    let writable = flags.is_writable();
+    let nonblock = flags.has_flag(OpenFlags::NONBLOCK);
+
+    if writable && !readable && nonblock && pipe.state.lock().readers == 0 {
+        return Err(VfsError::NoDevice);
+    }
+
+    let end = PipeEnd::new(Arc::clone(&pipe), readable, writable);
+
+    if !nonblock && readable != writable {
+        pipe.open_wait
+            .wait_until(|| {
+                let state = pipe.state.lock();
+                let peers = if readable { state.writers } else { state.readers };
+                (peers > 0).then_some(())
+            })
+            .map_err(wait_error)?;
+    }
+
+    Ok(end as Arc<dyn InodeOps>)
+}
+
+/// Allocate a page-sized buffer for filling pipe segments
+pub fn alloc_page(len: usize) -> Vec<u8> {
+    vec![0u8; core::cmp::min(len, PIPE_BUF)]
+}
+
+fn wait_error(err: WaitError) -> VfsError {
+    match err {
+        WaitError::Interrupted => VfsError::Interrupted,
+    }
+}
+
+/// Deliver SIGPIPE to the writer of a reader-less pipe
+fn raise_sigpipe() {
+    let pid = crate::process::current_pid();
+    if pid != 0 {
+        let _ = crate::process::send_signal(pid, crate::process::ipc::Signal::SIGPIPE, pid);
+    }
+}
@@ -0,0 +1,78 @@
+//! File Readiness
+//!
+//! Every open file reports its readiness through `InodeOps::poll`. Objects
+//! whose readiness can change (pipes, eventfd, timerfd, signalfd) call
+//! `notify_event` with their `EventSource` whenever it does. That marks the
+//! epoll entries watching the source, for EPOLLET, and wakes every process
+//! blocked in poll, select, epoll_wait or a blocking read/write on such an
+//! object.
+
+use super::{InodeOps, VfsError, VfsResult};
+use crate::process::wait_queue::{WaitError, WaitQueue};
+use crate::process::Pid;
+
+/// Data available to read
+pub const POLLIN: u16 = 0x001;
+/// Urgent data available
+pub const POLLPRI: u16 = 0x002;
+/// Writing will not block
+pub const POLLOUT: u16 = 0x004;
+/// Error condition
+pub const POLLERR: u16 = 0x008;
+/// Peer hung up
+pub const POLLHUP: u16 = 0x010;
+/// Invalid file descriptor
+pub const POLLNVAL: u16 = 0x020;
+
+/// Processes waiting for any file to change readiness
+static POLL_WAIT: WaitQueue = WaitQueue::new();
+
+/// Object whose readiness changes are reported through `notify_event`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventSource {
+    /// A file object, identified by its address
+    Object(usize),
+    /// Signals queued for a process, read through signalfd
+    Signals(Pid),
+}
+
+impl EventSource {
+    /// The source identified by `object`'s address
+    pub fn object<T: ?Sized>(object: &T) -> Self {
+        EventSource::Object(object as *const T as *const () as usize)
+    }
+}
+
+/// Wake everything waiting for a readiness change
+pub fn notify() {
+    POLL_WAIT.wake_all();
+}
+
+/// Report that `source` may have changed readiness
+///
+/// May be called from interrupt context.
+pub fn notify_event(source: EventSource) {
+    super::epoll::mark_event(source);
+    notify();
+}
+
+/// Block until `condition` yields a value, re-checking after every readiness
+/// change
+pub fn wait_until<T, F>(condition: F) -> VfsResult<T>
+where
+    F: FnMut() -> Option<T>,
+{
+    POLL_WAIT.wait_until(condition).map_err(|err| match err {
+        WaitError::Interrupted => VfsError::Interrupted,
+    })
+}
+
+/// Block until `inode` reports any of `events` (errors and hangups always
+/// count), returning the ready mask
+pub fn wait_ready(inode: &dyn InodeOps, events: u16) -> VfsResult<u16> {
+    let mask = events | POLLERR | POLLHUP;
+    wait_until(|| {
+        let ready = inode.poll() & mask;
+        (ready != 0).then_some(ready)
+    })
+}
@@ -0,0 +1,779 @@
+//! Process Filesystem (procfs)
+//!
+//! A directory per live process, named by its pid as the caller's PID
+//! namespace numbers it, plus `self` for the caller. Processes outside the
+//! caller's PID namespace don't show. Nothing is stored: every read formats the current value and
+//! every write goes straight to the subsystem that owns it. Entries can't
+//! be created, removed or renamed.
+//!
+//! Per-process files:
+//! - `status`: name, state, ids and the CPUs the process may run on
+//! - `cgroup`: the control group of the process, as "0::/path"
+//! - `oom_score`: the OOM killer's current badness score, 0..=2000
+//! - `oom_score_adj`: the adjustment to it, -1000..=1000; writable by the
+//!   process's owner, but only root may lower it
+//! - `ns/`: a file per namespace of the process, reading "<kind>:[<id>]".
+//!   An open one stands for that namespace in `setns`.
+//!
+//! `/proc/sys` holds the kernel tunables (see `sysctl`), one file each,
+//! in a directory per component of the dotted name.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+
+use super::{DirEntry, InodeOps, InodeType, Stat, StatFs, SuperblockOps, VfsError, VfsResult, PROC_DEV};
+use crate::memory::oom::{self, AdjError};
+use crate::process::namespace::{self, NsId, NsKind};
+use crate::process::{self, Pid, ProcessState};
+use crate::sysctl::{self, Sysctl, SysctlError};
+
+/// PROC_SUPER_MAGIC
+const PROC_SUPER_MAGIC: u64 = 0x9fa0;
+
+/// Inode number of the root directory; per-process inodes are derived from
+/// the pid so they stay stable between lookups
+const ROOT_INO: u64 = 1;
+
+/// Inode number of the directory of process `pid`
+fn dir_ino(pid: Pid) -> u64 {
+    ((pid as u64 + 1) << 8) | 1
+}
+
+/// Inode number of `/proc/sys/<path>`, with the top bit set to stay clear
+/// of the per-process ones
+fn sys_ino(path: &str) -> u64 {
+    // FNV-1a
+    let hash = path.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3));
+    hash | 1 << 63
+}
+
+/// Owner of process `pid`, if it is alive
+fn process_uid(pid: Pid) -> Option<u32> {
+    process::get_process_manager()
+        .get_process(pid)
+        .filter(|pcb| !matches!(pcb.state, ProcessState::Zombie | ProcessState::Terminated))
+        .map(|pcb| pcb.uid)
+}
+
+/// Caller's uid
+fn current_uid() -> u32 {
+    process::get_process_manager().get_process(process::current_pid()).map_or(0, |pcb| pcb.uid)
+}
+
+/// Pid the caller knows process `pid` by, if it can see it
+fn visible_pid(pid: Pid) -> Option<Pid> {
+    process::get_process_manager().pid_in_namespace_of(process::current_pid(), pid)
+}
+
+/// Files in each process directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PidFile {
+    OomScore,
+    OomScoreAdj,
+    Status,
+    Cgroup,
+}
+
+impl PidFile {
+    const ALL: [PidFile; 4] = [PidFile::OomScore, PidFile::OomScoreAdj, PidFile::Status, PidFile::Cgroup];
+
+    fn name(self) -> &'static str {
+        match self {
+            PidFile::Status => "status",
+            PidFile::OomScore => "oom_score",
+            PidFile::OomScoreAdj => "oom_score_adj",
+            PidFile::Cgroup => "cgroup",
+        }
+    }
+
+    fn mode(self) -> u32 {
+        match self {
+            PidFile::OomScore | PidFile::Status | PidFile::Cgroup => 0o444,
+            PidFile::OomScoreAdj => 0o644,
+        }
+    }
+
+    fn ino(self, pid: Pid) -> u64 {
+        ((pid as u64 + 1) << 8) | (self as u64 + 2)
+    }
+}
+
+/// `/proc/<pid>/<file>`
+struct ProcPidFile {
+    pid: Pid,
+    file: PidFile,
+}
+
+impl ProcPidFile {
+    fn contents(&self) -> VfsResult<String> {
+        process_uid(self.pid).ok_or(VfsError::NotFound)?;
+        Ok(match self.file {
+            PidFile::OomScore => {
+                let score = crate::memory::get_memory_manager().map_or(0, |mm| oom::oom_score(mm, self.pid));
+                format!("{}\n", score)
+            }
+            PidFile::OomScoreAdj => format!("{}\n", oom::score_adj(self.pid)),
+            PidFile::Status => status(self.pid)?,
+            PidFile::Cgroup => {
+                let path = process::cgroup::path(process::cgroup::cgroup_of(self.pid)).unwrap_or_else(|| String::from("/"));
+                format!("0::{}\n", path)
+            }
+        })
+    }
+}
+
+/// Contents of `/proc/<pid>/status`
+fn status(pid: Pid) -> VfsResult<String> {
+    let process_manager = process::get_process_manager();
+    let pcb = process_manager.get_process(pid).ok_or(VfsError::NotFound)?;
+    let state = match pcb.state {
+        _ if process::ptrace::is_stopped(pid) => "t (tracing stop)",
+        ProcessState::Running | ProcessState::Ready => "R (running)",
+        ProcessState::Blocked | ProcessState::Sleeping => "S (sleeping)",
+        ProcessState::Zombie => "Z (zombie)",
+        ProcessState::Terminated | ProcessState::Dead => "X (dead)",
+    };
+    let possible = match crate::smp::cpu_count() {
+        0 => 1,
+        n if n >= 64 => u64::MAX,
+        n => (1u64 << n) - 1,
+    };
+    let allowed = process_manager.cpu_affinity(pid).unwrap_or(u64::MAX) & possible;
+
+    Ok(format!(
+        "Name:\t{}\nState:\t{}\nPid:\t{}\nPPid:\t{}\nTracerPid:\t{}\nUid:\t{}\nGid:\t{}\nNoNewPrivs:\t{}\nSeccomp:\t{}\nSeccomp_filters:\t{}\nCpus_allowed:\t{:x}\nCpus_allowed_list:\t{}\n",
+        pcb.name_str(),
+        state,
+        visible_pid(pid).unwrap_or(0),
+        pcb.parent_pid.and_then(visible_pid).unwrap_or(0),
+        process::ptrace::tracer_of(pid).and_then(visible_pid).unwrap_or(0),
+        pcb.uid,
+        pcb.gid,
+        process::seccomp::no_new_privs(pid) as u8,
+        process::seccomp::mode(pid).number(),
+        process::seccomp::filter_count(pid),
+        allowed,
+        cpu_list(allowed),
+    ))
+}
+
+/// CPUs of `mask` as ranges, like "0-3,6"
+fn cpu_list(mask: u64) -> String {
+    let mut ranges = Vec::new();
+    let mut cpu = 0;
+    while cpu < 64 {
+        if mask & (1 << cpu) == 0 {
+            cpu += 1;
+            continue;
+        }
+        let start = cpu;
+        while cpu < 64 && mask & (1 << cpu) != 0 {
+            cpu += 1;
+        }
+        ranges.push(if cpu - 1 == start { format!("{}", start) } else { format!("{}-{}", start, cpu - 1) });
+    }
+    ranges.join(",")
+}
+
+impl InodeOps for ProcPidFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let contents = self.contents()?;
+        let bytes = contents.as_bytes();
+        if offset >= bytes.len() as u64 {
+            return Ok(0);
+        }
+        let len = buf.len().min(bytes.len() - offset as usize);
+        buf[..len].copy_from_slice(&bytes[offset as usize..offset as usize + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if self.file != PidFile::OomScoreAdj {
+            return Err(VfsError::PermissionDenied);
+        }
+        let owner = process_uid(self.pid).ok_or(VfsError::NotFound)?;
+        let uid = current_uid();
+        if uid != 0 && uid != owner {
+            return Err(VfsError::PermissionDenied);
+        }
+
+        let value = core::str::from_utf8(buf)
+            .ok()
+            .and_then(|text| text.trim().parse::<i32>().ok())
+            .ok_or(VfsError::InvalidArgument)?;
+        oom::set_score_adj(self.pid, value, uid == 0).map_err(|err| match err {
+            AdjError::Invalid => VfsError::InvalidArgument,
+            AdjError::PermissionDenied => VfsError::PermissionDenied,
+        })?;
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        let uid = process_uid(self.pid).ok_or(VfsError::NotFound)?;
+        Ok(Stat {
+            dev: PROC_DEV,
+            ino: self.file.ino(self.pid),
+            inode_type: InodeType::File,
+            mode: self.file.mode(),
+            nlink: 1,
+            uid,
+            ..Stat::default()
+        })
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult<()> {
+        // O_TRUNC on open is harmless; the value is replaced by the write
+        Ok(())
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn lookup(&self, _name: &str) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType, _mode: u32) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn unlink(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn link(&self, _name: &str, _target: Arc<dyn InodeOps>) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: Arc<dyn InodeOps>, _new_name: &str) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::File
+    }
+}
+
+/// `/proc/<pid>`
+struct ProcPidDir {
+    pid: Pid,
+}
+
+impl InodeOps for ProcPidDir {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        let uid = process_uid(self.pid).ok_or(VfsError::NotFound)?;
+        Ok(Stat {
+            dev: PROC_DEV,
+            ino: dir_ino(self.pid),
+            inode_type: InodeType::Directory,
+            mode: 0o555,
+            nlink: 2,
+            uid,
+            ..Stat::default()
+        })
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult<()> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn InodeOps>> {
+        process_uid(self.pid).ok_or(VfsError::NotFound)?;
+        if name == "ns" {
+            return Ok(Arc::new(ProcNsDir { pid: self.pid }));
+        }
+        let file = PidFile::ALL.into_iter().find(|file| file.name() == name).ok_or(VfsError::NotFound)?;
+        Ok(Arc::new(ProcPidFile { pid: self.pid, file }))
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType, _mode: u32) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn unlink(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn link(&self, _name: &str, _target: Arc<dyn InodeOps>) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: Arc<dyn InodeOps>, _new_name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        process_uid(self.pid).ok_or(VfsError::NotFound)?;
+        let mut entries: Vec<DirEntry> = PidFile::ALL
+            .into_iter()
+            .map(|file| DirEntry { ino: file.ino(self.pid), name: file.name().to_string(), inode_type: InodeType::File })
+            .collect();
+        entries.push(DirEntry { ino: ns_dir_ino(self.pid), name: String::from("ns"), inode_type: InodeType::Directory });
+        Ok(entries)
+    }
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::Directory
+    }
+}
+
+/// Inode number of `/proc/<pid>/ns`
+fn ns_dir_ino(pid: Pid) -> u64 {
+    ((pid as u64 + 1) << 8) | 0x10
+}
+
+/// Inode number of `/proc/<pid>/ns/<kind>`
+fn ns_file_ino(pid: Pid, kind: NsKind) -> u64 {
+    ns_dir_ino(pid) + 1 + kind as u64
+}
+
+/// `/proc/<pid>/ns`
+struct ProcNsDir {
+    pid: Pid,
+}
+
+impl InodeOps for ProcNsDir {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        let uid = process_uid(self.pid).ok_or(VfsError::NotFound)?;
+        Ok(Stat {
+            dev: PROC_DEV,
+            ino: ns_dir_ino(self.pid),
+            inode_type: InodeType::Directory,
+            mode: 0o511,
+            nlink: 2,
+            uid,
+            ..Stat::default()
+        })
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult<()> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn InodeOps>> {
+        let owner = process_uid(self.pid).ok_or(VfsError::NotFound)?;
+        let uid = current_uid();
+        if uid != 0 && uid != owner {
+            return Err(VfsError::PermissionDenied);
+        }
+        let kind = NsKind::ALL.into_iter().find(|kind| kind.name() == name).ok_or(VfsError::NotFound)?;
+        // The file stands for the namespace the process is in now, even
+        // if the process moves on
+        let id = namespace::of(self.pid).get(kind);
+        Ok(Arc::new(ProcNsFile { pid: self.pid, kind, id }))
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType, _mode: u32) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn unlink(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn link(&self, _name: &str, _target: Arc<dyn InodeOps>) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: Arc<dyn InodeOps>, _new_name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        process_uid(self.pid).ok_or(VfsError::NotFound)?;
+        Ok(NsKind::ALL
+            .into_iter()
+            .map(|kind| DirEntry { ino: ns_file_ino(self.pid, kind), name: kind.name().to_string(), inode_type: InodeType::File })
+            .collect())
+    }
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::Directory
+    }
+}
+
+/// `/proc/<pid>/ns/<kind>`, a handle on one namespace of the process
+pub struct ProcNsFile {
+    pid: Pid,
+    kind: NsKind,
+    id: NsId,
+}
+
+impl ProcNsFile {
+    /// The namespace the file stands for
+    pub fn namespace(&self) -> (NsKind, NsId) {
+        (self.kind, self.id)
+    }
+}
+
+impl InodeOps for ProcNsFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let contents = format!("{}:[{}]\n", self.kind.name(), self.id);
+        let bytes = contents.as_bytes();
+        if offset >= bytes.len() as u64 {
+            return Ok(0);
+        }
+        let len = buf.len().min(bytes.len() - offset as usize);
+        buf[..len].copy_from_slice(&bytes[offset as usize..offset as usize + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat {
+            dev: PROC_DEV,
+            ino: ns_file_ino(self.pid, self.kind),
+            inode_type: InodeType::File,
+            mode: 0o444,
+            nlink: 1,
+            uid: process_uid(self.pid).unwrap_or(0),
+            ..Stat::default()
+        })
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn lookup(&self, _name: &str) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType, _mode: u32) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn unlink(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn link(&self, _name: &str, _target: Arc<dyn InodeOps>) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: Arc<dyn InodeOps>, _new_name: &str) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::File
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+/// `/proc/sys/<path>` for one tunable
+struct ProcSysFile {
+    entry: &'static Sysctl,
+}
+
+impl InodeOps for ProcSysFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let contents = format!("{}\n", self.entry.get());
+        let bytes = contents.as_bytes();
+        if offset >= bytes.len() as u64 {
+            return Ok(0);
+        }
+        let len = buf.len().min(bytes.len() - offset as usize);
+        buf[..len].copy_from_slice(&bytes[offset as usize..offset as usize + len]);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let value = core::str::from_utf8(buf)
+            .ok()
+            .and_then(|text| text.trim().parse::<u64>().ok())
+            .ok_or(VfsError::InvalidArgument)?;
+        self.entry.set(value, current_uid() == 0).map_err(|err| match err {
+            SysctlError::PermissionDenied => VfsError::PermissionDenied,
+            SysctlError::Invalid | SysctlError::NotFound => VfsError::InvalidArgument,
+        })?;
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat {
+            dev: PROC_DEV,
+            ino: sys_ino(self.entry.name),
+            inode_type: InodeType::File,
+            mode: 0o644,
+            nlink: 1,
+            ..Stat::default()
+        })
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn lookup(&self, _name: &str) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType, _mode: u32) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn unlink(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn link(&self, _name: &str, _target: Arc<dyn InodeOps>) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: Arc<dyn InodeOps>, _new_name: &str) -> VfsResult<()> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        Err(VfsError::NotDirectory)
+    }
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::File
+    }
+}
+
+/// `/proc/sys` or a directory below it
+struct ProcSysDir {
+    /// Dotted name prefix of the tunables below, "" or ending in '.'
+    prefix: String,
+}
+
+impl InodeOps for ProcSysDir {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat {
+            dev: PROC_DEV,
+            ino: sys_ino(&self.prefix),
+            inode_type: InodeType::Directory,
+            mode: 0o555,
+            nlink: 2,
+            ..Stat::default()
+        })
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult<()> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn InodeOps>> {
+        let path = format!("{}{}", self.prefix, name);
+        if let Some(entry) = sysctl::find(&path) {
+            return Ok(Arc::new(ProcSysFile { entry }));
+        }
+        let prefix = path + ".";
+        if sysctl::entries().iter().any(|entry| entry.name.starts_with(&prefix)) {
+            return Ok(Arc::new(ProcSysDir { prefix }));
+        }
+        Err(VfsError::NotFound)
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType, _mode: u32) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn unlink(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn link(&self, _name: &str, _target: Arc<dyn InodeOps>) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: Arc<dyn InodeOps>, _new_name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        // The next component of every name below, and whether it is a file
+        let mut children = BTreeMap::new();
+        for entry in sysctl::entries() {
+            let Some(rest) = entry.name.strip_prefix(self.prefix.as_str()) else { continue };
+            match rest.split_once('.') {
+                Some((dir, _)) => children.insert(dir, InodeType::Directory),
+                None => children.insert(rest, InodeType::File),
+            };
+        }
+        Ok(children
+            .into_iter()
+            .map(|(name, inode_type)| {
+                let suffix = if inode_type == InodeType::Directory { "." } else { "" };
+                DirEntry { ino: sys_ino(&format!("{}{}{}", self.prefix, name, suffix)), name: name.to_string(), inode_type }
+            })
+            .collect())
+    }
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::Directory
+    }
+}
+
+/// The root directory of a procfs mount
+pub struct ProcRoot;
+
+impl InodeOps for ProcRoot {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(Stat { dev: PROC_DEV, ino: ROOT_INO, inode_type: InodeType::Directory, mode: 0o555, nlink: 2, ..Stat::default() })
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult<()> {
+        Err(VfsError::IsDirectory)
+    }
+
+    fn sync(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn InodeOps>> {
+        let pid = match name {
+            "sys" => return Ok(Arc::new(ProcSysDir { prefix: String::new() })),
+            "self" => process::current_pid(),
+            _ => {
+                let local = name.parse::<Pid>().map_err(|_| VfsError::NotFound)?;
+                process::get_process_manager()
+                    .pid_from_namespace_of(process::current_pid(), local)
+                    .ok_or(VfsError::NotFound)?
+            }
+        };
+        process_uid(pid).ok_or(VfsError::NotFound)?;
+        Ok(Arc::new(ProcPidDir { pid }))
+    }
+
+    fn create(&self, _name: &str, _inode_type: InodeType, _mode: u32) -> VfsResult<Arc<dyn InodeOps>> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn unlink(&self, _name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn link(&self, _name: &str, _target: Arc<dyn InodeOps>) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn rename(&self, _old_name: &str, _new_dir: Arc<dyn InodeOps>, _new_name: &str) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn readdir(&self) -> VfsResult<Vec<DirEntry>> {
+        let mut entries: Vec<DirEntry> = process::get_process_manager()
+            .list_processes()
+            .into_iter()
+            .filter(|(_, _, state, _)| !matches!(state, ProcessState::Zombie | ProcessState::Terminated))
+            .filter_map(|(pid, _, _, _)| {
+                Some(DirEntry { ino: dir_ino(pid), name: visible_pid(pid)?.to_string(), inode_type: InodeType::Directory })
+            })
+            .collect();
+        entries.push(DirEntry { ino: sys_ino(""), name: String::from("sys"), inode_type: InodeType::Directory });
+        Ok(entries)
+    }
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::Directory
+    }
+}
+
+/// procfs superblock
+pub struct ProcFs {
+    root: Arc<ProcRoot>,
+}
+
+impl ProcFs {
+    pub fn new() -> Self {
+        Self { root: Arc::new(ProcRoot) }
+    }
+}
+
+impl SuperblockOps for ProcFs {
+    fn root(&self) -> Arc<dyn InodeOps> {
+        Arc::clone(&self.root) as Arc<dyn InodeOps>
+    }
+
+    fn sync_fs(&self) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn statfs(&self) -> VfsResult<StatFs> {
+        Ok(StatFs {
+            fs_type: PROC_SUPER_MAGIC,
+            block_size: 4096,
+            total_blocks: 0,
+            free_blocks: 0,
+            avail_blocks: 0,
+            total_inodes: 0,
+            free_inodes: 0,
+            max_name_len: 255,
+        })
+    }
+}
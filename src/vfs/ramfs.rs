@@ -14,6 +14,7 @@ use super::{
     InodeOps, SuperblockOps, InodeType, Stat, DirEntry, StatFs,
     VfsResult, VfsError,
 };
+use super::xattr::{SetMode, XattrMap};
 
 /// RAM filesystem inode data
 enum RamFsInodeData {
@@ -21,10 +22,14 @@ enum RamFsInodeData {
     File(RwLock<Vec<u8>>),
     /// Directory entries (name -> inode)
     Directory(RwLock<BTreeMap<String, Arc<RamFsInode>>>),
+    /// Special file (FIFO, socket, device) with no content of its own
+    Special,
 }
 
 /// RAM filesystem inode
 pub struct RamFsInode {
+    /// Device of the filesystem
+    dev: u64,
     /// Inode number
     ino: u64,
     /// Inode type
@@ -43,15 +48,18 @@ pub struct RamFsInode {
     mtime: RwLock<u64>,
     /// Change time
     ctime: RwLock<u64>,
+    /// Extended attributes
+    xattrs: XattrMap,
     /// Inode data
     data: RamFsInodeData,
 }
 
 impl RamFsInode {
     /// Create a new file inode
-    pub fn new_file(ino: u64, mode: u32) -> Arc<Self> {
+    pub fn new_file(dev: u64, ino: u64, mode: u32) -> Arc<Self> {
         let now = get_time();
         Arc::new(Self {
+            dev,
             ino,
             inode_type: InodeType::File,
             mode,
@@ -61,14 +69,16 @@ impl RamFsInode {
             atime: RwLock::new(now),
             mtime: RwLock::new(now),
             ctime: RwLock::new(now),
+            xattrs: XattrMap::new(),
             data: RamFsInodeData::File(RwLock::new(Vec::new())),
         })
     }
 
     /// Create a new directory inode
-    pub fn new_directory(ino: u64, mode: u32) -> Arc<Self> {
+    pub fn new_directory(dev: u64, ino: u64, mode: u32) -> Arc<Self> {
         let now = get_time();
         Arc::new(Self {
+            dev,
             ino,
             inode_type: InodeType::Directory,
             mode: mode | 0o111, // Directories need execute permission
@@ -78,10 +88,30 @@ impl RamFsInode {
             atime: RwLock::new(now),
             mtime: RwLock::new(now),
             ctime: RwLock::new(now),
+            xattrs: XattrMap::new(),
             data: RamFsInodeData::Directory(RwLock::new(BTreeMap::new())),
         })
     }
 
+    /// Create a new special file inode (FIFO, socket or device node)
+    pub fn new_special(dev: u64, ino: u64, inode_type: InodeType, mode: u32) -> Arc<Self> {
+        let now = get_time();
+        Arc::new(Self {
+            dev,
+            ino,
+            inode_type,
+            mode,
+            uid: 0,
+            gid: 0,
+            nlink: RwLock::new(1),
+            atime: RwLock::new(now),
+            mtime: RwLock::new(now),
+            ctime: RwLock::new(now),
+            xattrs: XattrMap::new(),
+            data: RamFsInodeData::Special,
+        })
+    }
+
     /// Update modification time
     fn update_mtime(&self) {
         let now = get_time();
@@ -93,6 +123,11 @@ impl RamFsInode {
     fn update_atime(&self) {
         *self.atime.write() = get_time();
     }
+
+    /// Update change time
+    fn update_ctime(&self) {
+        *self.ctime.write() = get_time();
+    }
 }
 
 impl InodeOps for RamFsInode {
@@ -115,6 +150,7 @@ impl InodeOps for RamFsInode {
                 Ok(bytes_to_copy)
             }
             RamFsInodeData::Directory(_) => Err(VfsError::IsDirectory),
+            RamFsInodeData::Special => Err(VfsError::InvalidArgument),
         }
     }
 
@@ -136,6 +172,7 @@ impl InodeOps for RamFsInode {
                 Ok(buf.len())
             }
             RamFsInodeData::Directory(_) => Err(VfsError::IsDirectory),
+            RamFsInodeData::Special => Err(VfsError::InvalidArgument),
         }
     }
 
@@ -143,11 +180,13 @@ impl InodeOps for RamFsInode {
         let size = match &self.data {
             RamFsInodeData::File(content) => content.read().len() as u64,
             RamFsInodeData::Directory(entries) => entries.read().len() as u64,
+            RamFsInodeData::Special => 0,
         };
 
         let blocks = (size + 511) / 512;
 
         Ok(Stat {
+            dev: self.dev,
             ino: self.ino,
             inode_type: self.inode_type,
             size,
@@ -172,6 +211,7 @@ impl InodeOps for RamFsInode {
                 Ok(())
             }
             RamFsInodeData::Directory(_) => Err(VfsError::IsDirectory),
+            RamFsInodeData::Special => Err(VfsError::InvalidArgument),
         }
     }
 
@@ -190,7 +230,7 @@ impl InodeOps for RamFsInode {
                     .map(|inode| Arc::clone(inode) as Arc<dyn InodeOps>)
                     .ok_or(VfsError::NotFound)
             }
-            RamFsInodeData::File(_) => Err(VfsError::NotDirectory),
+            _ => Err(VfsError::NotDirectory),
         }
     }
 
@@ -219,8 +259,12 @@ impl InodeOps for RamFsInode {
 
                 // Create new inode
                 let new_inode = match inode_type {
-                    InodeType::File => RamFsInode::new_file(ino, mode),
-                    InodeType::Directory => RamFsInode::new_directory(ino, mode),
+                    InodeType::File => RamFsInode::new_file(self.dev, ino, mode),
+                    InodeType::Directory => RamFsInode::new_directory(self.dev, ino, mode),
+                    InodeType::Fifo | InodeType::Socket
+                    | InodeType::CharDevice | InodeType::BlockDevice => {
+                        RamFsInode::new_special(self.dev, ino, inode_type, mode)
+                    }
                     _ => return Err(VfsError::NotSupported),
                 };
 
@@ -231,7 +275,7 @@ impl InodeOps for RamFsInode {
 
                 Ok(new_inode as Arc<dyn InodeOps>)
             }
-            RamFsInodeData::File(_) => Err(VfsError::NotDirectory),
+            _ => Err(VfsError::NotDirectory),
         }
     }
 
@@ -249,7 +293,7 @@ impl InodeOps for RamFsInode {
 
                 Ok(())
             }
-            RamFsInodeData::File(_) => Err(VfsError::NotDirectory),
+            _ => Err(VfsError::NotDirectory),
         }
     }
 
@@ -299,7 +343,7 @@ impl InodeOps for RamFsInode {
 
                 Ok(())
             }
-            RamFsInodeData::File(_) => Err(VfsError::NotDirectory),
+            _ => Err(VfsError::NotDirectory),
         }
     }
 
@@ -318,7 +362,7 @@ impl InodeOps for RamFsInode {
                 self.update_mtime();
                 Ok(())
             }
-            RamFsInodeData::File(_) => Err(VfsError::NotDirectory),
+            _ => Err(VfsError::NotDirectory),
         }
     }
 
@@ -340,10 +384,30 @@ impl InodeOps for RamFsInode {
 
                 Ok(result)
             }
-            RamFsInodeData::File(_) => Err(VfsError::NotDirectory),
+            _ => Err(VfsError::NotDirectory),
         }
     }
 
+    fn getxattr(&self, name: &str) -> VfsResult<Vec<u8>> {
+        self.xattrs.get(name)
+    }
+
+    fn setxattr(&self, name: &str, value: &[u8], mode: SetMode) -> VfsResult<()> {
+        self.xattrs.set(name, value, mode)?;
+        self.update_ctime();
+        Ok(())
+    }
+
+    fn listxattr(&self) -> VfsResult<Vec<String>> {
+        Ok(self.xattrs.list())
+    }
+
+    fn removexattr(&self, name: &str) -> VfsResult<()> {
+        self.xattrs.remove(name)?;
+        self.update_ctime();
+        Ok(())
+    }
+
     fn inode_type(&self) -> InodeType {
         self.inode_type
     }
@@ -358,7 +422,7 @@ pub struct RamFs {
 impl RamFs {
     /// Create a new RAM filesystem
     pub fn new() -> Self {
-        let root = RamFsInode::new_directory(1, 0o755);
+        let root = RamFsInode::new_directory(super::get_vfs().alloc_dev(), 1, 0o755);
         Self { root }
     }
 }
@@ -0,0 +1,102 @@
+//! Signal File Descriptors
+//!
+//! A signalfd reads signals queued for its owning process as
+//! `struct signalfd_siginfo` records, removing them from the pending queue.
+//! The signals it accepts are normally blocked so they stay queued until read.
+
+use alloc::sync::Arc;
+use core::any::Any;
+use spin::Mutex;
+
+use super::anon_inode::{self, anon_inode_ops};
+use super::poll::{EventSource, POLLIN};
+use super::{InodeOps, InodeType, Stat, VfsError, VfsResult};
+use crate::process::ipc::{get_ipc_manager, Signal, signal_bit};
+use crate::process::Pid;
+
+/// Size of `struct signalfd_siginfo`
+pub const SIGINFO_SIZE: usize = 128;
+
+/// signalfd object
+pub struct SignalFd {
+    ino: u64,
+    pid: Pid,
+    /// Accepted signals, using the kernel's signal mask bit layout
+    mask: Mutex<u64>,
+}
+
+impl SignalFd {
+    /// Create a signalfd reading `pid`'s signals in `mask`
+    pub fn new(pid: Pid, mask: u64) -> Arc<Self> {
+        Arc::new(Self {
+            ino: anon_inode::alloc_ino(),
+            pid,
+            mask: Mutex::new(Self::sanitize(mask)),
+        })
+    }
+
+    /// Replace the set of accepted signals
+    pub fn set_mask(&self, mask: u64) {
+        *self.mask.lock() = Self::sanitize(mask);
+    }
+
+    /// SIGKILL and SIGSTOP can never be read through a signalfd
+    fn sanitize(mask: u64) -> u64 {
+        mask & !(signal_bit(Signal::SIGKILL) | signal_bit(Signal::SIGSTOP))
+    }
+}
+
+impl InodeOps for SignalFd {
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let max = buf.len() / SIGINFO_SIZE;
+        if max == 0 {
+            return Err(VfsError::InvalidArgument);
+        }
+
+        let mask = *self.mask.lock();
+        let signals = get_ipc_manager().dequeue_signals(self.pid, mask, max);
+        if signals.is_empty() {
+            return Err(VfsError::WouldBlock);
+        }
+
+        for (record, info) in buf.chunks_exact_mut(SIGINFO_SIZE).zip(signals.iter()) {
+            record.fill(0);
+            // ssi_signo, ssi_errno, ssi_code, ssi_pid
+            record[0..4].copy_from_slice(&(info.signal as u32).to_ne_bytes());
+            record[12..16].copy_from_slice(&info.sender.to_ne_bytes());
+            // ssi_int / ssi_ptr carry the signal's data word
+            record[44..48].copy_from_slice(&(info.data as i32).to_ne_bytes());
+            record[48..56].copy_from_slice(&info.data.to_ne_bytes());
+        }
+
+        Ok(signals.len() * SIGINFO_SIZE)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::InvalidArgument)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(anon_inode::stat(self.ino, InodeType::File, 0))
+    }
+
+    anon_inode_ops!();
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::File
+    }
+
+    fn poll(&self) -> u16 {
+        let mask = *self.mask.lock();
+        if get_ipc_manager().has_signals_in(self.pid, mask) { POLLIN } else { 0 }
+    }
+
+    /// Every signalfd of a process reports the arrival of its signals
+    fn event_source(&self) -> EventSource {
+        EventSource::Signals(self.pid)
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
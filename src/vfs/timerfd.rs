@@ -0,0 +1,227 @@
+//! Timer File Descriptors
+//!
+//! A timerfd counts expirations of a one-shot or periodic timer. All armed
+//! timerfds share one `hrtimer::DeadlineQueue`. Deadlines are kept on the
+//! monotonic clock; CLOCK_REALTIME absolute deadlines are converted when
+//! armed.
+//!
+//! The callback runs from the timer interrupt, so every lock it takes is only
+//! held elsewhere with interrupts disabled.
+
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::any::Any;
+use spin::Mutex;
+
+use super::anon_inode::{self, anon_inode_ops};
+use super::poll::{self, EventSource, POLLIN};
+use super::{InodeOps, InodeType, Stat, VfsError, VfsResult};
+use crate::interrupts::without_interrupts;
+use crate::hrtimer::{self, DeadlineKey, DeadlineQueue};
+use crate::time;
+
+/// Clock a timerfd measures against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerClock {
+    Realtime,
+    Monotonic,
+}
+
+impl TimerClock {
+    /// Current time on this clock in nanoseconds
+    pub fn now_ns(&self) -> u64 {
+        match self {
+            TimerClock::Realtime => time::realtime_ns(),
+            TimerClock::Monotonic => time::uptime_ns(),
+        }
+    }
+}
+
+/// Timer setting in nanoseconds; a zero value means disarmed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimerSpec {
+    pub interval_ns: u64,
+    pub value_ns: u64,
+}
+
+struct TimerFdState {
+    expirations: u64,
+    interval_ns: u64,
+    armed: Option<DeadlineKey>,
+}
+
+/// timerfd object
+pub struct TimerFd {
+    ino: u64,
+    clock: TimerClock,
+    this: Weak<TimerFd>,
+    state: Mutex<TimerFdState>,
+}
+
+/// Armed timerfds ordered by deadline
+static ARMED: Mutex<DeadlineQueue<Weak<TimerFd>>> = Mutex::new(DeadlineQueue::new(expire_timers));
+
+impl TimerFd {
+    /// Create a disarmed timerfd
+    pub fn new(clock: TimerClock) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            ino: anon_inode::alloc_ino(),
+            clock,
+            this: this.clone(),
+            state: Mutex::new(TimerFdState {
+                expirations: 0,
+                interval_ns: 0,
+                armed: None,
+            }),
+        })
+    }
+
+    /// Clock this timer measures against
+    pub fn clock(&self) -> TimerClock {
+        self.clock
+    }
+
+    /// Arm or disarm the timer, returning the previous setting
+    ///
+    /// With `absolute` set, `spec.value_ns` is a time on the timer's clock
+    /// rather than a delay. A deadline already in the past fires at once.
+    pub fn settime(&self, spec: TimerSpec, absolute: bool) -> TimerSpec {
+        without_interrupts(|| self.settime_locked(spec, absolute))
+    }
+
+    fn settime_locked(&self, spec: TimerSpec, absolute: bool) -> TimerSpec {
+        let mut armed = ARMED.lock();
+        let mut state = self.state.lock();
+        let old = Self::current(&state);
+
+        if let Some(key) = state.armed.take() {
+            armed.remove(&key);
+        }
+        state.expirations = 0;
+        state.interval_ns = spec.interval_ns;
+
+        if spec.value_ns != 0 {
+            let now = time::uptime_ns();
+            let deadline = if absolute {
+                let clock_now = self.clock.now_ns();
+                now.saturating_add(spec.value_ns.saturating_sub(clock_now))
+            } else {
+                now.saturating_add(spec.value_ns)
+            };
+            state.armed = Some(armed.insert(deadline, self.this.clone()));
+        }
+
+        armed.rearm();
+        old
+    }
+
+    /// Time until the next expiration and the reload interval
+    pub fn gettime(&self) -> TimerSpec {
+        without_interrupts(|| Self::current(&self.state.lock()))
+    }
+
+    fn current(state: &TimerFdState) -> TimerSpec {
+        let value_ns = state
+            .armed
+            .map(|(deadline, _)| deadline.saturating_sub(time::uptime_ns()).max(1))
+            .unwrap_or(0);
+        TimerSpec {
+            interval_ns: state.interval_ns,
+            value_ns,
+        }
+    }
+}
+
+impl Drop for TimerFd {
+    fn drop(&mut self) {
+        if let Some(key) = self.state.get_mut().armed.take() {
+            without_interrupts(|| {
+                let mut armed = ARMED.lock();
+                armed.remove(&key);
+                armed.rearm();
+            });
+        }
+    }
+}
+
+impl InodeOps for TimerFd {
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if buf.len() < 8 {
+            return Err(VfsError::InvalidArgument);
+        }
+
+        let expirations = without_interrupts(|| core::mem::take(&mut self.state.lock().expirations));
+        if expirations == 0 {
+            return Err(VfsError::WouldBlock);
+        }
+
+        buf[..8].copy_from_slice(&expirations.to_ne_bytes());
+        Ok(8)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::InvalidArgument)
+    }
+
+    fn stat(&self) -> VfsResult<Stat> {
+        Ok(anon_inode::stat(self.ino, InodeType::File, 0))
+    }
+
+    anon_inode_ops!();
+
+    fn inode_type(&self) -> InodeType {
+        InodeType::File
+    }
+
+    fn poll(&self) -> u16 {
+        let expirations = without_interrupts(|| self.state.lock().expirations);
+        if expirations > 0 { POLLIN } else { 0 }
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
+    }
+}
+
+/// Timer callback: account expirations for every timerfd that is due
+fn expire_timers() {
+    let now = time::uptime_ns();
+    let mut fired = Vec::new();
+    // Timers are dropped only after ARMED is unlocked, since dropping the
+    // last reference re-enters it
+    let mut visited = Vec::new();
+
+    {
+        let mut armed = ARMED.lock();
+        armed.fired();
+        while let Some((key, weak)) = armed.pop_due(now) {
+            let Some(timer) = weak.upgrade() else {
+                continue;
+            };
+
+            {
+                let mut state = timer.state.lock();
+                if state.armed == Some(key) {
+                    fired.push(EventSource::object(timer.as_ref()));
+                    if state.interval_ns > 0 {
+                        let (overruns, next) = hrtimer::periodic_next(key.0, state.interval_ns, now);
+                        state.expirations = state.expirations.saturating_add(1 + overruns);
+                        state.armed = Some(armed.insert(next, Arc::downgrade(&timer)));
+                    } else {
+                        state.expirations += 1;
+                        state.armed = None;
+                    }
+                }
+            }
+
+            visited.push(timer);
+        }
+        armed.rearm();
+    }
+
+    drop(visited);
+
+    for source in fired {
+        poll::notify_event(source);
+    }
+}
@@ -0,0 +1,241 @@
+//! Extended Attributes
+//!
+//! Attribute names carry a namespace prefix that decides who may use them:
+//!
+//! - `user.`: regular files and directories only, governed by the file's
+//!   read and write permission bits
+//! - `trusted.`: root only, and hidden from unprivileged listings
+//! - `security.` and `system.`: readable by anyone, writable by root
+//!
+//! Names in any other namespace are rejected with `NotSupported`. These
+//! checks are made here, before the inode's own xattr operations are
+//! called, so filesystems only store and fetch values.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::RwLock;
+
+use super::{InodeOps, InodeType, Stat, VfsError, VfsResult};
+
+/// Longest attribute name, prefix included
+pub const XATTR_NAME_MAX: usize = 255;
+
+/// Largest attribute value
+pub const XATTR_SIZE_MAX: usize = 65536;
+
+/// Largest name list returned by `list`
+pub const XATTR_LIST_MAX: usize = 65536;
+
+/// Attribute namespace, taken from the name prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XattrNamespace {
+    User,
+    Trusted,
+    Security,
+    System,
+}
+
+impl XattrNamespace {
+    /// Namespace of a full attribute name
+    pub fn of(name: &str) -> VfsResult<Self> {
+        if name.is_empty() {
+            return Err(VfsError::InvalidArgument);
+        }
+        if name.len() > XATTR_NAME_MAX {
+            return Err(VfsError::NameTooLong);
+        }
+
+        let namespace = [Self::User, Self::Trusted, Self::Security, Self::System]
+            .into_iter()
+            .find(|namespace| name.starts_with(namespace.prefix()))
+            .ok_or(VfsError::NotSupported)?;
+
+        // A bare prefix names nothing
+        if name.len() == namespace.prefix().len() {
+            return Err(VfsError::InvalidArgument);
+        }
+        Ok(namespace)
+    }
+
+    /// Name prefix, including the trailing dot
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::User => "user.",
+            Self::Trusted => "trusted.",
+            Self::Security => "security.",
+            Self::System => "system.",
+        }
+    }
+}
+
+/// How `set` treats an existing attribute
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetMode {
+    /// Create the attribute or replace its value
+    Any,
+    /// Fail with `AlreadyExists` if the attribute exists
+    Create,
+    /// Fail with `NoData` if the attribute does not exist
+    Replace,
+}
+
+/// Check that `uid` may read (or with `write`, change) attributes in
+/// `namespace` on the inode described by `stat`
+pub fn check_access(stat: &Stat, namespace: XattrNamespace, write: bool, uid: u32) -> VfsResult<()> {
+    let privileged = uid == 0;
+    match namespace {
+        XattrNamespace::Trusted if !privileged => Err(VfsError::PermissionDenied),
+        XattrNamespace::Security | XattrNamespace::System if write && !privileged => {
+            Err(VfsError::PermissionDenied)
+        }
+        XattrNamespace::User => {
+            // Other file types may not carry user attributes at all
+            if !matches!(stat.inode_type, InodeType::File | InodeType::Directory) {
+                return Err(if write { VfsError::PermissionDenied } else { VfsError::NoData });
+            }
+            if privileged {
+                return Ok(());
+            }
+            let bits = if stat.uid == uid { stat.mode >> 6 } else { stat.mode };
+            let needed = if write { 0o2 } else { 0o4 };
+            if bits & needed != 0 { Ok(()) } else { Err(VfsError::PermissionDenied) }
+        }
+        _ => Ok(()),
+    }
+}
+
+// ============================================================================
+// Checked operations
+// ============================================================================
+
+/// Value of attribute `name` of `inode`, as seen by `uid`
+pub fn get(inode: &Arc<dyn InodeOps>, name: &str, uid: u32) -> VfsResult<Vec<u8>> {
+    let namespace = XattrNamespace::of(name)?;
+    check_access(&inode.stat()?, namespace, false, uid)?;
+    inode.getxattr(name)
+}
+
+/// Set attribute `name` of `inode` on behalf of `uid`
+pub fn set(inode: &Arc<dyn InodeOps>, name: &str, value: &[u8], mode: SetMode, uid: u32) -> VfsResult<()> {
+    let namespace = XattrNamespace::of(name)?;
+    check_access(&inode.stat()?, namespace, true, uid)?;
+    inode.setxattr(name, value, mode)
+}
+
+/// Names of the attributes of `inode` that `uid` may see
+pub fn list(inode: &Arc<dyn InodeOps>, uid: u32) -> VfsResult<Vec<String>> {
+    let mut names = inode.listxattr()?;
+    if uid != 0 {
+        names.retain(|name| !name.starts_with(XattrNamespace::Trusted.prefix()));
+    }
+    Ok(names)
+}
+
+/// Remove attribute `name` of `inode` on behalf of `uid`
+pub fn remove(inode: &Arc<dyn InodeOps>, name: &str, uid: u32) -> VfsResult<()> {
+    let namespace = XattrNamespace::of(name)?;
+    check_access(&inode.stat()?, namespace, true, uid)?;
+    inode.removexattr(name)
+}
+
+// ============================================================================
+// In-memory attribute store
+// ============================================================================
+
+/// Attribute storage for inodes that live in memory
+#[derive(Default)]
+pub struct XattrMap {
+    attrs: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl XattrMap {
+    pub const fn new() -> Self {
+        Self {
+            attrs: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> VfsResult<Vec<u8>> {
+        self.attrs.read().get(name).cloned().ok_or(VfsError::NoData)
+    }
+
+    pub fn set(&self, name: &str, value: &[u8], mode: SetMode) -> VfsResult<()> {
+        let mut attrs = self.attrs.write();
+        match (mode, attrs.contains_key(name)) {
+            (SetMode::Create, true) => Err(VfsError::AlreadyExists),
+            (SetMode::Replace, false) => Err(VfsError::NoData),
+            _ => {
+                attrs.insert(name.to_string(), value.to_vec());
+                Ok(())
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.attrs.read().keys().cloned().collect()
+    }
+
+    pub fn remove(&self, name: &str) -> VfsResult<()> {
+        self.attrs.write().remove(name).map(|_| ()).ok_or(VfsError::NoData)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(uid: u32, mode: u32) -> Stat {
+        Stat { inode_type: InodeType::File, uid, mode, ..Stat::default() }
+    }
+
+    #[test]
+    fn test_namespace_of() {
+        assert_eq!(XattrNamespace::of("user.mime_type"), Ok(XattrNamespace::User));
+        assert_eq!(XattrNamespace::of("trusted.overlay"), Ok(XattrNamespace::Trusted));
+        assert_eq!(XattrNamespace::of("system.posix_acl_access"), Ok(XattrNamespace::System));
+        assert_eq!(XattrNamespace::of("user."), Err(VfsError::InvalidArgument));
+        assert_eq!(XattrNamespace::of(""), Err(VfsError::InvalidArgument));
+        assert_eq!(XattrNamespace::of("os2.name"), Err(VfsError::NotSupported));
+        assert_eq!(XattrNamespace::of(&"u".repeat(XATTR_NAME_MAX + 1)), Err(VfsError::NameTooLong));
+    }
+
+    #[test]
+    fn test_user_namespace_follows_mode_bits() {
+        let stat = file(1000, 0o640);
+        assert_eq!(check_access(&stat, XattrNamespace::User, true, 1000), Ok(()));
+        assert_eq!(check_access(&stat, XattrNamespace::User, false, 1001), Ok(()));
+        assert_eq!(check_access(&stat, XattrNamespace::User, true, 1001), Err(VfsError::PermissionDenied));
+        assert_eq!(check_access(&file(1000, 0o200), XattrNamespace::User, false, 1000), Err(VfsError::PermissionDenied));
+        assert_eq!(check_access(&file(1000, 0), XattrNamespace::User, true, 0), Ok(()));
+
+        let link = Stat { inode_type: InodeType::Symlink, ..stat };
+        assert_eq!(check_access(&link, XattrNamespace::User, false, 0), Err(VfsError::NoData));
+        assert_eq!(check_access(&link, XattrNamespace::User, true, 0), Err(VfsError::PermissionDenied));
+    }
+
+    #[test]
+    fn test_privileged_namespaces() {
+        let stat = file(1000, 0o666);
+        assert_eq!(check_access(&stat, XattrNamespace::Trusted, false, 1000), Err(VfsError::PermissionDenied));
+        assert_eq!(check_access(&stat, XattrNamespace::Trusted, true, 0), Ok(()));
+        for namespace in [XattrNamespace::Security, XattrNamespace::System] {
+            assert_eq!(check_access(&stat, namespace, false, 1000), Ok(()));
+            assert_eq!(check_access(&stat, namespace, true, 1000), Err(VfsError::PermissionDenied));
+            assert_eq!(check_access(&stat, namespace, true, 0), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_xattr_map_set_modes() {
+        let map = XattrMap::new();
+        assert_eq!(map.set("user.a", b"1", SetMode::Replace), Err(VfsError::NoData));
+        assert_eq!(map.set("user.a", b"1", SetMode::Create), Ok(()));
+        assert_eq!(map.set("user.a", b"2", SetMode::Create), Err(VfsError::AlreadyExists));
+        assert_eq!(map.set("user.a", b"3", SetMode::Replace), Ok(()));
+        assert_eq!(map.get("user.a"), Ok(b"3".to_vec()));
+        assert_eq!(map.remove("user.a"), Ok(()));
+        assert_eq!(map.remove("user.a"), Err(VfsError::NoData));
+    }
+}